@@ -6,11 +6,12 @@ Keeps models loaded in memory for fast per-request processing.
 """
 
 import io
+import json
 import logging
 import pathlib
 from typing import Any
 
-from fastapi import FastAPI, File, UploadFile, HTTPException
+from fastapi import FastAPI, File, Form, UploadFile, HTTPException
 from fastapi.responses import JSONResponse
 from pydantic import BaseModel
 
@@ -33,24 +34,118 @@ def touch_activity():
     except OSError:
         pass
 
-# Lazy-load docling to avoid import time at startup
-_converter = None
+# Lazy-load docling to avoid import time at startup. Converters are cached
+# per distinct options blob so each pipeline configuration loads its models once.
+_converters: dict[str, Any] = {}
 
-def get_converter():
-    """Get or create the document converter (singleton)."""
-    global _converter
-    if _converter is None:
-        logger.info("Loading Docling converter (first request)...")
+
+def parse_options(raw: str | None) -> dict[str, Any]:
+    """Parse the `options` form field sent by the extractor."""
+    if not raw:
+        return {}
+    try:
+        options = json.loads(raw)
+    except json.JSONDecodeError as e:
+        raise HTTPException(status_code=400, detail=f"Invalid options: {e}")
+    if not isinstance(options, dict):
+        raise HTTPException(status_code=400, detail="options must be a JSON object")
+    return options
+
+
+COMMON_OPTIONS = {"force_ocr", "table_mode", "ocr_engine", "languages"}
+
+
+def build_pipeline_options(options: dict[str, Any]):
+    """Translate extractor OCR options into Docling PDF pipeline options."""
+    from docling.datamodel.pipeline_options import (
+        EasyOcrOptions,
+        OcrMacOptions,
+        PdfPipelineOptions,
+        RapidOcrOptions,
+        TableFormerMode,
+        TesseractCliOcrOptions,
+    )
+
+    pipeline = PdfPipelineOptions()
+
+    engines = {
+        "easyocr": EasyOcrOptions,
+        "tesseract": TesseractCliOcrOptions,
+        "rapidocr": RapidOcrOptions,
+        "ocrmac": OcrMacOptions,
+    }
+    engine = options.get("ocr_engine")
+    if engine:
+        if engine not in engines:
+            raise HTTPException(status_code=400, detail=f"Unknown ocr_engine: {engine}")
+        pipeline.ocr_options = engines[engine]()
+    if options.get("languages"):
+        pipeline.ocr_options.lang = options["languages"]
+    if options.get("force_ocr"):
+        pipeline.do_ocr = True
+        pipeline.ocr_options.force_full_page_ocr = True
+
+    table_mode = options.get("table_mode")
+    if table_mode == "off":
+        pipeline.do_table_structure = False
+    elif table_mode in ("fast", "accurate"):
+        pipeline.do_table_structure = True
+        pipeline.table_structure_options.mode = (
+            TableFormerMode.FAST if table_mode == "fast" else TableFormerMode.ACCURATE
+        )
+
+    # Any other key comes from `extra.docling` and maps straight onto a
+    # PdfPipelineOptions attribute (e.g. images_scale, do_code_enrichment).
+    for key, value in options.items():
+        if key in COMMON_OPTIONS:
+            continue
+        if not hasattr(pipeline, key):
+            raise HTTPException(status_code=400, detail=f"Unknown pipeline option: {key}")
+        setattr(pipeline, key, value)
+
+    return pipeline
+
+
+def get_converter(options: dict[str, Any]):
+    """Get or create the document converter for the given options."""
+    key = json.dumps(options, sort_keys=True)
+    if key not in _converters:
+        logger.info(f"Loading Docling converter (options: {key})...")
         from docling.document_converter import DocumentConverter
-        _converter = DocumentConverter()
+
+        if options:
+            from docling.datamodel.base_models import InputFormat
+            from docling.document_converter import PdfFormatOption
+
+            pipeline = build_pipeline_options(options)
+            _converters[key] = DocumentConverter(
+                format_options={InputFormat.PDF: PdfFormatOption(pipeline_options=pipeline)}
+            )
+        else:
+            _converters[key] = DocumentConverter()
         logger.info("Docling converter loaded!")
-    return _converter
+    return _converters[key]
+
+
+def page_confidences(result) -> dict[int, float]:
+    """1-indexed page scores from Docling's confidence report (docling >= 2.34)."""
+    report = getattr(result, "confidence", None)
+    if report is None:
+        return {}
+    scores = {}
+    for page_no, page in report.pages.items():
+        score = page.mean_score
+        if score == score:  # skip NaN (page had nothing to score)
+            scores[page_no + 1] = float(score)
+    return scores
 
 
 class PageContent(BaseModel):
     """OCR content for a single page."""
     page_num: int
     text: str
+    # Docling's mean parse/layout/OCR score for the page, when available
+    confidence: float | None = None
 
 
 class ConversionResult(BaseModel):
@@ -68,9 +163,16 @@ async def health():
 
 
 @app.post("/convert", response_model=ConversionResult)
-async def convert_document(file: UploadFile = File(...)):
+async def convert_document(
+    file: UploadFile = File(...),
+    options: str | None = Form(None),
+):
     """
     Convert a PDF document to structured output.
+
+    `options` is an optional JSON object (force_ocr, table_mode, ocr_engine,
+    languages, plus any PdfPipelineOptions attribute) forwarded from the
+    extractor's `ocr_options`.
     
     Returns:
     - Full markdown export
@@ -81,13 +183,14 @@ async def convert_document(file: UploadFile = File(...)):
 
     if not file.filename:
         raise HTTPException(status_code=400, detail="No filename provided")
+    parsed_options = parse_options(options)
 
     # Read file content
     content = await file.read()
     logger.info(f"Received file: {file.filename} ({len(content)} bytes)")
     
     try:
-        converter = get_converter()
+        converter = get_converter(parsed_options)
         
         # Write to temp file (docling needs file path or URL)
         import tempfile
@@ -135,10 +238,12 @@ async def convert_document(file: UploadFile = File(...)):
                     pages_dict[page_no].append(text)
             
             # Build pages list
+            page_scores = page_confidences(result)
             pages = [
                 PageContent(
                     page_num=i, 
-                    text="\n\n".join(pages_dict.get(i, []))
+                    text="\n\n".join(pages_dict.get(i, [])),
+                    confidence=page_scores.get(i),
                 )
                 for i in range(1, num_pages + 1)
             ]
@@ -161,13 +266,18 @@ async def convert_document(file: UploadFile = File(...)):
             # Clean up temp file
             os.unlink(tmp_path)
             
+    except HTTPException:
+        raise
     except Exception as e:
         logger.exception(f"Conversion failed: {e}")
         raise HTTPException(status_code=500, detail=str(e))
 
 
 @app.post("/convert/json")
-async def convert_document_json(file: UploadFile = File(...)):
+async def convert_document_json(
+    file: UploadFile = File(...),
+    options: str | None = Form(None),
+):
     """
     Convert a PDF document to Docling's native JSON format.
     
@@ -177,12 +287,13 @@ async def convert_document_json(file: UploadFile = File(...)):
 
     if not file.filename:
         raise HTTPException(status_code=400, detail="No filename provided")
+    parsed_options = parse_options(options)
 
     content = await file.read()
     logger.info(f"Received file for JSON export: {file.filename} ({len(content)} bytes)")
     
     try:
-        converter = get_converter()
+        converter = get_converter(parsed_options)
         
         import tempfile
         import os
@@ -205,6 +316,8 @@ async def convert_document_json(file: UploadFile = File(...)):
         finally:
             os.unlink(tmp_path)
             
+    except HTTPException:
+        raise
     except Exception as e:
         logger.exception(f"JSON conversion failed: {e}")
         raise HTTPException(status_code=500, detail=str(e))
@@ -52,12 +52,30 @@ function registerTools(server: McpServer) {
         .string()
         .optional()
         .describe(
-          "Filter by readable_id (case-insensitive substring match). Example: '0266175' to find a specific case.",
+          "Filter by readable_id (substring match ignoring case and punctuation). Example: '0266175' to find a specific case.",
         ),
+      reviewed: z
+        .boolean()
+        .optional()
+        .describe("true: only extractions with reviewer-corrected nodes; false: only unreviewed ones."),
+      source_file: z
+        .string()
+        .optional()
+        .describe("Filter by source file name (case-insensitive substring)."),
+      limit: z
+        .number()
+        .int()
+        .optional()
+        .describe("Page size, newest first (default 100, max 1000)."),
+      offset: z.number().int().optional().describe("Number of extractions to skip."),
     },
-    async ({ readable_id }) => {
+    async ({ readable_id, reviewed, source_file, limit, offset }) => {
       const params = new URLSearchParams();
       if (readable_id) params.set("readable_id", readable_id);
+      if (reviewed !== undefined) params.set("reviewed", String(reviewed));
+      if (source_file) params.set("source_file", source_file);
+      if (limit !== undefined) params.set("limit", String(limit));
+      if (offset !== undefined) params.set("offset", String(offset));
       const qs = params.toString();
       const extractions = await api(`/extractions${qs ? `?${qs}` : ""}`);
       return {
@@ -207,14 +225,23 @@ Returns the full extraction result with ID, summary, structure map, and document
 
   server.tool(
     "get_extraction_snapshot",
-    "Get the full extraction tree for an extraction ID. Returns hierarchical structure with summaries, readable_id, structure_map, relationships, reference_index (entity cross-references like CPFs, CNPJs, process numbers), metadata, and content index — but no raw content blobs. Use get_content to lazy-load actual text.",
+    "Get the full extraction tree for an extraction ID. Returns hierarchical structure with summaries, readable_id, structure_map, relationships, reference_index (entity cross-references like CPFs, CNPJs, process numbers), metadata, and content index — but no raw content blobs unless inline_budget_chars is set. Use get_content to lazy-load actual text.",
     {
       extraction_id: z
         .string()
         .describe("The extraction ID (e.g. ext_abc123...)"),
+      inline_budget_chars: z
+        .number()
+        .int()
+        .optional()
+        .describe(
+          "Inline node contents into content_index up to this many characters, most important nodes first. Entries with lazy: true still need get_content.",
+        ),
     },
-    async ({ extraction_id }) => {
-      const result = await api(`/extractions/${extraction_id}/snapshot`);
+    async ({ extraction_id, inline_budget_chars }) => {
+      const qs =
+        inline_budget_chars !== undefined ? `?inline_budget_chars=${inline_budget_chars}` : "";
+      const result = await api(`/extractions/${extraction_id}/snapshot${qs}`);
       return {
         content: [{ type: "text", text: JSON.stringify(result, null, 2) }],
       };
@@ -240,7 +267,7 @@ Returns the full extraction result with ID, summary, structure map, and document
 
   server.tool(
     "get_content",
-    "Lazy-load the text content for a node via its content:// reference. Supports pagination. Returns the text chunk, total character count, and whether more content is available.",
+    "Lazy-load the text content for a node via its content:// reference. Supports pagination. Returns the text chunk, total character count, and whether more content is available. With summarize, returns a short LLM summary instead, to triage long sections cheaply.",
     {
       ref: z
         .string()
@@ -261,13 +288,30 @@ Returns the full extraction result with ID, summary, structure map, and document
         .optional()
         .default(4000)
         .describe("Maximum characters to return"),
+      redacted: z
+        .boolean()
+        .optional()
+        .describe("Return the PII-redacted copy (config pipeline must include the redact stage)"),
+      summarize: z
+        .boolean()
+        .optional()
+        .describe("Return an LLM summary of the content instead of the text (cached after the first call)"),
+      max_chars: z
+        .number()
+        .int()
+        .min(1)
+        .optional()
+        .describe("Summary length in characters (default 800)"),
     },
-    async ({ ref, offset, limit }) => {
+    async ({ ref, offset, limit, redacted, summarize, max_chars }) => {
       const refPath = ref.replace(/^content:\/\//, "");
 
       const params = new URLSearchParams();
       if (offset !== undefined) params.set("offset", String(offset));
       if (limit !== undefined) params.set("limit", String(limit));
+      if (redacted) params.set("redacted", "true");
+      if (summarize) params.set("summarize", "true");
+      if (max_chars !== undefined) params.set("max_chars", String(max_chars));
 
       const qs = params.toString();
       const result = await api(`/content/${refPath}${qs ? `?${qs}` : ""}`);
@@ -277,6 +321,34 @@ Returns the full extraction result with ID, summary, structure map, and document
     },
   );
 
+  server.tool(
+    "get_content_batch",
+    "Load the text content of several nodes in one call, each with its own offset/limit. Takes up to 200 refs and returns one chunk per ref, in order, up to 200,000 characters in total; refs past the cap come back with an error and truncated is true.",
+    {
+      refs: z
+        .array(
+          z.object({
+            ref: z.string().describe("The content reference (e.g. 'content://node_abc123' or just 'node_abc123')"),
+            offset: z.number().int().min(0).optional().describe("Character offset to start from"),
+            limit: z.number().int().min(1).optional().describe("Maximum characters to return (default 4000)"),
+            redacted: z.boolean().optional().describe("Return the PII-redacted copy"),
+          }),
+        )
+        .max(200)
+        .describe("The contents to load"),
+    },
+    async ({ refs }) => {
+      const result = await api("/content/batch", {
+        method: "POST",
+        headers: { "Content-Type": "application/json" },
+        body: JSON.stringify({ refs }),
+      });
+      return {
+        content: [{ type: "text", text: JSON.stringify(result, null, 2) }],
+      };
+    },
+  );
+
   // -------------------------------------------------------------------------
   // Sheet / Dataset tools
   // -------------------------------------------------------------------------
@@ -447,8 +519,24 @@ Returns the dataset placeholder JSON (id + processing status). Poll list_dataset
           node_types: z.array(z.any()).optional().describe("Node type definitions"),
           relationship_types: z.array(z.string()).optional().describe("Relationship type names"),
           metadata_schema: z.any().optional().describe("JSON schema for metadata"),
-          entity_patterns: z.array(z.any()).optional().describe("Regex-based entity patterns"),
+          entity_patterns: z.array(z.any()).optional().describe("Entity patterns: regexes, or {type: 'llm', description} for the LLM to find"),
           readable_id_hint: z.string().optional().describe("Hint for extracting readable document ID"),
+          readable_id_pattern: z.string().optional().describe("Regex for the readable document ID, tried on the OCR text first"),
+          pipeline: z
+            .array(z.enum(["ocr", "structure", "slice_content", "folios", "signatures", "entities", "readable_id", "dedup", "translate", "redact", "upload"]))
+            .optional()
+            .describe("Stages to run, in order (default: all except folios, signatures, translate and redact)"),
+          language: z.string().optional().describe("Document language, ISO 639-1 (e.g. 'pt'); detected from OCR text when unset"),
+          translate_to: z.string().optional().describe("Target language of the translate stage (default 'en')"),
+          redaction: z
+            .object({
+              detectors: z.array(z.enum(["cpf", "cnpj", "email", "phone"])).optional(),
+              entity_patterns: z.array(z.string()).optional(),
+              names: z.boolean().optional(),
+              llm_names: z.boolean().optional(),
+            })
+            .optional()
+            .describe("PII detectors for the redact stage"),
           sheet_config: z.any().optional().describe("Sheet extraction config"),
         })
         .describe("Full ExtractionConfig JSON object"),
@@ -484,7 +572,7 @@ Returns the dataset placeholder JSON (id + processing status). Poll list_dataset
           node_types: z.array(z.any()).optional().describe("Node type definitions"),
           relationship_types: z.array(z.string()).optional().describe("Relationship type names"),
           metadata_schema: z.any().optional().describe("JSON schema for metadata"),
-          entity_patterns: z.array(z.any()).optional().describe("Regex-based entity patterns"),
+          entity_patterns: z.array(z.any()).optional().describe("Entity patterns: regexes, or {type: 'llm', description} for the LLM to find"),
           readable_id_hint: z.string().optional().describe("Hint for extracting readable document ID"),
           sheet_config: z.any().optional().describe("Sheet extraction config"),
         })
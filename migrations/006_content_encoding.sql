@@ -0,0 +1,4 @@
+-- Migration: extraction.node_content.content_encoding
+-- NULL means plain text; 'zstd+base64' means base64-wrapped zstd (see CONTENT_ZSTD_LEVEL).
+
+ALTER TABLE extraction.node_content ADD COLUMN IF NOT EXISTS content_encoding TEXT;
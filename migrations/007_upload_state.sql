@@ -0,0 +1,29 @@
+-- Migration: idempotent, resumable uploads
+-- Run manually in Supabase SQL editor.
+
+-- Relationships have a surrogate key, so upserts need a natural unique key.
+-- Drop duplicates left behind by earlier retried uploads first.
+DELETE FROM extraction.extraction_relationships a
+USING extraction.extraction_relationships b
+WHERE a.id > b.id
+  AND a.extraction_id = b.extraction_id
+  AND a.from_node = b.from_node
+  AND a.to_node = b.to_node
+  AND a.relationship_type = b.relationship_type;
+
+ALTER TABLE extraction.extraction_relationships
+    ADD CONSTRAINT extraction_relationships_natural_key
+    UNIQUE (extraction_id, from_node, to_node, relationship_type);
+
+-- Per-upload progress, so a failed upload resumes from the batches still missing.
+CREATE TABLE IF NOT EXISTS extraction.upload_state (
+    target_type       TEXT NOT NULL,                  -- 'extraction' | 'dataset'
+    target_id         TEXT NOT NULL,
+    fingerprint       TEXT NOT NULL,                  -- hash of the batch plan
+    total_batches     INTEGER NOT NULL,
+    completed_batches JSONB NOT NULL DEFAULT '[]'::jsonb,
+    status            TEXT NOT NULL,                  -- 'in_progress' | 'partial' | 'completed'
+    last_error        TEXT,
+    updated_at        TEXT NOT NULL,
+    PRIMARY KEY (target_type, target_id)
+);
@@ -0,0 +1,8 @@
+-- Migration: extraction.extractions.fingerprint / duplicate_of
+-- Run manually in Supabase SQL editor.
+-- fingerprint is a 64-bit simhash of the OCR text (16 hex digits); duplicate_of
+-- points at the earlier extraction of the same document (see DUPLICATE_MAX_DISTANCE).
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS duplicate_of TEXT;
+CREATE INDEX IF NOT EXISTS idx_extractions_duplicate_of ON extraction.extractions(duplicate_of);
@@ -0,0 +1,5 @@
+-- Migration: extraction.extractions.language
+-- Run manually in Supabase SQL editor.
+-- ISO 639-1 code of the language detected from the OCR text (e.g. 'pt').
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS language TEXT;
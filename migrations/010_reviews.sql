@@ -0,0 +1,19 @@
+-- Migration: reviewer corrections (PATCH /extractions/:id/node/:node_id)
+-- Run manually in Supabase SQL editor.
+-- `reviewed` flags corrected/confirmed nodes and their extraction; node_reviews
+-- is the append-only audit trail (who changed which fields, from what, when).
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS reviewed BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS reviewed BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS extraction.node_reviews (
+    id            TEXT PRIMARY KEY,
+    extraction_id TEXT NOT NULL REFERENCES extraction.extractions(id) ON DELETE CASCADE,
+    node_id       TEXT NOT NULL,
+    reviewer      TEXT NOT NULL,
+    reviewed_at   TEXT NOT NULL,
+    changes       JSONB NOT NULL DEFAULT '[]'::jsonb  -- [{field, old, new}]
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_reviews_extraction ON extraction.node_reviews(extraction_id);
+CREATE INDEX IF NOT EXISTS idx_extractions_reviewed ON extraction.extractions(reviewed);
@@ -0,0 +1,7 @@
+-- Migration: citation anchors into the OCR text
+-- Run manually in Supabase SQL editor.
+-- `ocr_span_start`/`ocr_span_end` are the character offsets of the node's
+-- pages in the extraction's OCR text (pages joined by a blank line).
+
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS ocr_span_start BIGINT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS ocr_span_end BIGINT;
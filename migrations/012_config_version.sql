@@ -0,0 +1,5 @@
+-- Migration: extraction.extractions.config_version
+-- Run manually in Supabase SQL editor.
+-- Fingerprint of the config an extraction ran with (see GET /configs/:name/versions).
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS config_version TEXT;
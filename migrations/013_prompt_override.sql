@@ -0,0 +1,5 @@
+-- Migration: extraction.extractions.prompt_override
+-- Run manually in Supabase SQL editor.
+-- Structure prompt sent with POST /extract in place of the config's, if any.
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS prompt_override TEXT;
@@ -0,0 +1,5 @@
+-- Migration: extraction.extractions.source_url
+-- Run manually in Supabase SQL editor.
+-- Signed download link for the original file archived in Supabase Storage.
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS source_url TEXT;
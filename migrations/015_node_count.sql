@@ -0,0 +1,9 @@
+-- Migration: extraction.extractions.node_count
+-- Run manually in Supabase SQL editor.
+-- Node count written on upload, so extraction listings show real counts
+-- without fetching the node rows.
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS node_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE extraction.extractions e
+SET node_count = (SELECT COUNT(*) FROM extraction.extraction_nodes n WHERE n.extraction_id = e.id);
@@ -0,0 +1,6 @@
+-- Migration: extraction.extractions.diagnostics
+-- Run manually in Supabase SQL editor.
+-- Relationships the extractor repaired or dropped from the LLM's answer,
+-- because they pointed at unknown nodes or relationship types.
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS diagnostics JSONB;
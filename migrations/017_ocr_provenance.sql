@@ -0,0 +1,11 @@
+-- Migration: OCR provenance
+-- Run manually in Supabase SQL editor.
+-- `ocr_provider`/`ocr_confidence` record which OCR provider read the document
+-- and the confidence it reported. `pdf_page_start`/`pdf_page_end` are a
+-- node's page range in pages of the source PDF, set when the provider
+-- numbered pages differently (e.g. only some pages were OCR'd).
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS ocr_provider TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS ocr_confidence DOUBLE PRECISION;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS pdf_page_start BIGINT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS pdf_page_end BIGINT;
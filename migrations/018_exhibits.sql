@@ -0,0 +1,10 @@
+-- Migration: Exhibit extractions
+-- Run manually in Supabase SQL editor.
+-- `POST /extractions/:id/node/:node_id/extract` re-extracts an exhibit node's
+-- pages with another config. The node records the new extraction in
+-- `child_extraction_id`; the new extraction points back with
+-- `parent_extraction_id`/`parent_node_id`.
+
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS parent_extraction_id TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS parent_node_id TEXT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS child_extraction_id TEXT;
@@ -0,0 +1,85 @@
+-- Plain Postgres schema (STORAGE_BACKEND=postgres)
+-- Applied automatically on startup; mirrors the Supabase `extraction` schema
+-- without depending on PostgREST.
+
+CREATE SCHEMA IF NOT EXISTS extraction;
+
+CREATE TABLE IF NOT EXISTS extraction.extractions (
+    id                TEXT PRIMARY KEY,
+    config_name       TEXT,
+    source_file       TEXT NOT NULL,
+    content_hash      TEXT,
+    total_pages       INTEGER,
+    summary           TEXT NOT NULL DEFAULT '',
+    structure_map     JSONB DEFAULT '[]'::jsonb,
+    metadata          JSONB,
+    reference_index   JSONB,
+    readable_id       TEXT,
+    extracted_at      TEXT NOT NULL,
+    extractor_version TEXT
+);
+
+CREATE TABLE IF NOT EXISTS extraction.extraction_nodes (
+    extraction_id TEXT NOT NULL REFERENCES extraction.extractions(id) ON DELETE CASCADE,
+    id            TEXT NOT NULL,
+    parent_id     TEXT,
+    position      INTEGER NOT NULL,
+    type          TEXT NOT NULL,
+    subtype       TEXT,
+    label         TEXT,
+    page_start    INTEGER,
+    page_end      INTEGER,
+    date          TEXT,
+    author        TEXT,
+    summary       TEXT NOT NULL DEFAULT '',
+    confidence    JSONB,
+    metadata      JSONB,
+    PRIMARY KEY (extraction_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS extraction.node_content (
+    extraction_id TEXT NOT NULL REFERENCES extraction.extractions(id) ON DELETE CASCADE,
+    node_id       TEXT NOT NULL,
+    content       TEXT NOT NULL,
+    char_count    INTEGER NOT NULL,
+    PRIMARY KEY (extraction_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_content_node ON extraction.node_content(node_id);
+
+CREATE TABLE IF NOT EXISTS extraction.extraction_relationships (
+    id                BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    extraction_id     TEXT NOT NULL REFERENCES extraction.extractions(id) ON DELETE CASCADE,
+    from_node         TEXT NOT NULL,
+    to_node           TEXT NOT NULL,
+    relationship_type TEXT NOT NULL,
+    citation          TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_relationships_extraction ON extraction.extraction_relationships(extraction_id);
+
+CREATE TABLE IF NOT EXISTS extraction.datasets (
+    id            TEXT PRIMARY KEY,
+    source_file   TEXT NOT NULL,
+    config_name   TEXT,
+    extracted_at  TEXT NOT NULL,
+    summary       TEXT NOT NULL DEFAULT '',
+    schemas       JSONB NOT NULL,
+    relationships JSONB,
+    status        TEXT DEFAULT 'completed'
+);
+
+CREATE TABLE IF NOT EXISTS extraction.dataset_rows (
+    dataset_id  TEXT NOT NULL REFERENCES extraction.datasets(id) ON DELETE CASCADE,
+    schema_name TEXT NOT NULL,
+    row_index   INTEGER NOT NULL,
+    row_data    JSONB NOT NULL,
+    PRIMARY KEY (dataset_id, schema_name, row_index)
+);
+
+CREATE TABLE IF NOT EXISTS extraction.configs (
+    name       TEXT PRIMARY KEY,
+    config     JSONB NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+-- Compressed node content: NULL = plain text, 'zstd+base64' = base64-wrapped zstd frame
+ALTER TABLE extraction.node_content ADD COLUMN IF NOT EXISTS content_encoding TEXT;
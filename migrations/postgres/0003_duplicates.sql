@@ -0,0 +1,4 @@
+-- Near-duplicate detection: simhash of the OCR text and the extraction it duplicates
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS fingerprint TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS duplicate_of TEXT;
+CREATE INDEX IF NOT EXISTS idx_extractions_duplicate_of ON extraction.extractions(duplicate_of);
@@ -0,0 +1,2 @@
+-- Document language detected from the OCR text (ISO 639-1)
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS language TEXT;
@@ -0,0 +1,15 @@
+-- Reviewer corrections: reviewed flags and the append-only audit trail
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS reviewed BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS reviewed BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS extraction.node_reviews (
+    id            TEXT PRIMARY KEY,
+    extraction_id TEXT NOT NULL REFERENCES extraction.extractions(id) ON DELETE CASCADE,
+    node_id       TEXT NOT NULL,
+    reviewer      TEXT NOT NULL,
+    reviewed_at   TEXT NOT NULL,
+    changes       JSONB NOT NULL DEFAULT '[]'::jsonb
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_reviews_extraction ON extraction.node_reviews(extraction_id);
+CREATE INDEX IF NOT EXISTS idx_extractions_reviewed ON extraction.extractions(reviewed);
@@ -0,0 +1,3 @@
+-- Character offsets of each node's pages in the OCR text
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS ocr_span_start BIGINT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS ocr_span_end BIGINT;
@@ -0,0 +1,2 @@
+-- Fingerprint of the config the extraction ran with
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS config_version TEXT;
@@ -0,0 +1,2 @@
+-- Structure prompt that replaced the config's for this run
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS prompt_override TEXT;
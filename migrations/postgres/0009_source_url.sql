@@ -0,0 +1,2 @@
+-- Signed download link for the archived original file
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS source_url TEXT;
@@ -0,0 +1,5 @@
+-- Node count written on upload, so listings don't load the tree
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS node_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE extraction.extractions e
+SET node_count = (SELECT COUNT(*) FROM extraction.extraction_nodes n WHERE n.extraction_id = e.id);
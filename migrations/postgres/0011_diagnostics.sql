@@ -0,0 +1,2 @@
+-- Relationships repaired or dropped after the LLM's answer
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS diagnostics JSONB;
@@ -0,0 +1,6 @@
+-- OCR provider and confidence of each extraction, and node page ranges in
+-- pages of the source PDF when the provider numbered pages differently
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS ocr_provider TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS ocr_confidence DOUBLE PRECISION;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS pdf_page_start BIGINT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS pdf_page_end BIGINT;
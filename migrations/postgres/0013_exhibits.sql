@@ -0,0 +1,5 @@
+-- Exhibit nodes re-extracted with another config: the node links to the
+-- child extraction, the child back to its parent extraction and node
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS parent_extraction_id TEXT;
+ALTER TABLE extraction.extractions ADD COLUMN IF NOT EXISTS parent_node_id TEXT;
+ALTER TABLE extraction.extraction_nodes ADD COLUMN IF NOT EXISTS child_extraction_id TEXT;
@@ -0,0 +1,81 @@
+-- Embedded SQLite schema (STORAGE_BACKEND=sqlite)
+-- Applied automatically on startup. JSON values are stored as TEXT.
+
+CREATE TABLE IF NOT EXISTS extractions (
+    id                TEXT PRIMARY KEY,
+    config_name       TEXT,
+    source_file       TEXT NOT NULL,
+    content_hash      TEXT,
+    total_pages       INTEGER,
+    summary           TEXT NOT NULL DEFAULT '',
+    structure_map     TEXT,
+    metadata          TEXT,
+    reference_index   TEXT,
+    readable_id       TEXT,
+    extracted_at      TEXT NOT NULL,
+    extractor_version TEXT
+);
+
+CREATE TABLE IF NOT EXISTS extraction_nodes (
+    extraction_id TEXT NOT NULL REFERENCES extractions(id) ON DELETE CASCADE,
+    id            TEXT NOT NULL,
+    parent_id     TEXT,
+    position      INTEGER NOT NULL,
+    type          TEXT NOT NULL,
+    subtype       TEXT,
+    label         TEXT,
+    page_start    INTEGER,
+    page_end      INTEGER,
+    date          TEXT,
+    author        TEXT,
+    summary       TEXT NOT NULL DEFAULT '',
+    confidence    TEXT,
+    metadata      TEXT,
+    PRIMARY KEY (extraction_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS node_content (
+    extraction_id TEXT NOT NULL REFERENCES extractions(id) ON DELETE CASCADE,
+    node_id       TEXT NOT NULL,
+    content       TEXT NOT NULL,
+    char_count    INTEGER NOT NULL,
+    PRIMARY KEY (extraction_id, node_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_content_node ON node_content(node_id);
+
+CREATE TABLE IF NOT EXISTS extraction_relationships (
+    extraction_id     TEXT NOT NULL REFERENCES extractions(id) ON DELETE CASCADE,
+    from_node         TEXT NOT NULL,
+    to_node           TEXT NOT NULL,
+    relationship_type TEXT NOT NULL,
+    citation          TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_relationships_extraction ON extraction_relationships(extraction_id);
+
+CREATE TABLE IF NOT EXISTS datasets (
+    id            TEXT PRIMARY KEY,
+    source_file   TEXT NOT NULL,
+    config_name   TEXT,
+    extracted_at  TEXT NOT NULL,
+    summary       TEXT NOT NULL DEFAULT '',
+    schemas       TEXT NOT NULL,
+    relationships TEXT,
+    status        TEXT DEFAULT 'completed'
+);
+
+CREATE TABLE IF NOT EXISTS dataset_rows (
+    dataset_id  TEXT NOT NULL REFERENCES datasets(id) ON DELETE CASCADE,
+    schema_name TEXT NOT NULL,
+    row_index   INTEGER NOT NULL,
+    row_data    TEXT NOT NULL,
+    PRIMARY KEY (dataset_id, schema_name, row_index)
+);
+
+CREATE TABLE IF NOT EXISTS configs (
+    name       TEXT PRIMARY KEY,
+    config     TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Compressed node content: NULL = plain text, 'zstd+base64' = base64-wrapped zstd frame
+ALTER TABLE node_content ADD COLUMN content_encoding TEXT;
@@ -0,0 +1,4 @@
+-- Near-duplicate detection: simhash of the OCR text and the extraction it duplicates
+ALTER TABLE extractions ADD COLUMN fingerprint TEXT;
+ALTER TABLE extractions ADD COLUMN duplicate_of TEXT;
+CREATE INDEX IF NOT EXISTS idx_extractions_duplicate_of ON extractions(duplicate_of);
@@ -0,0 +1,15 @@
+-- Reviewer corrections: reviewed flags and the append-only audit trail
+ALTER TABLE extractions ADD COLUMN reviewed INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE extraction_nodes ADD COLUMN reviewed INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS node_reviews (
+    id            TEXT PRIMARY KEY,
+    extraction_id TEXT NOT NULL REFERENCES extractions(id) ON DELETE CASCADE,
+    node_id       TEXT NOT NULL,
+    reviewer      TEXT NOT NULL,
+    reviewed_at   TEXT NOT NULL,
+    changes       TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_reviews_extraction ON node_reviews(extraction_id);
+CREATE INDEX IF NOT EXISTS idx_extractions_reviewed ON extractions(reviewed);
@@ -0,0 +1,3 @@
+-- Character offsets of each node's pages in the OCR text
+ALTER TABLE extraction_nodes ADD COLUMN ocr_span_start INTEGER;
+ALTER TABLE extraction_nodes ADD COLUMN ocr_span_end INTEGER;
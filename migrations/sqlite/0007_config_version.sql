@@ -0,0 +1,2 @@
+-- Fingerprint of the config the extraction ran with
+ALTER TABLE extractions ADD COLUMN config_version TEXT;
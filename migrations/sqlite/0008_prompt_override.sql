@@ -0,0 +1,2 @@
+-- Structure prompt that replaced the config's for this run
+ALTER TABLE extractions ADD COLUMN prompt_override TEXT;
@@ -0,0 +1,2 @@
+-- Signed download link for the archived original file
+ALTER TABLE extractions ADD COLUMN source_url TEXT;
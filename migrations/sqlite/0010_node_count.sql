@@ -0,0 +1,5 @@
+-- Node count written on upload, so listings don't load the tree
+ALTER TABLE extractions ADD COLUMN node_count INTEGER NOT NULL DEFAULT 0;
+
+UPDATE extractions
+SET node_count = (SELECT COUNT(*) FROM extraction_nodes WHERE extraction_id = extractions.id);
@@ -0,0 +1,2 @@
+-- Relationships repaired or dropped after the LLM's answer (JSON)
+ALTER TABLE extractions ADD COLUMN diagnostics TEXT;
@@ -0,0 +1,6 @@
+-- OCR provider and confidence of each extraction, and node page ranges in
+-- pages of the source PDF when the provider numbered pages differently
+ALTER TABLE extractions ADD COLUMN ocr_provider TEXT;
+ALTER TABLE extractions ADD COLUMN ocr_confidence REAL;
+ALTER TABLE extraction_nodes ADD COLUMN pdf_page_start INTEGER;
+ALTER TABLE extraction_nodes ADD COLUMN pdf_page_end INTEGER;
@@ -0,0 +1,5 @@
+-- Exhibit nodes re-extracted with another config: the node links to the
+-- child extraction, the child back to its parent extraction and node
+ALTER TABLE extractions ADD COLUMN parent_extraction_id TEXT;
+ALTER TABLE extractions ADD COLUMN parent_node_id TEXT;
+ALTER TABLE extraction_nodes ADD COLUMN child_extraction_id TEXT;
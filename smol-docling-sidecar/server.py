@@ -6,13 +6,14 @@ Keeps model loaded in memory for fast per-request processing.
 Same API contract as the docling sidecar.
 """
 
+import json
 import logging
 import os
 import tempfile
 import time
 from typing import Any
 
-from fastapi import FastAPI, File, UploadFile, HTTPException
+from fastapi import FastAPI, File, Form, UploadFile, HTTPException
 from pydantic import BaseModel
 
 logging.basicConfig(level=logging.INFO)
@@ -72,10 +73,16 @@ async def health():
 
 
 @app.post("/convert", response_model=ConversionResult)
-async def convert_document(file: UploadFile = File(...)):
+async def convert_document(
+    file: UploadFile = File(...),
+    options: str | None = Form(None),
+):
     """
     Convert a PDF document to structured output using SmolDocling VLM.
 
+    `options` is an optional JSON object; only `dpi` (page rasterization
+    resolution, default 150) is honored.
+
     Returns:
     - Full markdown export
     - Page-by-page OCR text
@@ -84,6 +91,11 @@ async def convert_document(file: UploadFile = File(...)):
     if not file.filename:
         raise HTTPException(status_code=400, detail="No filename provided")
 
+    try:
+        dpi = int(json.loads(options).get("dpi", 150)) if options else 150
+    except (ValueError, TypeError, AttributeError) as e:
+        raise HTTPException(status_code=400, detail=f"Invalid options: {e}")
+
     content = await file.read()
     logger.info(f"Received file: {file.filename} ({len(content)} bytes)")
 
@@ -98,7 +110,7 @@ async def convert_document(file: UploadFile = File(...)):
 
         # Convert PDF pages to images
         logger.info("Converting PDF pages to images...")
-        images = convert_from_bytes(content, dpi=150)
+        images = convert_from_bytes(content, dpi=dpi)
         num_pages = len(images)
         logger.info(f"Got {num_pages} pages in {time.time() - t0:.1f}s")
 
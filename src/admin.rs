@@ -0,0 +1,205 @@
+//! Operator views of the running server: `GET /admin/state`, `POST /admin/gc`,
+//! and `GET /health/ready`.
+//!
+//! [`BackgroundTasks`] records the long-lived loops the server starts (sync,
+//! scheduler, ingest, mail) so the state report can list them; [`probe`]
+//! times a health check of an external dependency.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::content_store::ContentStoreStats;
+use crate::jobs::LaneCounts;
+use crate::quotas::DailyUsage;
+use crate::schema::now_iso8601;
+
+/// How long a single dependency probe may take before it counts as down.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A long-lived loop started by the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackgroundTask {
+    pub name: String,
+    pub detail: String,
+    pub started_at: String,
+}
+
+/// Registry of the server's background loops.
+#[derive(Default)]
+pub struct BackgroundTasks {
+    tasks: Mutex<Vec<BackgroundTask>>,
+}
+
+impl BackgroundTasks {
+    pub fn register(&self, name: &str, detail: impl Into<String>) {
+        self.tasks.lock().unwrap().push(BackgroundTask {
+            name: name.to_string(),
+            detail: detail.into(),
+            started_at: now_iso8601(),
+        });
+    }
+
+    pub fn list(&self) -> Vec<BackgroundTask> {
+        self.tasks.lock().unwrap().clone()
+    }
+}
+
+/// Outcome of one dependency health check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    pub name: String,
+    pub healthy: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Run `check` under [`PROBE_TIMEOUT`] and report how it went.
+pub async fn probe<F>(name: impl Into<String>, check: F) -> Health
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    let started = Instant::now();
+    let error = match tokio::time::timeout(PROBE_TIMEOUT, check).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("timed out after {}s", PROBE_TIMEOUT.as_secs())),
+    };
+    Health {
+        name: name.into(),
+        healthy: error.is_none(),
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+/// In-memory extractions by status.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatusCounts {
+    pub total: usize,
+    pub by_status: BTreeMap<String, usize>,
+}
+
+impl StatusCounts {
+    /// Tally serializable statuses by their serialized name.
+    pub fn tally<'a, T: Serialize + 'a>(statuses: impl IntoIterator<Item = &'a T>) -> Self {
+        let mut counts = Self::default();
+        for status in statuses {
+            let name = match serde_json::to_value(status) {
+                Ok(serde_json::Value::String(name)) => name,
+                _ => "unknown".to_string(),
+            };
+            *counts.by_status.entry(name).or_default() += 1;
+            counts.total += 1;
+        }
+        counts
+    }
+}
+
+/// Jobs of this process and the free run slots.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobsState {
+    pub running: Vec<String>,
+    pub free_slots: usize,
+    pub waiting: LaneCounts,
+}
+
+/// A loaded config and the fingerprint of its extraction-relevant fields.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigVersion {
+    pub name: String,
+    pub version: String,
+}
+
+/// Body of `GET /admin/state`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminState {
+    pub generated_at: String,
+    pub extractions: StatusCounts,
+    pub datasets: usize,
+    pub content_store: ContentStoreStats,
+    pub jobs: JobsState,
+    pub background_tasks: Vec<BackgroundTask>,
+    /// Uploads waiting in the sync outbox (`None` without storage).
+    pub sync_pending: Option<usize>,
+    pub ocr_providers: Vec<Health>,
+    /// Storage backend reachability (`None` without storage).
+    pub storage: Option<Health>,
+    pub configs: Vec<ConfigVersion>,
+    /// Today's LLM usage (UTC) per config, counted against their `quotas`.
+    pub quota_usage: BTreeMap<String, DailyUsage>,
+}
+
+/// Body of `GET /health/ready`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checked_at: String,
+    pub ocr_providers: Vec<Health>,
+    /// `None` without a storage backend.
+    pub storage: Option<Health>,
+    pub llm: Health,
+}
+
+impl Readiness {
+    /// Ready only when every probed dependency is healthy.
+    pub fn new(ocr_providers: Vec<Health>, storage: Option<Health>, llm: Health) -> Self {
+        let ready = ocr_providers.iter().all(|h| h.healthy)
+            && storage.as_ref().is_none_or(|h| h.healthy)
+            && llm.healthy;
+        Self {
+            ready,
+            checked_at: now_iso8601(),
+            ocr_providers,
+            storage,
+            llm,
+        }
+    }
+}
+
+/// Body of `POST /admin/gc`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    /// Completed extractions dropped from memory (still served from storage).
+    pub dropped: Vec<String>,
+    /// Completed extractions kept because storage does not have them yet.
+    pub kept_unpersisted: Vec<String>,
+    /// Extractions left in memory, whatever their status.
+    pub remaining: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::ExtractionStatus;
+
+    #[tokio::test]
+    async fn test_probe_and_tally() {
+        let ok = probe("docling", async { Ok(()) }).await;
+        assert!(ok.healthy);
+        assert!(ok.error.is_none());
+        let down = probe("storage", async {
+            Err(anyhow::anyhow!("connection refused"))
+        })
+        .await;
+        assert!(!down.healthy);
+        assert_eq!(down.error.as_deref(), Some("connection refused"));
+
+        assert!(Readiness::new(vec![ok.clone()], None, ok.clone()).ready);
+        assert!(!Readiness::new(vec![ok.clone()], Some(down), ok).ready);
+
+        let statuses = [
+            ExtractionStatus::Completed,
+            ExtractionStatus::Queued,
+            ExtractionStatus::Completed,
+        ];
+        let counts = StatusCounts::tally(&statuses);
+        assert_eq!(counts.total, 3);
+        assert_eq!(counts.by_status["completed"], 2);
+        assert_eq!(counts.by_status["queued"], 1);
+    }
+}
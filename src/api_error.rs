@@ -0,0 +1,239 @@
+//! The API's error type.
+//!
+//! Every error leaves the API as `{"error": "<code>", "detail": "<message>"}`.
+//! Handlers return an [`ApiError`]; the variants that name a failure
+//! (`config_not_found`, `ocr_failed`, `llm_parse_error`, ...) carry their own
+//! code, the rest are named after their status. Responses produced outside
+//! the handlers, such as axum's extractor rejections, are plain text;
+//! [`json_errors`] rewrites those the same way.
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use thiserror::Error;
+
+use crate::config::ConfigKind;
+use crate::extractor::LlmParseError;
+use crate::upload::Rejection;
+
+/// Plain-text error bodies longer than this are cut.
+const MAX_DETAIL_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("Unknown config: {name}. Available: {available:?}")]
+    ConfigNotFound {
+        name: String,
+        available: Vec<String>,
+    },
+    /// The request named no config and none is marked as the default
+    #[error(
+        "No config given and no default {0} config: set {env} or mark a config \"default\": true",
+        env = .0.env_var()
+    )]
+    NoDefaultConfig(ConfigKind),
+    /// A known OCR provider whose credentials or sidecar are not set up
+    #[error("OCR provider '{0}' is not configured. Check env vars.")]
+    ProviderUnconfigured(String),
+    #[error("OCR failed: {0}")]
+    OcrFailed(String),
+    #[error("OCR timed out after {0}s")]
+    OcrTimeout(u64),
+    #[error(transparent)]
+    LlmParse(#[from] LlmParseError),
+    #[error("{0}")]
+    MissingFile(&'static str),
+    #[error(transparent)]
+    Upload(#[from] Rejection),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Unprocessable(String),
+    /// A dependency (storage, object store, a URL) answered with an error
+    #[error("{0}")]
+    Upstream(String),
+    /// The config is at one of its `quotas`
+    #[error("{0}")]
+    QuotaExceeded(String),
+    #[error("Document has {pages} pages; config '{config}' allows at most {max}")]
+    TooManyPages {
+        config: String,
+        pages: u32,
+        max: u32,
+    },
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::ConfigNotFound { .. } | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::NoDefaultConfig(_)
+            | ApiError::ProviderUnconfigured(_)
+            | ApiError::MissingFile(_)
+            | ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::OcrFailed(_) | ApiError::LlmParse(_) | ApiError::Upstream(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            ApiError::OcrTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Upload(rejection) => match rejection {
+                Rejection::Empty => StatusCode::BAD_REQUEST,
+                Rejection::UnsupportedType { .. } | Rejection::ContentTypeMismatch { .. } => {
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE
+                }
+                Rejection::ContentMismatch { .. } | Rejection::EncryptedPdf => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+            },
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::TooManyPages { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine-readable code for the `error` field.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::ConfigNotFound { .. } => "config_not_found",
+            ApiError::NoDefaultConfig(_) => "no_default_config",
+            ApiError::ProviderUnconfigured(_) => "provider_unconfigured",
+            ApiError::OcrFailed(_) => "ocr_failed",
+            ApiError::OcrTimeout(_) => "ocr_timeout",
+            ApiError::LlmParse(_) => "llm_parse_error",
+            ApiError::MissingFile(_) => "missing_file",
+            ApiError::QuotaExceeded(_) => "quota_exceeded",
+            ApiError::TooManyPages { .. } => "too_many_pages",
+            ApiError::Upload(rejection) => rejection.code(),
+            _ => code_for(self.status()),
+        }
+    }
+
+    /// The code carried anywhere in an `anyhow` chain, for callers outside
+    /// the HTTP API (the `extract` command).
+    pub fn code_of(error: &anyhow::Error) -> Option<&'static str> {
+        error.chain().find_map(|cause| {
+            if let Some(api) = cause.downcast_ref::<ApiError>() {
+                Some(api.code())
+            } else if cause.is::<LlmParseError>() {
+                Some("llm_parse_error")
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({"error": self.code(), "detail": self.to_string()});
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/// Default error code for a status.
+pub fn code_for(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "too_many_requests",
+        StatusCode::BAD_GATEWAY => "upstream_error",
+        StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        StatusCode::GATEWAY_TIMEOUT => "upstream_timeout",
+        s if s.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+/// Rewrite a non-JSON error response as an [`ApiError`] body, keeping its
+/// status and other headers.
+pub async fn json_errors(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = axum::body::to_bytes(body, MAX_DETAIL_BYTES)
+        .await
+        .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+        .unwrap_or_default();
+    let detail = if text.is_empty() {
+        status.canonical_reason().unwrap_or("Error").to_string()
+    } else {
+        text
+    };
+    let body = serde_json::json!({"error": code_for(status), "detail": detail});
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_error_bodies() {
+        let plain = (StatusCode::NOT_FOUND, "Extraction x not found".to_string()).into_response();
+        let rewritten = json_errors(plain).await;
+        assert_eq!(rewritten.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            body_json(rewritten).await,
+            serde_json::json!({"error": "not_found", "detail": "Extraction x not found"})
+        );
+
+        let bare = json_errors(StatusCode::PAYLOAD_TOO_LARGE.into_response()).await;
+        assert_eq!(body_json(bare).await["error"], "payload_too_large");
+
+        let specific = ApiError::from(Rejection::EncryptedPdf);
+        let kept = json_errors(specific.into_response()).await;
+        assert_eq!(kept.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body_json(kept).await["error"], "encrypted_pdf");
+
+        let parse = anyhow::Error::new(LlmParseError {
+            call: "structure",
+            detail: "EOF".into(),
+            response: "{\"nodes\": [".into(),
+        })
+        .context("Structure stage failed");
+        assert_eq!(ApiError::code_of(&parse), Some("llm_parse_error"));
+        let plain = anyhow::anyhow!("disk full");
+        assert_eq!(ApiError::code_of(&plain), None);
+
+        let ok = json_errors("fine".into_response()).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+    }
+}
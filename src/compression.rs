@@ -0,0 +1,123 @@
+//! Transparent zstd compression for stored node content.
+//!
+//! Used by the ContentStore disk tier and by every storage backend's
+//! `node_content` path. Content below [`MIN_COMPRESS_BYTES`] is stored as-is.
+//! `CONTENT_ZSTD_LEVEL` tunes the tradeoff (1 = fastest, 19 = smallest,
+//! 0 = disabled); the default is 3.
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+
+/// Default zstd level: fast with a good ratio on OCR text.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// Content smaller than this is never compressed.
+pub const MIN_COMPRESS_BYTES: usize = 512;
+
+/// `content_encoding` value for base64-wrapped zstd text columns.
+pub const ENCODING_ZSTD_BASE64: &str = "zstd+base64";
+
+/// Compression settings (a level of 0 disables compression).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    level: i32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_ZSTD_LEVEL,
+        }
+    }
+}
+
+impl Compression {
+    pub fn new(level: i32) -> Self {
+        Self {
+            level: level.clamp(0, 22),
+        }
+    }
+
+    /// Read `CONTENT_ZSTD_LEVEL` (default 3, 0 disables).
+    pub fn from_env() -> Self {
+        std::env::var("CONTENT_ZSTD_LEVEL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Self::new)
+            .unwrap_or_default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.level > 0
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    /// Compress `text` if enabled and worthwhile; `None` means store it as-is.
+    pub fn compress(&self, text: &str) -> Option<Vec<u8>> {
+        if !self.enabled() || text.len() < MIN_COMPRESS_BYTES {
+            return None;
+        }
+        let compressed = zstd::encode_all(text.as_bytes(), self.level).ok()?;
+        (compressed.len() < text.len()).then_some(compressed)
+    }
+
+    /// Encode `text` for a TEXT column, returning `(value, content_encoding)`.
+    pub fn encode_text(&self, text: &str) -> (String, Option<&'static str>) {
+        match self.compress(text) {
+            Some(bytes) => (
+                base64::engine::general_purpose::STANDARD.encode(bytes),
+                Some(ENCODING_ZSTD_BASE64),
+            ),
+            None => (text.to_string(), None),
+        }
+    }
+}
+
+/// Decompress a zstd frame into UTF-8 text.
+pub fn decompress(data: &[u8]) -> Result<String> {
+    let bytes = zstd::decode_all(data).context("zstd decompression failed")?;
+    String::from_utf8(bytes).context("Decompressed content is not valid UTF-8")
+}
+
+/// Decode a TEXT column value according to its `content_encoding`.
+pub fn decode_text(value: String, encoding: Option<&str>) -> Result<String> {
+    match encoding {
+        None | Some("") | Some("identity") => Ok(value),
+        Some(ENCODING_ZSTD_BASE64) => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(value.as_bytes())
+                .context("Invalid base64 in compressed content")?;
+            decompress(&bytes)
+        }
+        Some(other) => bail!("Unknown content encoding: {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_roundtrip() {
+        let c = Compression::default();
+        let text = "Processo nº 0001234-56.2024.8.26.0100 — petição inicial. ".repeat(100);
+
+        let (encoded, encoding) = c.encode_text(&text);
+        assert_eq!(encoding, Some(ENCODING_ZSTD_BASE64));
+        assert!(encoded.len() < text.len());
+        assert_eq!(decode_text(encoded, encoding).unwrap(), text);
+    }
+
+    #[test]
+    fn test_small_or_disabled_is_plain() {
+        let (encoded, encoding) = Compression::default().encode_text("short");
+        assert_eq!((encoded.as_str(), encoding), ("short", None));
+
+        let text = "a".repeat(4096);
+        assert!(Compression::new(0).compress(&text).is_none());
+        assert_eq!(decode_text(text.clone(), None).unwrap(), text);
+    }
+}
@@ -0,0 +1,471 @@
+//! Per-node confidence scores computed from extraction signals.
+//!
+//! `extraction` starts at 1.0 and is discounted for each problem found:
+//! a missing or out-of-range `page_range`, child nodes that leave pages of
+//! their parent uncovered, a node type (or subtype) the config does not
+//! declare, and JSON repairs needed to parse the LLM response. The result is
+//! then scaled by OCR quality: the mean confidence of the node's pages, using
+//! the provider's per-page scores where it reports them and counting blank
+//! pages as zero. Every discount, and every page below
+//! [`LOW_OCR_CONFIDENCE`], is recorded as a `low_confidence_regions` entry,
+//! which the review queue surfaces.
+
+use serde::Serialize;
+
+use crate::config::ExtractionConfig;
+use crate::ocr::{OcrPage, OcrResult};
+use crate::schema::{ConfidenceScores, DocumentNode, Extraction, LowConfidenceRegion};
+
+/// Nodes scoring below this go to the review queue by default.
+pub const DEFAULT_REVIEW_THRESHOLD: f64 = 0.6;
+/// Pages whose OCR confidence is below this are flagged on overlapping nodes.
+pub const LOW_OCR_CONFIDENCE: f64 = 0.7;
+
+const MISSING_PAGE_RANGE: f64 = 0.7;
+const INVALID_PAGE_RANGE: f64 = 0.5;
+const UNKNOWN_NODE_TYPE: f64 = 0.75;
+const UNKNOWN_SUBTYPE: f64 = 0.9;
+const PER_JSON_REPAIR: f64 = 0.9;
+/// Floor of the child-coverage factor (a parent whose children cover none of its pages)
+const MIN_COVERAGE_FACTOR: f64 = 0.7;
+
+/// Document-wide inputs to scoring.
+pub struct Signals<'a> {
+    pub config: &'a ExtractionConfig,
+    pub pages: &'a [OcrPage],
+    pub total_pages: u32,
+    /// Provider-level OCR confidence, used for pages without their own score
+    pub ocr_confidence: f64,
+    /// JSON repairs applied to the LLM response before it parsed
+    pub json_repairs: u32,
+}
+
+/// Score every node of the tree in place.
+pub fn score(nodes: &mut [DocumentNode], signals: &Signals) {
+    for node in nodes.iter_mut() {
+        score(&mut node.children, signals);
+        node.confidence = Some(score_node(node, signals));
+    }
+}
+
+fn score_node(node: &DocumentNode, signals: &Signals) -> ConfidenceScores {
+    let mut extraction = 1.0;
+    let mut regions = Vec::new();
+
+    // OCR: mean page confidence over the node's range (provider-level without a usable range)
+    let mut ocr = signals.ocr_confidence;
+    match node.page_range {
+        None => {
+            extraction *= MISSING_PAGE_RANGE;
+            regions.push(region(None, "no page range".to_string()));
+        }
+        Some([start, end]) if start == 0 || start > end || end > signals.total_pages => {
+            extraction *= INVALID_PAGE_RANGE;
+            regions.push(region(
+                None,
+                format!(
+                    "page range {}-{} outside document (1-{})",
+                    start, end, signals.total_pages
+                ),
+            ));
+        }
+        Some([start, end]) => {
+            let mut total = 0.0;
+            for page in start..=end {
+                match page_confidence(signals.pages, page, signals.ocr_confidence) {
+                    None => regions.push(region(Some(page), "no OCR text".to_string())),
+                    Some(score) => {
+                        if score < LOW_OCR_CONFIDENCE {
+                            regions
+                                .push(region(Some(page), format!("OCR confidence {:.2}", score)));
+                        }
+                        total += score;
+                    }
+                }
+            }
+            ocr = total / (end - start + 1) as f64;
+
+            let uncovered = uncovered_pages([start, end], &node.children);
+            if !uncovered.is_empty() {
+                let coverage = 1.0 - uncovered.len() as f64 / (end - start + 1) as f64;
+                extraction *= MIN_COVERAGE_FACTOR + (1.0 - MIN_COVERAGE_FACTOR) * coverage;
+                regions.extend(
+                    uncovered.into_iter().map(|page| {
+                        region(Some(page), "not covered by any child node".to_string())
+                    }),
+                );
+            }
+        }
+    }
+
+    match signals
+        .config
+        .node_types
+        .iter()
+        .find(|t| t.id.eq_ignore_ascii_case(&node.node_type))
+    {
+        None if !signals.config.node_types.is_empty() => {
+            extraction *= UNKNOWN_NODE_TYPE;
+            regions.push(region(
+                None,
+                format!("node type {} not in config", node.node_type),
+            ));
+        }
+        Some(node_type) => {
+            if let Some(subtype) = node
+                .subtype
+                .as_deref()
+                .filter(|_| !node_type.subtypes.is_empty())
+            {
+                if !node_type
+                    .subtypes
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(subtype))
+                {
+                    extraction *= UNKNOWN_SUBTYPE;
+                    regions.push(region(
+                        None,
+                        format!("subtype {} not listed for {}", subtype, node_type.id),
+                    ));
+                }
+            }
+        }
+        None => {}
+    }
+
+    let repair_factor = PER_JSON_REPAIR.powi(signals.json_repairs as i32);
+    if signals.json_repairs > 0 {
+        extraction *= repair_factor;
+        regions.push(region(
+            None,
+            format!(
+                "LLM response needed {} JSON repair(s)",
+                signals.json_repairs
+            ),
+        ));
+    }
+
+    // Structure found on unreadable pages is only as good as the OCR
+    let extraction = extraction * (0.5 + 0.5 * ocr);
+    let summary = match node.summary.trim().chars().count() {
+        0 => 0.0,
+        n if n < 40 => 0.6,
+        _ => 0.9,
+    } * repair_factor;
+
+    ConfidenceScores {
+        ocr: Some(round(ocr)),
+        extraction: Some(round(extraction)),
+        summary: Some(round(summary)),
+        low_confidence_regions: regions,
+    }
+}
+
+/// OCR confidence of page `page_num`, or `None` when it produced no text.
+fn page_confidence(pages: &[OcrPage], page_num: u32, fallback: f64) -> Option<f64> {
+    pages
+        .iter()
+        .find(|page| page.page_num == page_num)
+        .filter(|page| !page.text.trim().is_empty())
+        .map(|page| page.confidence.unwrap_or(fallback))
+}
+
+/// Pages of `range` that no child covers (empty when the node has no children).
+fn uncovered_pages(range: [u32; 2], children: &[DocumentNode]) -> Vec<u32> {
+    if children.is_empty() {
+        return Vec::new();
+    }
+    (range[0]..=range[1])
+        .filter(|p| {
+            !children
+                .iter()
+                .filter_map(|c| c.page_range)
+                .any(|[start, end]| (start..=end).contains(p))
+        })
+        .collect()
+}
+
+fn region(page: Option<u32>, reason: String) -> LowConfidenceRegion {
+    LowConfidenceRegion {
+        page,
+        reason: Some(reason),
+    }
+}
+
+fn round(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
+
+/// A node that needs a human look.
+#[derive(Debug, Serialize)]
+pub struct ReviewItem {
+    pub node_id: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_range: Option<[u32; 2]>,
+    pub confidence: f64,
+    pub reasons: Vec<LowConfidenceRegion>,
+}
+
+/// Unreviewed nodes whose extraction confidence is below `threshold`, least
+/// confident first.
+pub fn review_queue(extraction: &Extraction, threshold: f64) -> Vec<ReviewItem> {
+    fn walk(nodes: &[DocumentNode], threshold: f64, out: &mut Vec<ReviewItem>) {
+        for node in nodes {
+            if let Some(scores) = node.confidence.as_ref().filter(|_| !node.reviewed) {
+                let confidence = scores.extraction.unwrap_or(0.0);
+                if confidence < threshold {
+                    out.push(ReviewItem {
+                        node_id: node.id.clone(),
+                        node_type: node.node_type.clone(),
+                        label: node.label.clone(),
+                        page_range: node.page_range,
+                        confidence,
+                        reasons: scores.low_confidence_regions.clone(),
+                    });
+                }
+            }
+            walk(&node.children, threshold, out);
+        }
+    }
+
+    let mut items = Vec::new();
+    walk(&extraction.children, threshold, &mut items);
+    items.sort_by(|a, b| a.confidence.total_cmp(&b.confidence));
+    items
+}
+
+/// OCR quality of one page.
+#[derive(Debug, Serialize)]
+pub struct PageQuality {
+    pub page: u32,
+    /// `None` for pages that produced no text
+    pub confidence: Option<f64>,
+    pub chars: usize,
+    pub low: bool,
+}
+
+/// A node that overlaps low-confidence pages.
+#[derive(Debug, Serialize)]
+pub struct NodeOcrQuality {
+    pub node_id: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub page_range: [u32; 2],
+    /// The node's `confidence.ocr` score
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<f64>,
+    pub low_pages: Vec<u32>,
+}
+
+/// Page-by-page OCR quality of an extraction.
+#[derive(Debug, Serialize)]
+pub struct OcrQualityReport {
+    pub extraction_id: String,
+    pub provider: String,
+    pub ocr_confidence: f64,
+    /// Whether the provider scored pages individually
+    pub per_page_scores: bool,
+    pub threshold: f64,
+    pub total_pages: u32,
+    pub pages: Vec<PageQuality>,
+    pub low_confidence_pages: Vec<u32>,
+    pub nodes: Vec<NodeOcrQuality>,
+}
+
+/// Report which pages fall below `threshold` and which nodes they affect.
+pub fn ocr_quality(extraction: &Extraction, ocr: &OcrResult, threshold: f64) -> OcrQualityReport {
+    fn walk(nodes: &[DocumentNode], low: &[u32], out: &mut Vec<NodeOcrQuality>) {
+        for node in nodes {
+            if let Some([start, end]) = node.page_range {
+                let low_pages: Vec<u32> = low
+                    .iter()
+                    .copied()
+                    .filter(|p| (start..=end).contains(p))
+                    .collect();
+                if !low_pages.is_empty() {
+                    out.push(NodeOcrQuality {
+                        node_id: node.id.clone(),
+                        node_type: node.node_type.clone(),
+                        label: node.label.clone(),
+                        page_range: [start, end],
+                        ocr: node.confidence.as_ref().and_then(|c| c.ocr),
+                        low_pages,
+                    });
+                }
+            }
+            walk(&node.children, low, out);
+        }
+    }
+
+    let total_pages = ocr
+        .pages
+        .iter()
+        .map(|p| p.page_num)
+        .max()
+        .unwrap_or(0)
+        .max(ocr.total_pages);
+    let pages: Vec<PageQuality> = (1..=total_pages)
+        .map(|page| {
+            let confidence = page_confidence(&ocr.pages, page, ocr.ocr_confidence).map(round);
+            PageQuality {
+                page,
+                confidence,
+                chars: ocr
+                    .pages
+                    .iter()
+                    .find(|p| p.page_num == page)
+                    .map_or(0, |p| p.text.trim().chars().count()),
+                low: confidence.is_none_or(|c| c < threshold),
+            }
+        })
+        .collect();
+    let low_confidence_pages: Vec<u32> = pages.iter().filter(|p| p.low).map(|p| p.page).collect();
+    let mut nodes = Vec::new();
+    walk(&extraction.children, &low_confidence_pages, &mut nodes);
+
+    OcrQualityReport {
+        extraction_id: extraction.id.clone(),
+        provider: ocr.provider_name.clone(),
+        ocr_confidence: round(ocr.ocr_confidence),
+        per_page_scores: ocr.pages.iter().any(|p| p.confidence.is_some()),
+        threshold,
+        total_pages,
+        pages,
+        low_confidence_pages,
+        nodes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::create_default_config;
+
+    fn node(id: &str, node_type: &str, page_range: Option<[u32; 2]>) -> DocumentNode {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": node_type,
+            "page_range": page_range,
+            "summary": "Petição inicial pedindo indenização por danos morais e materiais."
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_scores_reflect_signals() {
+        let config = create_default_config();
+        let pages: Vec<OcrPage> = (1..=10)
+            .map(|n| OcrPage {
+                page_num: n,
+                text: if n == 9 {
+                    String::new()
+                } else {
+                    format!("page {}", n)
+                },
+                confidence: None,
+                pdf_page: None,
+            })
+            .collect();
+        let signals = Signals {
+            config: &config,
+            pages: &pages,
+            total_pages: 10,
+            ocr_confidence: 0.95,
+            json_repairs: 0,
+        };
+
+        let mut parent = node("doc", "DOCUMENT", Some([1, 10]));
+        parent.children = vec![
+            node("s1", "SECTION", Some([1, 4])),
+            node("s2", "SECTION", Some([7, 10])),
+        ];
+        let mut nodes = vec![
+            parent,
+            node("clean", "SECTION", Some([1, 2])),
+            node("odd", "CERTIDAO", None),
+        ];
+        score(&mut nodes, &signals);
+
+        let conf = |n: &DocumentNode| n.confidence.as_ref().unwrap().extraction.unwrap();
+        assert_eq!(conf(&nodes[1]), 0.98);
+        // Pages 5-6 uncovered by children, page 9 blank
+        let doc = nodes[0].confidence.as_ref().unwrap();
+        assert_eq!(doc.low_confidence_regions.len(), 3);
+        assert!(conf(&nodes[0]) < conf(&nodes[1]));
+        // Unknown type and no page range
+        assert!(conf(&nodes[2]) < DEFAULT_REVIEW_THRESHOLD);
+
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.children = nodes;
+        let queue = review_queue(&extraction, DEFAULT_REVIEW_THRESHOLD);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue[0].node_id, "odd");
+        assert_eq!(queue[0].reasons.len(), 2);
+
+        // Reviewed nodes leave the queue
+        extraction.children[2].reviewed = true;
+        assert!(review_queue(&extraction, DEFAULT_REVIEW_THRESHOLD).is_empty());
+    }
+
+    #[test]
+    fn test_page_confidence_and_quality_report() {
+        let config = create_default_config();
+        let scores = [Some(0.9), Some(0.4), Some(0.95), None];
+        let ocr = OcrResult {
+            markdown: String::new(),
+            pages: scores
+                .iter()
+                .enumerate()
+                .map(|(i, confidence)| OcrPage {
+                    page_num: i as u32 + 1,
+                    text: format!("page {}", i + 1),
+                    confidence: *confidence,
+                    pdf_page: None,
+                })
+                .collect(),
+            total_pages: 5,
+            metadata: serde_json::Value::Null,
+            ocr_confidence: 0.8,
+            provider_name: "docling".to_string(),
+        };
+        let mut nodes = vec![
+            node("a", "SECTION", Some([1, 2])),
+            node("b", "SECTION", Some([3, 4])),
+        ];
+        score(
+            &mut nodes,
+            &Signals {
+                config: &config,
+                pages: &ocr.pages,
+                total_pages: 5,
+                ocr_confidence: ocr.ocr_confidence,
+                json_repairs: 0,
+            },
+        );
+        let a = nodes[0].confidence.as_ref().unwrap();
+        assert_eq!(a.ocr, Some(0.65));
+        assert_eq!(a.low_confidence_regions.len(), 1);
+        assert_eq!(a.low_confidence_regions[0].page, Some(2));
+        // Page 4 has no score of its own and falls back to the provider's
+        let b = nodes[1].confidence.as_ref().unwrap();
+        assert_eq!(b.ocr, Some(0.88));
+        assert!(b.low_confidence_regions.is_empty());
+
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.children = nodes;
+        let report = ocr_quality(&extraction, &ocr, LOW_OCR_CONFIDENCE);
+        assert!(report.per_page_scores);
+        // Page 5 produced no text
+        assert_eq!(report.low_confidence_pages, vec![2, 5]);
+        assert_eq!(report.pages[3].confidence, Some(0.8));
+        assert_eq!(report.nodes.len(), 1);
+        assert_eq!(report.nodes[0].node_id, "a");
+        assert_eq!(report.nodes[0].low_pages, vec![2]);
+    }
+}
@@ -6,11 +6,16 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 use tracing::info;
 
+use crate::ocr::OcrOptions;
+use crate::pipeline::{self, PipelineStage};
+use crate::redaction::RedactionConfig;
+use crate::signatures::SignatureConfig;
+
 /// Configuration for a specific extraction domain.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtractionConfig {
@@ -29,20 +34,269 @@ pub struct ExtractionConfig {
     /// Hint for extracting a human-readable document identifier (e.g. case number, invoice ID).
     #[serde(default)]
     pub readable_id_hint: Option<String>,
+    /// Regex for the readable identifier, tried against the OCR text before asking the LLM.
+    /// Capture group 1 is used when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readable_id_pattern: Option<String>,
+    /// Language the documents (and prompts) are written in, as an ISO 639-1 code (e.g. "pt").
+    /// When unset, the language detected from the OCR text is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Target language of the `translate` stage (default "en").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate_to: Option<String>,
     /// Sheet extraction config (for tabular data pipelines).
     #[serde(default)]
     pub sheet_config: Option<SheetConfig>,
+    /// Stages to run, in order (see `pipeline::DEFAULT_PIPELINE` for the default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<Vec<PipelineStage>>,
+    /// PII detectors for the `redact` stage (defaults apply when unset).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<RedactionConfig>,
+    /// Options of the `signatures` stage (text only when unset).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signatures: Option<SignatureConfig>,
+    /// Per-stage timeouts; unset stages fall back to the `*_TIMEOUT_SECS` env vars.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<StageTimeouts>,
+    /// Options passed to the OCR provider (the `ocr_options` query parameter overrides them).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_options: Option<OcrOptions>,
+    /// Incoming mail whose attachments this config extracts (see `IMAP_HOST`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mail_rules: Vec<MailRule>,
+    /// Cron expression (UTC) for re-running this config's extractions after it changes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reextract_schedule: Option<String>,
+    /// Purge this config's extractions and datasets after this many days
+    /// (overrides `RETENTION_DAYS`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u32>,
+    /// Limits that keep this config from using up the deployment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quotas: Option<QuotaConfig>,
+    /// Parse `metadata.partes` into structured parties, asking the LLM again
+    /// when they are incomplete (see `partes`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub structured_partes: bool,
+    /// Config that re-extracts exhibit nodes by node type or subtype, e.g.
+    /// `{"Nota Fiscal": "invoice"}` (see `POST /extractions/:id/node/:node_id/extract`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub exhibit_configs: BTreeMap<String, String>,
+    /// Used when a request names no config (see [`ConfigStore::default_config`]).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+}
+
+/// Which endpoints a config serves: sheet configs have a `sheet_config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigKind {
+    Document,
+    Sheet,
+}
+
+impl ConfigKind {
+    /// Env var naming the default config of this kind.
+    pub fn env_var(self) -> &'static str {
+        match self {
+            ConfigKind::Document => "DEFAULT_DOC_CONFIG",
+            ConfigKind::Sheet => "DEFAULT_SHEET_CONFIG",
+        }
+    }
+
+    pub fn of(config: &ExtractionConfig) -> Self {
+        if config.sheet_config.is_some() {
+            ConfigKind::Sheet
+        } else {
+            ConfigKind::Document
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConfigKind::Document => "document",
+            ConfigKind::Sheet => "sheet",
+        })
+    }
+}
+
+/// Which incoming mail a config handles: every field that is set must match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MailRule {
+    /// Regex matched against the sender's address (case-insensitive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Regex matched against the subject (case-insensitive).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// POST each completed extraction or dataset to this URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// OCR provider for PDF attachments (default `docling`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_provider: Option<String>,
+}
+
+/// Per-config limits, checked when a job is submitted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Extractions of this config running or waiting at once; more get 429.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// LLM tokens (prompt + completion) per UTC day; once spent, new jobs get 429.
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    /// LLM spend per UTC day in USD, priced as by `/estimate`.
+    #[serde(default)]
+    pub daily_cost_usd: Option<f64>,
+    /// Pages per document; longer uploads get 413.
+    #[serde(default)]
+    pub max_pages: Option<u32>,
+}
+
+/// Per-stage time limits for an extraction, in seconds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StageTimeouts {
+    #[serde(default)]
+    pub ocr_secs: Option<u64>,
+    #[serde(default)]
+    pub llm_secs: Option<u64>,
+    #[serde(default)]
+    pub upload_secs: Option<u64>,
+}
+
+impl StageTimeouts {
+    /// Resolve the effective timeouts: config value, then env var, then default.
+    pub fn resolve(config: Option<&StageTimeouts>) -> ResolvedTimeouts {
+        let pick = |value: Option<u64>, env: &str, default: u64| {
+            let secs = value
+                .or_else(|| std::env::var(env).ok().and_then(|v| v.parse().ok()))
+                .unwrap_or(default);
+            std::time::Duration::from_secs(secs)
+        };
+        let config = config.cloned().unwrap_or_default();
+        ResolvedTimeouts {
+            ocr: pick(config.ocr_secs, "OCR_TIMEOUT_SECS", 1800),
+            llm: pick(config.llm_secs, "LLM_TIMEOUT_SECS", 900),
+            upload: pick(config.upload_secs, "UPLOAD_TIMEOUT_SECS", 600),
+        }
+    }
+}
+
+/// Effective stage timeouts for one run.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolvedTimeouts {
+    pub ocr: std::time::Duration,
+    pub llm: std::time::Duration,
+    pub upload: std::time::Duration,
 }
 
 /// Configuration for sheet/tabular data extraction.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SheetConfig {
-    /// Expected columns the agent should look for.
+    /// How schemas are found: by the LLM, or from `expected_columns` alone.
+    #[serde(default)]
+    pub mode: SheetMode,
+    /// Expected columns the agent should look for. In deterministic mode
+    /// they are the schema.
     #[serde(default)]
     pub expected_columns: Vec<ExpectedColumn>,
+    /// Name of the schema built in deterministic mode (default: the config
+    /// name).
+    #[serde(default)]
+    pub schema_name: Option<String>,
     /// Business-specific hints injected into the LLM prompt.
     #[serde(default)]
     pub classification_hints: Option<String>,
+    /// Copy the formula of computed Excel columns into their column definitions.
+    #[serde(default)]
+    pub include_formulas: bool,
+    /// UTC offset the workbook's date-times are local to (e.g. `"-03:00"`);
+    /// Excel date-times are written with it.
+    #[serde(default)]
+    pub utc_offset: Option<String>,
+    /// How Excel date cells are written.
+    #[serde(default)]
+    pub date_locale: DateLocale,
+    /// Warehouses each completed dataset's rows are pushed to.
+    #[serde(default)]
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// A warehouse that completed datasets are pushed to, in batches of rows.
+/// Credentials are read from the environment, never stored in the config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    /// Label for the sink in the dataset's `deliveries` (default: its type).
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub target: SinkTarget,
+    /// Rows per request (default 500).
+    #[serde(default)]
+    pub batch_size: Option<usize>,
+}
+
+impl SinkConfig {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(match self.target {
+            SinkTarget::Webhook { .. } => "webhook",
+            SinkTarget::Postgres { .. } => "postgres",
+            SinkTarget::Bigquery { .. } => "bigquery",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkTarget {
+    /// POST NDJSON batches to `url`, with a bearer token from the
+    /// `token_env` variable when set.
+    Webhook {
+        url: String,
+        #[serde(default)]
+        token_env: Option<String>,
+    },
+    /// Upsert into `table` (created if missing) of the database whose URL
+    /// is in the `url_env` variable.
+    Postgres { url_env: String, table: String },
+    /// Stream into `project.dataset.table`, as the service account whose
+    /// key is at `BIGQUERY_SA_KEY_PATH`.
+    Bigquery {
+        project: String,
+        dataset: String,
+        table: String,
+    },
+}
+
+/// How a sheet extraction finds its schemas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SheetMode {
+    /// The LLM discovers schemas from a sample of the data.
+    #[default]
+    Llm,
+    /// One schema made of `expected_columns`, matched to the sheets by
+    /// header or position. The LLM is only used when the match is poor.
+    Deterministic,
+}
+
+/// Output style for Excel date cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateLocale {
+    /// `2024-03-01`, `2024-03-01 09:30:00`
+    #[default]
+    #[serde(rename = "iso")]
+    Iso,
+    /// `01/03/2024`
+    #[serde(rename = "pt-BR")]
+    PtBr,
+    /// `03/01/2024`
+    #[serde(rename = "en-US")]
+    EnUs,
 }
 
 /// A column the agent should expect to find in the data.
@@ -55,6 +309,13 @@ pub struct ExpectedColumn {
     pub format: Option<String>,
     #[serde(default)]
     pub required: bool,
+    /// Other headers this column appears under (`"Data Lançamento"`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Transform applied in deterministic mode; defaults to the one that
+    /// fits `data_type` (`parse_currency_brl` for `currency_brl`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,17 +336,92 @@ pub struct NodeTypeConfig {
     pub label: String,
     #[serde(default)]
     pub subtypes: Vec<String>,
+    /// Fields the LLM fills into `metadata` for nodes of this type
+    /// (same shape as the config's `metadata_schema`).
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub metadata_schema: serde_json::Value,
 }
 
-/// A regex-based entity pattern for extracting structured identifiers from OCR text.
+impl ExtractionConfig {
+    /// The declared node type `name` refers to, by id or label (case and
+    /// accents ignored), e.g. "Petição" → `PETICAO`.
+    pub fn node_type(&self, name: &str) -> Option<&NodeTypeConfig> {
+        let name = fold_name(name);
+        self.node_types
+            .iter()
+            .find(|t| fold_name(&t.id) == name || fold_name(&t.label) == name)
+    }
+
+    /// Check that node type ids are non-empty and unique, and that their
+    /// metadata schemas are JSON objects.
+    pub fn validate_node_types(&self) -> Result<()> {
+        let mut seen = std::collections::HashSet::new();
+        for node_type in &self.node_types {
+            if node_type.id.trim().is_empty() {
+                anyhow::bail!("node type id cannot be empty");
+            }
+            if !seen.insert(node_type.id.to_uppercase()) {
+                anyhow::bail!("node type \"{}\" is declared more than once", node_type.id);
+            }
+            if !(node_type.metadata_schema.is_null() || node_type.metadata_schema.is_object()) {
+                anyhow::bail!(
+                    "metadata_schema of node type \"{}\" must be an object",
+                    node_type.id
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Config named in `exhibit_configs` for a node, matched by its subtype
+    /// first and then its type (case and accents ignored).
+    pub fn exhibit_config(&self, node_type: &str, subtype: Option<&str>) -> Option<&str> {
+        let lookup = |name: &str| {
+            let name = fold_name(name);
+            self.exhibit_configs
+                .iter()
+                .find(|(key, _)| fold_name(key) == name)
+                .map(|(_, config)| config.as_str())
+        };
+        subtype.and_then(lookup).or_else(|| lookup(node_type))
+    }
+}
+
+fn same_config(a: &ExtractionConfig, b: &ExtractionConfig) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+fn fold_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(crate::readable_id::fold_accent)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// An entity pattern for extracting structured identifiers from OCR text:
+/// a regex, or (`"type": "llm"`) a description the LLM looks for.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityPattern {
     /// Unique identifier for this pattern (e.g. "cpf", "pnr", "flight_number")
     pub id: String,
     /// Human-readable label (e.g. "CPF", "PNR / Localizador")
     pub label: String,
-    /// Regex pattern string (should contain a capture group for the value)
+    /// How values are found: `regex` (default) or `llm`
+    #[serde(
+        default,
+        rename = "type",
+        skip_serializing_if = "EntityPatternKind::is_regex"
+    )]
+    pub kind: EntityPatternKind,
+    /// Regex pattern string (should contain a capture group for the value);
+    /// unused by `llm` patterns
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub pattern: String,
+    /// What an `llm` pattern's values are, as told to the LLM (e.g. "Amounts
+    /// claimed in the lawsuit, in reais")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     /// Optional normalization: "uppercase" | "strip_punctuation"
     #[serde(default)]
     pub normalize: Option<String>,
@@ -98,16 +434,57 @@ fn default_true() -> bool {
     true
 }
 
+/// How an [`EntityPattern`] finds its values; also recorded as the `source`
+/// of each reference index occurrence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityPatternKind {
+    /// Regex over node content
+    #[default]
+    Regex,
+    /// Asked of the LLM, node by node (see `Extractor::extract_llm_entities`)
+    Llm,
+}
+
+impl EntityPatternKind {
+    pub fn is_regex(&self) -> bool {
+        *self == Self::Regex
+    }
+}
+
+/// Config names a [`ConfigStore::replace`] added, changed, or dropped.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ConfigChanges {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
 /// In-memory store for all loaded configs, backed by `RwLock` for runtime mutations.
 #[derive(Debug)]
 pub struct ConfigStore {
     configs: Arc<RwLock<HashMap<String, ExtractionConfig>>>,
-    default_config: RwLock<String>,
 }
 
 impl ConfigStore {
     /// Load all configs from the specified directory.
     pub fn load_from_dir(dir: &Path) -> Result<Self> {
+        let configs = Self::read_dir(dir)?;
+
+        Ok(Self {
+            configs: Arc::new(RwLock::new(configs)),
+        })
+    }
+
+    /// Read and validate every `*.json` config in `dir`, failing on the first
+    /// invalid one.
+    pub fn read_dir(dir: &Path) -> Result<HashMap<String, ExtractionConfig>> {
         let mut configs = HashMap::new();
 
         if !dir.exists() {
@@ -124,6 +501,13 @@ impl ConfigStore {
 
                 let config: ExtractionConfig = serde_json::from_str(&content)
                     .with_context(|| format!("Failed to parse config: {:?}", path))?;
+                pipeline::validate(pipeline::stages(&config))
+                    .with_context(|| format!("Invalid pipeline in config: {:?}", path))?;
+                config
+                    .validate_node_types()
+                    .with_context(|| format!("Invalid node_types in config: {:?}", path))?;
+                crate::prompt::check(&config.prompts.structure)
+                    .with_context(|| format!("Invalid prompts.structure in config: {:?}", path))?;
 
                 info!("Loaded config: {} from {:?}", config.name, path);
                 configs.insert(config.name.clone(), config);
@@ -134,12 +518,7 @@ impl ConfigStore {
             anyhow::bail!("No configs found in {:?}", dir);
         }
 
-        let default_config = Self::pick_default(&configs);
-
-        Ok(Self {
-            configs: Arc::new(RwLock::new(configs)),
-            default_config: RwLock::new(default_config),
-        })
+        Ok(configs)
     }
 
     /// Create a ConfigStore from a list of configs (e.g. loaded from Supabase).
@@ -148,16 +527,11 @@ impl ConfigStore {
             anyhow::bail!("No configs provided");
         }
 
-        let map: HashMap<String, ExtractionConfig> = configs
-            .into_iter()
-            .map(|c| (c.name.clone(), c))
-            .collect();
-
-        let default_config = Self::pick_default(&map);
+        let map: HashMap<String, ExtractionConfig> =
+            configs.into_iter().map(|c| (c.name.clone(), c)).collect();
 
         Ok(Self {
             configs: Arc::new(RwLock::new(map)),
-            default_config: RwLock::new(default_config),
         })
     }
 
@@ -166,15 +540,23 @@ impl ConfigStore {
         self.configs.read().unwrap().get(name).cloned()
     }
 
-    /// Get the default config (returns clone).
-    pub fn default_config(&self) -> ExtractionConfig {
-        let default_name = self.default_config.read().unwrap().clone();
+    /// The config for requests that name none: the one `kind`'s env var
+    /// names (`DEFAULT_DOC_CONFIG`, `DEFAULT_SHEET_CONFIG`), else the first by
+    /// name of the `kind` configs marked `"default": true`.
+    pub fn default_config(&self, kind: ConfigKind) -> Option<ExtractionConfig> {
+        if let Some(name) = std::env::var(kind.env_var())
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+        {
+            return self.get(name.trim());
+        }
         self.configs
             .read()
             .unwrap()
-            .get(&default_name)
+            .values()
+            .filter(|c| c.default && ConfigKind::of(c) == kind)
+            .min_by(|a, b| a.name.cmp(&b.name))
             .cloned()
-            .expect("default config must exist")
     }
 
     /// List all available config names.
@@ -200,11 +582,35 @@ impl ConfigStore {
         self.configs.read().unwrap().values().cloned().collect()
     }
 
-    fn pick_default(configs: &HashMap<String, ExtractionConfig>) -> String {
-        configs
-            .get("default")
-            .map(|c| c.name.clone())
-            .unwrap_or_else(|| configs.keys().next().unwrap().clone())
+    /// Swap in a freshly loaded set of configs, dropping those not in it.
+    pub fn replace(&self, configs: HashMap<String, ExtractionConfig>) -> Result<ConfigChanges> {
+        if configs.is_empty() {
+            anyhow::bail!("No configs provided");
+        }
+        let mut current = self.configs.write().unwrap();
+        let mut changes = ConfigChanges::default();
+        for (name, config) in &configs {
+            match current.get(name) {
+                None => changes.added.push(name.clone()),
+                Some(old) if !same_config(old, config) => changes.updated.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.removed = current
+            .keys()
+            .filter(|name| !configs.contains_key(*name))
+            .cloned()
+            .collect();
+        for names in [
+            &mut changes.added,
+            &mut changes.updated,
+            &mut changes.removed,
+        ] {
+            names.sort();
+        }
+
+        *current = configs;
+        Ok(changes)
     }
 }
 
@@ -240,14 +646,106 @@ Return a JSON object with:
             summary: None,
         },
         node_types: vec![
-            NodeTypeConfig { id: "DOCUMENT".to_string(), label: "Document".to_string(), subtypes: vec![] },
-            NodeTypeConfig { id: "SECTION".to_string(), label: "Section".to_string(), subtypes: vec![] },
-            NodeTypeConfig { id: "GROUP".to_string(), label: "Group".to_string(), subtypes: vec![] },
+            NodeTypeConfig { id: "DOCUMENT".to_string(), label: "Document".to_string(), subtypes: vec![], metadata_schema: serde_json::Value::Null },
+            NodeTypeConfig { id: "SECTION".to_string(), label: "Section".to_string(), subtypes: vec![], metadata_schema: serde_json::Value::Null },
+            NodeTypeConfig { id: "GROUP".to_string(), label: "Group".to_string(), subtypes: vec![], metadata_schema: serde_json::Value::Null },
         ],
         relationship_types: vec!["references".to_string(), "contains".to_string()],
         metadata_schema: serde_json::json!({}),
         entity_patterns: Vec::new(),
         readable_id_hint: None,
+        readable_id_pattern: None,
+        language: None,
+        translate_to: None,
+        pipeline: None,
+        redaction: None,
+        signatures: None,
         sheet_config: None,
+        timeouts: None,
+        ocr_options: None,
+        mail_rules: Vec::new(),
+        reextract_schedule: None,
+        retention_days: None,
+        quotas: None,
+        structured_partes: false,
+        exhibit_configs: BTreeMap::new(),
+        default: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shipped_configs_and_node_types() {
+        let store = ConfigStore::load_from_dir(Path::new("configs")).unwrap();
+        for name in [
+            "legal_br",
+            "invoice",
+            "contract",
+            "medical_record",
+            "tax_filing",
+        ] {
+            let config = store.get(name).unwrap();
+            config.validate_node_types().unwrap();
+            crate::prompt::check(&config.prompts.structure).unwrap();
+            for pattern in config.entity_patterns.iter().filter(|p| p.kind.is_regex()) {
+                regex::Regex::new(&pattern.pattern).unwrap();
+            }
+        }
+
+        let legal = store.get("legal_br").unwrap();
+        assert_eq!(legal.node_type("Petição").unwrap().id, "PETICAO");
+        assert_eq!(legal.node_type("decisao").unwrap().id, "DECISAO");
+        assert!(legal.node_type("INVOICE").is_none());
+        let invoice = store.get("invoice").unwrap();
+        assert!(invoice.node_type("line items").unwrap().metadata_schema["items"].is_object());
+        assert_eq!(
+            legal.exhibit_config("DOCUMENTO", Some("nota fiscal")),
+            Some("invoice")
+        );
+        assert_eq!(legal.exhibit_config("DOCUMENTO", Some("Laudo")), None);
+
+        let mut config = create_default_config();
+        config.node_types[1].id = "document".into();
+        assert!(config
+            .validate_node_types()
+            .unwrap_err()
+            .to_string()
+            .contains("more than once"));
+        config.node_types[1].id = "SECTION".into();
+        config.node_types[1].metadata_schema = serde_json::json!(["not", "an", "object"]);
+        assert!(config.validate_node_types().is_err());
+    }
+
+    #[test]
+    fn test_replace_reports_changes() {
+        let store = ConfigStore::from_configs(vec![create_default_config()]).unwrap();
+        let mut configs = ConfigStore::read_dir(Path::new("configs")).unwrap();
+        let changes = store.replace(configs.clone()).unwrap();
+        assert!(changes.added.contains(&"legal_br".to_string()));
+        assert_eq!(changes.removed, vec!["default".to_string()]);
+        assert!(store.get("default").is_none());
+        assert_eq!(
+            store.default_config(ConfigKind::Document).unwrap().name,
+            "legal_br"
+        );
+        assert_eq!(
+            store.default_config(ConfigKind::Sheet).unwrap().name,
+            "financial_br"
+        );
+
+        configs.get_mut("invoice").unwrap().prompts.structure = "v2".into();
+        configs.remove("contract");
+        let changes = store.replace(configs).unwrap();
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.updated, vec!["invoice".to_string()]);
+        assert_eq!(changes.removed, vec!["contract".to_string()]);
+
+        // Without a config marked default, nothing is picked
+        let store = ConfigStore::from_configs(vec![create_default_config()]).unwrap();
+        assert!(store.default_config(ConfigKind::Document).is_none());
+        assert!(store.replace(HashMap::new()).is_err());
     }
 }
@@ -0,0 +1,148 @@
+//! Version history of extraction configs.
+//!
+//! Every config the server loads or saves is appended to
+//! `{CONFIG_HISTORY_DIR}/{name}.jsonl` (default `data/config_history`) unless
+//! it is unchanged. A version is the config's fingerprint (see
+//! [`crate::scheduler::fingerprint`]), the same hash extractions record as
+//! `config_version`, so an extraction can be traced back to the exact prompt
+//! and schema it ran with and a bad change can be rolled back.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::config::ExtractionConfig;
+use crate::safe_name;
+use crate::scheduler::fingerprint;
+
+/// One saved version of a config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigVersion {
+    pub version: String,
+    pub saved_at: String,
+    pub config: ExtractionConfig,
+}
+
+/// Append-only config history on disk.
+pub struct ConfigHistory {
+    dir: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl ConfigHistory {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Open the history in `CONFIG_HISTORY_DIR` (default `data/config_history`).
+    pub fn from_env() -> Result<Self> {
+        Self::open(
+            std::env::var("CONFIG_HISTORY_DIR")
+                .unwrap_or_else(|_| "data/config_history".to_string()),
+        )
+    }
+
+    /// Record `config` as its latest version. Saving the version that is
+    /// already the latest appends nothing.
+    pub fn record(&self, config: &ExtractionConfig) -> Result<ConfigVersion> {
+        let _guard = self.write_lock.lock().unwrap();
+        let version = fingerprint(config);
+        if let Some(latest) = self.versions(&config.name).pop() {
+            if latest.version == version {
+                return Ok(latest);
+            }
+        }
+        let entry = ConfigVersion {
+            version,
+            saved_at: crate::schema::now_iso8601(),
+            config: config.clone(),
+        };
+        let path = self.path(&config.name);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(entry)
+    }
+
+    /// All recorded versions of a config, oldest first.
+    pub fn versions(&self, name: &str) -> Vec<ConfigVersion> {
+        read_lines(&self.path(name))
+    }
+
+    /// The latest saved entry of `version`.
+    pub fn find(&self, name: &str, version: &str) -> Option<ConfigVersion> {
+        self.versions(name)
+            .into_iter()
+            .rev()
+            .find(|v| v.version == version && v.config.name == name)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.jsonl", safe_name::file_name(name)))
+    }
+}
+
+fn read_lines(path: &Path) -> Vec<ConfigVersion> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            error!("Failed to read config history {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                error!("Skipping unreadable entry in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_find_versions() {
+        let dir = std::env::temp_dir().join(format!("config_history_{}", uuid::Uuid::new_v4()));
+        let history = ConfigHistory::open(&dir).unwrap();
+        let mut config = crate::config::create_default_config();
+
+        let first = history.record(&config).unwrap();
+        assert_eq!(history.record(&config).unwrap().version, first.version);
+        config.prompts.structure = "v2".to_string();
+        let second = history.record(&config).unwrap();
+        assert_ne!(second.version, first.version);
+
+        let versions = history.versions(&config.name);
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, first.version);
+        let found = history.find(&config.name, &first.version).unwrap();
+        assert_ne!(found.config.prompts.structure, "v2");
+        assert!(history.find(&config.name, "unknown").is_none());
+        assert!(history.versions("other").is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,459 @@
+//! Checks a config before it is saved.
+//!
+//! [`lint`] collects every problem at once instead of stopping at the first,
+//! so `POST /configs/validate` can show them all. Errors are what would make
+//! the config rejected on save or fail at extraction time (invalid regexes,
+//! schemas, templates, pipelines); warnings are likely mistakes that still
+//! run, such as a relationship type listed twice. Paths are JSON pointers
+//! into the config, e.g. `/entity_patterns/2/pattern`.
+
+use std::collections::HashSet;
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::config::{EntityPatternKind, ExtractionConfig, SheetMode, SinkTarget};
+use crate::numeric::NumberLocale;
+use crate::{metadata, pipeline, prompt, redaction, scheduler, sheet_parser, sinks};
+
+/// Column types the sheet extractor asks the LLM for.
+const SHEET_DATA_TYPES: &[&str] = &[
+    "string",
+    "integer",
+    "float",
+    "date",
+    "currency_brl",
+    "boolean",
+];
+const NORMALIZATIONS: &[&str] = &["uppercase", "strip_punctuation"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigIssue {
+    /// JSON pointer to the offending field (empty for the whole config)
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LintReport {
+    pub valid: bool,
+    pub errors: Vec<ConfigIssue>,
+    pub warnings: Vec<ConfigIssue>,
+}
+
+impl LintReport {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(ConfigIssue {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warn(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.warnings.push(ConfigIssue {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    /// A report for a body that is not a config at all.
+    pub fn unparsable(message: impl Into<String>) -> Self {
+        let mut report = Self::default();
+        report.error("", message);
+        report
+    }
+}
+
+/// Check everything in `config` that can be checked without running it.
+pub fn lint(config: &ExtractionConfig) -> LintReport {
+    let mut report = LintReport::default();
+
+    if config.name.trim().is_empty() {
+        report.error("/name", "name cannot be empty");
+    }
+    lint_prompts(config, &mut report);
+    if let Err(e) = pipeline::validate(pipeline::stages(config)) {
+        report.error("/pipeline", e.to_string());
+    }
+    lint_types(config, &mut report);
+    if let Err(e) = metadata::check_schema(&config.metadata_schema) {
+        report.error(
+            "/metadata_schema",
+            format!("not a valid JSON Schema: {}", e),
+        );
+    }
+    if config.structured_partes
+        && config
+            .metadata_schema
+            .as_object()
+            .is_some_and(|s| !s.is_empty() && !s.contains_key("partes"))
+    {
+        report.warn(
+            "/structured_partes",
+            "structured_partes is on but metadata_schema has no partes field",
+        );
+    }
+    lint_patterns(config, &mut report);
+    lint_sheet_config(config, &mut report);
+    if let Some(ref schedule) = config.reextract_schedule {
+        if let Err(e) = scheduler::Cron::parse(schedule) {
+            report.error("/reextract_schedule", e.to_string());
+        }
+    }
+
+    report.valid = report.errors.is_empty();
+    report
+}
+
+fn lint_prompts(config: &ExtractionConfig, report: &mut LintReport) {
+    let structure = &config.prompts.structure;
+    if structure.trim().is_empty() {
+        report.error("/prompts/structure", "prompts.structure cannot be empty");
+        return;
+    }
+    if let Err(e) = prompt::check(structure) {
+        report.error("/prompts/structure", format!("{:#}", e));
+    }
+    if !structure.to_lowercase().contains("json") {
+        report.warn(
+            "/prompts/structure",
+            "prompt does not mention JSON; the structure call expects a JSON answer",
+        );
+    }
+}
+
+fn lint_types(config: &ExtractionConfig, report: &mut LintReport) {
+    if config.node_types.is_empty() {
+        report.warn(
+            "/node_types",
+            "no node types; the prompt falls back to DOCUMENT|SECTION|GROUP",
+        );
+    }
+    let mut ids = HashSet::new();
+    for (i, node_type) in config.node_types.iter().enumerate() {
+        let path = format!("/node_types/{}", i);
+        if node_type.id.trim().is_empty() {
+            report.error(format!("{}/id", path), "node type id cannot be empty");
+        } else if !ids.insert(node_type.id.to_uppercase()) {
+            report.error(
+                format!("{}/id", path),
+                format!("node type \"{}\" is declared more than once", node_type.id),
+            );
+        }
+        let schema = &node_type.metadata_schema;
+        if !(schema.is_null() || schema.is_object()) {
+            report.error(
+                format!("{}/metadata_schema", path),
+                "metadata_schema must be an object",
+            );
+        } else if let Err(e) = metadata::check_schema(schema) {
+            report.error(
+                format!("{}/metadata_schema", path),
+                format!("not a valid JSON Schema: {}", e),
+            );
+        }
+        let mut subtypes = HashSet::new();
+        for subtype in &node_type.subtypes {
+            if !subtypes.insert(subtype.to_lowercase()) {
+                report.warn(
+                    format!("{}/subtypes", path),
+                    format!("subtype \"{}\" is listed more than once", subtype),
+                );
+            }
+        }
+    }
+
+    let mut relationships = HashSet::new();
+    for (i, rel_type) in config.relationship_types.iter().enumerate() {
+        let path = format!("/relationship_types/{}", i);
+        if rel_type.trim().is_empty() {
+            report.error(path, "relationship type cannot be empty");
+        } else if !relationships.insert(rel_type.to_lowercase()) {
+            report.warn(
+                path,
+                format!(
+                    "relationship type \"{}\" is listed more than once",
+                    rel_type
+                ),
+            );
+        } else if ids.contains(&rel_type.to_uppercase()) {
+            report.warn(
+                path,
+                format!(
+                    "\"{}\" is both a node type and a relationship type",
+                    rel_type
+                ),
+            );
+        }
+    }
+}
+
+fn lint_patterns(config: &ExtractionConfig, report: &mut LintReport) {
+    let mut ids = HashSet::new();
+    for (i, pattern) in config.entity_patterns.iter().enumerate() {
+        let path = format!("/entity_patterns/{}", i);
+        if pattern.id.trim().is_empty() {
+            report.error(format!("{}/id", path), "entity pattern id cannot be empty");
+        } else if !ids.insert(pattern.id.as_str()) {
+            report.error(
+                format!("{}/id", path),
+                format!(
+                    "entity pattern \"{}\" is declared more than once",
+                    pattern.id
+                ),
+            );
+        }
+        match pattern.kind {
+            EntityPatternKind::Llm => {
+                if pattern
+                    .description
+                    .as_deref()
+                    .is_none_or(|d| d.trim().is_empty())
+                {
+                    report.warn(
+                        format!("{}/description", path),
+                        "no description; the LLM is only given the label",
+                    );
+                }
+            }
+            EntityPatternKind::Regex => match Regex::new(&pattern.pattern) {
+                Ok(_) if pattern.pattern.is_empty() => report.error(
+                    format!("{}/pattern", path),
+                    "regex entity patterns need a pattern",
+                ),
+                Ok(regex) if regex.captures_len() < 2 => report.warn(
+                    format!("{}/pattern", path),
+                    "no capture group; the whole match is used as the value",
+                ),
+                Ok(_) => {}
+                Err(e) => report.error(format!("{}/pattern", path), e.to_string()),
+            },
+        }
+        if let Some(ref normalize) = pattern.normalize {
+            if !NORMALIZATIONS.contains(&normalize.as_str()) {
+                report.warn(
+                    format!("{}/normalize", path),
+                    format!(
+                        "unknown normalization \"{}\" (expected one of {})",
+                        normalize,
+                        NORMALIZATIONS.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    if let Some(ref pattern) = config.readable_id_pattern {
+        if let Err(e) = Regex::new(pattern) {
+            report.error("/readable_id_pattern", e.to_string());
+        }
+    }
+
+    if let Some(ref redaction) = config.redaction {
+        for (i, detector) in redaction.detectors.iter().enumerate() {
+            if !redaction::is_builtin_detector(detector) {
+                report.error(
+                    format!("/redaction/detectors/{}", i),
+                    format!("unknown detector \"{}\"", detector),
+                );
+            }
+        }
+        for (i, id) in redaction.entity_patterns.iter().enumerate() {
+            if !ids.contains(id.as_str()) {
+                report.error(
+                    format!("/redaction/entity_patterns/{}", i),
+                    format!("no entity pattern with id \"{}\"", id),
+                );
+            } else if config
+                .entity_patterns
+                .iter()
+                .any(|p| &p.id == id && !p.kind.is_regex())
+            {
+                report.warn(
+                    format!("/redaction/entity_patterns/{}", i),
+                    format!(
+                        "\"{}\" is an llm entity pattern; only regex patterns are redacted",
+                        id
+                    ),
+                );
+            }
+        }
+    }
+
+    for (i, rule) in config.mail_rules.iter().enumerate() {
+        for (field, pattern) in [("from", &rule.from), ("subject", &rule.subject)] {
+            let Some(pattern) = pattern else {
+                continue;
+            };
+            if let Err(e) = RegexBuilder::new(pattern).case_insensitive(true).build() {
+                report.error(format!("/mail_rules/{}/{}", i, field), e.to_string());
+            }
+        }
+    }
+}
+
+fn lint_sheet_config(config: &ExtractionConfig, report: &mut LintReport) {
+    let Some(ref sheet) = config.sheet_config else {
+        return;
+    };
+    if let Some(ref offset) = sheet.utc_offset {
+        if sheet_parser::parse_utc_offset(offset).is_none() {
+            report.error(
+                "/sheet_config/utc_offset",
+                format!("\"{}\" is not a UTC offset like \"-03:00\"", offset),
+            );
+        }
+    }
+    if sheet.mode == SheetMode::Deterministic && sheet.expected_columns.is_empty() {
+        report.error(
+            "/sheet_config/expected_columns",
+            "deterministic mode needs expected_columns to build its schema from",
+        );
+    }
+    let mut names = HashSet::new();
+    for (i, column) in sheet.expected_columns.iter().enumerate() {
+        let path = format!("/sheet_config/expected_columns/{}", i);
+        if column.name.trim().is_empty() {
+            report.error(format!("{}/name", path), "column name cannot be empty");
+        } else if !names.insert(column.name.to_lowercase()) {
+            report.error(
+                format!("{}/name", path),
+                format!("column \"{}\" is listed more than once", column.name),
+            );
+        }
+        if let Some(ref data_type) = column.data_type {
+            if !SHEET_DATA_TYPES.contains(&data_type.as_str()) {
+                report.warn(
+                    format!("{}/data_type", path),
+                    format!(
+                        "unknown data_type \"{}\" (expected one of {})",
+                        data_type,
+                        SHEET_DATA_TYPES.join(", ")
+                    ),
+                );
+            }
+        }
+        if let Some(ref transform) = column.transform {
+            if NumberLocale::for_transform(transform).is_none() {
+                report.warn(
+                    format!("{}/transform", path),
+                    format!(
+                        "\"{}\" is not applied; only numeric transforms (parse_currency_brl, parse_currency_usd, to_number, to_integer) are",
+                        transform
+                    ),
+                );
+            }
+        }
+    }
+
+    let mut labels = HashSet::new();
+    for (i, sink) in sheet.sinks.iter().enumerate() {
+        let path = format!("/sheet_config/sinks/{}", i);
+        if !labels.insert(sink.label()) {
+            report.error(
+                format!("{}/name", path),
+                format!(
+                    "sink \"{}\" is listed more than once; give each a distinct name",
+                    sink.label()
+                ),
+            );
+        }
+        if sink.batch_size == Some(0) {
+            report.error(
+                format!("{}/batch_size", path),
+                "batch_size must be at least 1",
+            );
+        }
+        match sink.target {
+            SinkTarget::Webhook { ref url, .. } => {
+                if !url.starts_with("https://") && !url.starts_with("http://") {
+                    report.error(
+                        format!("{}/url", path),
+                        format!("\"{}\" is not an http(s) URL", url),
+                    );
+                }
+            }
+            SinkTarget::Postgres { ref table, .. } => {
+                if !sinks::is_table_name(table) {
+                    report.error(
+                        format!("{}/table", path),
+                        format!("\"{}\" is not a table name like \"analytics.rows\"", table),
+                    );
+                }
+            }
+            SinkTarget::Bigquery { .. } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigStore, EntityPattern};
+
+    #[test]
+    fn test_shipped_configs_pass() {
+        let store = ConfigStore::load_from_dir(std::path::Path::new("configs")).unwrap();
+        for config in store.all() {
+            let report = lint(&config);
+            assert!(report.valid, "{}: {:?}", config.name, report.errors);
+        }
+    }
+
+    #[test]
+    fn test_lint_reports_every_problem() {
+        let mut config = crate::config::create_default_config();
+        config.prompts.structure = "Types: {{node_typs}}".into();
+        config.metadata_schema = serde_json::json!({"valor": {"type": "money"}});
+        config.relationship_types = vec!["references".into(), "references".into()];
+        config.entity_patterns.push(EntityPattern {
+            id: "broken".into(),
+            label: "Broken".into(),
+            kind: EntityPatternKind::Regex,
+            pattern: "(unclosed".into(),
+            description: None,
+            normalize: Some("lowercase".into()),
+            deduplicate: true,
+        });
+        config.entity_patterns.push(EntityPattern {
+            id: "valor".into(),
+            label: "Valor".into(),
+            kind: EntityPatternKind::Llm,
+            pattern: String::new(),
+            description: None,
+            normalize: None,
+            deduplicate: true,
+        });
+        config.reextract_schedule = Some("every night".into());
+        config.sheet_config = Some(
+            serde_json::from_value(serde_json::json!({
+                "mode": "deterministic",
+                "sinks": [{"type": "postgres", "url_env": "WAREHOUSE_URL", "table": "rows; --"}],
+            }))
+            .unwrap(),
+        );
+
+        let report = lint(&config);
+        assert!(!report.valid);
+        let error_paths: Vec<&str> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        for path in [
+            "/prompts/structure",
+            "/metadata_schema",
+            "/entity_patterns/0/pattern",
+            "/reextract_schedule",
+            "/sheet_config/expected_columns",
+            "/sheet_config/sinks/0/table",
+        ] {
+            assert!(error_paths.contains(&path), "{:?}", error_paths);
+        }
+        let warning_paths: Vec<&str> = report.warnings.iter().map(|w| w.path.as_str()).collect();
+        for path in [
+            "/prompts/structure",
+            "/relationship_types/1",
+            "/entity_patterns/0/normalize",
+            "/entity_patterns/1/description",
+        ] {
+            assert!(warning_paths.contains(&path), "{:?}", warning_paths);
+        }
+    }
+}
@@ -1,8 +1,23 @@
 #![allow(dead_code)]
 //! Content store for lazy-loaded document content with pagination support.
+//!
+//! Content lives in a size-bounded in-memory LRU. When a disk tier is
+//! configured, every entry is also written to a file under the content
+//! directory (zstd-compressed per `compression::Compression`) and evicted entries are
+//! transparently reloaded on access.
 
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::compression::{self, Compression};
+use crate::safe_name;
+
+/// Default disk tier location.
+pub const DEFAULT_CONTENT_DIR: &str = "data/content";
+
+/// Default in-memory budget (bytes) when the disk tier is enabled.
+pub const DEFAULT_MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
 
 /// Response from content retrieval with pagination info.
 #[derive(Debug, Clone, serde::Serialize)]
@@ -14,30 +29,290 @@ pub struct ContentChunk {
     pub has_more: bool,
 }
 
-/// In-memory content store.
+/// Cache counters for the memory tier.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ContentStoreStats {
+    pub entries_in_memory: usize,
+    pub memory_bytes: usize,
+    pub max_memory_bytes: Option<usize>,
+    pub disk_enabled: bool,
+    pub hits: u64,
+    pub misses: u64,
+    pub disk_loads: u64,
+    pub evictions: u64,
+    pub disk_write_errors: u64,
+    /// zstd level for the disk tier (0 = uncompressed).
+    pub compression_level: i32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    content: String,
+    last_used: u64,
+    /// Whether the content is safely on disk (and may therefore be evicted).
+    on_disk: bool,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    /// `last_used` tick -> node_id, oldest first.
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    memory_bytes: usize,
+    stats: ContentStoreStats,
+}
+
+impl Inner {
+    fn touch(&mut self, node_id: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(node_id) {
+            self.lru.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.lru.insert(tick, node_id.to_string());
+        }
+    }
+
+    fn insert(&mut self, node_id: &str, content: String, on_disk: bool) {
+        self.remove(node_id);
+        self.tick += 1;
+        self.memory_bytes += content.len();
+        self.lru.insert(self.tick, node_id.to_string());
+        self.entries.insert(
+            node_id.to_string(),
+            Entry {
+                content,
+                last_used: self.tick,
+                on_disk,
+            },
+        );
+    }
+
+    fn remove(&mut self, node_id: &str) {
+        if let Some(old) = self.entries.remove(node_id) {
+            self.lru.remove(&old.last_used);
+            self.memory_bytes -= old.content.len();
+        }
+    }
+
+    /// Evict least-recently-used entries that are backed by disk until under budget.
+    fn evict_to(&mut self, max_bytes: usize) {
+        if self.memory_bytes <= max_bytes {
+            return;
+        }
+        // Walk oldest first, stopping as soon as enough is freed
+        let mut excess = self.memory_bytes - max_bytes;
+        let mut evicted = Vec::new();
+        for node_id in self.lru.values() {
+            if excess == 0 {
+                break;
+            }
+            if let Some(entry) = self.entries.get(node_id).filter(|e| e.on_disk) {
+                excess = excess.saturating_sub(entry.content.len());
+                evicted.push(node_id.clone());
+            }
+        }
+        for node_id in evicted {
+            self.remove(&node_id);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// Content store with an optional disk tier.
 ///
 /// Stores full text content and serves it with pagination support.
 /// Content refs have format: `content://{node_id}`
 #[derive(Debug, Clone, Default)]
 pub struct ContentStore {
-    inner: Arc<RwLock<HashMap<String, String>>>,
+    inner: Arc<Mutex<Inner>>,
+    /// Disk tier directory; `None` keeps everything in memory (unbounded).
+    dir: Option<Arc<PathBuf>>,
+    max_memory_bytes: Option<usize>,
+    compression: Compression,
+    mirror: Option<Mirror>,
+}
+
+type MirrorFn = dyn Fn(&str, Option<&str>) + Send + Sync;
+
+/// Receives every store (and, with `None`, every removal).
+#[derive(Clone)]
+struct Mirror(Arc<MirrorFn>);
+
+impl std::fmt::Debug for Mirror {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Mirror")
+    }
 }
 
 impl ContentStore {
+    /// Memory-only store without a size bound.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Store backed by files under `dir`, keeping at most `max_memory_bytes` in memory.
+    pub fn with_disk(dir: impl Into<PathBuf>, max_memory_bytes: usize) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner: Arc::default(),
+            dir: Some(Arc::new(dir)),
+            max_memory_bytes: Some(max_memory_bytes),
+            compression: Compression::default(),
+            mirror: None,
+        })
+    }
+
+    /// Copy every stored and removed entry to `f`, e.g. a shared cache.
+    pub fn with_mirror(mut self, f: impl Fn(&str, Option<&str>) + Send + Sync + 'static) -> Self {
+        self.mirror = Some(Mirror(Arc::new(f)));
+        self
+    }
+
+    /// Set the compression used for files in the disk tier.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Build from `CONTENT_DIR` / `CONTENT_MAX_MEMORY_BYTES` / `CONTENT_ZSTD_LEVEL`,
+    /// falling back to memory-only if the directory cannot be created.
+    pub fn from_env() -> Self {
+        let dir = std::env::var("CONTENT_DIR").unwrap_or_else(|_| DEFAULT_CONTENT_DIR.to_string());
+        let max_bytes = std::env::var("CONTENT_MAX_MEMORY_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_MEMORY_BYTES);
+
+        match Self::with_disk(&dir, max_bytes) {
+            Ok(store) => store.with_compression(Compression::from_env()),
+            Err(e) => {
+                tracing::error!(
+                    "ContentStore: cannot use {} ({}), keeping content in memory only",
+                    dir,
+                    e
+                );
+                Self::new()
+            }
+        }
+    }
+
+    /// Plain and compressed file paths for a node's content in the disk tier.
+    fn paths_for(&self, node_id: &str) -> Option<(PathBuf, PathBuf)> {
+        let dir = self.dir.as_ref()?;
+        let name = safe_name::file_name(node_id);
+        Some((
+            dir.join(format!("{}.txt", name)),
+            dir.join(format!("{}.txt.zst", name)),
+        ))
+    }
+
+    /// Read a node's content from the disk tier (compressed or plain).
+    fn read_disk(&self, node_id: &str) -> Option<String> {
+        let (plain, zst) = self.paths_for(node_id)?;
+        if let Ok(data) = std::fs::read(&zst) {
+            return match compression::decompress(&data) {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    tracing::error!("ContentStore: corrupt {}: {}", zst.display(), e);
+                    None
+                }
+            };
+        }
+        std::fs::read_to_string(&plain).ok()
+    }
+
+    /// Write a node's content to the disk tier, removing any stale variant.
+    fn write_disk(&self, node_id: &str, content: &str) -> Option<std::io::Result<()>> {
+        let (plain, zst) = self.paths_for(node_id)?;
+        let (path, data, stale) = match self.compression.compress(content) {
+            Some(bytes) => (zst, bytes, plain),
+            None => (plain, content.as_bytes().to_vec(), zst),
+        };
+        let result = std::fs::write(&path, data).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+        });
+        if result.is_ok() {
+            let _ = std::fs::remove_file(stale);
+        }
+        Some(result)
+    }
+
+    /// Look up content by node id, loading it from disk on a memory miss.
+    fn load(&self, node_id: &str) -> Option<String> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(entry) = inner.entries.get(node_id) {
+                let content = entry.content.clone();
+                inner.stats.hits += 1;
+                inner.touch(node_id);
+                return Some(content);
+            }
+            inner.stats.misses += 1;
+        }
+
+        let content = self.read_disk(node_id)?;
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.stats.disk_loads += 1;
+        if let Some(max) = self.max_memory_bytes {
+            if content.len() <= max {
+                inner.insert(node_id, content.clone(), true);
+                inner.evict_to(max);
+            }
+        }
+        Some(content)
+    }
+
     /// Store content for a node, returns the content ref.
     pub fn store(&self, node_id: &str, content: String) -> String {
+        if let Some(Mirror(ref mirror)) = self.mirror {
+            mirror(node_id, Some(&content));
+        }
+        self.cache(node_id, content)
+    }
+
+    /// Store content that came from the mirror, without copying it back.
+    pub fn cache(&self, node_id: &str, content: String) -> String {
         let content_ref = format!("content://{}", node_id);
         let content_len = content.len();
-        let mut store = self.inner.write().unwrap();
-        store.insert(node_id.to_string(), content);
+
+        let on_disk = match self.write_disk(node_id, &content) {
+            Some(result) => match result {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::error!("ContentStore: failed to write {}, keeping in memory", e);
+                    self.inner.lock().unwrap().stats.disk_write_errors += 1;
+                    false
+                }
+            },
+            None => false,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.insert(node_id, content, on_disk);
+        if let Some(max) = self.max_memory_bytes {
+            inner.evict_to(max);
+        }
         tracing::debug!("ContentStore: stored '{}' ({} chars)", node_id, content_len);
         content_ref
     }
 
+    /// Snapshot of cache counters.
+    pub fn stats(&self) -> ContentStoreStats {
+        let inner = self.inner.lock().unwrap();
+        ContentStoreStats {
+            entries_in_memory: inner.entries.len(),
+            memory_bytes: inner.memory_bytes,
+            max_memory_bytes: self.max_memory_bytes,
+            disk_enabled: self.dir.is_some(),
+            compression_level: self.compression.level(),
+            ..inner.stats.clone()
+        }
+    }
+
     /// Retrieve content with pagination.
     ///
     /// - `content_ref`: The content reference (e.g., `content://node_id`)
@@ -45,8 +320,7 @@ impl ContentStore {
     /// - `limit`: Maximum characters to return
     pub fn get(&self, content_ref: &str, offset: usize, limit: usize) -> Option<ContentChunk> {
         let node_id = content_ref.strip_prefix("content://")?;
-        let store = self.inner.read().unwrap();
-        let content = store.get(node_id)?;
+        let content = self.load(node_id)?;
 
         let total_chars = content.chars().count();
 
@@ -78,25 +352,47 @@ impl ContentStore {
     /// Get full content without pagination.
     pub fn get_full(&self, content_ref: &str) -> Option<String> {
         let node_id = content_ref.strip_prefix("content://")?;
-        let store = self.inner.read().unwrap();
-        store.get(node_id).cloned()
+        self.load(node_id)
     }
 
     /// Check if content exists.
     pub fn exists(&self, content_ref: &str) -> bool {
-        if let Some(node_id) = content_ref.strip_prefix("content://") {
-            let store = self.inner.read().unwrap();
-            store.contains_key(node_id)
-        } else {
-            false
+        let Some(node_id) = content_ref.strip_prefix("content://") else {
+            return false;
+        };
+        if self.inner.lock().unwrap().entries.contains_key(node_id) {
+            return true;
+        }
+        self.paths_for(node_id)
+            .is_some_and(|(plain, zst)| plain.exists() || zst.exists())
+    }
+
+    /// Drop content from memory and the disk tier. Returns true if it existed.
+    pub fn remove(&self, content_ref: &str) -> bool {
+        let Some(node_id) = content_ref.strip_prefix("content://") else {
+            return false;
+        };
+        let mut existed = {
+            let mut inner = self.inner.lock().unwrap();
+            let existed = inner.entries.contains_key(node_id);
+            inner.remove(node_id);
+            existed
+        };
+        if let Some(Mirror(ref mirror)) = self.mirror {
+            mirror(node_id, None);
+        }
+        if let Some((plain, zst)) = self.paths_for(node_id) {
+            for path in [plain, zst] {
+                existed |= std::fs::remove_file(path).is_ok();
+            }
         }
+        existed
     }
 
     /// Get total character count for a content ref.
     pub fn len(&self, content_ref: &str) -> Option<usize> {
         let node_id = content_ref.strip_prefix("content://")?;
-        let store = self.inner.read().unwrap();
-        store.get(node_id).map(|s| s.chars().count())
+        self.load(node_id).map(|s| s.chars().count())
     }
 }
 
@@ -142,4 +438,77 @@ mod tests {
         let chunk = store.get("content://utf8", 0, 10).unwrap();
         assert_eq!(chunk.content, "Olá, você ");
     }
+
+    #[test]
+    fn test_disk_tier_eviction() {
+        let dir = std::env::temp_dir().join(format!(
+            "content_store_test_{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let store = ContentStore::with_disk(&dir, 10).unwrap();
+
+        store.store("a", "aaaaaa".to_string());
+        store.store("b", "bbbbbb".to_string()); // 12 bytes > 10: evicts "a"
+
+        let stats = store.stats();
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.entries_in_memory, 1);
+        assert_eq!(stats.memory_bytes, 6);
+
+        // Evicted content is reloaded from disk
+        assert_eq!(store.get_full("content://a").unwrap(), "aaaaaa");
+        assert_eq!(store.stats().disk_loads, 1);
+        assert!(store.exists("content://b"));
+
+        // Oversized entries are served from disk without being cached
+        store.store("big", "x".repeat(50));
+        assert_eq!(store.len("content://big"), Some(50));
+        assert!(store.stats().memory_bytes <= 10);
+
+        // Removal clears both tiers
+        assert!(store.remove("content://b"));
+        assert!(!store.exists("content://b"));
+        assert!(!store.remove("content://b"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_evict_stops_under_budget() {
+        let mut inner = Inner::default();
+        inner.insert("a", "aaaa".to_string(), true);
+        inner.insert("pinned", "pppp".to_string(), false);
+        inner.insert("b", "bbbb".to_string(), true);
+        inner.insert("c", "cccc".to_string(), true);
+        inner.touch("a");
+
+        // Oldest disk-backed first ("pinned" is not on disk), only as many as needed
+        inner.evict_to(9);
+        assert_eq!(inner.memory_bytes, 8);
+        assert_eq!(inner.stats.evictions, 2);
+        let mut kept: Vec<&str> = inner.entries.keys().map(String::as_str).collect();
+        kept.sort();
+        assert_eq!(kept, ["a", "pinned"]);
+        assert_eq!(inner.lru.len(), 2);
+    }
+
+    #[test]
+    fn test_disk_tier_compression() {
+        let dir = std::env::temp_dir().join(format!(
+            "content_store_test_{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        // Memory budget of 0 forces every read through the disk tier
+        let store = ContentStore::with_disk(&dir, 0)
+            .unwrap()
+            .with_compression(Compression::new(3));
+
+        let text = "Olá, você está bem? ".repeat(200);
+        store.store("z", text.clone());
+        assert!(dir.join("z.txt.zst").exists());
+        assert!(!dir.join("z.txt").exists());
+        assert_eq!(store.get_full("content://z").unwrap(), text);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
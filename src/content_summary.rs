@@ -0,0 +1,86 @@
+//! Short LLM summaries of node content, for
+//! `GET /content/:ref_path?summarize=true`.
+//!
+//! A summary is kept in the content store like any content, under the
+//! summarized node ID plus [`SUMMARY_SUFFIX`] and the length asked for, so
+//! each length is generated once and other replicas find it through Redis.
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::openrouter::{Message, OpenRouterClient};
+
+pub const SUMMARY_SUFFIX: &str = ".summary-";
+
+/// Summary length when the request gives no `max_chars`.
+pub const DEFAULT_MAX_CHARS: usize = 800;
+
+/// Content characters shown to the LLM; the rest of a longer node is left out.
+const INPUT_CHARS: usize = 60_000;
+
+const SUMMARY_PROMPT: &str = "Summarize the document section below for a reader deciding \
+whether to read it in full. Keep the names, dates, amounts and identifiers that matter, \
+write in the section's own language, and answer with the summary text only, in at most \
+{max_chars} characters.";
+
+/// What `?summarize=true` returns instead of a content chunk.
+#[derive(Debug, Serialize)]
+pub struct ContentSummary {
+    pub content_ref: String,
+    pub summary: String,
+    pub max_chars: usize,
+    /// Length of the summarized content
+    pub total_chars: usize,
+    /// Whether the summary was generated by an earlier request
+    pub cached: bool,
+}
+
+/// Node ID the summary of `node_id` is stored under.
+pub fn summary_node_id(node_id: &str, max_chars: usize) -> String {
+    format!("{}{}{}", node_id, SUMMARY_SUFFIX, max_chars)
+}
+
+/// Ask the LLM for a summary of at most `max_chars` characters.
+pub async fn summarize(
+    client: &OpenRouterClient,
+    content: &str,
+    max_chars: usize,
+) -> Result<String> {
+    let prompt = SUMMARY_PROMPT.replace("{max_chars}", &max_chars.to_string());
+    let input: String = content.chars().take(INPUT_CHARS).collect();
+    let summary = client
+        .chat(vec![Message::system(prompt), Message::user(input)])
+        .await?;
+    Ok(fit(summary.trim(), max_chars))
+}
+
+/// Cut a summary that runs past `max_chars` at the last sentence end, or
+/// else the last word break, that fits.
+fn fit(summary: &str, max_chars: usize) -> String {
+    if summary.chars().count() <= max_chars {
+        return summary.to_string();
+    }
+    let head: String = summary.chars().take(max_chars).collect();
+    let cut = head
+        .rfind(['.', '!', '?'])
+        .map(|i| i + 1)
+        .or_else(|| head.rfind(char::is_whitespace))
+        .unwrap_or(head.len());
+    head[..cut].trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("Curta.", 800), "Curta.");
+        assert_eq!(
+            fit("Pedido de danos. Autor alega atraso do voo", 30),
+            "Pedido de danos."
+        );
+        assert_eq!(fit("Autor alega atraso do voo", 12), "Autor alega");
+        assert_eq!(summary_node_id("sentenca", 800), "sentenca.summary-800");
+    }
+}
@@ -0,0 +1,607 @@
+//! Appending a new file's rows to an existing dataset.
+//!
+//! Recurring exports (monthly bank statements, card bills) keep the same
+//! shape, so instead of running schema discovery again the new file's sheets
+//! are matched to the dataset's schemas by header. The columns are then
+//! reconciled: a header that names no column is mapped onto a similarly
+//! named column of a compatible type (`Valor (R$)` onto `valor`), or added to
+//! the schema as a new column, which bumps the schema's version. Whatever
+//! differs is recorded as drift in the schema's `changes`. Pure functions,
+//! like `dataset_query`: the handler in `server.rs` parses the upload and
+//! persists the result.
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::sheet_extractor::apply_numeric_transforms;
+use crate::sheet_parser::{cell_kind, header_key, CellKind, RawSheet};
+use crate::sheet_schema::{
+    ColumnDef, ColumnRename, DataSchema, RowSource, SchemaChange, SchemaDrift, SheetExtraction,
+    TypeChange,
+};
+
+/// How alike a header and a column name must be (0–1) for the header to be
+/// read as a renamed column.
+const RENAME_SIMILARITY: f64 = 0.6;
+
+/// What an append did, returned by `POST /datasets/:id/append`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppendReport {
+    pub dataset_id: String,
+    pub source_file: String,
+    pub appended: Vec<SchemaAppend>,
+    /// Sheets that matched no schema; their rows were not appended.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unmatched_sheets: Vec<String>,
+}
+
+impl AppendReport {
+    pub fn has_drift(&self) -> bool {
+        !self.unmatched_sheets.is_empty() || self.appended.iter().any(|a| !a.drift.is_empty())
+    }
+}
+
+/// Rows one sheet added to one schema.
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaAppend {
+    pub schema: String,
+    pub sheet: String,
+    /// Row index range `[start, end)` the new rows took in the schema.
+    pub rows: [usize; 2],
+    /// The sheet has no header that names the schema's columns, so they
+    /// were matched by position.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub by_position: bool,
+    /// The schema's version after the append.
+    pub version: u32,
+    #[serde(skip_serializing_if = "SchemaDrift::is_empty")]
+    pub drift: SchemaDrift,
+}
+
+/// Which schema a sheet feeds, as `(schema column, sheet column)` pairs.
+/// `renamed` pairs matched by similarity rather than by name.
+struct SheetMatch {
+    schema: usize,
+    columns: Vec<(usize, usize)>,
+    renamed: Vec<(usize, usize)>,
+    by_position: bool,
+}
+
+impl SheetMatch {
+    fn pairs(&self) -> impl Iterator<Item = &(usize, usize)> {
+        self.columns.iter().chain(&self.renamed)
+    }
+}
+
+/// Append the rows of `sheets` (parsed from `source_file`) to the matching
+/// schemas of `dataset`. Fails when no sheet matches any schema.
+pub fn append_sheets(
+    dataset: &mut SheetExtraction,
+    source_file: &str,
+    sheets: &[RawSheet],
+    now: &str,
+) -> Result<AppendReport> {
+    let mut report = AppendReport {
+        dataset_id: dataset.id.clone(),
+        source_file: source_file.to_string(),
+        appended: Vec::new(),
+        unmatched_sheets: Vec::new(),
+    };
+
+    for sheet in sheets {
+        let Some(found) = match_sheet(sheet, &dataset.schemas) else {
+            report.unmatched_sheets.push(sheet.name.clone());
+            continue;
+        };
+        let schema = &mut dataset.schemas[found.schema];
+        let (drift, added) = reconcile(sheet, schema, &found);
+
+        let previous_columns = schema.columns.clone();
+        let mut pairs: Vec<(usize, usize)> = found.pairs().copied().collect();
+        for (sheet_col, column) in added {
+            pairs.push((schema.columns.len(), sheet_col));
+            schema.columns.push(column);
+        }
+        if schema.columns.len() > previous_columns.len() {
+            schema.version += 1;
+        }
+        if !drift.is_empty() {
+            schema.changes.push(SchemaChange {
+                version: schema.version,
+                source_file: source_file.to_string(),
+                detected_at: now.to_string(),
+                drift: drift.clone(),
+                previous_columns: (schema.columns.len() > previous_columns.len())
+                    .then_some(previous_columns),
+            });
+        }
+
+        let mut rows: Vec<serde_json::Value> = sheet
+            .rows
+            .iter()
+            .map(|raw_row| {
+                let obj = pairs
+                    .iter()
+                    .map(|&(schema_col, sheet_col)| {
+                        let value = raw_row.get(sheet_col).map_or("", |v| v.as_str());
+                        (schema.columns[schema_col].name.clone(), value.into())
+                    })
+                    .collect();
+                serde_json::Value::Object(obj)
+            })
+            .collect();
+        let transforms = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.transform.as_deref()));
+        apply_numeric_transforms(&mut rows, transforms);
+
+        // The rows already there came from the file the dataset was extracted from
+        if schema.sources.is_empty() && !schema.rows.is_empty() {
+            schema.sources.push(RowSource {
+                source_file: dataset.source_file.clone(),
+                rows: [0, schema.rows.len()],
+                added_at: dataset.extracted_at.clone(),
+            });
+        }
+        let start = schema.rows.len();
+        schema.rows.extend(rows);
+        schema.row_count = schema.rows.len();
+        schema.refresh_stats();
+        let range = [start, schema.rows.len()];
+        schema.sources.push(RowSource {
+            source_file: source_file.to_string(),
+            rows: range,
+            added_at: now.to_string(),
+        });
+
+        report.appended.push(SchemaAppend {
+            schema: schema.name.clone(),
+            sheet: sheet.name.clone(),
+            rows: range,
+            by_position: found.by_position,
+            version: schema.version,
+            drift,
+        });
+    }
+
+    if report.appended.is_empty() {
+        bail!(
+            "{} matches none of the dataset's schemas ({})",
+            source_file,
+            dataset
+                .schemas
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(report)
+}
+
+/// The schema whose columns the sheet's header covers best: at least one
+/// by name, and at least half counting renames. A sheet without a usable
+/// header goes to the only schema with as many columns, if there is one.
+fn match_sheet(sheet: &RawSheet, schemas: &[DataSchema]) -> Option<SheetMatch> {
+    let header_keys: Vec<String> = sheet.headers.iter().map(|h| header_key(h)).collect();
+    let observed: Vec<Option<&str>> = (0..sheet.headers.len())
+        .map(|c| observed_type(sheet, c))
+        .collect();
+
+    let by_name = sheet
+        .has_header
+        .then(|| {
+            schemas
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| !s.columns.is_empty())
+                .filter_map(|(i, s)| {
+                    let columns: Vec<(usize, usize)> = s
+                        .columns
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(c, col)| {
+                            let key = header_key(&col.name);
+                            header_keys.iter().position(|h| *h == key).map(|h| (c, h))
+                        })
+                        .collect();
+                    let renamed = match_renames(&s.columns, &header_keys, &observed, &columns);
+                    let matched = columns.len() + renamed.len();
+                    (!columns.is_empty() && matched * 2 >= s.columns.len()).then_some(SheetMatch {
+                        schema: i,
+                        columns,
+                        renamed,
+                        by_position: false,
+                    })
+                })
+                .max_by(|a, b| {
+                    let share = |m: &SheetMatch| {
+                        let width = schemas[m.schema].columns.len() as f64;
+                        (m.columns.len() + m.renamed.len()) as f64 / width
+                    };
+                    share(a)
+                        .total_cmp(&share(b))
+                        .then(a.columns.len().cmp(&b.columns.len()))
+                        .then(b.schema.cmp(&a.schema))
+                })
+        })
+        .flatten();
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    let width = sheet.headers.len();
+    let mut same_width = schemas
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.columns.len() == width);
+    match (same_width.next(), same_width.next()) {
+        (Some((schema, _)), None) => Some(SheetMatch {
+            schema,
+            columns: (0..width).map(|i| (i, i)).collect(),
+            renamed: Vec::new(),
+            by_position: true,
+        }),
+        _ => None,
+    }
+}
+
+/// Map headers that name no column onto the unmatched column they most
+/// resemble, when the names are alike and the types compatible. Pairs are
+/// taken best first so each header and column is used once.
+fn match_renames(
+    columns: &[ColumnDef],
+    header_keys: &[String],
+    observed: &[Option<&str>],
+    matched: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+    for (c, column) in columns.iter().enumerate() {
+        if matched.iter().any(|&(mc, _)| mc == c) {
+            continue;
+        }
+        let key = header_key(&column.name);
+        for (h, header) in header_keys.iter().enumerate() {
+            if header.is_empty() || matched.iter().any(|&(_, mh)| mh == h) {
+                continue;
+            }
+            let score = name_similarity(&key, header);
+            if score >= RENAME_SIMILARITY && compatible(&column.data_type, observed[h]) {
+                // Same type breaks ties between equally similar names
+                let bonus = if observed[h] == Some(column.data_type.as_str()) {
+                    0.01
+                } else {
+                    0.0
+                };
+                candidates.push((score + bonus, c, h));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut used_columns = HashSet::new();
+    let mut used_headers = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|&(_, c, h)| used_columns.insert(c) && used_headers.insert(h))
+        .map(|(_, c, h)| (c, h))
+        .collect()
+}
+
+/// Compare the sheet with the schema it matched. Returns the drift and the
+/// columns to add for headers that matched nothing, with their sheet column.
+fn reconcile(
+    sheet: &RawSheet,
+    schema: &DataSchema,
+    found: &SheetMatch,
+) -> (SchemaDrift, Vec<(usize, ColumnDef)>) {
+    let mut drift = SchemaDrift::default();
+    let mut added = Vec::new();
+    if !found.by_position {
+        drift.missing_columns = (0..schema.columns.len())
+            .filter(|c| found.pairs().all(|(sc, _)| sc != c))
+            .map(|c| schema.columns[c].name.clone())
+            .collect();
+        drift.renamed_columns = found
+            .renamed
+            .iter()
+            .map(|&(c, h)| ColumnRename {
+                from: sheet.headers[h].clone(),
+                to: schema.columns[c].name.clone(),
+            })
+            .collect();
+
+        let mut names: HashSet<String> = schema.columns.iter().map(|c| c.name.clone()).collect();
+        for h in (0..sheet.headers.len()).filter(|h| found.pairs().all(|(_, sh)| sh != h)) {
+            let key = header_key(&sheet.headers[h]);
+            if key.is_empty() {
+                continue;
+            }
+            let mut name = key.clone();
+            let mut n = 2;
+            while !names.insert(name.clone()) {
+                name = format!("{key}_{n}");
+                n += 1;
+            }
+            drift.added_columns.push(name.clone());
+            added.push((
+                h,
+                ColumnDef {
+                    name,
+                    data_type: observed_type(sheet, h).unwrap_or("string").to_string(),
+                    format: None,
+                    transform: None,
+                    required: false,
+                    source: None,
+                    description: None,
+                    formula: None,
+                },
+            ));
+        }
+    }
+
+    for &(schema_col, sheet_col) in found.pairs() {
+        let column = &schema.columns[schema_col];
+        let hint = sheet.column_hints.get(sheet_col).and_then(|h| h.data_type);
+        let found = match hint {
+            // Whole numbers in a float column are still floats
+            Some("integer") if column.data_type == "float" => None,
+            Some(t) => Some(t).filter(|t| *t != column.data_type),
+            // Types read from the text are coarse: only a different kind counts
+            None => {
+                observed_type(sheet, sheet_col).filter(|t| !compatible(&column.data_type, Some(t)))
+            }
+        };
+        if let Some(found) = found {
+            drift.type_changes.push(TypeChange {
+                column: column.name.clone(),
+                expected: column.data_type.clone(),
+                found: found.to_string(),
+            });
+        }
+    }
+    (drift, added)
+}
+
+/// A column's type as its cells show it: the workbook's type for Excel,
+/// otherwise `float`, `date` or `string` when most non-empty values agree.
+fn observed_type(sheet: &RawSheet, col: usize) -> Option<&'static str> {
+    if let Some(t) = sheet.column_hints.get(col).and_then(|h| h.data_type) {
+        return Some(t);
+    }
+    let kinds: Vec<CellKind> = sheet
+        .rows
+        .iter()
+        .filter_map(|r| r.get(col))
+        .map(|v| cell_kind(v))
+        .filter(|k| *k != CellKind::Empty)
+        .collect();
+    let share = |kind: CellKind| kinds.iter().filter(|k| **k == kind).count() * 2;
+    [
+        (CellKind::Text, "string"),
+        (CellKind::Number, "float"),
+        (CellKind::Date, "date"),
+    ]
+    .into_iter()
+    .find(|&(kind, _)| !kinds.is_empty() && share(kind) > kinds.len())
+    .map(|(_, t)| t)
+}
+
+/// Whether values of type `observed` fit a column of type `expected`. Any
+/// value fits a string column; numeric types fit each other.
+fn compatible(expected: &str, observed: Option<&str>) -> bool {
+    fn family(t: &str) -> &str {
+        match t {
+            "integer" | "float" | "currency_brl" | "currency_usd" => "number",
+            other => other,
+        }
+    }
+    observed.is_none_or(|t| expected == "string" || family(expected) == family(t))
+}
+
+/// Similarity of two column keys (0–1): the better of their word overlap
+/// and their edit distance, with one key's words all within the other's
+/// (`conta` in `conta_corrente`) counting as 0.7.
+fn name_similarity(a: &str, b: &str) -> f64 {
+    let words_a: HashSet<&str> = a.split('_').collect();
+    let words_b: HashSet<&str> = b.split('_').collect();
+    let shared = words_a.intersection(&words_b).count() as f64;
+    let overlap = shared / words_a.union(&words_b).count() as f64;
+    let nested = if words_a.is_subset(&words_b) || words_b.is_subset(&words_a) {
+        0.7
+    } else {
+        0.0
+    };
+
+    let longest = a.chars().count().max(b.chars().count()).max(1) as f64;
+    let edits = 1.0 - levenshtein(a, b) as f64 / longest;
+    overlap.max(nested).max(edits)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != *cb);
+            row[j + 1] = substitute.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sheet_parser::{parse_file, DateStyle};
+    use crate::sheet_schema::ColumnDef;
+    use serde_json::json;
+
+    fn column(name: &str, data_type: &str, transform: Option<&str>) -> ColumnDef {
+        ColumnDef {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            format: None,
+            transform: transform.map(str::to_string),
+            required: false,
+            source: None,
+            description: None,
+            formula: None,
+        }
+    }
+
+    fn statement_dataset() -> SheetExtraction {
+        let mut dataset = SheetExtraction::new("extrato_01.csv".to_string(), None);
+        dataset.extracted_at = "2024-01-05T00:00:00Z".to_string();
+        dataset.schemas = vec![
+            DataSchema {
+                name: "transacoes".to_string(),
+                description: String::new(),
+                columns: vec![
+                    column("data", "date", None),
+                    column("data_lancamento", "string", None),
+                    column("valor", "currency_brl", Some("parse_currency_brl")),
+                ],
+                row_count: 1,
+                rows: vec![json!({"data": "01/01/2024", "data_lancamento": "PIX", "valor": 10.0})],
+                sources: Vec::new(),
+                version: 1,
+                changes: Vec::new(),
+                stats: Vec::new(),
+                edited: false,
+                edits: Vec::new(),
+            },
+            DataSchema {
+                name: "saldos".to_string(),
+                description: String::new(),
+                columns: vec![
+                    column("conta", "string", None),
+                    column("saldo", "float", None),
+                ],
+                row_count: 0,
+                rows: Vec::new(),
+                sources: Vec::new(),
+                version: 1,
+                changes: Vec::new(),
+                stats: Vec::new(),
+                edited: false,
+                edits: Vec::new(),
+            },
+        ];
+        dataset
+    }
+
+    #[test]
+    fn test_append_matches_headers_and_reports_drift() {
+        let mut dataset = statement_dataset();
+        let csv = "Data,Data Lançamento,Valor,Categoria\n\
+                   01/02/2024,PIX recebido,\"R$ 1.234,56\",receita\n\
+                   02/02/2024,Tarifa,\"(5,00)\",banco\n";
+        let sheets = parse_file("extrato_02.csv", csv.as_bytes(), DateStyle::default()).unwrap();
+        let report = append_sheets(
+            &mut dataset,
+            "extrato_02.csv",
+            &sheets,
+            "2024-03-01T00:00:00Z",
+        )
+        .unwrap();
+
+        assert_eq!(report.appended.len(), 1);
+        let append = &report.appended[0];
+        assert_eq!(
+            (append.schema.as_str(), append.rows),
+            ("transacoes", [1, 3])
+        );
+        assert_eq!(append.drift.added_columns, vec!["categoria"]);
+        assert!(append.drift.missing_columns.is_empty() && append.drift.type_changes.is_empty());
+
+        let schema = &dataset.schemas[0];
+        assert_eq!(schema.row_count, 3);
+        assert_eq!(schema.rows[1]["data_lancamento"], "PIX recebido");
+        assert_eq!(schema.rows[1]["categoria"], "receita");
+        assert_eq!((schema.version, append.version), (2, 2));
+        assert_eq!(schema.columns[3].data_type, "string");
+        assert_eq!(
+            schema.changes[0].previous_columns.as_ref().map(Vec::len),
+            Some(3)
+        );
+        assert_eq!(schema.rows[2]["valor"], -5.0);
+        assert_eq!(schema.sources.len(), 2);
+        assert_eq!(schema.sources[0].source_file, "extrato_01.csv");
+        assert_eq!(schema.sources[1].rows, [1, 3]);
+        assert_eq!(dataset.modified_at(), "2024-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_renamed_columns_reconciled() {
+        let mut dataset = statement_dataset();
+        let csv = "Data,Dt. Lançamento,Valor (R$)\n01/04/2024,PIX enviado,\"-30,00\"\n";
+        let sheets = parse_file("extrato_04.csv", csv.as_bytes(), DateStyle::default()).unwrap();
+        let report = append_sheets(&mut dataset, "extrato_04.csv", &sheets, "2024-04-02").unwrap();
+
+        let drift = &report.appended[0].drift;
+        let renamed: Vec<(&str, &str)> = drift
+            .renamed_columns
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str()))
+            .collect();
+        assert_eq!(
+            renamed,
+            vec![
+                ("Dt. Lançamento", "data_lancamento"),
+                ("Valor (R$)", "valor")
+            ]
+        );
+        assert!(drift.added_columns.is_empty() && drift.missing_columns.is_empty());
+
+        // Renames are recorded but leave the definition at its version
+        let schema = &dataset.schemas[0];
+        assert_eq!((schema.version, schema.changes.len()), (1, 1));
+        assert!(schema.changes[0].previous_columns.is_none());
+        assert_eq!(schema.rows[1]["valor"], -30.0);
+        assert!(name_similarity("saldo", "valor") < RENAME_SIMILARITY);
+    }
+
+    #[test]
+    fn test_append_by_position_and_type_drift() {
+        let mut dataset = statement_dataset();
+        let csv = "01/03/2024,PIX recebido,\"1.200,00\"\n02/03/2024,Tarifa,-5\n";
+        let sheets = parse_file("extrato_03.csv", csv.as_bytes(), DateStyle::default()).unwrap();
+        let report = append_sheets(&mut dataset, "extrato_03.csv", &sheets, "2024-03-01").unwrap();
+        let append = &report.appended[0];
+        assert_eq!(
+            (append.schema.as_str(), append.by_position),
+            ("transacoes", true)
+        );
+        assert_eq!(dataset.schemas[0].rows[2]["valor"], -5.0);
+
+        let csv = "Conta,Saldo\n0001-2,n/d\n0003-9,bloqueado\n";
+        let sheets = parse_file("saldos.csv", csv.as_bytes(), DateStyle::default()).unwrap();
+        let report = append_sheets(&mut dataset, "saldos.csv", &sheets, "2024-03-01").unwrap();
+        let append = &report.appended[0];
+        assert_eq!(append.schema, "saldos");
+        assert_eq!(
+            append.drift.type_changes,
+            vec![TypeChange {
+                column: "saldo".to_string(),
+                expected: "float".to_string(),
+                found: "string".to_string(),
+            }]
+        );
+        assert!(report.has_drift());
+
+        let unrelated = parse_file(
+            "x.csv",
+            b"nome,idade,cidade,uf\nAna,30,SP,SP\n",
+            DateStyle::default(),
+        )
+        .unwrap();
+        let err = append_sheets(&mut dataset, "x.csv", &unrelated, "2024-03-02").unwrap_err();
+        assert!(err.to_string().contains("matches none"));
+    }
+}
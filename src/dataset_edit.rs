@@ -0,0 +1,213 @@
+//! Manual corrections to dataset rows.
+//!
+//! OCR'd statements often come out with a few bad rows. `PATCH
+//! /datasets/:id/rows/:row_index` sets column values on one row of a schema
+//! and `DELETE` removes it. Either way the schema is marked `edited` and a
+//! [`RowEdit`] holding what the row had before goes to its `edits` trail.
+//! The handlers in `server.rs` find the row and persist the dataset.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::numeric;
+use crate::sheet_schema::{DataSchema, RowEdit, RowEditAction};
+
+/// Body of `PATCH /datasets/:id/rows/:row_index`.
+#[derive(Debug, Deserialize)]
+pub struct RowCorrection {
+    /// Who made the correction (falls back to the `X-Reviewer` header)
+    pub reviewer: Option<String>,
+    /// New values by column name; `null` clears a value
+    pub values: Map<String, Value>,
+}
+
+/// Set `values` on row `index` of `schema`. Text written to a column with a
+/// numeric transform is parsed like extracted text. Nothing is modified when
+/// a column is unknown.
+pub fn update_row(
+    schema: &mut DataSchema,
+    index: usize,
+    values: &Map<String, Value>,
+    reviewer: String,
+    now: &str,
+) -> Result<RowEdit, String> {
+    if values.is_empty() {
+        return Err("No values to set".to_string());
+    }
+    let unknown: Vec<&str> = values
+        .keys()
+        .filter(|k| !schema.columns.iter().any(|c| &c.name == *k))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "Unknown column(s) in schema '{}': {}",
+            schema.name,
+            unknown.join(", ")
+        ));
+    }
+
+    let Some(Value::Object(row)) = schema.rows.get_mut(index) else {
+        return Err(format!("Row {} is not an object", index));
+    };
+    let mut original = Map::new();
+    for column in &schema.columns {
+        let Some(value) = values.get(&column.name) else {
+            continue;
+        };
+        let value = column
+            .transform
+            .as_deref()
+            .filter(|_| value.is_string())
+            .and_then(|t| numeric::apply_transform(t, value))
+            .unwrap_or_else(|| value.clone());
+        let old = row.get(&column.name).cloned().unwrap_or(Value::Null);
+        if old != value {
+            original.insert(column.name.clone(), old);
+            row.insert(column.name.clone(), value);
+        }
+    }
+
+    Ok(record(
+        schema,
+        index,
+        RowEditAction::Update,
+        reviewer,
+        now,
+        original.into(),
+    ))
+}
+
+/// Remove row `index` from `schema`, shifting the row ranges in `sources`.
+pub fn delete_row(schema: &mut DataSchema, index: usize, reviewer: String, now: &str) -> RowEdit {
+    let removed = schema.rows.remove(index);
+    schema.row_count = schema.rows.len();
+    for source in &mut schema.sources {
+        let [start, end] = &mut source.rows;
+        if *start > index {
+            *start -= 1;
+        }
+        if *end > index {
+            *end -= 1;
+        }
+    }
+    schema.sources.retain(|s| s.rows[0] < s.rows[1]);
+
+    record(schema, index, RowEditAction::Delete, reviewer, now, removed)
+}
+
+fn record(
+    schema: &mut DataSchema,
+    row_index: usize,
+    action: RowEditAction,
+    reviewer: String,
+    now: &str,
+    original: Value,
+) -> RowEdit {
+    let edit = RowEdit {
+        row_index,
+        action,
+        reviewer,
+        edited_at: now.to_string(),
+        original,
+    };
+    schema.edited = true;
+    schema.edits.push(edit.clone());
+    schema.refresh_stats();
+    edit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sheet_schema::{ColumnDef, RowSource};
+    use serde_json::json;
+
+    fn statement() -> DataSchema {
+        let column = |name: &str, transform: Option<&str>| ColumnDef {
+            name: name.to_string(),
+            data_type: "string".to_string(),
+            format: None,
+            transform: transform.map(str::to_string),
+            required: false,
+            source: None,
+            description: None,
+            formula: None,
+        };
+        DataSchema {
+            name: "transacoes".to_string(),
+            description: String::new(),
+            columns: vec![
+                column("descricao", None),
+                column("valor", Some("parse_currency_brl")),
+            ],
+            row_count: 3,
+            rows: vec![
+                json!({"descricao": "PIX", "valor": 10.0}),
+                json!({"descricao": "TARIFA", "valor": "5,0O"}),
+                json!({"descricao": "SALDO", "valor": 100.0}),
+            ],
+            sources: vec![
+                RowSource {
+                    source_file: "jan.pdf".to_string(),
+                    rows: [0, 2],
+                    added_at: "2024-02-01T00:00:00Z".to_string(),
+                },
+                RowSource {
+                    source_file: "fev.pdf".to_string(),
+                    rows: [2, 3],
+                    added_at: "2024-03-01T00:00:00Z".to_string(),
+                },
+            ],
+            version: 1,
+            changes: Vec::new(),
+            stats: Vec::new(),
+            edited: false,
+            edits: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_update_row_keeps_original_values() {
+        let mut schema = statement();
+        let values = json!({"descricao": "TARIFA", "valor": "5,00"});
+        let edit = update_row(
+            &mut schema,
+            1,
+            values.as_object().unwrap(),
+            "ana".to_string(),
+            "2024-04-01T00:00:00Z",
+        )
+        .unwrap();
+
+        assert_eq!(schema.rows[1]["valor"], 5.0);
+        assert_eq!(edit.original, json!({"valor": "5,0O"}));
+        assert!(schema.edited);
+        assert_eq!(schema.edits.len(), 1);
+
+        let unknown = json!({"saldo": 1});
+        let err = update_row(
+            &mut schema,
+            1,
+            unknown.as_object().unwrap(),
+            "ana".to_string(),
+            "2024-04-01T00:00:00Z",
+        )
+        .unwrap_err();
+        assert!(err.contains("saldo"));
+        assert_eq!(schema.edits.len(), 1);
+    }
+
+    #[test]
+    fn test_delete_row_shifts_sources() {
+        let mut schema = statement();
+        let edit = delete_row(&mut schema, 1, "ana".to_string(), "2024-04-01T00:00:00Z");
+
+        assert_eq!(edit.action, RowEditAction::Delete);
+        assert_eq!(edit.original["descricao"], "TARIFA");
+        assert_eq!(schema.row_count, 2);
+        assert_eq!(schema.rows[1]["descricao"], "SALDO");
+        assert_eq!(schema.sources[0].rows, [0, 1]);
+        assert_eq!(schema.sources[1].rows, [1, 2]);
+    }
+}
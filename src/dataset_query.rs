@@ -0,0 +1,393 @@
+//! Query helpers over dataset rows (grouped aggregation, relationship joins).
+//!
+//! Pure functions, no async — handlers in `server.rs` resolve the dataset and
+//! delegate here. Row values are stored as extracted (usually strings such as
+//! `"1.234,56"`), so numeric aggregates parse them on the fly with
+//! [`crate::numeric`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::numeric::value_as_f64;
+use crate::sheet_schema::SchemaRelationship;
+
+/// Aggregate function applied to a group of rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// A single aggregate requested by the client, e.g. `sum:valor` or `count`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggSpec {
+    pub func: AggFunc,
+    /// Column the function applies to. `None` only for a bare `count`.
+    pub column: Option<String>,
+}
+
+impl AggSpec {
+    /// Key used for this aggregate in the output objects (`count`, `sum_valor`).
+    pub fn output_name(&self) -> String {
+        let func = match self.func {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        };
+        match &self.column {
+            Some(col) => format!("{}_{}", func, col),
+            None => func.to_string(),
+        }
+    }
+}
+
+/// Result of a grouped aggregation over one schema.
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateResult {
+    pub schema_name: String,
+    pub group_by: Vec<String>,
+    /// One object per group: the group-by columns plus one key per aggregate.
+    pub groups: Vec<serde_json::Value>,
+}
+
+/// Parse a comma-separated aggregate list such as `sum:valor,count,avg:valor`.
+pub fn parse_agg_specs(spec: &str) -> Result<Vec<AggSpec>> {
+    let mut specs = Vec::new();
+
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (func_name, column) = match part.split_once(':') {
+            Some((f, c)) => (f.trim(), Some(c.trim().to_string())),
+            None => (part, None),
+        };
+
+        let func = match func_name.to_lowercase().as_str() {
+            "count" => AggFunc::Count,
+            "sum" => AggFunc::Sum,
+            "avg" => AggFunc::Avg,
+            "min" => AggFunc::Min,
+            "max" => AggFunc::Max,
+            other => bail!(
+                "Unknown aggregate function '{}'. Supported: count, sum, avg, min, max",
+                other
+            ),
+        };
+
+        let column = column.filter(|c| !c.is_empty());
+        if func != AggFunc::Count && column.is_none() {
+            bail!(
+                "Aggregate '{}' requires a column (e.g. {}:valor)",
+                part,
+                func_name
+            );
+        }
+
+        specs.push(AggSpec { func, column });
+    }
+
+    if specs.is_empty() {
+        bail!("At least one aggregate is required (e.g. agg=count)");
+    }
+
+    Ok(specs)
+}
+
+/// Group rows by the given columns and compute the requested aggregates.
+///
+/// Groups are returned sorted by their key. Rows missing a group-by column are
+/// grouped under `null`. Numeric aggregates skip values that don't parse as
+/// numbers and yield `null` when a group has no numeric values at all.
+pub fn aggregate(
+    rows: &[serde_json::Value],
+    group_by: &[String],
+    specs: &[AggSpec],
+) -> Vec<serde_json::Value> {
+    // Serialized group key → (group values, per-spec accumulators)
+    let mut groups: BTreeMap<String, (Vec<serde_json::Value>, Vec<Accumulator>)> = BTreeMap::new();
+
+    for row in rows {
+        let key_values: Vec<serde_json::Value> = group_by
+            .iter()
+            .map(|col| row.get(col).cloned().unwrap_or(serde_json::Value::Null))
+            .collect();
+        let key = serde_json::to_string(&key_values).unwrap_or_default();
+
+        let (_, accumulators) = groups
+            .entry(key)
+            .or_insert_with(|| (key_values, vec![Accumulator::default(); specs.len()]));
+
+        for (spec, acc) in specs.iter().zip(accumulators.iter_mut()) {
+            acc.push(spec, row);
+        }
+    }
+
+    groups
+        .into_values()
+        .map(|(key_values, accumulators)| {
+            let mut obj = serde_json::Map::new();
+            for (col, value) in group_by.iter().zip(key_values) {
+                obj.insert(col.clone(), value);
+            }
+            for (spec, acc) in specs.iter().zip(accumulators) {
+                obj.insert(spec.output_name(), acc.finish(spec.func));
+            }
+            serde_json::Value::Object(obj)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+struct Accumulator {
+    count: usize,
+    numeric_count: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Accumulator {
+    fn push(&mut self, spec: &AggSpec, row: &serde_json::Value) {
+        let Some(col) = &spec.column else {
+            self.count += 1;
+            return;
+        };
+
+        let value = match row.get(col) {
+            Some(v) if !is_blank(v) => v,
+            _ => return,
+        };
+        self.count += 1;
+
+        if let Some(n) = value_as_f64(value) {
+            self.numeric_count += 1;
+            self.sum += n;
+            self.min = Some(self.min.map_or(n, |m| m.min(n)));
+            self.max = Some(self.max.map_or(n, |m| m.max(n)));
+        }
+    }
+
+    fn finish(self, func: AggFunc) -> serde_json::Value {
+        let number = |n: Option<f64>| {
+            n.and_then(serde_json::Number::from_f64)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null)
+        };
+
+        match func {
+            AggFunc::Count => serde_json::Value::from(self.count),
+            AggFunc::Sum => number((self.numeric_count > 0).then_some(self.sum)),
+            AggFunc::Avg => {
+                number((self.numeric_count > 0).then(|| self.sum / self.numeric_count as f64))
+            }
+            AggFunc::Min => number(self.min),
+            AggFunc::Max => number(self.max),
+        }
+    }
+}
+
+fn is_blank(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// How unmatched rows on the `from` side of a join are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinKind {
+    /// Only rows with at least one match on the `to` side.
+    Inner,
+    /// Every `from` row; `to` is `null` when there is no match.
+    Left,
+}
+
+impl JoinKind {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "inner" => Some(Self::Inner),
+            "left" => Some(Self::Left),
+            _ => None,
+        }
+    }
+}
+
+/// Joined rows materialized from a schema relationship (paginated).
+#[derive(Debug, Clone, Serialize)]
+pub struct JoinedRows {
+    pub relationship: SchemaRelationship,
+    pub join: JoinKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_dataset: Option<String>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    /// Each row is `{"from": {...}, "to": {...} | null}`.
+    pub rows: Vec<serde_json::Value>,
+}
+
+/// Split a relationship endpoint (`"schema_name.column_name"`) into its parts.
+pub fn parse_column_ref(column_ref: &str) -> Option<(&str, &str)> {
+    let (schema, column) = column_ref.split_once('.')?;
+    if schema.is_empty() || column.is_empty() {
+        return None;
+    }
+    Some((schema, column))
+}
+
+/// Join `from_rows` to `to_rows` on `from_rows[from_col] == to_rows[to_col]`.
+///
+/// Keys are compared as trimmed strings so `"42"` matches `42`. A `from` row
+/// with several matches produces one output row per match, like SQL. Rows
+/// with a blank key never match.
+pub fn join_rows(
+    from_rows: &[serde_json::Value],
+    from_col: &str,
+    to_rows: &[serde_json::Value],
+    to_col: &str,
+    kind: JoinKind,
+) -> Vec<serde_json::Value> {
+    let mut index: HashMap<String, Vec<&serde_json::Value>> = HashMap::new();
+    for row in to_rows {
+        if let Some(key) = row.get(to_col).and_then(join_key) {
+            index.entry(key).or_default().push(row);
+        }
+    }
+
+    let mut joined = Vec::new();
+    for row in from_rows {
+        let matches = row
+            .get(from_col)
+            .and_then(join_key)
+            .and_then(|key| index.get(&key));
+
+        match matches {
+            Some(targets) => {
+                for target in targets {
+                    joined.push(serde_json::json!({ "from": row, "to": target }));
+                }
+            }
+            None if kind == JoinKind::Left => {
+                joined.push(serde_json::json!({ "from": row, "to": null }));
+            }
+            None => {}
+        }
+    }
+
+    joined
+}
+
+fn join_key(value: &serde_json::Value) -> Option<String> {
+    let key = match value {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        _ => return None,
+    };
+    (!key.is_empty()).then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_agg_specs() {
+        let specs = parse_agg_specs("sum:valor, count").unwrap();
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].func, AggFunc::Sum);
+        assert_eq!(specs[0].column.as_deref(), Some("valor"));
+        assert_eq!(specs[0].output_name(), "sum_valor");
+        assert_eq!(specs[1].output_name(), "count");
+
+        assert!(parse_agg_specs("sum").is_err());
+        assert!(parse_agg_specs("median:valor").is_err());
+        assert!(parse_agg_specs("").is_err());
+    }
+
+    #[test]
+    fn test_value_as_f64_formats() {
+        assert_eq!(value_as_f64(&json!("1.234,56")), Some(1234.56));
+        assert_eq!(value_as_f64(&json!("R$ 1.234,56")), Some(1234.56));
+        assert_eq!(value_as_f64(&json!("1,234.56")), Some(1234.56));
+        assert_eq!(value_as_f64(&json!("(50,00)")), Some(-50.0));
+        assert_eq!(value_as_f64(&json!("-12,5")), Some(-12.5));
+        assert_eq!(value_as_f64(&json!("1.234")), Some(1234.0));
+        assert_eq!(value_as_f64(&json!(42)), Some(42.0));
+        assert_eq!(value_as_f64(&json!("abc")), None);
+    }
+
+    #[test]
+    fn test_aggregate_grouped() {
+        let rows = vec![
+            json!({"categoria": "alimentação", "valor": "10,50"}),
+            json!({"categoria": "transporte", "valor": "5,00"}),
+            json!({"categoria": "alimentação", "valor": "1.000,00"}),
+            json!({"valor": "2,00"}),
+        ];
+        let specs = parse_agg_specs("sum:valor,count,max:valor").unwrap();
+        let groups = aggregate(&rows, &["categoria".to_string()], &specs);
+
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0]["categoria"], json!("alimentação"));
+        assert_eq!(groups[0]["sum_valor"], json!(1010.5));
+        assert_eq!(groups[0]["count"], json!(2));
+        assert_eq!(groups[0]["max_valor"], json!(1000.0));
+        assert_eq!(groups[1]["sum_valor"], json!(5.0));
+        // rows missing the group-by column land in a null group
+        assert_eq!(groups[2]["categoria"], json!(null));
+        assert_eq!(groups[2]["count"], json!(1));
+    }
+
+    #[test]
+    fn test_aggregate_without_group_by() {
+        let rows = vec![
+            json!({"valor": "1,00"}),
+            json!({"valor": "3,00"}),
+            json!({"valor": ""}),
+        ];
+        let specs = parse_agg_specs("avg:valor,count:valor").unwrap();
+        let groups = aggregate(&rows, &[], &specs);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0]["avg_valor"], json!(2.0));
+        assert_eq!(groups[0]["count_valor"], json!(2));
+    }
+
+    #[test]
+    fn test_parse_column_ref() {
+        assert_eq!(
+            parse_column_ref("transacoes.conta_id"),
+            Some(("transacoes", "conta_id"))
+        );
+        assert_eq!(parse_column_ref("transacoes"), None);
+        assert_eq!(parse_column_ref(".conta_id"), None);
+    }
+
+    #[test]
+    fn test_join_rows() {
+        let from = vec![
+            json!({"id": "t1", "conta_id": "1"}),
+            json!({"id": "t2", "conta_id": "2"}),
+            json!({"id": "t3", "conta_id": ""}),
+        ];
+        let to = vec![json!({"id": 1, "banco": "Itaú"})];
+
+        let inner = join_rows(&from, "conta_id", &to, "id", JoinKind::Inner);
+        assert_eq!(inner.len(), 1);
+        assert_eq!(inner[0]["from"]["id"], json!("t1"));
+        assert_eq!(inner[0]["to"]["banco"], json!("Itaú"));
+
+        let left = join_rows(&from, "conta_id", &to, "id", JoinKind::Left);
+        assert_eq!(left.len(), 3);
+        assert_eq!(left[1]["to"], json!(null));
+    }
+}
@@ -0,0 +1,175 @@
+//! Near-duplicate detection over OCR text.
+//!
+//! Each extraction gets a 64-bit simhash of its OCR text, built from 3-word
+//! shingles of the lowercased alphanumeric tokens. Two documents are near
+//! duplicates when their fingerprints differ in at most `DUPLICATE_MAX_DISTANCE`
+//! bits (default 3), so a re-upload of the same processo under another file
+//! name, or with a different OCR run, still links to the first extraction.
+
+/// Default Hamming distance (in bits) under which two fingerprints match.
+pub const DEFAULT_MAX_DISTANCE: u32 = 3;
+
+/// Texts with fewer tokens than this only match on an identical content hash.
+const MIN_TOKENS: usize = 50;
+
+const SHINGLE_WORDS: usize = 3;
+
+/// An existing extraction to compare against.
+pub struct Candidate {
+    pub id: String,
+    pub content_hash: Option<String>,
+    pub fingerprint: Option<String>,
+    pub duplicate_of: Option<String>,
+}
+
+/// Read `DUPLICATE_MAX_DISTANCE` (default 3).
+pub fn max_distance_from_env() -> u32 {
+    std::env::var("DUPLICATE_MAX_DISTANCE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_DISTANCE)
+}
+
+/// Simhash fingerprint of `text` as 16 hex digits, or `None` if the text is too short.
+pub fn fingerprint(text: &str) -> Option<String> {
+    let tokens: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if tokens.len() < MIN_TOKENS {
+        return None;
+    }
+
+    let mut weights = [0i64; 64];
+    for shingle in tokens.windows(SHINGLE_WORDS) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    let hash = weights
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0u64, |acc, (bit, _)| acc | (1 << bit));
+    Some(format!("{:016x}", hash))
+}
+
+/// Number of differing bits between two hex fingerprints.
+pub fn distance(a: &str, b: &str) -> Option<u32> {
+    let a = u64::from_str_radix(a, 16).ok()?;
+    let b = u64::from_str_radix(b, 16).ok()?;
+    Some((a ^ b).count_ones())
+}
+
+/// Find the extraction a new document duplicates, resolved to the original
+/// (a match that is itself a duplicate links to what it duplicates).
+///
+/// An identical content hash wins; otherwise the closest fingerprint within
+/// `max_distance` bits.
+pub fn find_duplicate<'a>(
+    content_hash: Option<&str>,
+    fingerprint: Option<&str>,
+    candidates: &'a [Candidate],
+    max_distance: u32,
+) -> Option<&'a str> {
+    let exact = content_hash.and_then(|hash| {
+        candidates
+            .iter()
+            .find(|c| c.content_hash.as_deref() == Some(hash))
+    });
+    let best = exact.or_else(|| {
+        let fingerprint = fingerprint?;
+        candidates
+            .iter()
+            .filter_map(|c| Some((c, distance(fingerprint, c.fingerprint.as_deref()?)?)))
+            .filter(|(_, d)| *d <= max_distance)
+            .min_by_key(|(_, d)| *d)
+            .map(|(c, _)| c)
+    })?;
+    Some(best.duplicate_of.as_deref().unwrap_or(&best.id))
+}
+
+/// 64-bit FNV-1a; stable across builds, unlike `DefaultHasher`.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A multi-page petition; `ocr_noise` stands in for one misread word.
+    fn petition(ocr_noise: &str) -> String {
+        let mut text = String::from(
+            "EXCELENTÍSSIMO SENHOR DOUTOR JUIZ DE DIREITO DA VARA CÍVEL DA COMARCA DE SÃO PAULO. \
+             Processo 0001234-56.2024.8.26.0100. JOÃO DA SILVA, portador do CPF 123.456.789-00, \
+             vem propor AÇÃO DE INDENIZAÇÃO POR DANOS MORAIS em face de AZUL LINHAS AÉREAS.\n",
+        );
+        for page in 1..=20 {
+            text.push_str(&format!(
+                "Página {}. O autor adquiriu passagem para o voo AD26{:02} com partida prevista \
+                 para {:02}/03/2024, que foi cancelado sem aviso prévio, causando prejuízos \
+                 materiais de R$ {}.000,00 conforme documento {} anexo aos autos.\n",
+                page,
+                page,
+                page,
+                page * 3,
+                page + 40
+            ));
+        }
+        text.push_str(&format!(
+            "Requer a condenação da ré por danos {} e honorários.",
+            ocr_noise
+        ));
+        text
+    }
+
+    #[test]
+    fn test_near_duplicate_links_to_original() {
+        let original = petition("morais");
+        let rescan = petition("rnorais");
+        let other =
+            "Contrato de locação residencial entre as partes abaixo qualificadas. ".repeat(10);
+
+        let fp_original = fingerprint(&original).unwrap();
+        let fp_rescan = fingerprint(&rescan).unwrap();
+        let fp_other = fingerprint(&other).unwrap();
+        assert!(distance(&fp_original, &fp_rescan).unwrap() <= DEFAULT_MAX_DISTANCE);
+        assert!(distance(&fp_original, &fp_other).unwrap() > DEFAULT_MAX_DISTANCE);
+        assert!(fingerprint("too short").is_none());
+
+        let candidates = [
+            Candidate {
+                id: "ext_other".into(),
+                content_hash: Some("h_other".into()),
+                fingerprint: Some(fp_other),
+                duplicate_of: None,
+            },
+            Candidate {
+                id: "ext_copy".into(),
+                content_hash: Some("h_copy".into()),
+                fingerprint: Some(fp_original),
+                duplicate_of: Some("ext_first".into()),
+            },
+        ];
+        // Near match resolves to the original, not the intermediate copy.
+        assert_eq!(
+            find_duplicate(Some("h_new"), Some(&fp_rescan), &candidates, 3),
+            Some("ext_first")
+        );
+        // Identical content hash matches even without a fingerprint.
+        assert_eq!(
+            find_duplicate(Some("h_other"), None, &candidates, 3),
+            Some("ext_other")
+        );
+        assert_eq!(find_duplicate(Some("h_new"), None, &candidates, 3), None);
+    }
+}
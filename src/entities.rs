@@ -2,16 +2,19 @@
 //!
 //! Pure functions, no async — easily testable. Walks the document tree,
 //! reads content from ContentStore, runs compiled regex patterns, and
-//! returns per-node entity metadata plus a global ReferenceIndex.
+//! returns per-node entity metadata plus a global ReferenceIndex, whose
+//! variant spellings of one entity are then merged by [`resolve_entities`].
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use regex::Regex;
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
-use crate::config::EntityPattern;
+use crate::config::{EntityPattern, EntityPatternKind};
 use crate::content_store::ContentStore;
+use crate::ocr::OcrPage;
+use crate::readable_id::fold_accent;
 use crate::schema::DocumentNode;
 
 /// Pre-compiled regex patterns ready for matching.
@@ -26,6 +29,69 @@ struct CompiledPattern {
     regex: Regex,
     normalize: Option<String>,
     deduplicate: bool,
+    /// Named capture groups other than `value`, kept as structured fields
+    fields: Vec<String>,
+}
+
+impl CompiledPattern {
+    fn new(p: &EntityPattern) -> Result<Self, regex::Error> {
+        let regex = Regex::new(&p.pattern)?;
+        let fields = regex
+            .capture_names()
+            .flatten()
+            .filter(|n| *n != VALUE_GROUP)
+            .map(str::to_string)
+            .collect();
+        Ok(Self {
+            id: p.id.clone(),
+            label: p.label.clone(),
+            regex,
+            normalize: p.normalize.clone(),
+            deduplicate: p.deduplicate,
+            fields,
+        })
+    }
+
+    /// The part of a match that is the entity's value: the `value` group,
+    /// the whole match for patterns with named fields, else capture group 1
+    /// (or the whole match without groups).
+    fn value_match<'t>(&self, cap: &Captures<'t>) -> Option<regex::Match<'t>> {
+        if self
+            .regex
+            .capture_names()
+            .flatten()
+            .any(|n| n == VALUE_GROUP)
+        {
+            cap.name(VALUE_GROUP)
+        } else if !self.fields.is_empty() {
+            cap.get(0)
+        } else {
+            cap.get(1).or_else(|| cap.get(0))
+        }
+    }
+}
+
+/// Named group that, when present, holds the value of a structured pattern.
+const VALUE_GROUP: &str = "value";
+
+/// One match of a pattern: a plain value, or a value with the named groups
+/// of its pattern (e.g. a flight's `carrier`, `number` and `date`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EntityValue {
+    Plain(String),
+    Structured {
+        value: String,
+        fields: BTreeMap<String, String>,
+    },
+}
+
+impl EntityValue {
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Plain(value) | Self::Structured { value, .. } => value,
+        }
+    }
 }
 
 /// A single occurrence of an entity, tracking which nodes it appears in.
@@ -33,6 +99,18 @@ struct CompiledPattern {
 pub struct EntityOccurrence {
     pub value: String,
     pub node_ids: Vec<String>,
+    /// Character offsets `[start, end)` of each match in the OCR text
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<[usize; 2]>,
+    /// Other spellings merged into `value` (see [`resolve_entities`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// `llm` for values found by an LLM entity pattern
+    #[serde(default, skip_serializing_if = "EntityPatternKind::is_regex")]
+    pub source: EntityPatternKind,
+    /// Named capture groups of the first match, for patterns that have them
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
 }
 
 /// Global reference index: entity type → list of unique occurrences.
@@ -45,17 +123,9 @@ impl CompiledPatterns {
     /// Compile entity patterns from config. Skips invalid regexes with a warning.
     pub fn compile(patterns: &[EntityPattern]) -> Self {
         let mut compiled = Vec::new();
-        for p in patterns {
-            match Regex::new(&p.pattern) {
-                Ok(regex) => {
-                    compiled.push(CompiledPattern {
-                        id: p.id.clone(),
-                        label: p.label.clone(),
-                        regex,
-                        normalize: p.normalize.clone(),
-                        deduplicate: p.deduplicate,
-                    });
-                }
+        for p in patterns.iter().filter(|p| p.kind.is_regex()) {
+            match CompiledPattern::new(p) {
+                Ok(pattern) => compiled.push(pattern),
                 Err(e) => {
                     warn!(
                         "Skipping invalid entity pattern '{}' ({}): {}",
@@ -83,9 +153,9 @@ pub fn extract_entities(
     compiled: &CompiledPatterns,
 ) -> (HashMap<String, serde_json::Value>, ReferenceIndex) {
     // node_id → { pattern_id → Vec<matched_value> }
-    let mut node_entities: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
-    // pattern_id → { value → Vec<node_id> } for global index
-    let mut global_index: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    let mut node_entities: HashMap<String, HashMap<String, Vec<EntityValue>>> = HashMap::new();
+    // pattern_id → { value → (Vec<node_id>, fields of the first match) } for global index
+    let mut global_index: GlobalIndex = HashMap::new();
 
     // Recursively walk all nodes
     walk_nodes(
@@ -112,7 +182,14 @@ pub fn extract_entities(
             .map(|(pattern_id, value_map)| {
                 let occurrences: Vec<EntityOccurrence> = value_map
                     .into_iter()
-                    .map(|(value, node_ids)| EntityOccurrence { value, node_ids })
+                    .map(|(value, (node_ids, fields))| EntityOccurrence {
+                        value,
+                        node_ids,
+                        spans: Vec::new(),
+                        aliases: Vec::new(),
+                        source: EntityPatternKind::Regex,
+                        fields,
+                    })
                     .collect();
                 (pattern_id, occurrences)
             })
@@ -122,13 +199,16 @@ pub fn extract_entities(
     (node_metadata, reference_index)
 }
 
+/// pattern_id → { value → (node_ids, fields) }
+type GlobalIndex = HashMap<String, HashMap<String, (Vec<String>, BTreeMap<String, String>)>>;
+
 /// Recursively walk the node tree, extracting entities from each node's content.
 fn walk_nodes(
     nodes: &[DocumentNode],
     content_store: &ContentStore,
     compiled: &CompiledPatterns,
-    node_entities: &mut HashMap<String, HashMap<String, Vec<String>>>,
-    global_index: &mut HashMap<String, HashMap<String, Vec<String>>>,
+    node_entities: &mut HashMap<String, HashMap<String, Vec<EntityValue>>>,
+    global_index: &mut GlobalIndex,
 ) {
     for node in nodes {
         // Get content for this node from the content store
@@ -140,10 +220,13 @@ fn walk_nodes(
                 // Update global index
                 let global_entry = global_index.entry(pattern_id.clone()).or_default();
                 for value in values {
-                    global_entry
-                        .entry(value.clone())
-                        .or_default()
-                        .push(node.id.clone());
+                    let (node_ids, _) = global_entry
+                        .entry(value.value().to_string())
+                        .or_insert_with(|| match value {
+                            EntityValue::Plain(_) => (Vec::new(), BTreeMap::new()),
+                            EntityValue::Structured { fields, .. } => (Vec::new(), fields.clone()),
+                        });
+                    node_ids.push(node.id.clone());
                 }
             }
 
@@ -166,29 +249,16 @@ fn walk_nodes(
 }
 
 /// Run all compiled patterns against a text, returning pattern_id → matched values.
-fn extract_from_text(
-    text: &str,
-    compiled: &CompiledPatterns,
-) -> HashMap<String, Vec<String>> {
-    let mut results: HashMap<String, Vec<String>> = HashMap::new();
+fn extract_from_text(text: &str, compiled: &CompiledPatterns) -> HashMap<String, Vec<EntityValue>> {
+    let mut results: HashMap<String, Vec<EntityValue>> = HashMap::new();
 
     for pattern in &compiled.patterns {
-        let mut values: Vec<String> = Vec::new();
+        let mut values: Vec<EntityValue> = Vec::new();
 
         for cap in pattern.regex.captures_iter(text) {
-            // Use first capture group if available, otherwise full match
-            let raw = cap
-                .get(1)
-                .or_else(|| cap.get(0))
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default();
-
-            if raw.is_empty() {
-                continue;
+            if let Some((_, value)) = entity_value(pattern, &cap) {
+                values.push(value);
             }
-
-            let normalized = normalize_value(&raw, pattern.normalize.as_deref());
-            values.push(normalized);
         }
 
         // Deduplicate if configured
@@ -205,8 +275,103 @@ fn extract_from_text(
     results
 }
 
+/// The normalized value of one match, with the text it came from. `None`
+/// when the value is empty.
+fn entity_value<'t>(
+    pattern: &CompiledPattern,
+    cap: &Captures<'t>,
+) -> Option<(regex::Match<'t>, EntityValue)> {
+    let m = pattern.value_match(cap)?;
+    let raw = m.as_str().trim();
+    if raw.is_empty() {
+        return None;
+    }
+
+    let normalized = normalize_value(raw, pattern.normalize.as_deref());
+    if pattern.fields.is_empty() {
+        return Some((m, EntityValue::Plain(normalized)));
+    }
+    let fields = pattern
+        .fields
+        .iter()
+        .filter_map(|name| {
+            let field = cap.name(name)?.as_str().trim();
+            (!field.is_empty()).then(|| (name.clone(), field.to_string()))
+        })
+        .collect();
+    Some((
+        m,
+        EntityValue::Structured {
+            value: normalized,
+            fields,
+        },
+    ))
+}
+
+/// What one entity pattern matches in a sample text.
+#[derive(Debug, Serialize)]
+pub struct PatternTest {
+    pub id: String,
+    pub label: String,
+    /// Why the regex does not compile; such a pattern is skipped by the `entities` stage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub matches: Vec<PatternMatch>,
+    /// The values the `entities` stage would store (deduplicated if the pattern says so)
+    pub values: Vec<EntityValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatternMatch {
+    /// The matched value text before normalization
+    pub text: String,
+    pub value: EntityValue,
+    /// Character offsets of `text` in the sample
+    pub span: [usize; 2],
+}
+
+/// Run each regex entity pattern against a sample text, for
+/// `POST /configs/:name/test-entities`. `llm` patterns are left out.
+pub fn test_patterns(patterns: &[EntityPattern], text: &str) -> Vec<PatternTest> {
+    patterns
+        .iter()
+        .filter(|p| p.kind.is_regex())
+        .map(|p| {
+            let mut test = PatternTest {
+                id: p.id.clone(),
+                label: p.label.clone(),
+                error: None,
+                matches: Vec::new(),
+                values: Vec::new(),
+            };
+            let pattern = match CompiledPattern::new(p) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    test.error = Some(e.to_string());
+                    return test;
+                }
+            };
+            for cap in pattern.regex.captures_iter(text) {
+                let Some((m, value)) = entity_value(&pattern, &cap) else {
+                    continue;
+                };
+                let start = text[..m.start()].chars().count();
+                test.matches.push(PatternMatch {
+                    text: m.as_str().to_string(),
+                    value: value.clone(),
+                    span: [start, start + m.as_str().chars().count()],
+                });
+                if !pattern.deduplicate || !test.values.contains(&value) {
+                    test.values.push(value);
+                }
+            }
+            test
+        })
+        .collect()
+}
+
 /// Apply normalization to a matched value.
-fn normalize_value(value: &str, normalize: Option<&str>) -> String {
+pub(crate) fn normalize_value(value: &str, normalize: Option<&str>) -> String {
     match normalize {
         Some("uppercase") => value.to_uppercase(),
         Some("strip_punctuation") => value
@@ -222,6 +387,67 @@ fn normalize_value(value: &str, normalize: Option<&str>) -> String {
     }
 }
 
+/// Record where each indexed value occurs in the OCR text. Patterns run
+/// page by page, so matches spanning a page break are not located.
+/// `page_spans` comes from `extractor::page_spans`.
+pub fn locate_entities(
+    index: &mut ReferenceIndex,
+    pages: &[OcrPage],
+    page_spans: &BTreeMap<u32, [usize; 2]>,
+    compiled: &CompiledPatterns,
+) {
+    for page in pages {
+        let Some(&[page_start, _]) = page_spans.get(&page.page_num) else {
+            continue;
+        };
+        for pattern in &compiled.patterns {
+            let Some(occurrences) = index.entities.get_mut(&pattern.id) else {
+                continue;
+            };
+            for cap in pattern.regex.captures_iter(&page.text) {
+                let Some(m) = pattern.value_match(&cap) else {
+                    continue;
+                };
+                if m.as_str().is_empty() {
+                    continue;
+                }
+                let value = normalize_value(m.as_str(), pattern.normalize.as_deref());
+                if let Some(occ) = occurrences
+                    .iter_mut()
+                    .find(|o| o.value == value || o.aliases.contains(&value))
+                {
+                    let start = page_start + page.text[..m.start()].chars().count();
+                    occ.spans.push([start, start + m.as_str().chars().count()]);
+                }
+            }
+        }
+    }
+}
+
+/// Add values an LLM entity pattern found in one node to the index.
+pub fn add_llm_values(
+    index: &mut ReferenceIndex,
+    node_id: &str,
+    found: &HashMap<String, Vec<String>>,
+) {
+    for (pattern_id, values) in found {
+        let occurrences = index.entities.entry(pattern_id.clone()).or_default();
+        for value in values {
+            match occurrences.iter_mut().find(|o| &o.value == value) {
+                Some(occ) => occ.node_ids.push(node_id.to_string()),
+                None => occurrences.push(EntityOccurrence {
+                    value: value.clone(),
+                    node_ids: vec![node_id.to_string()],
+                    spans: Vec::new(),
+                    aliases: Vec::new(),
+                    source: EntityPatternKind::Llm,
+                    fields: BTreeMap::new(),
+                }),
+            }
+        }
+    }
+}
+
 /// Deduplicate node_ids in the global reference index (a node may match
 /// the same value multiple times, but we only want it listed once).
 pub fn dedup_reference_index(index: &mut ReferenceIndex) {
@@ -233,6 +459,177 @@ pub fn dedup_reference_index(index: &mut ReferenceIndex) {
     }
 }
 
+/// Words dropped when comparing names: connectives, titles, and company forms.
+const IGNORED_WORDS: &[&str] = &[
+    "a", "da", "das", "de", "do", "dos", "e", "dr", "dra", "sr", "sra", "ltda", "limitada", "sa",
+    "me", "epp", "eireli",
+];
+
+/// Abbreviations spelled out before comparing.
+const ABBREVIATIONS: &[(&str, &str)] =
+    &[("cia", "companhia"), ("bco", "banco"), ("adv", "advogado")];
+
+/// Words of a value as compared by [`resolve_entities`]: accents, case and
+/// punctuation dropped (`S.A.` and `S/A` read as `sa`), abbreviations spelled
+/// out, and [`IGNORED_WORDS`] left out. A value without letters (CPF, CNPJ,
+/// process numbers) is a single word of its digits.
+fn resolution_words(value: &str) -> Vec<String> {
+    let folded: String = value
+        .chars()
+        .map(fold_accent)
+        .flat_map(char::to_lowercase)
+        .collect();
+    if !folded.chars().any(char::is_alphabetic) {
+        let digits: String = folded.chars().filter(|c| c.is_alphanumeric()).collect();
+        return if digits.is_empty() {
+            Vec::new()
+        } else {
+            vec![digits]
+        };
+    }
+    let spaced: String = folded
+        .chars()
+        .filter(|c| !matches!(c, '.' | '/' | '\'' | '’'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let words: Vec<String> = spaced
+        .split_whitespace()
+        .filter(|w| !IGNORED_WORDS.contains(w))
+        .map(|w| {
+            ABBREVIATIONS
+                .iter()
+                .find(|(short, _)| short == &w)
+                .map_or(w, |(_, long)| long)
+                .to_string()
+        })
+        .collect();
+    if words.is_empty() {
+        // Nothing but ignored words: compare them rather than nothing
+        spaced.split_whitespace().map(str::to_string).collect()
+    } else {
+        words
+    }
+}
+
+/// Key under which variants of one entity meet, e.g. `joaosilva` for
+/// `João da Silva` and `JOAO SILVA`, or `09296295000160` for a CNPJ.
+pub fn resolution_key(value: &str) -> String {
+    resolution_words(value).concat()
+}
+
+/// Whether `short` abbreviates `long`: same first and last word, initials
+/// standing for whole words, and every word of `short` found in `long` in
+/// order (`J. Silva`, `João Silva` → `João Carlos da Silva`).
+fn abbreviates(short: &[String], long: &[String]) -> bool {
+    let word_matches =
+        |s: &String, l: &String| s == l || (s.len() == 1 && l.starts_with(s.as_str()));
+    if short.len() < 2 || short.len() > long.len() || short == long {
+        return false;
+    }
+    if short
+        .iter()
+        .chain(long)
+        .any(|w| w.chars().all(|c| c.is_ascii_digit()))
+    {
+        return false;
+    }
+    if !word_matches(&short[0], &long[0]) || short.last() != long.last() {
+        return false;
+    }
+    let mut rest = long[1..].iter();
+    short[1..].iter().all(|s| rest.any(|l| word_matches(s, l)))
+}
+
+/// Merge the occurrences of each entity type that spell the same entity:
+/// values with the same [`resolution_key`], then abbreviated names into the
+/// single fuller name they abbreviate. The fullest, most cited spelling
+/// becomes `value` and the others `aliases`; node IDs and spans are merged.
+pub fn resolve_entities(index: &mut ReferenceIndex) {
+    for occurrences in index.entities.values_mut() {
+        // Clusters of occurrences with the same words, in first-seen order
+        let mut clusters: Vec<(Vec<String>, Vec<EntityOccurrence>)> = Vec::new();
+        for occ in occurrences.drain(..) {
+            let words = resolution_words(&occ.value);
+            match clusters
+                .iter_mut()
+                .find(|(w, _)| !words.is_empty() && *w == words)
+            {
+                Some((_, members)) => members.push(occ),
+                None => clusters.push((words, vec![occ])),
+            }
+        }
+
+        // Fold each abbreviated cluster into the one fuller cluster it matches
+        let mut order: Vec<usize> = (0..clusters.len()).collect();
+        order.sort_by_key(|&i| clusters[i].0.len());
+        let mut merged_into: Vec<Option<usize>> = vec![None; clusters.len()];
+        for &i in &order {
+            let targets: Vec<usize> = (0..clusters.len())
+                .filter(|&j| j != i && merged_into[j].is_none())
+                .filter(|&j| abbreviates(&clusters[i].0, &clusters[j].0))
+                .collect();
+            if let [j] = targets[..] {
+                merged_into[i] = Some(j);
+            }
+        }
+        let mut groups: Vec<Vec<EntityOccurrence>> = Vec::new();
+        let mut group_of: HashMap<usize, usize> = HashMap::new();
+        let root = |mut i: usize| {
+            while let Some(j) = merged_into[i] {
+                i = j;
+            }
+            i
+        };
+        let roots: Vec<usize> = (0..clusters.len()).map(root).collect();
+        for (i, (_, members)) in clusters.into_iter().enumerate() {
+            let group = *group_of.entry(roots[i]).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[group].extend(members);
+        }
+
+        *occurrences = groups.into_iter().map(merge_occurrences).collect();
+    }
+}
+
+/// One occurrence standing for all of `members`.
+fn merge_occurrences(mut members: Vec<EntityOccurrence>) -> EntityOccurrence {
+    let rank = |o: &EntityOccurrence| {
+        (
+            resolution_words(&o.value).len(),
+            o.node_ids.len(),
+            o.value.chars().filter(|c| !c.is_ascii()).count(),
+            o.value.chars().count(),
+        )
+    };
+    let best = (0..members.len())
+        .max_by(|&a, &b| {
+            rank(&members[a])
+                .cmp(&rank(&members[b]))
+                .then_with(|| members[b].value.cmp(&members[a].value))
+        })
+        .unwrap_or(0);
+    let mut canonical = members.swap_remove(best);
+    for other in members {
+        canonical.node_ids.extend(other.node_ids);
+        canonical.spans.extend(other.spans);
+        canonical.aliases.push(other.value);
+        canonical.aliases.extend(other.aliases);
+        if canonical.fields.is_empty() {
+            canonical.fields = other.fields;
+        }
+    }
+    canonical.node_ids.sort();
+    canonical.node_ids.dedup();
+    canonical.spans.sort();
+    canonical.spans.dedup();
+    canonical.aliases.retain(|a| *a != canonical.value);
+    canonical.aliases.sort();
+    canonical.aliases.dedup();
+    canonical
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -243,14 +640,18 @@ mod tests {
             EntityPattern {
                 id: "cpf".to_string(),
                 label: "CPF".to_string(),
+                kind: EntityPatternKind::Regex,
                 pattern: r"(\d{3}\.\d{3}\.\d{3}-\d{2})".to_string(),
+                description: None,
                 normalize: Some("strip_punctuation".to_string()),
                 deduplicate: true,
             },
             EntityPattern {
                 id: "pnr".to_string(),
                 label: "PNR / Localizador".to_string(),
+                kind: EntityPatternKind::Regex,
                 pattern: r"\b([A-Z]{6})\b".to_string(),
+                description: None,
                 normalize: Some("uppercase".to_string()),
                 deduplicate: true,
             },
@@ -272,8 +673,73 @@ mod tests {
         let text = "CPF: 123.456.789-00, Localizador VJLXXZ, outro CPF 123.456.789-00";
         let results = extract_from_text(text, &compiled);
 
-        assert_eq!(results.get("cpf").unwrap(), &vec!["12345678900".to_string()]);
-        assert_eq!(results.get("pnr").unwrap(), &vec!["VJLXXZ".to_string()]);
+        assert_eq!(
+            results.get("cpf").unwrap(),
+            &vec![EntityValue::Plain("12345678900".to_string())]
+        );
+        assert_eq!(
+            results.get("pnr").unwrap(),
+            &vec![EntityValue::Plain("VJLXXZ".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_extract_named_fields() {
+        let patterns = vec![EntityPattern {
+            id: "flight".to_string(),
+            label: "Voo".to_string(),
+            kind: EntityPatternKind::Regex,
+            pattern: r"(?P<value>(?P<carrier>[A-Z0-9]{2})\s?(?P<number>\d{3,4}))(?:\s+em\s+(?P<date>\d{2}/\d{2}/\d{4}))?".to_string(),
+            description: None,
+            normalize: Some("strip_punctuation".to_string()),
+            deduplicate: true,
+        }];
+        let compiled = CompiledPatterns::compile(&patterns);
+
+        let results = extract_from_text("Voo LA 3456 em 12/03/2024 e voo G31234", &compiled);
+        let flights = results.get("flight").unwrap();
+        assert_eq!(flights.len(), 2);
+        let EntityValue::Structured { value, fields } = &flights[0] else {
+            panic!("expected structured value");
+        };
+        assert_eq!(value, "LA3456");
+        assert_eq!(fields["carrier"], "LA");
+        assert_eq!(fields["date"], "12/03/2024");
+        // An optional group that did not participate is left out
+        assert!(
+            matches!(&flights[1], EntityValue::Structured { fields, .. } if !fields.contains_key("date"))
+        );
+        assert_eq!(
+            serde_json::to_value(&flights[0]).unwrap(),
+            serde_json::json!({"value": "LA3456", "fields": {"carrier": "LA", "date": "12/03/2024", "number": "3456"}})
+        );
+    }
+
+    #[test]
+    fn test_test_patterns() {
+        let mut patterns = make_patterns();
+        patterns.push(EntityPattern {
+            id: "broken".to_string(),
+            label: "Broken".to_string(),
+            kind: EntityPatternKind::Regex,
+            pattern: r"(\d{3".to_string(),
+            description: None,
+            normalize: None,
+            deduplicate: true,
+        });
+
+        let tests = test_patterns(&patterns, "Réu CPF 123.456.789-00 e 123.456.789-00");
+        assert_eq!(tests.len(), 3);
+        assert_eq!(tests[0].id, "cpf");
+        assert_eq!(tests[0].matches.len(), 2);
+        assert_eq!(tests[0].matches[0].text, "123.456.789-00");
+        assert_eq!(tests[0].matches[0].span, [8, 22]);
+        assert_eq!(
+            tests[0].values,
+            vec![EntityValue::Plain("12345678900".to_string())]
+        );
+        assert!(tests[1].matches.is_empty());
+        assert!(tests[2].error.is_some());
     }
 
     #[test]
@@ -286,16 +752,131 @@ mod tests {
         assert_eq!(normalize_value("hello", None), "hello");
     }
 
+    #[test]
+    fn test_locate_entities() {
+        let compiled = CompiledPatterns::compile(&make_patterns());
+        let pages: Vec<OcrPage> = ["Réu CPF 123.456.789-00", "PNR VJLXXZ e 123.456.789-00"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| OcrPage {
+                page_num: i as u32 + 1,
+                text: text.to_string(),
+                confidence: None,
+                pdf_page: None,
+            })
+            .collect();
+        let page_spans = crate::extractor::page_spans(&pages);
+        let occurrence = |value: &str| EntityOccurrence {
+            value: value.to_string(),
+            node_ids: vec!["n1".to_string()],
+            spans: Vec::new(),
+            aliases: Vec::new(),
+            source: EntityPatternKind::Regex,
+            fields: BTreeMap::new(),
+        };
+        let mut index = ReferenceIndex {
+            entities: HashMap::from([("cpf".to_string(), vec![occurrence("12345678900")])]),
+        };
+        locate_entities(&mut index, &pages, &page_spans, &compiled);
+
+        let text = "Réu CPF 123.456.789-00\n\nPNR VJLXXZ e 123.456.789-00";
+        let spans = &index.entities["cpf"][0].spans;
+        assert_eq!(spans.len(), 2);
+        for [start, end] in spans {
+            let found: String = text.chars().skip(*start).take(end - start).collect();
+            assert_eq!(found, "123.456.789-00");
+        }
+        // Values the index does not hold are not added
+        assert!(!index.entities.contains_key("pnr"));
+    }
+
     #[test]
     fn test_invalid_regex_skipped() {
         let patterns = vec![EntityPattern {
             id: "bad".to_string(),
             label: "Bad".to_string(),
+            kind: EntityPatternKind::Regex,
             pattern: r"[invalid".to_string(),
+            description: None,
             normalize: None,
             deduplicate: true,
         }];
         let compiled = CompiledPatterns::compile(&patterns);
         assert!(compiled.is_empty());
     }
+
+    #[test]
+    fn test_resolve_entities() {
+        let occurrence = |value: &str, node_ids: &[&str]| EntityOccurrence {
+            value: value.to_string(),
+            node_ids: node_ids.iter().map(|n| n.to_string()).collect(),
+            spans: Vec::new(),
+            aliases: Vec::new(),
+            source: EntityPatternKind::Regex,
+            fields: BTreeMap::new(),
+        };
+        let mut index = ReferenceIndex {
+            entities: HashMap::from([
+                (
+                    "parte".to_string(),
+                    vec![
+                        occurrence("JOAO DA SILVA", &["n1", "n2"]),
+                        occurrence("João da Silva", &["n3"]),
+                        occurrence("J. Silva", &["n4"]),
+                        occurrence("Azul Linhas Aéreas S.A.", &["n1"]),
+                        occurrence("AZUL LINHAS AEREAS", &["n2"]),
+                        occurrence("Maria Silva", &["n5"]),
+                    ],
+                ),
+                (
+                    "cnpj".to_string(),
+                    vec![
+                        occurrence("09.296.295/0001-60", &["n1"]),
+                        occurrence("09296295000160", &["n2"]),
+                    ],
+                ),
+            ]),
+        };
+        resolve_entities(&mut index);
+
+        let partes = &index.entities["parte"];
+        assert_eq!(partes.len(), 3);
+        assert_eq!(partes[0].value, "JOAO DA SILVA");
+        assert_eq!(partes[0].aliases, vec!["J. Silva", "João da Silva"]);
+        assert_eq!(partes[0].node_ids, vec!["n1", "n2", "n3", "n4"]);
+        assert_eq!(partes[1].value, "Azul Linhas Aéreas S.A.");
+        assert_eq!(partes[1].aliases, vec!["AZUL LINHAS AEREAS"]);
+        // Different first names never merge
+        assert_eq!(partes[2].value, "Maria Silva");
+        assert!(partes[2].aliases.is_empty());
+
+        let cnpjs = &index.entities["cnpj"];
+        assert_eq!(cnpjs.len(), 1);
+        assert_eq!(cnpjs[0].node_ids, vec!["n1", "n2"]);
+        assert_eq!(resolution_key("Cia. Aérea"), "companhiaaerea");
+    }
+
+    #[test]
+    fn test_add_llm_values() {
+        let mut index = ReferenceIndex {
+            entities: HashMap::new(),
+        };
+        let found = HashMap::from([(
+            "valor".to_string(),
+            vec!["R$ 15.000,00".to_string(), "R$ 450,00".to_string()],
+        )]);
+        add_llm_values(&mut index, "n1", &found);
+        add_llm_values(
+            &mut index,
+            "n2",
+            &HashMap::from([("valor".to_string(), vec!["R$ 450,00".to_string()])]),
+        );
+
+        let valores = &index.entities["valor"];
+        assert_eq!(valores.len(), 2);
+        assert_eq!(valores[1].node_ids, vec!["n1", "n2"]);
+        assert_eq!(valores[1].source, EntityPatternKind::Llm);
+        let json = serde_json::to_value(&valores[1]).unwrap();
+        assert_eq!(json["source"], "llm");
+    }
 }
@@ -0,0 +1,302 @@
+//! Pre-extraction estimates: LLM tokens, cost, and processing time.
+//!
+//! Without OCR, a document's text is guessed from its PDF page count at
+//! `DEFAULT_CHARS_PER_PAGE`, at ~4 characters per token; with OCR the text is
+//! tokenized for each model. Either way the document is capped at the token
+//! budget extraction cuts it to. Prices are USD per million tokens, from the
+//! built-in table below or `LLM_PRICES` (`{"model": [input, output], ...}`),
+//! which overrides and extends it. Models with no known price get no cost.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::config::ExtractionConfig;
+use crate::ocr::OcrProviderKind;
+use crate::pipeline::{self, PipelineStage};
+use crate::tokens;
+
+const CHARS_PER_TOKEN: f64 = 4.0;
+
+/// Text assumed per page when the document has not been OCR'd.
+pub const DEFAULT_CHARS_PER_PAGE: usize = 2500;
+
+/// Characters of fixed instructions around the config's structure prompt.
+const STRUCTURE_INSTRUCTION_CHARS: usize = 1800;
+
+/// Structure output: a base answer plus node summaries per page, capped at `max_tokens`.
+const STRUCTURE_BASE_OUTPUT_TOKENS: u64 = 500;
+const STRUCTURE_OUTPUT_TOKENS_PER_PAGE: u64 = 60;
+const MAX_OUTPUT_TOKENS: u64 = 16384;
+
+/// A name-detection answer for `redaction.llm_names`.
+const NAMES_OUTPUT_TOKENS: u64 = 300;
+
+/// LLM entity pattern answers, per page of the document.
+const LLM_ENTITY_OUTPUT_TOKENS_PER_PAGE: u64 = 40;
+
+/// Per-call latency and generation speed of the LLM.
+const LLM_LATENCY_SECS: f64 = 2.0;
+const INPUT_TOKENS_PER_SEC: f64 = 20_000.0;
+const OUTPUT_TOKENS_PER_SEC: f64 = 150.0;
+
+/// USD per million input and output tokens.
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("google/gemini-3-flash-preview", 0.50, 3.00),
+    ("google/gemini-2.5-pro", 1.25, 10.00),
+    ("google/gemini-2.5-flash", 0.30, 2.50),
+    ("google/gemini-2.5-flash-lite", 0.10, 0.40),
+];
+
+/// How much text a document holds.
+pub struct DocumentSize {
+    pub pages: u32,
+    pub chars: usize,
+    /// The OCR text, when the document went through OCR
+    pub text: Option<String>,
+}
+
+impl DocumentSize {
+    /// Size guessed from the page count alone.
+    pub fn from_pages(pages: u32) -> Self {
+        Self {
+            pages,
+            chars: pages as usize * DEFAULT_CHARS_PER_PAGE,
+            text: None,
+        }
+    }
+}
+
+/// Token usage of one LLM-backed pipeline stage.
+#[derive(Debug, Serialize)]
+pub struct StageEstimate {
+    pub stage: &'static str,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Estimated cost and time of extracting a document with one config and model.
+#[derive(Debug, Serialize)]
+pub struct Estimate {
+    pub config: String,
+    pub model: String,
+    pub stages: Vec<StageEstimate>,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// `None` when the model has no known price.
+    pub cost_usd: Option<f64>,
+    pub ocr_secs: f64,
+    pub llm_secs: f64,
+    pub total_secs: f64,
+}
+
+/// Price table: the built-in prices with `LLM_PRICES` applied on top.
+pub struct Prices(HashMap<String, (f64, f64)>);
+
+impl Prices {
+    pub fn from_env() -> Result<Self> {
+        let mut prices: HashMap<String, (f64, f64)> = BUILTIN_PRICES
+            .iter()
+            .map(|(model, input, output)| (model.to_string(), (*input, *output)))
+            .collect();
+        if let Ok(raw) = std::env::var("LLM_PRICES") {
+            let extra: HashMap<String, (f64, f64)> =
+                serde_json::from_str(&raw).context("Invalid LLM_PRICES")?;
+            prices.extend(extra);
+        }
+        Ok(Self(prices))
+    }
+
+    /// USD for the given token counts, if the model is priced.
+    pub fn cost(&self, model: &str, input_tokens: u64, output_tokens: u64) -> Option<f64> {
+        let (input, output) = self.0.get(model)?;
+        Some((input_tokens as f64 * input + output_tokens as f64 * output) / 1_000_000.0)
+    }
+}
+
+/// Count the pages of a PDF. Anything that is not a PDF counts as one page.
+pub fn count_pages(data: &[u8]) -> Result<u32> {
+    if !data.starts_with(b"%PDF") {
+        return Ok(1);
+    }
+    let document = lopdf::Document::load_mem(data).context("Failed to parse PDF")?;
+    Ok(document.get_pages().len() as u32)
+}
+
+/// [`count_pages`] for a file on disk, parsed without reading it all into
+/// memory first. Blocking.
+pub fn count_file_pages(path: &Path) -> Result<u32> {
+    let mut magic = [0u8; 4];
+    let mut file = std::fs::File::open(path).context("Failed to open file")?;
+    if file.read_exact(&mut magic).is_err() || &magic != b"%PDF" {
+        return Ok(1);
+    }
+    let document = lopdf::Document::load(path).context("Failed to parse PDF")?;
+    Ok(document.get_pages().len() as u32)
+}
+
+/// Typical OCR time for a document, by provider.
+pub fn ocr_secs(provider: OcrProviderKind, pages: u32) -> f64 {
+    let per_page = match provider {
+        OcrProviderKind::Docling => 1.0,
+        OcrProviderKind::MistralOcr => 0.3,
+        OcrProviderKind::SmolDocling => 3.0,
+    };
+    per_page * pages as f64
+}
+
+/// Estimate the LLM stages of `config`'s pipeline for a document of `size`.
+pub fn estimate(
+    size: &DocumentSize,
+    config: &ExtractionConfig,
+    model: &str,
+    prices: &Prices,
+    ocr_secs: f64,
+) -> Estimate {
+    let document_tokens = match size.text {
+        Some(ref text) => tokens::count(model, text) as u64,
+        None => tokens(size.chars),
+    }
+    .min(tokens::document_budget(model) as u64);
+    let structure_output = (STRUCTURE_BASE_OUTPUT_TOKENS
+        + STRUCTURE_OUTPUT_TOKENS_PER_PAGE * size.pages as u64)
+        .min(MAX_OUTPUT_TOKENS);
+
+    let mut stages = Vec::new();
+    for stage in pipeline::stages(config) {
+        match stage {
+            PipelineStage::Structure => stages.push(StageEstimate {
+                stage: stage.as_str(),
+                input_tokens: document_tokens
+                    + tokens(config.prompts.structure.len() + STRUCTURE_INSTRUCTION_CHARS),
+                output_tokens: structure_output,
+            }),
+            // Summaries make up most of the structure answer and are sent back whole
+            PipelineStage::Translate => stages.push(StageEstimate {
+                stage: stage.as_str(),
+                input_tokens: structure_output,
+                output_tokens: structure_output,
+            }),
+            // Leaf nodes cover the document about once
+            PipelineStage::Entities
+                if config.entity_patterns.iter().any(|p| !p.kind.is_regex()) =>
+            {
+                stages.push(StageEstimate {
+                    stage: stage.as_str(),
+                    input_tokens: document_tokens,
+                    output_tokens: LLM_ENTITY_OUTPUT_TOKENS_PER_PAGE * size.pages as u64,
+                })
+            }
+            PipelineStage::Redact if config.redaction.as_ref().is_some_and(|r| r.llm_names) => {
+                stages.push(StageEstimate {
+                    stage: stage.as_str(),
+                    input_tokens: document_tokens,
+                    output_tokens: NAMES_OUTPUT_TOKENS,
+                })
+            }
+            _ => {}
+        }
+    }
+
+    let input_tokens = stages.iter().map(|s| s.input_tokens).sum();
+    let output_tokens = stages.iter().map(|s| s.output_tokens).sum();
+    let llm_secs = stages
+        .iter()
+        .map(|s| {
+            LLM_LATENCY_SECS
+                + s.input_tokens as f64 / INPUT_TOKENS_PER_SEC
+                + s.output_tokens as f64 / OUTPUT_TOKENS_PER_SEC
+        })
+        .sum::<f64>();
+    Estimate {
+        config: config.name.clone(),
+        model: model.to_string(),
+        stages,
+        input_tokens,
+        output_tokens,
+        cost_usd: prices.cost(model, input_tokens, output_tokens),
+        ocr_secs: round(ocr_secs),
+        llm_secs: round(llm_secs),
+        total_secs: round(ocr_secs + llm_secs),
+    }
+}
+
+fn tokens(chars: usize) -> u64 {
+    (chars as f64 / CHARS_PER_TOKEN).ceil() as u64
+}
+
+fn round(secs: f64) -> f64 {
+    (secs * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pipeline: serde_json::Value) -> ExtractionConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": "test",
+            "description": "",
+            "prompts": {"structure": "x".repeat(200)},
+            "pipeline": pipeline,
+            "redaction": {"llm_names": true},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_estimate() {
+        let prices = Prices::from_env().unwrap();
+        let size = DocumentSize::from_pages(10);
+
+        let plain = estimate(
+            &size,
+            &config(serde_json::Value::Null),
+            "google/gemini-2.5-pro",
+            &prices,
+            10.0,
+        );
+        assert_eq!(plain.stages.len(), 1);
+        assert_eq!(plain.stages[0].stage, "structure");
+        assert_eq!(plain.input_tokens, 6250 + 500);
+        assert_eq!(plain.output_tokens, 1100);
+        let cost = plain.cost_usd.unwrap();
+        assert!((cost - (6750.0 * 1.25 + 1100.0 * 10.0) / 1e6).abs() < 1e-9);
+        assert!(plain.total_secs > plain.ocr_secs);
+
+        let full = estimate(
+            &size,
+            &config(serde_json::json!([
+                "ocr",
+                "structure",
+                "slice_content",
+                "translate",
+                "redact"
+            ])),
+            "someone/unpriced",
+            &prices,
+            0.0,
+        );
+        let stages: Vec<_> = full.stages.iter().map(|s| s.stage).collect();
+        assert_eq!(stages, ["structure", "translate", "redact"]);
+        assert_eq!(full.stages[2].input_tokens, 6250);
+        assert!(full.cost_usd.is_none());
+
+        // Long documents are truncated before the LLM sees them
+        let long = estimate(
+            &DocumentSize::from_pages(1000),
+            &config(serde_json::Value::Null),
+            "",
+            &prices,
+            0.0,
+        );
+        assert_eq!(long.input_tokens, 40_000 + 500);
+        assert_eq!(long.output_tokens, MAX_OUTPUT_TOKENS);
+
+        assert_eq!(count_pages(b"plain text").unwrap(), 1);
+        assert!(count_pages(b"%PDF-1.4 garbage").is_err());
+    }
+}
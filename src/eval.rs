@@ -0,0 +1,436 @@
+//! Ground-truth evaluation of extraction quality.
+//!
+//! A golden case is a (usually reviewed) extraction tree saved together with
+//! the OCR output it was extracted from, under `EVAL_DIR` (default
+//! `data/eval`) as `goldens/{name}.json`. An eval run re-extracts every case's
+//! OCR with a config and model, scores the new tree against the golden one,
+//! and saves the report under `reports/`:
+//!
+//! - **boundaries** — nodes whose `page_range` matches a golden node exactly
+//! - **types** — boundary matches that also agree on the node type
+//! - **relationships** — relationships between matched nodes with the same type
+//!
+//! Each is reported as precision, recall and F1, per case and summed over
+//! the run.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+use crate::object_store::StoredOcr;
+use crate::safe_name;
+use crate::schema::{now_iso8601, DocumentNode, Relationship};
+
+/// A document with its expected structure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenCase {
+    pub name: String,
+    pub created_at: String,
+    /// Extraction the expected tree was taken from
+    pub extraction_id: String,
+    pub source_file: String,
+    pub config_name: Option<String>,
+    pub ocr: StoredOcr,
+    pub nodes: Vec<DocumentNode>,
+    pub relationships: Vec<Relationship>,
+}
+
+/// A golden case without its OCR text and tree.
+#[derive(Debug, Serialize)]
+pub struct GoldenSummary {
+    pub name: String,
+    pub created_at: String,
+    pub extraction_id: String,
+    pub source_file: String,
+    pub config_name: Option<String>,
+    pub total_pages: u32,
+    pub node_count: usize,
+    pub relationship_count: usize,
+}
+
+impl GoldenCase {
+    pub fn summary(&self) -> GoldenSummary {
+        GoldenSummary {
+            name: self.name.clone(),
+            created_at: self.created_at.clone(),
+            extraction_id: self.extraction_id.clone(),
+            source_file: self.source_file.clone(),
+            config_name: self.config_name.clone(),
+            total_pages: self.ocr.total_pages,
+            node_count: flatten(&self.nodes).len(),
+            relationship_count: self.relationships.len(),
+        }
+    }
+}
+
+/// Precision and recall of one kind of match.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Score {
+    pub matched: usize,
+    pub predicted: usize,
+    pub expected: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+impl Score {
+    pub fn new(matched: usize, predicted: usize, expected: usize) -> Self {
+        let ratio = |a: usize, b: usize| if b == 0 { 1.0 } else { a as f64 / b as f64 };
+        let precision = ratio(matched, predicted);
+        let recall = ratio(matched, expected);
+        let f1 = if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        };
+        Self {
+            matched,
+            predicted,
+            expected,
+            precision: round(precision),
+            recall: round(recall),
+            f1: round(f1),
+        }
+    }
+
+    /// Micro-average: add up the counts and recompute the ratios.
+    pub fn add(self, other: Score) -> Score {
+        Score::new(
+            self.matched + other.matched,
+            self.predicted + other.predicted,
+            self.expected + other.expected,
+        )
+    }
+}
+
+/// Scores of one extracted tree against its golden case.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Comparison {
+    pub boundaries: Score,
+    pub types: Score,
+    pub relationships: Score,
+}
+
+/// Compare a predicted tree and relationships against the expected ones.
+pub fn compare(
+    expected: &[DocumentNode],
+    expected_relationships: &[Relationship],
+    predicted: &[DocumentNode],
+    predicted_relationships: &[Relationship],
+) -> Comparison {
+    let expected_nodes = flatten(expected);
+    let predicted_nodes = flatten(predicted);
+
+    // Match each predicted node to an unused golden node with the same page
+    // range, preferring one of the same type.
+    let mut used = vec![false; expected_nodes.len()];
+    let mut matches: HashMap<&str, &str> = HashMap::new();
+    let mut typed = 0;
+    for node in &predicted_nodes {
+        let Some(range) = node.page_range else {
+            continue;
+        };
+        let candidates: Vec<usize> = (0..expected_nodes.len())
+            .filter(|&i| !used[i] && expected_nodes[i].page_range == Some(range))
+            .collect();
+        let same_type = candidates
+            .iter()
+            .copied()
+            .find(|&i| same_type(expected_nodes[i], node));
+        if let Some(i) = same_type.or(candidates.first().copied()) {
+            used[i] = true;
+            matches.insert(node.id.as_str(), expected_nodes[i].id.as_str());
+            if same_type.is_some() {
+                typed += 1;
+            }
+        }
+    }
+
+    let key = |from: &str, to: &str, rel_type: &str| {
+        (from.to_string(), to.to_string(), rel_type.to_lowercase())
+    };
+    let expected_rels: HashSet<_> = expected_relationships
+        .iter()
+        .map(|r| key(&r.from, &r.to, &r.rel_type))
+        .collect();
+    let predicted_rels: HashSet<_> = predicted_relationships
+        .iter()
+        .map(|r| {
+            // Unmatched endpoints keep their predicted ID and so never match
+            let from = matches.get(r.from.as_str()).copied().unwrap_or(&r.from);
+            let to = matches.get(r.to.as_str()).copied().unwrap_or(&r.to);
+            key(from, to, &r.rel_type)
+        })
+        .collect();
+
+    Comparison {
+        boundaries: Score::new(matches.len(), predicted_nodes.len(), expected_nodes.len()),
+        types: Score::new(typed, predicted_nodes.len(), expected_nodes.len()),
+        relationships: Score::new(
+            predicted_rels.intersection(&expected_rels).count(),
+            predicted_rels.len(),
+            expected_rels.len(),
+        ),
+    }
+}
+
+fn same_type(a: &DocumentNode, b: &DocumentNode) -> bool {
+    a.node_type.eq_ignore_ascii_case(&b.node_type)
+}
+
+/// Every node of a tree, parents before children.
+pub fn flatten(nodes: &[DocumentNode]) -> Vec<&DocumentNode> {
+    let mut out = Vec::new();
+    for node in nodes {
+        out.push(node);
+        out.extend(flatten(&node.children));
+    }
+    out
+}
+
+fn round(x: f64) -> f64 {
+    (x * 1000.0).round() / 1000.0
+}
+
+/// Result for one golden case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub case: String,
+    #[serde(flatten)]
+    pub scores: Comparison,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// One eval run of a config and model over the golden cases.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub id: String,
+    pub config: String,
+    pub model: String,
+    pub run_at: String,
+    /// Totals over the cases that extracted (micro-averaged)
+    #[serde(flatten)]
+    pub totals: Comparison,
+    pub failed: usize,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn new(config: String, model: String, cases: Vec<CaseResult>) -> Self {
+        let mut totals = Comparison::default();
+        for case in cases.iter().filter(|c| c.error.is_none()) {
+            totals.boundaries = totals.boundaries.add(case.scores.boundaries);
+            totals.types = totals.types.add(case.scores.types);
+            totals.relationships = totals.relationships.add(case.scores.relationships);
+        }
+        Self {
+            id: format!("eval_{}", uuid::Uuid::new_v4().simple()),
+            config,
+            model,
+            run_at: now_iso8601(),
+            totals,
+            failed: cases.iter().filter(|c| c.error.is_some()).count(),
+            cases,
+        }
+    }
+
+    /// One row of the config/model comparison.
+    pub fn summary(&self) -> ReportSummary {
+        ReportSummary {
+            id: self.id.clone(),
+            config: self.config.clone(),
+            model: self.model.clone(),
+            run_at: self.run_at.clone(),
+            cases: self.cases.len(),
+            failed: self.failed,
+            boundaries_f1: self.totals.boundaries.f1,
+            types_f1: self.totals.types.f1,
+            relationships_f1: self.totals.relationships.f1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportSummary {
+    pub id: String,
+    pub config: String,
+    pub model: String,
+    pub run_at: String,
+    pub cases: usize,
+    pub failed: usize,
+    pub boundaries_f1: f64,
+    pub types_f1: f64,
+    pub relationships_f1: f64,
+}
+
+/// Golden cases and reports on disk.
+pub struct EvalStore {
+    dir: PathBuf,
+}
+
+impl EvalStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(dir.join("goldens"))?;
+        std::fs::create_dir_all(dir.join("reports"))?;
+        Ok(Self { dir })
+    }
+
+    /// Open the store in `EVAL_DIR` (default `data/eval`).
+    pub fn from_env() -> Result<Self> {
+        Self::open(std::env::var("EVAL_DIR").unwrap_or_else(|_| "data/eval".to_string()))
+    }
+
+    /// Save a golden case, replacing one with the same name.
+    pub fn save_golden(&self, case: &GoldenCase) -> Result<()> {
+        write_json(&self.path("goldens", &case.name), case)
+    }
+
+    pub fn goldens(&self) -> Vec<GoldenCase> {
+        let mut cases: Vec<GoldenCase> = read_all(&self.dir.join("goldens"));
+        cases.sort_by(|a, b| a.name.cmp(&b.name));
+        cases
+    }
+
+    /// Delete a golden case; `false` if there was none.
+    pub fn delete_golden(&self, name: &str) -> Result<bool> {
+        match std::fs::remove_file(self.path("goldens", name)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_report(&self, report: &EvalReport) -> Result<()> {
+        write_json(&self.path("reports", &report.id), report)
+    }
+
+    /// All reports, newest first.
+    pub fn reports(&self) -> Vec<EvalReport> {
+        let mut reports: Vec<EvalReport> = read_all(&self.dir.join("reports"));
+        reports.sort_by(|a, b| b.run_at.cmp(&a.run_at));
+        reports
+    }
+
+    pub fn report(&self, id: &str) -> Result<Option<EvalReport>> {
+        let path = self.path("reports", id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    fn path(&self, kind: &str, name: &str) -> PathBuf {
+        self.dir
+            .join(kind)
+            .join(format!("{}.json", safe_name::file_name(name)))
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let bytes = serde_json::to_vec_pretty(value)?;
+    std::fs::write(path, bytes).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+fn read_all<T: for<'de> Deserialize<'de>>(dir: &Path) -> Vec<T> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Failed to read eval dir {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .filter_map(|path| {
+            match std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+            {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    error!("Skipping unreadable eval file {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, node_type: &str, range: [u32; 2]) -> DocumentNode {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": node_type,
+            "page_range": range,
+            "summary": "",
+        }))
+        .unwrap()
+    }
+
+    fn rel(from: &str, to: &str) -> Relationship {
+        Relationship {
+            from: from.into(),
+            to: to.into(),
+            rel_type: "responds_to".into(),
+            citation: None,
+        }
+    }
+
+    #[test]
+    fn test_compare_trees() {
+        let expected = vec![
+            node("g1", "PETICAO", [1, 5]),
+            node("g2", "CONTESTACAO", [6, 9]),
+            node("g3", "SENTENCA", [10, 12]),
+        ];
+        let predicted = vec![
+            node("p1", "PETICAO", [1, 5]),
+            node("p2", "DECISAO", [6, 9]),
+            node("p3", "SENTENCA", [10, 11]),
+            node("p4", "SENTENCA", [12, 12]),
+        ];
+        let scores = compare(
+            &expected,
+            &[rel("g2", "g1"), rel("g3", "g2")],
+            &predicted,
+            &[rel("p2", "p1"), rel("p3", "p2")],
+        );
+        assert_eq!(scores.boundaries.matched, 2);
+        assert_eq!(scores.boundaries.precision, 0.5);
+        assert_eq!(scores.boundaries.recall, 0.667);
+        assert_eq!(scores.types.matched, 1);
+        // p3 has no golden counterpart, so only p2 -> p1 matches
+        assert_eq!(scores.relationships.matched, 1);
+        assert_eq!(scores.relationships.f1, 0.5);
+
+        let report = EvalReport::new(
+            "legal_br".into(),
+            "m".into(),
+            vec![CaseResult {
+                case: "a".into(),
+                scores: scores.clone(),
+                error: None,
+                duration_ms: 0,
+            }],
+        );
+        assert_eq!(report.totals.boundaries.f1, scores.boundaries.f1);
+
+        let dir = std::env::temp_dir().join(format!("eval_test_{}", uuid::Uuid::new_v4()));
+        let store = EvalStore::open(&dir).unwrap();
+        store.save_report(&report).unwrap();
+        assert_eq!(store.reports()[0].id, report.id);
+        assert!(store.report("missing").unwrap().is_none());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
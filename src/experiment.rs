@@ -0,0 +1,118 @@
+//! A/B extraction of one document.
+//!
+//! `POST /experiments` runs OCR once and then extracts the structure twice,
+//! with variant `a` and variant `b` (each a config and a model) at the same
+//! time. [`compare`] scores `b` against `a` with the eval metrics (see
+//! [`crate::eval::compare`]): precision is the share of b's nodes that a also
+//! has, recall the share of a's nodes that b found. It also lists the page
+//! ranges only one side split out and the node count per type on each side.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::eval;
+use crate::schema::Extraction;
+
+/// One side of an experiment.
+#[derive(Debug, Serialize)]
+pub struct VariantResult {
+    pub config: String,
+    /// Fingerprint of the config (see `GET /configs/:name/versions`)
+    pub config_version: String,
+    pub model: String,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extraction: Option<Extraction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How the two trees differ.
+#[derive(Debug, Serialize)]
+pub struct StructuralDiff {
+    #[serde(flatten)]
+    pub scores: eval::Comparison,
+    /// Node count per type, as `[a, b]`
+    pub node_types: BTreeMap<String, [usize; 2]>,
+    /// Page ranges with a node in a but not in b
+    pub only_a: Vec<[u32; 2]>,
+    /// Page ranges with a node in b but not in a
+    pub only_b: Vec<[u32; 2]>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentReport {
+    pub id: String,
+    pub run_at: String,
+    pub source_file: String,
+    pub total_pages: u32,
+    pub ocr_provider: String,
+    pub ocr_ms: u64,
+    pub a: VariantResult,
+    pub b: VariantResult,
+    /// Missing when either variant failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<StructuralDiff>,
+}
+
+/// Score `b` against `a`.
+pub fn compare(a: &Extraction, b: &Extraction) -> StructuralDiff {
+    let a_nodes = eval::flatten(&a.children);
+    let b_nodes = eval::flatten(&b.children);
+
+    let mut node_types: BTreeMap<String, [usize; 2]> = BTreeMap::new();
+    for (side, nodes) in [&a_nodes, &b_nodes].into_iter().enumerate() {
+        for node in nodes {
+            node_types.entry(node.node_type.to_uppercase()).or_default()[side] += 1;
+        }
+    }
+
+    let ranges = |nodes: &[&crate::schema::DocumentNode]| -> BTreeSet<[u32; 2]> {
+        nodes.iter().filter_map(|n| n.page_range).collect()
+    };
+    let (a_ranges, b_ranges) = (ranges(&a_nodes), ranges(&b_nodes));
+
+    StructuralDiff {
+        scores: eval::compare(&a.children, &a.relationships, &b.children, &b.relationships),
+        node_types,
+        only_a: a_ranges.difference(&b_ranges).copied().collect(),
+        only_b: b_ranges.difference(&a_ranges).copied().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::DocumentNode;
+
+    fn node(id: &str, node_type: &str, range: [u32; 2]) -> DocumentNode {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": node_type,
+            "page_range": range,
+            "summary": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare_variants() {
+        let mut a = Extraction::new("doc.pdf".into(), Some("legal_br".into()));
+        a.children = vec![node("a1", "PETICAO", [1, 3]), node("a2", "DECISAO", [4, 5])];
+        let mut b = Extraction::new("doc.pdf".into(), Some("legal_br".into()));
+        b.children = vec![
+            node("b1", "PETICAO", [1, 3]),
+            node("b2", "DESPACHO", [4, 4]),
+            node("b3", "DESPACHO", [5, 5]),
+        ];
+
+        let diff = compare(&a, &b);
+        assert_eq!(diff.scores.boundaries.matched, 1);
+        assert_eq!(diff.scores.boundaries.recall, 0.5);
+        assert_eq!(diff.node_types["PETICAO"], [1, 1]);
+        assert_eq!(diff.node_types["DESPACHO"], [0, 2]);
+        assert_eq!(diff.only_a, vec![[4, 5]]);
+        assert_eq!(diff.only_b, vec![[4, 4], [5, 5]]);
+    }
+}
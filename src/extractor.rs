@@ -1,16 +1,49 @@
 //! Document extraction pipeline using LLM with pluggable OCR providers.
 
-use crate::config::ExtractionConfig;
+use crate::confidence;
+use crate::config::{EntityPattern, ExtractionConfig};
 use crate::content_store::ContentStore;
-use crate::entities::{self, CompiledPatterns};
+use crate::dedup;
+use crate::entities::{self, CompiledPatterns, ReferenceIndex};
+use crate::language;
+use crate::metadata;
 use crate::ocr::{OcrPage, OcrResult};
 use crate::openrouter::{Message, OpenRouterClient};
-use crate::schema::{
-    ConfidenceScores, DocumentNode, EmbeddedReference, Extraction, Relationship, StructureMapEntry,
-};
+use crate::partes;
+use crate::prompt::{self, PromptVars};
+use crate::readable_id;
+use crate::redaction::{self, Redactor};
+use crate::relationships;
+use crate::schema::{DocumentNode, EmbeddedReference, Extraction, Relationship};
+use crate::tokens;
 use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
-use tracing::{debug, info};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info, warn};
+
+/// An LLM answer that could not be read as the JSON a call asked for.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to parse LLM {call} response: {detail}")]
+pub struct LlmParseError {
+    /// Which call answered (`structure`, `parties`, ...)
+    pub call: &'static str,
+    pub detail: String,
+    /// The start of the raw answer, for failure bundles
+    pub response: String,
+}
+
+/// Characters of a raw LLM answer kept in an [`LlmParseError`].
+const RESPONSE_SNIPPET_CHARS: usize = 4000;
+
+impl LlmParseError {
+    pub fn new(call: &'static str, error: anyhow::Error, response: &str) -> Self {
+        Self {
+            call,
+            detail: format!("{:#}", error),
+            response: response.chars().take(RESPONSE_SNIPPET_CHARS).collect(),
+        }
+    }
+}
 
 /// Extraction pipeline orchestrator.
 pub struct Extractor {
@@ -26,9 +59,28 @@ impl Extractor {
         }
     }
 
-    /// Extract structure from a document using OCR output and LLM.
+    /// The OCR text to put in a prompt, cut to `max_tokens` (at a page break
+    /// when one fits).
+    fn document_context<'a>(&self, ocr: &'a OcrResult, max_tokens: usize) -> &'a str {
+        let model = self.client.model();
+        let text = tokens::fit(model, &ocr.markdown, max_tokens, &page_ends(ocr));
+        if text.len() < ocr.markdown.len() {
+            info!(
+                "Document cut to {} of {} bytes to fit {} tokens ({})",
+                text.len(),
+                ocr.markdown.len(),
+                max_tokens,
+                model
+            );
+        }
+        text
+    }
+
+    /// `structure` stage: ask the LLM for the document's hierarchy.
     /// Uses token-cache-friendly prompt structure: document in system, instructions in user.
-    pub async fn extract(
+    ///
+    /// Nodes come back without content; `readable_id` holds the LLM's raw answer.
+    pub async fn structure(
         &self,
         filename: &str,
         ocr: &OcrResult,
@@ -50,14 +102,40 @@ impl Extractor {
             format!("{:x}", hasher.finalize())
         };
 
+        let detected_language = language::detect(&ocr.markdown);
+        match (config.language.as_deref(), detected_language) {
+            (Some(expected), Some(detected)) if !expected.eq_ignore_ascii_case(detected) => warn!(
+                "{} looks like {} but config {} expects {}",
+                filename,
+                language::name(detected),
+                config.name,
+                language::name(expected)
+            ),
+            _ => debug!("Detected language for {}: {:?}", filename, detected_language),
+        }
+
         // Build token-cache-friendly messages:
         // - System message contains config prompt + full document (CACHED PREFIX)
         // - User message contains extraction instructions (VARIABLE SUFFIX)
+        let language = config.language.as_deref().or(detected_language);
+        let node_types = type_alternatives(config);
+        let relationship_types = relationship_alternatives(config);
+        let instructions = prompt::render(
+            &config.prompts.structure,
+            &PromptVars {
+                filename,
+                total_pages: ocr.total_pages,
+                node_types: &node_types,
+                relationship_types: &relationship_types,
+                language: language.unwrap_or(""),
+                config: &config.name,
+            },
+        )?;
         let system_prompt = format!(
             "{}\n\n--- DOCUMENT START (pages 1-{}) ---\n\n{}\n\n--- DOCUMENT END ---",
-            config.prompts.structure,
+            instructions,
             ocr.total_pages,
-            truncate_for_context(&ocr.markdown, 150000) // ~150K chars max
+            self.document_context(ocr, tokens::document_budget(self.client.model()))
         );
 
         let readable_id_line = if let Some(hint) = &config.readable_id_hint {
@@ -69,18 +147,17 @@ impl Extractor {
             r#"  "readable_id": "primary human-readable identifier (e.g. case number, invoice ID, contract number)","#.to_string()
         };
 
-        let user_prompt = format!(
+        let mut user_prompt = format!(
             r#"Based on the document above, extract its hierarchical structure as JSON. Return ONLY valid JSON with this structure:
 
 {{
   "summary": "2-4 sentence overview",
 {}
-  "structure_map": [{{"id": "...", "label": "...", "children": ["id1", "id2"]}}],
   "metadata": {{...}},
   "children": [
     {{
       "id": "unique_id",
-      "type": "DOCUMENT|PETICAO|DECISAO|RECURSO|SECTION|GROUP",
+      "type": "{}",
       "subtype": "Specific type if applicable",
       "label": "Human readable label",
       "page_range": [start_page, end_page],
@@ -91,13 +168,23 @@ impl Extractor {
     }}
   ],
   "relationships": [
-    {{"from": "id1", "to": "id2", "type": "references|responds_to|decides_on|appeals"}}
+    {{"from": "id1", "to": "id2", "type": "{}"}}
   ]
 }}"#,
-            readable_id_line
+            readable_id_line, node_types, relationship_types,
         );
+        user_prompt.push_str(&node_type_guide(config));
+        if let Some(lang) = language {
+            user_prompt.push_str(&format!(
+                "\n\nWrite labels and summaries in {}.",
+                language::name(lang)
+            ));
+        }
 
-        let messages = vec![Message::system(system_prompt), Message::user(user_prompt)];
+        let messages = vec![
+            Message::cached_system(system_prompt),
+            Message::user(user_prompt),
+        ];
 
         // Call LLM for structure extraction
         debug!("Calling LLM for structure extraction (document cached in system prompt)");
@@ -106,15 +193,26 @@ impl Extractor {
         debug!("Raw LLM response length: {} chars", response.len());
 
         // Parse the JSON response
-        let extracted: ExtractedStructure =
-            parse_llm_json(&response).context("Failed to parse LLM structure response")?;
+        let (extracted, json_repairs): (ExtractedStructure, u32) =
+            parse_llm_json_repaired(&response)
+                .map_err(|e| LlmParseError::new("structure", e, &response))?;
+        if json_repairs > 0 {
+            warn!(
+                "LLM structure response for {} needed {} JSON repair(s)",
+                filename, json_repairs
+            );
+        }
 
         // Build the Extraction object
         let mut extraction = Extraction::new(filename.to_string(), Some(config.name.clone()));
+        extraction.config_version = Some(crate::scheduler::fingerprint(config));
         extraction.content_hash = Some(content_hash);
+        extraction.fingerprint = dedup::fingerprint(&ocr.markdown);
+        extraction.language = detected_language.map(str::to_string);
         extraction.total_pages = Some(ocr.total_pages);
+        extraction.ocr_provider = Some(ocr.provider_name.clone());
+        extraction.ocr_confidence = Some(ocr.ocr_confidence);
         extraction.summary = extracted.summary;
-        extraction.structure_map = extracted.structure_map;
 
         // Convert relationships
         extraction.relationships = extracted
@@ -130,45 +228,43 @@ impl Extractor {
 
         // Store metadata as-is
         extraction.metadata = extracted.metadata.unwrap_or(serde_json::Value::Null);
-
-        // Store readable_id from LLM
         extraction.readable_id = extracted.readable_id;
-
-        // Process children and populate content_ref with page-sliced OCR
-        extraction.children =
-            self.process_children(extracted.children, &ocr.pages, ocr.ocr_confidence)?;
-
-        // Run regex-based entity extraction if config has patterns
-        if !config.entity_patterns.is_empty() {
-            let compiled = CompiledPatterns::compile(&config.entity_patterns);
-            if !compiled.is_empty() {
-                let (node_entity_map, mut ref_index) = entities::extract_entities(
-                    &extraction.children,
-                    &self.content_store,
-                    &compiled,
-                );
-
-                // Deduplicate node_ids in the global reference index
-                entities::dedup_reference_index(&mut ref_index);
-
-                // Merge regex entities into node metadata under `_entities` key
-                // LLM-provided metadata takes precedence (regex goes under `_entities`)
-                merge_entities_into_nodes(&mut extraction.children, &node_entity_map);
-
-                // Set extraction-level reference_index
-                extraction.reference_index =
-                    serde_json::to_value(&ref_index).unwrap_or(serde_json::Value::Null);
-
-                info!(
-                    "Entity extraction: {} entity types across {} nodes",
-                    ref_index.entities.len(),
-                    node_entity_map.len()
-                );
-            }
+        extraction.children = convert_nodes(extracted.children);
+        canonicalize_node_types(&mut extraction.children, config);
+        relationships::validate(&mut extraction, config);
+        relationships::link(&mut extraction);
+        extraction.refresh_structure_map();
+        if let Some(ref diagnostics) = extraction.diagnostics {
+            warn!(
+                "Relationships of {}: {} repaired, {} dropped",
+                filename,
+                diagnostics.repaired_relationships.len(),
+                diagnostics.dropped_relationships.len()
+            );
+        }
+        if config.structured_partes {
+            self.complete_partes(&mut extraction, ocr).await;
+        }
+        let validation = metadata::validate_extraction(&mut extraction, config);
+        if validation.errors > 0 {
+            warn!(
+                "Metadata of {} has {} schema error(s) after {} coercion(s)",
+                filename, validation.errors, validation.coerced
+            );
         }
+        confidence::score(
+            &mut extraction.children,
+            &confidence::Signals {
+                config,
+                pages: &ocr.pages,
+                total_pages: ocr.total_pages,
+                ocr_confidence: ocr.ocr_confidence,
+                json_repairs,
+            },
+        );
 
         info!(
-            "Extraction complete: {} top-level nodes, {} relationships",
+            "Structure extracted: {} top-level nodes, {} relationships",
             extraction.children.len(),
             extraction.relationships.len()
         );
@@ -176,76 +272,601 @@ impl Extractor {
         Ok(extraction)
     }
 
-    /// Process extracted children, storing sliced page content.
-    fn process_children(
+    /// Replace `metadata.partes` with structured parties, asking the LLM for
+    /// the parties alone when the structure call left them incomplete. A
+    /// failed follow-up keeps what the structure call returned.
+    async fn complete_partes(&self, extraction: &mut Extraction, ocr: &OcrResult) {
+        let mut parsed = extraction
+            .metadata
+            .get("partes")
+            .map(partes::parse)
+            .unwrap_or_default();
+        if !partes::is_complete(&parsed) {
+            match self.ask_partes(ocr).await {
+                Ok(follow_up) => partes::merge(&mut parsed, follow_up),
+                Err(e) => warn!(
+                    "Parties follow-up for {} failed: {}",
+                    extraction.source_file, e
+                ),
+            }
+        }
+        if parsed.is_empty() {
+            return;
+        }
+        if extraction.metadata.is_null() {
+            extraction.metadata = serde_json::Value::Object(serde_json::Map::new());
+        }
+        if let Some(obj) = extraction.metadata.as_object_mut() {
+            obj.insert("partes".to_string(), serde_json::json!(parsed));
+        }
+    }
+
+    async fn ask_partes(&self, ocr: &OcrResult) -> Result<Vec<partes::Parte>> {
+        let messages = vec![
+            Message::cached_system(format!(
+                "Você identifica as partes de processos judiciais brasileiros.\n\n--- DOCUMENT START ---\n\n{}\n\n--- DOCUMENT END ---",
+                self.document_context(
+                    ocr,
+                    PARTES_CONTEXT_TOKENS.min(tokens::document_budget(self.client.model()))
+                )
+            )),
+            Message::user(
+                r#"Liste todas as partes do processo com seus advogados. Retorne SOMENTE JSON válido:
+{"partes": [{"nome": "Nome completo ou razão social", "polo": "ATIVO|PASSIVO|TERCEIRO", "tipo_pessoa": "FÍSICA|JURÍDICA", "cpf_cnpj": "CPF ou CNPJ se constar", "advogados": [{"nome": "Nome", "oab": "OAB/UF 000000"}]}]}"#
+                    .to_string(),
+            ),
+        ];
+        let response = self.client.chat(messages).await?;
+        let parsed: serde_json::Value =
+            parse_llm_json(&response).map_err(|e| LlmParseError::new("parties", e, &response))?;
+        Ok(partes::parse(parsed.get("partes").unwrap_or(&parsed)))
+    }
+
+    /// `slice_content` stage: store each node's page range of OCR text as
+    /// lazy-loadable content, and anchor the node to it with `ocr_span`.
+    pub fn slice_content(&self, extraction: &mut Extraction, pages: &[OcrPage]) {
+        fn walk(store: &ContentStore, nodes: &mut [DocumentNode], pages: &[OcrPage]) {
+            for node in nodes {
+                if let Some(range) = node.page_range {
+                    let content = slice_pages(pages, range);
+                    if !content.is_empty() {
+                        node.content_ref = Some(store.store(&node.id, content));
+                    }
+                }
+                walk(store, &mut node.children, pages);
+            }
+        }
+        walk(&self.content_store, &mut extraction.children, pages);
+        assign_ocr_spans(&mut extraction.children, &page_spans(pages));
+        assign_pdf_page_ranges(&mut extraction.children, &pdf_pages(pages));
+    }
+
+    /// `entities` stage: run the config's regex patterns over node content.
+    /// With the OCR `pages`, every reference index entry also gets the
+    /// `spans` where its value occurs.
+    pub fn extract_entities(
         &self,
-        nodes: Vec<ExtractedNode>,
-        pages: &[OcrPage],
-        ocr_confidence: f64,
-    ) -> Result<Vec<DocumentNode>> {
-        let mut result = Vec::new();
+        extraction: &mut Extraction,
+        config: &ExtractionConfig,
+        pages: Option<&[OcrPage]>,
+    ) {
+        if config.entity_patterns.is_empty() {
+            return;
+        }
+        let compiled = CompiledPatterns::compile(&config.entity_patterns);
+        if compiled.is_empty() {
+            return;
+        }
 
-        for node in nodes {
-            // Extract content for this node's page range from Docling OCR
-            let content_ref = if let Some(range) = node.page_range {
-                let content = slice_pages(pages, range);
-                if !content.is_empty() {
-                    Some(self.content_store.store(&node.id, content))
-                } else {
-                    None
+        let (mut node_entity_map, mut ref_index) =
+            entities::extract_entities(&extraction.children, &self.content_store, &compiled);
+        // Values the LLM found are not asked for again; keep them
+        carry_llm_entities(extraction, config, &mut node_entity_map, &mut ref_index);
+
+        // Deduplicate node_ids in the global reference index
+        entities::dedup_reference_index(&mut ref_index);
+        // Merge variant spellings of one entity into a canonical value with aliases
+        entities::resolve_entities(&mut ref_index);
+        if let Some(pages) = pages {
+            entities::locate_entities(&mut ref_index, pages, &page_spans(pages), &compiled);
+        }
+
+        // Merge regex entities into node metadata under `_entities` key
+        // LLM-provided metadata takes precedence (regex goes under `_entities`)
+        merge_entities_into_nodes(&mut extraction.children, &node_entity_map);
+
+        // Set extraction-level reference_index
+        extraction.reference_index =
+            serde_json::to_value(&ref_index).unwrap_or(serde_json::Value::Null);
+
+        info!(
+            "Entity extraction: {} entity types across {} nodes",
+            ref_index.entities.len(),
+            node_entity_map.len()
+        );
+    }
+
+    /// `entities` stage, LLM part: ask for the config's `llm` entity patterns
+    /// in the content of each leaf node (one call per node covering every
+    /// pattern) and merge the values into `_entities` and `reference_index`,
+    /// marked `source: "llm"`. A failed call skips its node. Returns the
+    /// number of values found.
+    pub async fn extract_llm_entities(
+        &self,
+        extraction: &mut Extraction,
+        config: &ExtractionConfig,
+    ) -> usize {
+        fn leaves(nodes: &[DocumentNode], out: &mut Vec<(String, String)>) {
+            for node in nodes {
+                if node.children.is_empty() {
+                    if let Some(ref content_ref) = node.content_ref {
+                        out.push((node.id.clone(), content_ref.clone()));
+                    }
+                }
+                leaves(&node.children, out);
+            }
+        }
+        fn merge(
+            nodes: &mut [DocumentNode],
+            found: &HashMap<String, HashMap<String, Vec<String>>>,
+        ) {
+            for node in nodes.iter_mut() {
+                if let Some(values) = found.get(&node.id) {
+                    if node.metadata.is_null() {
+                        node.metadata = serde_json::Value::Object(serde_json::Map::new());
+                    }
+                    if let Some(obj) = node.metadata.as_object_mut() {
+                        let entry = obj
+                            .entry("_entities")
+                            .or_insert_with(|| serde_json::json!({}));
+                        if let Some(entities) = entry.as_object_mut() {
+                            for (id, values) in values {
+                                entities.insert(id.clone(), serde_json::json!(values));
+                            }
+                        }
+                    }
                 }
-            } else {
-                None
+                merge(&mut node.children, found);
+            }
+        }
+
+        let patterns: Vec<&EntityPattern> = config
+            .entity_patterns
+            .iter()
+            .filter(|p| !p.kind.is_regex())
+            .collect();
+        if patterns.is_empty() {
+            return 0;
+        }
+        let mut nodes = Vec::new();
+        leaves(&extraction.children, &mut nodes);
+
+        let mut found: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+        for (node_id, content_ref) in nodes {
+            let Some(content) = self.content_store.get_full(&content_ref) else {
+                continue;
             };
+            if content.trim().is_empty() {
+                continue;
+            }
+            match self.llm_entities(&patterns, &content).await {
+                Ok(values) if !values.is_empty() => {
+                    found.insert(node_id, values);
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "LLM entity extraction failed for {}/{}: {:#}",
+                    extraction.id, node_id, e
+                ),
+            }
+        }
+        if found.is_empty() {
+            return 0;
+        }
 
-            // Recursively process children
-            let children = self.process_children(node.children, pages, ocr_confidence)?;
-
-            result.push(DocumentNode {
-                id: node.id,
-                node_type: node.node_type,
-                subtype: node.subtype,
-                label: node.label,
-                page_range: node.page_range,
-                date: node.date,
-                author: node.author,
-                summary: node.summary,
-                references: node
-                    .references
-                    .into_iter()
-                    .map(|r| EmbeddedReference {
-                        node: r.node,
-                        ref_type: r.ref_type,
-                        citation: r.citation,
-                    })
-                    .collect(),
-                referenced_by: Vec::new(),
-                content_ref,
-                confidence: Some(ConfidenceScores {
-                    ocr: Some(ocr_confidence),
-                    extraction: Some(0.8),
-                    summary: Some(0.85),
-                    low_confidence_regions: Vec::new(),
-                }),
-                metadata: node.metadata.unwrap_or(serde_json::Value::Null),
-                children,
+        let mut index: ReferenceIndex = serde_json::from_value(extraction.reference_index.clone())
+            .unwrap_or_else(|_| ReferenceIndex {
+                entities: HashMap::new(),
             });
+        let mut count = 0;
+        for (node_id, values) in &found {
+            entities::add_llm_values(&mut index, node_id, values);
+            count += values.values().map(Vec::len).sum::<usize>();
+        }
+        entities::dedup_reference_index(&mut index);
+        entities::resolve_entities(&mut index);
+        merge(&mut extraction.children, &found);
+        extraction.reference_index =
+            serde_json::to_value(&index).unwrap_or(serde_json::Value::Null);
+        info!(
+            "LLM entity extraction: {} values across {} nodes of {}",
+            count,
+            found.len(),
+            extraction.id
+        );
+        count
+    }
+
+    /// Ask the LLM for the values of `patterns` in one node's content:
+    /// pattern ID → values, normalized and (where configured) deduplicated.
+    async fn llm_entities(
+        &self,
+        patterns: &[&EntityPattern],
+        content: &str,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        #[derive(serde::Deserialize)]
+        struct Found {
+            #[serde(default)]
+            entities: HashMap<String, Vec<String>>,
+        }
+
+        let types: Vec<String> = patterns
+            .iter()
+            .map(|p| match p.description {
+                Some(ref description) => format!("- {} ({}): {}", p.id, p.label, description),
+                None => format!("- {} ({})", p.id, p.label),
+            })
+            .collect();
+        let text: String = content.chars().take(LLM_ENTITY_CONTENT_CHARS).collect();
+        let messages = vec![
+            Message::system(format!(
+                "You find entities in a section of a document. The entity types, by ID:\n{}\n\nList every value of each type that occurs in the text, copied as written. Return ONLY valid JSON: {{\"entities\": {{\"<type ID>\": [\"...\"]}}}}, with an empty list for types that do not occur.",
+                types.join("\n")
+            )),
+            Message::user(text),
+        ];
+        let response = self.client.chat(messages).await?;
+        let parsed: Found =
+            parse_llm_json(&response).map_err(|e| LlmParseError::new("entities", e, &response))?;
+
+        let mut results = HashMap::new();
+        for pattern in patterns {
+            let Some(raw) = parsed.entities.get(&pattern.id) else {
+                continue;
+            };
+            let mut values: Vec<String> = raw
+                .iter()
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| entities::normalize_value(v, pattern.normalize.as_deref()))
+                .collect();
+            if pattern.deduplicate {
+                let mut seen = std::collections::HashSet::new();
+                values.retain(|v| seen.insert(v.clone()));
+            }
+            if !values.is_empty() {
+                results.insert(pattern.id.clone(), values);
+            }
+        }
+        Ok(results)
+    }
+
+    /// `readable_id` stage: config regex over the OCR text, the LLM's answer, then a slug.
+    pub fn assign_readable_id(
+        &self,
+        extraction: &mut Extraction,
+        ocr: &OcrResult,
+        config: &ExtractionConfig,
+    ) {
+        let resolved = readable_id::resolve(
+            config,
+            &readable_id::ReadableIdSources {
+                ocr_text: &ocr.markdown,
+                llm_value: extraction.readable_id.as_deref(),
+                metadata: &extraction.metadata,
+                filename: &extraction.source_file,
+                content_hash: extraction.content_hash.as_deref().unwrap_or_default(),
+            },
+        );
+        debug!("Readable ID for {}: {}", extraction.source_file, resolved);
+        extraction.readable_id = Some(resolved);
+    }
+
+    /// `translate` stage: translate the document and node summaries into
+    /// `translate_to`, stored under `metadata._translations.{lang}.summary`.
+    /// Returns the number of summaries translated.
+    pub async fn translate(
+        &self,
+        extraction: &mut Extraction,
+        config: &ExtractionConfig,
+    ) -> Result<usize> {
+        fn collect(nodes: &[DocumentNode], out: &mut serde_json::Map<String, serde_json::Value>) {
+            for node in nodes {
+                if !node.summary.is_empty() {
+                    out.insert(node.id.clone(), node.summary.clone().into());
+                }
+                collect(&node.children, out);
+            }
+        }
+        fn apply(nodes: &mut [DocumentNode], target: &str, translated: &HashMap<String, String>) {
+            for node in nodes {
+                if let Some(summary) = translated.get(&node.id) {
+                    insert_translation(&mut node.metadata, target, summary);
+                }
+                apply(&mut node.children, target, translated);
+            }
         }
 
-        Ok(result)
+        let target = config.translate_to.as_deref().unwrap_or(DEFAULT_TRANSLATION_TARGET);
+        let source = extraction.language.as_deref().or(config.language.as_deref());
+        if source.is_some_and(|s| s.eq_ignore_ascii_case(target)) {
+            debug!("{} is already in {}, skipping translation", extraction.id, target);
+            return Ok(0);
+        }
+
+        let mut summaries = serde_json::Map::new();
+        if !extraction.summary.is_empty() {
+            summaries.insert(DOCUMENT_SUMMARY_KEY.to_string(), extraction.summary.clone().into());
+        }
+        collect(&extraction.children, &mut summaries);
+        if summaries.is_empty() {
+            return Ok(0);
+        }
+
+        let messages = vec![
+            Message::system(format!(
+                "You translate document summaries into {}. Keep names, numbers, codes, dates and legal citations unchanged.",
+                language::name(target)
+            )),
+            Message::user(format!(
+                "Translate each value of this JSON object. Return ONLY valid JSON with the same keys: {{\"summaries\": {{\"<key>\": \"<translation>\"}}}}\n\n{}",
+                serde_json::Value::Object(summaries)
+            )),
+        ];
+        let response = self.client.chat(messages).await?;
+        let parsed: TranslatedSummaries = parse_llm_json(&response)
+            .map_err(|e| LlmParseError::new("translation", e, &response))?;
+        let mut translated = parsed.summaries;
+
+        if let Some(summary) = translated.remove(DOCUMENT_SUMMARY_KEY) {
+            insert_translation(&mut extraction.metadata, target, &summary);
+        }
+        apply(&mut extraction.children, target, &translated);
+        info!(
+            "Translated {} summaries of {} into {}",
+            translated.len(),
+            extraction.id,
+            target
+        );
+        Ok(translated.len())
+    }
+
+    /// `redact` stage: store a PII-redacted copy of each node's content as
+    /// `content://{node_id}.redacted`. Returns the number of replacements.
+    pub async fn redact(
+        &self,
+        extraction: &Extraction,
+        ocr: &OcrResult,
+        config: &ExtractionConfig,
+    ) -> usize {
+        fn walk(
+            store: &ContentStore,
+            redactor: &Redactor,
+            nodes: &[DocumentNode],
+            total: &mut usize,
+        ) {
+            for node in nodes {
+                if let Some(content) = node.content_ref.as_deref().and_then(|r| store.get_full(r)) {
+                    let (redacted, count) = redactor.redact(&content);
+                    store.store(&format!("{}{}", node.id, redaction::REDACTED_SUFFIX), redacted);
+                    *total += count;
+                }
+                walk(store, redactor, &node.children, total);
+            }
+        }
+
+        let redaction_config = config.redaction.clone().unwrap_or_default();
+        let mut names = if redaction_config.names {
+            redaction::known_names(extraction)
+        } else {
+            Vec::new()
+        };
+        if redaction_config.llm_names {
+            match self.person_names(ocr).await {
+                Ok(found) => names.extend(found),
+                Err(e) => warn!("LLM name detection failed for {}: {}", extraction.id, e),
+            }
+        }
+
+        let redactor = Redactor::new(&redaction_config, &config.entity_patterns, &names);
+        let mut total = 0;
+        walk(&self.content_store, &redactor, &extraction.children, &mut total);
+        info!("Redaction: {} values redacted in {}", total, extraction.id);
+        total
     }
+
+    /// Refresh the redacted copies of `node_ids` after their content changed.
+    /// Names are not asked of the LLM again; known names and detectors apply.
+    pub fn redact_nodes(
+        &self,
+        extraction: &Extraction,
+        node_ids: &[String],
+        config: &ExtractionConfig,
+    ) {
+        fn walk(
+            store: &ContentStore,
+            redactor: &Redactor,
+            nodes: &[DocumentNode],
+            ids: &[String],
+        ) {
+            for node in nodes {
+                let content = node
+                    .content_ref
+                    .as_deref()
+                    .filter(|_| ids.contains(&node.id))
+                    .and_then(|r| store.get_full(r));
+                if let Some(content) = content {
+                    let (redacted, _) = redactor.redact(&content);
+                    store.store(&format!("{}{}", node.id, redaction::REDACTED_SUFFIX), redacted);
+                }
+                walk(store, redactor, &node.children, ids);
+            }
+        }
+
+        let redaction_config = config.redaction.clone().unwrap_or_default();
+        let names = if redaction_config.names {
+            redaction::known_names(extraction)
+        } else {
+            Vec::new()
+        };
+        let redactor = Redactor::new(&redaction_config, &config.entity_patterns, &names);
+        walk(&self.content_store, &redactor, &extraction.children, node_ids);
+    }
+
+    /// Ask the LLM for the full names of the people mentioned in the document.
+    async fn person_names(&self, ocr: &OcrResult) -> Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Names {
+            #[serde(default)]
+            names: Vec<String>,
+        }
+
+        let messages = vec![
+            Message::cached_system(format!(
+                "--- DOCUMENT START ---\n\n{}\n\n--- DOCUMENT END ---",
+                self.document_context(ocr, tokens::document_budget(self.client.model()))
+            )),
+            Message::user(
+                r#"List the full names of every natural person mentioned in the document above (parties, lawyers, witnesses, judges). Return ONLY valid JSON: {"names": ["..."]}"#,
+            ),
+        ];
+        let response = self.client.chat(messages).await?;
+        let parsed: Names =
+            parse_llm_json(&response).map_err(|e| LlmParseError::new("names", e, &response))?;
+        Ok(parsed.names)
+    }
+}
+
+/// Characters of a node's content sent with the LLM entity call.
+const LLM_ENTITY_CONTENT_CHARS: usize = 30_000;
+
+/// OCR tokens sent with the parties follow-up; they are named at the start.
+const PARTES_CONTEXT_TOKENS: usize = 10_000;
+
+/// Key of the document-level summary in the translation request.
+const DOCUMENT_SUMMARY_KEY: &str = "_document";
+
+/// Target of the `translate` stage when the config sets no `translate_to`.
+const DEFAULT_TRANSLATION_TARGET: &str = "en";
+
+/// Store a translated summary under `metadata._translations.{lang}.summary`.
+fn insert_translation(metadata: &mut serde_json::Value, lang: &str, summary: &str) {
+    if metadata.is_null() {
+        *metadata = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let Some(obj) = metadata.as_object_mut() {
+        let translations = obj
+            .entry("_translations")
+            .or_insert_with(|| serde_json::json!({}));
+        if let Some(translations) = translations.as_object_mut() {
+            translations.insert(lang.to_string(), serde_json::json!({ "summary": summary }));
+        }
+    }
+}
+
+/// `"type"` alternatives for the structure prompt: the config's node types.
+fn type_alternatives(config: &ExtractionConfig) -> String {
+    if config.node_types.is_empty() {
+        return "DOCUMENT|SECTION|GROUP".to_string();
+    }
+    let ids: Vec<&str> = config.node_types.iter().map(|t| t.id.as_str()).collect();
+    ids.join("|")
+}
+
+fn relationship_alternatives(config: &ExtractionConfig) -> String {
+    if config.relationship_types.is_empty() {
+        return "references".to_string();
+    }
+    config.relationship_types.join("|")
+}
+
+/// One line per node type with its subtypes and type-specific metadata fields.
+fn node_type_guide(config: &ExtractionConfig) -> String {
+    if config.node_types.is_empty() {
+        return String::new();
+    }
+    let mut guide = String::from("\n\nNode types (use the id in \"type\"):");
+    for node_type in &config.node_types {
+        guide.push_str(&format!("\n- {} ({})", node_type.id, node_type.label));
+        if !node_type.subtypes.is_empty() {
+            guide.push_str(&format!("; subtypes: {}", node_type.subtypes.join(", ")));
+        }
+        if node_type
+            .metadata_schema
+            .as_object()
+            .is_some_and(|s| !s.is_empty())
+        {
+            guide.push_str(&format!("; metadata: {}", node_type.metadata_schema));
+        }
+    }
+    guide
+}
+
+/// Replace node types the LLM wrote as a label or in another case with the
+/// declared id. Undeclared types are kept (and lower the node's confidence).
+fn canonicalize_node_types(nodes: &mut [DocumentNode], config: &ExtractionConfig) {
+    for node in nodes {
+        match config.node_type(&node.node_type) {
+            Some(declared) => node.node_type = declared.id.clone(),
+            None if !config.node_types.is_empty() => {
+                debug!("Node {} has undeclared type {}", node.id, node.node_type)
+            }
+            None => {}
+        }
+        canonicalize_node_types(&mut node.children, config);
+    }
+}
+
+/// Convert LLM nodes into document nodes (content is attached by `slice_content`,
+/// confidence by `confidence::score`).
+fn convert_nodes(nodes: Vec<ExtractedNode>) -> Vec<DocumentNode> {
+    nodes
+        .into_iter()
+        .map(|node| DocumentNode {
+            children: convert_nodes(node.children),
+            id: node.id,
+            node_type: node.node_type,
+            subtype: node.subtype,
+            label: node.label,
+            page_range: node.page_range,
+            ocr_span: None,
+            pdf_page_range: None,
+            date: node.date,
+            author: node.author,
+            summary: node.summary,
+            references: node
+                .references
+                .into_iter()
+                .map(|r| EmbeddedReference {
+                    node: r.node,
+                    ref_type: r.ref_type,
+                    citation: r.citation,
+                })
+                .collect(),
+            referenced_by: Vec::new(),
+            content_ref: None,
+            confidence: None,
+            metadata: node.metadata.unwrap_or(serde_json::Value::Null),
+            reviewed: false,
+            child_extraction_id: None,
+        })
+        .collect()
 }
 
 // ============================================================================
 // Helper types for LLM response parsing
 // ============================================================================
 
+#[derive(Debug, serde::Deserialize)]
+struct TranslatedSummaries {
+    #[serde(default)]
+    summaries: HashMap<String, String>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ExtractedStructure {
     summary: String,
     #[serde(default)]
-    structure_map: Vec<StructureMapEntry>,
-    #[serde(default)]
     relationships: Vec<ExtractedRelationship>,
     #[serde(default)]
     metadata: Option<serde_json::Value>,
@@ -304,7 +925,7 @@ struct ExtractedRelationship {
 // ============================================================================
 
 /// Slice pages from OCR output for a given page range.
-fn slice_pages(pages: &[OcrPage], range: [u32; 2]) -> String {
+pub fn slice_pages(pages: &[OcrPage], range: [u32; 2]) -> String {
     pages
         .iter()
         .filter(|p| p.page_num >= range[0] && p.page_num <= range[1])
@@ -313,20 +934,172 @@ fn slice_pages(pages: &[OcrPage], range: [u32; 2]) -> String {
         .join("\n\n")
 }
 
-fn truncate_for_context(text: &str, max_chars: usize) -> &str {
-    if text.len() <= max_chars {
-        text
-    } else {
-        let mut end = max_chars;
-        while !text.is_char_boundary(end) && end > 0 {
-            end -= 1;
+/// Character offsets `[start, end)` of each page in the document's OCR text:
+/// the page texts in page order, joined by a blank line (`"\n\n"`).
+pub fn page_spans(pages: &[OcrPage]) -> BTreeMap<u32, [usize; 2]> {
+    let mut ordered: Vec<&OcrPage> = pages.iter().collect();
+    ordered.sort_by_key(|p| p.page_num);
+    let mut spans = BTreeMap::new();
+    let mut offset = 0;
+    for page in ordered {
+        let len = page.text.chars().count();
+        spans.insert(page.page_num, [offset, offset + len]);
+        offset += len + 2;
+    }
+    spans
+}
+
+/// Span of a page range: from the start of its first page to the end of its
+/// last one that has OCR output.
+pub fn range_span(spans: &BTreeMap<u32, [usize; 2]>, range: [u32; 2]) -> Option<[usize; 2]> {
+    if range[0] > range[1] {
+        return None;
+    }
+    let mut in_range = spans.range(range[0]..=range[1]).map(|(_, span)| *span);
+    let first = in_range.next()?;
+    let last = in_range.next_back().unwrap_or(first);
+    Some([first[0], last[1]])
+}
+
+/// Set every node's `ocr_span` from its page range.
+pub fn assign_ocr_spans(nodes: &mut [DocumentNode], spans: &BTreeMap<u32, [usize; 2]>) {
+    for node in nodes {
+        node.ocr_span = node.page_range.and_then(|range| range_span(spans, range));
+        assign_ocr_spans(&mut node.children, spans);
+    }
+}
+
+/// PDF page of each page whose provider reported one.
+pub fn pdf_pages(pages: &[OcrPage]) -> BTreeMap<u32, u32> {
+    pages
+        .iter()
+        .filter_map(|p| Some((p.page_num, p.pdf_page?)))
+        .collect()
+}
+
+/// `range` in PDF pages, or `None` when the PDF pages of its ends are
+/// unknown or the same as the range.
+pub fn pdf_page_range(pdf_pages: &BTreeMap<u32, u32>, range: [u32; 2]) -> Option<[u32; 2]> {
+    let pdf_range = [*pdf_pages.get(&range[0])?, *pdf_pages.get(&range[1])?];
+    (pdf_range != range).then_some(pdf_range)
+}
+
+/// Set every node's `pdf_page_range` from its `page_range`.
+pub fn assign_pdf_page_ranges(nodes: &mut [DocumentNode], pdf_pages: &BTreeMap<u32, u32>) {
+    for node in nodes {
+        node.pdf_page_range = node
+            .page_range
+            .and_then(|range| pdf_page_range(pdf_pages, range));
+        assign_pdf_page_ranges(&mut node.children, pdf_pages);
+    }
+}
+
+/// Recover the OCR pages of a content slice made by [`slice_pages`].
+pub fn pages_from_content(content: &str) -> Vec<OcrPage> {
+    let Some(rest) = content.strip_prefix("--- Page ") else {
+        return Vec::new();
+    };
+    rest.split("\n\n--- Page ")
+        .filter_map(|part| {
+            let (num, text) = part.split_once(" ---\n")?;
+            Some(OcrPage {
+                page_num: num.parse().ok()?,
+                text: text.to_string(),
+                confidence: None,
+                pdf_page: None,
+            })
+        })
+        .collect()
+}
+
+/// Byte offsets where each page ends in the OCR markdown, for pages laid
+/// out as [`page_spans`] expects. Pages found elsewhere are left out.
+fn page_ends(ocr: &OcrResult) -> Vec<usize> {
+    let mut ordered: Vec<&OcrPage> = ocr.pages.iter().collect();
+    ordered.sort_by_key(|p| p.page_num);
+    let mut ends = Vec::new();
+    let mut start = 0;
+    for page in ordered {
+        let end = start + page.text.len();
+        if ocr.markdown.get(start..end) == Some(page.text.as_str()) {
+            ends.push(end);
         }
-        &text[..end]
+        start = end + 2;
     }
+    ends
 }
 
 /// Recursively merge extracted entities into node metadata under `_entities` key.
 /// LLM-provided metadata fields are preserved; regex entities are added alongside them.
+/// Put the values of `llm` entity patterns from the current `_entities` and
+/// `reference_index` into freshly computed regex results, dropping the node
+/// IDs that are no longer in the tree.
+fn carry_llm_entities(
+    extraction: &Extraction,
+    config: &ExtractionConfig,
+    node_entity_map: &mut HashMap<String, serde_json::Value>,
+    ref_index: &mut ReferenceIndex,
+) {
+    fn walk(
+        nodes: &[DocumentNode],
+        llm_ids: &[&str],
+        node_entity_map: &mut HashMap<String, serde_json::Value>,
+        node_ids: &mut std::collections::HashSet<String>,
+    ) {
+        for node in nodes {
+            node_ids.insert(node.id.clone());
+            if let Some(previous) = node.metadata.get("_entities").and_then(|e| e.as_object()) {
+                for id in llm_ids {
+                    if let Some(values) = previous.get(*id) {
+                        let entry = node_entity_map
+                            .entry(node.id.clone())
+                            .or_insert_with(|| serde_json::json!({}));
+                        if let Some(obj) = entry.as_object_mut() {
+                            obj.insert(id.to_string(), values.clone());
+                        }
+                    }
+                }
+            }
+            walk(&node.children, llm_ids, node_entity_map, node_ids);
+        }
+    }
+
+    let llm_ids: Vec<&str> = config
+        .entity_patterns
+        .iter()
+        .filter(|p| !p.kind.is_regex())
+        .map(|p| p.id.as_str())
+        .collect();
+    if llm_ids.is_empty() {
+        return;
+    }
+    let mut node_ids = std::collections::HashSet::new();
+    walk(
+        &extraction.children,
+        &llm_ids,
+        node_entity_map,
+        &mut node_ids,
+    );
+
+    let Ok(mut previous) =
+        serde_json::from_value::<ReferenceIndex>(extraction.reference_index.clone())
+    else {
+        return;
+    };
+    for id in llm_ids {
+        let Some(mut occurrences) = previous.entities.remove(id) else {
+            continue;
+        };
+        for occ in occurrences.iter_mut() {
+            occ.node_ids.retain(|n| node_ids.contains(n));
+        }
+        occurrences.retain(|occ| !occ.node_ids.is_empty());
+        if !occurrences.is_empty() {
+            ref_index.entities.insert(id.to_string(), occurrences);
+        }
+    }
+}
+
 fn merge_entities_into_nodes(
     nodes: &mut [DocumentNode],
     entity_map: &std::collections::HashMap<String, serde_json::Value>,
@@ -349,6 +1122,17 @@ fn merge_entities_into_nodes(
 }
 
 fn parse_llm_json<T: serde::de::DeserializeOwned>(response: &str) -> Result<T> {
+    parse_llm_json_repaired(response).map(|(parsed, _)| parsed)
+}
+
+/// Repairs tried in order when the LLM response is not valid JSON.
+const JSON_REPAIRS: &[fn(&str) -> String] =
+    &[outermost_object, strip_trailing_commas, close_truncated];
+
+/// Parse an LLM JSON response, repairing common defects (surrounding prose,
+/// trailing commas, output cut off mid-object). Also returns how many repairs
+/// were applied, which lowers node confidence.
+fn parse_llm_json_repaired<T: serde::de::DeserializeOwned>(response: &str) -> Result<(T, u32)> {
     // Try to extract JSON from markdown code blocks if present
     let json_str = if response.contains("```json") {
         response
@@ -363,15 +1147,193 @@ fn parse_llm_json<T: serde::de::DeserializeOwned>(response: &str) -> Result<T> {
         response.trim()
     };
 
-    // First validate syntax
-    let _: serde_json::Value = serde_json::from_str(json_str).context(format!(
-        "Invalid JSON syntax: {}",
-        &json_str.chars().take(200).collect::<String>()
-    ))?;
+    // First validate syntax, repairing if needed
+    let mut json = json_str.to_string();
+    let mut repairs = 0;
+    let mut repair_steps = JSON_REPAIRS.iter();
+    while let Err(e) = serde_json::from_str::<serde_json::Value>(&json) {
+        let Some(repair) = repair_steps.next() else {
+            return Err(anyhow::Error::new(e).context(format!(
+                "Invalid JSON syntax: {}",
+                &json_str.chars().take(200).collect::<String>()
+            )));
+        };
+        let repaired = repair(&json);
+        if repaired != json {
+            json = repaired;
+            repairs += 1;
+        }
+    }
 
     // Parse as expected type
-    serde_json::from_str(json_str).context(format!(
+    let parsed = serde_json::from_str(&json).context(format!(
         "JSON structure mismatch: {}",
-        &json_str.chars().take(200).collect::<String>()
-    ))
+        &json.chars().take(200).collect::<String>()
+    ))?;
+    Ok((parsed, repairs))
+}
+
+/// Drop prose before the first `{` and after the last `}`.
+fn outermost_object(json: &str) -> String {
+    match (json.find('{'), json.rfind('}')) {
+        (Some(start), Some(end)) if start < end => json[start..=end].to_string(),
+        (Some(start), _) => json[start..].to_string(),
+        _ => json.to_string(),
+    }
+}
+
+/// Remove commas directly before a closing `}` or `]` (outside strings).
+fn strip_trailing_commas(json: &str) -> String {
+    let chars: Vec<char> = json.chars().collect();
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Close an unterminated string and any open objects/arrays (output cut off
+/// at the token limit), dropping a dangling comma or key first.
+fn close_truncated(json: &str) -> String {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    if stack.is_empty() && !in_string {
+        return json.to_string();
+    }
+
+    let mut out = json.to_string();
+    if in_string {
+        out.push('"');
+    }
+    let trimmed = out.trim_end().trim_end_matches([',', ':']).trim_end().len();
+    out.truncate(trimmed);
+    out.extend(stack.iter().rev());
+    strip_trailing_commas(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_llm_json_repairs() {
+        let (value, repairs): (serde_json::Value, u32) =
+            parse_llm_json_repaired("```json\n{\"summary\": \"ok\"}\n```").unwrap();
+        assert_eq!((value["summary"].as_str(), repairs), (Some("ok"), 0));
+
+        // Prose around the object, trailing comma, cut off mid-string
+        let truncated = "Here is the structure:\n{\"summary\": \"Petição, inicial\", \
+                         \"children\": [{\"id\": \"n1\", \"label\": \"Procura";
+        let (value, repairs): (serde_json::Value, u32) =
+            parse_llm_json_repaired(truncated).unwrap();
+        assert_eq!(value["children"][0]["label"], "Procura");
+        assert_eq!(value["summary"], "Petição, inicial");
+        assert_eq!(repairs, 2);
+
+        let (_, repairs): (serde_json::Value, u32) =
+            parse_llm_json_repaired("{\"a\": [1, 2,], }").unwrap();
+        assert_eq!(repairs, 1);
+        assert!(parse_llm_json::<serde_json::Value>("no json here").is_err());
+    }
+
+    #[test]
+    fn test_pages_from_content_inverts_slice() {
+        let pages: Vec<OcrPage> = (1..=3)
+            .map(|n| OcrPage {
+                page_num: n,
+                text: format!("text of page {}\n\nsecond paragraph", n),
+                confidence: None,
+                pdf_page: None,
+            })
+            .collect();
+        let recovered = pages_from_content(&slice_pages(&pages, [2, 3]));
+        assert_eq!(recovered.len(), 2);
+        assert_eq!(recovered[0].page_num, 2);
+        assert_eq!(recovered[1].text, pages[2].text);
+        assert!(pages_from_content("no page markers").is_empty());
+    }
+
+    #[test]
+    fn test_page_spans() {
+        let pages: Vec<OcrPage> = ["ação", "", "fim"]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| OcrPage {
+                page_num: i as u32 + 1,
+                text: text.to_string(),
+                confidence: None,
+                pdf_page: None,
+            })
+            .collect();
+        let spans = page_spans(&pages);
+        let text = pages
+            .iter()
+            .map(|p| p.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let [start, end] = spans[&3];
+        let chars: String = text.chars().skip(start).take(end - start).collect();
+        assert_eq!(chars, "fim");
+
+        assert_eq!(range_span(&spans, [1, 3]), Some([0, 11]));
+        assert_eq!(range_span(&spans, [3, 9]), Some([8, 11]));
+        assert_eq!(range_span(&spans, [4, 9]), None);
+    }
+
+    #[test]
+    fn test_pdf_page_range() {
+        // Pages 5-7 of the PDF, OCR'd on their own
+        let pages: Vec<OcrPage> = (1..=3)
+            .map(|n| OcrPage {
+                page_num: n,
+                text: String::new(),
+                confidence: None,
+                pdf_page: Some(n + 4),
+            })
+            .collect();
+        let pdf_pages = pdf_pages(&pages);
+        assert_eq!(pdf_page_range(&pdf_pages, [2, 3]), Some([6, 7]));
+        assert_eq!(pdf_page_range(&pdf_pages, [2, 4]), None);
+
+        let same: BTreeMap<u32, u32> = [(1, 1), (2, 2)].into();
+        assert_eq!(pdf_page_range(&same, [1, 2]), None);
+    }
 }
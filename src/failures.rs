@@ -0,0 +1,179 @@
+//! Diagnostics kept for failed extractions.
+//!
+//! An extraction's `error` is a single line. When a pipeline stage fails, a
+//! [`FailureBundle`] with the stage, the OCR provider, what OCR produced, the
+//! stage timings and, when the LLM answered with something unreadable, the
+//! start of that answer is written to `{FAILURES_DIR}/{id}/bundle.json`
+//! (default `data/failures`). A later successful run of the same extraction
+//! removes it.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobRecord;
+use crate::ocr::{self, OcrResult};
+use crate::safe_name;
+use crate::schema::ExtractionTiming;
+
+/// What was known about an extraction when it failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureBundle {
+    pub extraction_id: String,
+    pub failed_at: String,
+    /// Pipeline stage that failed (`ocr`, `structure`, ...)
+    pub stage: String,
+    pub error: String,
+    pub filename: String,
+    pub config: String,
+    pub config_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Absent when OCR had not finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<OcrStats>,
+    /// Start of the LLM answer that could not be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_response: Option<String>,
+    pub timing: ExtractionTiming,
+    /// How the job was started, for `POST /extractions/:id/retry`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrStats {
+    pub total_pages: u32,
+    pub pages_with_text: usize,
+    /// Pages whose text is blank, often scans the provider could not read
+    pub empty_pages: Vec<u32>,
+    pub markdown_chars: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_confidence: Option<f64>,
+}
+
+impl OcrStats {
+    pub fn of(ocr: &OcrResult) -> Self {
+        let empty_pages: Vec<u32> = ocr
+            .pages
+            .iter()
+            .filter(|page| page.text.trim().is_empty())
+            .map(|page| page.page_num)
+            .collect();
+        Self {
+            total_pages: ocr.total_pages,
+            pages_with_text: ocr.pages.len() - empty_pages.len(),
+            empty_pages,
+            markdown_chars: ocr.markdown.chars().count(),
+            mean_confidence: ocr::mean_page_confidence(&ocr.pages),
+        }
+    }
+}
+
+/// Failure bundles on disk, one directory per extraction.
+pub struct FailureStore {
+    dir: PathBuf,
+}
+
+impl FailureStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Open the store in `FAILURES_DIR` (default `data/failures`).
+    pub fn from_env() -> Result<Self> {
+        Self::open(std::env::var("FAILURES_DIR").unwrap_or_else(|_| "data/failures".to_string()))
+    }
+
+    /// Save a bundle, replacing an earlier one for the same extraction.
+    pub fn save(&self, bundle: &FailureBundle) -> Result<()> {
+        let dir = self.extraction_dir(&bundle.extraction_id);
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("bundle.json");
+        std::fs::write(&path, serde_json::to_vec_pretty(bundle)?)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<FailureBundle>> {
+        let path = self.extraction_dir(id).join("bundle.json");
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Drop the bundle for an extraction, if there is one.
+    pub fn remove(&self, id: &str) -> Result<()> {
+        match std::fs::remove_dir_all(self.extraction_dir(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn extraction_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(safe_name::file_name(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::OcrPage;
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let dir = std::env::temp_dir().join(format!("failures-test-{}", uuid::Uuid::new_v4()));
+        let store = FailureStore::open(&dir).unwrap();
+        let ocr = OcrResult {
+            markdown: "Petição inicial".to_string(),
+            pages: vec![
+                OcrPage {
+                    page_num: 1,
+                    text: "Petição inicial".to_string(),
+                    confidence: None,
+                    pdf_page: None,
+                },
+                OcrPage {
+                    page_num: 2,
+                    text: "  \n".to_string(),
+                    confidence: None,
+                    pdf_page: None,
+                },
+            ],
+            total_pages: 2,
+            metadata: serde_json::Value::Null,
+            ocr_confidence: 1.0,
+            provider_name: "docling".to_string(),
+        };
+        let bundle = FailureBundle {
+            extraction_id: "ext/1".to_string(),
+            failed_at: "2026-01-01T00:00:00Z".to_string(),
+            stage: "structure".to_string(),
+            error: "Extraction failed: Failed to parse LLM structure response".to_string(),
+            filename: "autos.pdf".to_string(),
+            config: "legal_br".to_string(),
+            config_version: "abc".to_string(),
+            provider: Some("docling".to_string()),
+            ocr: Some(OcrStats::of(&ocr)),
+            llm_response: Some("{\"nodes\": [".to_string()),
+            timing: ExtractionTiming::default(),
+            job: None,
+        };
+        store.save(&bundle).unwrap();
+
+        let loaded = store.get("ext/1").unwrap().unwrap();
+        let stats = loaded.ocr.unwrap();
+        assert_eq!(stats.empty_pages, vec![2]);
+        assert_eq!(stats.pages_with_text, 1);
+        assert_eq!(loaded.llm_response.as_deref(), Some("{\"nodes\": ["));
+
+        store.remove("ext/1").unwrap();
+        assert!(store.get("ext/1").unwrap().is_none());
+        store.remove("ext/1").unwrap();
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,255 @@
+//! Printed page numbers (folios).
+//!
+//! Court records are numbered sheet by sheet, usually stamped "fls. 123" in
+//! a corner, and a scanned record rarely starts at folio 1. The opt-in
+//! `folios` pipeline stage reads those numbers from the top and bottom lines
+//! of each OCR page and remaps node page ranges to them: `page_range` becomes
+//! the printed (logical) range and `pdf_page_range` the physical one.
+//!
+//! A number only counts when it agrees with the others: the folios kept must
+//! increase with the page. Pages without a readable number take one from
+//! the nearest numbered page, counting one folio per page. Nodes are left as
+//! they are when fewer than [`MIN_ANCHORS`] pages carry a number.
+//!
+//! Content is sliced by OCR page before this stage runs, so the stage must
+//! come after `slice_content`.
+
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde_json::json;
+
+use crate::extractor;
+use crate::ocr::OcrPage;
+use crate::schema::{DocumentNode, Extraction};
+
+/// Lines at the top and at the bottom of a page searched for its number.
+const EDGE_LINES: usize = 4;
+/// Numbered pages needed before any node is remapped.
+pub const MIN_ANCHORS: usize = 2;
+
+/// Outcome of [`apply`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FolioReport {
+    /// OCR pages whose printed number was read and kept
+    pub anchors: usize,
+    /// Nodes whose `page_range` now holds folios
+    pub nodes: usize,
+}
+
+struct Patterns {
+    /// "fls. 12", "Fl. 12", "folha 12"
+    folio: Regex,
+    /// "Página 3", "pág. 3 de 10", "3/10"
+    page: Regex,
+    /// A line holding nothing but a number, e.g. "- 3 -"
+    bare: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        Self {
+            folio: Regex::new(r"(?i)\b(?:fls?|folhas?)\s*\.?\s*(?:n[º°o.]\s*)?(\d{1,5})\b")
+                .expect("valid folio regex"),
+            page: Regex::new(
+                r"(?i)(?:\bp[áa]g(?:ina)?\.?\s*(\d{1,5})|^\s*(\d{1,5})\s*/\s*\d{1,5}\s*$)",
+            )
+            .expect("valid page regex"),
+            bare: Regex::new(r"^\s*[-–—]?\s*(\d{1,4})\s*[-–—]?\s*$").expect("valid bare regex"),
+        }
+    }
+
+    /// The printed number on a page: a folio stamp first, then a page
+    /// label, then a line that is only a number.
+    fn read(&self, text: &str) -> Option<u32> {
+        let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+        let edge: Vec<&str> = if lines.len() <= 2 * EDGE_LINES {
+            lines
+        } else {
+            lines[..EDGE_LINES]
+                .iter()
+                .chain(&lines[lines.len() - EDGE_LINES..])
+                .copied()
+                .collect()
+        };
+        let capture = |re: &Regex| {
+            edge.iter().find_map(|line| {
+                let caps = re.captures(line)?;
+                caps.iter().skip(1).flatten().next()?.as_str().parse().ok()
+            })
+        };
+        capture(&self.folio)
+            .or_else(|| capture(&self.page))
+            .or_else(|| capture(&self.bare))
+            .filter(|&n| n > 0)
+    }
+}
+
+/// `(page_num, folio)` of the pages whose printed number was read and
+/// agrees with the others.
+fn anchors(pages: &[OcrPage]) -> Vec<(u32, u32)> {
+    let patterns = Patterns::new();
+    let mut read: Vec<(u32, u32)> = pages
+        .iter()
+        .filter_map(|p| Some((p.page_num, patterns.read(&p.text)?)))
+        .collect();
+    read.sort();
+    increasing(&read)
+}
+
+/// Folio of each OCR page (`page_num` → printed number), counted from its
+/// nearest anchor; empty with fewer than [`MIN_ANCHORS`] anchors.
+fn fill(pages: &[OcrPage], anchors: &[(u32, u32)]) -> BTreeMap<u32, u32> {
+    if anchors.len() < MIN_ANCHORS {
+        return BTreeMap::new();
+    }
+    let mut folios = BTreeMap::new();
+    for page in pages.iter().map(|p| p.page_num) {
+        // Nearest anchor, preferring the one before on a tie
+        let nearest = anchors
+            .iter()
+            .min_by_key(|(anchor, _)| (anchor.abs_diff(page), *anchor > page))
+            .expect("at least MIN_ANCHORS anchors");
+        let folio = i64::from(nearest.1) + i64::from(page) - i64::from(nearest.0);
+        if let Ok(folio) = u32::try_from(folio) {
+            if folio > 0 {
+                folios.insert(page, folio);
+            }
+        }
+    }
+    folios
+}
+
+/// The longest chain of `(page, folio)` pairs, sorted by page, whose folios
+/// increase from one pair to the next by no more than the pages do (less
+/// when a sheet's back is not numbered). Page numbers that break the
+/// sequence, such as a "3" in a date line, fall out.
+fn increasing(read: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    // Quadratic, which is plenty for a document's pages
+    let mut best: Vec<usize> = vec![1; read.len()];
+    let mut prev: Vec<Option<usize>> = vec![None; read.len()];
+    for i in 0..read.len() {
+        for j in 0..i {
+            let (page_j, folio_j) = read[j];
+            let (page_i, folio_i) = read[i];
+            let fits = folio_i > folio_j && folio_i - folio_j <= page_i - page_j;
+            if fits && best[j] + 1 > best[i] {
+                best[i] = best[j] + 1;
+                prev[i] = Some(j);
+            }
+        }
+    }
+    let Some(mut at) = (0..read.len()).max_by_key(|&i| (best[i], std::cmp::Reverse(i))) else {
+        return Vec::new();
+    };
+    let mut run = vec![read[at]];
+    while let Some(p) = prev[at] {
+        run.push(read[p]);
+        at = p;
+    }
+    run.reverse();
+    run
+}
+
+/// Remap node page ranges to folios. Returns `None` when the document's
+/// pages carry too few printed numbers.
+pub fn apply(extraction: &mut Extraction, pages: &[OcrPage]) -> Option<FolioReport> {
+    let anchors = anchors(pages);
+    let folios = fill(pages, &anchors);
+    if folios.is_empty() {
+        return None;
+    }
+    let pdf_pages = extractor::pdf_pages(pages);
+
+    fn walk(
+        nodes: &mut [DocumentNode],
+        folios: &BTreeMap<u32, u32>,
+        pdf_pages: &BTreeMap<u32, u32>,
+        remapped: &mut usize,
+    ) {
+        for node in nodes {
+            if let Some([start, end]) = node.page_range {
+                if let (Some(&first), Some(&last)) = (folios.get(&start), folios.get(&end)) {
+                    let pdf = |page: u32| pdf_pages.get(&page).copied().unwrap_or(page);
+                    node.pdf_page_range = Some([pdf(start), pdf(end)]);
+                    node.page_range = Some([first, last]);
+                    *remapped += 1;
+                }
+            }
+            walk(&mut node.children, folios, pdf_pages, remapped);
+        }
+    }
+    let mut remapped = 0;
+    walk(&mut extraction.children, &folios, &pdf_pages, &mut remapped);
+
+    if extraction.metadata.is_null() {
+        extraction.metadata = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let Some(obj) = extraction.metadata.as_object_mut() {
+        let map: serde_json::Map<String, serde_json::Value> = folios
+            .iter()
+            .map(|(page, folio)| (page.to_string(), json!(folio)))
+            .collect();
+        obj.insert("_folios".to_string(), json!({ "pages": map }));
+    }
+    Some(FolioReport {
+        anchors: anchors.len(),
+        nodes: remapped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page_num: u32, text: &str) -> OcrPage {
+        OcrPage {
+            page_num,
+            text: text.to_string(),
+            confidence: None,
+            pdf_page: None,
+        }
+    }
+
+    fn node(id: &str, range: [u32; 2]) -> DocumentNode {
+        serde_json::from_value(json!({
+            "id": id, "type": "section", "label": id, "summary": "", "page_range": range,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_remap_to_folios() {
+        // Folios 41-45 stamped on pages 1-5; page 3's stamp is unreadable and
+        // page 4 has a date that looks like a page number
+        let body = "Texto\nda\npeça\nprocessual\ncom\nvárias\nlinhas\nde\nconteúdo";
+        let pages = vec![
+            page(1, &format!("Fls. 41\n{}", body)),
+            page(2, &format!("{}\nfls. 42", body)),
+            page(3, body),
+            page(4, "São Paulo, 3 de maio\n- 3 -"),
+            page(5, &format!("TJSP fl. 45\n{}", body)),
+        ];
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.children = vec![node("peticao", [1, 2]), node("sentenca", [3, 5])];
+
+        let report = apply(&mut extraction, &pages).unwrap();
+        assert_eq!(
+            report,
+            FolioReport {
+                anchors: 3,
+                nodes: 2
+            }
+        );
+        let sentenca = &extraction.children[1];
+        assert_eq!(sentenca.page_range, Some([43, 45]));
+        assert_eq!(sentenca.pdf_page_range, Some([3, 5]));
+        assert_eq!(extraction.metadata["_folios"]["pages"]["4"], 44);
+
+        // One number is not enough to trust
+        let mut single = Extraction::new("autos.pdf".into(), None);
+        single.children = vec![node("peticao", [1, 2])];
+        assert!(apply(&mut single, &pages[..1]).is_none());
+        assert_eq!(single.children[0].page_range, Some([1, 2]));
+    }
+}
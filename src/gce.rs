@@ -1,40 +1,40 @@
 //! GCE Compute Engine API client for on-demand instance management.
 //!
-//! Enables starting a stopped GCE instance (e.g., Docling GPU sidecar) via
+//! Enables starting stopped GCE instances (e.g., Docling GPU sidecars) via
 //! the Compute Engine REST API using service account JWT authentication.
 //! All env vars are optional — if any are missing, GCE on-demand is disabled.
+//!
+//! `GCE_INSTANCE_NAME` may list several instances (comma-separated), which
+//! form a pool: another instance is started once every running one has
+//! `GCE_SCALE_UP_DEPTH` (default 2) requests in flight. Each instance is
+//! stopped again once it has been idle for `GCE_IDLE_STOP_SECS` (default
+//! 1800, `0` keeps them running); see [`IdleTracker`].
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
 use serde::Deserialize;
-use std::sync::Mutex;
 use tracing::{debug, info, warn};
 
+use crate::gcp_auth::{now_secs, ServiceAccountAuth};
+
 const COMPUTE_SCOPE: &str = "https://www.googleapis.com/auth/compute";
-const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const DEFAULT_IDLE_STOP_SECS: u64 = 1800;
+const DEFAULT_SCALE_UP_DEPTH: usize = 2;
 
 /// Configuration loaded from environment. All four vars must be set.
 #[derive(Clone)]
 pub struct GceConfig {
     pub project_id: String,
     pub zone: String,
-    pub instance_name: String,
-    sa_key: ServiceAccountKey,
-    /// Cached OAuth2 access token.
-    token_cache: std::sync::Arc<Mutex<Option<CachedToken>>>,
-}
-
-#[derive(Clone)]
-struct CachedToken {
-    access_token: String,
-    expires_at: u64,
-}
-
-#[derive(Clone, Deserialize)]
-struct ServiceAccountKey {
-    client_email: String,
-    private_key: String,
-    #[allow(dead_code)]
-    token_uri: Option<String>,
+    /// The pool, in `DOCLING_URL` order
+    pub instances: Vec<String>,
+    /// Stop an instance after this long without OCR requests (`None`: never).
+    pub idle_stop: Option<Duration>,
+    /// In-flight requests per running instance before another is started.
+    pub scale_up_depth: usize,
+    auth: ServiceAccountAuth,
 }
 
 impl GceConfig {
@@ -42,108 +42,63 @@ impl GceConfig {
     pub fn from_env() -> Option<Self> {
         let project_id = std::env::var("GCE_PROJECT_ID").ok()?;
         let zone = std::env::var("GCE_ZONE").ok()?;
-        let instance_name = std::env::var("GCE_INSTANCE_NAME").ok()?;
+        let instances: Vec<String> = std::env::var("GCE_INSTANCE_NAME")
+            .ok()?
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if instances.is_empty() {
+            return None;
+        }
         let key_path = std::env::var("GCE_SA_KEY_PATH").ok()?;
 
-        let key_json = match std::fs::read_to_string(&key_path) {
-            Ok(json) => json,
+        let auth = match ServiceAccountAuth::from_key_path(&key_path, COMPUTE_SCOPE) {
+            Ok(a) => a,
             Err(e) => {
-                warn!("GCE_SA_KEY_PATH={} unreadable: {}", key_path, e);
+                warn!("GCE_SA_KEY_PATH={}: {:#}", key_path, e);
                 return None;
             }
         };
 
-        let sa_key: ServiceAccountKey = match serde_json::from_str(&key_json) {
-            Ok(k) => k,
-            Err(e) => {
-                warn!("Failed to parse GCE service account key: {}", e);
-                return None;
-            }
-        };
+        let idle_stop_secs = std::env::var("GCE_IDLE_STOP_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_IDLE_STOP_SECS);
+        let scale_up_depth = std::env::var("GCE_SCALE_UP_DEPTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&depth: &usize| depth > 0)
+            .unwrap_or(DEFAULT_SCALE_UP_DEPTH);
 
         Some(Self {
             project_id,
             zone,
-            instance_name,
-            sa_key,
-            token_cache: std::sync::Arc::new(Mutex::new(None)),
+            instances,
+            idle_stop: (idle_stop_secs > 0).then(|| Duration::from_secs(idle_stop_secs)),
+            scale_up_depth,
+            auth,
         })
     }
 
     /// Get a valid OAuth2 access token, refreshing if expired.
     pub async fn get_access_token(&self, client: &reqwest::Client) -> Result<String> {
-        // Check cache
-        {
-            let cache = self.token_cache.lock().unwrap();
-            if let Some(ref cached) = *cache {
-                let now = now_secs();
-                if now < cached.expires_at.saturating_sub(60) {
-                    return Ok(cached.access_token.clone());
-                }
-            }
-        }
-
-        // Mint a new JWT
-        let now = now_secs();
-        let claims = serde_json::json!({
-            "iss": self.sa_key.client_email,
-            "scope": COMPUTE_SCOPE,
-            "aud": TOKEN_URI,
-            "iat": now,
-            "exp": now + 3600,
-        });
-
-        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
-        let encoding_key =
-            jsonwebtoken::EncodingKey::from_rsa_pem(self.sa_key.private_key.as_bytes())
-                .context("Invalid RSA private key in service account JSON")?;
-
-        let jwt = jsonwebtoken::encode(&header, &claims, &encoding_key)
-            .context("Failed to encode JWT")?;
-
-        // Exchange JWT for access token
-        #[derive(Deserialize)]
-        struct TokenResponse {
-            access_token: String,
-            expires_in: u64,
-        }
-
-        let resp: TokenResponse = client
-            .post(TOKEN_URI)
-            .form(&[
-                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
-                ("assertion", &jwt),
-            ])
-            .send()
-            .await
-            .context("Token exchange request failed")?
-            .error_for_status()
-            .context("Token exchange returned error")?
-            .json()
-            .await
-            .context("Failed to parse token response")?;
-
-        let token = resp.access_token.clone();
-        {
-            let mut cache = self.token_cache.lock().unwrap();
-            *cache = Some(CachedToken {
-                access_token: resp.access_token,
-                expires_at: now + resp.expires_in,
-            });
-        }
-
-        Ok(token)
+        self.auth.get_access_token(client).await
     }
 
-    fn instance_url(&self) -> String {
+    fn instance_url(&self, instance: &str) -> String {
         format!(
             "https://compute.googleapis.com/compute/v1/projects/{}/zones/{}/instances/{}",
-            self.project_id, self.zone, self.instance_name
+            self.project_id, self.zone, instance
         )
     }
 
     /// Get the instance status (RUNNING, TERMINATED, STAGING, STOPPING, etc.)
-    pub async fn get_instance_status(&self, client: &reqwest::Client) -> Result<String> {
+    pub async fn get_instance_status(
+        &self,
+        client: &reqwest::Client,
+        instance: &str,
+    ) -> Result<String> {
         let token = self.get_access_token(client).await?;
 
         #[derive(Deserialize)]
@@ -152,7 +107,7 @@ impl GceConfig {
         }
 
         let info: InstanceInfo = client
-            .get(&self.instance_url())
+            .get(self.instance_url(instance))
             .bearer_auth(&token)
             .send()
             .await
@@ -163,14 +118,14 @@ impl GceConfig {
             .await
             .context("Failed to parse instance info")?;
 
-        debug!("GCE instance '{}' status: {}", self.instance_name, info.status);
+        debug!("GCE instance '{}' status: {}", instance, info.status);
         Ok(info.status)
     }
 
     /// Start the instance (idempotent — safe to call if already running).
-    pub async fn start_instance(&self, client: &reqwest::Client) -> Result<()> {
+    pub async fn start_instance(&self, client: &reqwest::Client, instance: &str) -> Result<()> {
         let token = self.get_access_token(client).await?;
-        let url = format!("{}/start", self.instance_url());
+        let url = format!("{}/start", self.instance_url(instance));
 
         let resp = client
             .post(&url)
@@ -181,12 +136,12 @@ impl GceConfig {
 
         let status = resp.status();
         if status.is_success() {
-            info!("GCE start request accepted for '{}'", self.instance_name);
+            info!("GCE start request accepted for '{}'", instance);
         } else {
             let body = resp.text().await.unwrap_or_default();
             // 409 = already running, which is fine
             if status.as_u16() == 409 {
-                info!("GCE instance '{}' is already running", self.instance_name);
+                info!("GCE instance '{}' is already running", instance);
             } else {
                 anyhow::bail!("GCE start failed ({}): {}", status, body);
             }
@@ -195,19 +150,41 @@ impl GceConfig {
         Ok(())
     }
 
+    /// Stop the instance (a no-op for an instance that is already stopped).
+    pub async fn stop_instance(&self, client: &reqwest::Client, instance: &str) -> Result<()> {
+        let token = self.get_access_token(client).await?;
+        let url = format!("{}/stop", self.instance_url(instance));
+
+        let resp = client
+            .post(&url)
+            .bearer_auth(&token)
+            .send()
+            .await
+            .context("Failed to send stop request")?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            anyhow::bail!("GCE stop failed ({}): {}", status, body);
+        }
+        info!("GCE stop request accepted for '{}'", instance);
+        Ok(())
+    }
+
     /// Poll until instance reaches RUNNING state. Timeout in seconds.
     pub async fn wait_until_running(
         &self,
         client: &reqwest::Client,
+        instance: &str,
         timeout_secs: u64,
     ) -> Result<()> {
         let deadline = now_secs() + timeout_secs;
 
         loop {
-            let status = self.get_instance_status(client).await?;
+            let status = self.get_instance_status(client, instance).await?;
             match status.as_str() {
                 "RUNNING" => {
-                    info!("GCE instance '{}' is RUNNING", self.instance_name);
+                    info!("GCE instance '{}' is RUNNING", instance);
                     return Ok(());
                 }
                 "STAGING" | "PROVISIONING" => {
@@ -225,7 +202,7 @@ impl GceConfig {
             if now_secs() >= deadline {
                 anyhow::bail!(
                     "Timed out waiting for instance '{}' to reach RUNNING (last status: {})",
-                    self.instance_name,
+                    instance,
                     status
                 );
             }
@@ -235,9 +212,108 @@ impl GceConfig {
     }
 }
 
-fn now_secs() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+/// When the instance was last needed, for stopping it once idle.
+///
+/// The Docling provider holds an [`ActivityGuard`] for each request; the
+/// idle window starts when the last one drops.
+pub struct IdleTracker {
+    state: Mutex<IdleState>,
+}
+
+struct IdleState {
+    in_flight: usize,
+    last_active: Instant,
+    /// Set once the instance was stopped for the current idle period.
+    stopped: bool,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(IdleState {
+                in_flight: 0,
+                last_active: Instant::now(),
+                stopped: false,
+            }),
+        }
+    }
+}
+
+impl IdleTracker {
+    /// Mark a request as running until the guard is dropped.
+    pub fn begin(self: &Arc<Self>) -> ActivityGuard {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight += 1;
+        state.last_active = Instant::now();
+        state.stopped = false;
+        ActivityGuard(self.clone())
+    }
+
+    /// Time since the last request ended; `None` while one is running or once
+    /// the instance was stopped for this idle period.
+    pub fn idle_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        (state.in_flight == 0 && !state.stopped).then(|| state.last_active.elapsed())
+    }
+
+    /// Record that the instance is down until the next request.
+    pub fn mark_stopped(&self) {
+        self.state.lock().unwrap().stopped = true;
+    }
+
+    /// Whether the instance was stopped and has not been used since.
+    pub fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+
+    /// Requests currently holding a guard.
+    pub fn in_flight(&self) -> usize {
+        self.state.lock().unwrap().in_flight
+    }
+}
+
+/// Keeps an [`IdleTracker`] busy while alive.
+pub struct ActivityGuard(Arc<IdleTracker>);
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.last_active = Instant::now();
+    }
+}
+
+/// What the server needs to stop idle Docling instances.
+pub struct IdleStop {
+    pub config: GceConfig,
+    /// Instance name and its tracker, for each instance in the pool
+    pub trackers: Vec<(String, Arc<IdleTracker>)>,
+    pub window: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_tracker() {
+        let tracker = Arc::new(IdleTracker::default());
+        assert!(tracker.idle_for().is_some());
+
+        let first = tracker.begin();
+        let second = tracker.begin();
+        drop(first);
+        assert_eq!(tracker.in_flight(), 1);
+        assert!(tracker.idle_for().is_none());
+        drop(second);
+        assert!(tracker.idle_for().unwrap() < Duration::from_secs(1));
+
+        // Stopped instances are not stopped again until they are used
+        tracker.mark_stopped();
+        assert!(tracker.is_stopped());
+        assert!(tracker.idle_for().is_none());
+        drop(tracker.begin());
+        assert!(!tracker.is_stopped());
+        assert!(tracker.idle_for().is_some());
+    }
 }
@@ -0,0 +1,121 @@
+//! Google service-account OAuth2 token minting (JWT bearer flow).
+//!
+//! Shared by the GCE on-demand client and the GCS object store. Tokens are
+//! cached per scope and refreshed a minute before they expire.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::sync::{Arc, Mutex};
+
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Service-account credentials bound to a single OAuth2 scope.
+#[derive(Clone)]
+pub struct ServiceAccountAuth {
+    sa_key: ServiceAccountKey,
+    scope: String,
+    /// Cached OAuth2 access token.
+    token_cache: Arc<Mutex<Option<CachedToken>>>,
+}
+
+#[derive(Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: u64,
+}
+
+#[derive(Clone, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    #[allow(dead_code)]
+    token_uri: Option<String>,
+}
+
+impl ServiceAccountAuth {
+    /// Load a service-account JSON key from disk.
+    pub fn from_key_path(key_path: &str, scope: &str) -> Result<Self> {
+        let key_json = std::fs::read_to_string(key_path)
+            .with_context(|| format!("Service account key {} unreadable", key_path))?;
+        let sa_key: ServiceAccountKey =
+            serde_json::from_str(&key_json).context("Failed to parse service account key")?;
+
+        Ok(Self {
+            sa_key,
+            scope: scope.to_string(),
+            token_cache: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Get a valid OAuth2 access token, refreshing if expired.
+    pub async fn get_access_token(&self, client: &reqwest::Client) -> Result<String> {
+        // Check cache
+        {
+            let cache = self.token_cache.lock().unwrap();
+            if let Some(ref cached) = *cache {
+                let now = now_secs();
+                if now < cached.expires_at.saturating_sub(60) {
+                    return Ok(cached.access_token.clone());
+                }
+            }
+        }
+
+        // Mint a new JWT
+        let now = now_secs();
+        let claims = serde_json::json!({
+            "iss": self.sa_key.client_email,
+            "scope": self.scope,
+            "aud": TOKEN_URI,
+            "iat": now,
+            "exp": now + 3600,
+        });
+
+        let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256);
+        let encoding_key =
+            jsonwebtoken::EncodingKey::from_rsa_pem(self.sa_key.private_key.as_bytes())
+                .context("Invalid RSA private key in service account JSON")?;
+
+        let jwt = jsonwebtoken::encode(&header, &claims, &encoding_key)
+            .context("Failed to encode JWT")?;
+
+        // Exchange JWT for access token
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: String,
+            expires_in: u64,
+        }
+
+        let resp: TokenResponse = client
+            .post(TOKEN_URI)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &jwt),
+            ])
+            .send()
+            .await
+            .context("Token exchange request failed")?
+            .error_for_status()
+            .context("Token exchange returned error")?
+            .json()
+            .await
+            .context("Failed to parse token response")?;
+
+        let token = resp.access_token.clone();
+        {
+            let mut cache = self.token_cache.lock().unwrap();
+            *cache = Some(CachedToken {
+                access_token: resp.access_token,
+                expires_at: now + resp.expires_in,
+            });
+        }
+
+        Ok(token)
+    }
+}
+
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
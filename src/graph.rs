@@ -0,0 +1,674 @@
+//! Cross-extraction graph built from reference indexes.
+//!
+//! Extractions are linked through the entities they share (process numbers,
+//! CPF/CNPJ, ...), through entity values that match another extraction's
+//! `readable_id` (one processo citing another), and through `duplicate_of`.
+//! Served by `GET /graph`.
+//!
+//! Also exports a single extraction's node tree and `Relationship` edges as
+//! GraphML or Cytoscape.js JSON (`GET /extractions/:id/graph`).
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use serde::Serialize;
+
+use crate::entities::resolution_key;
+use crate::readable_id::search_key;
+use crate::schema::{DocumentNode, Extraction};
+
+/// What `GET /graph` needs from each extraction.
+pub struct GraphSource {
+    pub id: String,
+    pub source_file: String,
+    pub config_name: Option<String>,
+    pub readable_id: Option<String>,
+    pub duplicate_of: Option<String>,
+    pub reference_index: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphNodeKind {
+    Extraction,
+    Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphEdgeKind {
+    /// Extraction → entity found in its text
+    Mentions,
+    /// Extraction → extraction whose `readable_id` it mentions
+    References,
+    /// Extraction → the earlier extraction it duplicates
+    DuplicateOf,
+}
+
+impl GraphEdgeKind {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "mentions" => Some(Self::Mentions),
+            "references" => Some(Self::References),
+            "duplicate_of" => Some(Self::DuplicateOf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    /// Extraction ID, or `entity:{type}:{key}` for entities
+    pub id: String,
+    pub kind: GraphNodeKind,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readable_id: Option<String>,
+    /// Other spellings of an entity seen across the extractions
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: GraphEdgeKind,
+    /// Entity value behind a `references` edge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// Nodes of the source extraction where the entity appears
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub node_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Which parts of the graph to return.
+#[derive(Debug, Clone)]
+pub struct GraphFilter {
+    /// Keep only these entity types (pattern IDs); `None` keeps all
+    pub entity_types: Option<HashSet<String>>,
+    /// Keep only these edge kinds; `None` keeps all
+    pub edge_kinds: Option<HashSet<GraphEdgeKind>>,
+    /// Drop entities mentioned by fewer extractions than this
+    pub min_extractions: usize,
+    /// Keep only the neighborhood of this extraction...
+    pub center: Option<String>,
+    /// ...up to this many hops away
+    pub depth: usize,
+}
+
+impl Default for GraphFilter {
+    fn default() -> Self {
+        Self {
+            entity_types: None,
+            edge_kinds: None,
+            min_extractions: 2,
+            center: None,
+            depth: 2,
+        }
+    }
+}
+
+/// One entity occurrence as stored in `reference_index.entities`.
+#[derive(serde::Deserialize)]
+struct Occurrence {
+    value: String,
+    #[serde(default)]
+    node_ids: Vec<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+}
+
+/// Build the graph for `sources`, then apply `filter`.
+pub fn build(sources: &[GraphSource], filter: &GraphFilter) -> Graph {
+    let wants_edge = |kind| filter.edge_kinds.as_ref().is_none_or(|k| k.contains(&kind));
+    let wants_entity = |t: &str| filter.entity_types.as_ref().is_none_or(|k| k.contains(t));
+
+    // Extractions by readable_id key, to resolve `references` edges
+    let by_readable_id: HashMap<String, &str> = sources
+        .iter()
+        .filter_map(|s| {
+            let key = search_key(s.readable_id.as_deref()?);
+            (!key.is_empty()).then_some((key, s.id.as_str()))
+        })
+        .collect();
+    let known: HashSet<&str> = sources.iter().map(|s| s.id.as_str()).collect();
+
+    let mut entities: BTreeMap<String, GraphNode> = BTreeMap::new();
+    let mut edges = Vec::new();
+
+    for source in sources {
+        let index: BTreeMap<String, Vec<Occurrence>> = source
+            .reference_index
+            .get("entities")
+            .cloned()
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+
+        let mut referenced: HashSet<&str> = HashSet::new();
+        for (entity_type, occurrences) in &index {
+            for occ in occurrences {
+                let key = resolution_key(&occ.value);
+                if key.is_empty() {
+                    continue;
+                }
+
+                if wants_edge(GraphEdgeKind::References) {
+                    let target = std::iter::once(&occ.value)
+                        .chain(&occ.aliases)
+                        .find_map(|value| by_readable_id.get(&search_key(value)));
+                    if let Some(&target) = target {
+                        if target != source.id && referenced.insert(target) {
+                            edges.push(GraphEdge {
+                                source: source.id.clone(),
+                                target: target.to_string(),
+                                kind: GraphEdgeKind::References,
+                                value: Some(occ.value.clone()),
+                                node_ids: occ.node_ids.clone(),
+                            });
+                        }
+                    }
+                }
+
+                if wants_edge(GraphEdgeKind::Mentions) && wants_entity(entity_type) {
+                    let id = format!("entity:{}:{}", entity_type, key);
+                    let node = entities.entry(id.clone()).or_insert_with(|| GraphNode {
+                        id: id.clone(),
+                        kind: GraphNodeKind::Entity,
+                        label: occ.value.clone(),
+                        entity_type: Some(entity_type.clone()),
+                        source_file: None,
+                        config_name: None,
+                        readable_id: None,
+                        aliases: Vec::new(),
+                    });
+                    for alias in std::iter::once(&occ.value).chain(&occ.aliases) {
+                        if *alias != node.label && !node.aliases.contains(alias) {
+                            node.aliases.push(alias.clone());
+                        }
+                    }
+                    edges.push(GraphEdge {
+                        source: source.id.clone(),
+                        target: id,
+                        kind: GraphEdgeKind::Mentions,
+                        value: None,
+                        node_ids: occ.node_ids.clone(),
+                    });
+                }
+            }
+        }
+
+        if wants_edge(GraphEdgeKind::DuplicateOf) {
+            if let Some(ref original) = source.duplicate_of {
+                if known.contains(original.as_str()) {
+                    edges.push(GraphEdge {
+                        source: source.id.clone(),
+                        target: original.clone(),
+                        kind: GraphEdgeKind::DuplicateOf,
+                        value: None,
+                        node_ids: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Entities mentioned by a single extraction connect nothing; drop them
+    let mut mentioned_by: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind == GraphEdgeKind::Mentions) {
+        mentioned_by
+            .entry(edge.target.as_str())
+            .or_default()
+            .insert(edge.source.as_str());
+    }
+    let dropped: HashSet<String> = entities
+        .keys()
+        .filter(|id| mentioned_by.get(id.as_str()).map_or(0, HashSet::len) < filter.min_extractions)
+        .cloned()
+        .collect();
+    edges.retain(|e| !dropped.contains(&e.target));
+    entities.retain(|id, _| !dropped.contains(id));
+
+    let mut nodes: Vec<GraphNode> = sources
+        .iter()
+        .map(|s| GraphNode {
+            id: s.id.clone(),
+            kind: GraphNodeKind::Extraction,
+            label: s
+                .readable_id
+                .clone()
+                .unwrap_or_else(|| s.source_file.clone()),
+            entity_type: None,
+            source_file: Some(s.source_file.clone()),
+            config_name: s.config_name.clone(),
+            readable_id: s.readable_id.clone(),
+            aliases: Vec::new(),
+        })
+        .collect();
+    nodes.extend(entities.into_values());
+
+    let graph = Graph { nodes, edges };
+    match filter.center {
+        Some(ref center) => neighborhood(graph, center, filter.depth),
+        None => graph,
+    }
+}
+
+/// Keep the nodes within `depth` hops of `center` (edges treated as undirected).
+fn neighborhood(graph: Graph, center: &str, depth: usize) -> Graph {
+    let mut adjacent: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &graph.edges {
+        adjacent.entry(&edge.source).or_default().push(&edge.target);
+        adjacent.entry(&edge.target).or_default().push(&edge.source);
+    }
+
+    let mut keep: HashSet<String> = HashSet::new();
+    if graph.nodes.iter().any(|n| n.id == center) {
+        keep.insert(center.to_string());
+        let mut queue = VecDeque::from([(center, 0)]);
+        while let Some((id, hops)) = queue.pop_front() {
+            if hops == depth {
+                continue;
+            }
+            for &next in adjacent.get(id).into_iter().flatten() {
+                if keep.insert(next.to_string()) {
+                    queue.push_back((next, hops + 1));
+                }
+            }
+        }
+    }
+
+    Graph {
+        nodes: graph
+            .nodes
+            .into_iter()
+            .filter(|n| keep.contains(&n.id))
+            .collect(),
+        edges: graph
+            .edges
+            .into_iter()
+            .filter(|e| keep.contains(&e.source) && keep.contains(&e.target))
+            .collect(),
+    }
+}
+
+// ============================================================================
+// Single-extraction export (GraphML / Cytoscape)
+// ============================================================================
+
+/// Edge type linking a parent node to its children.
+const CONTAINS_EDGE: &str = "contains";
+
+/// Node attributes exported for every node: (name, GraphML `attr.type`).
+const NODE_ATTRIBUTES: &[(&str, &str)] = &[
+    ("node_type", "string"),
+    ("subtype", "string"),
+    ("label", "string"),
+    ("page_start", "int"),
+    ("page_end", "int"),
+    ("date", "string"),
+    ("author", "string"),
+    ("ocr_confidence", "double"),
+    ("extraction_confidence", "double"),
+];
+
+/// Edge attributes: `edge_type` is `contains` or the relationship type.
+const EDGE_ATTRIBUTES: &[(&str, &str)] = &[("edge_type", "string"), ("citation", "string")];
+
+struct ExportNode {
+    id: String,
+    attributes: Vec<(&'static str, serde_json::Value)>,
+}
+
+struct ExportEdge {
+    source: String,
+    target: String,
+    attributes: Vec<(&'static str, serde_json::Value)>,
+}
+
+/// Flatten an extraction into nodes (the extraction itself is the root) and
+/// edges (tree `contains` edges plus relationships between known nodes).
+fn export_elements(extraction: &Extraction) -> (Vec<ExportNode>, Vec<ExportEdge>) {
+    fn walk(
+        nodes: &[DocumentNode],
+        parent: &str,
+        out_nodes: &mut Vec<ExportNode>,
+        out_edges: &mut Vec<ExportEdge>,
+    ) {
+        for node in nodes {
+            let confidence = node.confidence.as_ref();
+            let attributes = [
+                ("node_type", Some(node.node_type.clone().into())),
+                ("subtype", node.subtype.clone().map(Into::into)),
+                ("label", node.label.clone().map(Into::into)),
+                ("page_start", node.page_range.map(|r| r[0].into())),
+                ("page_end", node.page_range.map(|r| r[1].into())),
+                ("date", node.date.clone().map(Into::into)),
+                ("author", node.author.clone().map(Into::into)),
+                (
+                    "ocr_confidence",
+                    confidence.and_then(|c| c.ocr).map(Into::into),
+                ),
+                (
+                    "extraction_confidence",
+                    confidence.and_then(|c| c.extraction).map(Into::into),
+                ),
+            ];
+            out_nodes.push(ExportNode {
+                id: node.id.clone(),
+                attributes: attributes
+                    .into_iter()
+                    .filter_map(|(k, v)| Some((k, v?)))
+                    .collect(),
+            });
+            out_edges.push(ExportEdge {
+                source: parent.to_string(),
+                target: node.id.clone(),
+                attributes: vec![("edge_type", CONTAINS_EDGE.into())],
+            });
+            walk(&node.children, &node.id, out_nodes, out_edges);
+        }
+    }
+
+    let root_label = extraction
+        .readable_id
+        .clone()
+        .unwrap_or_else(|| extraction.source_file.clone());
+    let mut nodes = vec![ExportNode {
+        id: extraction.id.clone(),
+        attributes: vec![
+            ("node_type", "EXTRACTION".into()),
+            ("label", root_label.into()),
+        ],
+    }];
+    let mut edges = Vec::new();
+    walk(&extraction.children, &extraction.id, &mut nodes, &mut edges);
+
+    let known: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+    let relationships: Vec<ExportEdge> = extraction
+        .relationships
+        .iter()
+        .filter(|r| known.contains(r.from.as_str()) && known.contains(r.to.as_str()))
+        .map(|r| {
+            let mut attributes = vec![("edge_type", r.rel_type.clone().into())];
+            if let Some(ref citation) = r.citation {
+                attributes.push(("citation", citation.clone().into()));
+            }
+            ExportEdge {
+                source: r.from.clone(),
+                target: r.to.clone(),
+                attributes,
+            }
+        })
+        .collect();
+    edges.extend(relationships);
+    (nodes, edges)
+}
+
+/// Cytoscape.js elements JSON (`{"data": ..., "elements": {"nodes", "edges"}}`).
+pub fn to_cytoscape(extraction: &Extraction) -> serde_json::Value {
+    let (nodes, edges) = export_elements(extraction);
+    let data = |id: (&str, String), attributes: Vec<(&'static str, serde_json::Value)>| {
+        let mut map = serde_json::Map::new();
+        map.insert(id.0.to_string(), id.1.into());
+        for (k, v) in attributes {
+            map.insert(k.to_string(), v);
+        }
+        serde_json::json!({ "data": map })
+    };
+
+    let nodes: Vec<serde_json::Value> = nodes
+        .into_iter()
+        .map(|n| data(("id", n.id), n.attributes))
+        .collect();
+    let edges: Vec<serde_json::Value> = edges
+        .into_iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let mut attributes = vec![("source", e.source.into()), ("target", e.target.into())];
+            attributes.extend(e.attributes);
+            data(("id", format!("e{}", i)), attributes)
+        })
+        .collect();
+
+    serde_json::json!({
+        "data": {
+            "id": extraction.id,
+            "source_file": extraction.source_file,
+            "config_name": extraction.config_name,
+        },
+        "elements": { "nodes": nodes, "edges": edges },
+    })
+}
+
+/// GraphML document (directed graph, attributes declared as `<key>`s).
+pub fn to_graphml(extraction: &Extraction) -> String {
+    use std::fmt::Write;
+
+    let (nodes, edges) = export_elements(extraction);
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+    );
+    for (target, attributes) in [("node", NODE_ATTRIBUTES), ("edge", EDGE_ATTRIBUTES)] {
+        for (name, ty) in attributes {
+            let _ = writeln!(
+                xml,
+                "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>",
+                name, target, ty
+            );
+        }
+    }
+    let _ = writeln!(
+        xml,
+        "  <graph id=\"{}\" edgedefault=\"directed\">",
+        xml_escape(&extraction.id)
+    );
+
+    let write_data = |xml: &mut String, attributes: &[(&str, serde_json::Value)]| {
+        for (key, value) in attributes {
+            let text = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let _ = writeln!(
+                xml,
+                "      <data key=\"{}\">{}</data>",
+                key,
+                xml_escape(&text)
+            );
+        }
+    };
+    for node in &nodes {
+        let _ = writeln!(xml, "    <node id=\"{}\">", xml_escape(&node.id));
+        write_data(&mut xml, &node.attributes);
+        xml.push_str("    </node>\n");
+    }
+    for (i, edge) in edges.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "    <edge id=\"e{}\" source=\"{}\" target=\"{}\">",
+            i,
+            xml_escape(&edge.source),
+            xml_escape(&edge.target)
+        );
+        write_data(&mut xml, &edge.attributes);
+        xml.push_str("    </edge>\n");
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(id: &str, readable_id: &str, entities: serde_json::Value) -> GraphSource {
+        GraphSource {
+            id: id.to_string(),
+            source_file: format!("{}.pdf", id),
+            config_name: Some("legal_br".into()),
+            readable_id: Some(readable_id.to_string()),
+            duplicate_of: None,
+            reference_index: serde_json::json!({ "entities": entities }),
+        }
+    }
+
+    #[test]
+    fn test_links_extractions_through_entities() {
+        let mut copy = source("ext_c", "0000003-00.2024.8.26.0100", serde_json::json!({}));
+        copy.duplicate_of = Some("ext_a".into());
+        let sources = vec![
+            source(
+                "ext_a",
+                "0000001-00.2024.8.26.0100",
+                serde_json::json!({
+                    "cnpj": [{"value": "09296295000160", "node_ids": ["peticao"]}],
+                    "processo_cnj": [{"value": "0000002-00.2024.8.26.0100", "node_ids": ["decisao"]}],
+                    "cpf": [{"value": "12345678900", "node_ids": ["peticao"]}],
+                    "parte": [{"value": "João da Silva", "node_ids": ["peticao"], "aliases": ["J. Silva"]}]
+                }),
+            ),
+            source(
+                "ext_b",
+                "0000002-00.2024.8.26.0100",
+                serde_json::json!({
+                    "cnpj": [{"value": "09296295000160", "node_ids": ["contestacao"]}],
+                    "parte": [{"value": "JOAO SILVA", "node_ids": ["contestacao"]}]
+                }),
+            ),
+            copy,
+        ];
+
+        let graph = build(&sources, &GraphFilter::default());
+        let edges: Vec<(&str, &str, GraphEdgeKind)> = graph
+            .edges
+            .iter()
+            .map(|e| (e.source.as_str(), e.target.as_str(), e.kind))
+            .collect();
+        // The cited process number becomes a direct reference. The CPF and the
+        // process number appear in one extraction each, so only the CNPJ is an entity node.
+        assert!(edges.contains(&("ext_a", "ext_b", GraphEdgeKind::References)));
+        assert!(edges.contains(&(
+            "ext_a",
+            "entity:cnpj:09296295000160",
+            GraphEdgeKind::Mentions
+        )));
+        assert!(edges.contains(&(
+            "ext_b",
+            "entity:cnpj:09296295000160",
+            GraphEdgeKind::Mentions
+        )));
+        assert!(edges.contains(&("ext_c", "ext_a", GraphEdgeKind::DuplicateOf)));
+        // Spellings of one party meet on the same entity node
+        let parte = graph
+            .nodes
+            .iter()
+            .find(|n| n.id == "entity:parte:joaosilva")
+            .unwrap();
+        assert_eq!(parte.label, "João da Silva");
+        assert_eq!(parte.aliases, vec!["J. Silva", "JOAO SILVA"]);
+        assert_eq!(graph.nodes.len(), 5);
+
+        // One hop from ext_b, duplicates only: nothing but ext_b itself.
+        let filter = GraphFilter {
+            edge_kinds: Some(HashSet::from([GraphEdgeKind::DuplicateOf])),
+            center: Some("ext_b".into()),
+            depth: 1,
+            ..Default::default()
+        };
+        let graph = build(&sources, &filter);
+        assert_eq!(graph.nodes.len(), 1);
+        assert!(graph.edges.is_empty());
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let node = |id: &str, children: Vec<DocumentNode>| DocumentNode {
+            id: id.to_string(),
+            node_type: "PETICAO".into(),
+            subtype: Some("Inicial".into()),
+            label: Some("Petição <Inicial> & anexos".into()),
+            page_range: Some([1, 12]),
+            ocr_span: None,
+            pdf_page_range: None,
+            date: None,
+            author: None,
+            summary: String::new(),
+            references: Vec::new(),
+            referenced_by: Vec::new(),
+            content_ref: None,
+            confidence: Some(crate::schema::ConfidenceScores {
+                ocr: Some(0.9),
+                extraction: Some(0.8),
+                summary: None,
+                low_confidence_regions: Vec::new(),
+            }),
+            metadata: serde_json::Value::Null,
+            reviewed: false,
+            child_extraction_id: None,
+            children,
+        };
+        let mut extraction = Extraction::new("autos.pdf".into(), Some("legal_br".into()));
+        extraction.children = vec![
+            node("peticao", vec![node("anexo", vec![])]),
+            node("decisao", vec![]),
+        ];
+        extraction.relationships = vec![
+            crate::schema::Relationship {
+                from: "decisao".into(),
+                to: "peticao".into(),
+                rel_type: "decides_on".into(),
+                citation: None,
+            },
+            // Dangling relationships are left out.
+            crate::schema::Relationship {
+                from: "decisao".into(),
+                to: "missing".into(),
+                rel_type: "references".into(),
+                citation: None,
+            },
+        ];
+
+        let cy = to_cytoscape(&extraction);
+        let nodes = cy["elements"]["nodes"].as_array().unwrap();
+        let edges = cy["elements"]["edges"].as_array().unwrap();
+        assert_eq!(nodes.len(), 4);
+        assert_eq!(edges.len(), 4); // 3 contains + 1 relationship
+        assert_eq!(nodes[1]["data"]["page_end"], 12);
+        assert_eq!(edges[3]["data"]["edge_type"], "decides_on");
+
+        let xml = to_graphml(&extraction);
+        assert!(xml.contains(
+            r#"<key id="page_start" for="node" attr.name="page_start" attr.type="int"/>"#
+        ));
+        assert!(xml.contains("<data key=\"label\">Petição &lt;Inicial&gt; &amp; anexos</data>"));
+        assert_eq!(xml.matches("<edge ").count(), 4);
+    }
+}
@@ -0,0 +1,215 @@
+//! Conditional, streamed JSON for the large reads frontends poll.
+//!
+//! [`json`] tags a body with a weak `ETag` (a hash of its JSON) and answers
+//! `304 Not Modified` with no body when the request's `If-None-Match`
+//! already names that tag. `Last-Modified` is informational: extractions
+//! change status while their timestamps stay put, so only the ETag is used
+//! to validate, and `Cache-Control: no-cache` makes clients revalidate
+//! instead of guessing freshness from the date.
+//!
+//! Otherwise the body is serialized straight into the response in
+//! [`CHUNK_BYTES`] chunks, so a multi-megabyte extraction is never held as
+//! one JSON buffer and the compression layer can start sending before
+//! serialization ends. Hashing and serializing both run on a blocking
+//! thread, off the async workers.
+
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Serialize `body` as JSON with an ETag, or answer 304 if the client has it.
+/// `last_modified` is an ISO 8601 timestamp as written by `schema::iso8601`.
+pub async fn json<T: Serialize + Send + 'static>(
+    request: &HeaderMap,
+    body: T,
+    last_modified: Option<&str>,
+) -> Response {
+    let if_none_match = request
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let (tag_tx, tag_rx) = oneshot::channel();
+    let (tx, rx) = mpsc::channel(4);
+    tokio::task::spawn_blocking(move || {
+        let tag = match etag(&body) {
+            Ok(tag) => tag,
+            Err(e) => {
+                let _ = tag_tx.send(Err(e));
+                return;
+            }
+        };
+        let fresh = if_none_match.is_some_and(|v| none_match(&v, &tag));
+        if tag_tx.send(Ok(tag)).is_ok() && !fresh {
+            write_chunks(&body, tx);
+        }
+    });
+    let tag = match tag_rx.await {
+        Ok(Ok(tag)) => tag,
+        Ok(Err(e)) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "serialization task failed".to_string(),
+            )
+                .into_response()
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(value) = HeaderValue::from_str(&tag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(date) = last_modified.and_then(parse_timestamp) {
+        if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(date)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    let fresh = request
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| none_match(v, &tag));
+    if fresh {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    (headers, Body::from_stream(ReceiverStream::new(rx))).into_response()
+}
+
+/// Weak ETag of `body`: the first 16 bytes of the SHA-256 of its JSON.
+fn etag<T: Serialize>(body: &T) -> serde_json::Result<String> {
+    let mut hasher = Sha256::new();
+    serde_json::to_writer(&mut hasher, body)?;
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("W/\"{}\"", hex))
+}
+
+/// Whether an `If-None-Match` value names `tag` (weak comparison).
+fn none_match(header: &str, tag: &str) -> bool {
+    let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || opaque(candidate) == opaque(tag))
+}
+
+/// Serialize `body` into the response body's channel.
+fn write_chunks<T: Serialize>(body: &T, tx: mpsc::Sender<io::Result<Bytes>>) {
+    let mut writer = ChunkWriter {
+        buf: Vec::with_capacity(CHUNK_BYTES),
+        tx,
+    };
+    let result = serde_json::to_writer(&mut writer, body)
+        .map_err(io::Error::from)
+        .and_then(|()| writer.flush());
+    if let Err(e) = result {
+        // Aborts the response; the client sees a truncated body
+        let _ = writer.tx.blocking_send(Err(e));
+    }
+}
+
+/// Sends what is written to the response body in chunks.
+struct ChunkWriter {
+    buf: Vec<u8>,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buf,
+            Vec::with_capacity(CHUNK_BYTES),
+        ));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "response dropped"))
+    }
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS` (anything after the seconds is ignored, so
+/// the time is taken as UTC).
+fn parse_timestamp(iso: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| iso.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01 in the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second;
+    u64::try_from(secs)
+        .ok()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_conditional_json() {
+        let body = serde_json::json!({"id": "ext_1", "status": "queued"});
+        let first = json(
+            &HeaderMap::new(),
+            body.clone(),
+            Some("2024-02-29T03:30:00Z"),
+        )
+        .await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let tag = first.headers()[header::ETAG].clone();
+        assert_eq!(
+            first.headers()[header::LAST_MODIFIED],
+            "Thu, 29 Feb 2024 03:30:00 GMT"
+        );
+        let streamed = axum::body::to_bytes(first.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(streamed, serde_json::to_vec(&body).unwrap());
+
+        let mut request = HeaderMap::new();
+        request.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"other\", {}", tag.to_str().unwrap())).unwrap(),
+        );
+        let again = json(&request, body, None).await;
+        assert_eq!(again.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(again.headers()[header::ETAG], tag);
+
+        let changed = serde_json::json!({"id": "ext_1", "status": "completed"});
+        assert_eq!(json(&request, changed, None).await.status(), StatusCode::OK);
+        assert!(parse_timestamp("yesterday").is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! Watch-folder ingestion.
+//!
+//! With `INGEST_DIR` set, the server polls that directory every
+//! `INGEST_INTERVAL_SECS` (default 10) and extracts every new document in it,
+//! so bulk backfills need no client. A file's first subfolder picks its
+//! config: `INGEST_DIR/financial_br/balanco.pdf` uses `financial_br`, unless
+//! `INGEST_CONFIGS` maps the folder to another config
+//! (`balancos=financial_br,autos=legal_br`). Files directly in `INGEST_DIR`
+//! use `INGEST_DEFAULT_CONFIG`, or else the server's default document config
+//! (`DEFAULT_DOC_CONFIG`, or the config marked `"default": true`).
+//!
+//! A claimed file moves to `processing/`, then to `done/` or `failed/` when
+//! its extraction ends, keeping its subfolder; a failure also leaves the
+//! error in `{file}.error.txt`. Files changed in the last
+//! `INGEST_SETTLE_SECS` (default 5) are still being copied and wait for the
+//! next poll, and hidden files (`.name`) are never picked up.
+//!
+//! Only local directories are watched: S3 (or any `ObjectStore`) prefixes
+//! are out of scope, since the claim relies on an atomic rename. To ingest
+//! from a bucket, mount it (e.g. with `mountpoint-s3`) and point
+//! `INGEST_DIR` at the mount.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+const PROCESSING_DIR: &str = "processing";
+const DONE_DIR: &str = "done";
+const FAILED_DIR: &str = "failed";
+
+const DEFAULT_INTERVAL_SECS: u64 = 10;
+const DEFAULT_SETTLE_SECS: u64 = 5;
+
+/// A watched directory and its folder-to-config mapping.
+pub struct WatchFolder {
+    dir: PathBuf,
+    interval: Duration,
+    settle: Duration,
+    /// `INGEST_DEFAULT_CONFIG`; `None` uses the server's default
+    default_config: Option<String>,
+    folder_configs: HashMap<String, String>,
+    ocr_provider: String,
+    upload: bool,
+}
+
+/// A file moved into `processing/`, waiting for its extraction.
+#[derive(Debug)]
+pub struct Claimed {
+    /// Path under the watched directory (`financial_br/balanco.pdf`)
+    pub relative: PathBuf,
+    /// `None` for files directly in the watched directory without
+    /// `INGEST_DEFAULT_CONFIG`: they use the default document config
+    pub config_name: Option<String>,
+}
+
+impl Claimed {
+    pub fn filename(&self) -> String {
+        self.relative
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl WatchFolder {
+    /// Read `INGEST_*`; `None` when `INGEST_DIR` is not set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(dir) = std::env::var("INGEST_DIR") else {
+            return Ok(None);
+        };
+        let secs = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        let folder_configs = std::env::var("INGEST_CONFIGS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(folder, config)| (folder.trim().to_string(), config.trim().to_string()))
+            .collect();
+        let mut folder = Self::open(dir)?;
+        folder.interval = Duration::from_secs(secs("INGEST_INTERVAL_SECS", DEFAULT_INTERVAL_SECS));
+        folder.settle = Duration::from_secs(secs("INGEST_SETTLE_SECS", DEFAULT_SETTLE_SECS));
+        folder.folder_configs = folder_configs;
+        folder.default_config = std::env::var("INGEST_DEFAULT_CONFIG")
+            .ok()
+            .filter(|config| !config.trim().is_empty());
+        if let Ok(provider) = std::env::var("INGEST_OCR_PROVIDER") {
+            folder.ocr_provider = provider;
+        }
+        folder.upload = std::env::var("INGEST_UPLOAD").map_or(true, |v| v != "false");
+        Ok(Some(folder))
+    }
+
+    /// Watch `dir` with the default settings.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        for sub in [PROCESSING_DIR, DONE_DIR, FAILED_DIR] {
+            std::fs::create_dir_all(dir.join(sub))
+                .with_context(|| format!("Failed to create {}", dir.join(sub).display()))?;
+        }
+        Ok(Self {
+            dir,
+            interval: Duration::from_secs(DEFAULT_INTERVAL_SECS),
+            settle: Duration::from_secs(DEFAULT_SETTLE_SECS),
+            default_config: None,
+            folder_configs: HashMap::new(),
+            ocr_provider: "docling".to_string(),
+            upload: true,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// OCR provider for ingested files (`INGEST_OCR_PROVIDER`, default `docling`).
+    pub fn ocr_provider(&self) -> &str {
+        &self.ocr_provider
+    }
+
+    /// Whether results are uploaded to storage (`INGEST_UPLOAD`, default true).
+    pub fn upload(&self) -> bool {
+        self.upload
+    }
+
+    /// The config for a file at `relative` (a path under the watched directory).
+    pub fn config_for(&self, relative: &Path) -> Option<String> {
+        let mut components = relative.components();
+        match (components.next(), components.next()) {
+            (Some(folder), Some(_)) => {
+                let folder = folder.as_os_str().to_string_lossy();
+                Some(
+                    self.folder_configs
+                        .get(folder.as_ref())
+                        .cloned()
+                        .unwrap_or_else(|| folder.to_string()),
+                )
+            }
+            _ => self.default_config.clone(),
+        }
+    }
+
+    /// Claim every settled file: move it into `processing/` and return it.
+    pub fn scan(&self) -> Vec<Claimed> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, &self.dir, self.settle, &mut files);
+        files.sort();
+
+        let mut claimed = Vec::new();
+        for relative in files {
+            let target = self.dir.join(PROCESSING_DIR).join(&relative);
+            if let Err(e) = move_file(&self.dir.join(&relative), &target) {
+                warn!("Ingest: could not claim {}: {}", relative.display(), e);
+                continue;
+            }
+            claimed.push(Claimed {
+                config_name: self.config_for(&relative),
+                relative,
+            });
+        }
+        claimed
+    }
+
+    /// Path of a claimed file while it is processed.
+    pub fn processing_path(&self, claimed: &Claimed) -> PathBuf {
+        self.dir.join(PROCESSING_DIR).join(&claimed.relative)
+    }
+
+    /// Move a claimed file to `done/`, or to `failed/` with its error beside it.
+    pub fn finish(&self, claimed: &Claimed, error: Option<&str>) -> Result<PathBuf> {
+        let target = self
+            .dir
+            .join(if error.is_some() {
+                FAILED_DIR
+            } else {
+                DONE_DIR
+            })
+            .join(&claimed.relative);
+        move_file(&self.processing_path(claimed), &target)?;
+        if let Some(error) = error {
+            let mut error_path = target.clone().into_os_string();
+            error_path.push(".error.txt");
+            std::fs::write(error_path, error)?;
+        }
+        Ok(target)
+    }
+
+    /// Put a claimed file back in the inbox, to be claimed again by a later poll.
+    pub fn release(&self, claimed: &Claimed) -> Result<()> {
+        move_file(
+            &self.processing_path(claimed),
+            &self.dir.join(&claimed.relative),
+        )?;
+        Ok(())
+    }
+
+    /// Put files left in `processing/` by a previous run back in the inbox.
+    pub fn requeue_interrupted(&self) -> usize {
+        let processing = self.dir.join(PROCESSING_DIR);
+        let mut files = Vec::new();
+        collect_files(&processing, &processing, Duration::ZERO, &mut files);
+        let mut moved = 0;
+        for relative in files {
+            match move_file(&processing.join(&relative), &self.dir.join(&relative)) {
+                Ok(()) => moved += 1,
+                Err(e) => warn!("Ingest: could not requeue {}: {}", relative.display(), e),
+            }
+        }
+        if moved > 0 {
+            info!(
+                "Ingest: requeued {} file(s) interrupted by a restart",
+                moved
+            );
+        }
+        moved
+    }
+}
+
+/// Files under `dir` (relative to `root`) unchanged for at least `settle`,
+/// skipping hidden entries and, at the top level, the ingest's own folders.
+fn collect_files(root: &Path, dir: &Path, settle: Duration, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        if dir == root && [PROCESSING_DIR, DONE_DIR, FAILED_DIR].contains(&name.as_ref()) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        if meta.is_dir() {
+            collect_files(root, &path, settle, out);
+        } else if meta.is_file() {
+            let settled = meta
+                .modified()
+                .ok()
+                .and_then(|m| m.elapsed().ok())
+                .is_some_and(|age| age >= settle);
+            if settled {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    out.push(relative.to_path_buf());
+                }
+            }
+        }
+    }
+}
+
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_folder() {
+        let dir = std::env::temp_dir().join(format!("ingest_{}", uuid::Uuid::new_v4()));
+        let mut folder = WatchFolder::open(&dir).unwrap();
+        folder.settle = Duration::ZERO;
+        folder
+            .folder_configs
+            .insert("balancos".to_string(), "financial_br".to_string());
+
+        std::fs::create_dir_all(dir.join("balancos")).unwrap();
+        std::fs::create_dir_all(dir.join("legal_br/2024")).unwrap();
+        std::fs::write(dir.join("top.pdf"), b"a").unwrap();
+        std::fs::write(dir.join("balancos/b.pdf"), b"b").unwrap();
+        std::fs::write(dir.join("legal_br/2024/c.pdf"), b"c").unwrap();
+        std::fs::write(dir.join("balancos/.partial"), b"x").unwrap();
+
+        let claimed = folder.scan();
+        let found: Vec<(String, Option<&str>)> = claimed
+            .iter()
+            .map(|c| (c.relative.display().to_string(), c.config_name.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("balancos/b.pdf".to_string(), Some("financial_br")),
+                ("legal_br/2024/c.pdf".to_string(), Some("legal_br")),
+                ("top.pdf".to_string(), None),
+            ]
+        );
+        assert_eq!(claimed[0].filename(), "b.pdf");
+        assert!(folder.processing_path(&claimed[0]).exists());
+        assert!(folder.scan().is_empty());
+
+        folder.finish(&claimed[0], None).unwrap();
+        folder.finish(&claimed[1], Some("OCR failed")).unwrap();
+        assert!(dir.join("done/balancos/b.pdf").exists());
+        assert!(dir.join("failed/legal_br/2024/c.pdf").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("failed/legal_br/2024/c.pdf.error.txt")).unwrap(),
+            "OCR failed"
+        );
+
+        // A file the server can't take yet goes back to the inbox
+        folder.release(&claimed[2]).unwrap();
+        assert!(dir.join("top.pdf").exists());
+        let claimed = folder.scan();
+        assert_eq!(claimed.len(), 1);
+
+        // top.pdf was still processing when the "server" stopped
+        assert_eq!(folder.requeue_interrupted(), 1);
+        assert!(dir.join("top.pdf").exists());
+
+        // Unsettled files wait for the next poll
+        folder.settle = Duration::from_secs(3600);
+        assert!(folder.scan().is_empty());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
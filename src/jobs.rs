@@ -0,0 +1,545 @@
+//! On-disk journal of in-flight extraction jobs, used for crash recovery.
+//!
+//! Every extraction and sheet extraction writes a small record to `JOBS_DIR`
+//! (default `data/jobs/`) when it starts and removes it when the background
+//! task finishes, whatever the outcome. A record still present at startup
+//! therefore belongs to a job that was interrupted by a crash or restart.
+//! With Redis configured, records are also copied to the shared journal so
+//! another replica can take over the jobs of one that went away.
+//!
+//! [`RunningJobs`] tracks the jobs of the current process: a cancellation
+//! token per job and a bounded number of run slots (`MAX_CONCURRENT_JOBS`).
+//! Jobs waiting for a slot queue in one of three [`Priority`] lanes; freed
+//! slots go to the lanes by weight, so interactive uploads are not stuck
+//! behind a backfill while the backfill still makes progress.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
+
+use crate::ocr::OcrOptions;
+use crate::safe_name;
+use crate::schema::now_iso8601;
+use crate::shared::SharedState;
+
+/// What kind of job a record describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Extraction,
+    Dataset,
+}
+
+/// Which lane a job waits in for a run slot (`?priority=` on `POST /extract`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::High => "high",
+            Self::Normal => "normal",
+            Self::Low => "low",
+        }
+    }
+
+    fn is_normal(&self) -> bool {
+        *self == Self::Normal
+    }
+}
+
+/// Out of every ten slots handed out while all lanes wait, high gets six,
+/// normal three and low one.
+const LANE_CYCLE: [Priority; 10] = {
+    use Priority::*;
+    [
+        High, Normal, High, Low, High, Normal, High, High, Normal, High,
+    ]
+};
+
+/// The lanes in the order the `turn`-th slot handed out tries them: the
+/// lane [`LANE_CYCLE`] gives that turn, then the others from high to low.
+pub fn lane_order(turn: usize) -> [Priority; 3] {
+    let first = LANE_CYCLE[turn % LANE_CYCLE.len()];
+    let mut order = [first; 3];
+    for (slot, lane) in order[1..]
+        .iter_mut()
+        .zip(Priority::ALL.into_iter().filter(|p| *p != first))
+    {
+        *slot = lane;
+    }
+    order
+}
+
+/// Everything needed to re-run an interrupted job (except the file bytes).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    pub kind: JobKind,
+    pub source_file: String,
+    pub config_name: String,
+    #[serde(default)]
+    pub ocr_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_options: Option<OcrOptions>,
+    /// Set when the input was fetched from a URL (it can be fetched again).
+    #[serde(default)]
+    pub file_url: Option<String>,
+    pub upload: bool,
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Structure prompt sent with the request in place of the config's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_override: Option<String>,
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+    /// Extraction and node an exhibit re-extraction was started from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_extraction_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_node_id: Option<String>,
+    pub started_at: String,
+}
+
+/// How startup recovery handled a stale job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    /// Re-run from the archived OCR output (LLM stage only).
+    RequeuedFromOcrCache,
+    /// Re-run from the archived source file.
+    RequeuedFromSource,
+    /// Re-run by downloading `file_url` again.
+    RequeuedFromUrl,
+    /// Taken over from a stopped replica and put back on the shared queue
+    /// (`JOB_QUEUE=redis`), for whichever replica has a free slot.
+    Requeued,
+    /// Nothing to resume from; marked as failed.
+    MarkedFailed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveredJob {
+    pub id: String,
+    pub kind: JobKind,
+    pub source_file: String,
+    pub started_at: String,
+    pub action: RecoveryAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Result of the startup scan, served by `GET /admin/recovery`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RecoveryReport {
+    pub ran_at: Option<String>,
+    pub jobs: Vec<RecoveredJob>,
+}
+
+impl RecoveryReport {
+    pub fn new(jobs: Vec<RecoveredJob>) -> Self {
+        Self {
+            ran_at: Some(now_iso8601()),
+            jobs,
+        }
+    }
+}
+
+/// Directory of job records, one JSON file per running job.
+pub struct JobJournal {
+    dir: PathBuf,
+    shared: Option<Arc<SharedState>>,
+}
+
+impl JobJournal {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, shared: None })
+    }
+
+    /// Copy records to the shared journal in Redis as well.
+    pub fn with_shared(mut self, shared: Option<Arc<SharedState>>) -> Self {
+        self.shared = shared;
+        self
+    }
+
+    /// Open the journal in `JOBS_DIR` (default `data/jobs`).
+    pub fn from_env() -> Result<Self> {
+        Self::open(std::env::var("JOBS_DIR").unwrap_or_else(|_| "data/jobs".to_string()))
+    }
+
+    /// Record a job as running. Failures are logged: the job still runs, it
+    /// just cannot be recovered after a crash.
+    pub fn start(&self, record: &JobRecord) {
+        let result = serde_json::to_vec_pretty(record)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(std::fs::write(self.path_for(&record.id), bytes)?));
+        if let Err(e) = result {
+            error!("Failed to journal job {}: {}", record.id, e);
+        }
+        if let Some(ref shared) = self.shared {
+            shared.job_started(record);
+        }
+    }
+
+    /// Remove a job's record once its background task has finished.
+    pub fn finish(&self, id: &str) {
+        let path = self.path_for(id);
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove job record {}: {}", path.display(), e);
+            }
+        }
+        if let Some(ref shared) = self.shared {
+            shared.job_finished(id);
+        }
+    }
+
+    /// The record of a running job, if it has one.
+    pub fn get(&self, id: &str) -> Option<JobRecord> {
+        read_record(&self.path_for(id)).ok()
+    }
+
+    /// All records left in the journal, oldest first.
+    pub fn stale(&self) -> Vec<JobRecord> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read jobs dir {}: {}", self.dir.display(), e);
+                return Vec::new();
+            }
+        };
+
+        let mut records: Vec<JobRecord> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .filter_map(|path| match read_record(&path) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    error!("Skipping unreadable job record {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        records.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        records
+    }
+
+    fn path_for(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", safe_name::file_name(id)))
+    }
+}
+
+fn read_record(path: &Path) -> Result<JobRecord> {
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+/// Default number of extractions allowed to run at once.
+const DEFAULT_MAX_CONCURRENT_JOBS: usize = 4;
+
+/// Free run slots and the jobs waiting for one, by lane.
+#[derive(Default)]
+struct Lanes {
+    free: usize,
+    waiting: [VecDeque<oneshot::Sender<()>>; 3],
+    /// Slots handed to waiters so far, the position in [`LANE_CYCLE`]
+    turn: usize,
+}
+
+impl Lanes {
+    /// Give a released slot to the next waiter, or keep it free.
+    fn release(&mut self) {
+        loop {
+            let next = lane_order(self.turn)
+                .into_iter()
+                .find_map(|lane| self.waiting[lane as usize].pop_front());
+            let Some(waiter) = next else {
+                self.free += 1;
+                return;
+            };
+            // A waiter that was cancelled meanwhile can't take it
+            if waiter.send(()).is_ok() {
+                self.turn += 1;
+                return;
+            }
+        }
+    }
+}
+
+/// A run slot, released when dropped.
+pub struct SlotPermit {
+    lanes: Arc<Mutex<Lanes>>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        self.lanes.lock().unwrap().release();
+    }
+}
+
+/// A job waiting in a lane. Dropping it (the job was cancelled) passes on
+/// a slot that was handed to it in the meantime.
+struct Waiter {
+    granted: oneshot::Receiver<()>,
+    lanes: Arc<Mutex<Lanes>>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        self.granted.close();
+        if self.granted.try_recv().is_ok() {
+            self.lanes.lock().unwrap().release();
+        }
+    }
+}
+
+/// Jobs waiting for a run slot in each lane.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LaneCounts {
+    pub high: usize,
+    pub normal: usize,
+    pub low: usize,
+}
+
+/// Cancellation tokens and run slots for the jobs of this process.
+pub struct RunningJobs {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+    lanes: Arc<Mutex<Lanes>>,
+    max_concurrent: usize,
+}
+
+impl RunningJobs {
+    pub fn new(max_concurrent: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            tokens: Mutex::new(HashMap::new()),
+            lanes: Arc::new(Mutex::new(Lanes {
+                free: max_concurrent,
+                ..Default::default()
+            })),
+            max_concurrent,
+        }
+    }
+
+    /// Read `MAX_CONCURRENT_JOBS` (default 4).
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("MAX_CONCURRENT_JOBS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_JOBS),
+        )
+    }
+
+    /// Register a job and return the token that cancels it.
+    pub fn register(&self, id: &str) -> CancellationToken {
+        let token = CancellationToken::new();
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), token.clone());
+        token
+    }
+
+    /// Cancel a registered job. Returns `false` if it is not running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget a job once its task has ended.
+    pub fn finish(&self, id: &str) {
+        self.tokens.lock().unwrap().remove(id);
+    }
+
+    /// IDs of the registered jobs, sorted.
+    pub fn running(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.tokens.lock().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Whether every slot is taken or promised to a registered job, so
+    /// another job would have to wait.
+    pub fn is_full(&self) -> bool {
+        self.tokens.lock().unwrap().len() >= self.max_concurrent
+    }
+
+    /// Run slots not currently held by a job.
+    pub fn free_slots(&self) -> usize {
+        self.lanes.lock().unwrap().free
+    }
+
+    /// Jobs waiting for a slot, by lane.
+    pub fn waiting(&self) -> LaneCounts {
+        let lanes = self.lanes.lock().unwrap();
+        let count = |lane: Priority| {
+            lanes.waiting[lane as usize]
+                .iter()
+                .filter(|w| !w.is_closed())
+                .count()
+        };
+        LaneCounts {
+            high: count(Priority::High),
+            normal: count(Priority::Normal),
+            low: count(Priority::Low),
+        }
+    }
+
+    /// Wait for a free run slot in the `priority` lane; the slot is
+    /// released when the permit drops.
+    pub async fn acquire_lane(&self, priority: Priority) -> SlotPermit {
+        let permit = || SlotPermit {
+            lanes: self.lanes.clone(),
+        };
+        let mut waiter = {
+            let mut lanes = self.lanes.lock().unwrap();
+            if lanes.free > 0 {
+                lanes.free -= 1;
+                return permit();
+            }
+            let (tx, rx) = oneshot::channel();
+            lanes.waiting[priority as usize].push_back(tx);
+            Waiter {
+                granted: rx,
+                lanes: self.lanes.clone(),
+            }
+        };
+        (&mut waiter.granted)
+            .await
+            .expect("the lanes outlive their waiters");
+        permit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_lists_unfinished_jobs() {
+        let dir = std::env::temp_dir().join(format!("jobs_test_{}", uuid::Uuid::new_v4().simple()));
+        let journal = JobJournal::open(&dir).unwrap();
+
+        let record = |id: &str, started_at: &str| JobRecord {
+            id: id.to_string(),
+            kind: JobKind::Extraction,
+            source_file: "doc.pdf".into(),
+            config_name: "legal_br".into(),
+            ocr_provider: Some("docling".into()),
+            ocr_options: None,
+            prompt_override: None,
+            priority: Priority::Normal,
+            file_url: None,
+            upload: true,
+            callback_url: None,
+            parent_extraction_id: None,
+            parent_node_id: None,
+            started_at: started_at.to_string(),
+        };
+        journal.start(&record("ext_b", "2026-01-02T00:00:00Z"));
+        journal.start(&record("ext_a", "2026-01-01T00:00:00Z"));
+        journal.start(&record("ext_done", "2026-01-03T00:00:00Z"));
+        journal.finish("ext_done");
+
+        // A reopened journal (as after a restart) sees the unfinished jobs, oldest first.
+        let ids: Vec<String> = JobJournal::open(&dir)
+            .unwrap()
+            .stale()
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec!["ext_a", "ext_b"]);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_releases_slot() {
+        let running = RunningJobs::new(1);
+        let token = running.register("ext_a");
+
+        let slot = running.acquire_lane(Priority::Normal).await;
+        assert!(running.cancel("ext_a"));
+        assert!(token.is_cancelled());
+        assert!(!running.cancel("ext_unknown"));
+
+        // Dropping the cancelled job's permit frees the slot for the next job.
+        drop(slot);
+        running.finish("ext_a");
+        let _next = running.acquire_lane(Priority::Normal).await;
+        assert!(!running.cancel("ext_a"));
+    }
+
+    #[test]
+    fn test_lane_weights() {
+        let mut first = HashMap::new();
+        for turn in 0..LANE_CYCLE.len() {
+            *first.entry(lane_order(turn)[0]).or_insert(0) += 1;
+        }
+        assert_eq!(first[&Priority::High], 6);
+        assert_eq!(first[&Priority::Normal], 3);
+        assert_eq!(first[&Priority::Low], 1);
+        assert_eq!(
+            lane_order(3),
+            [Priority::Low, Priority::High, Priority::Normal]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_waiting_jobs_get_slots_by_lane() {
+        let running = Arc::new(RunningJobs::new(1));
+        let slot = running.acquire_lane(Priority::Normal).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for lane in [Priority::Low, Priority::Normal, Priority::High] {
+            let (running, order) = (running.clone(), order.clone());
+            tasks.push(tokio::spawn(async move {
+                let _slot = running.acquire_lane(lane).await;
+                order.lock().unwrap().push(lane);
+            }));
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(running.waiting().low, 1);
+        assert_eq!(running.waiting().high, 1);
+
+        // A cancelled waiter does not hold up the others
+        let cancelled = {
+            let running = running.clone();
+            tokio::spawn(async move { running.acquire_lane(Priority::High).await })
+        };
+        tokio::task::yield_now().await;
+        cancelled.abort();
+        let _ = cancelled.await;
+
+        drop(slot);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec![Priority::High, Priority::Normal, Priority::Low]
+        );
+        assert_eq!(running.free_slots(), 1);
+    }
+}
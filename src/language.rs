@@ -0,0 +1,130 @@
+//! Document language detection and names for prompts.
+//!
+//! Languages are ISO 639-1 codes (`pt`, `en`, ...). Detection counts common
+//! function words of each supported language in the OCR text; it needs no
+//! model and is reliable for anything longer than a paragraph.
+
+/// Only the start of the document is scanned.
+const SAMPLE_CHARS: usize = 20_000;
+
+/// Fewer stopword hits than this means "not enough text to tell".
+const MIN_HITS: usize = 10;
+
+/// (code, English name, common function words)
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    (
+        "pt",
+        "Portuguese",
+        &[
+            "de", "que", "não", "da", "do", "em", "para", "com", "uma", "os", "no", "na", "se",
+            "por", "mais", "dos", "das", "ao", "à", "seu", "sua", "ou", "foi", "são", "pelo",
+            "pela", "também", "já", "nos", "este", "esta",
+        ],
+    ),
+    (
+        "en",
+        "English",
+        &[
+            "the", "of", "and", "to", "in", "is", "that", "for", "it", "with", "as", "was", "on",
+            "be", "by", "this", "are", "from", "at", "or", "an", "which", "have", "not", "were",
+            "shall", "has",
+        ],
+    ),
+    (
+        "es",
+        "Spanish",
+        &[
+            "el", "la", "de", "que", "y", "en", "los", "del", "las", "por", "un", "para", "con",
+            "no", "una", "su", "al", "lo", "como", "más", "pero", "sus", "le", "es", "fue", "este",
+        ],
+    ),
+    (
+        "fr",
+        "French",
+        &[
+            "le", "la", "les", "de", "des", "et", "en", "un", "une", "du", "que", "est", "pour",
+            "qui", "dans", "pas", "au", "sur", "par", "avec", "ce", "il", "sont", "aux",
+        ],
+    ),
+    (
+        "it",
+        "Italian",
+        &[
+            "il", "di", "che", "la", "e", "per", "un", "una", "del", "della", "in", "non", "con",
+            "sono", "gli", "le", "si", "da", "al", "dei", "è", "anche", "nel", "alla",
+        ],
+    ),
+    (
+        "de",
+        "German",
+        &[
+            "der", "die", "und", "in", "den", "von", "zu", "das", "mit", "sich", "des", "auf",
+            "für", "ist", "im", "dem", "nicht", "ein", "eine", "als", "auch", "es", "an", "werden",
+            "wird",
+        ],
+    ),
+];
+
+/// English name of a language code for use in prompts (the code itself if unknown).
+pub fn name(code: &str) -> &str {
+    LANGUAGES
+        .iter()
+        .find(|(c, _, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, name, _)| *name)
+        .unwrap_or(code)
+}
+
+/// Detect the language of `text`, or `None` if it is too short or ambiguous.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let sample = match text.char_indices().nth(SAMPLE_CHARS) {
+        Some((i, _)) => &text[..i],
+        None => text,
+    };
+    let tokens: Vec<String> = sample
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut scores: Vec<(&'static str, usize)> = LANGUAGES
+        .iter()
+        .map(|(code, _, words)| {
+            let hits = tokens
+                .iter()
+                .filter(|t| words.contains(&t.as_str()))
+                .count();
+            (*code, hits)
+        })
+        .collect();
+    scores.sort_by_key(|(_, hits)| std::cmp::Reverse(*hits));
+
+    let (best, hits) = scores[0];
+    if hits < MIN_HITS || scores[1].1 == hits {
+        return None;
+    }
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let pt = "O autor adquiriu passagem para o voo AD2602, que foi cancelado sem aviso \
+                  prévio. Requer a condenação da ré ao pagamento de danos morais, pois a \
+                  companhia não prestou assistência e os prejuízos são evidentes nos autos.";
+        let en = "The plaintiff purchased a ticket for flight AD2602, which was cancelled \
+                  without notice. The defendant shall pay damages, as the airline did not \
+                  provide assistance and the losses are evident from the record of the case.";
+        let es = "El demandante compró un billete para el vuelo AD2602, que fue cancelado sin \
+                  aviso. Solicita la condena de la demandada al pago de los daños, pues la \
+                  compañía no prestó asistencia y los perjuicios son evidentes en el expediente.";
+        assert_eq!(detect(pt), Some("pt"));
+        assert_eq!(detect(en), Some("en"));
+        assert_eq!(detect(es), Some("es"));
+        assert_eq!(detect("Processo 0001234-56.2024.8.26.0100"), None);
+        assert_eq!(name("PT"), "Portuguese");
+        assert_eq!(name("xx"), "xx");
+    }
+}
@@ -0,0 +1,94 @@
+//! Generic Extractor - Config-driven hierarchical document extraction.
+//!
+//! The pipeline can run in-process, without HTTP: OCR a document with an
+//! [`OcrProvider`], then build its node tree with [`Extractor`] (or a table
+//! dataset with [`SheetExtractor`]) under a config from [`ConfigStore`].
+//! [`Server`] serves the same pipeline as the REST API.
+//!
+//! ```no_run
+//! use generic_extractor::{ConfigStore, ContentStore, Extractor, OpenRouterClient};
+//!
+//! # async fn run(ocr: generic_extractor::OcrResult) -> anyhow::Result<()> {
+//! let configs = ConfigStore::load_from_dir(std::path::Path::new("configs"))?;
+//! let config = configs.get("legal_br").expect("legal_br config");
+//! let extractor = Extractor::new(OpenRouterClient::from_env()?, ContentStore::new());
+//! let extraction = extractor.structure("autos.pdf", &ocr, &config).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod admin;
+mod api_error;
+mod compression;
+mod confidence;
+pub mod config;
+mod config_history;
+mod config_lint;
+pub mod content_store;
+mod content_summary;
+mod dataset_append;
+mod dataset_edit;
+mod dataset_query;
+mod dedup;
+mod entities;
+mod estimate;
+mod events;
+mod eval;
+mod experiment;
+pub mod extractor;
+mod failures;
+mod folios;
+mod gce;
+mod gcp_auth;
+mod graph;
+mod http_cache;
+mod ingest;
+mod jobs;
+mod language;
+mod live;
+mod mail;
+mod metadata;
+mod numeric;
+pub mod object_store;
+pub mod ocr;
+pub mod openrouter;
+mod page_image;
+mod partes;
+pub mod pipeline;
+mod prompt;
+mod quotas;
+mod readable_id;
+mod redaction;
+mod relationships;
+mod review;
+mod safe_name;
+mod scheduler;
+pub mod schema;
+pub mod sheet_extractor;
+mod sheet_parser;
+pub mod sheet_schema;
+mod server;
+mod shared;
+mod signatures;
+mod sinks;
+mod sparse;
+pub mod storage;
+mod supabase;
+mod sync;
+mod timeline;
+mod toc;
+mod tokens;
+mod training;
+mod upload;
+mod xlsx_formats;
+
+pub use api_error::ApiError;
+pub use config::{ConfigStore, ExtractionConfig};
+pub use content_store::ContentStore;
+pub use extractor::Extractor;
+pub use ocr::{OcrInput, OcrPage, OcrProvider, OcrProviderKind, OcrResult};
+pub use openrouter::OpenRouterClient;
+pub use schema::{DocumentNode, Extraction, ExtractionStatus, Relationship};
+pub use server::{Server, ServerBuilder};
+pub use sheet_extractor::SheetExtractor;
+pub use sheet_schema::SheetExtraction;
@@ -0,0 +1,160 @@
+//! In-memory extractions and datasets.
+//!
+//! A [`LiveMap`] is sharded ([`DashMap`]), so a write to one extraction
+//! only blocks readers of the same shard, and no lock is held across an
+//! `.await`. Each entry keeps a small summary next to the full value,
+//! refreshed on every write, so listings read summaries without cloning
+//! node trees.
+//!
+//! Closures passed to [`LiveMap::with`], [`LiveMap::update`],
+//! [`LiveMap::scan`] and [`LiveMap::retain`] run under a shard lock and
+//! must not touch the same map.
+//!
+//! An observer set with [`LiveMap::observe`] sees every write, with `None`
+//! for removals; the server uses it to mirror the map to Redis.
+
+use dashmap::DashMap;
+
+struct Entry<T, S> {
+    value: T,
+    summary: S,
+}
+
+type Observer<T> = Box<dyn Fn(&str, Option<&T>) + Send + Sync>;
+
+pub struct LiveMap<T, S> {
+    entries: DashMap<String, Entry<T, S>>,
+    summarize: fn(&T) -> S,
+    observer: Option<Observer<T>>,
+}
+
+impl<T: Clone, S: Clone> LiveMap<T, S> {
+    pub fn new(summarize: fn(&T) -> S) -> Self {
+        Self {
+            entries: DashMap::new(),
+            summarize,
+            observer: None,
+        }
+    }
+
+    /// Call `f` after every insert and update, and with `None` after every
+    /// removal. It runs under the entry's shard lock, on every write, so it
+    /// should only note the change and leave the heavy work for later.
+    pub fn observe(mut self, f: impl Fn(&str, Option<&T>) + Send + Sync + 'static) -> Self {
+        self.observer = Some(Box::new(f));
+        self
+    }
+
+    fn notify(&self, id: &str, value: Option<&T>) {
+        if let Some(ref observer) = self.observer {
+            observer(id, value);
+        }
+    }
+
+    pub fn insert(&self, id: String, value: T) {
+        let summary = (self.summarize)(&value);
+        self.notify(&id, Some(&value));
+        self.entries.insert(id, Entry { value, summary });
+    }
+
+    /// Insert without notifying the observer, for a value read back from
+    /// wherever the observer copies to.
+    pub fn refresh(&self, id: String, value: T) {
+        let summary = (self.summarize)(&value);
+        self.entries.insert(id, Entry { value, summary });
+    }
+
+    pub fn get(&self, id: &str) -> Option<T> {
+        self.entries.get(id).map(|entry| entry.value.clone())
+    }
+
+    /// Read the value in place, without cloning it.
+    pub fn with<R>(&self, id: &str, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.entries.get(id).map(|entry| f(&entry.value))
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn remove(&self, id: &str) -> Option<T> {
+        let removed = self.entries.remove(id).map(|(_, entry)| entry.value);
+        if removed.is_some() {
+            self.notify(id, None);
+        }
+        removed
+    }
+
+    /// Remove without notifying the observer, for a value whose copy the
+    /// observer writes to now belongs to someone else.
+    pub fn forget(&self, id: &str) -> Option<T> {
+        self.entries.remove(id).map(|(_, entry)| entry.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Apply `f` to the value, if it is there, and refresh its summary.
+    pub fn update<R>(&self, id: &str, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut entry = self.entries.get_mut(id)?;
+        let result = f(&mut entry.value);
+        entry.summary = (self.summarize)(&entry.value);
+        self.notify(id, Some(&entry.value));
+        Some(result)
+    }
+
+    /// Map every value through `f`, keeping the `Some`s.
+    pub fn scan<R>(&self, mut f: impl FnMut(&T) -> Option<R>) -> Vec<R> {
+        self.entries
+            .iter()
+            .filter_map(|entry| f(&entry.value))
+            .collect()
+    }
+
+    pub fn any(&self, mut f: impl FnMut(&T) -> bool) -> bool {
+        self.entries.iter().any(|entry| f(&entry.value))
+    }
+
+    /// Summaries of every value.
+    pub fn summaries(&self) -> Vec<S> {
+        self.entries
+            .iter()
+            .map(|entry| entry.summary.clone())
+            .collect()
+    }
+
+    pub fn retain(&self, mut f: impl FnMut(&str, &T) -> bool) {
+        self.entries.retain(|id, entry| {
+            let keep = f(id, &entry.value);
+            if !keep {
+                self.notify(id, None);
+            }
+            keep
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summaries_follow_updates() {
+        let map: LiveMap<Vec<u32>, usize> = LiveMap::new(Vec::len);
+        map.insert("a".into(), vec![1]);
+        map.insert("b".into(), vec![1, 2]);
+        assert_eq!(map.update("a", |v| v.push(3)), Some(()));
+        assert_eq!(map.update("missing", |v| v.push(3)), None);
+
+        let mut summaries = map.summaries();
+        summaries.sort();
+        assert_eq!(summaries, vec![2, 2]);
+        assert_eq!(map.scan(|v| v.contains(&3).then_some(v[0])), vec![1]);
+
+        map.retain(|id, _| id != "b");
+        assert!(!map.contains("b"));
+        assert_eq!(map.remove("a"), Some(vec![1, 3]));
+        assert_eq!(map.len(), 0);
+    }
+}
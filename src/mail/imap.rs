@@ -0,0 +1,213 @@
+//! Minimal IMAP4rev1 client: the handful of commands the mail connector needs.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls;
+
+/// Lines and literals of one command's response.
+struct Response {
+    lines: Vec<String>,
+    literals: Vec<Vec<u8>>,
+}
+
+pub struct ImapSession<S> {
+    stream: BufReader<S>,
+    next_tag: u32,
+}
+
+/// Open an IMAPS (implicit TLS) session.
+pub async fn connect(
+    host: &str,
+    port: u16,
+) -> Result<ImapSession<tokio_rustls::client::TlsStream<TcpStream>>> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = rustls::ServerName::try_from(host)
+        .map_err(|_| anyhow!("Invalid IMAP host name: {}", host))?;
+
+    let tcp = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+    let tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, tcp)
+        .await
+        .context("IMAP TLS handshake failed")?;
+    ImapSession::new(tls).await
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ImapSession<S> {
+    /// Start a session on a connected stream (reads the server greeting).
+    pub async fn new(stream: S) -> Result<Self> {
+        let mut session = Self {
+            stream: BufReader::new(stream),
+            next_tag: 1,
+        };
+        let greeting = session.read_line().await?;
+        if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+            bail!("Unexpected IMAP greeting: {}", greeting.trim());
+        }
+        Ok(session)
+    }
+
+    pub async fn login(&mut self, user: &str, password: &str) -> Result<()> {
+        self.command(&format!("LOGIN {} {}", quote(user), quote(password)))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn select(&mut self, mailbox: &str) -> Result<()> {
+        self.command(&format!("SELECT {}", quote(mailbox)))
+            .await
+            .map(|_| ())
+    }
+
+    /// UIDs of messages without the `\Seen` flag.
+    pub async fn search_unseen(&mut self) -> Result<Vec<u32>> {
+        let response = self.command("UID SEARCH UNSEEN").await?;
+        Ok(response
+            .lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace().filter_map(|uid| uid.parse().ok()))
+            .collect())
+    }
+
+    /// The raw message, without setting `\Seen`.
+    pub async fn fetch(&mut self, uid: u32) -> Result<Vec<u8>> {
+        let response = self
+            .command(&format!("UID FETCH {} BODY.PEEK[]", uid))
+            .await?;
+        response
+            .literals
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("IMAP server returned no body for UID {}", uid))
+    }
+
+    pub async fn mark_seen(&mut self, uid: u32) -> Result<()> {
+        self.command(&format!("UID STORE {} +FLAGS (\\Seen)", uid))
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn logout(&mut self) -> Result<()> {
+        self.command("LOGOUT").await.map(|_| ())
+    }
+
+    /// Send a tagged command and collect its response up to the tagged status.
+    async fn command(&mut self, command: &str) -> Result<Response> {
+        let tag = format!("A{}", self.next_tag);
+        self.next_tag += 1;
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await?;
+        stream.flush().await?;
+
+        let verb = command.split(' ').take(2).collect::<Vec<_>>().join(" ");
+        let mut response = Response {
+            lines: Vec::new(),
+            literals: Vec::new(),
+        };
+        loop {
+            let mut line = self.read_line().await?;
+            // `{N}` at the end of a line announces N bytes of literal data
+            while let Some(size) = literal_size(&line) {
+                let mut literal = vec![0; size];
+                self.stream.read_exact(&mut literal).await?;
+                response.literals.push(literal);
+                line.push_str(&self.read_line().await?);
+            }
+            if let Some(status) = line.strip_prefix(&format!("{} ", tag)) {
+                if status.starts_with("OK") {
+                    return Ok(response);
+                }
+                // Never echo LOGIN arguments (the password) into errors
+                bail!("IMAP {} failed: {}", verb, status.trim());
+            }
+            response.lines.push(line);
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String> {
+        let mut line = Vec::new();
+        if self.stream.read_until(b'\n', &mut line).await? == 0 {
+            bail!("IMAP server closed the connection");
+        }
+        Ok(String::from_utf8_lossy(&line).to_string())
+    }
+}
+
+fn literal_size(line: &str) -> Option<usize> {
+    let line = line.trim_end();
+    let start = line.rfind('{')?;
+    line.strip_suffix('}')?[start + 1..].parse().ok()
+}
+
+/// An IMAP quoted string.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_imap_session() {
+        let (client, mut server) = tokio::io::duplex(64 * 1024);
+        let message = "From: a@b.c\r\nSubject: hi\r\n\r\nbody\r\n";
+        server
+            .write_all(
+                format!(
+                    "* OK IMAP4rev1 ready\r\n\
+                     A1 OK LOGIN completed\r\n\
+                     * 3 EXISTS\r\n\
+                     A2 OK [READ-WRITE] SELECT completed\r\n\
+                     * SEARCH 7 9\r\n\
+                     A3 OK SEARCH completed\r\n\
+                     * 2 FETCH (UID 7 BODY[] {{{}}}\r\n{})\r\n\
+                     A4 OK FETCH completed\r\n\
+                     A5 NO [CANNOT] flags are read-only\r\n",
+                    message.len(),
+                    message
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let mut session = ImapSession::new(client).await.unwrap();
+        session.login("user", "pa\"ss").await.unwrap();
+        session.select("INBOX").await.unwrap();
+        assert_eq!(session.search_unseen().await.unwrap(), vec![7, 9]);
+        assert_eq!(session.fetch(7).await.unwrap(), message.as_bytes());
+        let err = session.mark_seen(7).await.unwrap_err().to_string();
+        assert!(err.contains("UID STORE failed"), "{}", err);
+        drop(session);
+
+        let mut sent = String::new();
+        server.read_to_string(&mut sent).await.unwrap();
+        assert_eq!(
+            sent,
+            "A1 LOGIN \"user\" \"pa\\\"ss\"\r\n\
+             A2 SELECT \"INBOX\"\r\n\
+             A3 UID SEARCH UNSEEN\r\n\
+             A4 UID FETCH 7 BODY.PEEK[]\r\n\
+             A5 UID STORE 7 +FLAGS (\\Seen)\r\n"
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Just enough MIME to pull attachments out of a message.
+//!
+//! Handles nested multiparts, base64 and quoted-printable bodies, RFC 2047
+//! encoded words in headers (UTF-8 and Latin-1), and RFC 2231 `filename*`.
+
+use base64::Engine;
+use std::collections::HashMap;
+
+/// A parsed message: the headers rules match on, and its attachments.
+#[derive(Debug, Default)]
+pub struct Message {
+    /// Sender address, without the display name
+    pub from: String,
+    pub subject: String,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Parse a raw RFC 5322 message.
+pub fn parse(raw: &[u8]) -> Message {
+    let (headers, body) = split_part(raw);
+    let from = headers
+        .get("from")
+        .map(|v| decode_words(v))
+        .unwrap_or_default();
+    let mut message = Message {
+        from: address(&from),
+        subject: headers
+            .get("subject")
+            .map(|v| decode_words(v))
+            .unwrap_or_default(),
+        attachments: Vec::new(),
+    };
+    collect_attachments(&headers, body, &mut message.attachments);
+    message
+}
+
+/// The address in `Name <addr>`, or the whole value.
+fn address(from: &str) -> String {
+    match (from.rfind('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => from[start + 1..end].trim().to_string(),
+        _ => from.trim().to_string(),
+    }
+}
+
+fn collect_attachments(headers: &HashMap<String, String>, body: &[u8], out: &mut Vec<Attachment>) {
+    let (content_type, type_params) = headers
+        .get("content-type")
+        .map(|v| header_params(v))
+        .unwrap_or_else(|| ("text/plain".to_string(), HashMap::new()));
+
+    if content_type.starts_with("multipart/") {
+        let Some(boundary) = type_params.get("boundary") else {
+            return;
+        };
+        for part in split_multipart(body, boundary) {
+            let (headers, body) = split_part(part);
+            collect_attachments(&headers, body, out);
+        }
+        return;
+    }
+
+    // Any leaf part with a file name counts, including inline ones
+    let (_, disposition_params) = headers
+        .get("content-disposition")
+        .map(|v| header_params(v))
+        .unwrap_or_default();
+    let Some(filename) = disposition_params
+        .get("filename")
+        .or_else(|| type_params.get("name"))
+        .map(|name| decode_words(name))
+    else {
+        return;
+    };
+
+    let encoding = headers
+        .get("content-transfer-encoding")
+        .map(|v| v.trim().to_lowercase())
+        .unwrap_or_default();
+    let data = match encoding.as_str() {
+        "base64" => {
+            let compact: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            match base64::engine::general_purpose::STANDARD.decode(compact) {
+                Ok(data) => data,
+                Err(_) => return,
+            }
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    };
+    out.push(Attachment {
+        filename,
+        content_type,
+        data,
+    });
+}
+
+/// Split a part into its (unfolded, lowercase-keyed) headers and body.
+fn split_part(raw: &[u8]) -> (HashMap<String, String>, &[u8]) {
+    let (head, body) = match find(raw, b"\r\n\r\n") {
+        Some(i) => (&raw[..i], &raw[i + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(i) => (&raw[..i], &raw[i + 2..]),
+            None => (raw, &raw[raw.len()..]),
+        },
+    };
+
+    let mut headers = HashMap::new();
+    let mut current: Option<(String, String)> = None;
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = current.as_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = current.take() {
+            headers.entry(name).or_insert(value);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            current = Some((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    if let Some((name, value)) = current {
+        headers.entry(name).or_insert(value);
+    }
+    (headers, body)
+}
+
+/// The parts between `--boundary` delimiters.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut rest = body;
+    let Some(start) = find(rest, delimiter.as_bytes()) else {
+        return parts;
+    };
+    rest = &rest[start + delimiter.len()..];
+    while !rest.starts_with(b"--") {
+        // Skip the rest of the delimiter line
+        let Some(eol) = find(rest, b"\n") else {
+            break;
+        };
+        rest = &rest[eol + 1..];
+        let end = find(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let part = &rest[..end];
+        let part = part
+            .strip_suffix(b"\r\n")
+            .or_else(|| part.strip_suffix(b"\n"))
+            .unwrap_or(part);
+        parts.push(part);
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+/// `type/subtype; key=value; ...` as the lowercase value and its parameters.
+fn header_params(value: &str) -> (String, HashMap<String, String>) {
+    let mut segments = split_params(value).into_iter();
+    let main = segments.next().unwrap_or_default().trim().to_lowercase();
+    let mut params = HashMap::new();
+    for segment in segments {
+        let Some((key, value)) = segment.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().trim_matches('"').to_string();
+        match key.strip_suffix('*') {
+            // RFC 2231: charset'language'percent-encoded
+            Some(key) => {
+                let encoded = value.splitn(3, '\'').last().unwrap_or_default();
+                params.insert(key.to_string(), percent_decode(encoded));
+            }
+            None => {
+                params.entry(key).or_insert(value);
+            }
+        }
+    }
+    (main, params)
+}
+
+/// Split on `;` outside quoted strings.
+fn split_params(value: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quoted = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                segments.last_mut().unwrap().push(c);
+            }
+            ';' if !quoted => segments.push(String::new()),
+            _ => segments.last_mut().unwrap().push(c),
+        }
+    }
+    segments
+}
+
+/// Decode RFC 2047 encoded words (`=?utf-8?B?...?=`) in a header value.
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let before = &rest[..start];
+        // Whitespace between two encoded words is dropped
+        if !(after_word && before.trim().is_empty()) {
+            out.push_str(before);
+        }
+        let word = &rest[start + 2..];
+        let decoded = (|| {
+            // charset?encoding?text?= (the text itself may start with `=`)
+            let mut fields = word.splitn(3, '?');
+            let (charset, encoding, tail) = (fields.next()?, fields.next()?, fields.next()?);
+            let text = &tail[..tail.find("?=")?];
+            let end = word.len() - tail.len() + text.len();
+            let bytes = match encoding.to_ascii_uppercase().as_str() {
+                "B" => base64::engine::general_purpose::STANDARD
+                    .decode(text)
+                    .ok()?,
+                "Q" => decode_quoted_printable(text.as_bytes(), true),
+                _ => return None,
+            };
+            Some((decode_charset(charset, &bytes), end))
+        })();
+        match decoded {
+            Some((text, end)) => {
+                out.push_str(&text);
+                rest = &word[end + 2..];
+                after_word = true;
+            }
+            None => {
+                out.push_str("=?");
+                rest = word;
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    let charset = charset.to_ascii_lowercase();
+    if charset == "iso-8859-1" || charset == "latin1" || charset == "windows-1252" {
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+/// Quoted-printable; `header` also maps `_` to a space (RFC 2047 Q encoding).
+fn decode_quoted_printable(input: &[u8], header: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' if input[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if input[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < input.len() => {
+                match std::str::from_utf8(&input[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            b'_' if header => {
+                out.push(b' ');
+                i += 1;
+            }
+            byte => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn percent_decode(input: &str) -> String {
+    String::from_utf8_lossy(&decode_quoted_printable(
+        input.replace('%', "=").as_bytes(),
+        false,
+    ))
+    .to_string()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_attachments() {
+        let raw = b"From: =?UTF-8?Q?Jo=C3=A3o_Silva?= <joao@tribunal.jus.br>\r\n\
+Subject: =?utf-8?B?SW50aW1hw6fDo28=?= =?ISO-8859-1?Q?=E9_processo?= 123\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed;\r\n boundary=\"outer\"\r\n\
+\r\n\
+preamble\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+Segue em anexo.\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"autos.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"autos.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0x\r\n\
+LjQ=\r\n\
+--outer\r\n\
+Content-Type: application/vnd.ms-excel\r\n\
+Content-Disposition: attachment; filename*=UTF-8''balan%C3%A7o.csv\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+conta;valor=0A=\r\n\
+caixa;1.234,56\r\n\
+--outer--\r\n";
+
+        let message = parse(raw);
+        assert_eq!(message.from, "joao@tribunal.jus.br");
+        assert_eq!(message.subject, "Intimaçãoé processo 123");
+        assert_eq!(message.attachments.len(), 2);
+        assert_eq!(message.attachments[0].filename, "autos.pdf");
+        assert_eq!(message.attachments[0].content_type, "application/pdf");
+        assert_eq!(message.attachments[0].data, b"%PDF-1.4");
+        assert_eq!(message.attachments[1].filename, "balanço.csv");
+        assert_eq!(message.attachments[1].data, b"conta;valor\ncaixa;1.234,56");
+    }
+}
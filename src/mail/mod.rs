@@ -0,0 +1,184 @@
+//! IMAP mailbox ingestion.
+//!
+//! With `IMAP_HOST` set, the server polls `IMAP_MAILBOX` (default `INBOX`)
+//! every `IMAP_INTERVAL_SECS` (default 60) over IMAPS and extracts the PDF,
+//! Excel and CSV attachments of each unseen message. A message goes to the
+//! first config (by name) with a `mail_rules` entry matching its sender and
+//! subject, or to `IMAP_DEFAULT_CONFIG` when none match; with neither it is
+//! left alone. Results are POSTed to the rule's `callback_url`. Messages are
+//! marked `\Seen` once their attachments are queued, so each is handled once;
+//! a message refused by its config's quotas stays unseen and is retried on a
+//! later poll.
+
+pub mod imap;
+pub mod mime;
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use regex::RegexBuilder;
+use tracing::warn;
+
+use crate::config::{ExtractionConfig, MailRule};
+
+const DEFAULT_PORT: u16 = 993;
+const DEFAULT_MAILBOX: &str = "INBOX";
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+/// Connection settings for the polled mailbox.
+pub struct Mailbox {
+    host: String,
+    port: u16,
+    user: String,
+    password: String,
+    mailbox: String,
+    interval: Duration,
+    default_config: Option<String>,
+}
+
+impl Mailbox {
+    /// Read `IMAP_*`; `None` when `IMAP_HOST` is not set.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(host) = std::env::var("IMAP_HOST") else {
+            return Ok(None);
+        };
+        let port = match std::env::var("IMAP_PORT") {
+            Ok(port) => port.parse().context("Invalid IMAP_PORT")?,
+            Err(_) => DEFAULT_PORT,
+        };
+        Ok(Some(Self {
+            host,
+            port,
+            user: std::env::var("IMAP_USER").context("IMAP_USER must be set")?,
+            password: std::env::var("IMAP_PASSWORD").context("IMAP_PASSWORD must be set")?,
+            mailbox: std::env::var("IMAP_MAILBOX").unwrap_or_else(|_| DEFAULT_MAILBOX.to_string()),
+            interval: Duration::from_secs(
+                std::env::var("IMAP_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(DEFAULT_INTERVAL_SECS),
+            ),
+            default_config: std::env::var("IMAP_DEFAULT_CONFIG").ok(),
+        }))
+    }
+
+    /// `user@host/mailbox`, for logs.
+    pub fn describe(&self) -> String {
+        format!("{}@{}/{}", self.user, self.host, self.mailbox)
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Config for messages no rule matches (`IMAP_DEFAULT_CONFIG`).
+    pub fn default_config(&self) -> Option<&str> {
+        self.default_config.as_deref()
+    }
+
+    /// Fetch every unseen message, calling `handle` on each and marking it
+    /// seen when it returns `true`; the others are fetched again next poll.
+    pub async fn poll<F: Future<Output = bool>>(
+        &self,
+        mut handle: impl FnMut(mime::Message) -> F,
+    ) -> Result<usize> {
+        let mut session = imap::connect(&self.host, self.port).await?;
+        session.login(&self.user, &self.password).await?;
+        session.select(&self.mailbox).await?;
+        let uids = session.search_unseen().await?;
+        for &uid in &uids {
+            let raw = session.fetch(uid).await?;
+            if handle(mime::parse(&raw)).await {
+                session.mark_seen(uid).await?;
+            }
+        }
+        if let Err(e) = session.logout().await {
+            warn!("IMAP logout failed: {}", e);
+        }
+        Ok(uids.len())
+    }
+}
+
+/// The first config (by name) with a rule matching the message.
+pub fn route<'a>(
+    configs: &'a [ExtractionConfig],
+    message: &mime::Message,
+) -> Option<(&'a ExtractionConfig, &'a MailRule)> {
+    let mut configs: Vec<_> = configs.iter().collect();
+    configs.sort_by(|a, b| a.name.cmp(&b.name));
+    configs.into_iter().find_map(|config| {
+        config
+            .mail_rules
+            .iter()
+            .find(|rule| {
+                matches(&config.name, rule.from.as_deref(), &message.from)
+                    && matches(&config.name, rule.subject.as_deref(), &message.subject)
+            })
+            .map(|rule| (config, rule))
+    })
+}
+
+/// Whether `value` matches `pattern` (case-insensitive); an unset pattern always matches.
+fn matches(config_name: &str, pattern: Option<&str>, value: &str) -> bool {
+    let Some(pattern) = pattern else {
+        return true;
+    };
+    match RegexBuilder::new(pattern).case_insensitive(true).build() {
+        Ok(re) => re.is_match(value),
+        Err(e) => {
+            warn!("Invalid mail rule pattern in config {}: {}", config_name, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(name: &str, rules: serde_json::Value) -> ExtractionConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "description": "",
+            "prompts": {"structure": ""},
+            "mail_rules": rules,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_route() {
+        let configs = vec![
+            config(
+                "legal_br",
+                serde_json::json!([{"from": "@tribunal\\.jus\\.br$"}]),
+            ),
+            config(
+                "financial_br",
+                serde_json::json!([
+                    {"from": "[", "subject": ""},
+                    {"subject": "balan[cç]o", "callback_url": "http://hook"}
+                ]),
+            ),
+            config("other", serde_json::json!([])),
+        ];
+        let message = |from: &str, subject: &str| mime::Message {
+            from: from.to_string(),
+            subject: subject.to_string(),
+            attachments: Vec::new(),
+        };
+
+        let (config, rule) = route(&configs, &message("x@TRIBUNAL.jus.br", "Intimação")).unwrap();
+        assert_eq!(config.name, "legal_br");
+        assert!(rule.callback_url.is_none());
+
+        // Configs are tried by name, and an invalid pattern never matches
+        let (config, rule) =
+            route(&configs, &message("x@tribunal.jus.br", "Balanço 2024")).unwrap();
+        assert_eq!(config.name, "financial_br");
+        assert_eq!(rule.callback_url.as_deref(), Some("http://hook"));
+
+        assert!(route(&configs, &message("a@b.com", "hello")).is_none());
+    }
+}
@@ -0,0 +1,377 @@
+//! Validation of LLM-returned metadata against the config's schemas.
+//!
+//! The extraction's `metadata` is checked against `config.metadata_schema`,
+//! and each node's against the `metadata_schema` of its node type. Schemas
+//! may be full JSON Schemas or, like the shipped configs, a bare map of
+//! property → schema. Before validating, simple mismatches are coerced in
+//! place: numbers written as strings (`"R$ 1.234,56"`), numbers or booleans
+//! where strings are expected, yes/no strings for booleans, a single value
+//! where an array is expected, enum values in another case or without
+//! accents, and `null` for optional fields (dropped). What still fails is
+//! recorded under `metadata._validation` as `{"coerced": [paths], "errors":
+//! [{"path", "message"}]}`. Keys starting with `_` (added by later stages)
+//! are not validated.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::config::ExtractionConfig;
+use crate::numeric::{self, NumberLocale};
+use crate::readable_id::fold_accent;
+use crate::schema::{DocumentNode, Extraction};
+
+/// A metadata value that does not match its schema after coercion.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataError {
+    /// JSON pointer into the metadata, e.g. `/partes/0/polo`
+    pub path: String,
+    pub message: String,
+}
+
+/// Result of checking one metadata object.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+    /// Paths of the values that were coerced
+    pub coerced: Vec<String>,
+    pub errors: Vec<MetadataError>,
+}
+
+impl Validation {
+    fn is_empty(&self) -> bool {
+        self.coerced.is_empty() && self.errors.is_empty()
+    }
+}
+
+/// Totals over an extraction, for logging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub coerced: usize,
+    pub errors: usize,
+}
+
+/// The JSON Schema for a config schema: a bare property map becomes an
+/// object schema. Empty schemas give `None`.
+pub fn json_schema(schema: &Value) -> Option<Value> {
+    let map = schema.as_object().filter(|m| !m.is_empty())?;
+    let is_full_schema = [
+        "$schema",
+        "type",
+        "properties",
+        "$ref",
+        "allOf",
+        "anyOf",
+        "oneOf",
+    ]
+    .iter()
+    .any(|k| map.contains_key(*k));
+    Some(if is_full_schema {
+        schema.clone()
+    } else {
+        json!({"type": "object", "properties": schema})
+    })
+}
+
+/// Check that a config schema compiles as JSON Schema.
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    match json_schema(schema) {
+        Some(schema) => jsonschema::validator_for(&schema)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Coerce and validate `metadata` against `schema` (a config schema).
+/// `Err` means the schema itself is not a valid JSON Schema.
+pub fn validate(metadata: &mut Value, schema: &Value) -> Result<Validation, String> {
+    let Some(schema) = json_schema(schema) else {
+        return Ok(Validation::default());
+    };
+    let validator = jsonschema::validator_for(&schema)
+        .map_err(|e| format!("invalid metadata_schema: {}", e))?;
+
+    let mut validation = Validation::default();
+    if metadata.is_null() {
+        return Ok(validation);
+    }
+    coerce(metadata, &schema, "", &mut validation.coerced);
+
+    // Stage annotations (`_entities`, `_toc`, ...) are not part of the schema
+    let checked = match metadata {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(k, _)| !k.starts_with('_'))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        ),
+        other => other.clone(),
+    };
+    validation.errors = validator
+        .iter_errors(&checked)
+        .map(|e| MetadataError {
+            path: e.instance_path.to_string(),
+            message: e.to_string(),
+        })
+        .collect();
+    Ok(validation)
+}
+
+/// Validate the extraction's metadata and every node's, recording the
+/// results under `metadata._validation`.
+pub fn validate_extraction(extraction: &mut Extraction, config: &ExtractionConfig) -> Summary {
+    let mut summary = Summary::default();
+    let validation =
+        validate(&mut extraction.metadata, &config.metadata_schema).unwrap_or_else(schema_error);
+    record(&mut extraction.metadata, &validation, &mut summary);
+    validate_nodes(&mut extraction.children, config, &mut summary);
+    summary
+}
+
+fn validate_nodes(nodes: &mut [DocumentNode], config: &ExtractionConfig, summary: &mut Summary) {
+    for node in nodes {
+        if let Some(node_type) = config.node_type(&node.node_type) {
+            let validation = validate(&mut node.metadata, &node_type.metadata_schema)
+                .unwrap_or_else(schema_error);
+            record(&mut node.metadata, &validation, summary);
+        }
+        validate_nodes(&mut node.children, config, summary);
+    }
+}
+
+fn schema_error(message: String) -> Validation {
+    Validation {
+        coerced: Vec::new(),
+        errors: vec![MetadataError {
+            path: String::new(),
+            message,
+        }],
+    }
+}
+
+fn record(metadata: &mut Value, validation: &Validation, summary: &mut Summary) {
+    if validation.is_empty() {
+        return;
+    }
+    summary.coerced += validation.coerced.len();
+    summary.errors += validation.errors.len();
+    if metadata.is_null() {
+        *metadata = Value::Object(Map::new());
+    }
+    if let Some(obj) = metadata.as_object_mut() {
+        obj.insert("_validation".to_string(), json!(validation));
+    }
+}
+
+/// Coerce `value` towards `schema` in place, collecting the coerced paths.
+fn coerce(value: &mut Value, schema: &Value, path: &str, coerced: &mut Vec<String>) {
+    let expected = schema.get("type").and_then(Value::as_str);
+    if let Some(new) = expected.and_then(|t| coerce_scalar(value, t)) {
+        *value = new;
+        coerced.push(path.to_string());
+    }
+    if let Some(new) = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|options| match_enum(value, options))
+    {
+        *value = new;
+        coerced.push(path.to_string());
+    }
+
+    match value {
+        Value::Object(map) => {
+            let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+                return;
+            };
+            // An optional field the LLM left null is as good as absent
+            let nulls: Vec<String> = map
+                .iter()
+                .filter(|(k, v)| v.is_null() && properties.get(*k).is_some_and(rejects_null))
+                .map(|(k, _)| k.clone())
+                .collect();
+            let required: Vec<&str> = schema
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|r| r.iter().filter_map(Value::as_str).collect())
+                .unwrap_or_default();
+            for key in nulls {
+                if !required.contains(&key.as_str()) {
+                    map.remove(&key);
+                    coerced.push(format!("{}/{}", path, pointer_escape(&key)));
+                }
+            }
+            for (key, child) in map.iter_mut() {
+                if let Some(child_schema) = properties.get(key) {
+                    let child_path = format!("{}/{}", path, pointer_escape(key));
+                    coerce(child, child_schema, &child_path, coerced);
+                }
+            }
+        }
+        Value::Array(items) => {
+            let Some(item_schema) = schema.get("items").filter(|s| s.is_object()) else {
+                return;
+            };
+            for (i, item) in items.iter_mut().enumerate() {
+                coerce(item, item_schema, &format!("{}/{}", path, i), coerced);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The coerced value when `value` is a simple mismatch for `expected`.
+fn coerce_scalar(value: &Value, expected: &str) -> Option<Value> {
+    match (expected, value) {
+        ("number", Value::String(s)) => parse_number(s).map(|n| json!(n)),
+        ("integer", Value::String(s)) => parse_number(s)
+            .filter(|n| n.fract() == 0.0 && n.abs() < 9e15)
+            .map(|n| json!(n as i64)),
+        ("integer", Value::Number(n)) => n
+            .as_f64()
+            .filter(|n| !n.is_nan() && n.fract() == 0.0 && n.abs() < 9e15 && !value.is_i64())
+            .map(|n| json!(n as i64)),
+        ("string", Value::Number(n)) => Some(json!(n.to_string())),
+        ("string", Value::Bool(b)) => Some(json!(b.to_string())),
+        ("boolean", Value::String(s)) => {
+            let folded: String = s.trim().chars().map(fold_accent).collect::<String>();
+            match folded.to_lowercase().as_str() {
+                "true" | "yes" | "sim" | "s" | "verdadeiro" | "1" => Some(json!(true)),
+                "false" | "no" | "nao" | "n" | "falso" | "0" => Some(json!(false)),
+                _ => None,
+            }
+        }
+        ("array", v) if !v.is_null() && !v.is_array() => Some(json!([v])),
+        _ => None,
+    }
+}
+
+/// A number written as text. Allows a currency symbol or code, but not
+/// words (`"Processo 123"` stays a string).
+fn parse_number(s: &str) -> Option<f64> {
+    numeric::parse_number(s, NumberLocale::Auto)
+}
+
+/// The enum option `value` spells differently (case, accents), if any.
+fn match_enum(value: &Value, options: &[Value]) -> Option<Value> {
+    let s = value.as_str()?;
+    if options.iter().any(|o| o.as_str() == Some(s)) {
+        return None;
+    }
+    let fold = |s: &str| -> String {
+        s.trim()
+            .chars()
+            .map(fold_accent)
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let wanted = fold(s);
+    options
+        .iter()
+        .find(|o| o.as_str().is_some_and(|o| fold(o) == wanted))
+        .cloned()
+}
+
+/// Whether a property schema has a single non-null `type`.
+fn rejects_null(schema: &Value) -> bool {
+    schema
+        .get("type")
+        .and_then(Value::as_str)
+        .is_some_and(|t| t != "null")
+}
+
+fn pointer_escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coerce_and_validate() {
+        let schema = json!({
+            "numero": {"type": "string"},
+            "valor_causa": {"type": "number"},
+            "justica_gratuita": {"type": "boolean"},
+            "assuntos": {"type": "array", "items": {"type": "string"}},
+            "classe": {"type": "string"},
+            "partes": {"type": "array", "items": {"type": "object", "properties": {
+                "polo": {"type": "string", "enum": ["ATIVO", "PASSIVO"]},
+                "tipo_pessoa": {"type": "string", "enum": ["FÍSICA", "JURÍDICA"]},
+            }}},
+        });
+        let mut metadata = json!({
+            "numero": 12345,
+            "valor_causa": "R$ 15.000,00",
+            "justica_gratuita": "Sim",
+            "assuntos": "Dano moral",
+            "classe": null,
+            "partes": [
+                {"polo": "ativo", "tipo_pessoa": "fisica"},
+                {"polo": "réu", "tipo_pessoa": "JURÍDICA"},
+            ],
+            "_entities": {"cpf": ["12345678900"]},
+        });
+
+        let validation = validate(&mut metadata, &schema).unwrap();
+        assert_eq!(metadata["numero"], "12345");
+        assert_eq!(metadata["valor_causa"], 15000.0);
+        assert_eq!(metadata["justica_gratuita"], true);
+        assert_eq!(metadata["assuntos"], json!(["Dano moral"]));
+        assert!(metadata.get("classe").is_none());
+        assert_eq!(
+            metadata["partes"][0],
+            json!({"polo": "ATIVO", "tipo_pessoa": "FÍSICA"})
+        );
+        assert_eq!(validation.coerced.len(), 7);
+        assert!(validation.coerced.contains(&"/partes/0/polo".to_string()));
+
+        // "réu" is not an option, and a description is not a number
+        assert_eq!(validation.errors.len(), 1);
+        assert_eq!(validation.errors[0].path, "/partes/1/polo");
+        let mut text = json!({"valor_causa": "quinze mil reais"});
+        assert_eq!(
+            validate(&mut text, &schema).unwrap().errors[0].path,
+            "/valor_causa"
+        );
+
+        assert!(validate(&mut json!({}), &json!({"type": 5})).is_err());
+        assert_eq!(
+            validate(&mut json!({"a": 1}), &json!({})).unwrap(),
+            Validation::default()
+        );
+    }
+
+    #[test]
+    fn test_validate_extraction_records_errors() {
+        let mut config = crate::config::create_default_config();
+        config.metadata_schema = json!({"total": {"type": "number"}});
+        config.node_types[0].metadata_schema = json!({"pages": {"type": "integer"}});
+
+        let mut extraction = Extraction::new("doc.pdf".into(), None);
+        extraction.metadata = json!({"total": "n/a"});
+        extraction.children = vec![serde_json::from_value(json!({
+            "id": "a",
+            "type": "document",
+            "summary": "",
+            "metadata": {"pages": "12"},
+        }))
+        .unwrap()];
+
+        let summary = validate_extraction(&mut extraction, &config);
+        assert_eq!(
+            summary,
+            Summary {
+                coerced: 1,
+                errors: 1
+            }
+        );
+        assert_eq!(
+            extraction.metadata["_validation"]["errors"][0]["path"],
+            "/total"
+        );
+        let node = &extraction.children[0].metadata;
+        assert_eq!(node["pages"], 12);
+        assert_eq!(node["_validation"]["coerced"], json!(["/pages"]));
+    }
+}
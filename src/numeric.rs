@@ -0,0 +1,326 @@
+//! Locale-aware parsing of numbers written as text.
+//!
+//! Extracted values arrive as strings in whatever convention the source used:
+//! Brazilian `"1.234,56"`, `"R$ 1.234,56"`, `"(1.234,56)"` for a negative, or
+//! US `"1,234.56"`. Dataset aggregates, metadata coercion and the numeric
+//! column transforms all parse through here so they agree on what a number is.
+//!
+//! Parsing is strict about shape: a currency symbol or code, a sign,
+//! parentheses and a trailing `%` are allowed around the digits, but anything
+//! else (`"12 kg"`, `"Processo 123"`) is not a number. Thousands groups must
+//! be well formed, which is what settles most ambiguous separators.
+
+use serde_json::Value;
+
+/// Currency markers accepted before or after the digits. Longest first so
+/// `US$` isn't read as `$` with a leftover `US`.
+const CURRENCY_MARKERS: &[&str] = &["US$", "R$", "BRL", "USD", "$", "€"];
+
+/// Which convention settles a value that reads validly both ways, such as
+/// `"1.234"` (1234 in Brazil, 1.234 in the US).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberLocale {
+    /// A lone separator before exactly three digits is a thousands
+    /// separator, otherwise it is the decimal separator.
+    #[default]
+    Auto,
+    /// `1.234,56`
+    PtBr,
+    /// `1,234.56`
+    EnUs,
+}
+
+impl NumberLocale {
+    /// The locale a numeric column transform parses with; `None` for
+    /// transforms that don't produce numbers.
+    pub fn for_transform(transform: &str) -> Option<Self> {
+        match transform {
+            "parse_currency_brl" => Some(Self::PtBr),
+            "parse_currency_usd" => Some(Self::EnUs),
+            "to_number" | "to_integer" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    /// Decimal separators to try, in order of preference. `None` reads the
+    /// digits as a whole number with thousands groups.
+    fn decimal_candidates(self, body: &str) -> Vec<Option<char>> {
+        match self {
+            Self::PtBr => vec![Some(','), Some('.')],
+            Self::EnUs => vec![Some('.'), Some(',')],
+            Self::Auto => {
+                // With both separators present the last one is the decimal
+                let last = body
+                    .rfind(['.', ','])
+                    .and_then(|i| body[i..].chars().next());
+                let other = match last {
+                    Some('.') => Some(','),
+                    _ => Some('.'),
+                };
+                vec![None, last, other]
+            }
+        }
+    }
+}
+
+/// Interpret a JSON value as a number: JSON numbers as is, strings through
+/// [`parse_number`] with [`NumberLocale::Auto`].
+pub fn value_as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => parse_number(s, NumberLocale::Auto),
+        _ => None,
+    }
+}
+
+/// Parse a number written as text. `None` when `raw` isn't a number under
+/// either convention; `locale` only decides between readings that are both
+/// valid. A trailing `%` is dropped, not divided out (`"12,5%"` is 12.5).
+pub fn parse_number(raw: &str, locale: NumberLocale) -> Option<f64> {
+    let (negative, body) = strip_decorations(raw)?;
+    if !body.chars().any(|c| c.is_ascii_digit())
+        || !body
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | ' '))
+    {
+        return None;
+    }
+
+    let n = locale
+        .decimal_candidates(&body)
+        .into_iter()
+        .find_map(|decimal| read_digits(&body, decimal))
+        .filter(|n| n.is_finite())?;
+    Some(if negative { -n } else { n })
+}
+
+/// Apply a numeric column transform to an extracted value. Strings that
+/// parse become JSON numbers; `None` when the transform isn't numeric or the
+/// value doesn't parse (the caller keeps the original value).
+pub fn apply_transform(transform: &str, value: &Value) -> Option<Value> {
+    let locale = NumberLocale::for_transform(transform)?;
+    let n = match value {
+        Value::String(s) => parse_number(s, locale)?,
+        _ => return None,
+    };
+    if transform == "to_integer" {
+        return (n.fract() == 0.0 && n.abs() < 9e15).then(|| Value::from(n as i64));
+    }
+    serde_json::Number::from_f64(n).map(Value::Number)
+}
+
+/// Peel the sign, parentheses, currency markers and `%` off `raw`, leaving
+/// the digits and separators. `None` when signs conflict (`"--5"`).
+fn strip_decorations(raw: &str) -> Option<(bool, String)> {
+    let mut s = raw.replace(['\u{a0}', '\u{202f}'], " ");
+    let mut negative = None;
+    let sign = |negative: &mut Option<bool>, negate: bool| match negative.replace(negate) {
+        Some(_) => None,
+        None => Some(()),
+    };
+
+    loop {
+        let t = s.trim();
+        let next = if t.len() > 1 && t.starts_with('(') && t.ends_with(')') {
+            sign(&mut negative, true)?;
+            &t[1..t.len() - 1]
+        } else if let Some(rest) = t.strip_prefix(['-', '\u{2212}']) {
+            sign(&mut negative, true)?;
+            rest
+        } else if let Some(rest) = t.strip_prefix('+') {
+            sign(&mut negative, false)?;
+            rest
+        } else if let Some(rest) = t.strip_suffix('-') {
+            // Bank statements write debits as "1.234,56-"
+            sign(&mut negative, true)?;
+            rest
+        } else if let Some(rest) = t.strip_suffix('%') {
+            rest
+        } else if let Some(rest) = strip_marker(t) {
+            rest
+        } else {
+            return Some((negative == Some(true), t.to_string()));
+        };
+        s = next.to_string();
+    }
+}
+
+fn strip_marker(s: &str) -> Option<&str> {
+    CURRENCY_MARKERS.iter().find_map(|m| {
+        let starts = s.get(..m.len()).is_some_and(|p| p.eq_ignore_ascii_case(m));
+        let ends = s.len() >= m.len()
+            && s.get(s.len() - m.len()..)
+                .is_some_and(|p| p.eq_ignore_ascii_case(m));
+        if starts {
+            Some(&s[m.len()..])
+        } else if ends {
+            Some(&s[..s.len() - m.len()])
+        } else {
+            None
+        }
+    })
+}
+
+/// Read `body` with `decimal` as the decimal separator and every other
+/// separator as thousands grouping, or `None` if it doesn't fit that reading.
+fn read_digits(body: &str, decimal: Option<char>) -> Option<f64> {
+    let (int, frac) = match decimal {
+        Some(d) if body.matches(d).count() > 1 => return None,
+        Some(d) => match body.split_once(d) {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (body, None),
+        },
+        None => (body, None),
+    };
+
+    if let Some(frac) = frac {
+        if frac.is_empty() || !frac.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    let int = ungroup(int.trim_end(), frac.is_some())?;
+
+    let normalized = match frac {
+        Some(frac) => format!("{int}.{frac}"),
+        None => int,
+    };
+    normalized.parse().ok()
+}
+
+/// The integer digits of `int` with thousands separators removed. Groups
+/// must use one separator, start with 1-3 digits (not a leading zero) and
+/// continue in threes. An empty integer part is allowed before a decimal
+/// (`",5"`).
+fn ungroup(int: &str, has_frac: bool) -> Option<String> {
+    if int.is_empty() {
+        return has_frac.then(|| "0".to_string());
+    }
+    let separators: Vec<char> = int.chars().filter(|c| !c.is_ascii_digit()).collect();
+    let Some(&sep) = separators.first() else {
+        return Some(int.to_string());
+    };
+    if separators.iter().any(|&c| c != sep) {
+        return None;
+    }
+
+    let groups: Vec<&str> = int.split(sep).collect();
+    let first = groups[0];
+    let well_formed = (1..=3).contains(&first.len())
+        && !first.starts_with('0')
+        && groups[1..].iter().all(|g| g.len() == 3);
+    well_formed.then(|| groups.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    /// `cents` written with the given separators, e.g. `1.234.567,89`.
+    fn format_cents(cents: u64, thousands: char, decimal: char) -> String {
+        let int = (cents / 100).to_string();
+        let mut grouped = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i).is_multiple_of(3) {
+                grouped.push(thousands);
+            }
+            grouped.push(c);
+        }
+        format!("{grouped}{decimal}{:02}", cents % 100)
+    }
+
+    #[test]
+    fn test_parse_number_formats() {
+        use NumberLocale::*;
+        assert_eq!(parse_number("R$ 1.234,56", Auto), Some(1234.56));
+        assert_eq!(parse_number("(1.234,56)", Auto), Some(-1234.56));
+        assert_eq!(parse_number("R$ -1.234,56", Auto), Some(-1234.56));
+        assert_eq!(parse_number("1.234,56-", Auto), Some(-1234.56));
+        assert_eq!(parse_number("US$ 1,234.56", Auto), Some(1234.56));
+        assert_eq!(parse_number("1.234,56 BRL", Auto), Some(1234.56));
+        assert_eq!(parse_number("1\u{a0}234,5", Auto), Some(1234.5));
+        assert_eq!(parse_number("12,5%", Auto), Some(12.5));
+        assert_eq!(parse_number("0,500", Auto), Some(0.5));
+        assert_eq!(parse_number(",5", Auto), Some(0.5));
+
+        // The locale settles readings that are valid both ways
+        assert_eq!(parse_number("1.234", PtBr), Some(1234.0));
+        assert_eq!(parse_number("1.234", EnUs), Some(1.234));
+        assert_eq!(parse_number("1,234", PtBr), Some(1.234));
+        // ...but not ones only the other convention can read
+        assert_eq!(parse_number("1234.5", PtBr), Some(1234.5));
+        assert_eq!(parse_number("1,234.56", PtBr), Some(1234.56));
+
+        for bad in [
+            "",
+            "-",
+            "R$",
+            "12 kg",
+            "Processo 123",
+            "1.2.3,4,5",
+            "--5",
+            "1.23.456",
+        ] {
+            assert_eq!(parse_number(bad, Auto), None, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_apply_transform() {
+        assert_eq!(
+            apply_transform("parse_currency_brl", &json!("R$ 1.234,56")),
+            Some(json!(1234.56))
+        );
+        assert_eq!(
+            apply_transform("parse_currency_usd", &json!("1.234")),
+            Some(json!(1.234))
+        );
+        assert_eq!(
+            apply_transform("to_integer", &json!("1.000")),
+            Some(json!(1000))
+        );
+        assert_eq!(apply_transform("to_integer", &json!("2,5")), None);
+        assert_eq!(apply_transform("to_number", &json!("n/a")), None);
+        assert_eq!(apply_transform("to_uppercase", &json!("12")), None);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_brazilian_round_trips(cents in 0u64..10_000_000_000_000) {
+            let text = format_cents(cents, '.', ',');
+            let expected = cents as f64 / 100.0;
+            prop_assert_eq!(parse_number(&text, NumberLocale::PtBr), Some(expected));
+            prop_assert_eq!(parse_number(&text, NumberLocale::Auto), Some(expected));
+            prop_assert_eq!(parse_number(&format!("(R$ {text})"), NumberLocale::Auto), Some(-expected));
+        }
+
+        #[test]
+        fn prop_us_round_trips(cents in 0u64..10_000_000_000_000) {
+            let text = format_cents(cents, ',', '.');
+            let expected = cents as f64 / 100.0;
+            prop_assert_eq!(parse_number(&text, NumberLocale::EnUs), Some(expected));
+            prop_assert_eq!(parse_number(&text, NumberLocale::Auto), Some(expected));
+            prop_assert_eq!(parse_number(&format!("-US$ {text}"), NumberLocale::Auto), Some(-expected));
+        }
+
+        #[test]
+        fn prop_grouped_integers_agree(n in 1_000u64..1_000_000_000_000) {
+            let dotted = format_cents(n * 100, '.', ',');
+            let dotted = dotted.trim_end_matches(",00");
+            let expected = Some(n as f64);
+            prop_assert_eq!(parse_number(dotted, NumberLocale::PtBr), expected);
+            prop_assert_eq!(parse_number(dotted, NumberLocale::Auto), expected);
+            prop_assert_eq!(parse_number(&dotted.replace('.', ","), NumberLocale::EnUs), expected);
+        }
+
+        #[test]
+        fn prop_arbitrary_text_never_panics(s in "\\PC{0,24}") {
+            for locale in [NumberLocale::Auto, NumberLocale::PtBr, NumberLocale::EnUs] {
+                if let Some(n) = parse_number(&s, locale) {
+                    prop_assert!(n.is_finite());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Google Cloud Storage object store (`OBJECT_STORE_BACKEND=gcs`).
+//!
+//! Uses the GCS JSON API with a service-account access token
+//! (`GCS_BUCKET`, `GCS_SA_KEY_PATH`).
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, StatusCode};
+use tracing::debug;
+
+use super::{uri_encode, ObjectStore};
+use crate::gcp_auth::ServiceAccountAuth;
+
+const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+const API_BASE: &str = "https://storage.googleapis.com";
+
+pub struct GcsStore {
+    client: Client,
+    bucket: String,
+    auth: ServiceAccountAuth,
+}
+
+impl GcsStore {
+    pub fn from_env() -> Result<Self> {
+        let bucket = std::env::var("GCS_BUCKET").map_err(|_| anyhow!("GCS_BUCKET not set"))?;
+        let key_path =
+            std::env::var("GCS_SA_KEY_PATH").map_err(|_| anyhow!("GCS_SA_KEY_PATH not set"))?;
+
+        Ok(Self {
+            client: Client::new(),
+            bucket,
+            auth: ServiceAccountAuth::from_key_path(&key_path, STORAGE_SCOPE)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for GcsStore {
+    fn name(&self) -> &str {
+        "gcs"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+        let token = self.auth.get_access_token(&self.client).await?;
+        let url = format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType=media&name={}",
+            API_BASE,
+            self.bucket,
+            uri_encode(key, false)
+        );
+
+        let resp = self
+            .client
+            .post(&url)
+            .bearer_auth(&token)
+            .header("Content-Type", content_type)
+            .body(data)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("GCS upload {} failed: {} - {}", key, status, text));
+        }
+
+        debug!("Stored gs://{}/{}", self.bucket, key);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let token = self.auth.get_access_token(&self.client).await?;
+        let url = format!(
+            "{}/storage/v1/b/{}/o/{}?alt=media",
+            API_BASE,
+            self.bucket,
+            uri_encode(key, false)
+        );
+
+        let resp = self.client.get(&url).bearer_auth(&token).send().await?;
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "GCS download {} failed: {} - {}",
+                key,
+                status,
+                text
+            ));
+        }
+
+        Ok(Some(resp.bytes().await?.to_vec()))
+    }
+}
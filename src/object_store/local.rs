@@ -0,0 +1,63 @@
+//! Local filesystem object store (`OBJECT_STORE_BACKEND=local`).
+//!
+//! Objects are written as plain files under `OBJECT_STORE_DIR`
+//! (default `data/objects`), mirroring the key layout.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+
+use super::ObjectStore;
+
+const DEFAULT_DIR: &str = "data/objects";
+
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn from_env() -> Self {
+        let dir = std::env::var("OBJECT_STORE_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_string());
+        Self::new(dir)
+    }
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve a key to a path, rejecting keys that could escape the root.
+    fn path_for(&self, key: &str) -> Result<PathBuf> {
+        if key.is_empty() || key.split('/').any(|seg| seg.is_empty() || seg == "..") {
+            bail!("Invalid object key: {}", key);
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for LocalStore {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        tokio::fs::write(&path, data)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path_for(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+}
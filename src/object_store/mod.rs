@@ -0,0 +1,271 @@
+//! Object storage for original uploads and raw OCR output.
+//!
+//! Defines the [`ObjectStore`] trait so archived files can live in an
+//! S3-compatible bucket, Google Cloud Storage, Supabase Storage, or a local
+//! directory. The
+//! backend is selected via `OBJECT_STORE_BACKEND`; when unset, nothing is
+//! archived.
+//!
+//! Layout under each extraction ID:
+//! - `extractions/{id}/source` — original uploaded file
+//! - `extractions/{id}/ocr.json` — provider, page count, markdown, and pages
+//! - `extractions/{id}/ocr/document.md` — raw OCR markdown
+//! - `extractions/{id}/ocr/pages/{n}.txt` — per-page text (1-indexed, zero-padded)
+//! - `extractions/{id}/pages/{n}@{dpi}.png` — rendered page images (see `page_image`)
+//!
+//! Sheet extractions that went through OCR keep the same `ocr*` objects under
+//! `datasets/{id}/`.
+
+pub mod gcs;
+pub mod local;
+pub mod s3;
+pub mod supabase;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::ocr::{OcrPage, OcrResult};
+
+/// Async trait implemented by each object storage backend.
+#[async_trait::async_trait]
+pub trait ObjectStore: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Store `data` under `key`, overwriting any existing object.
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()>;
+
+    /// Fetch the object at `key`, or `None` if it does not exist.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// A time-limited download link for `key`, for backends that can sign one.
+    async fn signed_url(&self, _key: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Known backend identifiers for `OBJECT_STORE_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectStoreKind {
+    S3,
+    Gcs,
+    Supabase,
+    Local,
+}
+
+impl ObjectStoreKind {
+    /// Parse an env-var string into a backend kind.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "s3" => Some(Self::S3),
+            "gcs" => Some(Self::Gcs),
+            "supabase" => Some(Self::Supabase),
+            "local" => Some(Self::Local),
+            _ => None,
+        }
+    }
+}
+
+/// Build the object store selected by `OBJECT_STORE_BACKEND` (`Ok(None)` when unset).
+pub fn from_env() -> Result<Option<Arc<dyn ObjectStore>>> {
+    let name = match std::env::var("OBJECT_STORE_BACKEND") {
+        Ok(v) if !v.is_empty() => v,
+        _ => return Ok(None),
+    };
+
+    let kind = ObjectStoreKind::from_str(&name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown OBJECT_STORE_BACKEND: '{}'. Available: s3, gcs, supabase, local",
+            name
+        )
+    })?;
+
+    let store: Arc<dyn ObjectStore> = match kind {
+        ObjectStoreKind::S3 => Arc::new(s3::S3Store::from_env()?),
+        ObjectStoreKind::Gcs => Arc::new(gcs::GcsStore::from_env()?),
+        ObjectStoreKind::Supabase => Arc::new(supabase::SupabaseStore::from_env()?),
+        ObjectStoreKind::Local => Arc::new(local::LocalStore::from_env()),
+    };
+
+    Ok(Some(store))
+}
+
+// ============================================================================
+// Key layout
+// ============================================================================
+
+pub fn source_key(extraction_id: &str) -> String {
+    format!("extractions/{}/source", extraction_id)
+}
+
+pub fn page_image_key(extraction_id: &str, page_num: u32, dpi: u32) -> String {
+    format!(
+        "extractions/{}/pages/{:04}@{}.png",
+        extraction_id, page_num, dpi
+    )
+}
+
+/// Key prefix for an extraction's archived objects.
+pub fn extraction_root(extraction_id: &str) -> String {
+    format!("extractions/{}", extraction_id)
+}
+
+/// Key prefix for a dataset's archived objects.
+pub fn dataset_root(dataset_id: &str) -> String {
+    format!("datasets/{}", dataset_id)
+}
+
+pub fn ocr_json_key(root: &str) -> String {
+    format!("{}/ocr.json", root)
+}
+
+pub fn ocr_markdown_key(root: &str) -> String {
+    format!("{}/ocr/document.md", root)
+}
+
+pub fn ocr_page_key(root: &str, page_num: u32) -> String {
+    format!("{}/ocr/pages/{:04}.txt", root, page_num)
+}
+
+/// Archived OCR output as stored in `ocr.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredOcr {
+    /// Extraction or dataset ID
+    pub extraction_id: String,
+    pub provider: String,
+    pub total_pages: u32,
+    pub ocr_confidence: f64,
+    pub markdown: String,
+    pub pages: Vec<StoredOcrPage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredOcrPage {
+    pub page_num: u32,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdf_page: Option<u32>,
+}
+
+impl StoredOcr {
+    pub fn from_result(id: &str, ocr: &OcrResult) -> Self {
+        Self {
+            extraction_id: id.to_string(),
+            provider: ocr.provider_name.clone(),
+            total_pages: ocr.total_pages,
+            ocr_confidence: ocr.ocr_confidence,
+            markdown: ocr.markdown.clone(),
+            pages: ocr
+                .pages
+                .iter()
+                .map(|p| StoredOcrPage {
+                    page_num: p.page_num,
+                    text: p.text.clone(),
+                    confidence: p.confidence,
+                    pdf_page: p.pdf_page,
+                })
+                .collect(),
+        }
+    }
+
+    /// Rebuild an OCR result from the archive, e.g. to resume an extraction.
+    pub fn into_ocr_result(self) -> OcrResult {
+        OcrResult {
+            markdown: self.markdown,
+            pages: self
+                .pages
+                .into_iter()
+                .map(|p| OcrPage {
+                    page_num: p.page_num,
+                    text: p.text,
+                    confidence: p.confidence,
+                    pdf_page: p.pdf_page,
+                })
+                .collect(),
+            total_pages: self.total_pages,
+            metadata: serde_json::Value::Null,
+            ocr_confidence: self.ocr_confidence,
+            provider_name: self.provider,
+        }
+    }
+}
+
+/// Guess a MIME type from a file name's extension.
+pub fn content_type_for(filename: &str) -> &'static str {
+    let ext = filename
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "tif" | "tiff" => "image/tiff",
+        "csv" => "text/csv",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xls" => "application/vnd.ms-excel",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Archive the original file for an extraction.
+pub async fn store_source(
+    store: &dyn ObjectStore,
+    extraction_id: &str,
+    filename: &str,
+    data: Vec<u8>,
+) -> Result<()> {
+    store
+        .put(&source_key(extraction_id), data, content_type_for(filename))
+        .await
+}
+
+/// Archive raw OCR output under `root` (see [`extraction_root`] and
+/// [`dataset_root`]): the combined JSON, the markdown, and one text object per page.
+pub async fn store_ocr(store: &dyn ObjectStore, root: &str, stored: &StoredOcr) -> Result<()> {
+    store
+        .put(
+            &ocr_json_key(root),
+            serde_json::to_vec(stored)?,
+            "application/json",
+        )
+        .await?;
+    store
+        .put(
+            &ocr_markdown_key(root),
+            stored.markdown.clone().into_bytes(),
+            "text/markdown; charset=utf-8",
+        )
+        .await?;
+    for page in &stored.pages {
+        store
+            .put(
+                &ocr_page_key(root, page.page_num),
+                page.text.clone().into_bytes(),
+                "text/plain; charset=utf-8",
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Percent-encode a string per RFC 3986, keeping `/` when `keep_slash` is set.
+pub(crate) fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
@@ -0,0 +1,148 @@
+//! Supabase Storage object store (`OBJECT_STORE_BACKEND=supabase`).
+//!
+//! Objects go to the bucket `SUPABASE_STORAGE_BUCKET` (default `documents`)
+//! of the project at `SUPABASE_URL`, authenticated with the service-role key.
+//! The bucket can stay private: sources are handed out as signed URLs valid
+//! for `SUPABASE_SIGNED_URL_TTL_SECS` (default 7 days).
+
+use anyhow::{anyhow, Result};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use tracing::debug;
+
+use super::{uri_encode, ObjectStore};
+
+const DEFAULT_BUCKET: &str = "documents";
+const DEFAULT_SIGNED_URL_TTL_SECS: u64 = 7 * 24 * 3600;
+
+pub struct SupabaseStore {
+    client: Client,
+    base_url: String,
+    service_role_key: String,
+    bucket: String,
+    signed_url_ttl_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct SignedUrl {
+    #[serde(rename = "signedURL")]
+    signed_url: String,
+}
+
+impl SupabaseStore {
+    pub fn from_env() -> Result<Self> {
+        let base_url =
+            std::env::var("SUPABASE_URL").map_err(|_| anyhow!("SUPABASE_URL not set"))?;
+        let service_role_key = std::env::var("SUPABASE_SERVICE_ROLE_KEY")
+            .map_err(|_| anyhow!("SUPABASE_SERVICE_ROLE_KEY not set"))?;
+
+        Ok(Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            service_role_key,
+            bucket: std::env::var("SUPABASE_STORAGE_BUCKET")
+                .unwrap_or_else(|_| DEFAULT_BUCKET.to_string()),
+            signed_url_ttl_secs: std::env::var("SUPABASE_SIGNED_URL_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_SIGNED_URL_TTL_SECS),
+        })
+    }
+
+    fn object_url(&self, action: &str, key: &str) -> String {
+        format!(
+            "{}/storage/v1/object/{}{}/{}",
+            self.base_url,
+            action,
+            self.bucket,
+            uri_encode(key, true)
+        )
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request
+            .bearer_auth(&self.service_role_key)
+            .header("apikey", &self.service_role_key)
+    }
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for SupabaseStore {
+    fn name(&self) -> &str {
+        "supabase"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+        let resp = self
+            .authorized(self.client.post(self.object_url("", key)))
+            .header("Content-Type", content_type)
+            .header("x-upsert", "true")
+            .body(data)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "Supabase Storage upload {} failed: {} - {}",
+                key,
+                status,
+                text
+            ));
+        }
+
+        debug!("Stored {}/{} in Supabase Storage", self.bucket, key);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let resp = self
+            .authorized(self.client.get(self.object_url("authenticated/", key)))
+            .send()
+            .await?;
+        let status = resp.status();
+        // Missing objects come back as 400 with a "not_found" error body
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            if status == StatusCode::BAD_REQUEST && text.contains("not_found") {
+                return Ok(None);
+            }
+            return Err(anyhow!(
+                "Supabase Storage download {} failed: {} - {}",
+                key,
+                status,
+                text
+            ));
+        }
+
+        Ok(Some(resp.bytes().await?.to_vec()))
+    }
+
+    async fn signed_url(&self, key: &str) -> Result<Option<String>> {
+        let resp = self
+            .authorized(self.client.post(self.object_url("sign/", key)))
+            .json(&serde_json::json!({ "expiresIn": self.signed_url_ttl_secs }))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "Supabase Storage signing {} failed: {} - {}",
+                key,
+                status,
+                text
+            ));
+        }
+
+        let signed: SignedUrl = resp.json().await?;
+        Ok(Some(format!(
+            "{}/storage/v1{}",
+            self.base_url, signed.signed_url
+        )))
+    }
+}
@@ -1,15 +1,29 @@
 //! Docling sidecar OCR provider.
 //!
+//! `DOCLING_URL` may list several sidecars (comma-separated); requests go
+//! round-robin to those that are up, and one that fails to connect is
+//! skipped for [`RETRY_DOWN_AFTER`].
+//!
 //! Supports two modes depending on whether GCE config is present:
 //! - **Always-on**: fail immediately on connection error (current behavior).
-//! - **Wake-on-demand**: on connection error, start the GCE instance, wait
-//!   for Docling to become healthy, then retry the request.
+//! - **Wake-on-demand**: each URL is paired with the GCE instance at the same
+//!   position in `GCE_INSTANCE_NAME`. On connection error, start the instance,
+//!   wait for Docling to become healthy, then retry the request. Under load,
+//!   stopped instances are started in the background. Requests are recorded
+//!   in each instance's [`IdleTracker`] so the server can stop it again.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::{OcrInput, OcrPage, OcrProvider, OcrResult};
-use crate::gce::GceConfig;
+use super::{OcrInput, OcrOptions, OcrPage, OcrProvider, OcrResult};
+use crate::gce::{GceConfig, IdleTracker};
 use serde::Deserialize;
 use tracing::{info, warn};
 
+/// How long a sidecar that failed to connect is skipped.
+const RETRY_DOWN_AFTER: Duration = Duration::from_secs(30);
+
 /// Docling sidecar response (private deserialization types).
 #[derive(Debug, Deserialize)]
 struct DoclingResponse {
@@ -24,53 +38,174 @@ struct DoclingResponse {
 struct DoclingPageContent {
     page_num: u32,
     text: String,
+    /// Docling's mean layout/parse/OCR score for the page (newer sidecars only)
+    #[serde(default)]
+    confidence: Option<f64>,
+    /// Page of the source PDF, for sidecars converting a page range
+    #[serde(default)]
+    pdf_page: Option<u32>,
 }
 
-pub struct DoclingProvider {
+/// One Docling sidecar, and the GCE instance it runs on (if any).
+struct Backend {
     url: String,
+    instance: Option<String>,
+    idle: Arc<IdleTracker>,
+    /// When a request last failed to connect
+    down_since: Mutex<Option<Instant>>,
+    /// Set while a scale-up is starting the instance
+    waking: AtomicBool,
+}
+
+impl Backend {
+    fn new(url: String, instance: Option<String>) -> Self {
+        Self {
+            url,
+            instance,
+            idle: Arc::new(IdleTracker::default()),
+            down_since: Mutex::new(None),
+            waking: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether requests may be sent here without waking it first.
+    fn is_up(&self) -> bool {
+        let recently_down = self
+            .down_since
+            .lock()
+            .unwrap()
+            .is_some_and(|since| since.elapsed() < RETRY_DOWN_AFTER);
+        !recently_down && !self.idle.is_stopped() && !self.waking.load(Ordering::SeqCst)
+    }
+
+    fn set_down(&self, down: bool) {
+        *self.down_since.lock().unwrap() = down.then(Instant::now);
+    }
+}
+
+pub struct DoclingProvider {
+    backends: Vec<Arc<Backend>>,
+    next: AtomicUsize,
     client: reqwest::Client,
     gce_config: Option<GceConfig>,
 }
 
 impl DoclingProvider {
     pub fn new(client: reqwest::Client, gce_config: Option<GceConfig>) -> Self {
-        let url =
-            std::env::var("DOCLING_URL").unwrap_or_else(|_| "http://localhost:3001".to_string());
+        let urls: Vec<String> = std::env::var("DOCLING_URL")
+            .unwrap_or_else(|_| "http://localhost:3001".to_string())
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        let instances = gce_config
+            .as_ref()
+            .map(|gce| gce.instances.clone())
+            .unwrap_or_default();
+        if gce_config.is_some() && instances.len() != urls.len() {
+            warn!(
+                "GCE_INSTANCE_NAME lists {} instance(s) but DOCLING_URL {} URL(s); only paired ones are managed",
+                instances.len(),
+                urls.len()
+            );
+        }
+        let backends = urls
+            .into_iter()
+            .enumerate()
+            .map(|(i, url)| Arc::new(Backend::new(url, instances.get(i).cloned())))
+            .collect();
         Self {
-            url,
+            backends,
+            next: AtomicUsize::new(0),
             client,
             gce_config,
         }
     }
 
-    /// Attempt to convert a document via the Docling sidecar.
-    async fn try_convert(&self, input: &OcrInput) -> anyhow::Result<OcrResult> {
-        use reqwest::multipart::{Form, Part};
-
-        let (filename, file_data) = match input {
-            OcrInput::Bytes { filename, data } => (filename.clone(), data.clone()),
-            OcrInput::Url { filename, url } => {
-                // Docling sidecar only accepts multipart — download first
-                info!("DoclingProvider: downloading {} for sidecar", url);
-                let resp = self.client.get(url).send().await?;
-                if !resp.status().is_success() {
-                    let status = resp.status();
-                    let text = resp.text().await.unwrap_or_default();
-                    anyhow::bail!("Failed to download file for Docling ({}): {}", status, text);
+    /// Each GCE instance in the pool with the tracker of its requests.
+    pub fn idle_trackers(&self) -> Vec<(String, Arc<IdleTracker>)> {
+        self.backends
+            .iter()
+            .filter_map(|b| b.instance.clone().map(|name| (name, b.idle.clone())))
+            .collect()
+    }
+
+    /// The next sidecar that is up, round-robin; every sidecar takes a turn
+    /// when none is up.
+    fn pick(&self) -> Arc<Backend> {
+        let up: Vec<&Arc<Backend>> = self.backends.iter().filter(|b| b.is_up()).collect();
+        let turn = self.next.fetch_add(1, Ordering::Relaxed);
+        if up.is_empty() {
+            self.backends[turn % self.backends.len()].clone()
+        } else {
+            up[turn % up.len()].clone()
+        }
+    }
+
+    /// A stopped instance to start because every running one is at
+    /// `GCE_SCALE_UP_DEPTH`. With no instance running, the request wakes one
+    /// itself instead.
+    fn scale_up_candidate(&self, depth: usize) -> Option<Arc<Backend>> {
+        let mut managed = self.backends.iter().filter(|b| b.instance.is_some());
+        let running: Vec<&Arc<Backend>> = managed.clone().filter(|b| b.is_up()).collect();
+        if running.is_empty() || running.iter().any(|b| b.idle.in_flight() < depth) {
+            return None;
+        }
+        managed
+            .find(|b| !b.is_up() && !b.waking.load(Ordering::SeqCst))
+            .cloned()
+    }
+
+    /// Start another instance in the background when the pool is saturated.
+    fn scale_up(&self) {
+        let Some(ref gce) = self.gce_config else {
+            return;
+        };
+        let Some(backend) = self.scale_up_candidate(gce.scale_up_depth) else {
+            return;
+        };
+        if backend.waking.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let (client, gce) = (self.client.clone(), gce.clone());
+        tokio::spawn(async move {
+            let _active = backend.idle.begin();
+            info!(
+                "All running Docling instances are busy, starting '{}'",
+                backend.instance.as_deref().unwrap_or_default()
+            );
+            match ensure_docling_ready(&client, &gce, &backend).await {
+                Ok(()) => backend.set_down(false),
+                Err(e) => {
+                    warn!("Docling scale-up failed: {:#}", e);
+                    backend.set_down(true);
                 }
-                (filename.clone(), resp.bytes().await?.to_vec())
             }
-        };
+            backend.waking.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Attempt to convert a document via the Docling sidecar at `url`.
+    async fn try_convert(
+        &self,
+        url: &str,
+        input: &OcrInput,
+        options: &OcrOptions,
+    ) -> anyhow::Result<OcrResult> {
+        use reqwest::multipart::Form;
 
-        let part = Part::bytes(file_data)
-            .file_name(filename)
-            .mime_str("application/pdf")?;
+        // Docling sidecar only accepts multipart — URLs are downloaded first
+        let part = input.file_part(&self.client, "Docling").await?;
 
-        let form = Form::new().part("file", part);
+        let mut form = Form::new().part("file", part);
+        let options = options.for_provider("docling");
+        if !options.is_empty() {
+            form = form.text("options", serde_json::Value::Object(options).to_string());
+        }
 
         let response = self
             .client
-            .post(format!("{}/convert", self.url))
+            .post(format!("{}/convert", url))
             .multipart(form)
             .send()
             .await?;
@@ -82,73 +217,85 @@ impl DoclingProvider {
         }
 
         let docling: DoclingResponse = response.json().await?;
+        let pages: Vec<OcrPage> = docling
+            .pages
+            .into_iter()
+            .map(|p| OcrPage {
+                page_num: p.page_num,
+                text: p.text,
+                confidence: p.confidence,
+                pdf_page: p.pdf_page,
+            })
+            .collect();
 
         Ok(OcrResult {
             markdown: docling.markdown,
-            pages: docling
-                .pages
-                .into_iter()
-                .map(|p| OcrPage {
-                    page_num: p.page_num,
-                    text: p.text,
-                })
-                .collect(),
+            ocr_confidence: super::mean_page_confidence(&pages).unwrap_or(0.95),
+            pages,
             total_pages: docling.total_pages,
             metadata: docling.metadata,
-            ocr_confidence: 0.95,
             provider_name: "docling".to_string(),
         })
     }
+}
 
-    /// Quick health check against the sidecar (5s timeout).
-    async fn health_check(&self) -> bool {
-        let url = format!("{}/health", self.url);
-        let result = self
-            .client
-            .get(&url)
-            .timeout(std::time::Duration::from_secs(5))
-            .send()
-            .await;
-        matches!(result, Ok(r) if r.status().is_success())
-    }
-
-    /// Ensure the Docling sidecar is reachable, starting the GCE instance if needed.
-    /// Only called when `gce_config` is `Some`.
-    async fn ensure_docling_ready(&self, gce: &GceConfig) -> anyhow::Result<()> {
-        // Quick check — maybe it's already up
-        if self.health_check().await {
-            return Ok(());
-        }
+/// Quick health check against a sidecar (5s timeout).
+async fn sidecar_health(client: &reqwest::Client, url: &str) -> anyhow::Result<()> {
+    client
+        .get(format!("{}/health", url))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
 
-        info!("Docling sidecar unreachable, checking GCE instance status...");
+/// Ensure a sidecar is reachable, starting its GCE instance if needed.
+/// Only called for backends with an instance.
+async fn ensure_docling_ready(
+    client: &reqwest::Client,
+    gce: &GceConfig,
+    backend: &Backend,
+) -> anyhow::Result<()> {
+    // Quick check — maybe it's already up
+    if sidecar_health(client, &backend.url).await.is_ok() {
+        return Ok(());
+    }
+    let instance = backend.instance.as_deref().unwrap_or_default();
 
-        let status = gce.get_instance_status(&self.client).await?;
-        if status != "RUNNING" {
-            info!("GCE instance is '{}', starting...", status);
-            gce.start_instance(&self.client).await?;
-            gce.wait_until_running(&self.client, 120).await?;
-        }
+    info!(
+        "Docling sidecar unreachable, checking GCE instance '{}' status...",
+        instance
+    );
 
-        // Instance is RUNNING, but Docling may still be loading models.
-        // Poll health endpoint for up to 3 minutes.
-        info!("Waiting for Docling sidecar to become healthy...");
-        let deadline =
-            tokio::time::Instant::now() + std::time::Duration::from_secs(180);
+    let status = gce.get_instance_status(client, instance).await?;
+    if status != "RUNNING" {
+        info!("GCE instance '{}' is '{}', starting...", instance, status);
+        gce.start_instance(client, instance).await?;
+        gce.wait_until_running(client, instance, 120).await?;
+    }
 
-        loop {
-            if self.health_check().await {
-                info!("Docling sidecar is healthy");
-                return Ok(());
-            }
+    // Instance is RUNNING, but Docling may still be loading models.
+    // Poll health endpoint for up to 3 minutes.
+    info!(
+        "Waiting for Docling sidecar {} to become healthy...",
+        backend.url
+    );
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(180);
 
-            if tokio::time::Instant::now() >= deadline {
-                anyhow::bail!(
-                    "Docling sidecar did not become healthy within 3 minutes after instance start"
-                );
-            }
+    loop {
+        if sidecar_health(client, &backend.url).await.is_ok() {
+            info!("Docling sidecar {} is healthy", backend.url);
+            return Ok(());
+        }
 
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+        if tokio::time::Instant::now() >= deadline {
+            anyhow::bail!(
+                "Docling sidecar did not become healthy within 3 minutes after instance start"
+            );
         }
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
     }
 }
 
@@ -169,23 +316,129 @@ impl OcrProvider for DoclingProvider {
         "docling"
     }
 
+    /// Healthy when any sidecar is. With GCE on-demand, a stopped instance
+    /// counts as healthy: the next request starts it.
+    async fn health(&self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+        for backend in &self.backends {
+            let err = match sidecar_health(&self.client, &backend.url).await {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            if let (Some(gce), Some(instance)) = (&self.gce_config, &backend.instance) {
+                if is_connection_error(&err) {
+                    match gce.get_instance_status(&self.client, instance).await {
+                        Ok(status)
+                            if matches!(
+                                status.as_str(),
+                                "TERMINATED" | "STOPPED" | "STOPPING" | "SUSPENDED"
+                            ) =>
+                        {
+                            return Ok(())
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            errors.push(format!("{}: {}", instance, e));
+                            continue;
+                        }
+                    }
+                }
+            }
+            errors.push(format!("{}: {}", backend.url, err));
+        }
+        anyhow::bail!("{}", errors.join("; "))
+    }
+
     async fn process(&self, input: &OcrInput) -> anyhow::Result<OcrResult> {
+        self.process_with_options(input, &OcrOptions::default())
+            .await
+    }
+
+    /// Options go to the sidecar as a JSON `options` form field.
+    async fn process_with_options(
+        &self,
+        input: &OcrInput,
+        options: &OcrOptions,
+    ) -> anyhow::Result<OcrResult> {
+        self.scale_up();
+        let backend = self.pick();
+        let _active = backend.idle.begin();
         // First attempt
-        match self.try_convert(input).await {
-            Ok(result) => return Ok(result),
+        match self.try_convert(&backend.url, input, options).await {
+            Ok(result) => {
+                backend.set_down(false);
+                Ok(result)
+            }
             Err(err) => {
-                // If it's a connection error and we have GCE config, try to wake the instance
-                if is_connection_error(&err) {
-                    if let Some(ref gce) = self.gce_config {
-                        warn!("Docling connection failed, attempting GCE wake-on-demand: {}", err);
-                        self.ensure_docling_ready(gce).await?;
-                        // Retry after waking
-                        return self.try_convert(input).await;
-                    }
+                if !is_connection_error(&err) {
+                    return Err(err);
                 }
-                // No GCE config or not a connection error — fail as before
-                return Err(err);
+                backend.set_down(true);
+                // With a GCE instance behind the sidecar, try to wake it
+                let (Some(gce), Some(_)) = (&self.gce_config, &backend.instance) else {
+                    return Err(err);
+                };
+                warn!(
+                    "Docling connection failed, attempting GCE wake-on-demand: {}",
+                    err
+                );
+                ensure_docling_ready(&self.client, gce, &backend).await?;
+                backend.set_down(false);
+                // Retry after waking
+                self.try_convert(&backend.url, input, options).await
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(backends: &[(&str, &str)]) -> DoclingProvider {
+        DoclingProvider {
+            backends: backends
+                .iter()
+                .map(|(url, instance)| {
+                    Arc::new(Backend::new(url.to_string(), Some(instance.to_string())))
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            client: reqwest::Client::new(),
+            gce_config: None,
+        }
+    }
+
+    #[test]
+    fn test_pool_routing_and_scale_up() {
+        let docling = provider(&[
+            ("http://a", "gpu-a"),
+            ("http://b", "gpu-b"),
+            ("http://c", "gpu-c"),
+        ]);
+        let picks: Vec<String> = (0..3).map(|_| docling.pick().url.clone()).collect();
+        assert_eq!(picks, ["http://a", "http://b", "http://c"]);
+
+        // Stopped and recently failed sidecars are skipped
+        docling.backends[1].idle.mark_stopped();
+        docling.backends[2].idle.mark_stopped();
+        assert!((0..3).all(|_| docling.pick().url == "http://a"));
+
+        // The running instance is saturated: start the next stopped one
+        let busy = [
+            docling.backends[0].idle.begin(),
+            docling.backends[0].idle.begin(),
+        ];
+        assert!(docling.scale_up_candidate(3).is_none());
+        assert_eq!(docling.scale_up_candidate(2).unwrap().url, "http://b");
+        docling.backends[1].waking.store(true, Ordering::SeqCst);
+        assert_eq!(docling.scale_up_candidate(2).unwrap().url, "http://c");
+        drop(busy);
+
+        // Nothing up: the request itself wakes one, taking turns
+        docling.backends[0].set_down(true);
+        assert!(docling.scale_up_candidate(2).is_none());
+        let picks: Vec<String> = (0..2).map(|_| docling.pick().url.clone()).collect();
+        assert_ne!(picks[0], picks[1]);
+    }
+}
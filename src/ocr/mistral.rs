@@ -1,6 +1,9 @@
 //! Mistral OCR provider (uses Mistral's OCR API).
+//!
+//! Of the [`OcrOptions`], only `extra.mistral_ocr` applies: its fields (e.g.
+//! `pages`) are added to the OCR request body.
 
-use super::{OcrInput, OcrPage, OcrProvider, OcrResult};
+use super::{OcrInput, OcrOptions, OcrPage, OcrProvider, OcrResult};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info};
 
@@ -23,6 +26,8 @@ impl MistralOcrProvider {
 struct OcrRequest {
     model: String,
     document: DocumentSource,
+    #[serde(flatten)]
+    extra: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Serialize)]
@@ -58,13 +63,36 @@ impl OcrProvider for MistralOcrProvider {
         "mistral_ocr"
     }
 
+    /// Lists models, which checks both reachability and the API key.
+    async fn health(&self) -> anyhow::Result<()> {
+        self.client
+            .get("https://api.mistral.ai/v1/models")
+            .bearer_auth(&self.api_key)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     async fn process(&self, input: &OcrInput) -> anyhow::Result<OcrResult> {
+        self.process_with_options(input, &OcrOptions::default())
+            .await
+    }
+
+    async fn process_with_options(
+        &self,
+        input: &OcrInput,
+        options: &OcrOptions,
+    ) -> anyhow::Result<OcrResult> {
         let document = match input {
             OcrInput::Url { url, .. } => DocumentSource::Url {
                 document_url: url.clone(),
             },
-            OcrInput::Bytes { filename, data } => {
-                let file_id = self.upload_file(filename, data).await?;
+            OcrInput::Bytes { filename, .. } | OcrInput::File { filename, .. } => {
+                info!("MistralOcrProvider: uploading {} to Files API", filename);
+                let part = input.file_part(&self.client, "Mistral").await?;
+                let file_id = self.upload_file(part).await?;
                 DocumentSource::File { file_id }
             }
         };
@@ -72,6 +100,11 @@ impl OcrProvider for MistralOcrProvider {
         let body = OcrRequest {
             model: "mistral-ocr-latest".to_string(),
             document,
+            extra: options
+                .extra
+                .get("mistral_ocr")
+                .cloned()
+                .unwrap_or_default(),
         };
 
         info!("MistralOcrProvider: calling OCR API");
@@ -108,12 +141,17 @@ impl OcrProvider for MistralOcrProvider {
             .collect::<Vec<_>>()
             .join("\n\n---\n\n");
 
+        // Pages are numbered in order; `index` is the PDF page (0-indexed),
+        // which differs when `pages` asked for only some of them
         let pages: Vec<OcrPage> = ocr
             .pages
             .into_iter()
-            .map(|p| OcrPage {
-                page_num: p.index + 1, // Normalize 0-indexed → 1-indexed
+            .enumerate()
+            .map(|(i, p)| OcrPage {
+                page_num: i as u32 + 1,
                 text: p.markdown,
+                confidence: None,
+                pdf_page: Some(p.index + 1),
             })
             .collect();
 
@@ -129,19 +167,9 @@ impl OcrProvider for MistralOcrProvider {
 }
 
 impl MistralOcrProvider {
-    /// Upload raw bytes to Mistral Files API, return the file_id.
-    async fn upload_file(&self, filename: &str, data: &[u8]) -> anyhow::Result<String> {
-        use reqwest::multipart::{Form, Part};
-
-        info!(
-            "MistralOcrProvider: uploading {} ({} bytes) to Files API",
-            filename,
-            data.len()
-        );
-
-        let part = Part::bytes(data.to_vec())
-            .file_name(filename.to_string())
-            .mime_str("application/pdf")?;
+    /// Upload a document to the Mistral Files API, return the file_id.
+    async fn upload_file(&self, part: reqwest::multipart::Part) -> anyhow::Result<String> {
+        use reqwest::multipart::Form;
 
         let form = Form::new()
             .part("file", part)
@@ -0,0 +1,97 @@
+//! Fixture-backed OCR provider for hermetic tests.
+//!
+//! Reads `{dir}/ocr/{filename}.json` for the uploaded file name, falling back
+//! to `{dir}/ocr/default.json`:
+//!
+//! ```json
+//! {"pages": ["text of page 1", "text of page 2"], "ocr_confidence": 0.95}
+//! ```
+//!
+//! An optional `page_confidence` array gives each page its own score, in
+//! which case `ocr_confidence` defaults to their mean.
+
+use super::{OcrInput, OcrPage, OcrProvider, OcrResult};
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::info;
+
+#[derive(Debug, Deserialize)]
+struct OcrFixture {
+    pages: Vec<String>,
+    ocr_confidence: Option<f64>,
+    #[serde(default)]
+    page_confidence: Vec<f64>,
+}
+
+const DEFAULT_CONFIDENCE: f64 = 0.95;
+
+pub struct MockProvider {
+    dir: PathBuf,
+}
+
+impl MockProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into().join("ocr"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl OcrProvider for MockProvider {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn process(&self, input: &OcrInput) -> anyhow::Result<OcrResult> {
+        let filename = input.filename();
+        let path = [format!("{}.json", filename), "default.json".to_string()]
+            .into_iter()
+            .map(|name| self.dir.join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                anyhow!(
+                    "No mock OCR fixture for {} in {}",
+                    filename,
+                    self.dir.display()
+                )
+            })?;
+        let fixture: OcrFixture = serde_json::from_slice(&std::fs::read(&path)?)
+            .with_context(|| format!("Invalid mock OCR fixture {}", path.display()))?;
+        info!(
+            "Mock OCR: {} pages for {} from {}",
+            fixture.pages.len(),
+            filename,
+            path.display()
+        );
+
+        let pages: Vec<OcrPage> = fixture
+            .pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| OcrPage {
+                page_num: i as u32 + 1,
+                text,
+                confidence: fixture.page_confidence.get(i).copied(),
+                pdf_page: None,
+            })
+            .collect();
+        let ocr_confidence = fixture
+            .ocr_confidence
+            .or_else(|| super::mean_page_confidence(&pages))
+            .unwrap_or(DEFAULT_CONFIDENCE);
+        Ok(OcrResult {
+            markdown: pages
+                .iter()
+                .map(|p| p.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            total_pages: pages.len() as u32,
+            pages,
+            metadata: serde_json::Value::Null,
+            ocr_confidence,
+            provider_name: "mock".to_string(),
+        })
+    }
+}
@@ -5,13 +5,25 @@
 
 pub mod docling;
 pub mod mistral;
+pub mod mock;
 pub mod smol_docling;
 
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use reqwest::multipart::Part;
+use serde::{Deserialize, Serialize};
+
 /// Per-page OCR output (always 1-indexed).
 #[derive(Debug, Clone)]
 pub struct OcrPage {
     pub page_num: u32,
     pub text: String,
+    /// Provider-reported confidence for this page (0-1), when it has one
+    pub confidence: Option<f64>,
+    /// Page of the source PDF this is, when the provider reports it (it
+    /// differs from `page_num` when only some pages were OCR'd)
+    pub pdf_page: Option<u32>,
 }
 
 /// Unified OCR result returned by every provider.
@@ -25,17 +37,205 @@ pub struct OcrResult {
     pub provider_name: String,
 }
 
-/// Input to an OCR provider — either raw bytes or a remote URL.
+/// Mean of the page confidences a provider reported, if it reported any.
+pub fn mean_page_confidence(pages: &[OcrPage]) -> Option<f64> {
+    let scores: Vec<f64> = pages.iter().filter_map(|p| p.confidence).collect();
+    if scores.is_empty() {
+        return None;
+    }
+    Some(scores.iter().sum::<f64>() / scores.len() as f64)
+}
+
+/// Input to an OCR provider — raw bytes, an upload spooled to disk, or a remote URL.
 pub enum OcrInput {
     Bytes { filename: String, data: Vec<u8> },
+    File { filename: String, file: SpooledFile },
     Url { filename: String, url: String },
 }
 
+impl OcrInput {
+    pub fn filename(&self) -> &str {
+        match self {
+            OcrInput::Bytes { filename, .. }
+            | OcrInput::File { filename, .. }
+            | OcrInput::Url { filename, .. } => filename,
+        }
+    }
+
+    /// The document as a multipart `file` part. Spooled files are streamed
+    /// from disk; URLs are downloaded first with `client`, for services that
+    /// only accept uploads (`service` names them in errors).
+    pub async fn file_part(&self, client: &reqwest::Client, service: &str) -> anyhow::Result<Part> {
+        let part = match self {
+            OcrInput::Bytes { data, .. } => Part::bytes(data.clone()),
+            OcrInput::File { file, .. } => file.part().await?,
+            OcrInput::Url { url, .. } => {
+                tracing::info!("Downloading {} for {}", url, service);
+                let resp = client.get(url).send().await?;
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    let text = resp.text().await.unwrap_or_default();
+                    anyhow::bail!(
+                        "Failed to download file for {} ({}): {}",
+                        service,
+                        status,
+                        text
+                    );
+                }
+                Part::bytes(resp.bytes().await?.to_vec())
+            }
+        };
+        Ok(part
+            .file_name(self.filename().to_string())
+            .mime_str("application/pdf")?)
+    }
+}
+
+/// An uploaded file written to `UPLOAD_SPOOL_DIR` (default the system temp
+/// dir) as it arrives, so large uploads are not held in memory. The file is
+/// removed when this is dropped.
+#[derive(Debug)]
+pub struct SpooledFile {
+    path: PathBuf,
+}
+
+impl SpooledFile {
+    /// Create an empty spool file, returning it with a handle to write it.
+    pub async fn create() -> std::io::Result<(Self, tokio::fs::File)> {
+        let dir = std::env::var("UPLOAD_SPOOL_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("generic-extractor-uploads"));
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!("upload_{}", uuid::Uuid::new_v4()));
+        let file = tokio::fs::File::create(&path).await?;
+        Ok((Self { path }, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn read(&self) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(&self.path).await
+    }
+
+    /// A multipart part that streams the file.
+    pub async fn part(&self) -> std::io::Result<Part> {
+        let file = tokio::fs::File::open(&self.path).await?;
+        let len = file.metadata().await?.len();
+        Ok(Part::stream_with_length(reqwest::Body::from(file), len))
+    }
+}
+
+impl Drop for SpooledFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::warn!(
+                "Failed to remove spooled upload {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Table structure recognition mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TableMode {
+    Off,
+    Fast,
+    Accurate,
+}
+
+/// OCR settings from a config's `ocr_options` or the `ocr_options` query
+/// parameter. Each provider applies the ones it supports.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OcrOptions {
+    /// OCR every page, even pages with a text layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force_ocr: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_mode: Option<TableMode>,
+    /// Engine the sidecar runs (Docling: `easyocr`, `tesseract`, `rapidocr`, `ocrmac`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_engine: Option<String>,
+    /// Language hints as ISO 639-1 codes (e.g. `["pt", "en"]`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+    /// Provider-specific fields, keyed by provider name, forwarded as-is.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, serde_json::Map<String, serde_json::Value>>,
+}
+
+impl OcrOptions {
+    /// These options with every field set in `overrides` replaced.
+    pub fn merge(&self, overrides: &OcrOptions) -> OcrOptions {
+        let mut extra = self.extra.clone();
+        for (provider, fields) in &overrides.extra {
+            extra
+                .entry(provider.clone())
+                .or_default()
+                .extend(fields.clone());
+        }
+        OcrOptions {
+            force_ocr: overrides.force_ocr.or(self.force_ocr),
+            table_mode: overrides.table_mode.or(self.table_mode),
+            ocr_engine: overrides
+                .ocr_engine
+                .clone()
+                .or_else(|| self.ocr_engine.clone()),
+            languages: if overrides.languages.is_empty() {
+                self.languages.clone()
+            } else {
+                overrides.languages.clone()
+            },
+            extra,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The common options plus `extra[provider]`, as one JSON object.
+    pub fn for_provider(&self, provider: &str) -> serde_json::Map<String, serde_json::Value> {
+        let mut fields = match serde_json::to_value(OcrOptions {
+            extra: BTreeMap::new(),
+            ..self.clone()
+        }) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        };
+        if let Some(extra) = self.extra.get(provider) {
+            fields.extend(extra.clone());
+        }
+        fields
+    }
+}
+
 /// Async trait implemented by each OCR backend.
 #[async_trait::async_trait]
 pub trait OcrProvider: Send + Sync {
     fn name(&self) -> &str;
     async fn process(&self, input: &OcrInput) -> anyhow::Result<OcrResult>;
+
+    /// Run OCR with `options`. Providers that take no options ignore them.
+    async fn process_with_options(
+        &self,
+        input: &OcrInput,
+        options: &OcrOptions,
+    ) -> anyhow::Result<OcrResult> {
+        let _ = options;
+        self.process(input).await
+    }
+
+    /// Check that the backend is reachable. Providers without a cheap probe
+    /// report healthy.
+    async fn health(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 /// Known provider identifiers used for registry lookup.
@@ -48,6 +248,7 @@ pub enum OcrProviderKind {
 
 impl OcrProviderKind {
     /// Parse a query-parameter string into a provider kind.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "docling" => Some(Self::Docling),
@@ -56,4 +257,73 @@ impl OcrProviderKind {
             _ => None,
         }
     }
+
+    /// The identifier `from_str` accepts.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Docling => "docling",
+            Self::MistralOcr => "mistral_ocr",
+            Self::SmolDocling => "smol_docling",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ocr_options_merge() {
+        let config: OcrOptions = serde_json::from_value(serde_json::json!({
+            "table_mode": "accurate",
+            "languages": ["pt"],
+            "extra": {"docling": {"images_scale": 2}, "mistral_ocr": {"pages": [0]}}
+        }))
+        .unwrap();
+        let query: OcrOptions = serde_json::from_value(serde_json::json!({
+            "force_ocr": true,
+            "languages": ["pt", "en"],
+            "extra": {"docling": {"do_picture_description": false}}
+        }))
+        .unwrap();
+
+        let merged = config.merge(&query);
+        assert_eq!(merged.force_ocr, Some(true));
+        assert_eq!(merged.table_mode, Some(TableMode::Accurate));
+        assert_eq!(merged.languages, ["pt", "en"]);
+        assert_eq!(
+            serde_json::Value::Object(merged.for_provider("docling")),
+            serde_json::json!({
+                "force_ocr": true,
+                "table_mode": "accurate",
+                "languages": ["pt", "en"],
+                "images_scale": 2,
+                "do_picture_description": false
+            })
+        );
+        assert!(!merged.for_provider("smol_docling").contains_key("pages"));
+        assert!(OcrOptions::default().is_empty());
+        assert!(serde_json::from_str::<OcrOptions>(r#"{"force": true}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spooled_file_is_removed_on_drop() {
+        use tokio::io::AsyncWriteExt;
+
+        let (spooled, mut out) = SpooledFile::create().await.unwrap();
+        out.write_all(b"%PDF-1.4").await.unwrap();
+        out.flush().await.unwrap();
+        let input = OcrInput::File {
+            filename: "doc.pdf".into(),
+            file: spooled,
+        };
+        assert_eq!(input.filename(), "doc.pdf");
+        let OcrInput::File { file, .. } = input else {
+            unreachable!()
+        };
+        assert_eq!(file.read().await.unwrap(), b"%PDF-1.4");
+        let path = file.path.clone();
+        drop(file);
+        assert!(!path.exists());
+    }
 }
@@ -1,8 +1,10 @@
 //! SmolDocling sidecar OCR provider.
+//!
+//! [`OcrOptions`] are sent like the Docling sidecar's; the sidecar reads
+//! `dpi` (from `extra.smol_docling`) and ignores the rest.
 
-use super::{OcrInput, OcrPage, OcrProvider, OcrResult};
+use super::{OcrInput, OcrOptions, OcrPage, OcrProvider, OcrResult};
 use serde::Deserialize;
-use tracing::info;
 
 /// SmolDocling sidecar response (same schema as docling sidecar).
 #[derive(Debug, Deserialize)]
@@ -41,32 +43,35 @@ impl OcrProvider for SmolDoclingProvider {
         "smol_docling"
     }
 
+    async fn health(&self) -> anyhow::Result<()> {
+        self.client
+            .get(format!("{}/health", self.url))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     async fn process(&self, input: &OcrInput) -> anyhow::Result<OcrResult> {
-        use reqwest::multipart::{Form, Part};
+        self.process_with_options(input, &OcrOptions::default())
+            .await
+    }
 
-        let (filename, file_data) = match input {
-            OcrInput::Bytes { filename, data } => (filename.clone(), data.clone()),
-            OcrInput::Url { filename, url } => {
-                info!("SmolDoclingProvider: downloading {} for sidecar", url);
-                let resp = self.client.get(url).send().await?;
-                if !resp.status().is_success() {
-                    let status = resp.status();
-                    let text = resp.text().await.unwrap_or_default();
-                    anyhow::bail!(
-                        "Failed to download file for SmolDocling ({}): {}",
-                        status,
-                        text
-                    );
-                }
-                (filename.clone(), resp.bytes().await?.to_vec())
-            }
-        };
+    async fn process_with_options(
+        &self,
+        input: &OcrInput,
+        options: &OcrOptions,
+    ) -> anyhow::Result<OcrResult> {
+        use reqwest::multipart::Form;
 
-        let part = Part::bytes(file_data)
-            .file_name(filename)
-            .mime_str("application/pdf")?;
+        let part = input.file_part(&self.client, "SmolDocling").await?;
 
-        let form = Form::new().part("file", part);
+        let mut form = Form::new().part("file", part);
+        let options = options.for_provider("smol_docling");
+        if !options.is_empty() {
+            form = form.text("options", serde_json::Value::Object(options).to_string());
+        }
 
         let response = self
             .client
@@ -91,6 +96,8 @@ impl OcrProvider for SmolDoclingProvider {
                 .map(|p| OcrPage {
                     page_num: p.page_num,
                     text: p.text,
+                    confidence: None,
+                    pdf_page: None,
                 })
                 .collect(),
             total_pages: result.total_pages,
@@ -0,0 +1,110 @@
+//! Fixture playback in place of OpenRouter, for hermetic tests.
+//!
+//! Fixtures are JSON files in `{dir}/llm/`, tried in file-name order:
+//!
+//! ```json
+//! {"contains": ["hierarchical structure"], "schema": null, "response": {"summary": "..."}}
+//! ```
+//!
+//! The first fixture whose `contains` strings all occur in the request's
+//! message text (and whose `schema` names the request's JSON schema, if set)
+//! answers the request. A string `response` is returned as is; any other JSON
+//! value is returned as its JSON text.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::debug;
+
+use super::{ContentPart, Message, MessageContent};
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    #[serde(skip)]
+    name: String,
+    #[serde(default)]
+    contains: Vec<String>,
+    #[serde(default)]
+    schema: Option<String>,
+    response: serde_json::Value,
+}
+
+pub struct MockLlmClient {
+    fixtures: Vec<Fixture>,
+    calls: AtomicUsize,
+}
+
+impl MockLlmClient {
+    /// Load the fixtures in `{dir}/llm/`.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let llm_dir = dir.join("llm");
+        let mut paths: Vec<_> = std::fs::read_dir(&llm_dir)
+            .with_context(|| format!("Failed to read mock LLM fixtures in {}", llm_dir.display()))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .collect();
+        paths.sort();
+
+        let fixtures = paths
+            .iter()
+            .map(|path| {
+                let mut fixture: Fixture = serde_json::from_slice(&std::fs::read(path)?)
+                    .with_context(|| format!("Invalid mock LLM fixture {}", path.display()))?;
+                fixture.name = path.display().to_string();
+                Ok(fixture)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            fixtures,
+            calls: AtomicUsize::new(0),
+        })
+    }
+
+    /// Answer a request from the first matching fixture.
+    pub fn respond(&self, messages: &[Message], schema: Option<&str>) -> Result<String> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let text: String = messages
+            .iter()
+            .flat_map(|m| match &m.content {
+                MessageContent::Text(text) => vec![text.as_str()],
+                MessageContent::Parts(parts) => parts
+                    .iter()
+                    .filter_map(|p| match p {
+                        ContentPart::Text { text, .. } => Some(text.as_str()),
+                        ContentPart::ImageUrl { .. } => None,
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let fixture = self
+            .fixtures
+            .iter()
+            .find(|f| {
+                f.schema.as_deref().is_none_or(|s| Some(s) == schema)
+                    && f.contains.iter().all(|c| text.contains(c.as_str()))
+            })
+            .ok_or_else(|| {
+                let start: String = text.chars().take(120).collect();
+                anyhow!(
+                    "No mock LLM fixture matches request (schema: {:?}, text: {:?}...)",
+                    schema,
+                    start
+                )
+            })?;
+        debug!("Mock LLM answered from {}", fixture.name);
+
+        Ok(match &fixture.response {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    }
+
+    /// Requests answered or refused so far.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+}
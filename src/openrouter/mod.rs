@@ -1,22 +1,97 @@
 #![allow(dead_code)]
 //! OpenRouter API client for LLM interactions.
 
+pub mod mock;
+
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use tracing::{debug, info};
 
+use mock::MockLlmClient;
+
 const OPENROUTER_API_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const OPENROUTER_MODELS_URL: &str = "https://openrouter.ai/api/v1/models";
 const DEFAULT_MODEL: &str = "google/gemini-3-flash-preview";
 
+/// Tokens used by the OpenRouter calls made inside [`metered`].
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+    /// Prompt tokens read from the provider's prompt cache
+    cached_tokens: AtomicU64,
+    /// Cost OpenRouter reported, in billionths of a USD
+    cost_nanos: AtomicU64,
+    /// Whether some response came without a cost
+    unpriced: AtomicBool,
+}
+
+impl UsageMeter {
+    pub fn prompt_tokens(&self) -> u64 {
+        self.prompt_tokens.load(Ordering::Relaxed)
+    }
+
+    pub fn completion_tokens(&self) -> u64 {
+        self.completion_tokens.load(Ordering::Relaxed)
+    }
+
+    pub fn cached_tokens(&self) -> u64 {
+        self.cached_tokens.load(Ordering::Relaxed)
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens() + self.completion_tokens()
+    }
+
+    /// What the calls cost, cache discounts included, when OpenRouter
+    /// reported a cost for every one of them.
+    pub fn reported_cost(&self) -> Option<f64> {
+        if self.unpriced.load(Ordering::Relaxed) || self.total_tokens() == 0 {
+            return None;
+        }
+        Some(self.cost_nanos.load(Ordering::Relaxed) as f64 / 1e9)
+    }
+
+    fn add(&self, usage: &Usage) {
+        self.prompt_tokens
+            .fetch_add(usage.prompt_tokens as u64, Ordering::Relaxed);
+        self.completion_tokens
+            .fetch_add(usage.completion_tokens as u64, Ordering::Relaxed);
+        self.cached_tokens
+            .fetch_add(usage.cached_tokens() as u64, Ordering::Relaxed);
+        match usage.cost {
+            Some(cost) => {
+                self.cost_nanos
+                    .fetch_add((cost * 1e9).round() as u64, Ordering::Relaxed);
+            }
+            None => self.unpriced.store(true, Ordering::Relaxed),
+        }
+    }
+}
+
+tokio::task_local! {
+    static METER: Arc<UsageMeter>;
+}
+
+/// Run `fut`, adding the usage of every OpenRouter response it receives to `meter`.
+pub async fn metered<F: Future>(meter: Arc<UsageMeter>, fut: F) -> F::Output {
+    METER.scope(meter, fut).await
+}
+
 /// OpenRouter client for chat completions.
 #[derive(Clone)]
 pub struct OpenRouterClient {
     client: Client,
     api_key: String,
     model: String,
+    /// Answers requests from fixtures instead of calling OpenRouter
+    mock: Option<Arc<MockLlmClient>>,
 }
 
 impl OpenRouterClient {
@@ -29,15 +104,50 @@ impl OpenRouterClient {
             client: Client::new(),
             api_key,
             model: DEFAULT_MODEL.to_string(),
+            mock: None,
         })
     }
 
+    /// Create a client that plays back fixtures and never touches the network.
+    pub fn mock(mock: MockLlmClient) -> Self {
+        Self {
+            client: Client::new(),
+            api_key: String::new(),
+            model: DEFAULT_MODEL.to_string(),
+            mock: Some(Arc::new(mock)),
+        }
+    }
+
     /// Create a client with a specific model.
     pub fn with_model(mut self, model: impl Into<String>) -> Self {
         self.model = model.into();
         self
     }
 
+    /// The model requests are sent to.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Check that OpenRouter answers, with a cheap models list call.
+    pub async fn health(&self) -> Result<()> {
+        if self.mock.is_some() {
+            return Ok(());
+        }
+        let response = self
+            .client
+            .get(OPENROUTER_MODELS_URL)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("OpenRouter models list returned {}", status);
+        }
+        Ok(())
+    }
+
     /// Send a chat completion request with text only.
     pub async fn chat(&self, messages: Vec<Message>) -> Result<String> {
         let request = ChatCompletionRequest {
@@ -50,6 +160,7 @@ impl OpenRouterClient {
                 only: Some(vec!["Google".to_string()]),
                 allow_fallbacks: Some(false),
             }),
+            usage: UsageAccounting { include: true },
         };
 
         self.send_request(request).await
@@ -77,6 +188,7 @@ impl OpenRouterClient {
                 only: Some(vec!["Google".to_string()]),
                 allow_fallbacks: Some(false),
             }),
+            usage: UsageAccounting { include: true },
         };
 
         let response = self.send_request(request).await?;
@@ -86,6 +198,13 @@ impl OpenRouterClient {
     }
 
     async fn send_request(&self, request: ChatCompletionRequest) -> Result<String> {
+        if let Some(ref mock) = self.mock {
+            let schema = request.response_format.as_ref().map(|f| match f {
+                ResponseFormat::JsonSchema { json_schema } => json_schema.name.as_str(),
+            });
+            return mock.respond(&request.messages, schema);
+        }
+
         debug!("Sending request to OpenRouter: model={}", request.model);
 
         let response = self
@@ -117,11 +236,13 @@ impl OpenRouterClient {
             .unwrap_or_default();
 
         info!(
-            "OpenRouter response: {} tokens (prompt: {}, completion: {})",
+            "OpenRouter response: {} tokens (prompt: {}, cached: {}, completion: {})",
             response.usage.total_tokens,
             response.usage.prompt_tokens,
+            response.usage.cached_tokens(),
             response.usage.completion_tokens
         );
+        let _ = METER.try_with(|meter| meter.add(&response.usage));
 
         Ok(content)
     }
@@ -142,6 +263,13 @@ struct ChatCompletionRequest {
     /// Provider routing for cache consistency
     #[serde(skip_serializing_if = "Option::is_none")]
     provider: Option<ProviderRouting>,
+    /// Ask for cached tokens and cost in `usage`
+    usage: UsageAccounting,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageAccounting {
+    include: bool,
 }
 
 /// Provider routing options for cache consistency.
@@ -188,6 +316,25 @@ struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    /// USD, with usage accounting on
+    #[serde(default)]
+    cost: Option<f64>,
+}
+
+impl Usage {
+    fn cached_tokens(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .map_or(0, |details| details.cached_tokens)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
 }
 
 // ============================================================================
@@ -218,8 +365,31 @@ pub enum MessageContent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ContentPart {
-    Text { text: String },
-    ImageUrl { image_url: ImageUrl },
+    Text {
+        text: String,
+        /// Cache breakpoint: the prompt up to and including this part is cached
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ImageUrl {
+        image_url: ImageUrl,
+    },
+}
+
+/// A prompt cache breakpoint. Anthropic and Gemini models reuse the prefix
+/// it ends for later requests that start with the same content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        Self {
+            kind: "ephemeral".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -235,6 +405,18 @@ impl Message {
         }
     }
 
+    /// A system message ending in a cache breakpoint, for a prefix (instructions
+    /// and the document) that repeats across requests.
+    pub fn cached_system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: MessageContent::Parts(vec![ContentPart::Text {
+                text: content.into(),
+                cache_control: Some(CacheControl::ephemeral()),
+            }]),
+        }
+    }
+
     pub fn user(content: impl Into<String>) -> Self {
         Self {
             role: Role::User,
@@ -251,7 +433,10 @@ impl Message {
 
     /// Create a user message with text and images (base64 encoded).
     pub fn user_with_images(text: impl Into<String>, images: Vec<Vec<u8>>) -> Self {
-        let mut parts = vec![ContentPart::Text { text: text.into() }];
+        let mut parts = vec![ContentPart::Text {
+            text: text.into(),
+            cache_control: None,
+        }];
 
         for image_data in images {
             let base64_data = BASE64.encode(&image_data);
@@ -268,3 +453,37 @@ impl Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_breakpoint_and_usage() {
+        let message = serde_json::to_value(Message::cached_system("doc")).unwrap();
+        assert_eq!(
+            message["content"][0],
+            serde_json::json!({"type": "text", "text": "doc", "cache_control": {"type": "ephemeral"}})
+        );
+        let plain = serde_json::to_value(Message::user_with_images("q", Vec::new())).unwrap();
+        assert!(plain["content"][0].get("cache_control").is_none());
+
+        let meter = UsageMeter::default();
+        let usage: Usage = serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 1000, "completion_tokens": 200, "total_tokens": 1200,
+            "prompt_tokens_details": {"cached_tokens": 800}, "cost": 0.0005
+        }))
+        .unwrap();
+        meter.add(&usage);
+        assert_eq!(meter.cached_tokens(), 800);
+        assert_eq!(meter.reported_cost(), Some(0.0005));
+        // Without usage accounting there is no cost to report
+        let usage: Usage = serde_json::from_value(serde_json::json!({
+            "prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12
+        }))
+        .unwrap();
+        meter.add(&usage);
+        assert_eq!(meter.cached_tokens(), 800);
+        assert_eq!(meter.reported_cost(), None);
+    }
+}
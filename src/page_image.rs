@@ -0,0 +1,94 @@
+//! Page images for showing the source document next to extracted nodes.
+//!
+//! PDF pages are rasterized with poppler's `pdftoppm` (`PDFTOPPM_PATH`,
+//! default `pdftoppm` on `PATH`). Image uploads have a single page, which is
+//! re-encoded as PNG. Rendered pages are cached in the object store next to
+//! the source file (see [`crate::object_store::page_image_key`]).
+
+use anyhow::{anyhow, bail, Context, Result};
+
+pub const DEFAULT_DPI: u32 = 150;
+pub const MIN_DPI: u32 = 36;
+pub const MAX_DPI: u32 = 600;
+
+/// DPI to pre-render every page at right after upload (`PAGE_IMAGE_PRERENDER_DPI`).
+/// Unset or 0 renders on first request only.
+pub fn prerender_dpi_from_env() -> Option<u32> {
+    std::env::var("PAGE_IMAGE_PRERENDER_DPI")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|dpi| *dpi > 0)
+        .map(|dpi: u32| dpi.clamp(MIN_DPI, MAX_DPI))
+}
+
+/// Render page `page` (1-indexed) of `source` as PNG.
+pub async fn render_page(source: &[u8], page: u32, dpi: u32) -> Result<Vec<u8>> {
+    if !source.starts_with(b"%PDF") {
+        if page != 1 {
+            bail!("Image sources have a single page");
+        }
+        return encode_png(source);
+    }
+
+    let dir = std::env::temp_dir().join(format!("page_image_{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&dir).await?;
+    let result = rasterize(&dir, source, page, dpi).await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn rasterize(dir: &std::path::Path, source: &[u8], page: u32, dpi: u32) -> Result<Vec<u8>> {
+    let pdf = dir.join("source.pdf");
+    tokio::fs::write(&pdf, source).await?;
+
+    let program = std::env::var("PDFTOPPM_PATH").unwrap_or_else(|_| "pdftoppm".to_string());
+    let output = tokio::process::Command::new(&program)
+        .arg("-png")
+        .arg("-singlefile")
+        .args(["-r", &dpi.to_string()])
+        .args(["-f", &page.to_string(), "-l", &page.to_string()])
+        .arg(&pdf)
+        .arg(dir.join("page"))
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {} (is poppler-utils installed?)", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    tokio::fs::read(dir.join("page.png"))
+        .await
+        .context("pdftoppm produced no image")
+}
+
+fn encode_png(data: &[u8]) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).context("Source is neither a PDF nor an image")?;
+    let mut png = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_image_source_renders_as_png() {
+        let mut source = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(4, 3)
+            .write_to(&mut source, image::ImageOutputFormat::Jpeg(90))
+            .unwrap();
+        let source = source.into_inner();
+
+        let png = render_page(&source, 1, DEFAULT_DPI).await.unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let decoded = image::load_from_memory(&png).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 3));
+        assert!(render_page(&source, 2, DEFAULT_DPI).await.is_err());
+        assert!(render_page(b"not an image", 1, DEFAULT_DPI).await.is_err());
+    }
+}
@@ -0,0 +1,496 @@
+//! Structured parties (partes) of Brazilian court cases.
+//!
+//! For configs with `structured_partes`, the LLM's `metadata.partes` is parsed
+//! into [`Parte`] records whatever shape it came in: a list of objects with
+//! Portuguese or English keys, a `{"ativo": [...], "passivo": [...]}` map, or
+//! free text such as `"João da Silva (autor), CPF 123.456.789-00"`. CPF/CNPJ
+//! are reformatted, `tipo_pessoa` follows from the document number, and
+//! lawyers are split into name and OAB registration. When the result is
+//! incomplete (see [`is_complete`]), `Extractor::complete_partes` asks the LLM
+//! for the parties alone and [`merge`]s its answer in.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::readable_id::fold_accent;
+
+/// Side of the case a party is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Polo {
+    Ativo,
+    Passivo,
+    Terceiro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TipoPessoa {
+    #[serde(rename = "FÍSICA")]
+    Fisica,
+    #[serde(rename = "JURÍDICA")]
+    Juridica,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Advogado {
+    pub nome: String,
+    /// Registration as `OAB/UF 123456`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oab: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parte {
+    pub id: String,
+    pub nome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polo: Option<Polo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tipo_pessoa: Option<TipoPessoa>,
+    /// Formatted CPF (`000.000.000-00`) or CNPJ (`00.000.000/0000-00`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpf_cnpj: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub advogados: Vec<Advogado>,
+}
+
+const NAME_KEYS: &[&str] = &["nome", "name", "razao_social", "parte"];
+const POLO_KEYS: &[&str] = &["polo", "role", "papel", "qualidade", "tipo"];
+const DOCUMENT_KEYS: &[&str] = &["cpf_cnpj", "cpf", "cnpj", "documento", "document", "tax_id"];
+const LAWYER_KEYS: &[&str] = &["advogados", "advogado", "lawyers", "procuradores"];
+
+const ATIVO_WORDS: &[&str] = &[
+    "ativo",
+    "autor",
+    "autora",
+    "autores",
+    "requerente",
+    "exequente",
+    "apelante",
+    "reclamante",
+    "impetrante",
+    "agravante",
+    "embargante",
+    "recorrente",
+    "plaintiff",
+];
+const PASSIVO_WORDS: &[&str] = &[
+    "passivo",
+    "reu",
+    "re",
+    "reus",
+    "requerido",
+    "requerida",
+    "executado",
+    "executada",
+    "apelado",
+    "apelada",
+    "reclamado",
+    "reclamada",
+    "impetrado",
+    "agravado",
+    "agravada",
+    "embargado",
+    "recorrido",
+    "recorrida",
+    "defendant",
+];
+const TERCEIRO_WORDS: &[&str] = &["terceiro", "interessado", "interessada", "assistente"];
+
+/// Parse whatever the LLM returned for `partes`.
+pub fn parse(value: &Value) -> Vec<Parte> {
+    let patterns = Patterns::new();
+    let mut partes = Vec::new();
+    collect(value, None, &patterns, &mut partes);
+    let mut merged: Vec<Parte> = Vec::new();
+    for parte in partes {
+        merge_one(&mut merged, parte);
+    }
+    for (i, parte) in merged.iter_mut().enumerate() {
+        if parte.id.is_empty() {
+            parte.id = format!("parte_{}", i + 1);
+        }
+    }
+    merged
+}
+
+/// Complete means at least one party on each side and a side for everyone.
+pub fn is_complete(partes: &[Parte]) -> bool {
+    partes.iter().all(|p| p.polo.is_some())
+        && partes.iter().any(|p| p.polo == Some(Polo::Ativo))
+        && partes.iter().any(|p| p.polo == Some(Polo::Passivo))
+}
+
+/// Add the parties of a follow-up answer to `partes`: fields missing on a
+/// party of the same name are filled in, unknown parties are appended.
+pub fn merge(partes: &mut Vec<Parte>, follow_up: Vec<Parte>) {
+    for mut parte in follow_up {
+        parte.id.clear();
+        merge_one(partes, parte);
+    }
+    let mut next = partes.len();
+    let taken: Vec<String> = partes.iter().map(|p| p.id.clone()).collect();
+    for parte in partes.iter_mut().filter(|p| p.id.is_empty()) {
+        loop {
+            let id = format!("parte_{}", next);
+            next += 1;
+            if !taken.contains(&id) {
+                parte.id = id;
+                break;
+            }
+        }
+    }
+}
+
+fn merge_one(partes: &mut Vec<Parte>, parte: Parte) {
+    let key = fold(&parte.nome);
+    let Some(existing) = partes.iter_mut().find(|p| fold(&p.nome) == key) else {
+        partes.push(parte);
+        return;
+    };
+    if existing.id.is_empty() {
+        existing.id = parte.id;
+    }
+    existing.polo = existing.polo.or(parte.polo);
+    existing.tipo_pessoa = existing.tipo_pessoa.or(parte.tipo_pessoa);
+    existing.cpf_cnpj = existing.cpf_cnpj.take().or(parte.cpf_cnpj);
+    for advogado in parte.advogados {
+        match existing
+            .advogados
+            .iter_mut()
+            .find(|a| fold(&a.nome) == fold(&advogado.nome))
+        {
+            Some(known) => known.oab = known.oab.take().or(advogado.oab),
+            None => existing.advogados.push(advogado),
+        }
+    }
+}
+
+struct Patterns {
+    document: Regex,
+    oab: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        Self {
+            document: Regex::new(r"\d{2,3}\.?\d{3}\.?\d{3}(?:/?\d{4})?-?\d{2}")
+                .expect("valid built-in regex"),
+            oab: Regex::new(r"(?i)OAB\s*[/\-]?\s*([A-Z]{2})\s*(?:n[º°o.]*\s*)?([\d.]{3,8}[A-Z]?)")
+                .expect("valid built-in regex"),
+        }
+    }
+}
+
+fn collect(value: &Value, polo: Option<Polo>, patterns: &Patterns, out: &mut Vec<Parte>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                collect(item, polo, patterns, out);
+            }
+        }
+        Value::Object(map) => {
+            // {"ativo": [...], "passivo": [...]}
+            let sides: Vec<(Polo, &Value)> = map
+                .iter()
+                .filter_map(|(k, v)| Some((parse_polo(k)?, v)))
+                .filter(|(_, v)| v.is_array() || v.is_object())
+                .collect();
+            if !sides.is_empty() && get_str(map, NAME_KEYS).is_none() {
+                for (side, v) in sides {
+                    collect(v, Some(side), patterns, out);
+                }
+                return;
+            }
+            if let Some(parte) = parse_object(map, polo, patterns) {
+                out.push(parte);
+            }
+        }
+        Value::String(text) => {
+            if let Some(parte) = parse_text(text, polo, patterns) {
+                out.push(parte);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_object(
+    map: &serde_json::Map<String, Value>,
+    polo: Option<Polo>,
+    patterns: &Patterns,
+) -> Option<Parte> {
+    let nome = clean_name(get_str(map, NAME_KEYS)?);
+    if nome.is_empty() {
+        return None;
+    }
+    let document = get_str(map, DOCUMENT_KEYS).and_then(format_document);
+    let tipo_pessoa = map
+        .get("tipo_pessoa")
+        .and_then(Value::as_str)
+        .and_then(parse_tipo_pessoa)
+        .or_else(|| document.as_deref().map(tipo_from_document));
+    let advogados = LAWYER_KEYS
+        .iter()
+        .filter_map(|k| map.get(*k))
+        .flat_map(|v| parse_advogados(v, patterns))
+        .collect();
+    Some(Parte {
+        id: map
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        nome,
+        polo: get_str(map, POLO_KEYS).and_then(parse_polo).or(polo),
+        tipo_pessoa,
+        cpf_cnpj: document,
+        advogados,
+    })
+}
+
+/// `"João da Silva (autor), CPF 123.456.789-00, adv. Maria OAB/SP 123.456"`
+fn parse_text(text: &str, polo: Option<Polo>, patterns: &Patterns) -> Option<Parte> {
+    let nome = clean_name(text.split(['(', ',', ';', '–']).next().unwrap_or_default());
+    if nome.is_empty() {
+        return None;
+    }
+    let document = patterns
+        .document
+        .find(text)
+        .and_then(|m| format_document(m.as_str()));
+    let advogados = patterns
+        .oab
+        .captures(text)
+        .map(|caps| {
+            // The lawyer's name is the text between the last separator and "OAB"
+            let before = &text[..caps.get(0).map_or(0, |m| m.start())];
+            let nome = before
+                .rsplit([',', ';', '(', ':'])
+                .next()
+                .map(|s| {
+                    let s = s.trim();
+                    let s = s
+                        .strip_prefix("adv.")
+                        .or_else(|| s.strip_prefix("advogado"))
+                        .or_else(|| s.strip_prefix("advogada"))
+                        .unwrap_or(s);
+                    clean_name(s)
+                })
+                .unwrap_or_default();
+            vec![Advogado {
+                nome,
+                oab: Some(format_oab(&caps[1], &caps[2])),
+            }]
+        })
+        .unwrap_or_default();
+    Some(Parte {
+        id: String::new(),
+        nome,
+        polo: parse_polo(text).or(polo),
+        tipo_pessoa: document.as_deref().map(tipo_from_document),
+        cpf_cnpj: document,
+        advogados,
+    })
+}
+
+fn parse_advogados(value: &Value, patterns: &Patterns) -> Vec<Advogado> {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .flat_map(|v| parse_advogados(v, patterns))
+            .collect(),
+        Value::Object(map) => {
+            let Some(nome) = get_str(map, NAME_KEYS).map(clean_name) else {
+                return Vec::new();
+            };
+            let oab = map.get("oab").and_then(Value::as_str).and_then(|raw| {
+                let raw = if raw.to_uppercase().contains("OAB") {
+                    raw.to_string()
+                } else {
+                    format!("OAB {}", raw)
+                };
+                let caps = patterns.oab.captures(&raw)?;
+                Some(format_oab(&caps[1], &caps[2]))
+            });
+            vec![Advogado { nome, oab }]
+        }
+        Value::String(text) => {
+            let caps = patterns.oab.captures(text);
+            let nome = match caps.as_ref().and_then(|c| c.get(0)) {
+                Some(m) => &text[..m.start()],
+                None => text.as_str(),
+            };
+            let nome = clean_name(nome.trim_end_matches([' ', ',', '(', '-', '–']));
+            if nome.is_empty() {
+                return Vec::new();
+            }
+            vec![Advogado {
+                nome,
+                oab: caps.map(|c| format_oab(&c[1], &c[2])),
+            }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn get_str<'a>(map: &'a serde_json::Map<String, Value>, keys: &[&str]) -> Option<&'a str> {
+    keys.iter()
+        .find_map(|k| map.get(*k).and_then(Value::as_str))
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+fn parse_polo(text: &str) -> Option<Polo> {
+    let folded = fold(text);
+    let words: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let has = |list: &[&str]| words.iter().any(|w| list.contains(w));
+    if has(ATIVO_WORDS) {
+        Some(Polo::Ativo)
+    } else if has(PASSIVO_WORDS) {
+        Some(Polo::Passivo)
+    } else if has(TERCEIRO_WORDS) {
+        Some(Polo::Terceiro)
+    } else {
+        None
+    }
+}
+
+fn parse_tipo_pessoa(text: &str) -> Option<TipoPessoa> {
+    let folded = fold(text);
+    if folded.contains("fisica") || folded == "pf" {
+        Some(TipoPessoa::Fisica)
+    } else if folded.contains("juridica") || folded == "pj" {
+        Some(TipoPessoa::Juridica)
+    } else {
+        None
+    }
+}
+
+fn tipo_from_document(document: &str) -> TipoPessoa {
+    if document.contains('/') {
+        TipoPessoa::Juridica
+    } else {
+        TipoPessoa::Fisica
+    }
+}
+
+/// Format an 11-digit CPF or 14-digit CNPJ; anything else is not a document.
+fn format_document(raw: &str) -> Option<String> {
+    let d: String = raw.chars().filter(char::is_ascii_digit).collect();
+    match d.len() {
+        11 => Some(format!(
+            "{}.{}.{}-{}",
+            &d[0..3],
+            &d[3..6],
+            &d[6..9],
+            &d[9..11]
+        )),
+        14 => Some(format!(
+            "{}.{}.{}/{}-{}",
+            &d[0..2],
+            &d[2..5],
+            &d[5..8],
+            &d[8..12],
+            &d[12..14]
+        )),
+        _ => None,
+    }
+}
+
+fn format_oab(uf: &str, number: &str) -> String {
+    let number: String = number
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect();
+    format!("OAB/{} {}", uf.to_uppercase(), number.to_uppercase())
+}
+
+fn clean_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '.')
+        .to_string()
+}
+
+fn fold(text: &str) -> String {
+    text.trim()
+        .chars()
+        .map(fold_accent)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_partes() {
+        let partes = parse(&json!([
+            {"id": "parte_1", "nome": "João da Silva", "polo": "Autor", "cpf": "12345678900",
+             "advogados": ["Maria Souza OAB/SP 123.456"]},
+            {"name": "Azul Linhas Aéreas S.A.", "role": "réu", "cnpj": "09.296.295/0001-60",
+             "advogados": [{"nome": "Pedro Lima", "oab": "RJ 98765"}]},
+            "Ministério Público (interessado)",
+            {"nome": "joão da silva", "tipo_pessoa": "física"},
+        ]));
+        assert_eq!(partes.len(), 3);
+
+        let joao = &partes[0];
+        assert_eq!(joao.id, "parte_1");
+        assert_eq!(joao.polo, Some(Polo::Ativo));
+        assert_eq!(joao.cpf_cnpj.as_deref(), Some("123.456.789-00"));
+        assert_eq!(joao.tipo_pessoa, Some(TipoPessoa::Fisica));
+        assert_eq!(
+            joao.advogados,
+            vec![Advogado {
+                nome: "Maria Souza".into(),
+                oab: Some("OAB/SP 123456".into())
+            }]
+        );
+
+        let azul = &partes[1];
+        assert_eq!(azul.id, "parte_2");
+        assert_eq!(azul.polo, Some(Polo::Passivo));
+        assert_eq!(azul.tipo_pessoa, Some(TipoPessoa::Juridica));
+        assert_eq!(azul.advogados[0].oab.as_deref(), Some("OAB/RJ 98765"));
+        assert_eq!(partes[2].polo, Some(Polo::Terceiro));
+        assert!(is_complete(&partes));
+
+        let serialized = serde_json::to_value(azul).unwrap();
+        assert_eq!(serialized["polo"], "PASSIVO");
+        assert_eq!(serialized["tipo_pessoa"], "JURÍDICA");
+    }
+
+    #[test]
+    fn test_sides_text_and_merge() {
+        let mut partes = parse(&json!({
+            "ativo": ["Ana Costa, CPF 111.222.333-44, adv. Carlos Reis OAB/MG 5.432"],
+            "passivo": [],
+        }));
+        assert_eq!(partes.len(), 1);
+        assert_eq!(partes[0].nome, "Ana Costa");
+        assert_eq!(partes[0].polo, Some(Polo::Ativo));
+        assert_eq!(partes[0].advogados[0].nome, "Carlos Reis");
+        assert_eq!(partes[0].advogados[0].oab.as_deref(), Some("OAB/MG 5432"));
+        assert!(!is_complete(&partes));
+
+        let follow_up = parse(&json!([
+            {"nome": "Ana Costa", "polo": "ATIVO", "advogados": [{"nome": "Carlos Reis", "oab": "OAB/MG 5432"}]},
+            {"nome": "Banco X S.A.", "polo": "PASSIVO", "cpf_cnpj": "00000000000191"},
+        ]));
+        merge(&mut partes, follow_up);
+        assert_eq!(partes.len(), 2);
+        assert_eq!(partes[0].advogados.len(), 1);
+        assert_eq!(partes[1].id, "parte_2");
+        assert_eq!(partes[1].cpf_cnpj.as_deref(), Some("00.000.000/0001-91"));
+        assert!(is_complete(&partes));
+    }
+}
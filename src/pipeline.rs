@@ -0,0 +1,151 @@
+//! Configurable extraction pipeline.
+//!
+//! A config's `pipeline` lists the stages a document extraction runs, in
+//! order. When unset, [`DEFAULT_PIPELINE`] runs every stage except the opt-in
+//! `folios`, `signatures`, `translate` and `redact`. The stage runner itself lives with the background task in `server.rs`.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::ExtractionConfig;
+
+/// One step of a document extraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    /// Run the OCR provider (skipped when resuming from archived OCR)
+    Ocr,
+    /// LLM call producing the node tree, summaries, metadata and relationships
+    Structure,
+    /// Check node boundaries and labels against the document's own índice
+    /// (see `toc`)
+    Toc,
+    /// Store each node's page range of OCR text as lazy-loadable content
+    SliceContent,
+    /// Remap node page ranges to the printed folio numbers (opt-in; see `folios`)
+    Folios,
+    /// Read signature blocks into node metadata (opt-in; see `signatures`)
+    Signatures,
+    /// Regex entity patterns over node content (fills `reference_index`)
+    Entities,
+    /// Resolve `readable_id` from `readable_id_pattern`, the LLM, or a slug
+    ReadableId,
+    /// Link near-duplicate documents via `duplicate_of`
+    Dedup,
+    /// Translate node summaries into `translate_to` (opt-in)
+    Translate,
+    /// Store a PII-redacted copy of node content (opt-in; see `redaction`)
+    Redact,
+    /// Persist to storage (only when the request asks for `upload=true`)
+    Upload,
+}
+
+/// Stages run when a config does not set `pipeline`.
+pub const DEFAULT_PIPELINE: &[PipelineStage] = &[
+    PipelineStage::Ocr,
+    PipelineStage::Structure,
+    PipelineStage::Toc,
+    PipelineStage::SliceContent,
+    PipelineStage::Entities,
+    PipelineStage::ReadableId,
+    PipelineStage::Dedup,
+    PipelineStage::Upload,
+];
+
+impl PipelineStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ocr => "ocr",
+            Self::Structure => "structure",
+            Self::Toc => "toc",
+            Self::SliceContent => "slice_content",
+            Self::Folios => "folios",
+            Self::Signatures => "signatures",
+            Self::Entities => "entities",
+            Self::ReadableId => "readable_id",
+            Self::Dedup => "dedup",
+            Self::Translate => "translate",
+            Self::Redact => "redact",
+            Self::Upload => "upload",
+        }
+    }
+
+    /// Stages that must run earlier in the pipeline.
+    fn requires(&self) -> &'static [PipelineStage] {
+        match self {
+            Self::Ocr => &[],
+            Self::Structure => &[Self::Ocr],
+            Self::Toc
+            | Self::SliceContent
+            | Self::ReadableId
+            | Self::Dedup
+            | Self::Translate
+            | Self::Upload => {
+                &[Self::Structure]
+            }
+            Self::Entities | Self::Redact | Self::Folios | Self::Signatures => {
+                &[Self::SliceContent]
+            }
+        }
+    }
+}
+
+/// The stages a config runs, in order.
+pub fn stages(config: &ExtractionConfig) -> &[PipelineStage] {
+    config.pipeline.as_deref().unwrap_or(DEFAULT_PIPELINE)
+}
+
+/// Check that a pipeline can run: `structure` present, no repeats,
+/// prerequisites first, and `upload` (if present) last.
+pub fn validate(stages: &[PipelineStage]) -> Result<()> {
+    if !stages.contains(&PipelineStage::Structure) {
+        bail!("pipeline must include \"structure\"");
+    }
+    for (i, stage) in stages.iter().enumerate() {
+        if stages[..i].contains(stage) {
+            bail!("pipeline lists \"{}\" more than once", stage.as_str());
+        }
+        if let Some(missing) = stage.requires().iter().find(|r| !stages[..i].contains(r)) {
+            bail!(
+                "pipeline stage \"{}\" must come after \"{}\"",
+                stage.as_str(),
+                missing.as_str()
+            );
+        }
+    }
+    if stages
+        .iter()
+        .position(|s| *s == PipelineStage::Upload)
+        .is_some_and(|i| i + 1 != stages.len())
+    {
+        bail!("\"upload\" must be the last pipeline stage");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Vec<PipelineStage> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_validate_pipeline() {
+        validate(DEFAULT_PIPELINE).unwrap();
+        // Entities can be skipped, readable_id moved earlier, upload dropped.
+        validate(&parse(r#"["ocr","structure","readable_id","slice_content"]"#)).unwrap();
+
+        let err = |json| validate(&parse(json)).unwrap_err().to_string();
+        assert!(err(r#"["ocr","slice_content"]"#).contains("must include"));
+        assert!(err(r#"["ocr","structure","entities"]"#).contains("after \"slice_content\""));
+        assert!(err(r#"["ocr","structure","redact"]"#).contains("after \"slice_content\""));
+        assert!(err(r#"["ocr","structure","folios"]"#).contains("after \"slice_content\""));
+        assert!(err(r#"["ocr","structure","signatures"]"#).contains("after \"slice_content\""));
+        assert!(err(r#"["ocr","toc","structure"]"#).contains("after \"structure\""));
+        assert!(err(r#"["ocr","structure","upload","dedup"]"#).contains("last"));
+        assert!(err(r#"["ocr","structure","dedup","dedup"]"#).contains("more than once"));
+        assert!(serde_json::from_str::<Vec<PipelineStage>>(r#"["ocr","classify"]"#).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Config prompts as templates.
+//!
+//! `prompts.structure` is rendered with minijinja before each extraction, so
+//! a config can refer to the document and to its own declarations instead of
+//! repeating them: `{{filename}}`, `{{total_pages}}`, `{{node_types}}` and
+//! `{{relationship_types}}` (`|`-separated ids, as in the JSON examples),
+//! `{{language}}` (empty when unknown) and `{{config}}` (the config name).
+//! Unknown variables are errors, caught when the config is loaded or saved.
+
+use anyhow::{anyhow, Result};
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+
+/// Values available to prompt templates.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptVars<'a> {
+    pub filename: &'a str,
+    pub total_pages: u32,
+    pub node_types: &'a str,
+    pub relationship_types: &'a str,
+    pub language: &'a str,
+    pub config: &'a str,
+}
+
+/// Render `template` with `vars`. Text without template syntax is returned
+/// as written.
+pub fn render(template: &str, vars: &PromptVars) -> Result<String> {
+    if !has_syntax(template) {
+        return Ok(template.to_string());
+    }
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    env.render_str(template, vars)
+        .map_err(|e| anyhow!("Failed to render prompt: {:#}", e))
+}
+
+/// Check that `template` parses and uses only known variables.
+pub fn check(template: &str) -> Result<()> {
+    let sample = PromptVars {
+        filename: "document.pdf",
+        total_pages: 1,
+        node_types: "SECTION",
+        relationship_types: "references",
+        language: "en",
+        config: "config",
+    };
+    render(template, &sample).map(|_| ())
+}
+
+fn has_syntax(template: &str) -> bool {
+    ["{{", "{%", "{#"]
+        .iter()
+        .any(|open| template.contains(open))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prompt() {
+        let vars = PromptVars {
+            filename: "processo.pdf",
+            total_pages: 12,
+            node_types: "PETICAO|DECISAO",
+            relationship_types: "responds_to|references",
+            language: "pt",
+            config: "legal_br",
+        };
+        let rendered = render(
+            "Analise {{filename}} ({{total_pages}} páginas).\n{\"type\": \"{{node_types}}\"}{% if language %} [{{language}}]{% endif %}",
+            &vars,
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "Analise processo.pdf (12 páginas).\n{\"type\": \"PETICAO|DECISAO\"} [pt]"
+        );
+
+        let plain = "{\"summary\": \"...\"}";
+        assert_eq!(render(plain, &vars).unwrap(), plain);
+        assert!(check("Types: {{node_types}}").is_ok());
+        assert!(check("Types: {{node_typs}}").is_err());
+        assert!(check("{% if %}").is_err());
+    }
+}
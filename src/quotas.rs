@@ -0,0 +1,290 @@
+//! Per-config quotas.
+//!
+//! A config's `quotas` cap how many of its extractions may be in progress,
+//! how many LLM tokens and dollars it may spend per UTC day, and how long
+//! its documents may be. The limits are checked when a job is queued,
+//! whatever queued it: an API request at its config's concurrency limit or
+//! out of budget gets 429 and a document over `max_pages` gets 413, a
+//! watch-folder file goes back to the inbox, a mail message stays unseen,
+//! and a scheduled re-extraction waits for a slot (or stops for the day
+//! once the budget is spent). Eval and experiment runs check the budget and
+//! are charged to it. With `JOB_QUEUE=redis` the jobs queued or running on
+//! every replica count towards `max_concurrent`. Jobs already admitted run
+//! to completion, so a day's spend can overshoot the budget by the jobs in
+//! flight.
+//!
+//! Daily usage persists in `QUOTA_STATE` (default `data/quotas.json`) so a
+//! restart does not reset the budget.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::QuotaConfig;
+
+const DEFAULT_STATE_PATH: &str = "data/quotas.json";
+
+/// LLM usage of one config on one UTC day.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyUsage {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub tokens: u64,
+    pub cost_usd: f64,
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    /// Prompt tokens served from the provider's prompt cache
+    #[serde(default)]
+    pub cached_tokens: u64,
+    /// `cached_tokens / prompt_tokens`
+    #[serde(default)]
+    pub cache_hit_rate: f64,
+}
+
+/// Daily LLM usage per config.
+pub struct QuotaLedger {
+    path: PathBuf,
+    usage: Mutex<HashMap<String, DailyUsage>>,
+    /// Jobs holding a [`Reservation`], per config
+    in_flight: Mutex<HashMap<String, usize>>,
+}
+
+/// A concurrency slot of a config; dropping it frees the slot.
+pub struct Reservation {
+    ledger: Arc<QuotaLedger>,
+    config: String,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut in_flight = self.ledger.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(&self.config) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                in_flight.remove(&self.config);
+            }
+        }
+    }
+}
+
+impl QuotaLedger {
+    /// Read `QUOTA_STATE`.
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("QUOTA_STATE").unwrap_or_else(|_| DEFAULT_STATE_PATH.to_string());
+        Self::open(path)
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let usage = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid quota state in {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self {
+            path,
+            usage: Mutex::new(usage),
+            in_flight: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Today's usage of every config that has used the LLM today.
+    pub fn today(&self) -> BTreeMap<String, DailyUsage> {
+        let day = today();
+        let usage = self.usage.lock().unwrap();
+        usage
+            .iter()
+            .filter(|(_, usage)| usage.day == day)
+            .map(|(name, usage)| (name.clone(), usage.clone()))
+            .collect()
+    }
+
+    /// Add a finished job's usage (its `day` is ignored) to its config's day.
+    pub fn record(&self, config: &str, job: &DailyUsage) {
+        if job.tokens == 0 {
+            return;
+        }
+        let mut usage = self.usage.lock().unwrap();
+        add(&mut usage, config, &today(), job);
+        if let Err(e) = self.save(&usage) {
+            warn!("Failed to save quota state: {:#}", e);
+        }
+    }
+
+    /// `Err` with a message when `config` has spent today's budget.
+    pub fn check_budget(&self, config: &str, quotas: &QuotaConfig) -> Result<(), String> {
+        let usage = self.usage.lock().unwrap();
+        over_budget(usage.get(config), &today(), quotas).map_or(Ok(()), |what| {
+            Err(format!(
+                "Config '{}' has used its daily {} budget; it resets at 00:00 UTC",
+                config, what
+            ))
+        })
+    }
+
+    /// Admit a new job of `config`, unless it has spent today's budget or
+    /// has `max_concurrent` jobs in progress: those holding a reservation
+    /// here plus `elsewhere` (jobs other replicas account for). The count
+    /// and the reservation are one step, so two concurrent submissions
+    /// cannot both take the last slot.
+    pub fn admit(
+        self: &Arc<Self>,
+        config: &str,
+        quotas: &QuotaConfig,
+        elsewhere: usize,
+    ) -> Result<Reservation, String> {
+        self.check_budget(config, quotas)?;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.get(config).copied().unwrap_or(0) + elsewhere;
+        if let Some(max) = quotas.max_concurrent.filter(|max| count >= *max) {
+            return Err(format!(
+                "Config '{}' already has {} extraction(s) in progress (max_concurrent: {})",
+                config, count, max
+            ));
+        }
+        *in_flight.entry(config.to_string()).or_default() += 1;
+        Ok(self.reservation(config))
+    }
+
+    /// A slot for a job admitted earlier (e.g. one resumed after a restart),
+    /// taken even over the limit.
+    pub fn hold(self: &Arc<Self>, config: &str) -> Reservation {
+        *self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(config.to_string())
+            .or_default() += 1;
+        self.reservation(config)
+    }
+
+    fn reservation(self: &Arc<Self>, config: &str) -> Reservation {
+        Reservation {
+            ledger: Arc::clone(self),
+            config: config.to_string(),
+        }
+    }
+
+    fn save(&self, usage: &HashMap<String, DailyUsage>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(usage)?)?;
+        Ok(())
+    }
+}
+
+fn today() -> String {
+    crate::schema::now_iso8601()[..10].to_string()
+}
+
+/// Add usage on `day`, starting over when the config's last usage was on another day.
+fn add(usage: &mut HashMap<String, DailyUsage>, config: &str, day: &str, job: &DailyUsage) {
+    let entry = usage.entry(config.to_string()).or_default();
+    if entry.day != day {
+        *entry = DailyUsage {
+            day: day.to_string(),
+            ..Default::default()
+        };
+    }
+    entry.tokens += job.tokens;
+    entry.cost_usd += job.cost_usd;
+    entry.prompt_tokens += job.prompt_tokens;
+    entry.cached_tokens += job.cached_tokens;
+    if entry.prompt_tokens > 0 {
+        entry.cache_hit_rate = entry.cached_tokens as f64 / entry.prompt_tokens as f64;
+    }
+}
+
+/// The budget (`"token"` or `"cost"`) that `usage` has reached on `day`, if any.
+fn over_budget(
+    usage: Option<&DailyUsage>,
+    day: &str,
+    quotas: &QuotaConfig,
+) -> Option<&'static str> {
+    let usage = usage.filter(|usage| usage.day == day)?;
+    if quotas.daily_tokens.is_some_and(|max| usage.tokens >= max) {
+        Some("token")
+    } else if quotas
+        .daily_cost_usd
+        .is_some_and(|max| usage.cost_usd >= max)
+    {
+        Some("cost")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit_concurrency_slot() {
+        let path = std::env::temp_dir().join(format!("quotas_{}.json", uuid::Uuid::new_v4()));
+        let ledger = Arc::new(QuotaLedger::open(&path).unwrap());
+        let quotas = QuotaConfig {
+            max_concurrent: Some(2),
+            ..Default::default()
+        };
+        let first = ledger.admit("contracts", &quotas, 0).unwrap();
+        let resumed = ledger.hold("contracts");
+        assert!(ledger
+            .admit("contracts", &quotas, 0)
+            .err()
+            .unwrap()
+            .contains("2 extraction(s) in progress"));
+        assert!(ledger.admit("invoices", &quotas, 1).is_ok());
+        drop(first);
+        // Jobs other replicas report count too
+        assert!(ledger.admit("contracts", &quotas, 1).is_err());
+        let second = ledger.admit("contracts", &quotas, 0).unwrap();
+        drop((resumed, second));
+        assert!(ledger.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_budget_resets_each_day() {
+        let quotas = QuotaConfig {
+            daily_tokens: Some(1000),
+            daily_cost_usd: Some(0.5),
+            ..Default::default()
+        };
+        let job = |tokens: u64, cost_usd: f64| DailyUsage {
+            tokens,
+            cost_usd,
+            prompt_tokens: tokens / 2,
+            cached_tokens: tokens / 4,
+            ..Default::default()
+        };
+        let mut usage = HashMap::new();
+        add(&mut usage, "contracts", "2026-10-16", &job(600, 0.1));
+        assert_eq!(
+            over_budget(usage.get("contracts"), "2026-10-16", &quotas),
+            None
+        );
+        add(&mut usage, "contracts", "2026-10-16", &job(400, 0.1));
+        assert_eq!(usage["contracts"].cache_hit_rate, 0.5);
+        assert_eq!(
+            over_budget(usage.get("contracts"), "2026-10-16", &quotas),
+            Some("token")
+        );
+        // Yesterday's usage does not count against today
+        assert_eq!(
+            over_budget(usage.get("contracts"), "2026-10-17", &quotas),
+            None
+        );
+        add(&mut usage, "contracts", "2026-10-17", &job(10, 0.6));
+        assert_eq!(usage["contracts"].tokens, 10);
+        assert_eq!(usage["contracts"].cached_tokens, 2);
+        assert_eq!(
+            over_budget(usage.get("contracts"), "2026-10-17", &quotas),
+            Some("cost")
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Human-readable document identifiers (case number, invoice ID, ...).
+//!
+//! Resolution order for `Extraction.readable_id`:
+//! 1. `readable_id_pattern` (regex) matched against the OCR text
+//! 2. the LLM's `readable_id` answer, prompted with `readable_id_hint`
+//! 3. `readable_id_pattern` matched against the extracted metadata
+//! 4. a slug of the file name plus a short content hash
+
+use regex::Regex;
+use tracing::warn;
+
+use crate::config::ExtractionConfig;
+
+/// Longest identifier kept; longer values are almost always LLM prose.
+const MAX_READABLE_ID_CHARS: usize = 80;
+
+/// LLM answers that mean "no identifier found".
+const EMPTY_ANSWERS: &[&str] = &["", "null", "none", "n/a", "na", "unknown", "desconhecido"];
+
+/// Inputs available once OCR and LLM extraction have run.
+pub struct ReadableIdSources<'a> {
+    pub ocr_text: &'a str,
+    pub llm_value: Option<&'a str>,
+    pub metadata: &'a serde_json::Value,
+    pub filename: &'a str,
+    pub content_hash: &'a str,
+}
+
+/// Pick the readable ID for an extraction. Always returns a value.
+pub fn resolve(config: &ExtractionConfig, sources: &ReadableIdSources) -> String {
+    let pattern = config
+        .readable_id_pattern
+        .as_deref()
+        .and_then(|p| match Regex::new(p) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                warn!(
+                    "Invalid readable_id_pattern in config '{}': {}",
+                    config.name, e
+                );
+                None
+            }
+        });
+
+    if let Some(found) = pattern
+        .as_ref()
+        .and_then(|re| first_match(re, sources.ocr_text))
+    {
+        return found;
+    }
+    if let Some(answer) = sources.llm_value.and_then(clean_llm_value) {
+        return answer;
+    }
+    if let Some(found) = pattern
+        .as_ref()
+        .and_then(|re| first_match(re, &metadata_text(sources.metadata)))
+    {
+        return found;
+    }
+    fallback_slug(sources.filename, sources.content_hash)
+}
+
+/// Lowercased alphanumerics only, so "0001234-56.2024" matches "000123456 2024".
+pub fn search_key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether a readable ID matches a `?readable_id=` filter, ignoring case and punctuation.
+pub fn matches_filter(readable_id: &str, filter: &str) -> bool {
+    if readable_id.to_lowercase().contains(&filter.to_lowercase()) {
+        return true;
+    }
+    let key = search_key(filter);
+    !key.is_empty() && search_key(readable_id).contains(&key)
+}
+
+/// First match of `re` in `text`, using capture group 1 when the pattern has one.
+fn first_match(re: &Regex, text: &str) -> Option<String> {
+    let caps = re.captures(text)?;
+    let m = caps.get(1).or_else(|| caps.get(0))?;
+    let value = m.as_str().trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn clean_llm_value(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches('"').trim();
+    if EMPTY_ANSWERS.contains(&value.to_lowercase().as_str())
+        || value.chars().count() > MAX_READABLE_ID_CHARS
+    {
+        return None;
+    }
+    Some(value.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// All string values in the metadata, one per line.
+fn metadata_text(metadata: &serde_json::Value) -> String {
+    fn collect(value: &serde_json::Value, out: &mut Vec<String>) {
+        match value {
+            serde_json::Value::String(s) => out.push(s.clone()),
+            serde_json::Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            serde_json::Value::Object(map) => map.values().for_each(|v| collect(v, out)),
+            _ => {}
+        }
+    }
+    let mut values = Vec::new();
+    collect(metadata, &mut values);
+    values.join("\n")
+}
+
+/// `contrato-locacao-3f2a1b` from `Contrato Locação.pdf` and its content hash.
+fn fallback_slug(filename: &str, content_hash: &str) -> String {
+    let stem = filename.rsplit_once('.').map_or(filename, |(stem, _)| stem);
+    let mut slug = String::new();
+    for c in stem.chars().map(fold_accent).flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.trim_end_matches('-').chars().take(48).collect();
+    let slug = slug.trim_end_matches('-');
+    let hash: String = content_hash.chars().take(6).collect();
+    match (slug.is_empty(), hash.is_empty()) {
+        (true, true) => "document".to_string(),
+        (true, false) => format!("doc-{}", hash),
+        (false, true) => slug.to_string(),
+        (false, false) => format!("{}-{}", slug, hash),
+    }
+}
+
+/// Strip the accents common in Portuguese file names.
+pub(crate) fn fold_accent(c: char) -> char {
+    match c {
+        'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+        'Á' | 'À' | 'Â' | 'Ã' | 'Ä' => 'A',
+        'é' | 'è' | 'ê' | 'ë' => 'e',
+        'É' | 'È' | 'Ê' | 'Ë' => 'E',
+        'í' | 'ì' | 'î' | 'ï' => 'i',
+        'Í' | 'Ì' | 'Î' | 'Ï' => 'I',
+        'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+        'Ó' | 'Ò' | 'Ô' | 'Õ' | 'Ö' => 'O',
+        'ú' | 'ù' | 'û' | 'ü' => 'u',
+        'Ú' | 'Ù' | 'Û' | 'Ü' => 'U',
+        'ç' => 'c',
+        'Ç' => 'C',
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pattern: Option<&str>) -> ExtractionConfig {
+        let mut config = crate::config::create_default_config();
+        config.readable_id_pattern = pattern.map(str::to_string);
+        config
+    }
+
+    fn sources<'a>(
+        ocr_text: &'a str,
+        llm_value: Option<&'a str>,
+        metadata: &'a serde_json::Value,
+    ) -> ReadableIdSources<'a> {
+        ReadableIdSources {
+            ocr_text,
+            llm_value,
+            metadata,
+            filename: "Petição Inicial (cópia).pdf",
+            content_hash: "3f2a1b9c",
+        }
+    }
+
+    #[test]
+    fn test_resolution_order() {
+        let cnj = Some(r"(\d{7}-\d{2}\.\d{4}\.\d\.\d{2}\.\d{4})");
+        let metadata = serde_json::json!({"numero": "Processo 0009999-11.2023.8.26.0001"});
+        let text = "Autos nº 0001234-56.2024.8.26.0100 — petição inicial";
+
+        // The regex over the OCR text wins over the LLM's answer.
+        let id = resolve(
+            &config(cnj),
+            &sources(text, Some("something else"), &metadata),
+        );
+        assert_eq!(id, "0001234-56.2024.8.26.0100");
+
+        // Then the LLM answer, then the regex over metadata.
+        let id = resolve(
+            &config(cnj),
+            &sources("no number", Some(" INV-42 "), &metadata),
+        );
+        assert_eq!(id, "INV-42");
+        let id = resolve(&config(cnj), &sources("no number", Some("N/A"), &metadata));
+        assert_eq!(id, "0009999-11.2023.8.26.0001");
+
+        // Nothing found: slug of the file name plus the content hash.
+        let id = resolve(&config(None), &sources("", None, &serde_json::Value::Null));
+        assert_eq!(id, "peticao-inicial-copia-3f2a1b");
+    }
+
+    #[test]
+    fn test_filter_ignores_punctuation() {
+        assert!(matches_filter("0001234-56.2024.8.26.0100", "0001234562024"));
+        assert!(matches_filter("INV-42", "inv-4"));
+        assert!(!matches_filter("INV-42", "inv-5"));
+        assert!(!matches_filter("INV-42", "--"));
+    }
+}
@@ -0,0 +1,237 @@
+//! PII detection and redaction for sharing node content externally.
+//!
+//! The `redact` pipeline stage stores a redacted copy of every node's content
+//! next to the original (as `content://{node_id}.redacted`), served by
+//! `GET /content/:ref_path?redacted=true`. Detected values are replaced with
+//! `[REDACTED:{kind}]`.
+//!
+//! Detection combines built-in patterns (CPF, CNPJ, e-mail, Brazilian phone
+//! numbers), selected config entity patterns, and person names: node authors
+//! and name-like metadata fields, plus names listed by the LLM when
+//! `redaction.llm_names` is enabled.
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::EntityPattern;
+use crate::schema::{DocumentNode, Extraction};
+
+/// Suffix of the node ID under which the redacted copy is stored.
+pub const REDACTED_SUFFIX: &str = ".redacted";
+
+/// Built-in detectors, applied in this order (CNPJ before CPF so a CNPJ is
+/// never half-matched as a CPF).
+const BUILTIN_DETECTORS: &[(&str, &str)] = &[
+    ("cnpj", r"\b\d{2}\.?\d{3}\.?\d{3}/?\d{4}-?\d{2}\b"),
+    ("cpf", r"\b\d{3}\.?\d{3}\.?\d{3}-?\d{2}\b"),
+    ("email", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    (
+        "phone",
+        r"(?:\+55\s?)?\(\d{2}\)\s?9?\d{4}-?\d{4}\b|\b\d{2}\s9?\d{4}-\d{4}\b",
+    ),
+];
+
+/// Metadata keys whose string values are treated as person names.
+const NAME_KEYS: &[&str] = &[
+    "nome", "name", "autor", "author", "advogado", "juiz", "relator",
+];
+
+/// Names shorter than this are ignored (initials, "Sr.", ...).
+const MIN_NAME_CHARS: usize = 5;
+
+/// `redaction` section of an extraction config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    /// Built-in detectors to apply: `cpf`, `cnpj`, `email`, `phone` (default: all)
+    #[serde(default = "default_detectors")]
+    pub detectors: Vec<String>,
+    /// IDs of config `entity_patterns` to redact as well (e.g. `oab`, `pnr`)
+    #[serde(default)]
+    pub entity_patterns: Vec<String>,
+    /// Redact node authors and name-like metadata values (default true)
+    #[serde(default = "default_true")]
+    pub names: bool,
+    /// Also ask the LLM for the people named in the document (one extra call)
+    #[serde(default)]
+    pub llm_names: bool,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            detectors: default_detectors(),
+            entity_patterns: Vec::new(),
+            names: true,
+            llm_names: false,
+        }
+    }
+}
+
+/// Whether `id` names one of the built-in detectors.
+pub fn is_builtin_detector(id: &str) -> bool {
+    BUILTIN_DETECTORS.iter().any(|(builtin, _)| *builtin == id)
+}
+
+fn default_detectors() -> Vec<String> {
+    BUILTIN_DETECTORS
+        .iter()
+        .map(|(id, _)| id.to_string())
+        .collect()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Compiled detectors for one extraction.
+pub struct Redactor {
+    /// (kind, regex), applied in order
+    detectors: Vec<(String, Regex)>,
+}
+
+impl Redactor {
+    pub fn new(
+        config: &RedactionConfig,
+        entity_patterns: &[EntityPattern],
+        names: &[String],
+    ) -> Self {
+        let mut detectors = Vec::new();
+
+        for (id, pattern) in BUILTIN_DETECTORS {
+            if config.detectors.iter().any(|d| d == id) {
+                detectors.push((
+                    id.to_string(),
+                    Regex::new(pattern).expect("valid built-in regex"),
+                ));
+            }
+        }
+        for id in &config.entity_patterns {
+            let Some(p) = entity_patterns.iter().find(|p| &p.id == id) else {
+                warn!("Redaction: unknown entity pattern '{}'", id);
+                continue;
+            };
+            if !p.kind.is_regex() {
+                warn!("Redaction: entity pattern '{}' is not a regex; skipped", id);
+                continue;
+            }
+            match Regex::new(&p.pattern) {
+                Ok(regex) => detectors.push((p.id.clone(), regex)),
+                Err(e) => warn!("Redaction: invalid entity pattern '{}': {}", p.id, e),
+            }
+        }
+
+        let mut names: Vec<&str> = names
+            .iter()
+            .map(|n| n.trim())
+            .filter(|n| n.chars().count() >= MIN_NAME_CHARS)
+            .collect();
+        // Longest first, so "João da Silva Santos" wins over "João da Silva"
+        names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+        names.dedup();
+        if !names.is_empty() {
+            let alternation = names
+                .iter()
+                .map(|n| regex::escape(n).replace(' ', r"\s+"))
+                .collect::<Vec<_>>()
+                .join("|");
+            match RegexBuilder::new(&format!(r"\b(?:{})\b", alternation))
+                .case_insensitive(true)
+                .build()
+            {
+                Ok(regex) => detectors.push(("name".to_string(), regex)),
+                Err(e) => warn!("Redaction: could not build name pattern: {}", e),
+            }
+        }
+
+        Self { detectors }
+    }
+
+    /// Replace every detected value; returns the redacted text and the number of replacements.
+    pub fn redact(&self, text: &str) -> (String, usize) {
+        let mut out = text.to_string();
+        let mut count = 0;
+        for (kind, regex) in &self.detectors {
+            let replacement = format!("[REDACTED:{}]", kind);
+            count += regex.find_iter(&out).count();
+            out = regex.replace_all(&out, replacement.as_str()).into_owned();
+        }
+        (out, count)
+    }
+}
+
+/// Person names already known from the extraction: node authors and
+/// string values under name-like metadata keys.
+pub fn known_names(extraction: &Extraction) -> Vec<String> {
+    fn from_metadata(value: &serde_json::Value, out: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, v) in map {
+                    match v {
+                        serde_json::Value::String(s)
+                            if NAME_KEYS.contains(&key.to_lowercase().as_str()) =>
+                        {
+                            out.push(s.clone())
+                        }
+                        _ => from_metadata(v, out),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|v| from_metadata(v, out)),
+            _ => {}
+        }
+    }
+    fn from_nodes(nodes: &[DocumentNode], out: &mut Vec<String>) {
+        for node in nodes {
+            out.extend(node.author.clone());
+            from_metadata(&node.metadata, out);
+            from_nodes(&node.children, out);
+        }
+    }
+
+    let mut names = Vec::new();
+    from_metadata(&extraction.metadata, &mut names);
+    from_nodes(&extraction.children, &mut names);
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EntityPatternKind;
+
+    #[test]
+    fn test_redacts_builtin_patterns_and_names() {
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.metadata = serde_json::json!({
+            "partes": [{"nome": "João da Silva", "polo": "ATIVO"}],
+            "numero": "0001234-56.2024.8.26.0100"
+        });
+        let entity_patterns = vec![EntityPattern {
+            id: "oab".into(),
+            label: "OAB".into(),
+            kind: EntityPatternKind::Regex,
+            pattern: r"OAB/[A-Z]{2}\s?\d+".into(),
+            description: None,
+            normalize: None,
+            deduplicate: true,
+        }];
+        let config = RedactionConfig {
+            entity_patterns: vec!["oab".into()],
+            ..Default::default()
+        };
+        let redactor = Redactor::new(&config, &entity_patterns, &known_names(&extraction));
+
+        let text = "JOÃO  DA SILVA, CPF 123.456.789-00, e a empresa 09.296.295/0001-60 \
+                    (joao@example.com, (11) 98765-4321), adv. OAB/SP 12345. \
+                    Processo 0001234-56.2024.8.26.0100.";
+        let (redacted, count) = redactor.redact(text);
+        assert_eq!(
+            redacted,
+            "[REDACTED:name], CPF [REDACTED:cpf], e a empresa [REDACTED:cnpj] \
+             ([REDACTED:email], [REDACTED:phone]), adv. [REDACTED:oab]. \
+             Processo 0001234-56.2024.8.26.0100."
+        );
+        assert_eq!(count, 6);
+    }
+}
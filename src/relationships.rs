@@ -0,0 +1,325 @@
+//! Validation of the relationships the LLM returns.
+//!
+//! A relationship must join two different nodes of the extraction's tree,
+//! with a type from the config's `relationship_types` (any type when it
+//! lists none). Node IDs and types that match a known one once case and
+//! punctuation are ignored, such as `Peticao-Inicial` for `peticao_inicial`,
+//! are corrected. Relationships that still point at an unknown node or type,
+//! join a node to itself, or repeat an earlier one are dropped. Every
+//! correction and drop is recorded in the extraction's `diagnostics`, so
+//! nothing disappears silently. References the LLM embedded in a node are
+//! validated the same way, as relationships from that node.
+//!
+//! [`link`] then mirrors the relationships onto the nodes: `references` on
+//! the `from` node and `referenced_by` on the `to` node, so clients walking
+//! the tree see both directions.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ExtractionConfig;
+use crate::schema::{
+    DocumentNode, DroppedRelationship, EmbeddedReference, Extraction, Relationship,
+    RepairedRelationship,
+};
+
+/// Keep the extraction's valid relationships, repairing what can be repaired,
+/// and record the rest in `extraction.diagnostics`.
+pub fn validate(extraction: &mut Extraction, config: &ExtractionConfig) {
+    let mut ids = HashSet::new();
+    collect_ids(&extraction.children, &mut ids);
+    let nodes = Resolver::new(ids.iter().map(String::as_str));
+    let types = Resolver::new(config.relationship_types.iter().map(String::as_str));
+    absorb_references(&mut extraction.children, &mut extraction.relationships);
+
+    let mut diagnostics = extraction.diagnostics.take().unwrap_or_default();
+    let mut seen = HashSet::new();
+    let mut kept = Vec::new();
+    for original in std::mem::take(&mut extraction.relationships) {
+        let mut relationship = original.clone();
+        let mut changes = Vec::new();
+        let outcome = (|| {
+            relationship.from = resolve(&nodes, "from", &original.from, &mut changes)
+                .ok_or_else(|| format!("unknown node '{}'", original.from))?;
+            relationship.to = resolve(&nodes, "to", &original.to, &mut changes)
+                .ok_or_else(|| format!("unknown node '{}'", original.to))?;
+            if !config.relationship_types.is_empty() {
+                relationship.rel_type =
+                    resolve(&types, "type", &original.rel_type, &mut changes)
+                        .ok_or_else(|| format!("unknown type '{}'", original.rel_type))?;
+            }
+            if relationship.from == relationship.to {
+                return Err("joins a node to itself".to_string());
+            }
+            let key = (
+                relationship.from.clone(),
+                relationship.to.clone(),
+                relationship.rel_type.clone(),
+            );
+            if !seen.insert(key) {
+                return Err("repeats an earlier relationship".to_string());
+            }
+            Ok(())
+        })();
+        match outcome {
+            Ok(()) => {
+                if !changes.is_empty() {
+                    diagnostics
+                        .repaired_relationships
+                        .push(RepairedRelationship { original, changes });
+                }
+                kept.push(relationship);
+            }
+            Err(reason) => diagnostics.dropped_relationships.push(DroppedRelationship {
+                relationship: original,
+                reason,
+            }),
+        }
+    }
+    extraction.relationships = kept;
+    extraction.diagnostics = (!diagnostics.is_empty()).then_some(diagnostics);
+}
+
+/// Fill every node's `references` and `referenced_by` from the extraction's
+/// relationships, replacing what was there.
+pub fn link(extraction: &mut Extraction) {
+    let mut outgoing: HashMap<&str, Vec<EmbeddedReference>> = HashMap::new();
+    let mut incoming: HashMap<&str, Vec<EmbeddedReference>> = HashMap::new();
+    for r in &extraction.relationships {
+        outgoing
+            .entry(&r.from)
+            .or_default()
+            .push(EmbeddedReference {
+                node: r.to.clone(),
+                ref_type: r.rel_type.clone(),
+                citation: r.citation.clone(),
+            });
+        incoming.entry(&r.to).or_default().push(EmbeddedReference {
+            node: r.from.clone(),
+            ref_type: r.rel_type.clone(),
+            citation: r.citation.clone(),
+        });
+    }
+
+    fn walk(
+        nodes: &mut [DocumentNode],
+        outgoing: &mut HashMap<&str, Vec<EmbeddedReference>>,
+        incoming: &mut HashMap<&str, Vec<EmbeddedReference>>,
+    ) {
+        for node in nodes {
+            node.references = outgoing.remove(node.id.as_str()).unwrap_or_default();
+            node.referenced_by = incoming.remove(node.id.as_str()).unwrap_or_default();
+            walk(&mut node.children, outgoing, incoming);
+        }
+    }
+    walk(&mut extraction.children, &mut outgoing, &mut incoming);
+}
+
+/// Move references embedded in nodes into `relationships`, skipping those
+/// already listed there.
+fn absorb_references(nodes: &mut [DocumentNode], relationships: &mut Vec<Relationship>) {
+    for node in nodes {
+        for r in std::mem::take(&mut node.references) {
+            let listed = relationships
+                .iter()
+                .any(|l| l.from == node.id && l.to == r.node && l.rel_type == r.ref_type);
+            if !listed {
+                relationships.push(Relationship {
+                    from: node.id.clone(),
+                    to: r.node,
+                    rel_type: r.ref_type,
+                    citation: r.citation,
+                });
+            }
+        }
+        absorb_references(&mut node.children, relationships);
+    }
+}
+
+fn collect_ids(nodes: &[DocumentNode], ids: &mut HashSet<String>) {
+    for node in nodes {
+        ids.insert(node.id.clone());
+        collect_ids(&node.children, ids);
+    }
+}
+
+/// Resolve `value`, noting a correction in `changes`.
+fn resolve(
+    resolver: &Resolver,
+    field: &str,
+    value: &str,
+    changes: &mut Vec<String>,
+) -> Option<String> {
+    let resolved = resolver.resolve(value)?;
+    if resolved != value {
+        changes.push(format!("{}: {:?} -> {:?}", field, value, resolved));
+    }
+    Some(resolved.to_string())
+}
+
+/// Known names, looked up exactly or by their [`loose`] form.
+struct Resolver<'a> {
+    exact: HashSet<&'a str>,
+    /// Loose form -> name; `None` when two names share it
+    loose: HashMap<String, Option<&'a str>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(names: impl Iterator<Item = &'a str>) -> Self {
+        let mut exact = HashSet::new();
+        let mut loose: HashMap<String, Option<&'a str>> = HashMap::new();
+        for name in names {
+            exact.insert(name);
+            loose
+                .entry(self::loose(name))
+                .and_modify(|known| {
+                    if *known != Some(name) {
+                        *known = None
+                    }
+                })
+                .or_insert(Some(name));
+        }
+        Self { exact, loose }
+    }
+
+    fn resolve<'v>(&self, value: &'v str) -> Option<&'v str>
+    where
+        'a: 'v,
+    {
+        if let Some(name) = self.exact.get(value) {
+            return Some(name);
+        }
+        self.loose.get(&self::loose(value)).copied().flatten()
+    }
+}
+
+/// `name` lowercased, with everything but letters and digits removed.
+fn loose(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, children: Vec<DocumentNode>) -> DocumentNode {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "type": "section", "label": id, "summary": "",
+        }))
+        .map(|mut n: DocumentNode| {
+            n.children = children;
+            n
+        })
+        .unwrap()
+    }
+
+    fn rel(from: &str, to: &str, rel_type: &str) -> Relationship {
+        Relationship {
+            from: from.to_string(),
+            to: to.to_string(),
+            rel_type: rel_type.to_string(),
+            citation: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_relationships() {
+        let mut config = crate::config::create_default_config();
+        config.relationship_types = vec!["references".into(), "responds_to".into()];
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.children = vec![
+            node("peticao_inicial", vec![node("doc_1", Vec::new())]),
+            node("sentenca", Vec::new()),
+        ];
+        extraction.relationships = vec![
+            rel("sentenca", "peticao_inicial", "responds_to"),
+            rel("Sentenca", "doc_1", "References"),
+            rel("sentenca", "peticao_inicial", "responds_to"),
+            rel("sentenca", "acordao", "references"),
+            rel("doc_1", "sentenca", "cites"),
+            rel("doc_1", "doc-1", "references"),
+        ];
+        validate(&mut extraction, &config);
+
+        let kept: Vec<_> = extraction
+            .relationships
+            .iter()
+            .map(|r| (r.from.as_str(), r.to.as_str(), r.rel_type.as_str()))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("sentenca", "peticao_inicial", "responds_to"),
+                ("sentenca", "doc_1", "references"),
+            ]
+        );
+        let diagnostics = extraction.diagnostics.unwrap();
+        assert_eq!(
+            diagnostics.repaired_relationships[0].changes,
+            [
+                r#"from: "Sentenca" -> "sentenca""#,
+                r#"type: "References" -> "references""#
+            ]
+        );
+        let reasons: Vec<_> = diagnostics
+            .dropped_relationships
+            .iter()
+            .map(|d| d.reason.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                "repeats an earlier relationship",
+                "unknown node 'acordao'",
+                "unknown type 'cites'",
+                "joins a node to itself"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_back_references() {
+        let mut config = crate::config::create_default_config();
+        config.relationship_types.clear();
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        let mut sentenca = node("sentenca", Vec::new());
+        sentenca.references.push(EmbeddedReference {
+            node: "doc_1".into(),
+            ref_type: "cites".into(),
+            citation: Some("fls. 3".into()),
+        });
+        extraction.children = vec![
+            node("peticao_inicial", vec![node("doc_1", Vec::new())]),
+            sentenca,
+        ];
+        extraction.relationships = vec![rel("sentenca", "peticao_inicial", "responds_to")];
+        validate(&mut extraction, &config);
+        link(&mut extraction);
+
+        assert_eq!(extraction.relationships.len(), 2);
+        let refs = |r: &[EmbeddedReference]| -> Vec<(String, String)> {
+            r.iter()
+                .map(|r| (r.node.clone(), r.ref_type.clone()))
+                .collect()
+        };
+        let [peticao, sentenca] = &extraction.children[..] else {
+            panic!("expected two top-level nodes");
+        };
+        assert_eq!(
+            refs(&sentenca.references),
+            [
+                ("peticao_inicial".into(), "responds_to".into()),
+                ("doc_1".into(), "cites".into())
+            ]
+        );
+        assert!(sentenca.referenced_by.is_empty());
+        assert_eq!(
+            refs(&peticao.referenced_by),
+            [("sentenca".into(), "responds_to".into())]
+        );
+        let doc = &peticao.children[0];
+        assert_eq!(doc.referenced_by[0].citation.as_deref(), Some("fls. 3"));
+    }
+}
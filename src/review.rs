@@ -0,0 +1,635 @@
+//! Reviewer corrections to extracted nodes.
+//!
+//! `PATCH /extractions/:id/node/:node_id` applies a [`NodeCorrection`] to one
+//! node, marks it (and its extraction) `reviewed`, and appends a
+//! [`NodeReview`](crate::schema::NodeReview) with the old and new value of
+//! every changed field to the extraction's audit trail.
+//!
+//! Structural fixes go through [`move_node`], [`merge_nodes`], and
+//! [`split_node`], which also re-slice node content from the pages already in
+//! the content store and retarget relationships.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::ExtractionConfig;
+use crate::content_store::ContentStore;
+use crate::extractor::{pages_from_content, slice_pages};
+use crate::ocr::OcrPage;
+use crate::schema::{DocumentNode, Extraction, FieldChange};
+
+/// Fields a reviewer may correct; unset fields are left as they are.
+/// A correction with no fields confirms the node as extracted.
+#[derive(Debug, Default, Deserialize)]
+pub struct NodeCorrection {
+    /// Who made the correction (falls back to the `X-Reviewer` header)
+    pub reviewer: Option<String>,
+    pub label: Option<String>,
+    #[serde(rename = "type")]
+    pub node_type: Option<String>,
+    pub subtype: Option<String>,
+    /// `YYYY-MM-DD`
+    pub date: Option<String>,
+    pub page_range: Option<[u32; 2]>,
+    pub summary: Option<String>,
+}
+
+/// Apply `correction` to `node`, returning the fields that changed.
+///
+/// `config` restricts `type` to the config's node types; `total_pages`
+/// bounds `page_range`. Nothing is modified when validation fails.
+pub fn apply(
+    node: &mut DocumentNode,
+    correction: &NodeCorrection,
+    config: Option<&ExtractionConfig>,
+    total_pages: Option<u32>,
+) -> Result<Vec<FieldChange>, String> {
+    if let Some(ref node_type) = correction.node_type {
+        let known = config.map(|c| &c.node_types).filter(|t| !t.is_empty());
+        if let Some(types) = known {
+            if !types.iter().any(|t| &t.id == node_type) {
+                return Err(format!(
+                    "Unknown node type '{}'. Available: {}",
+                    node_type,
+                    types
+                        .iter()
+                        .map(|t| t.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+    }
+    if let Some([start, end]) = correction.page_range {
+        if start == 0 || start > end || total_pages.is_some_and(|total| end > total) {
+            return Err(format!(
+                "Invalid page_range [{}, {}] (document has {} pages)",
+                start,
+                end,
+                total_pages.map_or("an unknown number of".to_string(), |t| t.to_string())
+            ));
+        }
+    }
+    if let Some(ref date) = correction.date {
+        if !is_iso_date(date) {
+            return Err(format!("Invalid date '{}': expected YYYY-MM-DD", date));
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut set = |field: &str, old: Value, new: Value| -> bool {
+        if old == new {
+            return false;
+        }
+        changes.push(FieldChange {
+            field: field.to_string(),
+            old,
+            new,
+        });
+        true
+    };
+
+    if let Some(ref label) = correction.label {
+        if set("label", node.label.clone().into(), label.clone().into()) {
+            node.label = Some(label.clone());
+        }
+    }
+    if let Some(ref node_type) = correction.node_type {
+        if set(
+            "type",
+            node.node_type.clone().into(),
+            node_type.clone().into(),
+        ) {
+            node.node_type = node_type.clone();
+        }
+    }
+    if let Some(ref subtype) = correction.subtype {
+        if set(
+            "subtype",
+            node.subtype.clone().into(),
+            subtype.clone().into(),
+        ) {
+            node.subtype = Some(subtype.clone());
+        }
+    }
+    if let Some(ref date) = correction.date {
+        if set("date", node.date.clone().into(), date.clone().into()) {
+            node.date = Some(date.clone());
+        }
+    }
+    if let Some(page_range) = correction.page_range {
+        let old = node.page_range.map_or(Value::Null, |r| r.to_vec().into());
+        if set("page_range", old, page_range.to_vec().into()) {
+            node.page_range = Some(page_range);
+        }
+    }
+    if let Some(ref summary) = correction.summary {
+        if set(
+            "summary",
+            node.summary.clone().into(),
+            summary.clone().into(),
+        ) {
+            node.summary = summary.clone();
+        }
+    }
+
+    node.reviewed = true;
+    Ok(changes)
+}
+
+fn is_iso_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return false;
+    };
+    let digits = |p: &str, len: usize| p.len() == len && p.bytes().all(|b| b.is_ascii_digit());
+    digits(year, 4)
+        && digits(month, 2)
+        && digits(day, 2)
+        && (1..=12).contains(&month.parse::<u32>().unwrap_or(0))
+        && (1..=31).contains(&day.parse::<u32>().unwrap_or(0))
+}
+
+/// Find a node anywhere in the tree for editing.
+pub fn find_node_mut<'a>(nodes: &'a mut [DocumentNode], id: &str) -> Option<&'a mut DocumentNode> {
+    for node in nodes {
+        if node.id == id {
+            return Some(node);
+        }
+        if let Some(found) = find_node_mut(&mut node.children, id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Body of `POST /extractions/:id/node/:node_id/move`.
+#[derive(Debug, Deserialize)]
+pub struct MoveRequest {
+    pub reviewer: Option<String>,
+    /// New parent; `null` moves the node to the top level
+    pub parent_id: Option<String>,
+    /// Index among the new siblings (appended when unset)
+    pub position: Option<usize>,
+}
+
+/// Body of `POST /extractions/:id/node/:node_id/merge`.
+#[derive(Debug, Deserialize)]
+pub struct MergeRequest {
+    pub reviewer: Option<String>,
+    /// Adjacent sibling to merge into the node
+    pub with: String,
+}
+
+/// Body of `POST /extractions/:id/node/:node_id/split`.
+#[derive(Debug, Deserialize)]
+pub struct SplitRequest {
+    pub reviewer: Option<String>,
+    /// First page of the new node
+    pub page: u32,
+    /// Label of the new node (defaults to the original's)
+    pub label: Option<String>,
+}
+
+/// Move `node_id` under `parent_id` (top level when `None`), at `position`
+/// among its new siblings (last when `None`).
+pub fn move_node(
+    extraction: &mut Extraction,
+    node_id: &str,
+    parent_id: Option<&str>,
+    position: Option<usize>,
+) -> Result<Vec<FieldChange>, String> {
+    let (old_parent, old_index) =
+        locate(&extraction.children, node_id, None).ok_or_else(|| not_found(node_id))?;
+    if let Some(parent) = parent_id {
+        if locate(&extraction.children, parent, None).is_none() {
+            return Err(not_found(parent));
+        }
+        let node = find_node_mut(&mut extraction.children, node_id).expect("located above");
+        if parent == node_id || locate(&node.children, parent, None).is_some() {
+            return Err(format!(
+                "Cannot move {} under itself or one of its descendants",
+                node_id
+            ));
+        }
+    }
+
+    let mut node = siblings_mut(extraction, old_parent.as_deref()).remove(old_index);
+    node.reviewed = true;
+    let siblings = siblings_mut(extraction, parent_id);
+    let index = position.unwrap_or(siblings.len()).min(siblings.len());
+    siblings.insert(index, node);
+
+    let mut changes = Vec::new();
+    let reparented = old_parent.as_deref() != parent_id;
+    if reparented {
+        changes.push(FieldChange {
+            field: "parent_id".to_string(),
+            old: old_parent.into(),
+            new: parent_id.into(),
+        });
+    }
+    if reparented || old_index != index {
+        changes.push(FieldChange {
+            field: "position".to_string(),
+            old: old_index.into(),
+            new: index.into(),
+        });
+    }
+    Ok(changes)
+}
+
+/// Merge the adjacent sibling `other_id` into `node_id`.
+///
+/// The merged node keeps `node_id`, spans both page ranges, takes the other
+/// node's children in document order, and gets its content re-sliced from
+/// the pages of both. Relationships to or from `other_id` now point at
+/// `node_id`; ones that would point at itself are dropped.
+pub fn merge_nodes(
+    extraction: &mut Extraction,
+    node_id: &str,
+    other_id: &str,
+    store: &ContentStore,
+) -> Result<Vec<FieldChange>, String> {
+    let (parent, index) =
+        locate(&extraction.children, node_id, None).ok_or_else(|| not_found(node_id))?;
+    let (other_parent, other_index) =
+        locate(&extraction.children, other_id, None).ok_or_else(|| not_found(other_id))?;
+    if parent != other_parent || index.abs_diff(other_index) != 1 {
+        return Err(format!(
+            "{} and {} are not adjacent siblings",
+            node_id, other_id
+        ));
+    }
+
+    let siblings = siblings_mut(extraction, parent.as_deref());
+    let other = siblings.remove(other_index);
+    let node = &mut siblings[index.min(other_index)];
+    let node_first = index < other_index;
+    let mut changes = Vec::new();
+
+    let mut pages = node_pages(node, store);
+    pages.extend(node_pages(&other, store));
+    let page_range = match (node.page_range, other.page_range) {
+        (Some(a), Some(b)) => Some([a[0].min(b[0]), a[1].max(b[1])]),
+        (a, b) => a.or(b),
+    };
+    if page_range != node.page_range {
+        changes.push(FieldChange {
+            field: "page_range".to_string(),
+            old: node.page_range.map_or(Value::Null, |r| r.to_vec().into()),
+            new: page_range.map_or(Value::Null, |r| r.to_vec().into()),
+        });
+        node.page_range = page_range;
+    }
+
+    let (first, second) = if node_first {
+        (node.summary.clone(), other.summary)
+    } else {
+        (other.summary, node.summary.clone())
+    };
+    let summary = [first, second]
+        .into_iter()
+        .filter(|s| !s.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if summary != node.summary {
+        changes.push(FieldChange {
+            field: "summary".to_string(),
+            old: node.summary.clone().into(),
+            new: summary.clone().into(),
+        });
+        node.summary = summary;
+    }
+
+    if node_first {
+        node.children.extend(other.children);
+    } else {
+        let mut children = other.children;
+        children.append(&mut node.children);
+        node.children = children;
+    }
+    node.label = node.label.take().or(other.label);
+    node.date = node.date.take().or(other.date);
+    node.author = node.author.take().or(other.author);
+    node.references.extend(other.references);
+    node.referenced_by.extend(other.referenced_by);
+    clear_entities(node);
+    node.reviewed = true;
+    reslice(node, &pages, store);
+    changes.push(FieldChange {
+        field: "merged".to_string(),
+        old: Value::Null,
+        new: other_id.into(),
+    });
+
+    retarget_relationships(extraction, other_id, node_id);
+    Ok(changes)
+}
+
+/// Split `node_id` so that a new sibling right after it starts at `page`.
+///
+/// Children are divided at the same boundary (one that straddles it is an
+/// error), and both parts get their content re-sliced. The new node has an
+/// empty summary; relationships stay with the original. Returns the new
+/// node's ID and the changes to the original.
+pub fn split_node(
+    extraction: &mut Extraction,
+    node_id: &str,
+    page: u32,
+    label: Option<String>,
+    store: &ContentStore,
+) -> Result<(String, Vec<FieldChange>), String> {
+    let (parent, index) =
+        locate(&extraction.children, node_id, None).ok_or_else(|| not_found(node_id))?;
+    let mut new_id = format!("{}_p{}", node_id, page);
+    let mut n = 2;
+    while locate(&extraction.children, &new_id, None).is_some() {
+        new_id = format!("{}_p{}_{}", node_id, page, n);
+        n += 1;
+    }
+
+    let siblings = siblings_mut(extraction, parent.as_deref());
+    let node = &mut siblings[index];
+    let [start, end] = node
+        .page_range
+        .ok_or_else(|| format!("{} has no page_range to split", node_id))?;
+    if page <= start || page > end {
+        return Err(format!(
+            "Split page {} must be in {}-{} (after the node's first page)",
+            page,
+            start + 1,
+            end
+        ));
+    }
+    if let Some(child) = node
+        .children
+        .iter()
+        .find(|c| c.page_range.is_some_and(|[s, e]| s < page && e >= page))
+    {
+        return Err(format!(
+            "Child {} spans page {}; move or split it first",
+            child.id, page
+        ));
+    }
+
+    let pages = node_pages(node, store);
+    let (head, tail): (Vec<_>, Vec<_>) = std::mem::take(&mut node.children)
+        .into_iter()
+        .partition(|c| c.page_range.is_none_or(|[s, _]| s < page));
+    node.children = head;
+    node.page_range = Some([start, page - 1]);
+    node.reviewed = true;
+    clear_entities(node);
+    reslice(node, &pages, store);
+
+    let mut new_node = node.clone();
+    new_node.id = new_id.clone();
+    new_node.label = label.or(new_node.label);
+    new_node.page_range = Some([page, end]);
+    new_node.summary = String::new();
+    new_node.references = Vec::new();
+    new_node.referenced_by = Vec::new();
+    new_node.confidence = None;
+    new_node.content_ref = None;
+    new_node.children = tail;
+    reslice(&mut new_node, &pages, store);
+    siblings.insert(index + 1, new_node);
+
+    let changes = vec![
+        FieldChange {
+            field: "page_range".to_string(),
+            old: vec![start, end].into(),
+            new: vec![start, page - 1].into(),
+        },
+        FieldChange {
+            field: "split".to_string(),
+            old: Value::Null,
+            new: new_id.clone().into(),
+        },
+    ];
+    Ok((new_id, changes))
+}
+
+fn not_found(node_id: &str) -> String {
+    format!("Node {} not found", node_id)
+}
+
+/// Parent ID (`None` at the top level) and index of a node.
+fn locate(
+    nodes: &[DocumentNode],
+    id: &str,
+    parent: Option<&str>,
+) -> Option<(Option<String>, usize)> {
+    for (index, node) in nodes.iter().enumerate() {
+        if node.id == id {
+            return Some((parent.map(str::to_string), index));
+        }
+        if let Some(found) = locate(&node.children, id, Some(&node.id)) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn siblings_mut<'a>(
+    extraction: &'a mut Extraction,
+    parent: Option<&str>,
+) -> &'a mut Vec<DocumentNode> {
+    match parent {
+        None => &mut extraction.children,
+        Some(id) => {
+            &mut find_node_mut(&mut extraction.children, id)
+                .expect("parent located")
+                .children
+        }
+    }
+}
+
+fn node_pages(node: &DocumentNode, store: &ContentStore) -> Vec<OcrPage> {
+    node.content_ref
+        .as_deref()
+        .and_then(|r| store.get_full(r))
+        .map(|content| pages_from_content(&content))
+        .unwrap_or_default()
+}
+
+/// Store the node's page range of `pages` as its content.
+fn reslice(node: &mut DocumentNode, pages: &[OcrPage], store: &ContentStore) {
+    if let Some(range) = node.page_range {
+        let content = slice_pages(pages, range);
+        if !content.is_empty() {
+            node.content_ref = Some(store.store(&node.id, content));
+        }
+    }
+}
+
+/// Regex entities are recomputed from the new content.
+fn clear_entities(node: &mut DocumentNode) {
+    if let Some(metadata) = node.metadata.as_object_mut() {
+        metadata.remove("_entities");
+    }
+}
+
+fn retarget_relationships(extraction: &mut Extraction, from: &str, to: &str) {
+    fn walk(nodes: &mut [DocumentNode], from: &str, to: &str) {
+        for node in nodes {
+            for r in node
+                .references
+                .iter_mut()
+                .chain(node.referenced_by.iter_mut())
+            {
+                if r.node == from {
+                    r.node = to.to_string();
+                }
+            }
+            let id = node.id.clone();
+            node.references.retain(|r| r.node != id);
+            node.referenced_by.retain(|r| r.node != id);
+            walk(&mut node.children, from, to);
+        }
+    }
+
+    for r in &mut extraction.relationships {
+        if r.from == from {
+            r.from = to.to_string();
+        }
+        if r.to == from {
+            r.to = to.to_string();
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    extraction.relationships.retain(|r| {
+        r.from != r.to && seen.insert((r.from.clone(), r.to.clone(), r.rel_type.clone()))
+    });
+    walk(&mut extraction.children, from, to);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::create_default_config;
+
+    #[test]
+    fn test_apply_correction() {
+        let mut node: DocumentNode = serde_json::from_value(serde_json::json!({
+            "id": "n1",
+            "type": "SECTION",
+            "label": "Petiçao",
+            "page_range": [1, 3],
+            "summary": "Petição inicial."
+        }))
+        .unwrap();
+        let config = create_default_config();
+
+        let correction: NodeCorrection = serde_json::from_value(serde_json::json!({
+            "reviewer": "ana",
+            "label": "Petição inicial",
+            "type": "DOCUMENT",
+            "page_range": [1, 4],
+            "summary": "Petição inicial."
+        }))
+        .unwrap();
+        let changes = apply(&mut node, &correction, Some(&config), Some(10)).unwrap();
+        let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+        // Unchanged summary is not recorded
+        assert_eq!(fields, vec!["label", "type", "page_range"]);
+        assert_eq!(changes[2].old, serde_json::json!([1, 3]));
+        assert_eq!(node.page_range, Some([1, 4]));
+        assert!(node.reviewed);
+
+        let bad = |json| {
+            let correction: NodeCorrection = serde_json::from_value(json).unwrap();
+            apply(&mut node.clone(), &correction, Some(&config), Some(10)).unwrap_err()
+        };
+        assert!(bad(serde_json::json!({"type": "PETICAO"})).contains("Unknown node type"));
+        assert!(bad(serde_json::json!({"page_range": [5, 11]})).contains("Invalid page_range"));
+        assert!(bad(serde_json::json!({"date": "15/03/2024"})).contains("YYYY-MM-DD"));
+
+        // Confirmation without changes
+        let mut fresh = node.clone();
+        fresh.reviewed = false;
+        assert!(apply(&mut fresh, &NodeCorrection::default(), None, None)
+            .unwrap()
+            .is_empty());
+        assert!(fresh.reviewed);
+    }
+
+    #[test]
+    fn test_tree_edits() {
+        let store = ContentStore::new();
+        let pages: Vec<OcrPage> = (1..=6)
+            .map(|n| OcrPage {
+                page_num: n,
+                text: format!("page {}", n),
+                confidence: None,
+                pdf_page: None,
+            })
+            .collect();
+        let node = |id: &str, range: [u32; 2]| -> DocumentNode {
+            let mut node: DocumentNode = serde_json::from_value(serde_json::json!({
+                "id": id,
+                "type": "SECTION",
+                "page_range": range,
+                "summary": format!("{} summary", id),
+            }))
+            .unwrap();
+            node.content_ref = Some(store.store(id, slice_pages(&pages, range)));
+            node
+        };
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        let mut doc = node("doc", [1, 6]);
+        doc.children = vec![node("a", [1, 2]), node("b", [3, 4]), node("c", [5, 6])];
+        extraction.children = vec![doc];
+        extraction.relationships = vec![
+            crate::schema::Relationship {
+                from: "c".into(),
+                to: "b".into(),
+                rel_type: "responds_to".into(),
+                citation: None,
+            },
+            crate::schema::Relationship {
+                from: "a".into(),
+                to: "b".into(),
+                rel_type: "cites".into(),
+                citation: None,
+            },
+        ];
+
+        // Merge b into a: pages 1-4, relationships retargeted, self-reference dropped
+        let changes = merge_nodes(&mut extraction, "a", "b", &store).unwrap();
+        assert_eq!(changes.last().unwrap().new, "b");
+        let doc = &extraction.children[0];
+        assert_eq!(doc.children.len(), 2);
+        assert_eq!(doc.children[0].page_range, Some([1, 4]));
+        assert_eq!(doc.children[0].summary, "a summary\n\nb summary");
+        assert!(store.get_full("content://a").unwrap().contains("page 4"));
+        assert_eq!(extraction.relationships.len(), 1);
+        assert_eq!(extraction.relationships[0].to, "a");
+        assert!(merge_nodes(&mut extraction, "a", "doc", &store).is_err());
+
+        // Split a at page 3
+        let (new_id, _) = split_node(&mut extraction, "a", 3, None, &store).unwrap();
+        assert_eq!(new_id, "a_p3");
+        let doc = &extraction.children[0];
+        assert_eq!(doc.children[1].id, "a_p3");
+        assert_eq!(doc.children[1].page_range, Some([3, 4]));
+        assert_eq!(
+            store.get_full("content://a_p3").unwrap(),
+            slice_pages(&pages, [3, 4])
+        );
+        assert!(!store.get_full("content://a").unwrap().contains("page 3"));
+        assert!(split_node(&mut extraction, "a", 1, None, &store).is_err());
+        assert!(split_node(&mut extraction, "doc", 2, None, &store)
+            .unwrap_err()
+            .contains("spans page 2"));
+
+        // Move c to the top level, then back under doc first
+        let changes = move_node(&mut extraction, "c", None, None).unwrap();
+        assert_eq!(changes[0].old, "doc");
+        assert_eq!(extraction.children[1].id, "c");
+        move_node(&mut extraction, "c", Some("doc"), Some(0)).unwrap();
+        assert_eq!(extraction.children[0].children[0].id, "c");
+        assert!(move_node(&mut extraction, "doc", Some("c"), None).is_err());
+    }
+}
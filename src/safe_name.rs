@@ -0,0 +1,41 @@
+//! File names for keys chosen by users (config names, job and extraction
+//! IDs, golden case names).
+//!
+//! A key made of ASCII letters, digits, `_`, `-` and `.` (not leading, at
+//! most 128 bytes) is used as is; any other key is replaced by the hex
+//! SHA-256 of it. Distinct keys never share a file, and a key can't climb
+//! out of its directory.
+
+use sha2::{Digest, Sha256};
+
+const MAX_PLAIN_BYTES: usize = 128;
+
+/// The file name (without extension) that stores `key`.
+pub fn file_name(key: &str) -> String {
+    let plain = !key.is_empty()
+        && key.len() <= MAX_PLAIN_BYTES
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if plain {
+        key.to_string()
+    } else {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("legal_br"), "legal_br");
+        assert_eq!(file_name("legal.br"), "legal.br");
+        let hashed = [file_name("legal br"), file_name("legal/br"), file_name("..")];
+        assert!(hashed.iter().all(|name| name.len() == 64));
+        assert_ne!(hashed[0], hashed[1]);
+        assert_eq!(file_name(&"a".repeat(129)).len(), 64);
+    }
+}
@@ -0,0 +1,368 @@
+//! Scheduled re-extraction and retention.
+//!
+//! A config's `reextract_schedule` is a five-field cron expression (minute,
+//! hour, day of month, month, day of week; UTC). At those times the server
+//! re-runs the config's completed extractions, but only if the config changed
+//! since its last re-run. Changes are detected with a fingerprint of the
+//! config, ignoring the fields that only control scheduling, retention,
+//! mail and quotas. Fingerprints persist in `SCHEDULER_STATE` (default
+//! `data/scheduler.json`), so a config edited while the server was down is
+//! still picked up. A config seen for the first time only records its
+//! fingerprint.
+//!
+//! Retention purges completed extractions and datasets older than their
+//! config's `retention_days`, or `RETENTION_DAYS` for configs without one,
+//! from memory, the content store, `data/datasets/`, and the storage backend.
+//! It runs at `RETENTION_SCHEDULE` (default `0 3 * * *`) and on
+//! `POST /admin/retention/run`. With neither setting, nothing expires.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::config::ExtractionConfig;
+
+const DEFAULT_STATE_PATH: &str = "data/scheduler.json";
+const DEFAULT_RETENTION_SCHEDULE: &str = "0 3 * * *";
+
+/// A parsed cron expression; each field is a bitmask of the allowed values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month / day of week were `*` (cron ORs them otherwise)
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    /// Parse `minute hour day-of-month month day-of-week`. Each field takes
+    /// `*`, values, ranges (`1-5`), steps (`*/15`, `0-30/10`) and lists of
+    /// those. Day of week runs 0-7, with both 0 and 7 meaning Sunday.
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("expected 5 fields, got {}: '{}'", fields.len(), expr);
+        };
+        let mut weekdays = field(weekday, 0, 7).context("day of week")?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: field(minute, 0, 59).context("minute")?,
+            hours: field(hour, 0, 23).context("hour")?,
+            days: field(day, 1, 31).context("day of month")?,
+            months: field(month, 1, 12).context("month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// Whether the schedule fires in the (UTC) minute containing `at`.
+    pub fn matches(&self, at: SystemTime) -> bool {
+        let secs = at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let days = secs / 86400;
+        let (_, month, day) = civil_from_days(days);
+        let minute = secs % 3600 / 60;
+        let hour = secs % 86400 / 3600;
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4) % 7;
+
+        let day_ok = self.days & (1 << day) != 0;
+        let weekday_ok = self.weekdays & (1 << weekday) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day_ok,
+            (true, false) => weekday_ok,
+            (false, false) => day_ok || weekday_ok,
+        };
+        self.minutes & (1 << minute) != 0
+            && self.hours & (1 << hour) != 0
+            && self.months & (1 << month) != 0
+            && day_matches
+    }
+}
+
+/// One cron field as a bitmask of the values in `min..=max`.
+fn field(spec: &str, min: u64, max: u64) -> Result<u64> {
+    let mut mask = 0u64;
+    for item in spec.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().context("invalid step")?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            bail!("step must be positive");
+        }
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse()?, b.parse()?),
+                None => {
+                    let value = range.parse()?;
+                    // `5/10` means from 5 to the end
+                    (value, if item.contains('/') { max } else { value })
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!("{} is outside {}-{}", item, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+/// (year, month, day) of a day count since 1970-01-01.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's algorithm, shifted so the era starts in March
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Hash of everything in a config that affects extraction results.
+pub fn fingerprint(config: &ExtractionConfig) -> String {
+    let mut config = config.clone();
+    config.reextract_schedule = None;
+    config.retention_days = None;
+    config.quotas = None;
+    config.mail_rules.clear();
+    let json = serde_json::to_vec(&config).unwrap_or_default();
+    format!("{:x}", Sha256::digest(json))[..16].to_string()
+}
+
+/// Records and triggers of scheduled work.
+pub struct Scheduler {
+    path: PathBuf,
+    /// Config name -> fingerprint at its last re-extraction (or first sighting)
+    fingerprints: Mutex<HashMap<String, String>>,
+    retention_days: Option<u32>,
+    retention_schedule: Cron,
+}
+
+/// What a retention run removed (or, for a dry run, would remove).
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub extractions: Vec<String>,
+    pub datasets: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl Scheduler {
+    /// Read `SCHEDULER_STATE`, `RETENTION_DAYS` and `RETENTION_SCHEDULE`.
+    pub fn from_env() -> Result<Self> {
+        let path =
+            std::env::var("SCHEDULER_STATE").unwrap_or_else(|_| DEFAULT_STATE_PATH.to_string());
+        let retention_days = match std::env::var("RETENTION_DAYS") {
+            Ok(days) => Some(days.parse().context("Invalid RETENTION_DAYS")?),
+            Err(_) => None,
+        };
+        let schedule = std::env::var("RETENTION_SCHEDULE")
+            .unwrap_or_else(|_| DEFAULT_RETENTION_SCHEDULE.to_string());
+        let retention_schedule = Cron::parse(&schedule).context("Invalid RETENTION_SCHEDULE")?;
+        let mut scheduler = Self::open(path)?;
+        scheduler.retention_days = retention_days;
+        scheduler.retention_schedule = retention_schedule;
+        Ok(scheduler)
+    }
+
+    /// Load fingerprints from `path`, with no default retention.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let fingerprints = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Invalid scheduler state in {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self {
+            path,
+            fingerprints: Mutex::new(fingerprints),
+            retention_days: None,
+            retention_schedule: Cron::parse(DEFAULT_RETENTION_SCHEDULE)?,
+        })
+    }
+
+    /// Days a config's results are kept (`None`: forever).
+    pub fn retention_days(&self, config: Option<&ExtractionConfig>) -> Option<u32> {
+        config
+            .and_then(|c| c.retention_days)
+            .or(self.retention_days)
+    }
+
+    /// Whether a retention run is scheduled in the minute containing `at`.
+    pub fn retention_due(&self, at: SystemTime) -> bool {
+        self.retention_schedule.matches(at)
+    }
+
+    /// Configs whose re-extraction is scheduled at `at` and which changed
+    /// since their last one. Their new fingerprints are recorded right away,
+    /// so each change is re-extracted once.
+    pub fn due_reextractions(
+        &self,
+        configs: &[ExtractionConfig],
+        at: SystemTime,
+    ) -> Vec<ExtractionConfig> {
+        let mut fingerprints = self.fingerprints.lock().unwrap();
+        let mut due = Vec::new();
+        let mut dirty = false;
+        for config in configs {
+            let current = fingerprint(config);
+            let Some(previous) = fingerprints.get(&config.name) else {
+                fingerprints.insert(config.name.clone(), current);
+                dirty = true;
+                continue;
+            };
+            let Some(ref expr) = config.reextract_schedule else {
+                continue;
+            };
+            let scheduled = match Cron::parse(expr) {
+                Ok(cron) => cron.matches(at),
+                Err(e) => {
+                    warn!(
+                        "Config {} has an invalid reextract_schedule: {}",
+                        config.name, e
+                    );
+                    false
+                }
+            };
+            if scheduled && *previous != current {
+                info!(
+                    "Config {} changed ({} -> {}); re-extracting",
+                    config.name, previous, current
+                );
+                fingerprints.insert(config.name.clone(), current);
+                due.push(config.clone());
+                dirty = true;
+            }
+        }
+        if dirty {
+            if let Err(e) = self.save(&fingerprints) {
+                warn!("Failed to save scheduler state: {}", e);
+            }
+        }
+        due
+    }
+
+    fn save(&self, fingerprints: &HashMap<String, String>) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(fingerprints)?)?;
+        Ok(())
+    }
+}
+
+/// Timestamp before which results kept for `days` have expired.
+pub fn cutoff(days: u32, now: SystemTime) -> String {
+    crate::schema::iso8601(now - Duration::from_secs(u64::from(days) * 86400))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `at("2024-02-29T03:30")`, in UTC.
+    fn at(iso: &str) -> SystemTime {
+        let secs = match iso {
+            "2024-02-29T03:30" => 1709177400,
+            "2024-02-29T03:31" => 1709177460,
+            "2024-03-01T00:00" => 1709251200,
+            "2024-03-04T12:00" => 1709553600,
+            "2024-03-04T12:05" => 1709553900,
+            "2024-03-04T13:00" => 1709557200,
+            "2024-03-09T00:00" => 1709942400,
+            "2024-03-10T00:00" => 1710028800,
+            "2024-06-01T18:00" => 1717264800,
+            _ => unreachable!("add {} to the table", iso),
+        };
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_cron() {
+        let nightly = Cron::parse("30 3 * * *").unwrap();
+        assert!(nightly.matches(at("2024-02-29T03:30")));
+        assert!(!nightly.matches(at("2024-02-29T03:31")));
+
+        // 2024-03-04 is a Monday; day-of-month and day-of-week are ORed
+        let weekly = Cron::parse("0 */6 1 * 1-5").unwrap();
+        assert!(weekly.matches(at("2024-03-04T12:00")));
+        assert!(weekly.matches(at("2024-06-01T18:00"))); // Saturday, but the 1st
+        assert!(!weekly.matches(at("2024-03-09T00:00")));
+        assert!(!weekly.matches(at("2024-03-04T13:00")));
+        assert!(Cron::parse("0 0 * * 7")
+            .unwrap()
+            .matches(at("2024-03-10T00:00")));
+
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(cutoff(1, at("2024-03-01T00:00")).starts_with("2024-02-29T00:00"));
+    }
+
+    #[test]
+    fn test_due_reextractions() {
+        let path = std::env::temp_dir().join(format!("scheduler_{}.json", uuid::Uuid::new_v4()));
+        let mut config: ExtractionConfig = serde_json::from_value(serde_json::json!({
+            "name": "legal_br",
+            "description": "",
+            "prompts": {"structure": "v1"},
+            "reextract_schedule": "0 * * * *",
+        }))
+        .unwrap();
+        let on_the_hour = at("2024-03-04T12:00");
+
+        let scheduler = Scheduler::open(&path).unwrap();
+        assert!(scheduler
+            .due_reextractions(&[config.clone()], on_the_hour)
+            .is_empty());
+
+        // Scheduling fields are not changes
+        config.retention_days = Some(30);
+        assert!(scheduler
+            .due_reextractions(&[config.clone()], on_the_hour)
+            .is_empty());
+
+        config.prompts.structure = "v2".to_string();
+        assert!(scheduler
+            .due_reextractions(&[config.clone()], at("2024-03-04T12:05"))
+            .is_empty());
+        // The change survives a restart and is picked up once, at the scheduled time
+        let scheduler = Scheduler::open(&path).unwrap();
+        assert_eq!(
+            scheduler
+                .due_reextractions(&[config.clone()], on_the_hour)
+                .len(),
+            1
+        );
+        assert!(scheduler
+            .due_reextractions(&[config.clone()], on_the_hour)
+            .is_empty());
+
+        assert_eq!(scheduler.retention_days(Some(&config)), Some(30));
+        assert_eq!(scheduler.retention_days(None), None);
+        std::fs::remove_file(path).ok();
+    }
+}
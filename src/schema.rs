@@ -9,7 +9,12 @@ use uuid::Uuid;
 
 /// Generate ISO8601 timestamp for current time.
 pub fn now_iso8601() -> String {
-    let duration = SystemTime::now()
+    iso8601(SystemTime::now())
+}
+
+/// ISO8601 timestamp (UTC, whole seconds) for `time`.
+pub fn iso8601(time: SystemTime) -> String {
+    let duration = time
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
     let secs = duration.as_secs();
@@ -58,12 +63,49 @@ fn is_leap_year(year: i32) -> bool {
 }
 
 /// Extraction processing status.
+///
+/// Document extractions move through `queued` → `ocr_running` →
+/// `llm_running` → `processing` (post-LLM pipeline stages) → `uploading` →
+/// `completed`; sheet extractions report `processing` throughout.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ExtractionStatus {
+    /// Waiting for a free run slot
+    Queued,
     Processing,
+    OcrRunning,
+    LlmRunning,
+    Uploading,
     Completed,
     Failed,
+    Cancelled,
+}
+
+impl ExtractionStatus {
+    /// Whether the job is still queued or running.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            Self::Queued | Self::Processing | Self::OcrRunning | Self::LlmRunning | Self::Uploading
+        )
+    }
+}
+
+/// Wall-clock timing of an extraction run (durations in milliseconds).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractionTiming {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_ms: Option<u64>,
 }
 
 /// Root extraction result.
@@ -72,27 +114,73 @@ pub struct Extraction {
     pub id: String,
     pub version: u32,
     pub status: ExtractionStatus,
-    /// Error message when status is "failed"
+    /// Error message when status is "failed" or "cancelled"
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Current (or, after a failure, last) pipeline step, e.g. "Running OCR (docling)"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    /// Coarse progress estimate, 0-100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_pct: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<ExtractionTiming>,
+    /// Times this extraction was re-run with `POST /extractions/:id/retry`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retry_count: u32,
     /// Which config was used for this extraction
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config_name: Option<String>,
+    /// Fingerprint of the config as it was when this extraction ran (see `config_history`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_version: Option<String>,
+    /// Structure prompt that replaced the config's for this run (`POST /extract`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_override: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub previous_version_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content_hash: Option<String>,
+    /// Simhash of the OCR text, for near-duplicate detection (see `dedup`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Earlier extraction of the same document, if this one is a (near) duplicate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    /// Extraction this one was re-extracted from, for an exhibit node
+    /// (`POST /extractions/:id/node/:node_id/extract`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_extraction_id: Option<String>,
+    /// Node of the parent extraction whose pages this one covers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_node_id: Option<String>,
+    /// Language detected from the OCR text (ISO 639-1, e.g. "pt")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
     pub source_file: String,
+    /// Signed download link for the archived original, when the object store
+    /// can sign one (Supabase Storage); it expires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
     pub extracted_at: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub extractor_version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_pages: Option<u32>,
+    /// OCR provider that read the document (`docling`, `mistral_ocr`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_provider: Option<String>,
+    /// Document-level OCR confidence (0-1) the provider reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_confidence: Option<f64>,
     pub summary: String,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub structure_map: Vec<StructureMapEntry>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub relationships: Vec<Relationship>,
+    /// What was repaired or dropped from the LLM's answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<ExtractionDiagnostics>,
     /// Dynamic metadata - structure defined by config
     #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub metadata: serde_json::Value,
@@ -102,6 +190,12 @@ pub struct Extraction {
     /// Human-readable document identifier (e.g. case number, invoice ID)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub readable_id: Option<String>,
+    /// Whether a reviewer has corrected or confirmed any node
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reviewed: bool,
+    /// Audit trail of reviewer corrections, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviews: Vec<NodeReview>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub children: Vec<DocumentNode>,
 }
@@ -113,22 +207,69 @@ impl Extraction {
             version: 1,
             status: ExtractionStatus::Processing,
             error: None,
+            stage: None,
+            progress_pct: None,
+            timing: None,
+            retry_count: 0,
             config_name,
+            config_version: None,
+            prompt_override: None,
             previous_version_id: None,
             content_hash: None,
+            fingerprint: None,
+            duplicate_of: None,
+            parent_extraction_id: None,
+            parent_node_id: None,
+            language: None,
             source_file,
+            source_url: None,
             extracted_at: now_iso8601(),
             extractor_version: Some(env!("CARGO_PKG_VERSION").to_string()),
             total_pages: None,
+            ocr_provider: None,
+            ocr_confidence: None,
             summary: String::new(),
             structure_map: Vec::new(),
             relationships: Vec::new(),
+            diagnostics: None,
             metadata: serde_json::Value::Null,
             reference_index: serde_json::Value::Null,
             readable_id: None,
+            reviewed: false,
+            reviews: Vec::new(),
             children: Vec::new(),
         }
     }
+
+    /// Number of nodes in the tree, at every depth.
+    pub fn node_count(&self) -> usize {
+        fn count(nodes: &[DocumentNode]) -> usize {
+            nodes.iter().map(|n| 1 + count(&n.children)).sum()
+        }
+        count(&self.children)
+    }
+
+    /// Rebuild `structure_map` from the node tree: one entry per node, in
+    /// document order, labelled with its type when it has no label.
+    pub fn refresh_structure_map(&mut self) {
+        fn walk(nodes: &[DocumentNode], map: &mut Vec<StructureMapEntry>) {
+            for node in nodes {
+                map.push(StructureMapEntry {
+                    id: node.id.clone(),
+                    label: node.label.clone().unwrap_or_else(|| node.node_type.clone()),
+                    children: node.children.iter().map(|c| c.id.clone()).collect(),
+                });
+                walk(&node.children, map);
+            }
+        }
+        let mut map = Vec::new();
+        walk(&self.children, &mut map);
+        self.structure_map = map;
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
 }
 
 /// Flat structure map entry for quick navigation.
@@ -140,6 +281,39 @@ pub struct StructureMapEntry {
     pub children: Vec<String>,
 }
 
+/// Relationships the LLM returned that did not fit the document's tree or
+/// the config (see `relationships`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtractionDiagnostics {
+    /// Left out: an end or the type could not be resolved, or it repeats another
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped_relationships: Vec<DroppedRelationship>,
+    /// Kept after a node ID or the type was corrected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repaired_relationships: Vec<RepairedRelationship>,
+}
+
+impl ExtractionDiagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.dropped_relationships.is_empty() && self.repaired_relationships.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedRelationship {
+    /// As the LLM returned it
+    pub relationship: Relationship,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairedRelationship {
+    /// As the LLM returned it; the corrected one is in `relationships`
+    pub original: Relationship,
+    /// One entry per corrected field, e.g. `to: "Sentenca" -> "sentenca"`
+    pub changes: Vec<String>,
+}
+
 /// Cross-reference between document nodes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Relationship {
@@ -163,6 +337,14 @@ pub struct DocumentNode {
     pub label: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub page_range: Option<[u32; 2]>,
+    /// Character offsets `[start, end)` of the node's pages in the OCR text
+    /// (see `extractor::page_spans`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_span: Option<[usize; 2]>,
+    /// `page_range` in pages of the source PDF, when the OCR provider
+    /// numbered pages differently (see `OcrPage::pdf_page`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdf_page_range: Option<[u32; 2]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub date: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -179,10 +361,49 @@ pub struct DocumentNode {
     /// Node-level dynamic metadata
     #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub metadata: serde_json::Value,
+    /// Corrected or confirmed by a reviewer (see `PATCH /extractions/:id/node/:node_id`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reviewed: bool,
+    /// Extraction of this node's pages with another config, for exhibits
+    /// such as a contract or an invoice attached to a filing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub child_extraction_id: Option<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub children: Vec<DocumentNode>,
 }
 
+/// Audit trail entry: one reviewer correction to a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeReview {
+    pub id: String,
+    pub node_id: String,
+    pub reviewer: String,
+    pub reviewed_at: String,
+    /// Fields changed; empty when the reviewer confirmed the node as-is
+    #[serde(default)]
+    pub changes: Vec<FieldChange>,
+}
+
+impl NodeReview {
+    pub fn new(node_id: String, reviewer: String, changes: Vec<FieldChange>) -> Self {
+        Self {
+            id: format!("rev_{}", Uuid::new_v4().simple()),
+            node_id,
+            reviewer,
+            reviewed_at: now_iso8601(),
+            changes,
+        }
+    }
+}
+
+/// One field's value before and after a correction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
 /// Embedded cross-reference within a node.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddedReference {
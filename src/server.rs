@@ -0,0 +1,7501 @@
+//! HTTP API: the axum router, its handlers, and the background jobs they start.
+
+use crate::{
+    admin, api_error, confidence, config, config_history, config_lint, content_store,
+    content_summary, dataset_append, dataset_edit, dataset_query, dedup, entities, estimate, eval,
+    events, experiment, extractor, failures, folios, gce, graph, http_cache, ingest, jobs, live,
+    mail, object_store, ocr, openrouter, page_image, pipeline, prompt, quotas, readable_id,
+    redaction, review, scheduler, schema, shared, sheet_extractor, sheet_parser, sheet_schema,
+    signatures, sinks, sparse, storage, sync, timeline, toc, training, upload,
+};
+use api_error::ApiError;
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use config::{ConfigKind, ConfigStore};
+use content_store::ContentStore;
+use extractor::Extractor;
+use ocr::{OcrInput, OcrProvider, OcrProviderKind};
+use openrouter::{mock::MockLlmClient, OpenRouterClient};
+use schema::{Extraction, ExtractionStatus};
+use sheet_schema::SheetExtraction;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::CorsLayer;
+use tower_http::trace::TraceLayer;
+use tracing::{debug, error, info, warn};
+
+/// Application state shared across handlers.
+#[derive(Clone)]
+struct AppState {
+    extractions: Arc<live::LiveMap<Extraction, ExtractionSummary>>,
+    datasets: Arc<live::LiveMap<SheetExtraction, DatasetSummary>>,
+    content_store: ContentStore,
+    openrouter: Arc<OpenRouterClient>,
+    configs: Arc<ConfigStore>,
+    config_history: Arc<config_history::ConfigHistory>,
+    http_client: reqwest::Client,
+    storage: Option<Arc<dyn storage::Storage>>,
+    object_store: Option<Arc<dyn object_store::ObjectStore>>,
+    outbox: Option<Arc<sync::SyncOutbox>>,
+    events: Option<events::EventBus>,
+    shared: Option<Arc<shared::SharedState>>,
+    jobs: Arc<jobs::JobJournal>,
+    running: Arc<jobs::RunningJobs>,
+    recovery: Arc<RwLock<jobs::RecoveryReport>>,
+    eval: Arc<eval::EvalStore>,
+    failures: Arc<failures::FailureStore>,
+    ocr_providers: Arc<HashMap<OcrProviderKind, Arc<dyn OcrProvider>>>,
+    scheduler: Arc<scheduler::Scheduler>,
+    quotas: Arc<quotas::QuotaLedger>,
+    background: Arc<admin::BackgroundTasks>,
+}
+
+/// The extraction API server.
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let server = generic_extractor::Server::builder().build().await?;
+/// server.serve("0.0.0.0:3002").await
+/// # }
+/// ```
+pub struct Server {
+    state: AppState,
+    /// Stops the Docling GCE instance once idle (GCE on-demand only).
+    idle_stop: Option<gce::IdleStop>,
+}
+
+/// Builds a [`Server`]. Whatever is not set here is configured from the
+/// environment, the same way the `generic-extractor` binary does it.
+#[derive(Default)]
+pub struct ServerBuilder {
+    openrouter: Option<OpenRouterClient>,
+    configs: Option<ConfigStore>,
+    storage: Option<Arc<dyn storage::Storage>>,
+    object_store: Option<Arc<dyn object_store::ObjectStore>>,
+    content_store: Option<ContentStore>,
+    ocr_providers: HashMap<OcrProviderKind, Arc<dyn OcrProvider>>,
+    mock_dir: Option<PathBuf>,
+    skip_recovery: bool,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// The API routes, for mounting into another axum app.
+    pub fn router(&self) -> Router {
+        build_router(self.state.clone())
+    }
+
+    /// Run the config's full pipeline on one document in-process, without
+    /// the HTTP API or the job journal, and return the completed extraction.
+    /// Node content stays in the server's content store (see [`Server::export_content`]).
+    /// Without `config_name` the default document config is used.
+    pub async fn extract(
+        &self,
+        input: OcrInput,
+        config_name: Option<&str>,
+        ocr_provider: OcrProviderKind,
+        upload: bool,
+    ) -> anyhow::Result<Extraction> {
+        let config = requested_config(&self.state, config_name, ConfigKind::Document)?;
+        let provider = self
+            .state
+            .ocr_providers
+            .get(&ocr_provider)
+            .cloned()
+            .ok_or_else(|| ApiError::ProviderUnconfigured(ocr_provider.as_str().to_string()))?;
+        let filename = input.filename().to_string();
+
+        let mut extraction = Extraction::new(filename.clone(), Some(config.name.clone()));
+        mark_queued(&mut extraction);
+        let id = extraction.id.clone();
+        self.state.extractions.insert(id.clone(), extraction);
+
+        let job = ExtractionJob {
+            id: id.clone(),
+            filename,
+            config: Arc::new(config),
+            prompt_override: None,
+            upload,
+            callback_url: None,
+            priority: jobs::Priority::Normal,
+        };
+        run_extraction(&self.state, job, PipelineInput::Source(provider, input)).await;
+
+        let extraction = self.state.extractions.remove(&id);
+        match extraction {
+            Some(ext) if ext.status == ExtractionStatus::Completed => Ok(ext),
+            Some(ext) => Err(anyhow::anyhow!(ext
+                .error
+                .unwrap_or_else(|| "Extraction failed".to_string()))),
+            None => Err(anyhow::anyhow!("Extraction {} disappeared", id)),
+        }
+    }
+
+    /// Write each node's content to `{dir}/{node_id}.txt` (and the redacted
+    /// copy, if any, to `{node_id}.redacted.txt`). Returns the files written.
+    pub fn export_content(
+        &self,
+        extraction: &Extraction,
+        dir: &std::path::Path,
+    ) -> std::io::Result<usize> {
+        fn walk(
+            store: &ContentStore,
+            nodes: &[schema::DocumentNode],
+            dir: &std::path::Path,
+            written: &mut usize,
+        ) -> std::io::Result<()> {
+            for node in nodes {
+                if let Some(ref content_ref) = node.content_ref {
+                    let copies = [
+                        (content_ref.clone(), node.id.clone()),
+                        (
+                            format!("{}{}", content_ref, redaction::REDACTED_SUFFIX),
+                            format!("{}{}", node.id, redaction::REDACTED_SUFFIX),
+                        ),
+                    ];
+                    for (content_ref, name) in copies {
+                        if let Some(content) = store.get_full(&content_ref) {
+                            std::fs::write(dir.join(format!("{}.txt", name)), content)?;
+                            *written += 1;
+                        }
+                    }
+                }
+                walk(store, &node.children, dir, written)?;
+            }
+            Ok(())
+        }
+
+        std::fs::create_dir_all(dir)?;
+        let mut written = 0;
+        walk(
+            &self.state.content_store,
+            &extraction.children,
+            dir,
+            &mut written,
+        )?;
+        Ok(written)
+    }
+
+    /// Listen on `addr` and serve the API until the process stops.
+    /// Also runs scheduled re-extraction and retention, and watches
+    /// `INGEST_DIR` and polls the `IMAP_HOST` mailbox, if set.
+    pub async fn serve(self, addr: &str) -> anyhow::Result<()> {
+        spawn_scheduler(self.state.clone());
+        self.state
+            .background
+            .register("scheduler", "config re-extraction and retention");
+        if let Some(interval) = config_reload_interval_from_env() {
+            info!("Reloading configs every {}s", interval.as_secs());
+            self.state
+                .background
+                .register("config-reload", format!("every {}s", interval.as_secs()));
+            spawn_config_reload(self.state.clone(), interval);
+        }
+        if let Some(folder) = ingest::WatchFolder::from_env()? {
+            info!(
+                "Watching {} for new documents (every {}s)",
+                folder.dir().display(),
+                folder.interval().as_secs()
+            );
+            self.state
+                .background
+                .register("ingest", folder.dir().display().to_string());
+            spawn_ingest(self.state.clone(), folder);
+        }
+        if let Some(mailbox) = mail::Mailbox::from_env()? {
+            info!(
+                "Polling {} for attachments (every {}s)",
+                mailbox.describe(),
+                mailbox.interval().as_secs()
+            );
+            self.state.background.register("mail", mailbox.describe());
+            spawn_mail(self.state.clone(), mailbox);
+        }
+        if let Some(idle_stop) = self.idle_stop {
+            let instances = idle_stop.config.instances.join(", ");
+            info!(
+                "Stopping GCE instance(s) {} after {}s without OCR requests",
+                instances,
+                idle_stop.window.as_secs()
+            );
+            self.state.background.register(
+                "gce-idle-stop",
+                format!("{} after {}s idle", instances, idle_stop.window.as_secs()),
+            );
+            spawn_gce_idle_stop(self.state.clone(), idle_stop);
+        }
+        if let Some(shared) = self.state.shared.clone() {
+            self.state.background.register(
+                "job-takeover",
+                format!(
+                    "resumes jobs of stopped replicas every {}s",
+                    JOB_TAKEOVER_INTERVAL.as_secs()
+                ),
+            );
+            if shared.queues_jobs() {
+                self.state
+                    .background
+                    .register("job-queue", "runs jobs from the shared queue");
+                spawn_job_queue(self.state.clone(), shared.clone());
+            }
+            spawn_job_takeover(self.state.clone(), shared);
+        }
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Server listening on http://{}", addr);
+        axum::serve(listener, build_router(self.state)).await?;
+        Ok(())
+    }
+}
+
+impl ServerBuilder {
+    /// LLM client (default: `OpenRouterClient::from_env`).
+    pub fn openrouter(mut self, client: OpenRouterClient) -> Self {
+        self.openrouter = Some(client);
+        self
+    }
+
+    /// Extraction configs (default: the storage backend's, else `configs/`).
+    pub fn configs(mut self, configs: ConfigStore) -> Self {
+        self.configs = Some(configs);
+        self
+    }
+
+    /// Storage backend (default: `STORAGE_BACKEND`, if any).
+    pub fn storage(mut self, storage: Arc<dyn storage::Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Object store for source files and OCR output (default: `OBJECT_STORE_BACKEND`, if any).
+    pub fn object_store(mut self, store: Arc<dyn object_store::ObjectStore>) -> Self {
+        self.object_store = Some(store);
+        self
+    }
+
+    /// Node content store (default: `ContentStore::from_env`).
+    pub fn content_store(mut self, store: ContentStore) -> Self {
+        self.content_store = Some(store);
+        self
+    }
+
+    /// Serve `kind` with `provider` instead of the built-in one.
+    pub fn ocr_provider(mut self, kind: OcrProviderKind, provider: Arc<dyn OcrProvider>) -> Self {
+        self.ocr_providers.insert(kind, provider);
+        self
+    }
+
+    /// Play back LLM and OCR fixtures from `dir` (default: `EXTRACTOR_MOCK`).
+    pub fn mock(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.mock_dir = Some(dir.into());
+        self
+    }
+
+    /// Whether `build` re-enqueues jobs left in the journal by a previous run
+    /// (default: true). One-shot runs turn this off so they do not pick up
+    /// a server's interrupted work.
+    pub fn recover_jobs(mut self, recover: bool) -> Self {
+        self.skip_recovery = !recover;
+        self
+    }
+
+    /// Connect the backends, load configs and datasets, start background
+    /// sync, and recover jobs interrupted by a previous run.
+    pub async fn build(self) -> anyhow::Result<Server> {
+        // EXTRACTOR_MOCK=1 swaps OpenRouter and every OCR provider for fixture playback
+        let mock_dir = self.mock_dir.or_else(mock_fixture_dir);
+
+        // Initialize OpenRouter client
+        let openrouter = match (self.openrouter, &mock_dir) {
+            (Some(client), _) => client,
+            (None, Some(dir)) => {
+                let mock = MockLlmClient::from_dir(dir)?;
+                info!("Mock LLM enabled with fixtures from {}", dir.display());
+                OpenRouterClient::mock(mock)
+            }
+            (None, None) => OpenRouterClient::from_env()?,
+        };
+        info!("OpenRouter client initialized");
+
+        // Initialize storage backend (optional): STORAGE_BACKEND selects it,
+        // defaulting to Supabase when its env vars are set
+        let storage = match self.storage {
+            Some(st) => Some(st),
+            None => storage::from_env().await?,
+        };
+        if let Some(ref st) = storage {
+            info!("Storage backend initialized: {}", st.name());
+        }
+
+        // Initialize object store (optional) for source files and raw OCR output
+        let object_store = match self.object_store {
+            Some(os) => Some(os),
+            None => object_store::from_env()?,
+        };
+        match object_store {
+            Some(ref os) => info!("Object store initialized: {}", os.name()),
+            None => info!("Object store not configured (set OBJECT_STORE_BACKEND to archive source files and OCR output)"),
+        }
+
+        // Load configs: storage-first with filesystem fallback + auto-seed
+        let config_dir = std::path::Path::new(CONFIG_DIR);
+        let configs = if let Some(configs) = self.configs {
+            configs
+        } else if let Some(ref st) = storage {
+            match st.list_configs().await {
+                Ok(stored) if !stored.is_empty() => {
+                    info!("Loaded {} configs from {}", stored.len(), st.name());
+                    ConfigStore::from_configs(stored)?
+                }
+                Ok(_) => {
+                    // Configs table is empty — seed from disk if available
+                    info!("{} configs table is empty, seeding from disk", st.name());
+                    let store = ConfigStore::load_from_dir(config_dir)?;
+                    let all = store.all();
+                    for cfg in &all {
+                        if let Err(e) = st.upsert_config(cfg).await {
+                            error!(
+                                "Failed to seed config '{}' to {}: {}",
+                                cfg.name,
+                                st.name(),
+                                e
+                            );
+                        }
+                    }
+                    info!("Seeded {} configs from disk to {}", all.len(), st.name());
+                    store
+                }
+                Err(e) => {
+                    info!(
+                        "Failed to load configs from {} ({}), falling back to disk",
+                        st.name(),
+                        e
+                    );
+                    ConfigStore::load_from_dir(config_dir)?
+                }
+            }
+        } else {
+            ConfigStore::load_from_dir(config_dir)?
+        };
+        info!(
+            "Loaded {} configs: {:?}",
+            configs.list().len(),
+            configs.list()
+        );
+        // Configs edited on disk or in storage while the server was down get a new version
+        let config_history = config_history::ConfigHistory::from_env()?;
+        for config in configs.all() {
+            if let Err(e) = config_history.record(&config) {
+                error!(
+                    "Failed to record version of config '{}': {}",
+                    config.name, e
+                );
+            }
+        }
+
+        // Initialize OCR providers
+        let http_client = reqwest::Client::new();
+        let mut ocr_providers: HashMap<OcrProviderKind, Arc<dyn OcrProvider>> = HashMap::new();
+
+        // GCE on-demand config (optional — all 4 env vars must be set)
+        let gce_config = gce::GceConfig::from_env();
+        if gce_config.is_some() {
+            info!("GCE on-demand enabled for Docling (will auto-start instance on connection failure)");
+        } else {
+            info!("GCE on-demand disabled (set GCE_PROJECT_ID, GCE_ZONE, GCE_INSTANCE_NAME, GCE_SA_KEY_PATH to enable)");
+        }
+
+        // Docling is always available
+        let docling = ocr::docling::DoclingProvider::new(http_client.clone(), gce_config.clone());
+        let idle_stop = gce_config.and_then(|config| {
+            config.idle_stop.map(|window| gce::IdleStop {
+                config,
+                trackers: docling.idle_trackers(),
+                window,
+            })
+        });
+        ocr_providers.insert(OcrProviderKind::Docling, Arc::new(docling));
+        info!("OCR provider registered: docling");
+
+        // Mistral OCR is optional (only if MISTRAL_API_KEY is set)
+        match ocr::mistral::MistralOcrProvider::from_env(http_client.clone()) {
+            Ok(provider) => {
+                ocr_providers.insert(OcrProviderKind::MistralOcr, Arc::new(provider));
+                info!("OCR provider registered: mistral_ocr");
+            }
+            Err(_) => {
+                info!("OCR provider skipped: mistral_ocr (MISTRAL_API_KEY not set)");
+            }
+        }
+
+        // SmolDocling is optional (only if SMOL_DOCLING_URL is set)
+        if let Some(provider) =
+            ocr::smol_docling::SmolDoclingProvider::from_env(http_client.clone())
+        {
+            ocr_providers.insert(OcrProviderKind::SmolDocling, Arc::new(provider));
+            info!("OCR provider registered: smol_docling");
+        } else {
+            info!("OCR provider skipped: smol_docling (SMOL_DOCLING_URL not set)");
+        }
+
+        // Nothing tracks Docling's use when it is replaced, so never stop it then
+        let idle_stop = idle_stop.filter(|_| {
+            !self.ocr_providers.contains_key(&OcrProviderKind::Docling) && mock_dir.is_none()
+        });
+        ocr_providers.extend(self.ocr_providers);
+
+        if let Some(ref dir) = mock_dir {
+            let provider: Arc<dyn OcrProvider> = Arc::new(ocr::mock::MockProvider::new(dir));
+            for kind in [
+                OcrProviderKind::Docling,
+                OcrProviderKind::MistralOcr,
+                OcrProviderKind::SmolDocling,
+            ] {
+                ocr_providers.insert(kind, provider.clone());
+            }
+            info!("Mock OCR enabled for all providers");
+        }
+
+        // Shared state for multi-replica deployments (optional)
+        let shared = shared::SharedState::from_env().await?;
+        shared::log_config(shared.as_deref());
+        if shared.as_ref().is_some_and(|s| s.queues_jobs()) && object_store.is_none() {
+            anyhow::bail!(
+                "JOB_QUEUE=redis needs OBJECT_STORE_BACKEND, so every replica can read uploads"
+            );
+        }
+        if let Some(ref shared) = shared {
+            shared.spawn();
+        }
+
+        // Load persisted datasets from disk
+        let datasets = mirrored(live::LiveMap::new(dataset_summary), &shared, "dataset");
+        for (id, dataset) in load_datasets_from_disk() {
+            datasets.insert(id, dataset);
+        }
+        info!("Loaded {} dataset(s) from data/datasets/", datasets.len());
+
+        // Content store: size-bounded memory LRU over files in data/content/
+        let mut content_store = self.content_store.unwrap_or_else(ContentStore::from_env);
+        if let Some(shared) = shared.clone() {
+            content_store =
+                content_store.with_mirror(move |id, content| shared.put_content(id, content));
+        }
+        {
+            let stats = content_store.stats();
+            info!(
+                "ContentStore initialized (disk tier: {}, max memory: {:?} bytes)",
+                stats.disk_enabled, stats.max_memory_bytes
+            );
+        }
+
+        // Outbox for failed storage uploads, drained by a background sync task
+        let background = Arc::new(admin::BackgroundTasks::default());
+        let outbox = match storage {
+            Some(ref st) => {
+                let outbox = Arc::new(sync::SyncOutbox::from_env()?);
+                info!(
+                    "Sync outbox initialized ({} pending upload(s))",
+                    outbox.pending()
+                );
+                outbox.clone().spawn(st.clone());
+                background.register("sync", format!("outbox retries to {}", st.name()));
+                Some(outbox)
+            }
+            None => None,
+        };
+
+        // Pipeline events for downstream consumers (optional)
+        let events = events::EventBus::from_env(http_client.clone())?;
+        events::log_config(events.as_ref());
+
+        // Build application state
+        let state = AppState {
+            extractions: mirrored(
+                live::LiveMap::new(extraction_summary),
+                &shared,
+                "extraction",
+            ),
+            datasets,
+            content_store,
+            openrouter: Arc::new(openrouter),
+            configs: Arc::new(configs),
+            config_history: Arc::new(config_history),
+            http_client,
+            storage,
+            object_store,
+            outbox,
+            events,
+            jobs: Arc::new(jobs::JobJournal::from_env()?.with_shared(shared.clone())),
+            shared,
+            running: Arc::new(jobs::RunningJobs::from_env()),
+            recovery: Arc::new(RwLock::new(jobs::RecoveryReport::default())),
+            eval: Arc::new(eval::EvalStore::from_env()?),
+            failures: Arc::new(failures::FailureStore::from_env()?),
+            ocr_providers: Arc::new(ocr_providers),
+            scheduler: Arc::new(scheduler::Scheduler::from_env()?),
+            quotas: Arc::new(quotas::QuotaLedger::from_env()?),
+            background,
+        };
+
+        // Recover jobs interrupted by a crash or restart (still listed in the journal)
+        if !self.skip_recovery {
+            let report = recover_interrupted_jobs(&state).await;
+            if !report.jobs.is_empty() {
+                info!(
+                    "Recovered {} interrupted job(s); see GET /admin/recovery",
+                    report.jobs.len()
+                );
+            }
+            *state.recovery.write().unwrap() = report;
+        }
+
+        Ok(Server { state, idle_stop })
+    }
+}
+
+/// Copy the writes to `map` to the Redis snapshots of `kind`, if Redis is
+/// configured. Writes only mark the entry dirty; its snapshot is serialized
+/// later, outside the map's locks.
+fn mirrored<T, S>(
+    map: live::LiveMap<T, S>,
+    shared: &Option<Arc<shared::SharedState>>,
+    kind: &'static str,
+) -> Arc<live::LiveMap<T, S>>
+where
+    T: Clone + serde::Serialize + Send + Sync + 'static,
+    S: Clone + Send + Sync + 'static,
+{
+    let Some(shared) = shared.clone() else {
+        return Arc::new(map);
+    };
+    let observer = shared.clone();
+    let map =
+        Arc::new(map.observe(move |id, value| observer.mark_snapshot(kind, id, value.is_none())));
+    let source = Arc::downgrade(&map);
+    shared.mirror(kind, move |id| source.upgrade()?.get(id));
+    map
+}
+
+/// Fixture directory when `EXTRACTOR_MOCK` is set (`EXTRACTOR_MOCK_DIR`,
+/// default `tests/fixtures/mock`).
+fn mock_fixture_dir() -> Option<std::path::PathBuf> {
+    let enabled = std::env::var("EXTRACTOR_MOCK")
+        .is_ok_and(|v| matches!(v.as_str(), "1" | "true"));
+    enabled.then(|| {
+        std::env::var("EXTRACTOR_MOCK_DIR")
+            .unwrap_or_else(|_| "tests/fixtures/mock".to_string())
+            .into()
+    })
+}
+
+fn build_router(state: AppState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/health/ready", get(health_ready))
+        .route("/stats/content-store", get(content_store_stats))
+        .route("/sync/status", get(sync_status))
+        .route("/admin/recovery", get(recovery_report))
+        .route("/admin/state", get(admin_state))
+        .route("/admin/gc", post(admin_gc))
+        .route("/admin/retention/run", post(run_retention_now))
+        .route("/configs", get(list_configs).post(create_config))
+        .route("/configs/reload", post(reload_configs))
+        .route("/configs/validate", post(validate_config))
+        .route("/configs/:name", get(get_config).put(update_config).delete(delete_config))
+        .route("/configs/:name/versions", get(list_config_versions))
+        .route("/configs/:name/rollback", post(rollback_config))
+        .route("/configs/:name/test-entities", post(test_entity_patterns))
+        .route("/extract", post(extract_document))
+        .route("/estimate", post(estimate_document))
+        .route("/extractions", get(list_extractions))
+        .route("/graph", get(get_graph))
+        .route("/extractions/:id/snapshot", get(get_extraction_snapshot))
+        .route("/extractions/:id", get(get_extraction))
+        .route(
+            "/extractions/:id/node/:node_id",
+            get(get_node).patch(update_node),
+        )
+        .route("/extractions/:id/node/:node_id/move", post(move_node))
+        .route("/extractions/:id/node/:node_id/merge", post(merge_nodes))
+        .route("/extractions/:id/node/:node_id/split", post(split_node))
+        .route("/extractions/:id/node/:node_id/extract", post(extract_node))
+        .route("/extractions/:id/graph", get(export_extraction_graph))
+        .route("/extractions/:id/export", get(export_extraction))
+        .route("/extractions/:id/review-queue", get(get_review_queue))
+        .route("/extractions/:id/ocr-quality", get(get_ocr_quality))
+        .route("/extractions/:id/timeline", get(get_timeline))
+        .route("/extractions/:id/source", get(get_extraction_source))
+        .route("/extractions/:id/ocr", get(get_extraction_ocr))
+        .route("/extractions/:id/pages/:n/image", get(get_page_image))
+        .route("/extractions/:id/cancel", post(cancel_extraction))
+        .route("/extractions/:id/retry", post(retry_extraction))
+        .route("/extractions/:id/failure", get(get_extraction_failure))
+        .route("/content/batch", post(get_content_batch))
+        .route("/content/:ref_path", get(get_content))
+        .route("/extract-sheet", post(extract_sheet))
+        .route("/datasets", get(list_datasets))
+        .route("/datasets/:id", get(get_dataset))
+        .route("/datasets/:id/rows", get(get_dataset_rows))
+        .route(
+            "/datasets/:id/rows/:row_index",
+            axum::routing::patch(update_dataset_row).delete(delete_dataset_row),
+        )
+        .route("/datasets/:id/append", post(append_dataset))
+        .route("/datasets/:id/push", post(push_dataset))
+        .route("/datasets/:id/aggregate", get(aggregate_dataset))
+        .route("/datasets/:id/joined", get(get_joined_rows))
+        .route("/datasets/:id/ocr", get(get_dataset_ocr))
+        .route("/eval/goldens", get(list_goldens).post(create_golden))
+        .route("/eval/goldens/:name", axum::routing::delete(delete_golden))
+        .route("/eval/run", post(run_eval))
+        .route("/eval/reports", get(list_eval_reports))
+        .route("/eval/reports/:id", get(get_eval_report))
+        .route("/experiments", post(run_experiment))
+        .layer(DefaultBodyLimit::max(100 * 1024 * 1024)) // 100MB
+        .layer(axum::middleware::map_response(api_error::json_errors))
+        .layer(TraceLayer::new_for_http())
+        .layer(CompressionLayer::new())
+        .layer(CorsLayer::permissive())
+        .with_state(state)
+}
+
+// ============================================================================
+// Handlers
+// ============================================================================
+
+/// Health check endpoint.
+async fn health() -> &'static str {
+    "ok"
+}
+
+/// Readiness for load balancers: probes every OCR provider, the storage
+/// backend, and OpenRouter concurrently; 503 unless all are healthy.
+async fn health_ready(State(state): State<AppState>) -> (StatusCode, Json<admin::Readiness>) {
+    let openrouter = state.openrouter.clone();
+    let (ocr_providers, storage, llm) = tokio::join!(
+        probe_ocr_providers(&state),
+        probe_storage(&state),
+        admin::probe("openrouter", async move { openrouter.health().await }),
+    );
+    let readiness = admin::Readiness::new(ocr_providers, storage, llm);
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Probe every OCR provider at once, in provider-name order.
+async fn probe_ocr_providers(state: &AppState) -> Vec<admin::Health> {
+    let mut probes = tokio::task::JoinSet::new();
+    for (kind, provider) in state.ocr_providers.iter() {
+        let (name, provider) = (kind.as_str(), provider.clone());
+        probes.spawn(async move { admin::probe(name, provider.health()).await });
+    }
+    let mut results = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        match result {
+            Ok(health) => results.push(health),
+            Err(e) => error!("OCR health probe failed to run: {}", e),
+        }
+    }
+    results.sort_by(|a, b| a.name.cmp(&b.name));
+    results
+}
+
+/// Probe the storage backend, if any.
+async fn probe_storage(state: &AppState) -> Option<admin::Health> {
+    match state.storage {
+        Some(ref st) => Some(admin::probe(st.name(), st.ping()).await),
+        None => None,
+    }
+}
+
+/// Content store cache counters (memory usage, hits/misses, evictions).
+async fn content_store_stats(
+    State(state): State<AppState>,
+) -> Json<content_store::ContentStoreStats> {
+    Json(state.content_store.stats())
+}
+
+/// Background sync backlog (uploads waiting in the outbox).
+async fn sync_status(State(state): State<AppState>) -> Result<Json<sync::SyncStatus>, ApiError> {
+    let outbox = state
+        .outbox
+        .as_ref()
+        .ok_or(ApiError::Unavailable("Storage not configured".to_string()))?;
+    Ok(Json(outbox.status()))
+}
+
+/// Jobs found interrupted at startup and what was done with them.
+async fn recovery_report(State(state): State<AppState>) -> Json<jobs::RecoveryReport> {
+    Json(state.recovery.read().unwrap().clone())
+}
+
+/// In-memory counts, running jobs, background loops, dependency health, and
+/// config versions.
+async fn admin_state(State(state): State<AppState>) -> Json<admin::AdminState> {
+    let summaries = state.extractions.summaries();
+    let extractions = admin::StatusCounts::tally(summaries.iter().map(|ext| &ext.status));
+
+    let (ocr_providers, storage) = tokio::join!(probe_ocr_providers(&state), probe_storage(&state));
+
+    let mut configs: Vec<admin::ConfigVersion> = state
+        .configs
+        .all()
+        .iter()
+        .map(|config| admin::ConfigVersion {
+            name: config.name.clone(),
+            version: scheduler::fingerprint(config),
+        })
+        .collect();
+    configs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Json(admin::AdminState {
+        generated_at: schema::now_iso8601(),
+        extractions,
+        datasets: state.datasets.len(),
+        content_store: state.content_store.stats(),
+        jobs: admin::JobsState {
+            running: state.running.running(),
+            free_slots: state.running.free_slots(),
+            waiting: state.running.waiting(),
+        },
+        background_tasks: state.background.list(),
+        sync_pending: state.outbox.as_ref().map(|outbox| outbox.pending()),
+        ocr_providers,
+        storage,
+        configs,
+        quota_usage: state.quotas.today(),
+    })
+}
+
+/// Drop completed extractions that storage already holds from memory; later
+/// reads hydrate them from storage again. Extractions still waiting in the
+/// sync outbox are kept.
+async fn admin_gc(State(state): State<AppState>) -> Result<Json<admin::GcReport>, ApiError> {
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or(ApiError::Unavailable("Storage not configured".to_string()))?;
+    let persisted: HashSet<String> = storage
+        .list_extractions(&storage::ExtractionFilter::default())
+        .await
+        .map_err(|e| ApiError::Upstream(format!("Failed to list stored extractions: {}", e)))?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+    let pending: HashSet<String> = state
+        .outbox
+        .as_ref()
+        .map(|outbox| {
+            outbox
+                .status()
+                .entries
+                .into_iter()
+                .filter(|entry| entry.kind == sync::OutboxKind::Extraction)
+                .map(|entry| entry.id)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut report = admin::GcReport::default();
+    state.extractions.retain(|id, ext| {
+        if ext.status != ExtractionStatus::Completed {
+            return true;
+        }
+        if persisted.contains(id) && !pending.contains(id) {
+            report.dropped.push(id.to_string());
+            false
+        } else {
+            report.kept_unpersisted.push(id.to_string());
+            true
+        }
+    });
+    report.remaining = state.extractions.len();
+    report.dropped.sort();
+    report.kept_unpersisted.sort();
+    info!(
+        "GC: dropped {} persisted extraction(s) from memory, kept {} unpersisted",
+        report.dropped.len(),
+        report.kept_unpersisted.len()
+    );
+    Ok(Json(report))
+}
+
+/// List available configs.
+async fn list_configs(State(state): State<AppState>) -> Json<Vec<String>> {
+    Json(state.configs.list())
+}
+
+/// Get a specific config.
+async fn get_config(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<config::ExtractionConfig>, ApiError> {
+    state
+        .configs
+        .get(&name)
+        .map(Json)
+        .ok_or_else(|| ApiError::ConfigNotFound {
+            available: state.configs.list(),
+            name,
+        })
+}
+
+/// Check a config without saving it. Always 200; see `valid` in the report.
+async fn validate_config(Json(body): Json<serde_json::Value>) -> Json<config_lint::LintReport> {
+    let report = match serde_json::from_value::<config::ExtractionConfig>(body) {
+        Ok(config) => config_lint::lint(&config),
+        Err(e) => config_lint::LintReport::unparsable(format!("Not a valid config: {}", e)),
+    };
+    Json(report)
+}
+
+/// Create a new config.
+async fn create_config(
+    State(state): State<AppState>,
+    Json(config): Json<config::ExtractionConfig>,
+) -> Result<(StatusCode, Json<config::ExtractionConfig>), ApiError> {
+    if config.name.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Config name cannot be empty".to_string(),
+        ));
+    }
+    if config.prompts.structure.is_empty() {
+        return Err(ApiError::BadRequest(
+            "prompts.structure cannot be empty".to_string(),
+        ));
+    }
+    pipeline::validate(pipeline::stages(&config))
+        .map_err(|e| ApiError::BadRequest(format!("Invalid pipeline: {}", e)))?;
+    config
+        .validate_node_types()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid node_types: {}", e)))?;
+    prompt::check(&config.prompts.structure)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid prompts.structure: {}", e)))?;
+    if let Some(ref schedule) = config.reextract_schedule {
+        scheduler::Cron::parse(schedule)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid reextract_schedule: {}", e)))?;
+    }
+
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| ApiError::Unavailable("Storage not configured".to_string()))?;
+
+    storage
+        .upsert_config(&config)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to save config: {}", e)))?;
+
+    state.configs.insert(config.clone());
+    record_config_version(&state, &config);
+    info!("Created config: {}", config.name);
+
+    Ok((StatusCode::CREATED, Json(config)))
+}
+
+/// Update an existing config.
+async fn update_config(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(config): Json<config::ExtractionConfig>,
+) -> Result<Json<config::ExtractionConfig>, ApiError> {
+    if config.name != name {
+        return Err(ApiError::BadRequest(format!(
+            "URL name '{}' does not match config name '{}'",
+            name, config.name
+        )));
+    }
+    pipeline::validate(pipeline::stages(&config))
+        .map_err(|e| ApiError::BadRequest(format!("Invalid pipeline: {}", e)))?;
+    config
+        .validate_node_types()
+        .map_err(|e| ApiError::BadRequest(format!("Invalid node_types: {}", e)))?;
+    prompt::check(&config.prompts.structure)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid prompts.structure: {}", e)))?;
+    if let Some(ref schedule) = config.reextract_schedule {
+        scheduler::Cron::parse(schedule)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid reextract_schedule: {}", e)))?;
+    }
+
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| ApiError::Unavailable("Storage not configured".to_string()))?;
+
+    storage
+        .upsert_config(&config)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to update config: {}", e)))?;
+
+    state.configs.insert(config.clone());
+    record_config_version(&state, &config);
+    info!("Updated config: {}", config.name);
+
+    Ok(Json(config))
+}
+
+/// Configs read when there is no storage backend, or it holds none.
+const CONFIG_DIR: &str = "configs";
+
+#[derive(serde::Serialize)]
+struct ConfigReload {
+    /// Storage backend name, or `disk`
+    source: String,
+    #[serde(flatten)]
+    changes: config::ConfigChanges,
+    /// All configs loaded after the reload
+    configs: Vec<String>,
+}
+
+/// Re-read configs from where startup reads them: the storage backend when
+/// it holds any, else `configs/`. On any error the loaded configs are kept.
+async fn reload_config_store(state: &AppState) -> anyhow::Result<ConfigReload> {
+    let stored = match state.storage {
+        Some(ref st) => st
+            .list_configs()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list configs from {}: {}", st.name(), e))?,
+        None => Vec::new(),
+    };
+    let (source, configs) = match state.storage {
+        Some(ref st) if !stored.is_empty() => (
+            st.name().to_string(),
+            stored.into_iter().map(|c| (c.name.clone(), c)).collect(),
+        ),
+        _ => (
+            "disk".to_string(),
+            ConfigStore::read_dir(std::path::Path::new(CONFIG_DIR))?,
+        ),
+    };
+
+    let changes = state.configs.replace(configs)?;
+    for name in changes.added.iter().chain(&changes.updated) {
+        if let Some(config) = state.configs.get(name) {
+            record_config_version(state, &config);
+        }
+    }
+    let mut configs = state.configs.list();
+    configs.sort();
+    Ok(ConfigReload {
+        source,
+        changes,
+        configs,
+    })
+}
+
+/// Reload configs from storage or disk without restarting the server.
+async fn reload_configs(State(state): State<AppState>) -> Result<Json<ConfigReload>, ApiError> {
+    let reload = reload_config_store(&state)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to reload configs: {:#}", e)))?;
+    info!(
+        "Reloaded configs from {}: added {:?}, updated {:?}, removed {:?}",
+        reload.source, reload.changes.added, reload.changes.updated, reload.changes.removed
+    );
+    Ok(Json(reload))
+}
+
+/// `CONFIG_RELOAD_SECS`: how often to reload configs in the background.
+/// Unset or 0 disables it.
+fn config_reload_interval_from_env() -> Option<std::time::Duration> {
+    std::env::var("CONFIG_RELOAD_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(std::time::Duration::from_secs)
+}
+
+/// Reload configs on an interval, so configs edited on disk or saved to
+/// storage by another server instance are picked up.
+fn spawn_config_reload(state: AppState, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick fires at once; configs were just loaded at startup
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match reload_config_store(&state).await {
+                Ok(reload) if reload.changes.is_empty() => {
+                    debug!("Config reload: no changes in {}", reload.source)
+                }
+                Ok(reload) => info!(
+                    "Config reload from {}: added {:?}, updated {:?}, removed {:?}",
+                    reload.source,
+                    reload.changes.added,
+                    reload.changes.updated,
+                    reload.changes.removed
+                ),
+                Err(e) => error!("Config reload failed, keeping loaded configs: {:#}", e),
+            }
+        }
+    });
+}
+
+/// Add a saved config to its history. The config is already saved, so a
+/// failure here is only logged.
+fn record_config_version(state: &AppState, config: &config::ExtractionConfig) {
+    if let Err(e) = state.config_history.record(config) {
+        error!(
+            "Failed to record version of config '{}': {}",
+            config.name, e
+        );
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ConfigVersionsResponse {
+    name: String,
+    /// Version of the config as currently loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    /// Newest first
+    versions: Vec<config_history::ConfigVersion>,
+}
+
+/// List the saved versions of a config.
+async fn list_config_versions(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<ConfigVersionsResponse>, ApiError> {
+    let current = state.configs.get(&name).map(|c| scheduler::fingerprint(&c));
+    let mut versions = state.config_history.versions(&name);
+    versions.retain(|v| v.config.name == name);
+    if current.is_none() && versions.is_empty() {
+        return Err(ApiError::ConfigNotFound {
+            available: state.configs.list(),
+            name,
+        });
+    }
+    versions.reverse();
+    Ok(Json(ConfigVersionsResponse {
+        name,
+        current,
+        versions,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct RollbackQuery {
+    version: String,
+}
+
+/// Restore an earlier version of a config. It is saved like a `PUT` and
+/// becomes the latest version again; extractions keep the version they ran with.
+async fn rollback_config(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<RollbackQuery>,
+) -> Result<Json<config::ExtractionConfig>, ApiError> {
+    let config = state
+        .config_history
+        .find(&name, &query.version)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Config '{}' has no version '{}'",
+                name, query.version
+            ))
+        })?
+        .config;
+
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| ApiError::Unavailable("Storage not configured".to_string()))?;
+
+    storage
+        .upsert_config(&config)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to roll back config: {}", e)))?;
+
+    state.configs.insert(config.clone());
+    record_config_version(&state, &config);
+    info!("Rolled back config {} to version {}", name, query.version);
+
+    Ok(Json(config))
+}
+
+#[derive(serde::Deserialize)]
+struct TestEntitiesRequest {
+    /// Sample text, typically OCR output
+    text: String,
+    /// Patterns to try instead of the config's, to test edits before saving
+    entity_patterns: Option<Vec<config::EntityPattern>>,
+}
+
+/// Run a config's entity patterns against sample text and show what each
+/// one matches, normalized as the `entities` stage would store it.
+async fn test_entity_patterns(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(req): Json<TestEntitiesRequest>,
+) -> Result<Json<Vec<entities::PatternTest>>, ApiError> {
+    let config = state
+        .configs
+        .get(&name)
+        .ok_or_else(|| ApiError::ConfigNotFound {
+            available: state.configs.list(),
+            name,
+        })?;
+    let patterns = req.entity_patterns.unwrap_or(config.entity_patterns);
+    Ok(Json(entities::test_patterns(&patterns, &req.text)))
+}
+
+/// Delete a config.
+async fn delete_config(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let storage = state
+        .storage
+        .as_ref()
+        .ok_or_else(|| ApiError::Unavailable("Storage not configured".to_string()))?;
+
+    storage
+        .delete_config(&name)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to delete config: {}", e)))?;
+
+    state.configs.remove(&name);
+    info!("Deleted config: {}", name);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(serde::Deserialize)]
+struct ExtractQuery {
+    config: Option<String>,
+    upload: Option<bool>,
+    file_url: Option<String>,
+    callback_url: Option<String>,
+    ocr_provider: Option<String>,
+    ocr_options: Option<String>,
+    priority: Option<jobs::Priority>,
+}
+
+/// Upload a document and start async extraction using OCR + LLM.
+/// Returns immediately with extraction ID and status "queued".
+/// Poll GET /extractions/:id to check when status becomes "completed" or "failed".
+///
+/// Query params:
+///   - `config` — extraction config name (default: the default document config)
+///   - `upload` — upload result to storage (default: false)
+///   - `file_url` — download file from this URL instead of multipart upload
+///   - `callback_url` — POST completed extraction to this URL
+///   - `ocr_provider` — `docling` (default) or `mistral_ocr`
+///   - `ocr_options` — JSON object of OCR options, applied over the config's
+///   - `priority` — `high`, `normal` (default) or `low`: the lane the job
+///     waits in for a run slot
+///
+/// A multipart `prompt_override` text field replaces the config's structure
+/// prompt for this run only; the extraction records it as `prompt_override`.
+async fn extract_document(
+    State(state): State<AppState>,
+    Query(query): Query<ExtractQuery>,
+    multipart: Option<Multipart>,
+) -> Result<Json<Extraction>, ApiError> {
+    // Get the config
+    let config = requested_config(&state, query.config.as_deref(), ConfigKind::Document)?;
+    let config = Arc::new(with_ocr_options(config, query.ocr_options.as_deref())?);
+    let admission = admit_job(&state, &config).await?;
+
+    // Resolve OCR provider
+    let provider_name = query.ocr_provider.as_deref().unwrap_or("docling");
+    let provider_kind = OcrProviderKind::from_str(provider_name).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "Unknown ocr_provider: '{}'. Available: docling, mistral_ocr, smol_docling",
+            provider_name
+        ))
+    })?;
+    let provider = state
+        .ocr_providers
+        .get(&provider_kind)
+        .ok_or_else(|| ApiError::ProviderUnconfigured(provider_name.to_string()))?;
+    let provider = Arc::clone(provider);
+
+    // Read file input from multipart or URL
+    let FileInput {
+        filename: filename_for_log,
+        file,
+        size,
+        mut fields,
+    } = read_file_input(
+        multipart,
+        query.file_url.as_deref(),
+        upload::Accept::Document,
+    )
+    .await?;
+    let prompt_override = fields.remove("prompt_override");
+    if let Some(ref prompt) = prompt_override {
+        check_prompt_override(prompt)?;
+    }
+
+    // Build OCR input
+    let ocr_input = match (&query.file_url, file) {
+        (Some(file_url), _) => {
+            info!(
+                "Received file_url: {} (ocr_provider={})",
+                file_url, provider_name
+            );
+            OcrInput::Url {
+                filename: filename_for_log.clone(),
+                url: file_url.clone(),
+            }
+        }
+        (None, file) => {
+            info!(
+                "Received file: {} ({} bytes, ocr_provider={})",
+                filename_for_log, size, provider_name
+            );
+            let file = file.expect("read_file_input returns a file without file_url");
+            check_page_quota(&config, &file).await?;
+            OcrInput::File {
+                filename: filename_for_log.clone(),
+                file,
+            }
+        }
+    };
+
+    let extraction = queue_extraction(
+        &state,
+        admission,
+        config,
+        provider_name,
+        provider,
+        ocr_input,
+        prompt_override,
+        query.upload.unwrap_or(true),
+        query.callback_url.clone(),
+        query.priority.unwrap_or_default(),
+    );
+
+    // Return immediately with the placeholder
+    Ok(Json(extraction))
+}
+
+/// A new job's pass through its config's quotas, holding its concurrency
+/// slot (see `admit_job`). Jobs admitted before a restart, or taken from
+/// the shared queue, run under `Admission::resumed`.
+struct Admission(Option<quotas::Reservation>);
+
+impl Admission {
+    fn resumed() -> Self {
+        Self(None)
+    }
+}
+
+/// Admit a new job of `config`, refusing it when the config has spent its
+/// daily LLM budget or has `max_concurrent` jobs in progress: those running
+/// here, or with the shared queue, those queued or running on any replica.
+async fn admit_job(
+    state: &AppState,
+    config: &config::ExtractionConfig,
+) -> Result<Admission, ApiError> {
+    let Some(ref quotas) = config.quotas else {
+        return Ok(Admission(None));
+    };
+    let elsewhere = match state.shared.as_ref().filter(|s| s.queues_jobs()) {
+        Some(shared) if quotas.max_concurrent.is_some() => shared.active_jobs(&config.name).await,
+        _ => 0,
+    };
+    state
+        .quotas
+        .admit(&config.name, quotas, elsewhere)
+        .map(|slot| Admission(Some(slot)))
+        .map_err(ApiError::QuotaExceeded)
+}
+
+/// The slot a job holds while it runs here. With the shared queue Redis
+/// lists the jobs in progress, so the admission's slot is only held until
+/// the job is listed there.
+fn job_slot(state: &AppState, config: &str, admission: Admission) -> Option<quotas::Reservation> {
+    if state.shared.as_ref().is_some_and(|s| s.queues_jobs()) {
+        return None;
+    }
+    Some(admission.0.unwrap_or_else(|| state.quotas.hold(config)))
+}
+
+/// Refuse an LLM run outside the job system (evals, experiments) of a config
+/// that has spent its daily budget. The run is charged with `record_usage`.
+fn check_budget(state: &AppState, config: &config::ExtractionConfig) -> Result<(), ApiError> {
+    let Some(ref quotas) = config.quotas else {
+        return Ok(());
+    };
+    state
+        .quotas
+        .check_budget(&config.name, quotas)
+        .map_err(ApiError::QuotaExceeded)
+}
+
+/// Reject an upload longer than its config's `max_pages`. Documents whose
+/// pages cannot be counted here are left to the check after OCR.
+async fn check_page_quota(
+    config: &config::ExtractionConfig,
+    file: &ocr::SpooledFile,
+) -> Result<(), ApiError> {
+    let Some(max) = config.quotas.as_ref().and_then(|q| q.max_pages) else {
+        return Ok(());
+    };
+    let path = file.path().to_path_buf();
+    let pages = tokio::task::spawn_blocking(move || estimate::count_file_pages(&path))
+        .await
+        .map_err(|e| ApiError::Internal(format!("Page count task failed: {}", e)))?;
+    match pages {
+        Ok(pages) if pages > max => Err(ApiError::TooManyPages {
+            config: config.name.clone(),
+            pages,
+            max,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Charge a finished job's LLM usage to its config's daily budget. The cost
+/// is OpenRouter's when it reported one, else the price table's (which
+/// knows nothing of cache discounts).
+fn record_usage(state: &AppState, config: &str, meter: &openrouter::UsageMeter) {
+    let cost = meter.reported_cost().or_else(|| {
+        estimate::Prices::from_env().ok().and_then(|prices| {
+            prices.cost(
+                state.openrouter.model(),
+                meter.prompt_tokens(),
+                meter.completion_tokens(),
+            )
+        })
+    });
+    let usage = quotas::DailyUsage {
+        tokens: meter.total_tokens(),
+        cost_usd: cost.unwrap_or(0.0),
+        prompt_tokens: meter.prompt_tokens(),
+        cached_tokens: meter.cached_tokens(),
+        ..Default::default()
+    };
+    state.quotas.record(config, &usage);
+}
+
+/// Apply the `ocr_options` query parameter (a JSON object) over the config's options.
+fn with_ocr_options(
+    mut config: config::ExtractionConfig,
+    raw: Option<&str>,
+) -> Result<config::ExtractionConfig, ApiError> {
+    if let Some(raw) = raw {
+        let overrides: ocr::OcrOptions = serde_json::from_str(raw)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid ocr_options: {}", e)))?;
+        config.ocr_options = Some(config.ocr_options.unwrap_or_default().merge(&overrides));
+    }
+    Ok(config)
+}
+
+/// Reject a `prompt_override` that is empty or not a valid prompt template.
+fn check_prompt_override(prompt: &str) -> Result<(), ApiError> {
+    if prompt.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "prompt_override cannot be empty".to_string(),
+        ));
+    }
+    prompt::check(prompt)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid prompt_override: {:#}", e)))
+}
+
+/// Create a `queued` placeholder for a document, journal the job, and
+/// start its pipeline in the background.
+#[allow(clippy::too_many_arguments)]
+fn queue_extraction(
+    state: &AppState,
+    admission: Admission,
+    config: Arc<config::ExtractionConfig>,
+    provider_name: &str,
+    provider: Arc<dyn OcrProvider>,
+    ocr_input: OcrInput,
+    prompt_override: Option<String>,
+    upload: bool,
+    callback_url: Option<String>,
+    priority: jobs::Priority,
+) -> Extraction {
+    let filename = ocr_input.filename().to_string();
+    let file_url = match &ocr_input {
+        OcrInput::Url { url, .. } => Some(url.clone()),
+        OcrInput::Bytes { .. } | OcrInput::File { .. } => None,
+    };
+
+    // Create a placeholder extraction with status "queued"
+    let mut extraction = Extraction::new(filename.clone(), Some(config.name.clone()));
+    extraction.prompt_override = prompt_override.clone();
+    mark_queued(&mut extraction);
+    let extraction_id = extraction.id.clone();
+
+    let record = jobs::JobRecord {
+        id: extraction_id.clone(),
+        kind: jobs::JobKind::Extraction,
+        source_file: filename.clone(),
+        config_name: config.name.clone(),
+        ocr_provider: Some(provider_name.to_string()),
+        ocr_options: config.ocr_options.clone(),
+        file_url,
+        upload,
+        callback_url: callback_url.clone(),
+        prompt_override: prompt_override.clone(),
+        priority,
+        parent_extraction_id: None,
+        parent_node_id: None,
+        started_at: extraction.extracted_at.clone(),
+    };
+
+    // With the shared queue, whichever replica claims the job runs it
+    if let Some(shared) = state.shared.clone().filter(|s| s.queues_jobs()) {
+        shared.put_snapshot("extraction", &extraction_id, Some(&extraction));
+        tokio::spawn(enqueue_extraction(
+            state.clone(),
+            shared,
+            record,
+            ocr_input,
+            admission,
+        ));
+        return extraction;
+    }
+
+    // Store the placeholder in memory
+    state
+        .extractions
+        .insert(extraction.id.clone(), extraction.clone());
+    state.jobs.start(&record);
+
+    info!("Queued extraction {} for async processing", extraction_id);
+
+    // Spawn background task to run the pipeline
+    spawn_extraction(
+        state.clone(),
+        ExtractionJob {
+            id: extraction_id,
+            filename,
+            config,
+            prompt_override,
+            upload,
+            callback_url,
+            priority,
+        },
+        PipelineInput::Source(provider, ocr_input),
+        admission,
+    );
+    extraction
+}
+
+/// Archive a job's input where every replica can read it, then put the job
+/// on the shared queue. URL inputs are downloaded again by the replica that
+/// claims them. The job's slot is held until it is on the queue.
+async fn enqueue_extraction(
+    state: AppState,
+    shared: Arc<shared::SharedState>,
+    record: jobs::JobRecord,
+    input: OcrInput,
+    _admission: Admission,
+) {
+    if record.file_url.is_none() {
+        if let Some(ref store) = state.object_store {
+            archive_source(&state, store.as_ref(), &record.id, &input).await;
+        }
+    }
+    match shared.enqueue(&record).await {
+        Ok(()) => info!("Queued extraction {} on the shared job queue", record.id),
+        Err(e) => {
+            error!("Failed to queue extraction {}: {:#}", record.id, e);
+            mark_job_failed(&state, &record, format!("Could not be queued: {:#}", e));
+        }
+    }
+}
+
+/// Parameters of a background extraction run.
+struct ExtractionJob {
+    id: String,
+    filename: String,
+    config: Arc<config::ExtractionConfig>,
+    /// Replaces `config.prompts.structure` for this run only
+    prompt_override: Option<String>,
+    upload: bool,
+    callback_url: Option<String>,
+    /// Lane to wait in for a run slot
+    priority: jobs::Priority,
+}
+
+/// Where a background extraction starts.
+enum PipelineInput {
+    /// Run OCR on the input first.
+    Source(Arc<dyn OcrProvider>, OcrInput),
+    /// OCR is already done (recovered from the object store archive).
+    Ocr(ocr::OcrResult),
+}
+
+/// Run the extraction pipeline in the background and clear its job record when done.
+///
+/// The run waits for a free slot (`MAX_CONCURRENT_JOBS`) and is dropped as
+/// soon as `POST /extractions/:id/cancel` fires its token, which aborts any
+/// in-flight OCR or LLM request and releases the slot.
+fn spawn_extraction(
+    state: AppState,
+    job: ExtractionJob,
+    input: PipelineInput,
+    admission: Admission,
+) {
+    let token = state.running.register(&job.id);
+    let quota_slot = job_slot(&state, &job.config.name, admission);
+    tokio::spawn(async move {
+        let id = job.id.clone();
+        let config = job.config.name.clone();
+        let meter = Arc::new(openrouter::UsageMeter::default());
+        tokio::select! {
+            _ = token.cancelled() => info!("Extraction {} cancelled", id),
+            _ = openrouter::metered(meter.clone(), async {
+                let _slot = state.running.acquire_lane(job.priority).await;
+                run_extraction(&state, job, input).await;
+            }) => {}
+        }
+        record_usage(&state, &config, &meter);
+        state.running.finish(&id);
+        state.jobs.finish(&id);
+        drop(quota_slot);
+    });
+}
+
+/// Put a fresh placeholder into the `queued` state.
+fn mark_queued(ext: &mut Extraction) {
+    ext.status = ExtractionStatus::Queued;
+    ext.stage = Some("Waiting for a free slot".to_string());
+    ext.progress_pct = Some(0);
+    ext.timing = Some(schema::ExtractionTiming {
+        queued_at: Some(schema::now_iso8601()),
+        ..Default::default()
+    });
+}
+
+/// Apply `f` to an in-memory extraction, if it is still there.
+fn update_extraction(state: &AppState, id: &str, f: impl FnOnce(&mut Extraction)) {
+    state.extractions.update(id, f);
+}
+
+/// Note the page count and OCR provenance on the in-progress extraction, so
+/// they are known even if a later stage fails.
+fn record_ocr(state: &AppState, id: &str, ocr: &ocr::OcrResult) {
+    update_extraction(state, id, |ext| {
+        ext.total_pages = Some(ocr.total_pages);
+        ext.ocr_provider = Some(ocr.provider_name.clone());
+        ext.ocr_confidence = Some(ocr.ocr_confidence);
+    });
+}
+
+/// Move an extraction to the next pipeline stage.
+fn set_stage(state: &AppState, id: &str, status: ExtractionStatus, stage: String, progress_pct: u8) {
+    update_extraction(state, id, |ext| {
+        ext.status = status;
+        ext.stage = Some(stage);
+        ext.progress_pct = Some(progress_pct);
+    });
+}
+
+/// Mark an in-memory extraction as failed, keeping the stage it failed in,
+/// and announce it on the event bus.
+fn fail_extraction(state: &AppState, id: &str, error: String) {
+    let failed = state.extractions.update(id, |ext| {
+        ext.status = ExtractionStatus::Failed;
+        ext.error = Some(error);
+        ext.timing.get_or_insert_with(Default::default).finished_at = Some(schema::now_iso8601());
+        ext.clone()
+    });
+    if let (Some(events), Some(failed)) = (&state.events, failed) {
+        events.publish(
+            events::EventKind::ExtractionFailed,
+            id,
+            &failed,
+            failed.error.as_deref(),
+        );
+    }
+}
+
+/// Milliseconds elapsed since `start`.
+fn elapsed_ms(start: std::time::Instant) -> Option<u64> {
+    Some(start.elapsed().as_millis() as u64)
+}
+
+/// State handed from one pipeline stage to the next.
+struct PipelineRun {
+    input: Option<PipelineInput>,
+    ocr: Option<ocr::OcrResult>,
+    extraction: Option<Extraction>,
+    timing: schema::ExtractionTiming,
+    /// Kept for the failure bundle
+    provider: Option<String>,
+    llm_response: Option<String>,
+}
+
+/// Run the config's pipeline stages in order, then store the result and
+/// send the callback.
+///
+/// Each stage is bounded by the config's `timeouts` (or the env defaults);
+/// the first stage to fail fails the extraction.
+async fn run_extraction(state: &AppState, job: ExtractionJob, input: PipelineInput) {
+    let bg_id = job.id.clone();
+    let stages = pipeline::stages(&job.config).to_vec();
+    if let Err(e) = pipeline::validate(&stages) {
+        fail_extraction(state, &bg_id, format!("Invalid pipeline: {}", e));
+        return;
+    }
+    if job.upload && !stages.contains(&pipeline::PipelineStage::Upload) {
+        warn!(
+            "upload=true ignored for {}: config '{}' has no upload stage",
+            bg_id, job.config.name
+        );
+    }
+
+    update_extraction(state, &bg_id, |ext| {
+        ext.timing.get_or_insert_with(Default::default).started_at = Some(schema::now_iso8601());
+    });
+    let mut run = PipelineRun {
+        input: Some(input),
+        ocr: None,
+        extraction: None,
+        timing: state
+            .extractions
+            .with(&bg_id, |ext| ext.timing.clone())
+            .flatten()
+            .unwrap_or_default(),
+        provider: None,
+        llm_response: None,
+    };
+
+    for (i, stage) in stages.iter().enumerate() {
+        if !still_owns_job(state, &bg_id).await {
+            return;
+        }
+        let progress_pct = (i * 100 / stages.len()) as u8;
+        if let Err(e) = run_stage(state, &job, &mut run, *stage, progress_pct).await {
+            error!("Stage {} failed for {}: {}", stage.as_str(), bg_id, e);
+            update_extraction(state, &bg_id, |ext| ext.timing = Some(run.timing.clone()));
+            record_failure(state, &job, &run, *stage, &e);
+            fail_extraction(state, &bg_id, e);
+            return;
+        }
+    }
+
+    // `structure` is a required stage, so there is always an extraction here
+    let Some(mut completed) = run.extraction else {
+        fail_extraction(state, &bg_id, "Pipeline produced no extraction".to_string());
+        return;
+    };
+
+    if !still_owns_job(state, &bg_id).await {
+        return;
+    }
+
+    // Store completed extraction in memory
+    completed.status = ExtractionStatus::Completed;
+    completed.stage = None;
+    completed.progress_pct = Some(100);
+    run.timing.finished_at = Some(schema::now_iso8601());
+    completed.timing = Some(run.timing);
+    state.extractions.insert(bg_id.clone(), completed.clone());
+    if let Err(e) = state.failures.remove(&bg_id) {
+        warn!("Failed to drop the old failure bundle for {}: {}", bg_id, e);
+    }
+
+    // POST result to callback URL if provided
+    if let Some(ref url) = job.callback_url {
+        info!("Sending callback for {} to {}", bg_id, url);
+        match state.http_client.post(url).json(&completed).send().await {
+            Ok(resp) => info!("Callback for {} returned {}", bg_id, resp.status()),
+            Err(e) => error!("Callback for {} failed: {}", bg_id, e),
+        }
+    }
+    if let Some(ref events) = state.events {
+        events.publish(
+            events::EventKind::ExtractionCompleted,
+            &bg_id,
+            &completed,
+            None,
+        );
+    }
+
+    info!("Extraction complete: {}", bg_id);
+}
+
+/// Whether this replica still owns the job it is running. Another replica
+/// takes a job over when this one's heartbeat lapses, for example during a
+/// long stall; the run then stops and leaves the job, its snapshot, and its
+/// upload to the new owner.
+async fn still_owns_job(state: &AppState, id: &str) -> bool {
+    let Some(ref shared) = state.shared else {
+        return true;
+    };
+    if shared.owns_job(id).await {
+        return true;
+    }
+    warn!(
+        "Job {} was taken over by another replica; stopping this run",
+        id
+    );
+    state.extractions.forget(id);
+    false
+}
+
+/// Ask the LLM for handwritten signatures and stamps on the last page of
+/// each top-level node the text pass found unsigned. Needs the archived
+/// source file; failures are logged and leave the node as it was.
+async fn read_signature_images(
+    state: &AppState,
+    id: &str,
+    extraction: &mut Extraction,
+    timeout: std::time::Duration,
+) {
+    if extraction.children.iter().all(signatures::is_signed) {
+        return;
+    }
+    let source = match state.object_store {
+        Some(ref store) => match store.get(&object_store::source_key(id)).await {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                debug!("No archived source for {}; signature images skipped", id);
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to read the source of {} from {}: {}",
+                    id,
+                    store.name(),
+                    e
+                );
+                return;
+            }
+        },
+        None => {
+            debug!("No object store; signature images skipped for {}", id);
+            return;
+        }
+    };
+    for node in extraction.children.iter_mut() {
+        let Some([_, last]) = node.page_range else {
+            continue;
+        };
+        if signatures::is_signed(node) {
+            continue;
+        }
+        let pdf_page = node.pdf_page_range.map_or(last, |range| range[1]);
+        let png = match page_image::render_page(&source, pdf_page, page_image::DEFAULT_DPI).await {
+            Ok(png) => png,
+            Err(e) => {
+                warn!("Failed to render page {} of {}: {:#}", pdf_page, id, e);
+                continue;
+            }
+        };
+        let read = signatures::read_page_image(&state.openrouter, png, last);
+        match tokio::time::timeout(timeout, read).await {
+            Ok(Ok(found)) => signatures::annotate(node, &found),
+            Ok(Err(e)) => warn!("Signature check of {}/{} failed: {:#}", id, node.id, e),
+            Err(_) => warn!("Signature check of {}/{} timed out", id, node.id),
+        }
+    }
+}
+
+/// Save what is known about a failed run for `GET /extractions/:id/failure`.
+fn record_failure(
+    state: &AppState,
+    job: &ExtractionJob,
+    run: &PipelineRun,
+    stage: pipeline::PipelineStage,
+    error: &str,
+) {
+    let bundle = failures::FailureBundle {
+        extraction_id: job.id.clone(),
+        failed_at: schema::now_iso8601(),
+        stage: stage.as_str().to_string(),
+        error: error.to_string(),
+        filename: job.filename.clone(),
+        config: job.config.name.clone(),
+        config_version: scheduler::fingerprint(&job.config),
+        provider: run
+            .provider
+            .clone()
+            .or_else(|| run.ocr.as_ref().map(|ocr| ocr.provider_name.clone())),
+        ocr: run.ocr.as_ref().map(failures::OcrStats::of),
+        llm_response: run.llm_response.clone(),
+        timing: run.timing.clone(),
+        job: state.jobs.get(&job.id),
+    };
+    if let Err(e) = state.failures.save(&bundle) {
+        error!("Failed to save the failure bundle for {}: {}", job.id, e);
+    }
+}
+
+/// Run one pipeline stage; `Err` carries the extraction's error message.
+async fn run_stage(
+    state: &AppState,
+    job: &ExtractionJob,
+    run: &mut PipelineRun,
+    stage: pipeline::PipelineStage,
+    progress_pct: u8,
+) -> Result<(), String> {
+    use pipeline::PipelineStage;
+
+    let bg_id = &job.id;
+    let timeouts = config::StageTimeouts::resolve(job.config.timeouts.as_ref());
+    let extractor = Extractor::new((*state.openrouter).clone(), state.content_store.clone());
+    let stage_start = std::time::Instant::now();
+    let processing = |label: &str| {
+        set_stage(state, bg_id, ExtractionStatus::Processing, label.to_string(), progress_pct)
+    };
+
+    match stage {
+        PipelineStage::Ocr => match run.input.take() {
+            Some(PipelineInput::Source(provider, ocr_input)) => {
+                run.provider = Some(provider.name().to_string());
+                set_stage(
+                    state,
+                    bg_id,
+                    ExtractionStatus::OcrRunning,
+                    format!("Running OCR ({})", provider.name()),
+                    progress_pct,
+                );
+                let options = job.config.ocr_options.clone().unwrap_or_default();
+                let ocr = provider.process_with_options(&ocr_input, &options);
+                let ocr_result = match tokio::time::timeout(timeouts.ocr, ocr).await {
+                    Ok(Ok(result)) => result,
+                    Ok(Err(e)) => return Err(format!("OCR ({}) failed: {}", provider.name(), e)),
+                    Err(_) => {
+                        return Err(format!(
+                            "OCR ({}) timed out after {}s",
+                            provider.name(),
+                            timeouts.ocr.as_secs()
+                        ))
+                    }
+                };
+
+                info!(
+                    "{} extracted {} pages, {} chars markdown for {}",
+                    ocr_result.provider_name,
+                    ocr_result.total_pages,
+                    ocr_result.markdown.len(),
+                    bg_id
+                );
+                // URL inputs are only counted once OCR has fetched them
+                if let Some(max) = job.config.quotas.as_ref().and_then(|q| q.max_pages) {
+                    if ocr_result.total_pages > max {
+                        return Err(ApiError::TooManyPages {
+                            config: job.config.name.clone(),
+                            pages: ocr_result.total_pages,
+                            max,
+                        }
+                        .to_string());
+                    }
+                }
+                run.timing.ocr_ms = elapsed_ms(stage_start);
+                record_ocr(state, bg_id, &ocr_result);
+
+                // Archive the source file if an object store is configured, and keep the raw OCR output
+                if let Some(ref store) = state.object_store {
+                    archive_source(state, store.as_ref(), bg_id, &ocr_input).await;
+                }
+                keep_ocr(
+                    state,
+                    &object_store::extraction_root(bg_id),
+                    bg_id,
+                    &ocr_result,
+                )
+                .await;
+                run.ocr = Some(ocr_result);
+            }
+            // Resumed from archived OCR output
+            Some(PipelineInput::Ocr(ocr_result)) => {
+                record_ocr(state, bg_id, &ocr_result);
+                run.ocr = Some(ocr_result);
+            }
+            None => {}
+        },
+
+        PipelineStage::Structure => {
+            let ocr_result = run.ocr.as_ref().ok_or("No OCR output to extract from")?;
+            set_stage(
+                state,
+                bg_id,
+                ExtractionStatus::LlmRunning,
+                format!("Extracting structure (LLM, {} pages)", ocr_result.total_pages),
+                progress_pct,
+            );
+            let overridden = job.prompt_override.as_ref().map(|prompt| {
+                let mut config = (*job.config).clone();
+                config.prompts.structure = prompt.clone();
+                config
+            });
+            let mut extraction = match tokio::time::timeout(
+                timeouts.llm,
+                extractor.structure(
+                    &job.filename,
+                    ocr_result,
+                    overridden.as_ref().unwrap_or(&job.config),
+                ),
+            )
+            .await
+            {
+                Ok(Ok(ext)) => ext,
+                Ok(Err(e)) => {
+                    run.llm_response = e
+                        .chain()
+                        .find_map(|cause| cause.downcast_ref::<extractor::LlmParseError>())
+                        .map(|parse| parse.response.clone());
+                    return Err(format!("Extraction failed: {}", e));
+                }
+                Err(_) => {
+                    return Err(format!(
+                        "Extraction timed out after {}s",
+                        timeouts.llm.as_secs()
+                    ))
+                }
+            };
+            // Preserve the original ID (the extractor creates a new one)
+            extraction.id = bg_id.clone();
+            // Keep what earlier stages and retries recorded on the placeholder
+            if let Some((retry_count, source_url, parent)) = state.extractions.with(bg_id, |ext| {
+                (
+                    ext.retry_count,
+                    ext.source_url.clone(),
+                    (ext.parent_extraction_id.clone(), ext.parent_node_id.clone()),
+                )
+            }) {
+                extraction.retry_count = retry_count;
+                extraction.source_url = source_url;
+                (extraction.parent_extraction_id, extraction.parent_node_id) = parent;
+            }
+            if overridden.is_some() {
+                // Keep the shared config's version so the run can be compared with it
+                extraction.config_version = Some(scheduler::fingerprint(&job.config));
+                extraction.prompt_override = job.prompt_override.clone();
+            }
+            run.timing.llm_ms = elapsed_ms(stage_start);
+            run.extraction = Some(extraction);
+        }
+
+        PipelineStage::Toc => {
+            processing("Checking against the table of contents");
+            if let (Some(extraction), Some(ocr_result)) = (run.extraction.as_mut(), run.ocr.as_ref()) {
+                if let Some(report) = toc::apply(extraction, &ocr_result.pages) {
+                    info!(
+                        "Table of contents for {}: {} nodes checked, {} entries unmatched",
+                        bg_id,
+                        report.nodes.len(),
+                        report.unmatched.len()
+                    );
+                }
+            }
+        }
+
+        PipelineStage::SliceContent => {
+            processing("Slicing content");
+            if let (Some(extraction), Some(ocr_result)) = (run.extraction.as_mut(), run.ocr.as_ref()) {
+                extractor.slice_content(extraction, &ocr_result.pages);
+            }
+        }
+
+        PipelineStage::Folios => {
+            processing("Reading printed page numbers");
+            if let (Some(extraction), Some(ocr_result)) = (run.extraction.as_mut(), run.ocr.as_ref()) {
+                match folios::apply(extraction, &ocr_result.pages) {
+                    Some(report) => info!(
+                        "Folios for {}: {} numbered pages, {} nodes remapped",
+                        bg_id, report.anchors, report.nodes
+                    ),
+                    None => debug!("No printed page numbers found for {}", bg_id),
+                }
+            }
+        }
+
+        PipelineStage::Signatures => {
+            processing("Detecting signatures");
+            if let Some(extraction) = run.extraction.as_mut() {
+                let report = signatures::apply(extraction, &state.content_store);
+                info!(
+                    "Signatures for {}: {} found on {} nodes",
+                    bg_id, report.signatures, report.nodes
+                );
+                if job.config.signatures.as_ref().is_some_and(|s| s.vision) {
+                    read_signature_images(state, bg_id, extraction, timeouts.llm).await;
+                }
+            }
+        }
+
+        PipelineStage::Entities => {
+            processing("Extracting entities");
+            if let Some(extraction) = run.extraction.as_mut() {
+                let pages = run.ocr.as_ref().map(|ocr| ocr.pages.as_slice());
+                extractor.extract_entities(extraction, &job.config, pages);
+                let llm = extractor.extract_llm_entities(extraction, &job.config);
+                if tokio::time::timeout(timeouts.llm, llm).await.is_err() {
+                    warn!("LLM entity extraction of {} timed out", bg_id);
+                }
+            }
+        }
+
+        PipelineStage::ReadableId => {
+            processing("Resolving readable ID");
+            if let (Some(extraction), Some(ocr_result)) = (run.extraction.as_mut(), run.ocr.as_ref()) {
+                extractor.assign_readable_id(extraction, ocr_result, &job.config);
+            }
+        }
+
+        PipelineStage::Dedup => {
+            processing("Checking for duplicates");
+            // Link re-uploads of the same document to the first extraction
+            let duplicate_of = match run.extraction.as_ref() {
+                Some(extraction) => find_duplicate_of(state, extraction).await,
+                None => None,
+            };
+            if let Some(ref original) = duplicate_of {
+                info!("Extraction {} duplicates {}", bg_id, original);
+            }
+            if let Some(extraction) = run.extraction.as_mut() {
+                extraction.duplicate_of = duplicate_of;
+            }
+        }
+
+        PipelineStage::Translate => {
+            let Some(extraction) = run.extraction.as_mut() else {
+                return Ok(());
+            };
+            set_stage(
+                state,
+                bg_id,
+                ExtractionStatus::LlmRunning,
+                format!(
+                    "Translating summaries into {}",
+                    job.config.translate_to.as_deref().unwrap_or("en")
+                ),
+                progress_pct,
+            );
+            // A failed translation keeps the untranslated result
+            match tokio::time::timeout(timeouts.llm, extractor.translate(extraction, &job.config))
+                .await
+            {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Translation failed for {}: {}", bg_id, e),
+                Err(_) => warn!(
+                    "Translation timed out for {} after {}s",
+                    bg_id,
+                    timeouts.llm.as_secs()
+                ),
+            }
+        }
+
+        PipelineStage::Redact => {
+            processing("Redacting PII");
+            if let (Some(extraction), Some(ocr_result)) = (run.extraction.as_ref(), run.ocr.as_ref()) {
+                extractor.redact(extraction, ocr_result, &job.config).await;
+            }
+        }
+
+        PipelineStage::Upload => {
+            // Upload to storage if requested
+            let storage = match state.storage.as_ref().filter(|_| job.upload) {
+                Some(storage) => storage,
+                None => return Ok(()),
+            };
+            let Some(extraction) = run.extraction.as_mut() else {
+                return Ok(());
+            };
+
+            // Readers see the result while it uploads
+            extraction.status = ExtractionStatus::Completed;
+            let mut uploading = extraction.clone();
+            uploading.status = ExtractionStatus::Uploading;
+            uploading.stage = Some(format!("Uploading to {}", storage.name()));
+            uploading.progress_pct = Some(progress_pct);
+            uploading.timing = Some(run.timing.clone());
+            state.extractions.insert(bg_id.clone(), uploading);
+
+            let upload = tokio::time::timeout(
+                timeouts.upload,
+                storage.upload_extraction(extraction, &state.content_store),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow::anyhow!(
+                    "upload timed out after {}s",
+                    timeouts.upload.as_secs()
+                ))
+            });
+            match upload {
+                Ok(()) => info!("Uploaded extraction {} to storage", bg_id),
+                Err(e) => {
+                    error!("Storage upload failed for {}: {}", bg_id, e);
+                    if let Some(ref outbox) = state.outbox {
+                        if let Err(e) =
+                            outbox.enqueue_extraction(extraction, &state.content_store, &e.to_string())
+                        {
+                            error!("Failed to queue {} for background sync: {}", bg_id, e);
+                        }
+                    }
+                }
+            }
+            run.timing.upload_ms = elapsed_ms(stage_start);
+        }
+    }
+
+    Ok(())
+}
+
+/// Find an earlier extraction (in memory or in storage) of the same document.
+async fn find_duplicate_of(state: &AppState, extraction: &Extraction) -> Option<String> {
+    if extraction.content_hash.is_none() && extraction.fingerprint.is_none() {
+        return None;
+    }
+
+    let mut candidates: Vec<dedup::Candidate> = state.extractions.scan(|e| {
+        (e.id != extraction.id && e.status == ExtractionStatus::Completed).then(|| {
+            dedup::Candidate {
+                id: e.id.clone(),
+                content_hash: e.content_hash.clone(),
+                fingerprint: e.fingerprint.clone(),
+                duplicate_of: e.duplicate_of.clone(),
+            }
+        })
+    });
+    if let Some(ref storage) = state.storage {
+        match storage
+            .list_extractions(&storage::ExtractionFilter::default())
+            .await
+        {
+            Ok(rows) => {
+                let seen: HashSet<String> = candidates.iter().map(|c| c.id.clone()).collect();
+                candidates.extend(
+                    rows.into_iter()
+                        .filter(|row| row.id != extraction.id && !seen.contains(&row.id))
+                        .map(|row| dedup::Candidate {
+                            id: row.id,
+                            content_hash: row.content_hash,
+                            fingerprint: row.fingerprint,
+                            duplicate_of: row.duplicate_of,
+                        }),
+                );
+            }
+            Err(e) => error!("Duplicate check could not list stored extractions: {}", e),
+        }
+    }
+
+    dedup::find_duplicate(
+        extraction.content_hash.as_deref(),
+        extraction.fingerprint.as_deref(),
+        &candidates,
+        dedup::max_distance_from_env(),
+    )
+    .map(str::to_string)
+}
+
+/// Cancel a running extraction: aborts its background task and marks it `cancelled`.
+async fn cancel_extraction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Extraction>, ApiError> {
+    // Checked and marked in one step, so a job that finishes meanwhile is
+    // neither cancelled nor reported as such
+    let ext = state
+        .extractions
+        .update(&id, |ext| {
+            if !ext.status.is_active() || !state.running.cancel(&id) {
+                return Err(ext.status.clone());
+            }
+            ext.status = ExtractionStatus::Cancelled;
+            ext.error = Some("Cancelled by request".to_string());
+            ext.timing.get_or_insert_with(Default::default).finished_at =
+                Some(schema::now_iso8601());
+            Ok(ext.clone())
+        })
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?
+        .map_err(|status| {
+            ApiError::Conflict(format!(
+                "Extraction {} is not running (status: {:?})",
+                id, status
+            ))
+        })?;
+    info!("Cancelled extraction {}", id);
+    Ok(Json(ext))
+}
+
+/// Re-run a failed extraction from the furthest point still available (kept
+/// OCR output, archived source, or its URL), under the same ID.
+async fn retry_extraction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Extraction>, ApiError> {
+    let failed = state
+        .extractions
+        .get(&id)
+        .ok_or_else(|| ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    if failed.status != ExtractionStatus::Failed {
+        return Err(ApiError::Conflict(format!(
+            "Extraction {} has not failed (status: {:?})",
+            id, failed.status
+        )));
+    }
+
+    // The bundle keeps the job as it was started; older failures have none
+    let bundle = state.failures.get(&id).ok().flatten();
+    let mut record = match bundle.as_ref().and_then(|b| b.job.clone()) {
+        Some(record) => record,
+        None => jobs::JobRecord {
+            id: id.clone(),
+            kind: jobs::JobKind::Extraction,
+            source_file: failed.source_file.clone(),
+            config_name: failed.config_name.clone().unwrap_or_default(),
+            ocr_provider: bundle.as_ref().and_then(|b| b.provider.clone()),
+            ocr_options: None,
+            file_url: None,
+            upload: false,
+            callback_url: None,
+            prompt_override: failed.prompt_override.clone(),
+            priority: jobs::Priority::Normal,
+            parent_extraction_id: failed.parent_extraction_id.clone(),
+            parent_node_id: failed.parent_node_id.clone(),
+            started_at: String::new(),
+        },
+    };
+    if state.configs.get(&record.config_name).is_none() {
+        return Err(ApiError::ConfigNotFound {
+            name: record.config_name,
+            available: state.configs.list(),
+        });
+    }
+    let (action, config, input) = resume_point(&state, &record)
+        .await
+        .map_err(|e| ApiError::Conflict(format!("Extraction {} cannot be retried: {}", id, e)))?;
+    let admission = admit_job(&state, &config).await?;
+
+    // Claim the retry; a concurrent one already moved it out of `failed`
+    let queued = state
+        .extractions
+        .update(&id, |ext| {
+            (ext.status == ExtractionStatus::Failed).then(|| {
+                mark_queued(ext);
+                ext.error = None;
+                ext.retry_count += 1;
+                ext.clone()
+            })
+        })
+        .flatten()
+        .ok_or_else(|| ApiError::Conflict(format!("Extraction {} is already being retried", id)))?;
+
+    record.started_at = schema::now_iso8601();
+    state.jobs.start(&record);
+    info!(
+        "Retrying extraction {} (attempt {}, {:?})",
+        id,
+        queued.retry_count + 1,
+        action
+    );
+    spawn_extraction(
+        state.clone(),
+        extraction_job(&record, config),
+        input,
+        admission,
+    );
+    Ok(Json(queued))
+}
+
+/// Diagnostics saved when the extraction's pipeline failed (see `failures`).
+async fn get_extraction_failure(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<failures::FailureBundle>, ApiError> {
+    match state.failures.get(&id) {
+        Ok(Some(bundle)) => Ok(Json(bundle)),
+        Ok(None) => Err(ApiError::NotFound(format!(
+            "No failure recorded for extraction {}",
+            id
+        ))),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+/// Store the original file under the extraction ID.
+/// Failures are logged and never fail the extraction.
+async fn archive_source(
+    state: &AppState,
+    store: &dyn object_store::ObjectStore,
+    extraction_id: &str,
+    input: &OcrInput,
+) {
+    let (filename, data) = match input {
+        OcrInput::Bytes { filename, data } => (filename.as_str(), Some(data.clone())),
+        OcrInput::File { filename, file } => match file.read().await {
+            Ok(data) => (filename.as_str(), Some(data)),
+            Err(e) => {
+                error!("Failed to read upload for {}: {}", extraction_id, e);
+                (filename.as_str(), None)
+            }
+        },
+        OcrInput::Url { filename, url } => {
+            let downloaded = match state.http_client.get(url).send().await {
+                Ok(resp) if resp.status().is_success() => resp.bytes().await.ok(),
+                Ok(resp) => {
+                    error!("Source download for {} returned {}", extraction_id, resp.status());
+                    None
+                }
+                Err(e) => {
+                    error!("Source download for {} failed: {}", extraction_id, e);
+                    None
+                }
+            };
+            (filename.as_str(), downloaded.map(|b| b.to_vec()))
+        }
+    };
+
+    if let Some(data) = data {
+        let prerender = page_image::prerender_dpi_from_env().map(|dpi| (dpi, data.clone()));
+        match object_store::store_source(store, extraction_id, filename, data).await {
+            Ok(()) => {
+                info!(
+                    "Archived source file for {} to {}",
+                    extraction_id,
+                    store.name()
+                );
+                match store
+                    .signed_url(&object_store::source_key(extraction_id))
+                    .await
+                {
+                    Ok(Some(url)) => {
+                        update_extraction(state, extraction_id, |ext| ext.source_url = Some(url))
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to sign the source URL for {}: {}", extraction_id, e),
+                }
+            }
+            Err(e) => error!("Failed to archive source file for {}: {}", extraction_id, e),
+        }
+        if let Some((dpi, data)) = prerender {
+            tokio::spawn(prerender_page_images(
+                state.clone(),
+                extraction_id.to_string(),
+                data,
+                dpi,
+            ));
+        }
+    }
+}
+
+/// Render and cache every page of a freshly archived source at `dpi`.
+async fn prerender_page_images(state: AppState, extraction_id: String, source: Vec<u8>, dpi: u32) {
+    let Some(ref store) = state.object_store else {
+        return;
+    };
+    let pages = match estimate::count_pages(&source) {
+        Ok(pages) => pages,
+        Err(e) => {
+            warn!("Not pre-rendering pages of {}: {}", extraction_id, e);
+            return;
+        }
+    };
+    for page in 1..=pages {
+        let png = match page_image::render_page(&source, page, dpi).await {
+            Ok(png) => png,
+            Err(e) => {
+                warn!(
+                    "Pre-rendering page {} of {} failed: {:#}",
+                    page, extraction_id, e
+                );
+                return;
+            }
+        };
+        let key = object_store::page_image_key(&extraction_id, page, dpi);
+        if let Err(e) = store.put(&key, png, "image/png").await {
+            warn!("Failed to cache {}: {}", key, e);
+            return;
+        }
+    }
+    info!(
+        "Pre-rendered {} page image(s) of {} at {} dpi",
+        pages, extraction_id, dpi
+    );
+}
+
+/// Keep raw OCR output under `root`: in the object store when one is
+/// configured, otherwise in the content store. Failures are logged.
+async fn keep_ocr(state: &AppState, root: &str, id: &str, ocr_result: &ocr::OcrResult) {
+    let stored = object_store::StoredOcr::from_result(id, ocr_result);
+    match state.object_store {
+        Some(ref store) => match object_store::store_ocr(store.as_ref(), root, &stored).await {
+            Ok(()) => info!(
+                "Archived OCR output for {} to {} ({} pages)",
+                id,
+                store.name(),
+                stored.pages.len()
+            ),
+            Err(e) => error!("Failed to archive OCR output for {}: {}", id, e),
+        },
+        None => match serde_json::to_string(&stored) {
+            Ok(json) => {
+                state
+                    .content_store
+                    .store(&object_store::ocr_json_key(root), json);
+            }
+            Err(e) => error!("Failed to serialize OCR output for {}: {}", id, e),
+        },
+    }
+}
+
+/// OCR pages kept for an extraction, for re-anchoring `ocr_span`s after edits.
+async fn kept_ocr_pages(state: &AppState, id: &str) -> Option<Vec<ocr::OcrPage>> {
+    match load_ocr(state, &object_store::extraction_root(id)).await {
+        Ok(stored) => stored.map(|stored| stored.into_ocr_result().pages),
+        Err(e) => {
+            warn!("Kept OCR for {} is unusable: {}", id, e);
+            None
+        }
+    }
+}
+
+/// Raw OCR output kept under `root` by [`keep_ocr`], if any.
+async fn load_ocr(state: &AppState, root: &str) -> Result<Option<object_store::StoredOcr>, String> {
+    let key = object_store::ocr_json_key(root);
+    let data = match state.object_store {
+        Some(ref store) => store
+            .get(&key)
+            .await
+            .map_err(|e| format!("failed to read {} from {}: {}", key, store.name(), e))?,
+        None => state
+            .content_store
+            .get_full(&format!("content://{}", key))
+            .map(String::into_bytes),
+    };
+    data.map(|data| serde_json::from_slice(&data).map_err(|e| format!("{} is invalid: {}", key, e)))
+        .transpose()
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ExtractionSummary {
+    id: String,
+    status: ExtractionStatus,
+    source_file: String,
+    config_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config_version: Option<String>,
+    /// Run with a per-request structure prompt
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prompt_override: bool,
+    extracted_at: String,
+    total_pages: Option<u32>,
+    summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readable_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duplicate_of: Option<String>,
+    /// At least one node has been reviewed
+    reviewed: bool,
+    node_count: usize,
+}
+
+/// The summary kept next to each in-memory extraction.
+fn extraction_summary(e: &Extraction) -> ExtractionSummary {
+    ExtractionSummary {
+        id: e.id.clone(),
+        status: e.status.clone(),
+        source_file: e.source_file.clone(),
+        config_name: e.config_name.clone(),
+        config_version: e.config_version.clone(),
+        prompt_override: e.prompt_override.is_some(),
+        extracted_at: e.extracted_at.clone(),
+        total_pages: e.total_pages,
+        summary: e.summary.clone(),
+        readable_id: e.readable_id.clone(),
+        duplicate_of: e.duplicate_of.clone(),
+        reviewed: e.reviewed,
+        node_count: e.node_count(),
+    }
+}
+
+/// The config a request names, or else the default config of `kind`
+/// (`DEFAULT_DOC_CONFIG` / `DEFAULT_SHEET_CONFIG`, or `"default": true`).
+fn requested_config(
+    state: &AppState,
+    name: Option<&str>,
+    kind: ConfigKind,
+) -> Result<config::ExtractionConfig, ApiError> {
+    match name {
+        Some(name) => state
+            .configs
+            .get(name)
+            .ok_or_else(|| ApiError::ConfigNotFound {
+                name: name.to_string(),
+                available: state.configs.list(),
+            }),
+        None => state
+            .configs
+            .default_config(kind)
+            .ok_or(ApiError::NoDefaultConfig(kind)),
+    }
+}
+
+/// Try to get an extraction from memory, falling back to storage if configured.
+/// Caches hydrated extractions in memory for subsequent requests.
+async fn get_or_hydrate_extraction(state: &AppState, id: &str) -> Option<Extraction> {
+    // 1. Check in-memory cache (a job running here is never newer elsewhere)
+    let local = state.extractions.get(id);
+    if local
+        .as_ref()
+        .is_some_and(|e| state.shared.is_none() || e.status.is_active())
+    {
+        return local;
+    }
+
+    // 2. Check the snapshot written by the last replica to change it
+    if let Some(ref shared) = state.shared {
+        if let Some(extraction) = shared.snapshot::<Extraction>("extraction", id).await {
+            if !extraction.status.is_active() {
+                state
+                    .extractions
+                    .refresh(id.to_string(), extraction.clone());
+            }
+            return Some(extraction);
+        }
+    }
+    if local.is_some() {
+        return local;
+    }
+
+    // 3. Fall back to storage
+    if let Some(ref storage) = state.storage {
+        match storage.fetch_extraction(id).await {
+            Ok(Some(extraction)) => {
+                // Cache in memory for future requests
+                state
+                    .extractions
+                    .insert(extraction.id.clone(), extraction.clone());
+                info!("Hydrated extraction {} from storage into cache", id);
+                return Some(extraction);
+            }
+            Ok(None) => {
+                debug!("Extraction {} not found in storage", id);
+            }
+            Err(e) => {
+                error!("Failed to fetch extraction {} from storage: {}", id, e);
+            }
+        }
+    }
+
+    None
+}
+
+/// Load the node content of an extraction hydrated from storage, which
+/// arrives with content refs only. Does nothing when every ref resolves.
+async fn hydrate_content(state: &AppState, extraction: &Extraction) -> Result<(), ApiError> {
+    fn missing(nodes: &[schema::DocumentNode], store: &ContentStore) -> bool {
+        nodes.iter().any(|n| {
+            n.content_ref.as_deref().is_some_and(|r| !store.exists(r))
+                || missing(&n.children, store)
+        })
+    }
+
+    let Some(ref storage) = state.storage else {
+        return Ok(());
+    };
+    if !missing(&extraction.children, &state.content_store) {
+        return Ok(());
+    }
+    let count = storage
+        .fetch_extraction_content(&extraction.id, &state.content_store)
+        .await
+        .map_err(|e| {
+            error!(
+                "Failed to fetch content for {} from storage: {}",
+                extraction.id, e
+            );
+            ApiError::Upstream(format!(
+                "Failed to load content of {}: {}",
+                extraction.id, e
+            ))
+        })?;
+    info!(
+        "Hydrated content of {} nodes of {} from storage",
+        count, extraction.id
+    );
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ListExtractionsQuery {
+    /// Filter by readable_id (substring match, ignoring case and punctuation)
+    readable_id: Option<String>,
+    /// Only extractions with (`true`) or without (`false`) reviewed nodes
+    reviewed: Option<bool>,
+    /// Only extractions run with this config version
+    config_version: Option<String>,
+    /// Only extractions in this status
+    status: Option<ExtractionStatus>,
+    config_name: Option<String>,
+    /// Case-insensitive substring of the source file name
+    source_file: Option<String>,
+    /// Extracted at or after this ISO 8601 date or timestamp
+    since: Option<String>,
+    /// Extracted before this ISO 8601 date or timestamp
+    until: Option<String>,
+    /// Page size (default 100, at most 1000)
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// List extractions (lightweight summaries), newest first, one page at a time.
+/// Merges in-memory extractions with storage if configured; filters other
+/// than `readable_id` and `status` are applied by the storage query.
+async fn list_extractions(
+    State(state): State<AppState>,
+    Query(query): Query<ListExtractionsQuery>,
+) -> Json<Vec<ExtractionSummary>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .min(MAX_LIST_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let mut filter = storage::ExtractionFilter {
+        config_name: query.config_name.clone(),
+        config_version: query.config_version.clone(),
+        source_file: query.source_file.clone(),
+        since: query.since.clone(),
+        until: query.until.clone(),
+        reviewed: query.reviewed,
+        limit: None,
+    };
+
+    // Collect in-memory extractions
+    let mut list: Vec<ExtractionSummary> = state.extractions.summaries();
+    list.retain(|e| {
+        query.status.as_ref().is_none_or(|s| &e.status == s)
+            && filter.matches(
+                e.config_name.as_deref(),
+                e.config_version.as_deref(),
+                &e.source_file,
+                &e.extracted_at,
+                e.reviewed,
+            )
+    });
+
+    // Merge stored extractions (dedup by ID). Stored entries are always
+    // completed, and when every filter runs in the query only the first
+    // `offset + limit` rows can make it onto this page.
+    let stored_status = query
+        .status
+        .as_ref()
+        .is_none_or(|s| *s == ExtractionStatus::Completed);
+    if query.readable_id.is_none() {
+        filter.limit = Some(offset + limit);
+    }
+    if let Some(storage) = state.storage.as_ref().filter(|_| stored_status) {
+        match storage.list_extractions(&filter).await {
+            Ok(rows) => {
+                let in_memory_ids: HashSet<String> =
+                    list.iter().map(|e| e.id.clone()).collect();
+                for row in rows {
+                    if !in_memory_ids.contains(&row.id) {
+                        list.push(ExtractionSummary {
+                            id: row.id,
+                            status: ExtractionStatus::Completed,
+                            source_file: row.source_file,
+                            config_name: row.config_name,
+                            config_version: row.config_version,
+                            prompt_override: row.prompt_override.is_some(),
+                            extracted_at: row.extracted_at,
+                            total_pages: row.total_pages,
+                            summary: row.summary,
+                            readable_id: row.readable_id,
+                            duplicate_of: row.duplicate_of,
+                            reviewed: row.reviewed,
+                            node_count: row.node_count as usize,
+                        });
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to list extractions from storage: {}", e);
+            }
+        }
+    }
+
+    // Filter by readable_id if provided ("0001234-56.2024" also matches "0001234562024")
+    if let Some(ref filter) = query.readable_id {
+        list.retain(|e| {
+            e.readable_id
+                .as_ref()
+                .is_some_and(|rid| readable_id::matches_filter(rid, filter))
+        });
+    }
+
+    list.sort_by(|a, b| b.extracted_at.cmp(&a.extracted_at));
+    Json(list.into_iter().skip(offset).take(limit).collect())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GraphQuery {
+    /// Comma-separated entity types to keep (e.g. `processo_cnj,cnpj`)
+    entity_types: Option<String>,
+    /// Comma-separated edge kinds to keep: `mentions`, `references`, `duplicate_of`
+    edges: Option<String>,
+    /// Drop entities shared by fewer extractions (default 2)
+    min_extractions: Option<usize>,
+    /// Only return the neighborhood of this extraction
+    extraction: Option<String>,
+    /// Hops from `extraction` to include (default 2)
+    depth: Option<usize>,
+}
+
+/// Cross-extraction graph: extractions linked by shared entities, cited
+/// process numbers, and duplicates.
+async fn get_graph(
+    State(state): State<AppState>,
+    Query(query): Query<GraphQuery>,
+) -> Result<Json<graph::Graph>, ApiError> {
+    let split = |s: &str| -> Vec<String> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    let edge_kinds = match query.edges {
+        Some(ref edges) => Some(
+            split(edges)
+                .iter()
+                .map(|e| {
+                    graph::GraphEdgeKind::from_str(e).ok_or(ApiError::BadRequest(format!(
+                        "Unknown edge kind '{}'. Available: mentions, references, duplicate_of",
+                        e
+                    )))
+                })
+                .collect::<Result<HashSet<_>, _>>()?,
+        ),
+        None => None,
+    };
+    let defaults = graph::GraphFilter::default();
+    let filter = graph::GraphFilter {
+        entity_types: query
+            .entity_types
+            .as_deref()
+            .map(|t| split(t).into_iter().collect()),
+        edge_kinds,
+        min_extractions: query.min_extractions.unwrap_or(defaults.min_extractions),
+        center: query.extraction,
+        depth: query.depth.unwrap_or(defaults.depth),
+    };
+
+    // Completed extractions in memory, plus stored ones not loaded yet
+    let mut sources: Vec<graph::GraphSource> = state.extractions.scan(|e| {
+        (e.status == ExtractionStatus::Completed).then(|| graph::GraphSource {
+            id: e.id.clone(),
+            source_file: e.source_file.clone(),
+            config_name: e.config_name.clone(),
+            readable_id: e.readable_id.clone(),
+            duplicate_of: e.duplicate_of.clone(),
+            reference_index: e.reference_index.clone(),
+        })
+    });
+    if let Some(ref storage) = state.storage {
+        match storage
+            .list_extractions(&storage::ExtractionFilter::default())
+            .await
+        {
+            Ok(rows) => {
+                let seen: HashSet<String> = sources.iter().map(|s| s.id.clone()).collect();
+                sources.extend(rows.into_iter().filter(|r| !seen.contains(&r.id)).map(|r| {
+                    graph::GraphSource {
+                        id: r.id,
+                        source_file: r.source_file,
+                        config_name: r.config_name,
+                        readable_id: r.readable_id,
+                        duplicate_of: r.duplicate_of,
+                        reference_index: r.reference_index.unwrap_or_default(),
+                    }
+                }));
+            }
+            Err(e) => error!("Failed to list extractions from storage: {}", e),
+        }
+    }
+    sources.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(Json(graph::build(&sources, &filter)))
+}
+
+/// Get an extraction by ID (in-memory + storage fallback).
+#[derive(serde::Deserialize)]
+struct GetExtractionQuery {
+    /// Comma-separated fields to keep; `children.<field>` for node fields
+    fields: Option<String>,
+    /// Comma-separated keys to drop from the extraction and every node
+    exclude: Option<String>,
+    /// Tree levels to return; deeper children become `child_count`
+    depth: Option<usize>,
+    /// Inline each node's text as `content`
+    include_content: Option<bool>,
+}
+
+/// Get an extraction, optionally trimmed (see `sparse`) or with node text
+/// inlined. Answers 304 when `If-None-Match` matches (see `http_cache`).
+async fn get_extraction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<GetExtractionQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    let modified = last_modified(&extraction).to_string();
+    let include_content = query.include_content.unwrap_or(false);
+    let projection = sparse::Projection::new(
+        query.fields.as_deref(),
+        query.exclude.as_deref(),
+        query.depth,
+    );
+    if projection.is_empty() && !include_content {
+        return Ok(http_cache::json(&headers, extraction, Some(&modified)).await);
+    }
+    let mut value =
+        serde_json::to_value(&extraction).map_err(|e| ApiError::Internal(e.to_string()))?;
+    if include_content {
+        hydrate_content(&state, &extraction).await?;
+        if let Some(nodes) = value.get_mut("children") {
+            inline_content(nodes, &state.content_store);
+        }
+    }
+    projection.apply(&mut value);
+    Ok(http_cache::json(&headers, value, Some(&modified)).await)
+}
+
+/// When an extraction last changed: its latest review, else when it was extracted.
+fn last_modified(extraction: &Extraction) -> &str {
+    extraction
+        .reviews
+        .iter()
+        .map(|r| r.reviewed_at.as_str())
+        .chain([extraction.extracted_at.as_str()])
+        .max()
+        .unwrap_or_default()
+}
+
+#[derive(serde::Deserialize)]
+struct SnapshotQuery {
+    include_content_meta: Option<bool>,
+    /// Inline node contents into `content_index` up to this many characters
+    inline_budget_chars: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+struct ExtractionSnapshot {
+    #[serde(flatten)]
+    extraction: Extraction,
+    content_blobs_included: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inlined_chars: Option<usize>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    content_index: Vec<NodeContentMeta>,
+}
+
+#[derive(serde::Serialize)]
+struct NodeContentMeta {
+    node_id: String,
+    content_ref: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    char_count: Option<usize>,
+    available: bool,
+    /// With an inline budget: whether the content still has to be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lazy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+/// Get a full extraction snapshot optimized for MCP/context loading.
+///
+/// Returns the entire extraction tree in a single call. Raw content text is
+/// only included with `inline_budget_chars`; use `/content/:ref_path` to
+/// lazy-load the rest.
+async fn get_extraction_snapshot(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<SnapshotQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Extraction {} not found", id)))?;
+
+    let include_content_meta =
+        query.include_content_meta.unwrap_or(true) || query.inline_budget_chars.is_some();
+    let mut content_index = if include_content_meta {
+        let mut index = Vec::new();
+        collect_content_meta(
+            &extraction.children,
+            &state.content_store,
+            state.storage.is_some(),
+            &mut index,
+        );
+        index
+    } else {
+        Vec::new()
+    };
+
+    if query.inline_budget_chars.is_some() {
+        load_contents(&state, &extraction, &mut content_index).await;
+    }
+    let inlined_chars = query.inline_budget_chars.map(|budget| {
+        let node_types: Vec<String> = extraction
+            .config_name
+            .as_deref()
+            .and_then(|name| state.configs.get(name))
+            .map(|config| config.node_types.into_iter().map(|t| t.id).collect())
+            .unwrap_or_default();
+        inline_within_budget(
+            &extraction.children,
+            &node_types,
+            &state.content_store,
+            &mut content_index,
+            budget,
+        )
+    });
+
+    let modified = last_modified(&extraction).to_string();
+    let snapshot = ExtractionSnapshot {
+        extraction,
+        content_blobs_included: inlined_chars.is_some_and(|chars| chars > 0),
+        inlined_chars,
+        content_index,
+    };
+    Ok(http_cache::json(&headers, snapshot, Some(&modified)).await)
+}
+
+/// Get a specific node from an extraction (in-memory + storage fallback).
+async fn get_node(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+) -> Result<Json<schema::DocumentNode>, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Extraction {} not found", id)))?;
+
+    find_node(&extraction.children, &node_id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Node {} not found", node_id)))
+}
+
+/// The reviewer named in the request body, or else the `X-Reviewer` header.
+fn reviewer_from(body: Option<String>, headers: &HeaderMap) -> Result<String, ApiError> {
+    body.or_else(|| {
+        headers
+            .get("x-reviewer")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    })
+    .filter(|r| !r.trim().is_empty())
+    .ok_or(ApiError::BadRequest(
+        "Missing reviewer: set `reviewer` in the body or the X-Reviewer header".to_string(),
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct NodeUpdate {
+    node: schema::DocumentNode,
+    review: schema::NodeReview,
+}
+
+/// Apply a reviewer's correction to a node and record it in the audit trail.
+/// The reviewer comes from the body's `reviewer` or the `X-Reviewer` header.
+async fn update_node(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(correction): Json<review::NodeCorrection>,
+) -> Result<Json<NodeUpdate>, ApiError> {
+    let reviewer = reviewer_from(correction.reviewer.clone(), &headers)?;
+
+    let mut extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    let config = extraction
+        .config_name
+        .as_deref()
+        .and_then(|name| state.configs.get(name));
+    let total_pages = extraction.total_pages;
+    // A new page range moves the node's anchor in the OCR text and the PDF
+    let page_spans = match correction.page_range {
+        Some(_) => kept_ocr_pages(&state, &id)
+            .await
+            .map(|pages| (extractor::page_spans(&pages), extractor::pdf_pages(&pages))),
+        None => None,
+    };
+
+    let node = review::find_node_mut(&mut extraction.children, &node_id).ok_or(
+        ApiError::NotFound(format!("Node {} not found in extraction {}", node_id, id)),
+    )?;
+    let changes = review::apply(node, &correction, config.as_ref(), total_pages)
+        .map_err(ApiError::BadRequest)?;
+    if changes.iter().any(|c| c.field == "page_range") {
+        node.ocr_span = page_spans
+            .as_ref()
+            .zip(node.page_range)
+            .and_then(|((spans, _), range)| extractor::range_span(spans, range));
+        node.pdf_page_range = page_spans
+            .as_ref()
+            .zip(node.page_range)
+            .and_then(|((_, pdf_pages), range)| extractor::pdf_page_range(pdf_pages, range));
+    }
+    let node = node.clone();
+    if changes.iter().any(|c| c.field == "label") {
+        extraction.refresh_structure_map();
+    }
+    let review = schema::NodeReview::new(node_id, reviewer, changes);
+
+    // Persist first so memory never holds a correction storage rejected.
+    // Extractions not yet uploaded carry the review until they are.
+    if let Some(ref storage) = state.storage {
+        match storage.record_review(&id, &node, &review).await {
+            Ok(true) => {}
+            Ok(false) => debug!("Extraction {} not in storage; review kept in memory", id),
+            Err(e) => {
+                error!("Failed to record review for {}/{}: {}", id, node.id, e);
+                return Err(ApiError::Upstream(format!(
+                    "Failed to record review: {}",
+                    e
+                )));
+            }
+        }
+    }
+
+    info!(
+        "Node {}/{} reviewed by {} ({} change(s))",
+        id,
+        node.id,
+        review.reviewer,
+        review.changes.len()
+    );
+    extraction.reviewed = true;
+    extraction.reviews.push(review.clone());
+    state.extractions.insert(extraction.id.clone(), extraction);
+
+    Ok(Json(NodeUpdate { node, review }))
+}
+
+#[derive(serde::Serialize)]
+struct TreeEdit {
+    /// The moved node, the merged node, or both parts of a split
+    nodes: Vec<schema::DocumentNode>,
+    review: schema::NodeReview,
+}
+
+/// What a structural edit did to the tree.
+struct TreeChange {
+    changes: Vec<schema::FieldChange>,
+    /// Nodes to return, in order
+    nodes: Vec<String>,
+    /// Nodes whose content was re-sliced
+    resliced: Vec<String>,
+    /// Nodes that no longer exist
+    removed: Vec<String>,
+}
+
+/// Move a node under another parent (`parent_id: null` for the top level).
+async fn move_node(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<review::MoveRequest>,
+) -> Result<Json<TreeEdit>, ApiError> {
+    let reviewer = reviewer_from(req.reviewer.clone(), &headers)?;
+    edit_tree(&state, &id, node_id, reviewer, |extraction, node_id, _| {
+        let changes =
+            review::move_node(extraction, node_id, req.parent_id.as_deref(), req.position)?;
+        Ok(TreeChange {
+            changes,
+            nodes: vec![node_id.to_string()],
+            resliced: Vec::new(),
+            removed: Vec::new(),
+        })
+    })
+    .await
+}
+
+/// Merge an adjacent sibling (`with`) into a node.
+async fn merge_nodes(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<review::MergeRequest>,
+) -> Result<Json<TreeEdit>, ApiError> {
+    let reviewer = reviewer_from(req.reviewer.clone(), &headers)?;
+    edit_tree(&state, &id, node_id, reviewer, |extraction, node_id, store| {
+        let changes = review::merge_nodes(extraction, node_id, &req.with, store)?;
+        Ok(TreeChange {
+            changes,
+            nodes: vec![node_id.to_string()],
+            resliced: vec![node_id.to_string()],
+            removed: vec![req.with.clone()],
+        })
+    })
+    .await
+}
+
+/// Split a node in two, the second part starting at `page`.
+async fn split_node(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(req): Json<review::SplitRequest>,
+) -> Result<Json<TreeEdit>, ApiError> {
+    let reviewer = reviewer_from(req.reviewer.clone(), &headers)?;
+    edit_tree(&state, &id, node_id, reviewer, |extraction, node_id, store| {
+        let (new_id, changes) =
+            review::split_node(extraction, node_id, req.page, req.label.clone(), store)?;
+        Ok(TreeChange {
+            changes,
+            nodes: vec![node_id.to_string(), new_id.clone()],
+            resliced: vec![node_id.to_string(), new_id],
+            removed: Vec::new(),
+        })
+    })
+    .await
+}
+
+/// Apply a structural edit to an extraction, refresh what derives from node
+/// content (regex entities, redacted copies), persist, and record the review.
+async fn edit_tree(
+    state: &AppState,
+    id: &str,
+    node_id: String,
+    reviewer: String,
+    edit: impl FnOnce(&mut Extraction, &str, &ContentStore) -> Result<TreeChange, String>,
+) -> Result<Json<TreeEdit>, ApiError> {
+    let mut extraction = get_or_hydrate_extraction(state, id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    let node = find_node(&extraction.children, &node_id).ok_or(ApiError::NotFound(format!(
+        "Node {} not found in extraction {}",
+        node_id, id
+    )))?;
+    // Edits re-slice content, and storage rewrites the tree with it
+    hydrate_content(state, &extraction).await?;
+    // The edit re-slices this node's content in place; keep it for rollback
+    let previous_content = node
+        .content_ref
+        .as_deref()
+        .and_then(|r| state.content_store.get_full(r));
+    let previous_redacted = node
+        .content_ref
+        .as_deref()
+        .and_then(|r| {
+            state
+                .content_store
+                .get_full(&format!("{}{}", r, redaction::REDACTED_SUFFIX))
+        });
+
+    let change =
+        edit(&mut extraction, &node_id, &state.content_store).map_err(ApiError::BadRequest)?;
+    extraction.refresh_structure_map();
+
+    // Re-anchor the edited nodes; without the kept OCR their spans are unknown
+    let pages = kept_ocr_pages(state, id).await;
+    match pages {
+        Some(ref pages) => {
+            extractor::assign_ocr_spans(&mut extraction.children, &extractor::page_spans(pages));
+            extractor::assign_pdf_page_ranges(
+                &mut extraction.children,
+                &extractor::pdf_pages(pages),
+            );
+        }
+        None => {
+            for resliced in &change.resliced {
+                if let Some(node) = review::find_node_mut(&mut extraction.children, resliced) {
+                    node.ocr_span = None;
+                    node.pdf_page_range = None;
+                }
+            }
+        }
+    }
+
+    let config = extraction
+        .config_name
+        .as_deref()
+        .and_then(|name| state.configs.get(name));
+    if let Some(ref config) = config {
+        let extractor = Extractor::new((*state.openrouter).clone(), state.content_store.clone());
+        extractor.extract_entities(&mut extraction, config, pages.as_deref());
+        if previous_redacted.is_some() {
+            extractor.redact_nodes(&extraction, &change.resliced, config);
+        }
+    }
+
+    let review = schema::NodeReview::new(node_id.clone(), reviewer, change.changes);
+    extraction.reviewed = true;
+    extraction.reviews.push(review.clone());
+
+    if let Some(ref storage) = state.storage {
+        match storage
+            .replace_tree(&extraction, &state.content_store, &change.removed)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => debug!("Extraction {} not in storage; edit kept in memory", id),
+            Err(e) => {
+                error!("Failed to save tree edit for {}/{}: {}", id, node_id, e);
+                if let Some(content) = previous_content {
+                    state.content_store.store(&node_id, content);
+                }
+                if let Some(content) = previous_redacted {
+                    state.content_store.store(
+                        &format!("{}{}", node_id, redaction::REDACTED_SUFFIX),
+                        content,
+                    );
+                }
+                return Err(ApiError::Upstream(format!(
+                    "Failed to save tree edit: {}",
+                    e
+                )));
+            }
+        }
+    }
+
+    info!(
+        "Tree edit on {}/{} by {}: {:?}",
+        id,
+        node_id,
+        review.reviewer,
+        review.changes.iter().map(|c| c.field.as_str()).collect::<Vec<_>>()
+    );
+    let nodes = change
+        .nodes
+        .iter()
+        .filter_map(|n| find_node(&extraction.children, n).cloned())
+        .collect();
+    state.extractions.insert(extraction.id.clone(), extraction);
+
+    Ok(Json(TreeEdit { nodes, review }))
+}
+
+#[derive(serde::Deserialize)]
+struct ExtractNodeQuery {
+    config: Option<String>,
+    upload: Option<bool>,
+    callback_url: Option<String>,
+}
+
+/// Re-extract a node's pages with another config, e.g. a contract or an
+/// invoice attached to a filing, as a new extraction linked to the node.
+/// Returns the child's `queued` placeholder, like `POST /extract`; the node
+/// records it as `child_extraction_id`.
+///
+/// Query params:
+///   - `config` — config to extract the pages with (default: the one the
+///     parent's config lists for the node in `exhibit_configs`)
+///   - `upload` — upload the child to storage (default: true)
+///   - `callback_url` — POST the completed child to this URL
+///
+/// The pages come from the node's content, or from the kept OCR output when
+/// it has none; no OCR is run again.
+async fn extract_node(
+    State(state): State<AppState>,
+    Path((id, node_id)): Path<(String, String)>,
+    Query(query): Query<ExtractNodeQuery>,
+) -> Result<Json<Extraction>, ApiError> {
+    let parent = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    if parent.status.is_active() {
+        return Err(ApiError::Conflict(format!(
+            "Extraction {} is still running (status: {:?})",
+            id, parent.status
+        )));
+    }
+    let node = find_node(&parent.children, &node_id).ok_or(ApiError::NotFound(format!(
+        "Node {} not found in extraction {}",
+        node_id, id
+    )))?;
+
+    let config_name = match query.config {
+        Some(name) => name,
+        None => parent
+            .config_name
+            .as_deref()
+            .and_then(|name| state.configs.get(name))
+            .and_then(|config| {
+                config
+                    .exhibit_config(&node.node_type, node.subtype.as_deref())
+                    .map(str::to_string)
+            })
+            .ok_or_else(|| {
+                ApiError::BadRequest(format!(
+                    "No config given, and none is set for {} nodes in exhibit_configs",
+                    node.node_type
+                ))
+            })?,
+    };
+    let config = Arc::new(requested_config(
+        &state,
+        Some(&config_name),
+        ConfigKind::Document,
+    )?);
+    let admission = admit_job(&state, &config).await?;
+
+    // The node's content is sliced by OCR page; the kept OCR has the rest
+    hydrate_content(&state, &parent).await?;
+    let kept = kept_ocr_pages(&state, &id).await.unwrap_or_default();
+    let mut pages = node
+        .content_ref
+        .as_deref()
+        .and_then(|r| state.content_store.get_full(r))
+        .map(|content| extractor::pages_from_content(&content))
+        .unwrap_or_default();
+    if pages.is_empty() {
+        if let Some([start, end]) = node.page_range {
+            pages = kept
+                .iter()
+                .filter(|p| (start..=end).contains(&p.page_num))
+                .cloned()
+                .collect();
+        }
+    }
+    if pages.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "Node {} of extraction {} has no OCR text to extract",
+            node_id, id
+        )));
+    }
+    let pages = exhibit_pages(pages, &kept);
+    let ocr_result = ocr::OcrResult {
+        markdown: pages
+            .iter()
+            .map(|p| p.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        total_pages: pages.len() as u32,
+        metadata: serde_json::json!({ "parent_extraction_id": id, "parent_node_id": node_id }),
+        ocr_confidence: ocr::mean_page_confidence(&pages)
+            .or(parent.ocr_confidence)
+            .unwrap_or(0.0),
+        provider_name: parent
+            .ocr_provider
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string()),
+        pages,
+    };
+
+    let label = node.label.clone().unwrap_or_else(|| node_id.clone());
+    let mut child = Extraction::new(
+        format!("{} ({})", parent.source_file, label),
+        Some(config.name.clone()),
+    );
+    child.parent_extraction_id = Some(id.clone());
+    child.parent_node_id = Some(node_id.clone());
+    mark_queued(&mut child);
+    let record = jobs::JobRecord {
+        id: child.id.clone(),
+        kind: jobs::JobKind::Extraction,
+        source_file: child.source_file.clone(),
+        config_name: config.name.clone(),
+        ocr_provider: Some(ocr_result.provider_name.clone()),
+        ocr_options: None,
+        file_url: None,
+        upload: query.upload.unwrap_or(true),
+        callback_url: query.callback_url,
+        prompt_override: None,
+        priority: jobs::Priority::Normal,
+        parent_extraction_id: child.parent_extraction_id.clone(),
+        parent_node_id: child.parent_node_id.clone(),
+        started_at: child.extracted_at.clone(),
+    };
+    // Kept like any OCR output, so retries and recovery resume from it
+    keep_ocr(
+        &state,
+        &object_store::extraction_root(&child.id),
+        &child.id,
+        &ocr_result,
+    )
+    .await;
+
+    // Link the node before the run starts, so the parent lists it at once.
+    // The link goes on the live entry, keeping edits made since it was read.
+    let linked = state
+        .extractions
+        .update(&id, |ext| {
+            let node = review::find_node_mut(&mut ext.children, &node_id)?;
+            node.child_extraction_id = Some(child.id.clone());
+            Some(ext.clone())
+        })
+        .flatten()
+        .ok_or_else(|| {
+            ApiError::Conflict(format!(
+                "Node {} of extraction {} changed while being extracted",
+                node_id, id
+            ))
+        })?;
+    if let Some(ref storage) = state.storage {
+        match storage
+            .replace_tree(&linked, &state.content_store, &[])
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => debug!("Extraction {} not in storage; link kept in memory", id),
+            Err(e) => {
+                error!("Failed to save exhibit link for {}/{}: {}", id, node_id, e);
+                state.extractions.update(&id, |ext| {
+                    let node = review::find_node_mut(&mut ext.children, &node_id)?;
+                    if node.child_extraction_id.as_deref() == Some(child.id.as_str()) {
+                        node.child_extraction_id = None;
+                    }
+                    Some(())
+                });
+                return Err(ApiError::Upstream(format!(
+                    "Failed to save exhibit link: {}",
+                    e
+                )));
+            }
+        }
+    }
+
+    if let Some(shared) = state.shared.clone().filter(|s| s.queues_jobs()) {
+        shared.put_snapshot("extraction", &child.id, Some(&child));
+        if let Err(e) = shared.enqueue(&record).await {
+            error!("Failed to queue extraction {}: {:#}", record.id, e);
+            mark_job_failed(&state, &record, format!("Could not be queued: {:#}", e));
+        }
+        return Ok(Json(child));
+    }
+    state.extractions.insert(child.id.clone(), child.clone());
+    state.jobs.start(&record);
+    info!(
+        "Queued exhibit extraction {} of {}/{} with config {}",
+        child.id, id, node_id, config.name
+    );
+    spawn_extraction(
+        state.clone(),
+        extraction_job(&record, (*config).clone()),
+        PipelineInput::Ocr(ocr_result),
+        admission,
+    );
+    Ok(Json(child))
+}
+
+/// An exhibit's pages numbered from 1, each remembering its page of the
+/// source PDF (from the parent's kept OCR when it has one).
+fn exhibit_pages(pages: Vec<ocr::OcrPage>, kept: &[ocr::OcrPage]) -> Vec<ocr::OcrPage> {
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page)| {
+            let original = kept.iter().find(|k| k.page_num == page.page_num);
+            ocr::OcrPage {
+                page_num: i as u32 + 1,
+                confidence: page.confidence.or(original.and_then(|k| k.confidence)),
+                pdf_page: original
+                    .and_then(|k| k.pdf_page)
+                    .or(page.pdf_page)
+                    .or(Some(page.page_num)),
+                text: page.text,
+            }
+        })
+        .collect()
+}
+
+/// Fetch an archived object, mapping a missing store or object to an HTTP error.
+async fn get_archived_object(state: &AppState, key: &str, what: &str) -> Result<Vec<u8>, ApiError> {
+    let store = state
+        .object_store
+        .as_ref()
+        .ok_or_else(|| ApiError::Unavailable("Object store not configured".to_string()))?;
+
+    store
+        .get(key)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch {} from {}: {}", key, store.name(), e);
+            ApiError::Internal(format!("Failed to fetch {}: {}", what, e))
+        })?
+        .ok_or_else(|| ApiError::NotFound(format!("{} not found", what)))
+}
+
+/// Download the original uploaded file for an extraction.
+async fn get_extraction_source(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let data = get_archived_object(&state, &object_store::source_key(&id), "Source file").await?;
+
+    let filename = get_or_hydrate_extraction(&state, &id)
+        .await
+        .map(|e| e.source_file)
+        .unwrap_or_else(|| "document".to_string());
+
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            object_store::content_type_for(&filename).to_string(),
+        ),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename.replace('"', "")),
+        ),
+    ];
+    Ok((headers, data).into_response())
+}
+
+#[derive(serde::Deserialize)]
+struct PageImageQuery {
+    /// Render resolution (default 150, clamped to 36-600)
+    dpi: Option<u32>,
+}
+
+/// A page of an extraction's source file as PNG, rendered on first request
+/// and cached in the object store.
+async fn get_page_image(
+    State(state): State<AppState>,
+    Path((id, page)): Path<(String, u32)>,
+    Query(query): Query<PageImageQuery>,
+) -> Result<Response, ApiError> {
+    let dpi = query
+        .dpi
+        .unwrap_or(page_image::DEFAULT_DPI)
+        .clamp(page_image::MIN_DPI, page_image::MAX_DPI);
+    let key = object_store::page_image_key(&id, page, dpi);
+    let png = match get_archived_object(&state, &key, "Page image").await {
+        Ok(png) => png,
+        Err(ApiError::NotFound(_)) => render_page_image(&state, &id, page, dpi).await?,
+        Err(e) => return Err(e),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        png,
+    )
+        .into_response())
+}
+
+/// Render one page from the archived source and cache it (caching failures are logged).
+async fn render_page_image(
+    state: &AppState,
+    id: &str,
+    page: u32,
+    dpi: u32,
+) -> Result<Vec<u8>, ApiError> {
+    let source = get_archived_object(state, &object_store::source_key(id), "Source file").await?;
+    let pages =
+        estimate::count_pages(&source).map_err(|e| ApiError::Unprocessable(format!("{:#}", e)))?;
+    if page == 0 || page > pages {
+        return Err(ApiError::NotFound(format!(
+            "Page {} not found (document has {} pages)",
+            page, pages
+        )));
+    }
+
+    let png = page_image::render_page(&source, page, dpi)
+        .await
+        .map_err(|e| {
+            error!("Failed to render page {} of {}: {:#}", page, id, e);
+            ApiError::Internal(format!("Failed to render page {}: {:#}", page, e))
+        })?;
+    if let Some(ref store) = state.object_store {
+        let key = object_store::page_image_key(id, page, dpi);
+        if let Err(e) = store.put(&key, png.clone(), "image/png").await {
+            warn!("Failed to cache {}: {}", key, e);
+        }
+    }
+    Ok(png)
+}
+
+#[derive(serde::Deserialize)]
+struct OcrQuery {
+    page: Option<u32>,
+    format: Option<String>,
+    /// First page to return (0-based index into `pages`) in paginated `json` output
+    offset: Option<usize>,
+    /// Pages per paginated `json` response (default 20)
+    limit: Option<usize>,
+}
+
+/// A window of OCR pages, returned for `json` when `offset` or `limit` is set.
+#[derive(serde::Serialize)]
+struct OcrPageWindow {
+    id: String,
+    provider: String,
+    total_pages: u32,
+    ocr_confidence: f64,
+    offset: usize,
+    limit: usize,
+    has_more: bool,
+    pages: Vec<object_store::StoredOcrPage>,
+}
+
+/// Get the raw OCR output kept for an extraction.
+///
+/// Query params:
+///   - `page` — return the plain text of a single page (1-indexed)
+///   - `format` — `json` (default: provider, pages, and markdown) or `markdown`
+///   - `offset` / `limit` — page through `json` output without the markdown
+async fn get_extraction_ocr(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<OcrQuery>,
+) -> Result<Response, ApiError> {
+    ocr_response(&state, &object_store::extraction_root(&id), query).await
+}
+
+/// Get the raw OCR output kept for a dataset extracted from a PDF.
+/// Same query params as `GET /extractions/:id/ocr`.
+async fn get_dataset_ocr(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<OcrQuery>,
+) -> Result<Response, ApiError> {
+    ocr_response(&state, &object_store::dataset_root(&id), query).await
+}
+
+async fn ocr_response(state: &AppState, root: &str, query: OcrQuery) -> Result<Response, ApiError> {
+    let format = query.format.as_deref().unwrap_or("json");
+    if !matches!(format, "json" | "markdown") {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown format: '{}'. Available: json, markdown",
+            format
+        )));
+    }
+
+    // The object store keeps each page and the markdown as separate objects
+    if state.object_store.is_some() {
+        if let Some(page) = query.page {
+            let data = get_archived_object(
+                state,
+                &object_store::ocr_page_key(root, page),
+                &format!("OCR text for page {}", page),
+            )
+            .await?;
+            return Ok(
+                ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], data).into_response(),
+            );
+        }
+        if format == "markdown" {
+            let data =
+                get_archived_object(state, &object_store::ocr_markdown_key(root), "OCR markdown")
+                    .await?;
+            return Ok((
+                [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+                data,
+            )
+                .into_response());
+        }
+    }
+
+    let stored = load_ocr(state, root)
+        .await
+        .map_err(|e| {
+            error!("Failed to load OCR output: {}", e);
+            ApiError::Internal(format!("Failed to load OCR output: {}", e))
+        })?
+        .ok_or(ApiError::NotFound("OCR output not found".to_string()))?;
+
+    if let Some(page) = query.page {
+        let text = stored
+            .pages
+            .into_iter()
+            .find(|p| p.page_num == page)
+            .ok_or(ApiError::NotFound(format!(
+                "OCR text for page {} not found",
+                page
+            )))?
+            .text;
+        return Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], text).into_response());
+    }
+    if format == "markdown" {
+        return Ok((
+            [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            stored.markdown,
+        )
+            .into_response());
+    }
+    if query.offset.is_none() && query.limit.is_none() {
+        return Ok(Json(stored).into_response());
+    }
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(20);
+    let has_more = offset.saturating_add(limit) < stored.pages.len();
+    Ok(Json(OcrPageWindow {
+        id: stored.extraction_id,
+        provider: stored.provider,
+        total_pages: stored.total_pages,
+        ocr_confidence: stored.ocr_confidence,
+        offset,
+        limit,
+        has_more,
+        pages: stored.pages.into_iter().skip(offset).take(limit).collect(),
+    })
+    .into_response())
+}
+
+#[derive(serde::Deserialize)]
+struct GraphExportQuery {
+    /// `cytoscape` (default) or `graphml`
+    format: Option<String>,
+}
+
+/// Export an extraction's node tree and relationships for graph tools.
+async fn export_extraction_graph(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<GraphExportQuery>,
+) -> Result<Response, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+
+    match query.format.as_deref().unwrap_or("cytoscape") {
+        "cytoscape" => Ok(Json(graph::to_cytoscape(&extraction)).into_response()),
+        "graphml" => Ok((
+            [
+                (header::CONTENT_TYPE, "application/graphml+xml".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("inline; filename=\"{}.graphml\"", extraction.id),
+                ),
+            ],
+            graph::to_graphml(&extraction),
+        )
+            .into_response()),
+        other => Err(ApiError::BadRequest(format!(
+            "Unknown format: '{}'. Available: cytoscape, graphml",
+            other
+        ))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ExportQuery {
+    /// `training_jsonl` (default)
+    format: Option<String>,
+}
+
+/// Export an extraction as fine-tuning data: one JSONL record per node with
+/// content, pairing its text with the (reviewed) structured output.
+async fn export_extraction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let format = query.format.as_deref().unwrap_or("training_jsonl");
+    if format != "training_jsonl" {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown format: '{}'. Available: training_jsonl",
+            format
+        )));
+    }
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+
+    let mut contents = HashMap::new();
+    for node in eval::flatten(&extraction.children) {
+        let Some(content_ref) = &node.content_ref else {
+            continue;
+        };
+        let node_id = content_ref
+            .strip_prefix("content://")
+            .unwrap_or(content_ref);
+        if ensure_content(&state, node_id, true).await {
+            if let Some(content) = state.content_store.get_full(content_ref) {
+                contents.insert(content_ref.clone(), content);
+            }
+        }
+    }
+
+    let records = training::records(&extraction, &contents);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.jsonl\"", extraction.id),
+            ),
+        ],
+        training::to_jsonl(&records),
+    )
+        .into_response())
+}
+
+#[derive(serde::Deserialize)]
+struct ReviewQueueQuery {
+    /// Nodes with extraction confidence below this are listed (default 0.6)
+    threshold: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct ReviewQueue {
+    extraction_id: String,
+    threshold: f64,
+    nodes: Vec<confidence::ReviewItem>,
+}
+
+/// List an extraction's low-confidence nodes, least confident first.
+async fn get_review_queue(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ReviewQueueQuery>,
+) -> Result<Json<ReviewQueue>, ApiError> {
+    let threshold = query
+        .threshold
+        .unwrap_or(confidence::DEFAULT_REVIEW_THRESHOLD);
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+
+    Ok(Json(ReviewQueue {
+        nodes: confidence::review_queue(&extraction, threshold),
+        extraction_id: extraction.id,
+        threshold,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct OcrQualityQuery {
+    /// Pages scoring below this are flagged (default 0.7)
+    threshold: Option<f64>,
+}
+
+/// Per-page OCR confidence and the nodes that overlap low-confidence pages.
+async fn get_ocr_quality(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<OcrQualityQuery>,
+) -> Result<Json<confidence::OcrQualityReport>, ApiError> {
+    let threshold = query.threshold.unwrap_or(confidence::LOW_OCR_CONFIDENCE);
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    let ocr = extraction_ocr(&state, &extraction)
+        .await
+        .ok_or(ApiError::NotFound(format!(
+            "No OCR output available for {}",
+            id
+        )))?;
+
+    Ok(Json(confidence::ocr_quality(
+        &extraction,
+        &ocr.into_ocr_result(),
+        threshold,
+    )))
+}
+
+#[derive(serde::Deserialize)]
+struct TimelineQuery {
+    /// Ask the LLM for each event's `event_type`
+    classify: Option<bool>,
+}
+
+/// Dated nodes and entity mentions of an extraction, oldest first.
+async fn get_timeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<timeline::Timeline>, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &id)
+        .await
+        .ok_or(ApiError::NotFound(format!("Extraction {} not found", id)))?;
+    let mut timeline = timeline::build(&extraction);
+
+    if query.classify.unwrap_or(false) {
+        let config = extraction
+            .config_name
+            .as_deref()
+            .and_then(|name| state.configs.get(name));
+        let timeout =
+            config::StageTimeouts::resolve(config.as_ref().and_then(|c| c.timeouts.as_ref())).llm;
+        let classify = timeline::classify(&state.openrouter, &extraction, &mut timeline);
+        match tokio::time::timeout(timeout, classify).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                return Err(ApiError::Upstream(format!(
+                    "Failed to classify timeline events: {:#}",
+                    e
+                )))
+            }
+            Err(_) => {
+                return Err(ApiError::Upstream(format!(
+                    "Classifying timeline events timed out after {}s",
+                    timeout.as_secs()
+                )))
+            }
+        }
+    }
+    Ok(Json(timeline))
+}
+
+#[derive(serde::Deserialize)]
+struct ContentQuery {
+    offset: Option<usize>,
+    limit: Option<usize>,
+    /// Serve the copy produced by the `redact` pipeline stage
+    redacted: Option<bool>,
+    /// Return an LLM summary of the content instead of the text
+    summarize: Option<bool>,
+    /// Summary length (default `content_summary::DEFAULT_MAX_CHARS`)
+    max_chars: Option<usize>,
+}
+
+/// Copy a node's content from Redis into the content store. Returns whether
+/// there was any.
+async fn fetch_shared_content(state: &AppState, node_id: &str) -> bool {
+    let Some(ref shared) = state.shared else {
+        return false;
+    };
+    match shared.content(node_id).await {
+        Some(content) => {
+            state.content_store.cache(node_id, content);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Make sure a node's content is in the content store, copying it from
+/// another replica or, with `from_storage`, the storage backend. Returns
+/// whether it is there.
+async fn ensure_content(state: &AppState, node_id: &str, from_storage: bool) -> bool {
+    // 1. In-memory content store
+    if state
+        .content_store
+        .exists(&format!("content://{}", node_id))
+    {
+        return true;
+    }
+
+    // 2. Content stored by another replica
+    if fetch_shared_content(state, node_id).await {
+        return true;
+    }
+
+    // 3. Fall back to storage
+    let Some(storage) = state.storage.as_ref().filter(|_| from_storage) else {
+        return false;
+    };
+    match storage.fetch_content_by_node_id(node_id).await {
+        Ok(Some(content)) => {
+            info!(
+                "Hydrated content for {} from storage ({} chars)",
+                node_id,
+                content.len()
+            );
+            state.content_store.store(node_id, content);
+            true
+        }
+        Ok(None) => {
+            debug!("Content for {} not found in storage", node_id);
+            false
+        }
+        Err(e) => {
+            error!(
+                "Failed to fetch content for {} from storage: {}",
+                node_id, e
+            );
+            false
+        }
+    }
+}
+
+/// Get content by reference with pagination (in-memory + storage fallback).
+async fn get_content(
+    State(state): State<AppState>,
+    Path(ref_path): Path<String>,
+    Query(query): Query<ContentQuery>,
+) -> Result<Response, ApiError> {
+    // Redacted copies live only in the content store; never fall back to the original
+    let redacted = query.redacted.unwrap_or(false);
+    let node_id = if redacted {
+        format!("{}{}", ref_path, redaction::REDACTED_SUFFIX)
+    } else {
+        ref_path
+    };
+    let content_ref = format!("content://{}", node_id);
+
+    if query.summarize.unwrap_or(false) {
+        let max_chars = query
+            .max_chars
+            .unwrap_or(content_summary::DEFAULT_MAX_CHARS);
+        let summary = summarize_content(&state, &node_id, !redacted, max_chars).await?;
+        return Ok(Json(summary).into_response());
+    }
+
+    ensure_content(&state, &node_id, !redacted).await;
+    state
+        .content_store
+        .get(
+            &content_ref,
+            query.offset.unwrap_or(0),
+            query.limit.unwrap_or(4000),
+        )
+        .map(|chunk| Json(chunk).into_response())
+        .ok_or_else(|| ApiError::NotFound(format!("Content {} not found", content_ref)))
+}
+
+/// Characters one `POST /content/batch` returns across all its refs.
+const CONTENT_BATCH_MAX_CHARS: usize = 200_000;
+
+/// Refs one `POST /content/batch` may ask for.
+const CONTENT_BATCH_MAX_REFS: usize = 200;
+
+#[derive(serde::Deserialize)]
+struct ContentBatchRequest {
+    refs: Vec<ContentBatchRef>,
+}
+
+#[derive(serde::Deserialize)]
+struct ContentBatchRef {
+    /// `content://node_id` or just `node_id`
+    #[serde(rename = "ref")]
+    content_ref: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    redacted: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+struct ContentBatchResponse {
+    chunks: Vec<ContentBatchItem>,
+    total_chars: usize,
+    /// The size cap shortened or skipped some chunks
+    truncated: bool,
+}
+
+#[derive(serde::Serialize)]
+struct ContentBatchItem {
+    #[serde(rename = "ref")]
+    content_ref: String,
+    #[serde(flatten)]
+    chunk: Option<content_store::ContentChunk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Get several content chunks in one call. Refs are served in order until
+/// `CONTENT_BATCH_MAX_CHARS` is spent: the chunk that reaches the cap is
+/// shortened (`has_more` tells to continue from there) and later refs are
+/// skipped. A missing ref gets an `error` instead of failing the batch; more
+/// than `CONTENT_BATCH_MAX_REFS` refs are refused.
+async fn get_content_batch(
+    State(state): State<AppState>,
+    Json(req): Json<ContentBatchRequest>,
+) -> Result<Json<ContentBatchResponse>, ApiError> {
+    if req.refs.len() > CONTENT_BATCH_MAX_REFS {
+        return Err(ApiError::BadRequest(format!(
+            "A batch takes at most {} refs, got {}",
+            CONTENT_BATCH_MAX_REFS,
+            req.refs.len()
+        )));
+    }
+    let mut remaining = CONTENT_BATCH_MAX_CHARS;
+    let mut truncated = false;
+    let mut chunks = Vec::with_capacity(req.refs.len());
+    for item in req.refs {
+        let ref_path = item
+            .content_ref
+            .strip_prefix("content://")
+            .unwrap_or(&item.content_ref);
+        let redacted = item.redacted.unwrap_or(false);
+        let node_id = if redacted {
+            format!("{}{}", ref_path, redaction::REDACTED_SUFFIX)
+        } else {
+            ref_path.to_string()
+        };
+        let content_ref = format!("content://{}", node_id);
+
+        let limit = item.limit.unwrap_or(4000);
+        if remaining == 0 {
+            truncated = true;
+            chunks.push(ContentBatchItem {
+                content_ref,
+                chunk: None,
+                error: Some("Batch size cap reached".to_string()),
+            });
+            continue;
+        }
+        ensure_content(&state, &node_id, !redacted).await;
+        let chunk =
+            state
+                .content_store
+                .get(&content_ref, item.offset.unwrap_or(0), limit.min(remaining));
+        let error = chunk
+            .is_none()
+            .then(|| format!("Content {} not found", content_ref));
+        if let Some(chunk) = &chunk {
+            remaining -= chunk.content.chars().count();
+            truncated |= limit > chunk.limit && chunk.has_more;
+        }
+        chunks.push(ContentBatchItem {
+            content_ref,
+            chunk,
+            error,
+        });
+    }
+    Ok(Json(ContentBatchResponse {
+        chunks,
+        total_chars: CONTENT_BATCH_MAX_CHARS - remaining,
+        truncated,
+    }))
+}
+
+/// A node's content summary, generated on first request and then kept in
+/// the content store next to the content.
+async fn summarize_content(
+    state: &AppState,
+    node_id: &str,
+    from_storage: bool,
+    max_chars: usize,
+) -> Result<content_summary::ContentSummary, ApiError> {
+    if max_chars == 0 {
+        return Err(ApiError::BadRequest(
+            "max_chars must be at least 1".to_string(),
+        ));
+    }
+    let content_ref = format!("content://{}", node_id);
+    if !ensure_content(state, node_id, from_storage).await {
+        return Err(ApiError::NotFound(format!(
+            "Content {} not found",
+            content_ref
+        )));
+    }
+    let total_chars = state.content_store.len(&content_ref).unwrap_or_default();
+
+    let summary_id = content_summary::summary_node_id(node_id, max_chars);
+    let cached_summary = if ensure_content(state, &summary_id, false).await {
+        state
+            .content_store
+            .get_full(&format!("content://{}", summary_id))
+    } else {
+        None
+    };
+    let cached = cached_summary.is_some();
+    let summary = match cached_summary {
+        Some(summary) => summary,
+        None => {
+            let content = state
+                .content_store
+                .get_full(&content_ref)
+                .unwrap_or_default();
+            let timeout = config::StageTimeouts::resolve(None).llm;
+            let summarize = content_summary::summarize(&state.openrouter, &content, max_chars);
+            let summary = match tokio::time::timeout(timeout, summarize).await {
+                Ok(Ok(summary)) => summary,
+                Ok(Err(e)) => {
+                    return Err(ApiError::Upstream(format!(
+                        "Failed to summarize {}: {:#}",
+                        content_ref, e
+                    )))
+                }
+                Err(_) => {
+                    return Err(ApiError::Upstream(format!(
+                        "Summarizing {} timed out after {}s",
+                        content_ref,
+                        timeout.as_secs()
+                    )))
+                }
+            };
+            state.content_store.store(&summary_id, summary.clone());
+            summary
+        }
+    };
+
+    Ok(content_summary::ContentSummary {
+        content_ref,
+        summary,
+        max_chars,
+        total_chars,
+        cached,
+    })
+}
+
+// ============================================================================
+// Sheet extraction handlers
+// ============================================================================
+
+#[derive(serde::Deserialize)]
+struct SheetExtractQuery {
+    config: Option<String>,
+    upload: Option<bool>,
+    ocr_provider: Option<String>,
+    ocr_options: Option<String>,
+}
+
+/// Upload a file and start async sheet extraction.
+/// Supports CSV, Excel (.xlsx/.xlsm/.xlsb), and PDF (via OCR → table parsing).
+/// Returns immediately with dataset ID and status "processing".
+/// Poll GET /datasets/:id to check when status becomes "completed" or "failed".
+async fn extract_sheet(
+    State(state): State<AppState>,
+    Query(query): Query<SheetExtractQuery>,
+    multipart: Option<Multipart>,
+) -> Result<Json<SheetExtraction>, ApiError> {
+    let config = requested_config(&state, query.config.as_deref(), ConfigKind::Sheet)?;
+    let config = Arc::new(with_ocr_options(config, query.ocr_options.as_deref())?);
+    let admission = admit_job(&state, &config).await?;
+
+    let input = read_file_input(multipart, None, upload::Accept::Sheet).await?;
+    let file_data = input.bytes().await?;
+    let filename = input.filename;
+
+    let ext = filename
+        .rsplit('.')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    let is_pdf = ext == "pdf";
+
+    // For PDFs, resolve OCR provider
+    let ocr = if is_pdf {
+        let provider_name = query.ocr_provider.as_deref().unwrap_or("docling");
+        let provider_kind = OcrProviderKind::from_str(provider_name).ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Unknown ocr_provider: '{}'. Available: docling, mistral_ocr, smol_docling",
+                provider_name
+            ))
+        })?;
+        let provider = state
+            .ocr_providers
+            .get(&provider_kind)
+            .ok_or_else(|| ApiError::ProviderUnconfigured(provider_name.to_string()))?;
+        Some((provider_name, Arc::clone(provider)))
+    } else {
+        None
+    };
+
+    info!(
+        "Received sheet file: {} ({} bytes, config={}, pdf={})",
+        filename,
+        file_data.len(),
+        config.name,
+        is_pdf
+    );
+
+    Ok(Json(queue_sheet_extraction(
+        &state,
+        admission,
+        config,
+        filename,
+        file_data,
+        ocr,
+        query.upload.unwrap_or(true),
+        None,
+    )))
+}
+
+/// Create a `processing` placeholder for a sheet, journal the job, and start
+/// its extraction in the background. `ocr` is the provider for PDFs.
+#[allow(clippy::too_many_arguments)]
+fn queue_sheet_extraction(
+    state: &AppState,
+    admission: Admission,
+    config: Arc<config::ExtractionConfig>,
+    filename: String,
+    file_data: Vec<u8>,
+    ocr: Option<(&str, Arc<dyn OcrProvider>)>,
+    upload: bool,
+    callback_url: Option<String>,
+) -> SheetExtraction {
+    // Create placeholder
+    let dataset = SheetExtraction::new(filename.clone(), Some(config.name.clone()));
+    let dataset_id = dataset.id.clone();
+
+    state.datasets.insert(dataset.id.clone(), dataset.clone());
+
+    info!("Queued sheet extraction {} for async processing", dataset_id);
+
+    state.jobs.start(&jobs::JobRecord {
+        id: dataset_id.clone(),
+        kind: jobs::JobKind::Dataset,
+        source_file: filename.clone(),
+        config_name: config.name.clone(),
+        ocr_provider: ocr.as_ref().map(|(name, _)| name.to_string()),
+        ocr_options: config.ocr_options.clone(),
+        file_url: None,
+        upload,
+        callback_url: callback_url.clone(),
+        prompt_override: None,
+        priority: jobs::Priority::Normal,
+        parent_extraction_id: None,
+        parent_node_id: None,
+        started_at: dataset.extracted_at.clone(),
+    });
+
+    // Spawn background task
+    let bg_state = state.clone();
+    let bg_id = dataset_id.clone();
+    let ocr_provider = ocr.map(|(_, provider)| provider);
+    let quota_slot = job_slot(state, &config.name, admission);
+
+    tokio::spawn(async move {
+        let config_name = config.name.clone();
+        let job = SheetJob {
+            id: bg_id.clone(),
+            filename,
+            config,
+            upload,
+            callback_url,
+        };
+        let meter = Arc::new(openrouter::UsageMeter::default());
+        let run = run_sheet_extraction(&bg_state, job, file_data, ocr_provider);
+        openrouter::metered(meter.clone(), run).await;
+        record_usage(&bg_state, &config_name, &meter);
+        bg_state.jobs.finish(&bg_id);
+        drop(quota_slot);
+    });
+
+    dataset
+}
+
+/// Parameters of a background sheet extraction run.
+struct SheetJob {
+    id: String,
+    filename: String,
+    config: Arc<config::ExtractionConfig>,
+    upload: bool,
+    callback_url: Option<String>,
+}
+
+/// Parse (or OCR) the sheet, discover schemas, persist, upload, and send the callback.
+async fn run_sheet_extraction(
+    bg_state: &AppState,
+    job: SheetJob,
+    file_data: Vec<u8>,
+    ocr_provider: Option<Arc<dyn OcrProvider>>,
+) {
+    let SheetJob {
+        id: bg_id,
+        filename,
+        config: bg_config,
+        upload: bg_upload,
+        callback_url,
+    } = job;
+    let timeouts = config::StageTimeouts::resolve(bg_config.timeouts.as_ref());
+    let timed_out = |stage: std::time::Duration| {
+        anyhow::anyhow!("timed out after {}s", stage.as_secs())
+    };
+
+    // Step 1: Get raw sheets — either direct parse or OCR → table extraction
+    let sheets = if let Some(provider) = ocr_provider {
+        // PDF path: OCR → markdown → extract tables
+        let ocr_input = OcrInput::Bytes {
+            filename: filename.clone(),
+            data: file_data,
+        };
+
+        let options = bg_config.ocr_options.clone().unwrap_or_default();
+        let ocr = provider.process_with_options(&ocr_input, &options);
+        let ocr_result = match tokio::time::timeout(timeouts.ocr, ocr)
+            .await
+            .unwrap_or_else(|_| Err(timed_out(timeouts.ocr)))
+        {
+            Ok(r) => r,
+            Err(e) => {
+                error!("OCR failed for sheet extraction {}: {}", bg_id, e);
+                bg_state.datasets.update(&bg_id, |ds| {
+                    ds.status = ExtractionStatus::Failed;
+                    ds.error = Some(format!("OCR failed: {}", e));
+                });
+                return;
+            }
+        };
+
+        info!(
+            "OCR complete for {}: {} pages, {} chars",
+            bg_id, ocr_result.total_pages, ocr_result.markdown.len()
+        );
+
+        keep_ocr(
+            bg_state,
+            &object_store::dataset_root(&bg_id),
+            &bg_id,
+            &ocr_result,
+        )
+        .await;
+
+        // Debug: dump OCR markdown to disk for inspection (OCR_DEBUG_DUMP=false disables)
+        if std::env::var("OCR_DEBUG_DUMP").map_or(true, |v| v != "false") {
+            let dump_dir = std::path::Path::new("data/debug");
+            let _ = std::fs::create_dir_all(dump_dir);
+            let dump_path = dump_dir.join(format!("{}_ocr.md", bg_id));
+            if let Err(e) = std::fs::write(&dump_path, &ocr_result.markdown) {
+                error!("Failed to dump OCR markdown: {}", e);
+            } else {
+                info!("Dumped OCR markdown to {:?}", dump_path);
+            }
+        }
+
+        match sheet_parser::parse_ocr_markdown(&ocr_result) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("No tables found in OCR output for {}: {}", bg_id, e);
+                bg_state.datasets.update(&bg_id, |ds| {
+                    ds.status = ExtractionStatus::Failed;
+                    ds.error = Some(format!("No tables found in PDF: {}", e));
+                });
+                return;
+            }
+        }
+    } else {
+        // Direct parse: CSV / Excel
+        let dates = sheet_parser::DateStyle::from_config(bg_config.sheet_config.as_ref());
+        match sheet_parser::parse_file(&filename, &file_data, dates) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Sheet parsing failed for {}: {}", bg_id, e);
+                bg_state.datasets.update(&bg_id, |ds| {
+                    ds.status = ExtractionStatus::Failed;
+                    ds.error = Some(format!("Parsing failed: {}", e));
+                });
+                return;
+            }
+        }
+    };
+
+    info!(
+        "Parsed {} sheet(s) for {}: {}",
+        sheets.len(),
+        bg_id,
+        sheets
+            .iter()
+            .map(|s| format!("\"{}\" ({} rows)", s.name, s.rows.len()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    // Step 2: LLM schema discovery
+    let extractor = sheet_extractor::SheetExtractor::new((*bg_state.openrouter).clone());
+    let mut completed = match tokio::time::timeout(
+        timeouts.llm,
+        extractor.extract(&filename, &sheets, &bg_config),
+    )
+    .await
+    .unwrap_or_else(|_| Err(timed_out(timeouts.llm)))
+    {
+        Ok(ext) => ext,
+        Err(e) => {
+            error!("Sheet extraction failed for {}: {}", bg_id, e);
+            bg_state.datasets.update(&bg_id, |ds| {
+                ds.status = ExtractionStatus::Failed;
+                ds.error = Some(format!("Extraction failed: {}", e));
+            });
+            return;
+        }
+    };
+
+    // Preserve original ID and mark completed
+    completed.id = bg_id.clone();
+    completed.status = ExtractionStatus::Completed;
+
+    // Persist to disk
+    if let Err(e) = save_dataset_to_disk(&completed) {
+        error!("Failed to persist dataset {} to disk: {}", bg_id, e);
+    }
+
+    // Upload to storage if requested
+    if bg_upload {
+        upload_dataset(bg_state, &completed, timeouts.upload).await;
+    }
+
+    bg_state.datasets.insert(bg_id.clone(), completed.clone());
+
+    // POST dataset to callback URL if provided
+    if let Some(ref url) = callback_url {
+        info!("Sending callback for {} to {}", bg_id, url);
+        match bg_state.http_client.post(url).json(&completed).send().await {
+            Ok(resp) => info!("Callback for {} returned {}", bg_id, resp.status()),
+            Err(e) => error!("Callback for {} failed: {}", bg_id, e),
+        }
+    }
+    if let Some(ref events) = bg_state.events {
+        events.publish(
+            events::EventKind::DatasetCompleted,
+            &bg_id,
+            &completed,
+            None,
+        );
+    }
+
+    if let Some(sinks) = bg_config.sheet_config.as_ref().map(|s| &s.sinks) {
+        push_to_sinks(bg_state, &completed, sinks, timeouts.upload).await;
+    }
+
+    info!("Sheet extraction complete: {}", bg_id);
+}
+
+/// Push a dataset to each sink in turn and record how it went in the
+/// dataset's `deliveries`, replacing earlier pushes to the same sink.
+async fn push_to_sinks(
+    state: &AppState,
+    dataset: &SheetExtraction,
+    sinks: &[config::SinkConfig],
+    timeout: std::time::Duration,
+) -> Vec<sheet_schema::SinkDelivery> {
+    let mut deliveries = Vec::new();
+    for sink in sinks {
+        deliveries.push(sinks::deliver(&state.http_client, dataset, sink, timeout).await);
+    }
+    if deliveries.is_empty() {
+        return deliveries;
+    }
+
+    let updated = state.datasets.update(&dataset.id, |ds| {
+        for delivery in &deliveries {
+            ds.deliveries.retain(|d| d.sink != delivery.sink);
+            ds.deliveries.push(delivery.clone());
+        }
+        ds.clone()
+    });
+    if let Some(updated) = updated {
+        if let Err(e) = save_dataset_to_disk(&updated) {
+            error!("Failed to persist dataset {} to disk: {}", dataset.id, e);
+        }
+    }
+    deliveries
+}
+
+/// Upload a dataset to storage, queueing it for background sync on failure.
+async fn upload_dataset(state: &AppState, dataset: &SheetExtraction, timeout: std::time::Duration) {
+    let Some(ref storage) = state.storage else {
+        return;
+    };
+    match tokio::time::timeout(timeout, storage.upload_dataset(dataset))
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {}s", timeout.as_secs())))
+    {
+        Ok(()) => info!("Uploaded dataset {} to storage", dataset.id),
+        Err(e) => {
+            error!("Storage upload failed for dataset {}: {}", dataset.id, e);
+            if let Some(ref outbox) = state.outbox {
+                if let Err(e) = outbox.enqueue_dataset(dataset, &e.to_string()) {
+                    error!("Failed to queue dataset {} for background sync: {}", dataset.id, e);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct DatasetSummary {
+    id: String,
+    status: ExtractionStatus,
+    source_file: String,
+    config_name: Option<String>,
+    extracted_at: String,
+    summary: String,
+    schema_count: usize,
+    total_rows: usize,
+}
+
+/// The summary kept next to each in-memory dataset.
+fn dataset_summary(d: &SheetExtraction) -> DatasetSummary {
+    DatasetSummary {
+        id: d.id.clone(),
+        status: d.status.clone(),
+        source_file: d.source_file.clone(),
+        config_name: d.config_name.clone(),
+        extracted_at: d.extracted_at.clone(),
+        summary: d.summary.clone(),
+        schema_count: d.schemas.len(),
+        total_rows: d.schemas.iter().map(|s| s.row_count).sum(),
+    }
+}
+
+/// Try to get a dataset from memory, falling back to storage if configured.
+/// Caches hydrated datasets in memory for subsequent requests.
+async fn get_or_hydrate_dataset(state: &AppState, id: &str) -> Option<SheetExtraction> {
+    // 1. Check in-memory cache (a job running here is never newer elsewhere)
+    let local = state.datasets.get(id);
+    if local
+        .as_ref()
+        .is_some_and(|d| state.shared.is_none() || d.status.is_active())
+    {
+        return local;
+    }
+
+    // 2. Check the snapshot written by the last replica to change it
+    if let Some(ref shared) = state.shared {
+        if let Some(dataset) = shared.snapshot::<SheetExtraction>("dataset", id).await {
+            if !dataset.status.is_active() {
+                state.datasets.refresh(id.to_string(), dataset.clone());
+            }
+            return Some(dataset);
+        }
+    }
+    if local.is_some() {
+        return local;
+    }
+
+    // 3. Fall back to storage
+    if let Some(ref storage) = state.storage {
+        match storage.fetch_dataset(id).await {
+            Ok(Some(dataset)) => {
+                state.datasets.insert(dataset.id.clone(), dataset.clone());
+                info!("Hydrated dataset {} from storage into cache", id);
+                return Some(dataset);
+            }
+            Ok(None) => {
+                debug!("Dataset {} not found in storage", id);
+            }
+            Err(e) => {
+                error!("Failed to fetch dataset {} from storage: {}", id, e);
+            }
+        }
+    }
+
+    None
+}
+
+/// List all datasets (lightweight summaries).
+/// Merges in-memory datasets with storage if configured.
+async fn list_datasets(State(state): State<AppState>) -> Json<Vec<DatasetSummary>> {
+    // Collect in-memory datasets
+    let mut list: Vec<DatasetSummary> = state.datasets.summaries();
+
+    // Merge stored datasets (dedup by ID)
+    if let Some(ref storage) = state.storage {
+        match storage.list_datasets().await {
+            Ok(rows) => {
+                let in_memory_ids: HashSet<String> =
+                    list.iter().map(|d| d.id.clone()).collect();
+                for row in rows {
+                    if !in_memory_ids.contains(&row.id) {
+                        // Parse schema count from JSONB
+                        let schema_count = row
+                            .schemas
+                            .as_array()
+                            .map(|a| a.len())
+                            .unwrap_or(0);
+                        let total_rows: usize = row
+                            .schemas
+                            .as_array()
+                            .map(|arr| {
+                                arr.iter()
+                                    .filter_map(|s| s.get("row_count").and_then(|v| v.as_u64()))
+                                    .sum::<u64>() as usize
+                            })
+                            .unwrap_or(0);
+
+                        list.push(DatasetSummary {
+                            id: row.id,
+                            status: ExtractionStatus::Completed,
+                            source_file: row.source_file,
+                            config_name: row.config_name,
+                            extracted_at: row.extracted_at,
+                            summary: row.summary,
+                            schema_count,
+                            total_rows,
+                        });
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to list datasets from storage: {}", e);
+            }
+        }
+    }
+
+    list.sort_by(|a, b| b.extracted_at.cmp(&a.extracted_at));
+    Json(list)
+}
+
+/// Get a dataset by ID (in-memory + storage fallback).
+async fn get_dataset(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let dataset = get_or_hydrate_dataset(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+    let modified = dataset.modified_at().to_string();
+    Ok(http_cache::json(&headers, dataset, Some(&modified)).await)
+}
+
+#[derive(serde::Deserialize)]
+struct AppendQuery {
+    upload: Option<bool>,
+    strict: Option<bool>,
+}
+
+/// Append the rows of another CSV/Excel file with the same layout to a
+/// completed dataset, matching its sheets to the existing schemas by header.
+/// POST /datasets/:id/append?strict=true rejects the file on any drift.
+async fn append_dataset(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<AppendQuery>,
+    multipart: Option<Multipart>,
+) -> Result<Json<dataset_append::AppendReport>, ApiError> {
+    let dataset = get_or_hydrate_dataset(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+    if dataset.status != ExtractionStatus::Completed {
+        return Err(ApiError::Conflict(format!(
+            "Dataset {} is not completed; append after its extraction finishes",
+            id
+        )));
+    }
+
+    let input = read_file_input(multipart, None, upload::Accept::Sheet).await?;
+    let file_data = input.bytes().await?;
+    let filename = input.filename;
+    if filename.to_lowercase().ends_with(".pdf") {
+        return Err(ApiError::BadRequest(
+            "Append supports CSV and Excel files; extract PDFs with /extract-sheet".to_string(),
+        ));
+    }
+
+    let config = dataset.config_name.as_deref().and_then(|name| state.configs.get(name));
+    let dates = sheet_parser::DateStyle::from_config(
+        config.as_ref().and_then(|c| c.sheet_config.as_ref()),
+    );
+    let sheets = sheet_parser::parse_file(&filename, &file_data, dates)
+        .map_err(|e| ApiError::Unprocessable(format!("Parsing failed: {}", e)))?;
+
+    // Applied under the map's lock so concurrent appends don't drop rows
+    let now = schema::now_iso8601();
+    let strict = query.strict.unwrap_or(false);
+    let (report, updated) = state
+        .datasets
+        .update(&id, |ds| {
+            let mut next = ds.clone();
+            let report = dataset_append::append_sheets(&mut next, &filename, &sheets, &now)
+                .map_err(|e| ApiError::Unprocessable(e.to_string()))?;
+            if strict && report.has_drift() {
+                return Err(ApiError::Conflict(format!(
+                    "{} differs from the dataset's schemas: {}",
+                    filename,
+                    serde_json::to_string(&report).unwrap_or_default()
+                )));
+            }
+            *ds = next;
+            Ok((report, ds.clone()))
+        })
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))??;
+
+    info!(
+        "Appended {} to dataset {}: {}",
+        filename,
+        id,
+        report
+            .appended
+            .iter()
+            .map(|a| format!("{} rows to \"{}\"", a.rows[1] - a.rows[0], a.schema))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    if let Err(e) = save_dataset_to_disk(&updated) {
+        error!("Failed to persist dataset {} to disk: {}", id, e);
+    }
+    if query.upload.unwrap_or(true) {
+        let timeouts =
+            config::StageTimeouts::resolve(config.as_ref().and_then(|c| c.timeouts.as_ref()));
+        upload_dataset(&state, &updated, timeouts.upload).await;
+    }
+
+    Ok(Json(report))
+}
+
+/// Push a completed dataset to its config's sinks again, e.g. after a
+/// failed delivery or a manual correction.
+/// POST /datasets/:id/push
+async fn push_dataset(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<sheet_schema::SinkDelivery>>, ApiError> {
+    let dataset = get_or_hydrate_dataset(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+    if dataset.status != ExtractionStatus::Completed {
+        return Err(ApiError::Conflict(format!(
+            "Dataset {} is not completed; push it after its extraction finishes",
+            id
+        )));
+    }
+    let config = dataset
+        .config_name
+        .as_deref()
+        .and_then(|name| state.configs.get(name));
+    let sinks = config
+        .as_ref()
+        .and_then(|c| c.sheet_config.as_ref())
+        .map(|s| s.sinks.as_slice())
+        .unwrap_or_default();
+    if sinks.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "Dataset {}'s config has no sheet_config.sinks",
+            id
+        )));
+    }
+
+    let timeouts =
+        config::StageTimeouts::resolve(config.as_ref().and_then(|c| c.timeouts.as_ref()));
+    Ok(Json(
+        push_to_sinks(&state, &dataset, sinks, timeouts.upload).await,
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct RowEditQuery {
+    schema_name: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct RowUpdate {
+    row: serde_json::Value,
+    edit: sheet_schema::RowEdit,
+}
+
+/// Correct values in one row of a dataset schema, keeping what they were in
+/// the schema's `edits`.
+/// PATCH /datasets/:id/rows/:row_index?schema_name=...
+async fn update_dataset_row(
+    State(state): State<AppState>,
+    Path((id, row_index)): Path<(String, usize)>,
+    Query(query): Query<RowEditQuery>,
+    headers: HeaderMap,
+    Json(correction): Json<dataset_edit::RowCorrection>,
+) -> Result<Json<RowUpdate>, ApiError> {
+    let reviewer = reviewer_from(correction.reviewer.clone(), &headers)?;
+    let now = schema::now_iso8601();
+    let (update, updated) = edit_dataset_row(&state, &id, row_index, query, |schema| {
+        let edit = dataset_edit::update_row(schema, row_index, &correction.values, reviewer, &now)
+            .map_err(ApiError::BadRequest)?;
+        let row = schema.rows[row_index].clone();
+        Ok(RowUpdate { row, edit })
+    })
+    .await?;
+
+    info!(
+        "Dataset {} row {} corrected by {}",
+        id, row_index, update.edit.reviewer
+    );
+    persist_edited_dataset(&state, &updated).await;
+    Ok(Json(update))
+}
+
+/// Delete one row of a dataset schema, keeping it in the schema's `edits`.
+/// The reviewer comes from the `X-Reviewer` header.
+/// DELETE /datasets/:id/rows/:row_index?schema_name=...
+async fn delete_dataset_row(
+    State(state): State<AppState>,
+    Path((id, row_index)): Path<(String, usize)>,
+    Query(query): Query<RowEditQuery>,
+    headers: HeaderMap,
+) -> Result<Json<sheet_schema::RowEdit>, ApiError> {
+    let reviewer = reviewer_from(None, &headers)?;
+    let now = schema::now_iso8601();
+    let (edit, updated) = edit_dataset_row(&state, &id, row_index, query, |schema| {
+        Ok(dataset_edit::delete_row(schema, row_index, reviewer, &now))
+    })
+    .await?;
+
+    info!(
+        "Dataset {} row {} deleted by {}",
+        id, row_index, edit.reviewer
+    );
+    persist_edited_dataset(&state, &updated).await;
+    Ok(Json(edit))
+}
+
+/// Run `edit` on the schema named in `query` under the map's lock, once the
+/// row is known to exist. Returns its result and the updated dataset.
+async fn edit_dataset_row<R>(
+    state: &AppState,
+    id: &str,
+    row_index: usize,
+    query: RowEditQuery,
+    edit: impl FnOnce(&mut sheet_schema::DataSchema) -> Result<R, ApiError>,
+) -> Result<(R, SheetExtraction), ApiError> {
+    let schema_name = query.schema_name.filter(|s| !s.is_empty()).ok_or_else(|| {
+        ApiError::BadRequest("schema_name query parameter is required".to_string())
+    })?;
+    let dataset = get_or_hydrate_dataset(state, id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+    if dataset.status != ExtractionStatus::Completed {
+        return Err(ApiError::Conflict(format!(
+            "Dataset {} is not completed; edit rows after its extraction finishes",
+            id
+        )));
+    }
+
+    state
+        .datasets
+        .update(id, |ds| {
+            let schema = ds
+                .schemas
+                .iter_mut()
+                .find(|s| s.name == schema_name)
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!("Schema '{}' not found in dataset", schema_name))
+                })?;
+            if row_index >= schema.rows.len() {
+                return Err(ApiError::NotFound(format!(
+                    "Row {} not found in schema '{}' ({} rows)",
+                    row_index,
+                    schema_name,
+                    schema.rows.len()
+                )));
+            }
+            let result = edit(schema)?;
+            Ok((result, ds.clone()))
+        })
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?
+}
+
+/// Save a dataset changed by hand to disk and storage.
+async fn persist_edited_dataset(state: &AppState, dataset: &SheetExtraction) {
+    if let Err(e) = save_dataset_to_disk(dataset) {
+        error!("Failed to persist dataset {} to disk: {}", dataset.id, e);
+    }
+    let config = dataset
+        .config_name
+        .as_deref()
+        .and_then(|name| state.configs.get(name));
+    let timeouts =
+        config::StageTimeouts::resolve(config.as_ref().and_then(|c| c.timeouts.as_ref()));
+    upload_dataset(state, dataset, timeouts.upload).await;
+}
+
+#[derive(serde::Deserialize)]
+struct DatasetRowsQuery {
+    schema_name: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// Query rows from a specific schema within a dataset (paginated).
+/// GET /datasets/:id/rows?schema_name=...&offset=0&limit=100
+async fn get_dataset_rows(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DatasetRowsQuery>,
+) -> Result<Json<Vec<serde_json::Value>>, ApiError> {
+    let schema_name = query.schema_name.as_deref().unwrap_or("");
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100);
+
+    if schema_name.is_empty() {
+        return Err(ApiError::BadRequest(
+            "schema_name query parameter is required".to_string(),
+        ));
+    }
+
+    // 1. Try in-memory
+    let in_memory = state.datasets.with(&id, |dataset| {
+        let schema = dataset.schemas.iter().find(|s| s.name == schema_name)?;
+        Some(
+            schema
+                .rows
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect::<Vec<serde_json::Value>>(),
+        )
+    });
+    match in_memory {
+        Some(Some(rows)) => return Ok(Json(rows)),
+        Some(None) => {
+            return Err(ApiError::NotFound(format!(
+                "Schema '{}' not found in dataset",
+                schema_name
+            )))
+        }
+        None => {}
+    }
+
+    // 2. Fall back to storage
+    if let Some(ref storage) = state.storage {
+        match storage
+            .query_dataset_rows(&id, schema_name, offset, limit)
+            .await
+        {
+            Ok(rows) => return Ok(Json(rows)),
+            Err(e) => {
+                error!(
+                    "Failed to query dataset rows from storage: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    Err(ApiError::NotFound(format!("Dataset {} not found", id)))
+}
+
+#[derive(serde::Deserialize)]
+struct DatasetAggregateQuery {
+    schema_name: Option<String>,
+    /// Comma-separated column names to group by (omit for a single total group)
+    group_by: Option<String>,
+    /// Comma-separated aggregates, e.g. `sum:valor,count` (default: `count`)
+    agg: Option<String>,
+}
+
+/// Compute grouped aggregates over a schema's rows.
+/// GET /datasets/:id/aggregate?schema_name=...&group_by=categoria&agg=sum:valor,count
+async fn aggregate_dataset(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DatasetAggregateQuery>,
+) -> Result<Json<dataset_query::AggregateResult>, ApiError> {
+    let schema_name = query.schema_name.as_deref().unwrap_or("");
+    if schema_name.is_empty() {
+        return Err(ApiError::BadRequest(
+            "schema_name query parameter is required".to_string(),
+        ));
+    }
+
+    let specs = dataset_query::parse_agg_specs(query.agg.as_deref().unwrap_or("count"))
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
+    let group_by: Vec<String> = query
+        .group_by
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+
+    let dataset = get_or_hydrate_dataset(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+
+    let schema = dataset
+        .schemas
+        .iter()
+        .find(|s| s.name == schema_name)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Schema '{}' not found in dataset", schema_name))
+        })?;
+
+    // Reject columns the schema doesn't define so typos don't silently group by null
+    let known: HashSet<&str> = schema.columns.iter().map(|c| c.name.as_str()).collect();
+    let referenced = group_by
+        .iter()
+        .map(String::as_str)
+        .chain(specs.iter().filter_map(|s| s.column.as_deref()));
+    for col in referenced {
+        if !known.contains(col) {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown column '{}' in schema '{}'",
+                col, schema_name
+            )));
+        }
+    }
+
+    let groups = dataset_query::aggregate(&schema.rows, &group_by, &specs);
+
+    Ok(Json(dataset_query::AggregateResult {
+        schema_name: schema_name.to_string(),
+        group_by,
+        groups,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+struct DatasetJoinQuery {
+    /// Relationship index (0-based) or its `from` column ref (e.g. `transacoes.conta_id`)
+    relationship: Option<String>,
+    /// Dataset holding the `to` schema when it isn't part of this dataset
+    target_dataset: Option<String>,
+    /// `left` (default) or `inner`
+    join: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// Materialize joined rows across the two schemas of a declared relationship.
+/// GET /datasets/:id/joined?relationship=0&join=left&offset=0&limit=100
+async fn get_joined_rows(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<DatasetJoinQuery>,
+) -> Result<Json<dataset_query::JoinedRows>, ApiError> {
+    let selector = query.relationship.as_deref().unwrap_or("");
+    if selector.is_empty() {
+        return Err(ApiError::BadRequest(
+            "relationship query parameter is required".to_string(),
+        ));
+    }
+
+    let join_name = query.join.as_deref().unwrap_or("left");
+    let join = dataset_query::JoinKind::from_str(join_name).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "Unknown join: '{}'. Available: left, inner",
+            join_name
+        ))
+    })?;
+
+    let dataset = get_or_hydrate_dataset(&state, &id)
+        .await
+        .ok_or_else(|| ApiError::NotFound(format!("Dataset {} not found", id)))?;
+
+    let relationship = match selector.parse::<usize>() {
+        Ok(idx) => dataset.relationships.get(idx),
+        Err(_) => dataset.relationships.iter().find(|r| r.from == selector),
+    }
+    .cloned()
+    .ok_or_else(|| {
+        ApiError::NotFound(format!(
+            "Relationship '{}' not found in dataset ({} declared)",
+            selector,
+            dataset.relationships.len()
+        ))
+    })?;
+
+    let malformed = |r: &str| {
+        ApiError::Unprocessable(format!(
+            "Relationship endpoint '{}' is not in schema.column form",
+            r
+        ))
+    };
+    let (from_schema, from_col) = dataset_query::parse_column_ref(&relationship.from)
+        .ok_or_else(|| malformed(&relationship.from))?;
+    let (to_schema, to_col) = dataset_query::parse_column_ref(&relationship.to)
+        .ok_or_else(|| malformed(&relationship.to))?;
+
+    let from = dataset
+        .schemas
+        .iter()
+        .find(|s| s.name == from_schema)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Schema '{}' not found in dataset", from_schema))
+        })?;
+
+    // The `to` side lives in this dataset unless a target dataset is given
+    let target = match query.target_dataset.as_deref() {
+        Some(target_id) if target_id != id => Some(
+            get_or_hydrate_dataset(&state, target_id)
+                .await
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!("Target dataset {} not found", target_id))
+                })?,
+        ),
+        _ => None,
+    };
+    let to = target
+        .as_ref()
+        .unwrap_or(&dataset)
+        .schemas
+        .iter()
+        .find(|s| s.name == to_schema)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Schema '{}' not found{}",
+                to_schema,
+                if target.is_some() {
+                    " in target dataset"
+                } else {
+                    " in dataset (pass target_dataset to join across datasets)"
+                }
+            ))
+        })?;
+
+    let joined = dataset_query::join_rows(&from.rows, from_col, &to.rows, to_col, join);
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(100);
+    let total = joined.len();
+    let rows = joined.into_iter().skip(offset).take(limit).collect();
+
+    Ok(Json(dataset_query::JoinedRows {
+        relationship,
+        join,
+        target_dataset: target.map(|t| t.id),
+        total,
+        offset,
+        limit,
+        rows,
+    }))
+}
+
+// ============================================================================
+// Estimate handlers
+// ============================================================================
+
+#[derive(serde::Deserialize)]
+struct EstimateQuery {
+    /// Comma-separated config names (default: the default document config)
+    config: Option<String>,
+    /// Comma-separated model ids (default: the server's model)
+    model: Option<String>,
+    ocr: Option<bool>,
+    ocr_provider: Option<String>,
+    file_url: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct EstimateResponse {
+    source_file: String,
+    total_pages: u32,
+    chars: usize,
+    /// `pdf_page_count` (text size guessed from pages) or `ocr` (measured)
+    method: &'static str,
+    ocr_provider: String,
+    estimates: Vec<estimate::Estimate>,
+}
+
+/// Estimate tokens, LLM cost, and processing time before extracting a document.
+///
+/// Query params:
+///   - `config` — config names, comma-separated (default: the default document config)
+///   - `model` — model ids, comma-separated (default: the server's model)
+///   - `ocr` — run OCR for exact text size and OCR time (default: false, count PDF pages)
+///   - `ocr_provider` — `docling` (default), `mistral_ocr`, or `smol_docling`
+///   - `file_url` — read the file from this URL instead of multipart upload
+async fn estimate_document(
+    State(state): State<AppState>,
+    Query(query): Query<EstimateQuery>,
+    multipart: Option<Multipart>,
+) -> Result<Json<EstimateResponse>, ApiError> {
+    let configs = match query.config.as_deref() {
+        Some(names) => names
+            .split(',')
+            .map(|name| requested_config(&state, Some(name.trim()), ConfigKind::Document))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![requested_config(&state, None, ConfigKind::Document)?],
+    };
+    let models: Vec<String> = match query.model.as_deref() {
+        Some(models) => models.split(',').map(|m| m.trim().to_string()).collect(),
+        None => vec![state.openrouter.model().to_string()],
+    };
+    let prices = estimate::Prices::from_env().map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let provider_name = query.ocr_provider.as_deref().unwrap_or("docling");
+    let provider_kind = OcrProviderKind::from_str(provider_name).ok_or_else(|| {
+        ApiError::BadRequest(format!(
+            "Unknown ocr_provider: '{}'. Available: docling, mistral_ocr, smol_docling",
+            provider_name
+        ))
+    })?;
+
+    let FileInput { filename, file, .. } = read_file_input(
+        multipart,
+        query.file_url.as_deref(),
+        upload::Accept::Document,
+    )
+    .await?;
+    let (size, method, ocr_secs) = if query.ocr.unwrap_or(false) {
+        let provider = state
+            .ocr_providers
+            .get(&provider_kind)
+            .ok_or_else(|| ApiError::ProviderUnconfigured(provider_name.to_string()))?;
+        let ocr_input = match (&query.file_url, file) {
+            (Some(url), _) => OcrInput::Url {
+                filename: filename.clone(),
+                url: url.clone(),
+            },
+            (None, file) => OcrInput::File {
+                filename: filename.clone(),
+                file: file.expect("read_file_input returns a file without file_url"),
+            },
+        };
+        // Estimates are quick checks, so the strictest configured OCR timeout applies
+        let timeout = configs
+            .iter()
+            .map(|c| config::StageTimeouts::resolve(c.timeouts.as_ref()).ocr)
+            .min()
+            .unwrap_or_default();
+        let started = std::time::Instant::now();
+        let ocr = match tokio::time::timeout(timeout, provider.process(&ocr_input)).await {
+            Ok(result) => result.map_err(|e| ApiError::OcrFailed(e.to_string()))?,
+            Err(_) => return Err(ApiError::OcrTimeout(timeout.as_secs())),
+        };
+        let size = estimate::DocumentSize {
+            pages: ocr.total_pages,
+            chars: ocr.markdown.len(),
+            text: Some(ocr.markdown),
+        };
+        (size, "ocr", Some(started.elapsed().as_secs_f64()))
+    } else {
+        let data = match (&query.file_url, file) {
+            (Some(url), _) => download(&state.http_client, url).await?,
+            (None, file) => read_spooled(file.as_ref()).await?,
+        };
+        let pages =
+            estimate::count_pages(&data).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        (
+            estimate::DocumentSize::from_pages(pages),
+            "pdf_page_count",
+            None,
+        )
+    };
+    info!(
+        "Estimate for {}: {} pages, {} chars ({})",
+        filename, size.pages, size.chars, method
+    );
+
+    let ocr_secs = ocr_secs.unwrap_or_else(|| estimate::ocr_secs(provider_kind, size.pages));
+    let estimates = configs
+        .iter()
+        .flat_map(|config| {
+            models
+                .iter()
+                .map(|model| estimate::estimate(&size, config, model, &prices, ocr_secs))
+        })
+        .collect();
+
+    Ok(Json(EstimateResponse {
+        source_file: filename,
+        total_pages: size.pages,
+        chars: size.chars,
+        method,
+        ocr_provider: provider_name.to_string(),
+        estimates,
+    }))
+}
+
+/// Fetch a file for handlers that need its bytes rather than a URL.
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, ApiError> {
+    let fail = |e: String| ApiError::Upstream(format!("Failed to download {}: {}", url, e));
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| fail(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(fail(response.status().to_string()));
+    }
+    let bytes = response.bytes().await.map_err(|e| fail(e.to_string()))?;
+    Ok(bytes.to_vec())
+}
+
+// ============================================================================
+// Evaluation handlers
+// ============================================================================
+
+/// List golden cases.
+async fn list_goldens(State(state): State<AppState>) -> Json<Vec<eval::GoldenSummary>> {
+    Json(state.eval.goldens().iter().map(|c| c.summary()).collect())
+}
+
+#[derive(serde::Deserialize)]
+struct GoldenRequest {
+    extraction_id: String,
+    /// Case name (defaults to the readable ID, then the extraction ID)
+    name: Option<String>,
+}
+
+/// Save an extraction's current tree (typically after review) as a golden case.
+async fn create_golden(
+    State(state): State<AppState>,
+    Json(req): Json<GoldenRequest>,
+) -> Result<Json<eval::GoldenSummary>, ApiError> {
+    let extraction = get_or_hydrate_extraction(&state, &req.extraction_id)
+        .await
+        .ok_or(ApiError::NotFound(format!(
+            "Extraction {} not found",
+            req.extraction_id
+        )))?;
+    if extraction.status != ExtractionStatus::Completed {
+        return Err(ApiError::Conflict(format!(
+            "Extraction {} has not completed",
+            extraction.id
+        )));
+    }
+    let ocr = extraction_ocr(&state, &extraction)
+        .await
+        .ok_or(ApiError::BadRequest(format!(
+            "No OCR text available for {}: neither archived OCR output nor node content",
+            extraction.id
+        )))?;
+
+    let case = eval::GoldenCase {
+        name: req
+            .name
+            .or_else(|| extraction.readable_id.clone())
+            .unwrap_or_else(|| extraction.id.clone()),
+        created_at: schema::now_iso8601(),
+        extraction_id: extraction.id.clone(),
+        source_file: extraction.source_file.clone(),
+        config_name: extraction.config_name.clone(),
+        ocr,
+        nodes: extraction.children,
+        relationships: extraction.relationships,
+    };
+    state
+        .eval
+        .save_golden(&case)
+        .map_err(|e| ApiError::Internal(format!("Failed to save golden case: {}", e)))?;
+    info!(
+        "Saved golden case {} from {}",
+        case.name, case.extraction_id
+    );
+    Ok(Json(case.summary()))
+}
+
+/// OCR for an extraction: the kept OCR output if there is one, otherwise the
+/// pages recovered from node content (which carry no page scores).
+async fn extraction_ocr(
+    state: &AppState,
+    extraction: &Extraction,
+) -> Option<object_store::StoredOcr> {
+    match load_ocr(state, &object_store::extraction_root(&extraction.id)).await {
+        Ok(Some(stored)) => return Some(stored),
+        Ok(None) => {}
+        Err(e) => warn!("Archived OCR for {} is unusable: {}", extraction.id, e),
+    }
+
+    fn collect(
+        store: &ContentStore,
+        nodes: &[schema::DocumentNode],
+        pages: &mut std::collections::BTreeMap<u32, String>,
+    ) {
+        for node in nodes {
+            if let Some(content) = node.content_ref.as_deref().and_then(|r| store.get_full(r)) {
+                for page in extractor::pages_from_content(&content) {
+                    pages.entry(page.page_num).or_insert(page.text);
+                }
+            }
+            collect(store, &node.children, pages);
+        }
+    }
+    if let Err(e) = hydrate_content(state, extraction).await {
+        warn!(
+            "No node content to recover OCR text for {}: {}",
+            extraction.id, e
+        );
+    }
+    let mut pages = std::collections::BTreeMap::new();
+    collect(&state.content_store, &extraction.children, &mut pages);
+    if pages.is_empty() {
+        return None;
+    }
+
+    let pages: Vec<object_store::StoredOcrPage> = pages
+        .into_iter()
+        .map(|(page_num, text)| object_store::StoredOcrPage {
+            page_num,
+            text,
+            confidence: None,
+            pdf_page: None,
+        })
+        .collect();
+    Some(object_store::StoredOcr {
+        extraction_id: extraction.id.clone(),
+        provider: "content_store".to_string(),
+        total_pages: extraction
+            .total_pages
+            .unwrap_or_else(|| pages.last().map_or(0, |p| p.page_num)),
+        ocr_confidence: 1.0,
+        markdown: pages
+            .iter()
+            .map(|p| p.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        pages,
+    })
+}
+
+/// Delete a golden case.
+async fn delete_golden(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match state.eval.delete_golden(&name) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound(format!(
+            "Golden case {} not found",
+            name
+        ))),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct EvalQuery {
+    config: Option<String>,
+    /// OpenRouter model (defaults to the server's)
+    model: Option<String>,
+    /// Comma-separated case names (default: all)
+    cases: Option<String>,
+}
+
+/// Re-extract the golden cases with a config and model and score the results.
+/// Cases run one after another; the report is saved and returned.
+async fn run_eval(
+    State(state): State<AppState>,
+    Query(query): Query<EvalQuery>,
+) -> Result<Json<eval::EvalReport>, ApiError> {
+    let config = requested_config(&state, query.config.as_deref(), ConfigKind::Document)?;
+    check_budget(&state, &config)?;
+
+    let wanted: Option<HashSet<&str>> = query
+        .cases
+        .as_deref()
+        .map(|c| c.split(',').map(str::trim).collect());
+    let cases: Vec<eval::GoldenCase> = state
+        .eval
+        .goldens()
+        .into_iter()
+        .filter(|c| wanted.as_ref().is_none_or(|w| w.contains(c.name.as_str())))
+        .collect();
+    if cases.is_empty() {
+        return Err(ApiError::BadRequest(
+            "No golden cases to run; save some with POST /eval/goldens".to_string(),
+        ));
+    }
+
+    let client = match query.model {
+        Some(model) => (*state.openrouter).clone().with_model(model),
+        None => (*state.openrouter).clone(),
+    };
+    let model = client.model().to_string();
+    // Eval extractions are never served, so their content goes to a throwaway store
+    let extractor = Extractor::new(client, ContentStore::new());
+    let timeouts = config::StageTimeouts::resolve(config.timeouts.as_ref());
+    info!(
+        "Eval run: {} case(s) with config {} and model {}",
+        cases.len(),
+        config.name,
+        model
+    );
+
+    let mut results = Vec::new();
+    let meter = Arc::new(openrouter::UsageMeter::default());
+    for case in cases {
+        let started = std::time::Instant::now();
+        let ocr = case.ocr.clone().into_ocr_result();
+        let outcome = tokio::time::timeout(
+            timeouts.llm,
+            openrouter::metered(
+                meter.clone(),
+                extractor.structure(&case.source_file, &ocr, &config),
+            ),
+        )
+        .await;
+        let (scores, error) = match outcome {
+            Ok(Ok(extraction)) => (
+                eval::compare(
+                    &case.nodes,
+                    &case.relationships,
+                    &extraction.children,
+                    &extraction.relationships,
+                ),
+                None,
+            ),
+            Ok(Err(e)) => (eval::Comparison::default(), Some(e.to_string())),
+            Err(_) => (
+                eval::Comparison::default(),
+                Some(format!("timed out after {}s", timeouts.llm.as_secs())),
+            ),
+        };
+        if let Some(ref e) = error {
+            warn!("Eval case {} failed: {}", case.name, e);
+        }
+        results.push(eval::CaseResult {
+            case: case.name,
+            scores,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+
+    record_usage(&state, &config.name, &meter);
+
+    let report = eval::EvalReport::new(config.name.clone(), model, results);
+    if let Err(e) = state.eval.save_report(&report) {
+        error!("Failed to save eval report {}: {}", report.id, e);
+    }
+    info!(
+        "Eval {}: boundaries F1 {}, types F1 {}, relationships F1 {} ({} failed)",
+        report.id,
+        report.totals.boundaries.f1,
+        report.totals.types.f1,
+        report.totals.relationships.f1,
+        report.failed
+    );
+    Ok(Json(report))
+}
+
+#[derive(serde::Deserialize)]
+struct EvalReportsQuery {
+    config: Option<String>,
+    model: Option<String>,
+}
+
+/// Compare eval runs: one row per report, newest first.
+async fn list_eval_reports(
+    State(state): State<AppState>,
+    Query(query): Query<EvalReportsQuery>,
+) -> Json<Vec<eval::ReportSummary>> {
+    Json(
+        state
+            .eval
+            .reports()
+            .iter()
+            .filter(|r| query.config.as_ref().is_none_or(|c| &r.config == c))
+            .filter(|r| query.model.as_ref().is_none_or(|m| &r.model == m))
+            .map(|r| r.summary())
+            .collect(),
+    )
+}
+
+/// A full eval report with per-case scores.
+async fn get_eval_report(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<eval::EvalReport>, ApiError> {
+    match state.eval.report(&id) {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err(ApiError::NotFound(format!("Eval report {} not found", id))),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ExperimentQuery {
+    /// Config of variant a (default: the default document config)
+    config_a: Option<String>,
+    /// Config of variant b (default: `config_a`)
+    config_b: Option<String>,
+    /// OpenRouter models (default: the server's)
+    model_a: Option<String>,
+    model_b: Option<String>,
+    ocr_provider: Option<String>,
+    file_url: Option<String>,
+}
+
+/// Extract one document with two configs or models and compare the trees.
+///
+/// OCR runs once, with variant a's `ocr_options` and OCR timeout; the two
+/// structure calls then run concurrently. Nothing is stored: the response
+/// carries both extractions (node content is not kept) and the comparison.
+async fn run_experiment(
+    State(state): State<AppState>,
+    Query(query): Query<ExperimentQuery>,
+    multipart: Option<Multipart>,
+) -> Result<Json<experiment::ExperimentReport>, ApiError> {
+    let config_a = requested_config(&state, query.config_a.as_deref(), ConfigKind::Document)?;
+    let config_b = match query.config_b.as_deref() {
+        Some(name) => requested_config(&state, Some(name), ConfigKind::Document)?,
+        None => config_a.clone(),
+    };
+    let [client_a, client_b] = [&query.model_a, &query.model_b].map(|model| match model {
+        Some(model) => (*state.openrouter).clone().with_model(model.clone()),
+        None => (*state.openrouter).clone(),
+    });
+    if config_a.name == config_b.name && client_a.model() == client_b.model() {
+        return Err(ApiError::BadRequest(
+            "Variants a and b are the same; set config_b or model_b".to_string(),
+        ));
+    }
+    check_budget(&state, &config_a)?;
+    check_budget(&state, &config_b)?;
+
+    let provider_name = query.ocr_provider.as_deref().unwrap_or("docling");
+    let provider = OcrProviderKind::from_str(provider_name)
+        .and_then(|kind| state.ocr_providers.get(&kind))
+        .cloned()
+        .ok_or_else(|| ApiError::ProviderUnconfigured(provider_name.to_string()))?;
+
+    let FileInput { filename, file, .. } = read_file_input(
+        multipart,
+        query.file_url.as_deref(),
+        upload::Accept::Document,
+    )
+    .await?;
+    let ocr_input = match (&query.file_url, file) {
+        (Some(url), _) => OcrInput::Url {
+            filename: filename.clone(),
+            url: url.clone(),
+        },
+        (None, file) => OcrInput::File {
+            filename: filename.clone(),
+            file: file.expect("read_file_input returns a file without file_url"),
+        },
+    };
+
+    let started = std::time::Instant::now();
+    let timeout = config::StageTimeouts::resolve(config_a.timeouts.as_ref()).ocr;
+    let options = config_a.ocr_options.clone().unwrap_or_default();
+    let ocr = provider.process_with_options(&ocr_input, &options);
+    let ocr = match tokio::time::timeout(timeout, ocr).await {
+        Ok(result) => result.map_err(|e| ApiError::OcrFailed(e.to_string()))?,
+        Err(_) => return Err(ApiError::OcrTimeout(timeout.as_secs())),
+    };
+    let ocr_ms = started.elapsed().as_millis() as u64;
+    info!(
+        "Experiment on {}: {} ({}) vs {} ({})",
+        filename,
+        config_a.name,
+        client_a.model(),
+        config_b.name,
+        client_b.model()
+    );
+
+    let [meter_a, meter_b] = [(); 2].map(|_| Arc::new(openrouter::UsageMeter::default()));
+    let (a, b) = tokio::join!(
+        openrouter::metered(
+            meter_a.clone(),
+            run_variant(client_a, &config_a, &filename, &ocr)
+        ),
+        openrouter::metered(
+            meter_b.clone(),
+            run_variant(client_b, &config_b, &filename, &ocr)
+        ),
+    );
+    record_usage(&state, &config_a.name, &meter_a);
+    record_usage(&state, &config_b.name, &meter_b);
+    let comparison = match (&a.extraction, &b.extraction) {
+        (Some(a), Some(b)) => Some(experiment::compare(a, b)),
+        _ => None,
+    };
+    Ok(Json(experiment::ExperimentReport {
+        id: format!("exp_{}", uuid::Uuid::new_v4().simple()),
+        run_at: schema::now_iso8601(),
+        source_file: filename,
+        total_pages: ocr.total_pages,
+        ocr_provider: ocr.provider_name.clone(),
+        ocr_ms,
+        a,
+        b,
+        comparison,
+    }))
+}
+
+/// Run the structure call of one experiment variant.
+async fn run_variant(
+    client: openrouter::OpenRouterClient,
+    config: &config::ExtractionConfig,
+    filename: &str,
+    ocr: &ocr::OcrResult,
+) -> experiment::VariantResult {
+    let model = client.model().to_string();
+    // Experiment extractions are never served, so their content goes to a throwaway store
+    let extractor = Extractor::new(client, ContentStore::new());
+    let timeout = config::StageTimeouts::resolve(config.timeouts.as_ref()).llm;
+    let started = std::time::Instant::now();
+    let (extraction, error) =
+        match tokio::time::timeout(timeout, extractor.structure(filename, ocr, config)).await {
+            Ok(Ok(extraction)) => (Some(extraction), None),
+            Ok(Err(e)) => (None, Some(e.to_string())),
+            Err(_) => (
+                None,
+                Some(format!("timed out after {}s", timeout.as_secs())),
+            ),
+        };
+    if let Some(ref e) = error {
+        warn!(
+            "Experiment variant {} ({}) failed: {}",
+            config.name, model, e
+        );
+    }
+    experiment::VariantResult {
+        config: config.name.clone(),
+        config_version: scheduler::fingerprint(config),
+        model,
+        duration_ms: started.elapsed().as_millis() as u64,
+        extraction,
+        error,
+    }
+}
+
+// ============================================================================
+// Shared helpers
+// ============================================================================
+
+/// An uploaded file and the other multipart text fields sent with it.
+struct FileInput {
+    filename: String,
+    /// The upload, spooled to disk; `None` for `file_url` input (OCR
+    /// providers fetch the URL themselves)
+    file: Option<ocr::SpooledFile>,
+    /// Bytes uploaded
+    size: u64,
+    /// Text fields other than `file`, e.g. `prompt_override`
+    fields: HashMap<String, String>,
+}
+
+impl FileInput {
+    /// Read the upload back into memory, for handlers that parse it.
+    async fn bytes(&self) -> Result<Vec<u8>, ApiError> {
+        read_spooled(self.file.as_ref()).await
+    }
+}
+
+async fn read_spooled(file: Option<&ocr::SpooledFile>) -> Result<Vec<u8>, ApiError> {
+    let Some(file) = file else {
+        return Ok(Vec::new());
+    };
+    file.read()
+        .await
+        .map_err(|e| ApiError::Internal(format!("Failed to read upload: {}", e)))
+}
+
+/// Read file data from either a multipart upload or a URL parameter.
+/// Uploads are written to a spool file chunk by chunk as they arrive, then
+/// checked against what the endpoint `accept`s.
+async fn read_file_input(
+    multipart: Option<Multipart>,
+    file_url: Option<&str>,
+    accept: upload::Accept,
+) -> Result<FileInput, ApiError> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut input = FileInput {
+        filename: String::new(),
+        file: None,
+        size: 0,
+        fields: HashMap::new(),
+    };
+    let mut content_type = None;
+    let (mut head, mut tail) = (Vec::new(), Vec::new());
+    let has_multipart = multipart.is_some();
+    if let Some(mut multipart) = multipart {
+        while let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| ApiError::BadRequest(format!("Multipart error: {}", e)))?
+        {
+            let Some(name) = field.name().map(str::to_string) else {
+                continue;
+            };
+            if name == "file" {
+                input.filename = field.file_name().unwrap_or("document").to_string();
+                content_type = field.content_type().map(str::to_string);
+                let spool_failed = |e: std::io::Error| {
+                    ApiError::Internal(format!("Failed to spool upload: {}", e))
+                };
+                let (spooled, mut out) = ocr::SpooledFile::create().await.map_err(spool_failed)?;
+                let mut size = 0;
+                while let Some(chunk) = field
+                    .chunk()
+                    .await
+                    .map_err(|e| ApiError::BadRequest(format!("Failed to read file: {}", e)))?
+                {
+                    size += chunk.len() as u64;
+                    out.write_all(&chunk).await.map_err(spool_failed)?;
+                    let room = upload::PROBE_BYTES.saturating_sub(head.len());
+                    head.extend_from_slice(&chunk[..room.min(chunk.len())]);
+                    tail.extend_from_slice(&chunk);
+                    let excess = tail.len().saturating_sub(upload::PROBE_BYTES);
+                    tail.drain(..excess);
+                }
+                out.flush().await.map_err(spool_failed)?;
+                input.file = Some(spooled);
+                input.size = size;
+            } else {
+                let value = field.text().await.map_err(|e| {
+                    ApiError::BadRequest(format!("Failed to read field '{}': {}", name, e))
+                })?;
+                input.fields.insert(name, value);
+            }
+        }
+    }
+
+    if let Some(file_url) = file_url {
+        input.filename = file_url
+            .rsplit('/')
+            .next()
+            .and_then(|s| s.split('?').next())
+            .filter(|s| !s.is_empty())
+            .unwrap_or("document")
+            .to_string();
+
+        // For URL-based input, we don't download here (OCR providers handle URLs directly)
+        // Return no file — the caller will use OcrInput::Url
+        input.file = None;
+        input.size = 0;
+        Ok(input)
+    } else if input.file.is_some() {
+        upload::check(
+            accept,
+            &input.filename,
+            content_type.as_deref(),
+            input.size,
+            &head,
+            &tail,
+        )?;
+        Ok(input)
+    } else if has_multipart {
+        Err(ApiError::MissingFile(
+            "No file uploaded. Send multipart 'file' field or use ?file_url= parameter.",
+        ))
+    } else {
+        Err(ApiError::MissingFile(
+            "No file provided. Send multipart 'file' field or use ?file_url= parameter.",
+        ))
+    }
+}
+
+// ============================================================================
+// Startup recovery
+// ============================================================================
+
+/// Resolve every job left in the journal by a previous run: re-enqueue
+/// extractions whose OCR output, source file, or URL is still reachable, and
+/// mark everything else as failed.
+async fn recover_interrupted_jobs(state: &AppState) -> jobs::RecoveryReport {
+    let records = state.jobs.stale();
+    // Claim them in the shared journal, so no other replica takes them over
+    if state.shared.is_some() {
+        for record in &records {
+            state.jobs.start(record);
+        }
+    }
+    recover_jobs(state, records).await
+}
+
+/// Re-run or fail each of `records`, which are in this replica's journal.
+async fn recover_jobs(state: &AppState, records: Vec<jobs::JobRecord>) -> jobs::RecoveryReport {
+    let mut recovered = Vec::new();
+
+    for record in records {
+        let outcome = match record.kind {
+            jobs::JobKind::Extraction => {
+                requeue_extraction(state, &record, Admission::resumed()).await
+            }
+            jobs::JobKind::Dataset => Err("sheet inputs are not retained".to_string()),
+        };
+
+        let (action, reason) = match outcome {
+            Ok(action) => {
+                info!("Recovery: re-enqueued {} ({:?})", record.id, action);
+                (action, None)
+            }
+            Err(reason) => {
+                error!("Recovery: marking {} as failed: {}", record.id, reason);
+                mark_interrupted_failed(state, &record, &reason);
+                state.jobs.finish(&record.id);
+                (jobs::RecoveryAction::MarkedFailed, Some(reason))
+            }
+        };
+
+        recovered.push(jobs::RecoveredJob {
+            id: record.id,
+            kind: record.kind,
+            source_file: record.source_file,
+            started_at: record.started_at,
+            action,
+            reason,
+        });
+    }
+
+    jobs::RecoveryReport::new(recovered)
+}
+
+/// How often to look for jobs left behind by a replica that stopped.
+const JOB_TAKEOVER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Resume the jobs of replicas whose Redis heartbeat expired, the same way
+/// as jobs interrupted by a restart, and add them to the recovery report.
+/// With the shared queue, extractions go back on the queue instead.
+fn spawn_job_takeover(state: AppState, shared: Arc<shared::SharedState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(JOB_TAKEOVER_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut requeued = Vec::new();
+            let mut records = Vec::new();
+            for record in shared.claim_orphaned_jobs().await {
+                if shared.queues_jobs() && record.kind == jobs::JobKind::Extraction {
+                    match shared.requeue(&record).await {
+                        Ok(()) => {
+                            info!("Put {} back on the shared job queue", record.id);
+                            requeued.push(jobs::RecoveredJob {
+                                id: record.id,
+                                kind: record.kind,
+                                source_file: record.source_file,
+                                started_at: record.started_at,
+                                action: jobs::RecoveryAction::Requeued,
+                                reason: None,
+                            });
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to requeue {}, resuming it here: {:#}", record.id, e)
+                        }
+                    }
+                }
+                state.jobs.start(&record);
+                records.push(record);
+            }
+            if requeued.is_empty() && records.is_empty() {
+                continue;
+            }
+            let mut report = recover_jobs(&state, records).await;
+            report.jobs.extend(requeued);
+            info!(
+                "Took over {} job(s) from stopped replicas; see GET /admin/recovery",
+                report.jobs.len()
+            );
+            let mut recovery = state.recovery.write().unwrap();
+            recovery.ran_at = report.ran_at;
+            recovery.jobs.extend(report.jobs);
+        }
+    });
+}
+
+/// How long a replica with free slots waits before checking an empty
+/// shared queue again.
+const JOB_QUEUE_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Pull jobs off the shared queue (`JOB_QUEUE=redis`) while this replica
+/// has a free run slot, and run them like recovered jobs. Lanes are tried
+/// in the same weighted order as local slots.
+fn spawn_job_queue(state: AppState, shared: Arc<shared::SharedState>) {
+    tokio::spawn(async move {
+        let mut turn = 0;
+        loop {
+            if state.running.is_full() {
+                tokio::time::sleep(JOB_QUEUE_POLL).await;
+                continue;
+            }
+            let lanes = jobs::lane_order(turn);
+            let Some(record) = shared.claim_queued_job(lanes).await else {
+                tokio::time::sleep(JOB_QUEUE_POLL).await;
+                continue;
+            };
+            turn += 1;
+            // The claim already lists the job under this replica in Redis
+            state.jobs.start(&record);
+            match requeue_extraction(&state, &record, Admission::resumed()).await {
+                Ok(action) => info!("Claimed queued job {} ({:?})", record.id, action),
+                Err(reason) => {
+                    error!("Cannot run queued job {}: {}", record.id, reason);
+                    mark_job_failed(&state, &record, reason);
+                    state.jobs.finish(&record.id);
+                }
+            }
+        }
+    });
+}
+
+/// Restart an interrupted extraction from the furthest point still available.
+async fn requeue_extraction(
+    state: &AppState,
+    record: &jobs::JobRecord,
+    admission: Admission,
+) -> Result<jobs::RecoveryAction, String> {
+    let (action, config, input) = resume_point(state, record).await?;
+
+    let mut placeholder =
+        Extraction::new(record.source_file.clone(), Some(record.config_name.clone()));
+    placeholder.id = record.id.clone();
+    placeholder.extracted_at = record.started_at.clone();
+    placeholder.prompt_override = record.prompt_override.clone();
+    placeholder.parent_extraction_id = record.parent_extraction_id.clone();
+    placeholder.parent_node_id = record.parent_node_id.clone();
+    mark_queued(&mut placeholder);
+    state.extractions.insert(record.id.clone(), placeholder);
+
+    spawn_extraction(
+        state.clone(),
+        extraction_job(record, config),
+        input,
+        admission,
+    );
+    Ok(action)
+}
+
+/// The job a journal record describes, run with `config`.
+fn extraction_job(record: &jobs::JobRecord, config: config::ExtractionConfig) -> ExtractionJob {
+    ExtractionJob {
+        id: record.id.clone(),
+        filename: record.source_file.clone(),
+        config: Arc::new(config),
+        prompt_override: record.prompt_override.clone(),
+        upload: record.upload,
+        callback_url: record.callback_url.clone(),
+        priority: record.priority,
+    }
+}
+
+/// Where to restart a recorded extraction: its kept OCR output (skips the
+/// OCR stage), then its archived source, then its URL.
+async fn resume_point(
+    state: &AppState,
+    record: &jobs::JobRecord,
+) -> Result<
+    (
+        jobs::RecoveryAction,
+        config::ExtractionConfig,
+        PipelineInput,
+    ),
+    String,
+> {
+    let mut config = state
+        .configs
+        .get(&record.config_name)
+        .ok_or_else(|| format!("config '{}' no longer exists", record.config_name))?;
+    // Keep the options the job started with (they may have come from the query)
+    if record.ocr_options.is_some() {
+        config.ocr_options = record.ocr_options.clone();
+    }
+
+    // Prefer kept OCR output (skips the OCR stage), then the archived source, then the URL
+    let archived_ocr = match load_ocr(state, &object_store::extraction_root(&record.id)).await {
+        Ok(stored) => stored.map(object_store::StoredOcr::into_ocr_result),
+        Err(e) => {
+            error!("Kept OCR for {} is unusable: {}", record.id, e);
+            None
+        }
+    };
+    let mut archived_source = None;
+    if let Some(ref store) = state.object_store {
+        if archived_ocr.is_none() {
+            match store.get(&object_store::source_key(&record.id)).await {
+                Ok(data) => archived_source = data,
+                Err(e) => error!("Failed to read archived source for {}: {}", record.id, e),
+            }
+        }
+    }
+
+    let (action, input) = match archived_ocr {
+        Some(ocr_result) => (
+            jobs::RecoveryAction::RequeuedFromOcrCache,
+            PipelineInput::Ocr(ocr_result),
+        ),
+        None => {
+            let provider_name = record.ocr_provider.as_deref().unwrap_or("docling");
+            let provider = OcrProviderKind::from_str(provider_name)
+                .and_then(|kind| state.ocr_providers.get(&kind))
+                .cloned()
+                .ok_or_else(|| format!("OCR provider '{}' is not configured", provider_name))?;
+            let filename = record.source_file.clone();
+            match (archived_source, &record.file_url) {
+                (Some(data), _) => (
+                    jobs::RecoveryAction::RequeuedFromSource,
+                    PipelineInput::Source(provider, OcrInput::Bytes { filename, data }),
+                ),
+                (None, Some(url)) => (
+                    jobs::RecoveryAction::RequeuedFromUrl,
+                    PipelineInput::Source(
+                        provider,
+                        OcrInput::Url {
+                            filename,
+                            url: url.clone(),
+                        },
+                    ),
+                ),
+                (None, None) => return Err("original file was not retained".to_string()),
+            }
+        }
+    };
+    Ok((action, config, input))
+}
+
+/// Record an interrupted job as failed so clients polling it get an answer.
+fn mark_interrupted_failed(state: &AppState, record: &jobs::JobRecord, reason: &str) {
+    mark_job_failed(
+        state,
+        record,
+        format!("Interrupted by server restart ({})", reason),
+    );
+}
+
+/// Record a job that never ran its pipeline here as failed with `error`.
+fn mark_job_failed(state: &AppState, record: &jobs::JobRecord, error: String) {
+    match record.kind {
+        jobs::JobKind::Extraction => {
+            let mut ext = Extraction::new(record.source_file.clone(), Some(record.config_name.clone()));
+            ext.id = record.id.clone();
+            ext.extracted_at = record.started_at.clone();
+            ext.status = ExtractionStatus::Failed;
+            ext.error = Some(error);
+            state.extractions.insert(record.id.clone(), ext);
+        }
+        jobs::JobKind::Dataset => {
+            let mut ds = SheetExtraction::new(record.source_file.clone(), Some(record.config_name.clone()));
+            ds.id = record.id.clone();
+            ds.extracted_at = record.started_at.clone();
+            ds.status = ExtractionStatus::Failed;
+            ds.error = Some(error);
+            if let Err(e) = save_dataset_to_disk(&ds) {
+                error!("Failed to persist failed dataset {}: {}", ds.id, e);
+            }
+            state.datasets.insert(record.id.clone(), ds);
+        }
+    }
+}
+
+// ============================================================================
+// Scheduled re-extraction and retention
+// ============================================================================
+
+/// How often a scheduled re-extraction retries a config at its `max_concurrent`.
+const REEXTRACT_ADMISSION_POLL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// At the start of every minute, re-extract the configs whose schedule fires
+/// and that changed, and purge expired results when `RETENTION_SCHEDULE` fires.
+fn spawn_scheduler(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            let since_epoch = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            let into_minute = since_epoch.as_millis() % 60_000;
+            tokio::time::sleep(std::time::Duration::from_millis(
+                (60_000 - into_minute) as u64,
+            ))
+            .await;
+
+            let now = std::time::SystemTime::now();
+            for config in state.scheduler.due_reextractions(&state.configs.all(), now) {
+                // Admission may wait for the config's quota; the tick must not
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let queued = reextract_config(&state, &config).await;
+                    info!(
+                        "Scheduler: re-extracting {} {} document(s)",
+                        queued, config.name
+                    );
+                });
+            }
+            if state.scheduler.retention_due(now) {
+                let report = run_retention(&state, false).await;
+                info!(
+                    "Retention: purged {} extraction(s) and {} dataset(s), {} error(s)",
+                    report.extractions.len(),
+                    report.datasets.len(),
+                    report.errors.len()
+                );
+            }
+        }
+    });
+}
+
+/// Re-run every completed extraction of `config`, in memory or in storage,
+/// from its archived OCR output or source file. Each waits for a free slot
+/// of the config's `max_concurrent`; once its daily budget is spent the rest
+/// are left for the next change. Returns how many were queued.
+async fn reextract_config(state: &AppState, config: &config::ExtractionConfig) -> usize {
+    if state.object_store.is_none() {
+        warn!(
+            "Scheduler: {} changed, but without an object store its documents cannot be re-run",
+            config.name
+        );
+        return 0;
+    }
+    let mut targets: Vec<(String, String)> = state
+        .extractions
+        .summaries()
+        .into_iter()
+        .filter(|ext| {
+            ext.config_name.as_deref() == Some(config.name.as_str())
+                && ext.status == ExtractionStatus::Completed
+        })
+        .map(|ext| (ext.id, ext.source_file))
+        .collect();
+    if let Some(ref storage) = state.storage {
+        let filter = storage::ExtractionFilter {
+            config_name: Some(config.name.clone()),
+            ..Default::default()
+        };
+        match storage.list_extractions(&filter).await {
+            Ok(rows) => {
+                for row in rows {
+                    if !state.extractions.contains(&row.id) {
+                        targets.push((row.id, row.source_file));
+                    }
+                }
+            }
+            Err(e) => error!("Scheduler: failed to list extractions of {}: {}", config.name, e),
+        }
+    }
+
+    let mut queued = 0;
+    for (id, source_file) in targets {
+        let admission = loop {
+            let over_budget = config
+                .quotas
+                .as_ref()
+                .and_then(|quotas| state.quotas.check_budget(&config.name, quotas).err());
+            if let Some(reason) = over_budget {
+                warn!(
+                    "Scheduler: stopping re-extraction of {}: {}",
+                    config.name, reason
+                );
+                return queued;
+            }
+            match admit_job(state, config).await {
+                Ok(admission) => break admission,
+                Err(_) => tokio::time::sleep(REEXTRACT_ADMISSION_POLL).await,
+            }
+        };
+        let record = jobs::JobRecord {
+            id: id.clone(),
+            kind: jobs::JobKind::Extraction,
+            source_file,
+            config_name: config.name.clone(),
+            ocr_provider: None,
+            ocr_options: None,
+            file_url: None,
+            upload: true,
+            callback_url: None,
+            prompt_override: None,
+            // Re-runs are backfill; they must not hold up new uploads
+            priority: jobs::Priority::Low,
+            parent_extraction_id: None,
+            parent_node_id: None,
+            started_at: schema::now_iso8601(),
+        };
+        state.jobs.start(&record);
+        match requeue_extraction(state, &record, admission).await {
+            Ok(_) => queued += 1,
+            Err(e) => {
+                state.jobs.finish(&id);
+                warn!("Scheduler: cannot re-extract {}: {}", id, e);
+            }
+        }
+    }
+    queued
+}
+
+#[derive(serde::Deserialize)]
+struct RetentionQuery {
+    dry_run: Option<bool>,
+}
+
+/// Purge expired extractions and datasets now; `?dry_run=true` only lists them.
+async fn run_retention_now(
+    State(state): State<AppState>,
+    Query(query): Query<RetentionQuery>,
+) -> Json<scheduler::RetentionReport> {
+    Json(run_retention(&state, query.dry_run.unwrap_or(false)).await)
+}
+
+/// Remove finished extractions and datasets past their config's retention
+/// from memory, the content store, `data/datasets/`, and storage.
+async fn run_retention(state: &AppState, dry_run: bool) -> scheduler::RetentionReport {
+    let now = std::time::SystemTime::now();
+    let configs: HashMap<String, config::ExtractionConfig> = state
+        .configs
+        .all()
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+    let expired = |config_name: Option<&str>, extracted_at: &str| {
+        let config = config_name.and_then(|name| configs.get(name));
+        state
+            .scheduler
+            .retention_days(config)
+            .is_some_and(|days| extracted_at < scheduler::cutoff(days, now).as_str())
+    };
+    let mut report = scheduler::RetentionReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    // In memory (unless still running) and in storage
+    let mut extractions: Vec<String> = state
+        .extractions
+        .summaries()
+        .into_iter()
+        .filter(|ext| !ext.status.is_active())
+        .filter(|ext| expired(ext.config_name.as_deref(), &ext.extracted_at))
+        .map(|ext| ext.id)
+        .collect();
+    let mut datasets: Vec<String> = state
+        .datasets
+        .summaries()
+        .into_iter()
+        .filter(|ds| !ds.status.is_active())
+        .filter(|ds| expired(ds.config_name.as_deref(), &ds.extracted_at))
+        .map(|ds| ds.id)
+        .collect();
+    if let Some(ref storage) = state.storage {
+        match storage
+            .list_extractions(&storage::ExtractionFilter::default())
+            .await
+        {
+            Ok(rows) => extractions.extend(
+                rows.into_iter()
+                    .filter(|row| expired(row.config_name.as_deref(), &row.extracted_at))
+                    .map(|row| row.id),
+            ),
+            Err(e) => report.errors.push(format!("Failed to list extractions: {}", e)),
+        }
+        match storage.list_datasets().await {
+            Ok(rows) => datasets.extend(
+                rows.into_iter()
+                    .filter(|row| expired(row.config_name.as_deref(), &row.extracted_at))
+                    .map(|row| row.id),
+            ),
+            Err(e) => report.errors.push(format!("Failed to list datasets: {}", e)),
+        }
+    }
+    extractions.sort();
+    extractions.dedup();
+    datasets.sort();
+    datasets.dedup();
+
+    if dry_run {
+        report.extractions = extractions;
+        report.datasets = datasets;
+        return report;
+    }
+
+    for id in extractions {
+        // Storage first: on failure everything is kept and the next run retries
+        if let Some(ref storage) = state.storage {
+            if let Err(e) = storage.delete_extraction(&id).await {
+                report.errors.push(format!("Extraction {}: {}", id, e));
+                continue;
+            }
+        }
+        let removed = state.extractions.remove(&id);
+        if let Some(ext) = removed {
+            let mut nodes = Vec::new();
+            storage::flatten_nodes(&ext.children, None, &mut nodes);
+            for content_ref in nodes.iter().filter_map(|(_, node)| node.content_ref.as_ref()) {
+                state.content_store.remove(content_ref);
+                state
+                    .content_store
+                    .remove(&format!("{}{}", content_ref, redaction::REDACTED_SUFFIX));
+            }
+        }
+        state.content_store.remove(&format!(
+            "content://{}",
+            object_store::ocr_json_key(&object_store::extraction_root(&id))
+        ));
+        if let Err(e) = state.failures.remove(&id) {
+            report.errors.push(format!("Extraction {}: {}", id, e));
+        }
+        report.extractions.push(id);
+    }
+    for id in datasets {
+        if let Some(ref storage) = state.storage {
+            if let Err(e) = storage.delete_dataset(&id).await {
+                report.errors.push(format!("Dataset {}: {}", id, e));
+                continue;
+            }
+        }
+        state.datasets.remove(&id);
+        state.content_store.remove(&format!(
+            "content://{}",
+            object_store::ocr_json_key(&object_store::dataset_root(&id))
+        ));
+        let path = std::path::Path::new(DATASETS_DIR).join(format!("{}.json", id));
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                report.errors.push(format!("Dataset {}: {}", id, e));
+            }
+        }
+        report.datasets.push(id);
+    }
+    report
+}
+
+// ============================================================================
+// Watch-folder ingestion
+// ============================================================================
+
+/// Poll the watch folder: queue extractions for settled files, and move
+/// files whose extraction ended to `done/` or `failed/`.
+fn spawn_ingest(state: AppState, folder: ingest::WatchFolder) {
+    folder.requeue_interrupted();
+    tokio::spawn(async move {
+        let mut pending: Vec<(String, ingest::Claimed)> = Vec::new();
+        let mut ticker = tokio::time::interval(folder.interval());
+        loop {
+            ticker.tick().await;
+
+            pending.retain(|(id, claimed)| {
+                let outcome = state
+                    .extractions
+                    .with(id, |ext| (ext.status.clone(), ext.error.clone()));
+                let error = match outcome {
+                    Some((status, _)) if status.is_active() => return true,
+                    Some((ExtractionStatus::Completed, _)) => None,
+                    Some((ExtractionStatus::Cancelled, _)) => {
+                        Some("Extraction cancelled".to_string())
+                    }
+                    Some((_, error)) => {
+                        Some(error.unwrap_or_else(|| "Extraction failed".to_string()))
+                    }
+                    None => Some("Extraction is no longer tracked".to_string()),
+                };
+                match folder.finish(claimed, error.as_deref()) {
+                    Ok(path) => info!("Ingest: {} finished, moved to {}", id, path.display()),
+                    Err(e) => error!(
+                        "Ingest: failed to move {}: {}",
+                        claimed.relative.display(),
+                        e
+                    ),
+                }
+                false
+            });
+
+            for claimed in folder.scan() {
+                match ingest_file(&state, &folder, &claimed).await {
+                    Ok(Some(id)) => {
+                        info!("Ingest: {} queued as {}", claimed.relative.display(), id);
+                        pending.push((id, claimed));
+                    }
+                    Ok(None) => {
+                        if let Err(e) = folder.release(&claimed) {
+                            error!(
+                                "Ingest: failed to release {}: {}",
+                                claimed.relative.display(),
+                                e
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Ingest: {} rejected: {}", claimed.relative.display(), e);
+                        if let Err(e) = folder.finish(&claimed, Some(&e)) {
+                            error!(
+                                "Ingest: failed to move {}: {}",
+                                claimed.relative.display(),
+                                e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Queue the extraction of a claimed file; returns its extraction ID, or
+/// `None` when the config's quotas refuse it for now.
+async fn ingest_file(
+    state: &AppState,
+    folder: &ingest::WatchFolder,
+    claimed: &ingest::Claimed,
+) -> Result<Option<String>, String> {
+    let config = requested_config(state, claimed.config_name.as_deref(), ConfigKind::Document)
+        .map_err(|e| e.to_string())?;
+    let provider_name = folder.ocr_provider();
+    let provider = OcrProviderKind::from_str(provider_name)
+        .and_then(|kind| state.ocr_providers.get(&kind))
+        .ok_or_else(|| format!("OCR provider '{}' is not configured", provider_name))?;
+    let admission = match admit_job(state, &config).await {
+        Ok(admission) => admission,
+        Err(e) => {
+            info!(
+                "Ingest: {} waits for the next poll: {}",
+                claimed.relative.display(),
+                e
+            );
+            return Ok(None);
+        }
+    };
+    let data = std::fs::read(folder.processing_path(claimed))
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let extraction = queue_extraction(
+        state,
+        admission,
+        Arc::new(config),
+        provider_name,
+        Arc::clone(provider),
+        OcrInput::Bytes {
+            filename: claimed.filename(),
+            data,
+        },
+        None,
+        folder.upload(),
+        None,
+        jobs::Priority::Normal,
+    );
+    Ok(Some(extraction.id))
+}
+
+// ============================================================================
+// Mailbox ingestion
+// ============================================================================
+
+/// Longest a single mailbox poll may take before it is abandoned until the next tick.
+const MAIL_POLL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Poll the mailbox and queue the attachments of each unseen message.
+fn spawn_mail(state: AppState, mailbox: mail::Mailbox) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(mailbox.interval());
+        loop {
+            ticker.tick().await;
+            let poll = mailbox.poll(|message| ingest_mail(&state, &mailbox, message));
+            match tokio::time::timeout(MAIL_POLL_TIMEOUT, poll).await {
+                Ok(Ok(0)) => debug!("Mail: no new messages"),
+                Ok(Ok(count)) => info!("Mail: processed {} message(s)", count),
+                Ok(Err(e)) => error!("Mail: poll of {} failed: {:#}", mailbox.describe(), e),
+                Err(_) => error!(
+                    "Mail: poll of {} timed out after {}s",
+                    mailbox.describe(),
+                    MAIL_POLL_TIMEOUT.as_secs()
+                ),
+            }
+        }
+    });
+}
+
+/// How often the idle Docling instance is checked for stopping.
+const GCE_IDLE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Stop each Docling GCE instance once it has been idle for the window. The
+/// last running instance is kept while a queued or running job may still
+/// need OCR.
+fn spawn_gce_idle_stop(state: AppState, idle_stop: gce::IdleStop) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(GCE_IDLE_CHECK_INTERVAL.min(idle_stop.window));
+        loop {
+            ticker.tick().await;
+            for (instance, tracker) in &idle_stop.trackers {
+                let Some(idle) = tracker.idle_for() else {
+                    continue;
+                };
+                if idle < idle_stop.window {
+                    continue;
+                }
+                let others_running = idle_stop
+                    .trackers
+                    .iter()
+                    .any(|(other, t)| other != instance && !t.is_stopped());
+                if !others_running && ocr_work_pending(&state) {
+                    debug!(
+                        "GCE idle stop: jobs may still need OCR, keeping '{}' up",
+                        instance
+                    );
+                    continue;
+                }
+
+                let gce = &idle_stop.config;
+                match gce.get_instance_status(&state.http_client, instance).await {
+                    Ok(status) if status == "RUNNING" => {
+                        info!(
+                            "Docling idle for {}s, stopping GCE instance '{}'",
+                            idle.as_secs(),
+                            instance
+                        );
+                        match gce.stop_instance(&state.http_client, instance).await {
+                            Ok(()) => tracker.mark_stopped(),
+                            Err(e) => error!("GCE idle stop failed: {:#}", e),
+                        }
+                    }
+                    Ok(status) => {
+                        debug!("GCE instance '{}' is {}, nothing to stop", instance, status);
+                        tracker.mark_stopped();
+                    }
+                    Err(e) => warn!("GCE idle stop: could not get instance status: {:#}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Whether an extraction is queued or in its OCR stage, or a dataset is
+/// still processing (its PDF sheets go through OCR too).
+fn ocr_work_pending(state: &AppState) -> bool {
+    let extraction_pending = state.extractions.any(|ext| {
+        matches!(
+            ext.status,
+            ExtractionStatus::Queued | ExtractionStatus::Processing | ExtractionStatus::OcrRunning
+        )
+    });
+    extraction_pending || state.datasets.any(|ds| ds.status.is_active())
+}
+
+/// Route a message to a config and queue each of its attachments. Returns
+/// `false`, queueing nothing, when the config's quotas can't take them all
+/// yet, so the message is read again next poll.
+///
+/// Spreadsheets, and every attachment for configs with a `sheet_config`, go
+/// through sheet extraction; PDFs otherwise go through the document pipeline.
+async fn ingest_mail(
+    state: &AppState,
+    mailbox: &mail::Mailbox,
+    message: mail::mime::Message,
+) -> bool {
+    let configs = state.configs.all();
+    let default_rule = config::MailRule::default();
+    let routed = match mail::route(&configs, &message) {
+        Some((config, rule)) => Some((config.clone(), rule)),
+        None => mailbox
+            .default_config()
+            .and_then(|name| state.configs.get(name))
+            .map(|config| (config, &default_rule)),
+    };
+    let Some((config, rule)) = routed else {
+        info!(
+            "Mail: no config handles \"{}\" from {}; skipping",
+            message.subject, message.from
+        );
+        return true;
+    };
+    let config = Arc::new(config);
+
+    let provider_name = rule.ocr_provider.as_deref().unwrap_or("docling");
+    let provider = OcrProviderKind::from_str(provider_name)
+        .and_then(|kind| state.ocr_providers.get(&kind))
+        .cloned();
+    let mut accepted = Vec::new();
+    for attachment in message.attachments {
+        let ext = attachment
+            .filename
+            .rsplit('.')
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        let is_pdf = ext == "pdf" || attachment.content_type == "application/pdf";
+        let is_sheet = matches!(ext.as_str(), "csv" | "xlsx" | "xlsm" | "xlsb");
+        if !is_pdf && !is_sheet {
+            info!("Mail: skipping attachment {}", attachment.filename);
+            continue;
+        }
+        let provider = match (&provider, is_pdf) {
+            (Some(provider), true) => Some(Arc::clone(provider)),
+            (None, true) => {
+                warn!(
+                    "Mail: OCR provider '{}' is not configured; skipping {}",
+                    provider_name, attachment.filename
+                );
+                continue;
+            }
+            (_, false) => None,
+        };
+        accepted.push((attachment, provider));
+    }
+
+    let mut admissions = Vec::with_capacity(accepted.len());
+    for _ in &accepted {
+        match admit_job(state, &config).await {
+            Ok(admission) => admissions.push(admission),
+            Err(e) => {
+                info!(
+                    "Mail: \"{}\" from {} waits for the next poll: {}",
+                    message.subject, message.from, e
+                );
+                return false;
+            }
+        }
+    }
+
+    for ((attachment, provider), admission) in accepted.into_iter().zip(admissions) {
+        let id = match provider {
+            Some(provider) if config.sheet_config.is_none() => {
+                queue_extraction(
+                    state,
+                    admission,
+                    Arc::clone(&config),
+                    provider_name,
+                    provider,
+                    OcrInput::Bytes {
+                        filename: attachment.filename.clone(),
+                        data: attachment.data,
+                    },
+                    None,
+                    true,
+                    rule.callback_url.clone(),
+                    jobs::Priority::Normal,
+                )
+                .id
+            }
+            provider => {
+                queue_sheet_extraction(
+                    state,
+                    admission,
+                    Arc::clone(&config),
+                    attachment.filename.clone(),
+                    attachment.data,
+                    provider.map(|p| (provider_name, p)),
+                    true,
+                    rule.callback_url.clone(),
+                )
+                .id
+            }
+        };
+        info!(
+            "Mail: {} from {} queued as {} (config={})",
+            attachment.filename, message.from, id, config.name
+        );
+    }
+    true
+}
+
+// ============================================================================
+// Dataset persistence (file-backed)
+// ============================================================================
+
+const DATASETS_DIR: &str = "data/datasets";
+
+/// Load all datasets from `data/datasets/*.json` on startup.
+fn load_datasets_from_disk() -> HashMap<String, SheetExtraction> {
+    let dir = std::path::Path::new(DATASETS_DIR);
+    let mut map = HashMap::new();
+
+    if !dir.exists() {
+        return map;
+    }
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) => {
+            error!("Failed to read datasets dir: {}", e);
+            return map;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map(|e| e == "json").unwrap_or(false) {
+            match std::fs::read_to_string(&path) {
+                Ok(content) => match serde_json::from_str::<SheetExtraction>(&content) {
+                    Ok(ds) => {
+                        info!("Loaded dataset {} from {:?}", ds.id, path);
+                        map.insert(ds.id.clone(), ds);
+                    }
+                    Err(e) => error!("Failed to parse dataset {:?}: {}", path, e),
+                },
+                Err(e) => error!("Failed to read {:?}: {}", path, e),
+            }
+        }
+    }
+
+    map
+}
+
+/// Save a completed dataset to `data/datasets/{id}.json`.
+fn save_dataset_to_disk(dataset: &SheetExtraction) -> anyhow::Result<()> {
+    let dir = std::path::Path::new(DATASETS_DIR);
+    std::fs::create_dir_all(dir)?;
+
+    let path = dir.join(format!("{}.json", dataset.id));
+    let json = serde_json::to_string_pretty(dataset)?;
+    std::fs::write(&path, json)?;
+
+    info!("Persisted dataset {} to {:?}", dataset.id, path);
+    Ok(())
+}
+
+// ============================================================================
+// Helper functions
+// ============================================================================
+
+/// Recursively find a node by ID.
+fn find_node<'a>(
+    nodes: &'a [schema::DocumentNode],
+    node_id: &str,
+) -> Option<&'a schema::DocumentNode> {
+    for node in nodes {
+        if node.id == node_id {
+            return Some(node);
+        }
+        if let Some(found) = find_node(&node.children, node_id) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Recursively collect content metadata for all nodes.
+/// Index node content refs. With `lazy`, content not yet in the store still
+/// counts as available: `/content/:ref_path` fetches it from storage.
+fn collect_content_meta(
+    nodes: &[schema::DocumentNode],
+    content_store: &ContentStore,
+    lazy: bool,
+    out: &mut Vec<NodeContentMeta>,
+) {
+    for node in nodes {
+        if let Some(content_ref) = &node.content_ref {
+            let char_count = content_store.len(content_ref);
+            out.push(NodeContentMeta {
+                node_id: node.id.clone(),
+                content_ref: content_ref.clone(),
+                char_count,
+                available: lazy || char_count.is_some(),
+                lazy: None,
+                content: None,
+            });
+        }
+
+        if !node.children.is_empty() {
+            collect_content_meta(&node.children, content_store, lazy, out);
+        }
+    }
+}
+
+/// Bring the contents of a snapshot's index this server doesn't hold into
+/// the content store, so they can be inlined: from other replicas first,
+/// then the rest of the extraction's from storage in one fetch.
+async fn load_contents(state: &AppState, extraction: &Extraction, index: &mut [NodeContentMeta]) {
+    for meta in index.iter().filter(|meta| meta.char_count.is_none()) {
+        let node_id = meta
+            .content_ref
+            .strip_prefix("content://")
+            .unwrap_or(&meta.content_ref);
+        ensure_content(state, node_id, false).await;
+    }
+    // Failures are logged; those contents stay lazy
+    hydrate_content(state, extraction).await.ok();
+    for meta in index.iter_mut() {
+        meta.char_count = state.content_store.len(&meta.content_ref);
+        meta.available |= meta.char_count.is_some();
+    }
+}
+
+/// Inline contents into a snapshot's content index until `budget` characters
+/// are spent, and mark the rest `lazy`. Nodes of types listed earlier in the
+/// config go first, then the more confident ones (reviewed nodes count as
+/// certain), then the shorter; a content that doesn't fit is passed over for
+/// smaller ones. Only contents in the content store are inlined (see
+/// `load_contents`). Returns the characters inlined.
+fn inline_within_budget(
+    nodes: &[schema::DocumentNode],
+    node_types: &[String],
+    content_store: &ContentStore,
+    index: &mut [NodeContentMeta],
+    budget: usize,
+) -> usize {
+    fn priorities(
+        nodes: &[schema::DocumentNode],
+        node_types: &[String],
+        out: &mut HashMap<String, (usize, f64)>,
+    ) {
+        for node in nodes {
+            let type_rank = node_types
+                .iter()
+                .position(|t| *t == node.node_type)
+                .unwrap_or(node_types.len());
+            let confidence = if node.reviewed {
+                1.0
+            } else {
+                node.confidence
+                    .as_ref()
+                    .and_then(|c| c.extraction)
+                    .unwrap_or(0.0)
+            };
+            out.insert(node.id.clone(), (type_rank, confidence));
+            priorities(&node.children, node_types, out);
+        }
+    }
+
+    let mut ranks = HashMap::new();
+    priorities(nodes, node_types, &mut ranks);
+    let rank = |meta: &NodeContentMeta| {
+        ranks
+            .get(&meta.node_id)
+            .copied()
+            .unwrap_or((node_types.len(), 0.0))
+    };
+
+    let mut order: Vec<usize> = (0..index.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (type_a, confidence_a) = rank(&index[a]);
+        let (type_b, confidence_b) = rank(&index[b]);
+        type_a
+            .cmp(&type_b)
+            .then(confidence_b.total_cmp(&confidence_a))
+            .then(index[a].char_count.cmp(&index[b].char_count))
+    });
+
+    let mut remaining = budget;
+    for i in order {
+        let meta = &mut index[i];
+        meta.lazy = Some(true);
+        if meta.char_count.is_none_or(|chars| chars > remaining) {
+            continue;
+        }
+        if let Some(content) = content_store.get_full(&meta.content_ref) {
+            remaining -= meta.char_count.unwrap_or_default();
+            meta.content = Some(content);
+            meta.lazy = Some(false);
+        }
+    }
+    budget - remaining
+}
+
+/// Add `content` with the full text to every serialized node that has it.
+fn inline_content(nodes: &mut serde_json::Value, content_store: &ContentStore) {
+    let Some(nodes) = nodes.as_array_mut() else {
+        return;
+    };
+    for node in nodes {
+        let content = node
+            .get("content_ref")
+            .and_then(|r| r.as_str())
+            .and_then(|r| content_store.get_full(r));
+        if let (Some(content), Some(fields)) = (content, node.as_object_mut()) {
+            fields.insert("content".to_string(), content.into());
+        }
+        if let Some(children) = node.get_mut("children") {
+            inline_content(children, content_store);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State on the mock LLM and OCR fixtures, keeping its files in `tmp`.
+    fn mock_state(tmp: &std::path::Path) -> AppState {
+        let fixtures = std::path::Path::new("tests/fixtures/mock");
+        let mut ocr_providers: HashMap<OcrProviderKind, Arc<dyn OcrProvider>> = HashMap::new();
+        ocr_providers.insert(
+            OcrProviderKind::Docling,
+            Arc::new(ocr::mock::MockProvider::new(fixtures)),
+        );
+        AppState {
+            extractions: Arc::new(live::LiveMap::new(extraction_summary)),
+            datasets: Arc::new(live::LiveMap::new(dataset_summary)),
+            content_store: ContentStore::new(),
+            openrouter: Arc::new(OpenRouterClient::mock(
+                MockLlmClient::from_dir(fixtures).unwrap(),
+            )),
+            configs: Arc::new(ConfigStore::load_from_dir(std::path::Path::new("configs")).unwrap()),
+            config_history: Arc::new(
+                config_history::ConfigHistory::open(tmp.join("config_history")).unwrap(),
+            ),
+            http_client: reqwest::Client::new(),
+            storage: None,
+            object_store: None,
+            outbox: None,
+            events: None,
+            shared: None,
+            jobs: Arc::new(jobs::JobJournal::open(tmp.join("jobs")).unwrap()),
+            running: Arc::new(jobs::RunningJobs::new(1)),
+            recovery: Arc::new(RwLock::new(jobs::RecoveryReport::default())),
+            eval: Arc::new(eval::EvalStore::open(tmp.join("eval")).unwrap()),
+            failures: Arc::new(failures::FailureStore::open(tmp.join("failures")).unwrap()),
+            ocr_providers: Arc::new(ocr_providers),
+            scheduler: Arc::new(scheduler::Scheduler::open(tmp.join("scheduler.json")).unwrap()),
+            quotas: Arc::new(quotas::QuotaLedger::open(tmp.join("quotas.json")).unwrap()),
+            background: Arc::new(admin::BackgroundTasks::default()),
+        }
+    }
+
+    /// A server on the mock fixtures, listening on a free local port.
+    struct MockServer {
+        server: Server,
+        base: String,
+        client: reqwest::Client,
+        tmp: std::path::PathBuf,
+    }
+
+    impl MockServer {
+        async fn start() -> Self {
+            let tmp = std::env::temp_dir().join(format!("extractor_mock_{}", uuid::Uuid::new_v4()));
+            let state = mock_state(&tmp);
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let router = build_router(state.clone());
+            tokio::spawn(async move { axum::serve(listener, router).await });
+            Self {
+                server: Server {
+                    state,
+                    idle_stop: None,
+                },
+                base,
+                client: reqwest::Client::new(),
+                tmp,
+            }
+        }
+
+        fn state(&self) -> &AppState {
+            &self.server.state
+        }
+
+        /// Upload the mock PDF to `/extract?config=legal_br`.
+        async fn submit(&self) -> reqwest::Response {
+            let form = reqwest::multipart::Form::new().part(
+                "file",
+                reqwest::multipart::Part::bytes(b"%PDF-1.4 mock".to_vec()).file_name("autos.pdf"),
+            );
+            self.client
+                .post(format!("{}/extract?config=legal_br", self.base))
+                .multipart(form)
+                .send()
+                .await
+                .unwrap()
+        }
+
+        /// Upload the mock PDF and wait for its extraction to end.
+        async fn extract(&self) -> Extraction {
+            let queued: Extraction = self.submit().await.json().await.unwrap();
+            self.wait(&queued.id).await
+        }
+
+        /// Poll extraction `id` until it is no longer queued or running.
+        async fn wait(&self, id: &str) -> Extraction {
+            let mut extraction = self.state().extractions.get(id).unwrap();
+            for _ in 0..100 {
+                if !extraction.status.is_active() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                extraction = self.state().extractions.get(id).unwrap();
+            }
+            extraction
+        }
+
+        async fn get_json(&self, path: &str) -> serde_json::Value {
+            self.client
+                .get(format!("{}{}", self.base, path))
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap()
+        }
+    }
+
+    impl Drop for MockServer {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.tmp).ok();
+        }
+    }
+
+    /// The whole HTTP pipeline against the mock LLM and OCR fixtures.
+    #[tokio::test]
+    async fn test_extract_with_mocks() {
+        let mock = MockServer::start().await;
+        let extraction = mock.extract().await;
+
+        assert_eq!(extraction.status, ExtractionStatus::Completed);
+        assert_eq!(extraction.total_pages, Some(4));
+        assert_eq!(extraction.ocr_provider.as_deref(), Some("mock"));
+        assert_eq!(
+            extraction.readable_id.as_deref(),
+            Some("0001234-56.2024.8.26.0100")
+        );
+        assert_eq!(extraction.language.as_deref(), Some("pt"));
+        let ids: Vec<&str> = extraction.children.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, vec!["peticao_inicial", "sentenca"]);
+        // The mocked answer has no structure_map; it comes from the tree
+        let mapped: Vec<&str> = extraction
+            .structure_map
+            .iter()
+            .map(|e| e.id.as_str())
+            .collect();
+        assert_eq!(mapped, ids);
+        assert_eq!(extraction.relationships.len(), 1);
+        assert!(extraction
+            .reference_index
+            .to_string()
+            .contains("12345678909"));
+    }
+
+    /// Uploads are checked before anything is queued.
+    #[tokio::test]
+    async fn test_extract_rejects_unsupported_upload() {
+        let mock = MockServer::start().await;
+        let form = reqwest::multipart::Form::new().part(
+            "file",
+            reqwest::multipart::Part::bytes(b"PK\x03\x04".to_vec()).file_name("autos.docx"),
+        );
+        let rejected = mock
+            .client
+            .post(format!("{}/extract?config=legal_br", mock.base))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            rejected.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        let body: serde_json::Value = rejected.json().await.unwrap();
+        assert_eq!(body["error"], "unsupported_file_type");
+        assert_eq!(mock.state().extractions.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_content_and_batch() {
+        let mock = MockServer::start().await;
+        mock.extract().await;
+
+        let chunk = mock.get_json("/content/sentenca").await;
+        assert!(chunk["content"]
+            .as_str()
+            .is_some_and(|c| c.contains("JULGO PROCEDENTE")));
+
+        let batch: serde_json::Value = mock
+            .client
+            .post(format!("{}/content/batch", mock.base))
+            .json(&serde_json::json!({"refs": [
+                {"ref": "content://sentenca", "limit": 10},
+                {"ref": "missing"}
+            ]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            batch["chunks"][0]["content"]
+                .as_str()
+                .map(|c| c.chars().count()),
+            Some(10)
+        );
+        assert_eq!(batch["chunks"][0]["has_more"], true);
+        assert!(batch["chunks"][1]["error"].is_string());
+        assert_eq!(batch["total_chars"], 10);
+        assert_eq!(batch["truncated"], false);
+        let refs = vec![serde_json::json!({"ref": "sentenca"}); CONTENT_BATCH_MAX_REFS + 1];
+        let too_many = mock
+            .client
+            .post(format!("{}/content/batch", mock.base))
+            .json(&serde_json::json!({ "refs": refs }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(too_many.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    /// The snapshot inlines contents only within the budget.
+    #[tokio::test]
+    async fn test_snapshot_inline_budget() {
+        let mock = MockServer::start().await;
+        let extraction = mock.extract().await;
+        for (budget, inlined) in [(0, 0), (1_000_000, 2)] {
+            let snapshot = mock
+                .get_json(&format!(
+                    "/extractions/{}/snapshot?inline_budget_chars={}",
+                    extraction.id, budget
+                ))
+                .await;
+            let index = snapshot["content_index"].as_array().unwrap();
+            let count = index.iter().filter(|m| m["lazy"] == false).count();
+            assert_eq!(count, inlined);
+            assert_eq!(snapshot["content_blobs_included"], inlined > 0);
+        }
+    }
+
+    /// Raw OCR output is kept in the content store without an object store.
+    #[tokio::test]
+    async fn test_ocr_output_window() {
+        let mock = MockServer::start().await;
+        let extraction = mock.extract().await;
+        let window = mock
+            .get_json(&format!("/extractions/{}/ocr?limit=3", extraction.id))
+            .await;
+        assert_eq!(window["pages"].as_array().map(Vec::len), Some(3));
+        assert_eq!(window["has_more"], true);
+        let page = mock
+            .client
+            .get(format!(
+                "{}/extractions/{}/ocr?page=4",
+                mock.base, extraction.id
+            ))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(!page.is_empty());
+    }
+
+    /// Only failed extractions are retried; the retry resumes from the kept OCR.
+    #[tokio::test]
+    async fn test_retry_failed_extraction() {
+        let mock = MockServer::start().await;
+        let extraction = mock.extract().await;
+        let retry_url = format!("{}/extractions/{}/retry", mock.base, extraction.id);
+        let conflict = mock.client.post(&retry_url).send().await.unwrap();
+        assert_eq!(conflict.status(), reqwest::StatusCode::CONFLICT);
+
+        fail_extraction(mock.state(), &extraction.id, "LLM timed out".to_string());
+        let queued: Extraction = mock
+            .client
+            .post(&retry_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(queued.retry_count, 1);
+        let retried = mock.wait(&extraction.id).await;
+        assert_eq!(retried.status, ExtractionStatus::Completed);
+        assert_eq!(retried.retry_count, 1);
+        assert_eq!(retried.children.len(), 2);
+    }
+
+    /// An exhibit node re-extracted with another config, from its own pages.
+    #[tokio::test]
+    async fn test_extract_exhibit_node() {
+        let mock = MockServer::start().await;
+        let extraction = mock.extract().await;
+        let node_url = format!(
+            "{}/extractions/{}/node/sentenca/extract",
+            mock.base, extraction.id
+        );
+        let unmapped = mock.client.post(&node_url).send().await.unwrap();
+        assert_eq!(unmapped.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // With no `config`, the parent's config picks one by the node's subtype
+        let mut mapped = mock.state().configs.get("legal_br").unwrap();
+        mapped
+            .exhibit_configs
+            .insert("Sentença".to_string(), "invoice".to_string());
+        mock.state().configs.insert(mapped);
+        let queued: Extraction = mock
+            .client
+            .post(&node_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let child = mock.wait(&queued.id).await;
+        assert_eq!(child.status, ExtractionStatus::Completed);
+        assert_eq!(child.config_name.as_deref(), Some("invoice"));
+        assert_eq!(
+            child.parent_extraction_id.as_deref(),
+            Some(extraction.id.as_str())
+        );
+        assert_eq!(child.parent_node_id.as_deref(), Some("sentenca"));
+        assert_eq!(child.total_pages, Some(2));
+        // Its pages 1-2 are pages 3-4 of the parent's PDF
+        assert_eq!(child.children[0].pdf_page_range, Some([3, 4]));
+        let parent = mock.state().extractions.get(&extraction.id).unwrap();
+        assert_eq!(
+            find_node(&parent.children, "sentenca")
+                .unwrap()
+                .child_extraction_id,
+            Some(child.id.clone())
+        );
+    }
+
+    /// The same pipeline in-process, as `generic-extractor extract` runs it.
+    #[tokio::test]
+    async fn test_extract_in_process() {
+        let mock = MockServer::start().await;
+        let server = &mock.server;
+        let input = OcrInput::Bytes {
+            filename: "autos.pdf".to_string(),
+            data: b"%PDF-1.4 mock".to_vec(),
+        };
+        let extraction = server
+            .extract(input, Some("legal_br"), OcrProviderKind::Docling, false)
+            .await
+            .unwrap();
+        assert_eq!(extraction.children.len(), 2);
+        // One-shot runs do not stay in memory
+        assert!(!server.state.extractions.contains(&extraction.id));
+        let written = server
+            .export_content(&extraction, &mock.tmp.join("content"))
+            .unwrap();
+        assert_eq!(written, 2);
+        let sentenca = std::fs::read_to_string(mock.tmp.join("content/sentenca.txt")).unwrap();
+        assert!(sentenca.contains("JULGO PROCEDENTE"));
+        assert!(server
+            .extract(
+                OcrInput::Bytes {
+                    filename: "x.pdf".to_string(),
+                    data: Vec::new(),
+                },
+                Some("nope"),
+                OcrProviderKind::Docling,
+                false,
+            )
+            .await
+            .is_err());
+    }
+
+    /// The 4-page mock document is over `max_pages` once OCR counts it.
+    #[tokio::test]
+    async fn test_quota_max_pages() {
+        let mock = MockServer::start().await;
+        let mut limited = mock.state().configs.get("legal_br").unwrap();
+        limited.quotas = Some(config::QuotaConfig {
+            max_pages: Some(2),
+            ..Default::default()
+        });
+        mock.state().configs.insert(limited);
+        let too_long = mock.extract().await;
+        assert_eq!(too_long.status, ExtractionStatus::Failed);
+        assert!(too_long
+            .error
+            .is_some_and(|e| e.contains("4 pages; config 'legal_br' allows at most 2")));
+    }
+
+    #[tokio::test]
+    async fn test_quota_max_concurrent() {
+        let mock = MockServer::start().await;
+        let mut limited = mock.state().configs.get("legal_br").unwrap();
+        limited.quotas = Some(config::QuotaConfig {
+            max_concurrent: Some(0),
+            ..Default::default()
+        });
+        mock.state().configs.insert(limited);
+        let refused = mock.submit().await;
+        assert_eq!(refused.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        let body: serde_json::Value = refused.json().await.unwrap();
+        assert_eq!(body["error"], "quota_exceeded");
+    }
+
+    /// An extraction known only to storage still has its contents inlined.
+    #[tokio::test]
+    async fn test_snapshot_inlines_stored_content() {
+        use storage::Storage;
+
+        let tmp = std::env::temp_dir().join(format!("extractor_mock_{}", uuid::Uuid::new_v4()));
+        let mut state = mock_state(&tmp);
+        let storage = storage::sqlite::SqliteStorage::open(tmp.join("db.sqlite").to_str().unwrap())
+            .await
+            .unwrap();
+
+        let mut extraction = Extraction::new("autos.pdf".to_string(), Some("legal_br".to_string()));
+        extraction.status = ExtractionStatus::Completed;
+        extraction.children = serde_json::from_value(serde_json::json!([{
+            "id": "peticao",
+            "type": "PETICAO",
+            "summary": "Pedido de danos",
+            "content_ref": "content://peticao"
+        }, {
+            "id": "sentenca",
+            "type": "DECISAO",
+            "summary": "Julga procedente",
+            "content_ref": "content://sentenca"
+        }]))
+        .unwrap();
+        let uploaded = ContentStore::new();
+        uploaded.store("peticao", "O autor pede danos morais".to_string());
+        uploaded.store("sentenca", "JULGO PROCEDENTE o pedido".to_string());
+        storage
+            .upload_extraction(&extraction, &uploaded)
+            .await
+            .unwrap();
+        state.storage = Some(Arc::new(storage));
+
+        let query =
+            serde_json::from_value(serde_json::json!({"inline_budget_chars": 1000})).unwrap();
+        let response = get_extraction_snapshot(
+            State(state.clone()),
+            Path(extraction.id.clone()),
+            Query(query),
+            HeaderMap::new(),
+        )
+        .await
+        .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let snapshot: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(snapshot["inlined_chars"], 50);
+        let index = snapshot["content_index"].as_array().unwrap();
+        assert!(index.iter().all(|m| m["lazy"] == false));
+        assert_eq!(index[1]["content"], "JULGO PROCEDENTE o pedido");
+        std::fs::remove_dir_all(tmp).ok();
+    }
+}
@@ -0,0 +1,1056 @@
+//! Redis-backed state shared by the replicas of one deployment.
+//!
+//! Extractions and datasets live in each process's memory, so a poll that
+//! lands on another replica behind the load balancer would not find a job
+//! still running elsewhere. With `REDIS_URL` set, each replica writes
+//! through to Redis, under `REDIS_KEY_PREFIX` (default `extractor`):
+//!
+//! - `{prefix}:extraction:{id}`, `{prefix}:dataset:{id}` — a JSON snapshot
+//!   of every in-memory extraction and dataset, rewritten on each change.
+//!   Replicas read it before their own copy (unless they are running the
+//!   job themselves) and before storage.
+//! - `{prefix}:content:{node_id}` — node content, so any replica can serve
+//!   `GET /content/...` for a document another one extracted.
+//! - `{prefix}:jobs` — a hash of the running jobs' journal records with the
+//!   replica running each. Every replica refreshes
+//!   `{prefix}:replica:{name}` while it is up, over a connection of its
+//!   own; the jobs of a replica whose key expired are claimed by another one
+//!   and resumed the same way as after a restart.
+//! - `{prefix}:owner:{id}` — the replica that last started or claimed a
+//!   job. A run checks it between stages and stops once another replica
+//!   took the job over, so a replica that only stalled never uploads a job
+//!   twice; it also clears the job's `{prefix}:jobs` entry only while it
+//!   still owns it.
+//! - `{prefix}:queue` (and `:queue:high`, `:queue:low` for the other
+//!   priority lanes) — with `JOB_QUEUE=redis`, new extractions wait here
+//!   instead of running where they were submitted. Each replica pops one
+//!   whenever it has a free run slot, trying the lanes in the weighted
+//!   order of [`crate::jobs::lane_order`]; the pop and the entry in
+//!   `{prefix}:jobs` happen in one script, so a job is never lost between
+//!   the two. Jobs taken over from a stopped replica go back to the front.
+//!
+//! Snapshots and content expire after `REDIS_TTL_SECS` (default one day);
+//! storage stays the system of record. Writes are queued and sent in the
+//! background, so a slow or unreachable Redis never blocks a request; past
+//! [`WRITE_QUEUE_CAPACITY`] queued writes, new ones are dropped. A changed
+//! extraction or dataset is only marked dirty: its snapshot is serialized
+//! [`SNAPSHOT_DEBOUNCE`] later, once for all the changes made meanwhile.
+//! The client speaks RESP over plain TCP and handles one command at a time.
+//! A command that takes longer than [`COMMAND_TIMEOUT`] fails, and a command
+//! that is cancelled or fails before its reply is fully read drops the
+//! connection, so the next command never reads a stale reply.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex, Notify};
+use tracing::{error, info, warn};
+
+use crate::jobs::{JobRecord, Priority};
+
+const DEFAULT_PREFIX: &str = "extractor";
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+/// How often a replica refreshes its liveness key, and how long the key lives.
+const HEARTBEAT: Duration = Duration::from_secs(10);
+const HEARTBEAT_TTL_SECS: u64 = 30;
+/// Longest one command (write and reply) may take before the connection is dropped.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most writes waiting to be sent; further ones are dropped.
+pub const WRITE_QUEUE_CAPACITY: usize = 10_000;
+/// How long a changed snapshot waits for more changes before it is written.
+pub const SNAPSHOT_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Pop the oldest job of the first non-empty lane among `KEYS[1..3]` and
+/// record it in `KEYS[4]` as run by replica `ARGV[1]`. The record is
+/// spliced in as-is, so it round-trips exactly.
+const CLAIM_SCRIPT: &str = r#"
+local job
+for i = 1, 3 do
+  job = redis.call('RPOP', KEYS[i])
+  if job then break end
+end
+if job then
+  local id = cjson.decode(job).id
+  redis.call('HSET', KEYS[4], id, '{"replica":' .. cjson.encode(ARGV[1]) .. ',"record":' .. job .. '}')
+  redis.call('SET', ARGV[2] .. id, ARGV[1], 'EX', ARGV[3])
+end
+return job
+"#;
+
+/// Take job `ARGV[1]` from the shared journal `KEYS[1]` if replica
+/// `ARGV[2]` still holds it, and make `ARGV[3]` its owner (`KEYS[2]`).
+const TAKEOVER_SCRIPT: &str = r#"
+local job = redis.call('HGET', KEYS[1], ARGV[1])
+if job and cjson.decode(job).replica == ARGV[2] then
+  redis.call('HDEL', KEYS[1], ARGV[1])
+  redis.call('SET', KEYS[2], ARGV[3], 'EX', ARGV[4])
+  return 1
+end
+return 0
+"#;
+
+/// Clear job `ARGV[1]` from `KEYS[1]` and its owner `KEYS[2]`, unless
+/// another replica than `ARGV[2]` has taken it over.
+const FINISH_SCRIPT: &str = r#"
+local owner = redis.call('GET', KEYS[2])
+if owner and owner ~= ARGV[2] then
+  return 0
+end
+redis.call('HDEL', KEYS[1], ARGV[1])
+redis.call('DEL', KEYS[2])
+return 1
+"#;
+
+/// Count the jobs of config `ARGV[1]` waiting in the lanes `KEYS[1..3]` or
+/// listed as running in `KEYS[4]`. Unreadable entries are not counted.
+const COUNT_SCRIPT: &str = r#"
+local function config_of(json, field)
+  local ok, value = pcall(cjson.decode, json)
+  if not ok then return nil end
+  if field then value = value[field] end
+  return type(value) == 'table' and value.config_name or nil
+end
+local n = 0
+for i = 1, 3 do
+  for _, job in ipairs(redis.call('LRANGE', KEYS[i], 0, -1)) do
+    if config_of(job) == ARGV[1] then n = n + 1 end
+  end
+end
+for _, entry in ipairs(redis.call('HVALS', KEYS[4])) do
+  if config_of(entry, 'record') == ARGV[1] then n = n + 1 end
+end
+return n
+"#;
+
+/// A job record in the shared journal, with the replica running it.
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedJob {
+    replica: String,
+    record: JobRecord,
+}
+
+pub struct SharedState {
+    client: RedisClient,
+    /// Only for the liveness key, so a busy or stalled `client` can't let it expire
+    heartbeat: RedisClient,
+    prefix: String,
+    ttl_secs: u64,
+    replica: String,
+    /// Whether new extractions go through `{prefix}:queue` (`JOB_QUEUE=redis`)
+    queue: bool,
+    writes: mpsc::Sender<Vec<String>>,
+    queued: std::sync::Mutex<Option<mpsc::Receiver<Vec<String>>>>,
+    /// Snapshots changed since the last flush, by kind and id, and whether
+    /// the change was a removal
+    dirty: std::sync::Mutex<HashMap<(&'static str, String), bool>>,
+    flush: Notify,
+    /// How to read the current value of each kind of snapshot
+    sources: std::sync::Mutex<HashMap<&'static str, SnapshotSource>>,
+}
+
+/// Reads an in-memory value as snapshot JSON; `None` when it is gone.
+type SnapshotSource = Box<dyn Fn(&str) -> Option<serde_json::Result<String>> + Send + Sync>;
+
+impl SharedState {
+    /// Connect to `REDIS_URL`, or `None` when it is unset. The replica is
+    /// named by `REPLICA_ID`, else `HOSTNAME`, else a random id.
+    pub async fn from_env() -> Result<Option<Arc<Self>>> {
+        let queue = match std::env::var("JOB_QUEUE").as_deref() {
+            Ok("redis") => true,
+            Ok("local") | Err(_) => false,
+            Ok(other) => bail!("Unknown JOB_QUEUE '{}'. Available: local, redis", other),
+        };
+        let Some(url) = std::env::var("REDIS_URL").ok().filter(|v| !v.is_empty()) else {
+            if queue {
+                bail!("JOB_QUEUE=redis needs REDIS_URL");
+            }
+            return Ok(None);
+        };
+        let target = parse_redis_url(&url)?;
+        let client = RedisClient::new(target.clone());
+        client
+            .command(&["PING"])
+            .await
+            .with_context(|| format!("Redis at {} unreachable", client.target.addr))?;
+
+        let (writes, queued) = mpsc::channel(WRITE_QUEUE_CAPACITY);
+        Ok(Some(Arc::new(Self {
+            client,
+            heartbeat: RedisClient::new(target),
+            prefix: std::env::var("REDIS_KEY_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.into()),
+            ttl_secs: std::env::var("REDIS_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_TTL_SECS),
+            replica: std::env::var("REPLICA_ID")
+                .or_else(|_| std::env::var("HOSTNAME"))
+                .unwrap_or_else(|_| format!("replica_{}", uuid::Uuid::new_v4().simple())),
+            queue,
+            writes,
+            queued: std::sync::Mutex::new(Some(queued)),
+            dirty: Default::default(),
+            flush: Notify::new(),
+            sources: Default::default(),
+        })))
+    }
+
+    /// Whether new extractions are queued for any replica to run.
+    pub fn queues_jobs(&self) -> bool {
+        self.queue
+    }
+
+    /// Human-readable description for the startup log.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} as replica {} ({}:*, snapshots kept {}s{})",
+            self.client.target.addr,
+            self.replica,
+            self.prefix,
+            self.ttl_secs,
+            if self.queue { ", shared job queue" } else { "" }
+        )
+    }
+
+    /// Start sending queued writes and dirty snapshots, and refreshing this
+    /// replica's liveness key. Call once.
+    pub fn spawn(self: &Arc<Self>) {
+        let Some(mut queued) = self.queued.lock().unwrap().take() else {
+            return;
+        };
+        let shared = self.clone();
+        tokio::spawn(async move {
+            while let Some(args) = queued.recv().await {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                if let Err(e) = shared.client.command(&args).await {
+                    error!("Redis {} {} failed: {:#}", args[0], args[1], e);
+                }
+            }
+        });
+
+        let shared = self.clone();
+        tokio::spawn(async move {
+            loop {
+                shared.flush.notified().await;
+                tokio::time::sleep(SNAPSHOT_DEBOUNCE).await;
+                shared.flush_snapshots();
+            }
+        });
+
+        let shared = self.clone();
+        tokio::spawn(async move {
+            let key = shared.key(&["replica", &shared.replica]);
+            let ttl = HEARTBEAT_TTL_SECS.to_string();
+            let mut interval = tokio::time::interval(HEARTBEAT);
+            loop {
+                interval.tick().await;
+                if let Err(e) = shared
+                    .heartbeat
+                    .command(&["SET", &key, "1", "EX", &ttl])
+                    .await
+                {
+                    warn!(
+                        "Redis heartbeat for replica {} failed: {:#}",
+                        shared.replica, e
+                    );
+                }
+            }
+        });
+    }
+
+    fn key(&self, parts: &[&str]) -> String {
+        let mut key = self.prefix.clone();
+        for part in parts {
+            key.push(':');
+            key.push_str(part);
+        }
+        key
+    }
+
+    fn queue(&self, args: Vec<String>) {
+        // The receiver only goes away with the runtime
+        if let Err(mpsc::error::TrySendError::Full(args)) = self.writes.try_send(args) {
+            error!(
+                "Redis write queue is full; dropping {} {}",
+                args[0], args[1]
+            );
+        }
+    }
+
+    /// Read the snapshots of `kind` marked with [`SharedState::mark_snapshot`]
+    /// through `read` when they are flushed.
+    pub fn mirror<T: Serialize>(
+        &self,
+        kind: &'static str,
+        read: impl Fn(&str) -> Option<T> + Send + Sync + 'static,
+    ) {
+        let source = move |id: &str| read(id).map(|value| serde_json::to_string(&value));
+        self.sources.lock().unwrap().insert(kind, Box::new(source));
+    }
+
+    /// Note that an extraction or dataset changed (or, with `removed`, is
+    /// gone). Cheap enough to call under a lock: the snapshot is read and
+    /// written later, once for every change made until then.
+    pub fn mark_snapshot(&self, kind: &'static str, id: &str, removed: bool) {
+        self.dirty
+            .lock()
+            .unwrap()
+            .insert((kind, id.to_string()), removed);
+        self.flush.notify_one();
+    }
+
+    /// Queue the writes of every snapshot marked since the last flush.
+    fn flush_snapshots(&self) {
+        let dirty = std::mem::take(&mut *self.dirty.lock().unwrap());
+        let sources = self.sources.lock().unwrap();
+        for ((kind, id), removed) in dirty {
+            if removed {
+                self.queue(vec!["DEL".into(), self.key(&[kind, &id])]);
+                continue;
+            }
+            // Gone without a removal: forgotten, and another replica's now
+            match sources.get(kind).and_then(|read| read(&id)) {
+                Some(Ok(json)) => self.queue(self.snapshot_write(kind, &id, json)),
+                Some(Err(e)) => error!("Failed to serialize {} {} for Redis: {}", kind, id, e),
+                None => {}
+            }
+        }
+    }
+
+    fn snapshot_write(&self, kind: &str, id: &str, json: String) -> Vec<String> {
+        vec![
+            "SET".into(),
+            self.key(&[kind, id]),
+            json,
+            "EX".into(),
+            self.ttl_secs.to_string(),
+        ]
+    }
+
+    /// Write (or, with `None`, delete) the snapshot of an extraction or
+    /// dataset; `kind` is `extraction` or `dataset`.
+    pub fn put_snapshot<T: Serialize>(&self, kind: &str, id: &str, value: Option<&T>) {
+        match value.map(serde_json::to_string) {
+            Some(Ok(json)) => self.queue(self.snapshot_write(kind, id, json)),
+            Some(Err(e)) => error!("Failed to serialize {} {} for Redis: {}", kind, id, e),
+            None => self.queue(vec!["DEL".into(), self.key(&[kind, id])]),
+        }
+    }
+
+    /// The snapshot another replica wrote, if any.
+    pub async fn snapshot<T: DeserializeOwned>(&self, kind: &str, id: &str) -> Option<T> {
+        let json = self.get(&self.key(&[kind, id])).await?;
+        serde_json::from_str(&json)
+            .map_err(|e| {
+                warn!(
+                    "Ignoring unreadable Redis snapshot of {} {}: {}",
+                    kind, id, e
+                )
+            })
+            .ok()
+    }
+
+    /// Write (or, with `None`, delete) a node's content.
+    pub fn put_content(&self, node_id: &str, content: Option<&str>) {
+        let key = self.key(&["content", node_id]);
+        match content {
+            Some(content) => self.queue(vec![
+                "SET".into(),
+                key,
+                content.to_string(),
+                "EX".into(),
+                self.ttl_secs.to_string(),
+            ]),
+            None => self.queue(vec!["DEL".into(), key]),
+        }
+    }
+
+    pub async fn content(&self, node_id: &str) -> Option<String> {
+        self.get(&self.key(&["content", node_id])).await
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        match self.client.command(&["GET", key]).await {
+            Ok(Reply::Bulk(Some(bytes))) => String::from_utf8(bytes).ok(),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Redis GET {} failed: {:#}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Record a job as run by this replica.
+    pub fn job_started(&self, record: &JobRecord) {
+        let job = SharedJob {
+            replica: self.replica.clone(),
+            record: record.clone(),
+        };
+        match serde_json::to_string(&job) {
+            Ok(json) => {
+                // Owner first: once the job is listed, another replica may take it over
+                self.queue(vec![
+                    "SET".into(),
+                    self.key(&["owner", &record.id]),
+                    self.replica.clone(),
+                    "EX".into(),
+                    self.ttl_secs.to_string(),
+                ]);
+                self.queue(vec![
+                    "HSET".into(),
+                    self.key(&["jobs"]),
+                    record.id.clone(),
+                    json,
+                ]);
+            }
+            Err(e) => error!("Failed to serialize job {} for Redis: {}", record.id, e),
+        }
+    }
+
+    /// Clear a job from the shared journal, unless another replica took it over.
+    pub fn job_finished(&self, id: &str) {
+        self.queue(vec![
+            "EVAL".into(),
+            FINISH_SCRIPT.into(),
+            "2".into(),
+            self.key(&["jobs"]),
+            self.key(&["owner", id]),
+            id.to_string(),
+            self.replica.clone(),
+        ]);
+    }
+
+    /// Whether this replica still owns a job it runs. If Redis can't tell,
+    /// the run goes on.
+    pub async fn owns_job(&self, id: &str) -> bool {
+        let key = self.key(&["owner", id]);
+        match self.client.command(&["GET", &key]).await {
+            Ok(Reply::Bulk(Some(owner))) => owner == self.replica.as_bytes(),
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Redis GET {} failed: {:#}", key, e);
+                true
+            }
+        }
+    }
+
+    /// Put a job at the back of the shared queue. Unlike the other writes
+    /// this waits for Redis, so the caller knows the job was taken.
+    pub async fn enqueue(&self, record: &JobRecord) -> Result<()> {
+        self.push("LPUSH", record).await
+    }
+
+    /// Put a job taken over from a stopped replica at the front of the queue.
+    pub async fn requeue(&self, record: &JobRecord) -> Result<()> {
+        self.push("RPUSH", record).await
+    }
+
+    async fn push(&self, command: &str, record: &JobRecord) -> Result<()> {
+        let json = serde_json::to_string(record)?;
+        self.client
+            .command(&[command, &self.queue_key(record.priority), &json])
+            .await?;
+        Ok(())
+    }
+
+    /// The list a lane's jobs wait in; the normal lane keeps the plain name.
+    fn queue_key(&self, lane: Priority) -> String {
+        match lane {
+            Priority::Normal => self.key(&["queue"]),
+            lane => self.key(&["queue", lane.as_str()]),
+        }
+    }
+
+    /// Take the oldest job of the first lane in `lanes` that has one,
+    /// recording it as run by this replica.
+    pub async fn claim_queued_job(&self, lanes: [Priority; 3]) -> Option<JobRecord> {
+        let [first, second, third] = lanes.map(|lane| self.queue_key(lane));
+        let reply = self
+            .client
+            .command(&[
+                "EVAL",
+                CLAIM_SCRIPT,
+                "4",
+                &first,
+                &second,
+                &third,
+                &self.key(&["jobs"]),
+                &self.replica,
+                &self.key(&["owner", ""]),
+                &self.ttl_secs.to_string(),
+            ])
+            .await;
+        match reply {
+            Ok(Reply::Bulk(Some(json))) => serde_json::from_slice(&json)
+                .map_err(|e| error!("Dropping unreadable queued job: {}", e))
+                .ok(),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Claiming a queued job failed: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// How many jobs of `config` are queued or running on any replica. If
+    /// Redis can't tell, none are.
+    pub async fn active_jobs(&self, config: &str) -> usize {
+        let [first, second, third] = Priority::ALL.map(|lane| self.queue_key(lane));
+        let reply = self
+            .client
+            .command(&[
+                "EVAL",
+                COUNT_SCRIPT,
+                "4",
+                &first,
+                &second,
+                &third,
+                &self.key(&["jobs"]),
+                config,
+            ])
+            .await;
+        match reply {
+            Ok(Reply::Int(n)) => n.max(0) as usize,
+            Ok(_) => 0,
+            Err(e) => {
+                warn!("Counting the jobs of {} failed: {:#}", config, e);
+                0
+            }
+        }
+    }
+
+    /// Take over the jobs of replicas that stopped refreshing their
+    /// liveness key. A job is claimed by whichever replica removes it from
+    /// the shared journal first.
+    pub async fn claim_orphaned_jobs(&self) -> Vec<JobRecord> {
+        let jobs_key = self.key(&["jobs"]);
+        let entries = match self.client.command(&["HGETALL", &jobs_key]).await {
+            Ok(Reply::Array(Some(entries))) => entries,
+            Ok(_) => return Vec::new(),
+            Err(e) => {
+                warn!("Redis HGETALL {} failed: {:#}", jobs_key, e);
+                return Vec::new();
+            }
+        };
+
+        let mut claimed = Vec::new();
+        for pair in entries.chunks(2) {
+            let [Reply::Bulk(Some(id)), Reply::Bulk(Some(json))] = pair else {
+                continue;
+            };
+            let id = String::from_utf8_lossy(id).into_owned();
+            let Ok(job) = serde_json::from_slice::<SharedJob>(json) else {
+                warn!("Ignoring unreadable shared job record {}", id);
+                continue;
+            };
+            if job.replica == self.replica {
+                continue;
+            }
+            let alive = self
+                .client
+                .command(&["EXISTS", &self.key(&["replica", &job.replica])])
+                .await;
+            if !matches!(alive, Ok(Reply::Int(0))) {
+                continue;
+            }
+            let takeover = self
+                .client
+                .command(&[
+                    "EVAL",
+                    TAKEOVER_SCRIPT,
+                    "2",
+                    &jobs_key,
+                    &self.key(&["owner", &id]),
+                    &id,
+                    &job.replica,
+                    &self.replica,
+                    &self.ttl_secs.to_string(),
+                ])
+                .await;
+            if let Ok(Reply::Int(1)) = takeover {
+                info!(
+                    "Claimed job {} from replica {}, which stopped responding",
+                    id, job.replica
+                );
+                claimed.push(job.record);
+            }
+        }
+        claimed
+    }
+}
+
+/// Log whether replicas share state, and through which Redis.
+pub fn log_config(shared: Option<&SharedState>) {
+    match shared {
+        Some(shared) => info!("Shared state enabled: {}", shared.describe()),
+        None => info!("Shared state disabled (set REDIS_URL to run several replicas)"),
+    }
+}
+
+/// Where to reach Redis, from `redis://[[user]:password@]host[:port][/db]`.
+#[derive(Debug, Clone, PartialEq)]
+struct RedisTarget {
+    /// `host:port`
+    addr: String,
+    username: Option<String>,
+    password: Option<String>,
+    db: Option<u32>,
+}
+
+fn parse_redis_url(url: &str) -> Result<RedisTarget> {
+    let rest = url
+        .strip_prefix("redis://")
+        .ok_or_else(|| anyhow!("REDIS_URL must start with redis:// (got '{}')", url))?;
+    let (host, db) = match rest.split_once('/') {
+        Some((host, "")) => (host, None),
+        Some((host, db)) => (
+            host,
+            Some(
+                db.parse()
+                    .map_err(|_| anyhow!("REDIS_URL has an invalid database '{}'", db))?,
+            ),
+        ),
+        None => (rest, None),
+    };
+    let (credentials, host) = match host.rsplit_once('@') {
+        Some((credentials, host)) => (Some(credentials), host),
+        None => (None, host),
+    };
+    let (username, password) = match credentials.map(|c| c.split_once(':').unwrap_or(("", c))) {
+        Some((user, pass)) => (
+            Some(user.to_string()).filter(|u| !u.is_empty()),
+            Some(pass.to_string()).filter(|p| !p.is_empty()),
+        ),
+        None => (None, None),
+    };
+    if host.is_empty() {
+        bail!("REDIS_URL has no host (got '{}')", url);
+    }
+    Ok(RedisTarget {
+        addr: if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:6379", host)
+        },
+        username,
+        password,
+        db,
+    })
+}
+
+/// An error reply from Redis. The reply was read in full, so unlike I/O
+/// errors it leaves the connection usable.
+#[derive(Debug)]
+struct RedisError(String);
+
+impl std::fmt::Display for RedisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Redis error: {}", self.0)
+    }
+}
+
+impl std::error::Error for RedisError {}
+
+/// A RESP reply; error replies are returned as `Err`.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Int(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+/// One connection, reopened after any I/O error.
+struct RedisClient {
+    target: RedisTarget,
+    conn: Mutex<Option<BufStream<TcpStream>>>,
+}
+
+impl RedisClient {
+    fn new(target: RedisTarget) -> Self {
+        Self {
+            target,
+            conn: Mutex::new(None),
+        }
+    }
+
+    async fn command(&self, args: &[&str]) -> Result<Reply> {
+        let mut conn = self.conn.lock().await;
+        if conn.is_none() {
+            *conn = Some(self.connect().await?);
+        }
+        // Dropped (with the connection) if this future is cancelled mid-command
+        let mut guard = ConnGuard {
+            conn: &mut conn,
+            keep: false,
+        };
+        let stream = guard.conn.as_mut().expect("connected above");
+        let result = tokio::time::timeout(COMMAND_TIMEOUT, async {
+            stream.write_all(&encode(args)).await?;
+            stream.flush().await?;
+            read_reply(stream).await
+        })
+        .await
+        .unwrap_or_else(|_| {
+            Err(anyhow!(
+                "Redis {} timed out after {}s",
+                args[0],
+                COMMAND_TIMEOUT.as_secs()
+            ))
+        });
+        guard.keep = result
+            .as_ref()
+            .map_or_else(|e| e.is::<RedisError>(), |_| true);
+        result
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>> {
+        tokio::time::timeout(COMMAND_TIMEOUT, self.open())
+            .await
+            .map_err(|_| anyhow!("timed out connecting to {}", self.target.addr))?
+    }
+
+    async fn open(&self) -> Result<BufStream<TcpStream>> {
+        let stream = TcpStream::connect(&self.target.addr).await?;
+        let mut stream = BufStream::new(stream);
+        let mut setup: Vec<Vec<&str>> = Vec::new();
+        if let Some(ref password) = self.target.password {
+            match self.target.username {
+                Some(ref user) => setup.push(vec!["AUTH", user, password]),
+                None => setup.push(vec!["AUTH", password]),
+            }
+        }
+        let db = self.target.db.map(|db| db.to_string());
+        if let Some(ref db) = db {
+            setup.push(vec!["SELECT", db]);
+        }
+        for args in setup {
+            stream.write_all(&encode(&args)).await?;
+            stream.flush().await?;
+            read_reply(&mut stream)
+                .await
+                .with_context(|| format!("Redis {} failed", args[0]))?;
+        }
+        Ok(stream)
+    }
+}
+
+/// Drops the connection on the way out unless `keep` was set once the
+/// reply was read in full.
+struct ConnGuard<'a> {
+    conn: &'a mut Option<BufStream<TcpStream>>,
+    keep: bool,
+}
+
+impl Drop for ConnGuard<'_> {
+    fn drop(&mut self) {
+        if !self.keep {
+            *self.conn = None;
+        }
+    }
+}
+
+/// A command as a RESP array of bulk strings.
+fn encode(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend(arg.as_bytes());
+        out.extend(b"\r\n");
+    }
+    out
+}
+
+async fn read_reply<R: AsyncBufRead + Unpin + Send>(reader: &mut R) -> Result<Reply> {
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line).await?;
+    if !line.ends_with(b"\r\n") {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    let text = String::from_utf8_lossy(&line[1..line.len() - 2]).into_owned();
+    let length = || -> Result<i64> {
+        text.parse()
+            .map_err(|_| anyhow!("malformed Redis reply '{}'", text))
+    };
+    Ok(match line[0] {
+        b'+' => Reply::Simple(text),
+        b'-' => return Err(RedisError(text).into()),
+        b':' => Reply::Int(length()?),
+        b'$' => match usize::try_from(length()?) {
+            Ok(len) => {
+                let mut data = vec![0; len + 2];
+                reader.read_exact(&mut data).await?;
+                data.truncate(len);
+                Reply::Bulk(Some(data))
+            }
+            Err(_) => Reply::Bulk(None),
+        },
+        b'*' => match usize::try_from(length()?) {
+            Ok(len) => {
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    // The rest of the array is unread, so the connection can't be kept
+                    let item = Box::pin(read_reply(reader)).await.map_err(|e| match e
+                        .downcast::<RedisError>()
+                    {
+                        Ok(e) => anyhow!("{}", e),
+                        Err(e) => e,
+                    })?;
+                    items.push(item);
+                }
+                Reply::Array(Some(items))
+            }
+            Err(_) => Reply::Array(None),
+        },
+        other => bail!("unknown Redis reply type '{}'", other as char),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_redis_url() {
+        let target = parse_redis_url("redis://:s3cret@cache.internal/2").unwrap();
+        assert_eq!(target.addr, "cache.internal:6379");
+        assert_eq!(target.username, None);
+        assert_eq!(target.password.as_deref(), Some("s3cret"));
+        assert_eq!(target.db, Some(2));
+        let target = parse_redis_url("redis://app:pw@10.0.0.7:6380").unwrap();
+        assert_eq!(target.username.as_deref(), Some("app"));
+        assert_eq!(target.addr, "10.0.0.7:6380");
+        assert!(parse_redis_url("rediss://cache").is_err());
+    }
+
+    /// A Redis stand-in that answers the commands it gets, in order, with
+    /// `replies`, and returns the commands.
+    async fn mock_redis(
+        replies: Vec<String>,
+    ) -> (RedisTarget, tokio::task::JoinHandle<Vec<Reply>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = RedisTarget {
+            addr: listener.local_addr().unwrap().to_string(),
+            username: None,
+            password: None,
+            db: None,
+        };
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufStream::new(stream);
+            let mut commands = Vec::new();
+            for reply in replies {
+                commands.push(read_reply(&mut stream).await.unwrap());
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                stream.flush().await.unwrap();
+            }
+            commands
+        });
+        (target, server)
+    }
+
+    fn shared_state(target: RedisTarget, replica: &str) -> SharedState {
+        let (writes, queued) = mpsc::channel(WRITE_QUEUE_CAPACITY);
+        SharedState {
+            client: RedisClient::new(target.clone()),
+            heartbeat: RedisClient::new(target),
+            prefix: "test".into(),
+            ttl_secs: DEFAULT_TTL_SECS,
+            replica: replica.into(),
+            queue: true,
+            writes,
+            queued: std::sync::Mutex::new(Some(queued)),
+            dirty: Default::default(),
+            flush: Notify::new(),
+            sources: Default::default(),
+        }
+    }
+
+    /// The arguments of a command the mock received.
+    fn args(command: &Reply) -> Vec<&[u8]> {
+        let Reply::Array(Some(args)) = command else {
+            panic!("expected a command, got {:?}", command);
+        };
+        args.iter()
+            .map(|a| match a {
+                Reply::Bulk(Some(bytes)) => bytes.as_slice(),
+                other => panic!("expected a bulk string, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_writes_coalesce() {
+        let shared = shared_state(parse_redis_url("redis://localhost").unwrap(), "r1");
+        let values: Arc<std::sync::Mutex<HashMap<String, u32>>> = Default::default();
+        let source = values.clone();
+        shared.mirror("extraction", move |id| {
+            source.lock().unwrap().get(id).copied()
+        });
+
+        for stage in 1..=3 {
+            values.lock().unwrap().insert("ext_1".into(), stage);
+            shared.mark_snapshot("extraction", "ext_1", false);
+        }
+        shared.mark_snapshot("extraction", "ext_2", true);
+        // Forgotten since it was marked: another replica's snapshot now
+        shared.mark_snapshot("extraction", "ext_3", false);
+        shared.flush_snapshots();
+
+        let mut queued = shared.queued.lock().unwrap().take().unwrap();
+        let mut writes = Vec::new();
+        while let Ok(args) = queued.try_recv() {
+            writes.push(args[..3.min(args.len())].join(" "));
+        }
+        writes.sort();
+        assert_eq!(
+            writes,
+            vec!["DEL test:extraction:ext_2", "SET test:extraction:ext_1 3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claim_queued_job() {
+        let queued = serde_json::json!({
+            "id": "ext_1",
+            "kind": "extraction",
+            "source_file": "contrato.pdf",
+            "config_name": "legal_br",
+            "upload": true,
+            "started_at": "2026-10-17T12:00:00Z",
+        })
+        .to_string();
+        let (target, server) = mock_redis(vec![
+            format!("${}\r\n{}\r\n", queued.len(), queued),
+            "$-1\r\n".into(),
+        ])
+        .await;
+
+        let shared = shared_state(target, "worker-2");
+        let lanes = crate::jobs::lane_order(3);
+        let record = shared.claim_queued_job(lanes).await.unwrap();
+        assert_eq!(record.id, "ext_1");
+        assert_eq!(record.config_name, "legal_br");
+        assert!(shared.claim_queued_job(lanes).await.is_none());
+
+        let commands = server.await.unwrap();
+        let args = args(&commands[0]);
+        assert_eq!(args[0], b"EVAL");
+        assert_eq!(
+            &args[2..],
+            [
+                &b"4"[..],
+                b"test:queue:low",
+                b"test:queue:high",
+                b"test:queue",
+                b"test:jobs",
+                b"worker-2",
+                b"test:owner:",
+                b"86400"
+            ]
+        );
+    }
+
+    /// A replica whose liveness key is still there keeps its jobs, and a
+    /// replica that lost a job to a takeover learns so before it uploads.
+    #[tokio::test]
+    async fn test_takeover_of_live_replica() {
+        let job = serde_json::json!({
+            "replica": "worker-1",
+            "record": {
+                "id": "ext_1",
+                "kind": "extraction",
+                "source_file": "contrato.pdf",
+                "config_name": "legal_br",
+                "upload": true,
+                "started_at": "2026-10-17T12:00:00Z",
+            }
+        })
+        .to_string();
+        let (target, server) = mock_redis(vec![
+            format!("*2\r\n$5\r\next_1\r\n${}\r\n{}\r\n", job.len(), job),
+            ":1\r\n".into(),
+            "$8\r\nworker-2\r\n".into(),
+        ])
+        .await;
+
+        let shared = shared_state(target, "worker-2");
+        assert!(shared.claim_orphaned_jobs().await.is_empty());
+        let stalled = SharedState {
+            replica: "worker-1".into(),
+            ..shared
+        };
+        assert!(!stalled.owns_job("ext_1").await);
+
+        let commands = server.await.unwrap();
+        assert_eq!(
+            args(&commands[1]),
+            [&b"EXISTS"[..], b"test:replica:worker-1"]
+        );
+        assert_eq!(args(&commands[2]), [&b"GET"[..], b"test:owner:ext_1"]);
+    }
+
+    /// A command cancelled before its reply arrives must not leave that
+    /// reply for the next command.
+    #[tokio::test]
+    async fn test_cancelled_command_drops_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = RedisClient::new(RedisTarget {
+            addr: listener.local_addr().unwrap().to_string(),
+            username: None,
+            password: None,
+            db: None,
+        });
+        let server = tokio::spawn(async move {
+            let (first, _) = listener.accept().await.unwrap();
+            let mut first = BufStream::new(first);
+            read_reply(&mut first).await.unwrap();
+            let (second, _) = listener.accept().await.unwrap();
+            let mut second = BufStream::new(second);
+            // The stale reply only now reaches the first connection
+            first.write_all(b"$5\r\nstale\r\n").await.unwrap();
+            first.flush().await.unwrap();
+            read_reply(&mut second).await.unwrap();
+            second.write_all(b"+PONG\r\n").await.unwrap();
+            second.flush().await.unwrap();
+        });
+
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(100), client.command(&["GET", "k"])).await;
+        assert!(cancelled.is_err());
+        assert_eq!(
+            client.command(&["PING"]).await.unwrap(),
+            Reply::Simple("PONG".into())
+        );
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resp_round_trip() {
+        assert_eq!(
+            encode(&["SET", "k", "v1"]),
+            b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$2\r\nv1\r\n"
+        );
+
+        let mut reply: &[u8] = b"*3\r\n$2\r\nid\r\n$-1\r\n:1\r\n";
+        assert_eq!(
+            read_reply(&mut reply).await.unwrap(),
+            Reply::Array(Some(vec![
+                Reply::Bulk(Some(b"id".to_vec())),
+                Reply::Bulk(None),
+                Reply::Int(1),
+            ]))
+        );
+        let mut error: &[u8] = b"-WRONGTYPE Operation against a key\r\n";
+        assert!(read_reply(&mut error)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("WRONGTYPE"));
+    }
+}
@@ -2,17 +2,40 @@
 //!
 //! Phase 1: Single-turn extraction — sends a data sample to the LLM which discovers
 //! schemas, defines column types, and classifies rows.
+//!
+//! When more than one schema is found, a second pass sends the rows in batches
+//! of [`CLASSIFY_BATCH_ROWS`] and asks which schema each belongs to, so a sheet
+//! that interleaves record types (transactions and balance lines) is split
+//! between them instead of being copied into every schema.
+//!
+//! Sheets whose header the parser could not place with confidence get a
+//! short LLM check first, on their opening rows.
+
+use std::borrow::Cow;
+use std::ops::Range;
 
-use crate::config::ExtractionConfig;
+use crate::config::{ExpectedColumn, ExtractionConfig, SheetConfig, SheetMode};
+use crate::extractor::LlmParseError;
+use crate::numeric::{self, NumberLocale};
 use crate::openrouter::{Message, OpenRouterClient};
-use crate::sheet_parser::RawSheet;
+use crate::sheet_parser::{header_key, ColumnHint, RawSheet};
 use crate::sheet_schema::{ColumnDef, DataSchema, SchemaRelationship, SheetExtraction};
 use anyhow::{Context, Result};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 /// Maximum rows to include in the data sample sent to the LLM.
 const MAX_SAMPLE_ROWS: usize = 50;
 
+/// Rows sent per row classification call.
+const CLASSIFY_BATCH_ROWS: usize = 200;
+
+/// Opening rows shown when asking the LLM where a sheet's header is.
+const HEADER_CHECK_ROWS: usize = 15;
+
+/// Share of `expected_columns` every sheet must match by header, required
+/// ones included, for deterministic mode to skip the LLM.
+const DETERMINISTIC_MIN_MATCH: f64 = 0.5;
+
 /// Sheet extraction pipeline orchestrator.
 pub struct SheetExtractor {
     client: OpenRouterClient,
@@ -37,6 +60,37 @@ impl SheetExtractor {
             config.name
         );
 
+        if let Some(sheet_config) = config
+            .sheet_config
+            .as_ref()
+            .filter(|c| c.mode == SheetMode::Deterministic)
+        {
+            let name = sheet_config.schema_name.as_deref().unwrap_or(&config.name);
+            match map_expected_columns(sheets, sheet_config, name) {
+                Some(schema) => {
+                    let mut extraction =
+                        SheetExtraction::new(filename.to_string(), Some(config.name.clone()));
+                    extraction.summary = format!(
+                        "{} rows from {} sheet(s) mapped onto the {} configured columns of '{}'.",
+                        schema.row_count,
+                        sheets.len(),
+                        schema.columns.len(),
+                        schema.name
+                    );
+                    info!("Deterministic mapping: {} rows, LLM skipped", schema.row_count);
+                    extraction.schemas = vec![schema];
+                    return Ok(extraction);
+                }
+                None => warn!(
+                    "Sheets of {} don't match the configured columns well; falling back to LLM schema discovery",
+                    filename
+                ),
+            }
+        }
+
+        let sheets = self.confirm_headers(sheets).await;
+        let sheets: &[RawSheet] = &sheets;
+
         let total_rows: usize = sheets.iter().map(|s| s.rows.len()).sum();
         info!("Total rows across all sheets: {}", total_rows);
 
@@ -59,6 +113,7 @@ Rules:
 - Column names should be lowercase_snake_case
 - Every row must belong to exactly one schema
 - Non-tabular context (headers, annotations) should become metadata columns
+- Column types listed under "Cell formats" come from the workbook itself; use them
 - Be specific about data types: "string", "integer", "float", "date", "currency_brl", "currency_usd", "boolean"
 
 Available transforms you may assign to columns:
@@ -98,6 +153,9 @@ Available transforms you may assign to columns:
                         if let Some(ref fmt) = c.format {
                             desc.push_str(&format!(" [{}]", fmt));
                         }
+                        if !c.aliases.is_empty() {
+                            desc.push_str(&format!(" (headers: {})", c.aliases.join(", ")));
+                        }
                         if c.required {
                             desc.push_str(" *required*");
                         }
@@ -157,7 +215,7 @@ Available transforms you may assign to columns:
 
         // Parse LLM response
         let discovered: DiscoveredSchemas =
-            parse_llm_json(&response).context("Failed to parse LLM schema response")?;
+            parse_llm_json(&response).map_err(|e| LlmParseError::new("schema", e, &response))?;
 
         info!(
             "Discovered {} schema(s), {} relationship(s)",
@@ -165,8 +223,41 @@ Available transforms you may assign to columns:
             discovered.relationships.len()
         );
 
+        // Sheets may mix record types: ask which schema each row belongs to.
+        // Without an answer, every sheet is mapped into every schema as before.
+        let classes = if discovered.schemas.len() > 1 {
+            match self.classify_rows(sheets, &discovered.schemas).await {
+                Ok(classes) => {
+                    info!(
+                        "Classified rows: {} assigned, {} without a schema",
+                        classes.assigned(),
+                        total_rows - classes.assigned()
+                    );
+                    Some(classes)
+                }
+                Err(e) => {
+                    warn!(
+                        "Row classification failed, mapping every sheet into every schema: {:#}",
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         // Map raw rows to discovered schemas
-        let populated_schemas = map_rows_to_schemas(sheets, discovered.schemas)?;
+        let include_formulas = config
+            .sheet_config
+            .as_ref()
+            .is_some_and(|c| c.include_formulas);
+        let populated_schemas = map_rows_to_schemas(
+            sheets,
+            discovered.schemas,
+            classes.as_ref(),
+            include_formulas,
+        )?;
 
         // Build result
         let mut extraction = SheetExtraction::new(filename.to_string(), Some(config.name.clone()));
@@ -190,6 +281,186 @@ Available transforms you may assign to columns:
 
         Ok(extraction)
     }
+
+    /// Ask the LLM about sheets whose detected header was a close call.
+    /// A failed check keeps the detected header.
+    async fn confirm_headers<'a>(&self, sheets: &'a [RawSheet]) -> Cow<'a, [RawSheet]> {
+        if sheets.iter().all(|s| s.header_confident) {
+            return Cow::Borrowed(sheets);
+        }
+
+        let mut sheets = sheets.to_vec();
+        for sheet in sheets.iter_mut().filter(|s| !s.header_confident) {
+            let header = match self.locate_header(sheet).await {
+                Ok(header) => header,
+                Err(e) => {
+                    warn!(
+                        "Header check failed for sheet '{}', keeping the detected header: {:#}",
+                        sheet.name, e
+                    );
+                    continue;
+                }
+            };
+            let detected = sheet.has_header.then_some(sheet.header_row_index);
+            if header != detected && sheet.set_header(header) {
+                info!(
+                    "Sheet '{}': header moved from {:?} to {:?}",
+                    sheet.name, detected, header
+                );
+            }
+        }
+        Cow::Owned(sheets)
+    }
+
+    /// Which of the sheet's opening rows is its header, if any.
+    async fn locate_header(&self, sheet: &RawSheet) -> Result<Option<usize>> {
+        let mut lines = String::new();
+        for (i, row) in sheet.source_rows().take(HEADER_CHECK_ROWS).enumerate() {
+            lines.push_str(&format!("{} | {} |\n", i, row.join(" | ")));
+        }
+        let messages = vec![
+            Message::system(
+                r#"You are a tabular data analyst. You receive the opening rows of a spreadsheet, each prefixed with its line number. Some spreadsheets have title lines above the column header; some have no header at all and start with data.
+
+Return ONLY valid JSON: {"header_row": <line number of the column header, or null if there is none>}"#
+                    .to_string(),
+            ),
+            Message::user(format!("Sheet: \"{}\"\n{}", sheet.name, lines)),
+        ];
+        let response = self.client.chat(messages).await?;
+        let answer: HeaderAnswer =
+            parse_llm_json(&response).map_err(|e| LlmParseError::new("header", e, &response))?;
+        Ok(answer.header_row)
+    }
+
+    /// Assign every row to one of `schemas`, one LLM call per batch of rows.
+    async fn classify_rows(
+        &self,
+        sheets: &[RawSheet],
+        schemas: &[DiscoveredSchema],
+    ) -> Result<RowClasses> {
+        let system_prompt = format!(
+            r#"You are a tabular data analyst. The rows you receive come from a spreadsheet that mixes several record types. These are the schemas found in it:
+
+{}
+
+Assign every row to exactly one schema. Rows that hold no record (blank lines, titles, repeated headers) get no schema.
+
+Return ONLY valid JSON with this structure:
+
+{{
+  "assignments": [
+    {{"schema": "schema_name", "rows": [[first, last], row]}}
+  ]
+}}
+
+"rows" lists the row numbers shown at the start of each line, as single numbers or inclusive [first, last] ranges."#,
+            describe_schemas(schemas)
+        );
+        let names: Vec<&str> = schemas.iter().map(|s| s.name.as_str()).collect();
+
+        let mut classes = RowClasses::new(sheets);
+        for (sheet_idx, sheet) in sheets.iter().enumerate() {
+            for start in (0..sheet.rows.len()).step_by(CLASSIFY_BATCH_ROWS) {
+                let batch = start..(start + CLASSIFY_BATCH_ROWS).min(sheet.rows.len());
+                debug!(
+                    "Classifying rows {}..{} of sheet '{}'",
+                    batch.start, batch.end, sheet.name
+                );
+                let messages = vec![
+                    Message::cached_system(system_prompt.clone()),
+                    Message::user(build_row_batch(sheet, batch.clone())),
+                ];
+                let response = self.client.chat(messages).await?;
+                let assignments: RowAssignments = parse_llm_json(&response)
+                    .map_err(|e| LlmParseError::new("row_classes", e, &response))?;
+                classes.apply(sheet_idx, batch, &assignments, &names);
+            }
+        }
+        Ok(classes)
+    }
+}
+
+/// Schema names, descriptions, and columns, one schema per paragraph.
+fn describe_schemas(schemas: &[DiscoveredSchema]) -> String {
+    schemas
+        .iter()
+        .map(|s| {
+            let columns: Vec<&str> = s.columns.iter().map(|c| c.name.as_str()).collect();
+            format!(
+                "- {}: {}\n  columns: {}",
+                s.name,
+                s.description,
+                columns.join(", ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The rows of `batch`, each line prefixed with its row number.
+fn build_row_batch(sheet: &RawSheet, batch: Range<usize>) -> String {
+    let mut text = format!(
+        "Sheet: \"{}\" (rows {}-{} of {})\nheader | {} |\n",
+        sheet.name,
+        batch.start,
+        batch.end.saturating_sub(1),
+        sheet.rows.len(),
+        sheet.headers.join(" | ")
+    );
+    for (i, row) in sheet.rows[batch.clone()].iter().enumerate() {
+        text.push_str(&format!("{} | {} |\n", batch.start + i, row.join(" | ")));
+    }
+    text
+}
+
+/// Which schema each row belongs to, per sheet; `None` for rows that hold
+/// no record or that the LLM left out.
+struct RowClasses {
+    sheets: Vec<Vec<Option<usize>>>,
+}
+
+impl RowClasses {
+    fn new(sheets: &[RawSheet]) -> Self {
+        Self {
+            sheets: sheets.iter().map(|s| vec![None; s.rows.len()]).collect(),
+        }
+    }
+
+    /// Record one batch's answer. Rows outside `batch` and unknown schema
+    /// names are ignored.
+    fn apply(
+        &mut self,
+        sheet: usize,
+        batch: Range<usize>,
+        assignments: &RowAssignments,
+        names: &[&str],
+    ) {
+        for assignment in &assignments.assignments {
+            let wanted = assignment.schema.trim();
+            let Some(schema) = names.iter().position(|n| n.eq_ignore_ascii_case(wanted)) else {
+                warn!("Row classification named unknown schema '{}'", wanted);
+                continue;
+            };
+            for range in &assignment.rows {
+                let (first, last) = match *range {
+                    RowRange::One(row) => (row, row),
+                    RowRange::Span([first, last]) => (first, last),
+                };
+                for row in first.max(batch.start)..=last.min(batch.end.saturating_sub(1)) {
+                    self.sheets[sheet][row] = Some(schema);
+                }
+            }
+        }
+    }
+
+    fn of(&self, sheet: usize, row: usize) -> Option<usize> {
+        self.sheets.get(sheet)?.get(row).copied().flatten()
+    }
+
+    fn assigned(&self) -> usize {
+        self.sheets.iter().flatten().filter(|c| c.is_some()).count()
+    }
 }
 
 /// Build a readable text representation of sheet data for the LLM prompt.
@@ -199,6 +470,36 @@ fn build_data_sample(sheets: &[RawSheet], max_rows: usize) -> String {
     for sheet in sheets {
         let mut section = format!("Sheet: \"{}\" ({} rows)\n", sheet.name, sheet.rows.len());
 
+        for line in &sheet.preamble {
+            let cells: Vec<&str> = line
+                .iter()
+                .map(|c| c.trim())
+                .filter(|c| !c.is_empty())
+                .collect();
+            section.push_str(&format!("Above the table: {}\n", cells.join(" ")));
+        }
+        if !sheet.has_header {
+            section.push_str("(no header row; columns are numbered)\n");
+        }
+        let typed: Vec<String> = sheet
+            .headers
+            .iter()
+            .zip(&sheet.column_hints)
+            .filter_map(|(header, hint)| {
+                let mut desc = format!("{}: {}", header, hint.data_type?);
+                if let Some(ref format) = hint.format {
+                    desc.push_str(&format!(" [{}]", format));
+                }
+                if let Some(ref formula) = hint.formula {
+                    desc.push_str(&format!(", computed as {}", formula));
+                }
+                Some(desc)
+            })
+            .collect();
+        if !typed.is_empty() {
+            section.push_str(&format!("Cell formats: {}\n", typed.join("; ")));
+        }
+
         // Header row
         section.push_str(&format!("| {} |\n", sheet.headers.join(" | ")));
         section.push_str(&format!(
@@ -235,17 +536,36 @@ fn build_data_sample(sheets: &[RawSheet], max_rows: usize) -> String {
 /// 2. **Positional fallback**: map columns by index position when name matching fails.
 ///    Common for OCR-extracted tables where "headers" are actually the first data row.
 ///    Used when column count is close (sheet cols ≥ schema cols - 1).
+///
+/// With `classes`, a schema only gets the rows classified into it; a
+/// headerless table's header row follows its first row.
+///
+/// A column's type comes from the first sheet column mapped to it that has a
+/// workbook type (see [`ColumnHint`]), overriding the LLM's guess; its formula
+/// is kept with `include_formulas`.
 fn map_rows_to_schemas(
     sheets: &[RawSheet],
     schemas: Vec<DiscoveredSchema>,
+    classes: Option<&RowClasses>,
+    include_formulas: bool,
 ) -> Result<Vec<DataSchema>> {
     let mut result = Vec::new();
 
-    for schema in schemas {
+    for (schema_idx, schema) in schemas.into_iter().enumerate() {
         let column_names: Vec<&str> = schema.columns.iter().map(|c| c.name.as_str()).collect();
         let mut rows = Vec::new();
+        let mut hints: Vec<Option<&ColumnHint>> = vec![None; column_names.len()];
+        let mut note_hint = |schema_col: usize, sheet_idx: usize, sheet_col: usize| {
+            let hint = sheets[sheet_idx].column_hints.get(sheet_col);
+            if hints[schema_col].is_none() && hint.is_some_and(|h| h.data_type.is_some()) {
+                hints[schema_col] = hint;
+            }
+        };
+
+        for (sheet_idx, sheet) in sheets.iter().enumerate() {
+            let belongs =
+                |row: usize| classes.is_none_or(|c| c.of(sheet_idx, row) == Some(schema_idx));
 
-        for sheet in sheets {
             // Build header-to-index mapping
             let header_map: std::collections::HashMap<String, usize> = sheet
                 .headers
@@ -255,12 +575,13 @@ fn map_rows_to_schemas(
                 .collect();
 
             // Try name-based matching first
-            let name_matched: Vec<(&str, usize)> = column_names
+            let name_matched: Vec<(usize, &str, usize)> = column_names
                 .iter()
-                .filter_map(|col| {
+                .enumerate()
+                .filter_map(|(i, col)| {
                     header_map
                         .get(&col.to_lowercase())
-                        .map(|&idx| (*col, idx))
+                        .map(|&idx| (i, *col, idx))
                 })
                 .collect();
 
@@ -268,10 +589,17 @@ fn map_rows_to_schemas(
             let use_name_matching = name_matched.len() * 2 >= column_names.len();
 
             if use_name_matching && !name_matched.is_empty() {
+                for &(schema_col, _, idx) in &name_matched {
+                    note_hint(schema_col, sheet_idx, idx);
+                }
+
                 // Name-based mapping
-                for raw_row in &sheet.rows {
+                for (row_idx, raw_row) in sheet.rows.iter().enumerate() {
+                    if !belongs(row_idx) {
+                        continue;
+                    }
                     let mut obj = serde_json::Map::new();
-                    for (col_name, idx) in &name_matched {
+                    for (_, col_name, idx) in &name_matched {
                         let value = raw_row
                             .get(*idx)
                             .map(|v| v.as_str())
@@ -299,11 +627,16 @@ fn map_rows_to_schemas(
                     "Using positional mapping for schema '{}' on sheet '{}' ({} sheet cols → {} schema cols)",
                     schema.name, sheet.name, sheet_cols, schema_cols
                 );
+                for i in 0..mappable {
+                    note_hint(i, sheet_idx, i);
+                }
 
-                // The "headers" row is actually data for headerless tables — include it
-                let include_header_as_data = name_matched.is_empty();
+                // A detected header that matches no schema column may be a
+                // data row the parser took for a header — include it
+                let include_header_as_data =
+                    sheet.header_rows.len() == 1 && name_matched.is_empty();
 
-                if include_header_as_data {
+                if include_header_as_data && belongs(0) {
                     let mut obj = serde_json::Map::new();
                     for (i, col_name) in column_names.iter().enumerate().take(mappable) {
                         let value = &sheet.headers[i];
@@ -312,7 +645,10 @@ fn map_rows_to_schemas(
                     rows.push(serde_json::Value::Object(obj));
                 }
 
-                for raw_row in &sheet.rows {
+                for (row_idx, raw_row) in sheet.rows.iter().enumerate() {
+                    if !belongs(row_idx) {
+                        continue;
+                    }
                     let mut obj = serde_json::Map::new();
                     for (i, col_name) in column_names.iter().enumerate().take(mappable) {
                         let value = raw_row
@@ -329,6 +665,12 @@ fn map_rows_to_schemas(
             }
         }
 
+        let transforms = schema
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.transform.as_deref()));
+        apply_numeric_transforms(&mut rows, transforms);
+
         let row_count = rows.len();
         info!(
             "Schema '{}': mapped {} rows from {} sheets",
@@ -341,24 +683,183 @@ fn map_rows_to_schemas(
             columns: schema
                 .columns
                 .into_iter()
-                .map(|c| ColumnDef {
+                .zip(hints)
+                .map(|(c, hint)| ColumnDef {
                     name: c.name,
-                    data_type: c.data_type,
-                    format: c.format,
+                    data_type: hint
+                        .and_then(|h| h.data_type)
+                        .map_or(c.data_type, str::to_string),
+                    format: c.format.or_else(|| hint.and_then(|h| h.format.clone())),
                     transform: c.transform,
                     required: c.required,
                     source: c.source,
                     description: c.description,
+                    formula: hint
+                        .filter(|_| include_formulas)
+                        .and_then(|h| h.formula.clone()),
                 })
                 .collect(),
             row_count,
             rows,
+            sources: Vec::new(),
+            version: 1,
+            changes: Vec::new(),
+            stats: Vec::new(),
+            edited: false,
+            edits: Vec::new(),
         });
     }
 
+    for schema in &mut result {
+        schema.refresh_stats();
+    }
     Ok(result)
 }
 
+/// Deterministic mode: one schema made of the configured columns, with
+/// every sheet's rows mapped onto them by header (name or alias) or, for a
+/// sheet without a header, by position. `None` when a sheet matches too
+/// few columns or misses a required one.
+fn map_expected_columns(
+    sheets: &[RawSheet],
+    sheet_config: &SheetConfig,
+    name: &str,
+) -> Option<DataSchema> {
+    let expected = &sheet_config.expected_columns;
+    if expected.is_empty() {
+        return None;
+    }
+
+    let mut rows = Vec::new();
+    let mut hints: Vec<Option<&ColumnHint>> = vec![None; expected.len()];
+    for sheet in sheets {
+        let pairs = match_expected_columns(sheet, expected)?;
+        for &(col, sheet_col) in &pairs {
+            hints[col] = hints[col].or(sheet.column_hints.get(sheet_col));
+        }
+        rows.extend(sheet.rows.iter().map(|raw_row| {
+            let obj = pairs
+                .iter()
+                .map(|&(col, sheet_col)| {
+                    let value = raw_row.get(sheet_col).map_or("", |v| v.as_str());
+                    (expected[col].name.clone(), value.into())
+                })
+                .collect();
+            serde_json::Value::Object(obj)
+        }));
+    }
+
+    let columns: Vec<ColumnDef> = expected
+        .iter()
+        .zip(hints)
+        .map(|(c, hint)| {
+            let data_type = c
+                .data_type
+                .clone()
+                .or_else(|| hint.and_then(|h| h.data_type).map(str::to_string))
+                .unwrap_or_else(|| "string".to_string());
+            ColumnDef {
+                name: c.name.clone(),
+                transform: c
+                    .transform
+                    .clone()
+                    .or_else(|| default_transform(&data_type).map(str::to_string)),
+                data_type,
+                format: c.format.clone().or_else(|| hint.and_then(|h| h.format.clone())),
+                required: c.required,
+                source: None,
+                description: None,
+                formula: hint
+                    .filter(|_| sheet_config.include_formulas)
+                    .and_then(|h| h.formula.clone()),
+            }
+        })
+        .collect();
+    let transforms = columns
+        .iter()
+        .map(|c| (c.name.as_str(), c.transform.as_deref()));
+    apply_numeric_transforms(&mut rows, transforms);
+
+    let mut schema = DataSchema {
+        name: name.to_string(),
+        description: "Rows mapped onto the configured columns.".to_string(),
+        columns,
+        row_count: rows.len(),
+        rows,
+        sources: Vec::new(),
+        version: 1,
+        changes: Vec::new(),
+        stats: Vec::new(),
+        edited: false,
+        edits: Vec::new(),
+    };
+    schema.refresh_stats();
+    Some(schema)
+}
+
+/// `(expected column, sheet column)` pairs for one sheet, or `None` when
+/// the sheet doesn't match confidently.
+fn match_expected_columns(
+    sheet: &RawSheet,
+    expected: &[ExpectedColumn],
+) -> Option<Vec<(usize, usize)>> {
+    if !sheet.has_header {
+        return (sheet.headers.len() == expected.len())
+            .then(|| (0..expected.len()).map(|i| (i, i)).collect());
+    }
+
+    let header_keys: Vec<String> = sheet.headers.iter().map(|h| header_key(h)).collect();
+    let mut pairs: Vec<(usize, usize)> = Vec::new();
+    for (col, column) in expected.iter().enumerate() {
+        let names: Vec<String> = std::iter::once(&column.name)
+            .chain(&column.aliases)
+            .map(|n| header_key(n))
+            .collect();
+        let found = header_keys
+            .iter()
+            .enumerate()
+            .find(|(i, key)| names.contains(key) && pairs.iter().all(|(_, s)| s != i));
+        match found {
+            Some((sheet_col, _)) => pairs.push((col, sheet_col)),
+            None if column.required => return None,
+            None => {}
+        }
+    }
+    let share = pairs.len() as f64 / expected.len() as f64;
+    (share >= DETERMINISTIC_MIN_MATCH).then_some(pairs)
+}
+
+/// The transform that fits a configured column type, if any.
+fn default_transform(data_type: &str) -> Option<&'static str> {
+    match data_type {
+        "currency_brl" => Some("parse_currency_brl"),
+        "currency_usd" => Some("parse_currency_usd"),
+        "integer" => Some("to_integer"),
+        "float" => Some("to_number"),
+        _ => None,
+    }
+}
+
+/// Run the numeric column transforms (`parse_currency_brl`, `to_number`, ...)
+/// over mapped rows, given `(column, transform)` pairs. A value that doesn't
+/// parse keeps its extracted text.
+pub(crate) fn apply_numeric_transforms<'a>(
+    rows: &mut [serde_json::Value],
+    columns: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+) {
+    for (column, transform) in columns {
+        let Some(transform) = transform.filter(|t| NumberLocale::for_transform(t).is_some())
+        else {
+            continue;
+        };
+        for value in rows.iter_mut().filter_map(|row| row.get_mut(column)) {
+            if let Some(number) = numeric::apply_transform(transform, value) {
+                *value = number;
+            }
+        }
+    }
+}
+
 // ============================================================================
 // LLM response types
 // ============================================================================
@@ -396,6 +897,33 @@ struct DiscoveredColumn {
     description: Option<String>,
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct HeaderAnswer {
+    #[serde(default)]
+    header_row: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RowAssignments {
+    #[serde(default)]
+    assignments: Vec<RowAssignment>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RowAssignment {
+    schema: String,
+    #[serde(default)]
+    rows: Vec<RowRange>,
+}
+
+/// A row number or an inclusive `[first, last]` range.
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum RowRange {
+    One(usize),
+    Span([usize; 2]),
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct DiscoveredRelationship {
     from: String,
@@ -434,3 +962,128 @@ fn parse_llm_json<T: serde::de::DeserializeOwned>(response: &str) -> Result<T> {
         &json_str.chars().take(200).collect::<String>()
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sheet_parser::SourceType;
+
+    fn schema(name: &str, columns: &[&str]) -> DiscoveredSchema {
+        DiscoveredSchema {
+            name: name.to_string(),
+            description: String::new(),
+            columns: columns
+                .iter()
+                .map(|c| DiscoveredColumn {
+                    name: c.to_string(),
+                    data_type: "string".to_string(),
+                    format: None,
+                    transform: None,
+                    required: false,
+                    source: None,
+                    description: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_classified_rows_go_to_one_schema() {
+        let sheets = vec![RawSheet {
+            name: "extrato".to_string(),
+            headers: vec!["data".into(), "historico".into(), "valor".into()],
+            rows: vec![
+                vec!["01/03".into(), "PIX recebido".into(), "100,00".into()],
+                vec!["02/03".into(), "Tarifa".into(), "-5,00".into()],
+                vec!["".into(), "SALDO DO DIA".into(), "95,00".into()],
+                vec!["".into(), "".into(), "".into()],
+            ],
+            source_type: SourceType::Csv,
+            header_rows: Vec::new(),
+            header_row_index: 0,
+            has_header: true,
+            preamble: Vec::new(),
+            header_confident: true,
+            column_hints: Vec::new(),
+        }];
+        let schemas = vec![
+            schema("transacoes", &["data", "historico", "valor"]),
+            schema("saldos", &["historico", "valor"]),
+        ];
+        let answer: RowAssignments = parse_llm_json(
+            r#"```json
+{"assignments": [{"schema": "transacoes", "rows": [[0, 1]]}, {"schema": "SALDOS", "rows": [2, 9]}, {"schema": "outros", "rows": [3]}]}
+```"#,
+        )
+        .unwrap();
+        let mut classes = RowClasses::new(&sheets);
+        classes.apply(0, 0..4, &answer, &["transacoes", "saldos"]);
+        assert_eq!(classes.assigned(), 3);
+
+        let mapped = map_rows_to_schemas(&sheets, schemas, Some(&classes), false).unwrap();
+        assert_eq!(mapped[0].row_count, 2);
+        assert_eq!(mapped[0].rows[1]["historico"], "Tarifa");
+        assert_eq!(mapped[1].row_count, 1);
+        assert_eq!(mapped[1].rows[0]["valor"], "95,00");
+
+        // Without classes every schema still takes every row
+        let schemas = vec![schema("a", &["data", "valor"]), schema("b", &["valor"])];
+        let mapped = map_rows_to_schemas(&sheets, schemas, None, false).unwrap();
+        assert!(mapped.iter().all(|s| s.row_count == 4));
+    }
+
+    #[test]
+    fn test_deterministic_mapping_by_header_and_alias() {
+        let sheet_config: SheetConfig = serde_json::from_value(serde_json::json!({
+            "mode": "deterministic",
+            "expected_columns": [
+                {"name": "data", "data_type": "date", "required": true, "aliases": ["Data Lançamento"]},
+                {"name": "descricao", "data_type": "string"},
+                {"name": "valor", "data_type": "currency_brl", "required": true},
+                {"name": "categoria"}
+            ]
+        }))
+        .unwrap();
+        let csv = "Data Lançamento,Descrição,Valor\n01/03/2024,PIX,\"1.234,56\"\n02/03/2024,Tarifa,\"(5,00)\"\n";
+        let sheets =
+            crate::sheet_parser::parse_file("extrato.csv", csv.as_bytes(), Default::default())
+                .unwrap();
+
+        let schema = map_expected_columns(&sheets, &sheet_config, "transacoes").unwrap();
+        assert_eq!(schema.row_count, 2);
+        assert_eq!(schema.rows[0]["data"], "01/03/2024");
+        assert_eq!(schema.rows[0]["descricao"], "PIX");
+        assert_eq!(schema.rows[1]["valor"], -5.0);
+        assert!(schema.rows[0].get("categoria").is_none());
+        assert_eq!(schema.columns[2].transform.as_deref(), Some("parse_currency_brl"));
+
+        // A missing required column is a poor match: the LLM takes over
+        let csv = "Data Lançamento,Descrição\n01/03/2024,PIX\n";
+        let sheets =
+            crate::sheet_parser::parse_file("extrato.csv", csv.as_bytes(), Default::default())
+                .unwrap();
+        assert!(map_expected_columns(&sheets, &sheet_config, "transacoes").is_none());
+    }
+
+    #[test]
+    fn test_numeric_transforms_applied() {
+        let mut rows = vec![
+            serde_json::json!({"valor": "R$ 1.234,56", "qtd": "3"}),
+            serde_json::json!({"valor": "(50,00)", "qtd": "n/d"}),
+        ];
+        let mut columns = schema("t", &["valor", "qtd"]).columns;
+        columns[0].transform = Some("parse_currency_brl".to_string());
+        columns[1].transform = Some("to_integer".to_string());
+        apply_numeric_transforms(
+            &mut rows,
+            columns
+                .iter()
+                .map(|c| (c.name.as_str(), c.transform.as_deref())),
+        );
+
+        assert_eq!(rows[0]["valor"], 1234.56);
+        assert_eq!(rows[0]["qtd"], 3);
+        assert_eq!(rows[1]["valor"], -50.0);
+        assert_eq!(rows[1]["qtd"], "n/d");
+    }
+}
@@ -1,9 +1,23 @@
 //! Tabular data parsing for CSV, Excel (.xlsx/.xls/.xlsm), and OCR markdown tables.
-
+//!
+//! Exports often put title rows above the table or leave the header out, so
+//! the header is not assumed to be the first row: see [`detect_header`].
+//! Stacked header rows (a group row over sub-columns) are flattened into
+//! compound names like `Receita/2023`: see [`header_stack`].
+//!
+//! For Excel files, cell types, number formats, and formulas are summarized
+//! per column as [`ColumnHint`]s, so dates, currency, and percentages are typed
+//! from the workbook rather than guessed from rendered text.
+
+use crate::config::{DateLocale, SheetConfig};
+use crate::numeric::{self, NumberLocale};
 use crate::ocr::OcrResult;
+use crate::readable_id::fold_accent;
+use crate::xlsx_formats::{self, CellFormats, FormatKind};
 use anyhow::{Context, Result};
 use calamine::{open_workbook_from_rs, Data, Reader, Xlsx, Xlsb};
 use std::io::Cursor;
+use std::ops::Range;
 
 /// Source type of the parsed data.
 #[derive(Debug, Clone)]
@@ -17,13 +31,161 @@ pub enum SourceType {
 #[derive(Debug, Clone)]
 pub struct RawSheet {
     pub name: String,
+    /// Column names; `column_1`, `column_2`, … when the sheet has no header.
     pub headers: Vec<String>,
+    /// The rows `headers` came from; more than one when stacked header rows
+    /// were flattened.
+    pub header_rows: Vec<Vec<String>>,
     pub rows: Vec<Vec<String>>,
     pub source_type: SourceType,
+    /// Index of the (first) header row among the sheet's non-empty rows, which
+    /// is also the number of `preamble` rows. Without a header, the first data row.
+    pub header_row_index: usize,
+    pub has_header: bool,
+    /// Non-empty rows above the table: report titles, periods, account numbers.
+    pub preamble: Vec<Vec<String>>,
+    /// False when the header guess was a close call; the sheet extractor
+    /// asks the LLM to confirm those.
+    pub header_confident: bool,
+    /// What the workbook says about each column's data cells (Excel only;
+    /// empty for CSV and OCR tables).
+    pub column_hints: Vec<ColumnHint>,
+}
+
+/// A column's type, number format, and formula as stored in the workbook.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnHint {
+    /// One of the sheet data types (`date`, `currency_brl`, `integer`, …)
+    /// when every data cell agrees.
+    pub data_type: Option<&'static str>,
+    /// The number format code of the first formatted data cell.
+    pub format: Option<String>,
+    /// The first data cell's formula, when most of the column is computed.
+    pub formula: Option<String>,
 }
 
-/// Dispatch file parsing by extension.
-pub fn parse_file(filename: &str, data: &[u8]) -> Result<Vec<RawSheet>> {
+impl RawSheet {
+    /// Split a sheet's non-empty rows into preamble, header, and data.
+    /// `None` when no data rows remain.
+    fn from_rows(name: &str, rows: Vec<Vec<String>>, source_type: SourceType) -> Option<Self> {
+        let guess = detect_header(&rows);
+        let mut sheet = Self {
+            name: name.to_string(),
+            headers: Vec::new(),
+            header_rows: Vec::new(),
+            rows: Vec::new(),
+            source_type,
+            header_row_index: 0,
+            has_header: false,
+            preamble: Vec::new(),
+            header_confident: guess.confident,
+            column_hints: Vec::new(),
+        };
+        sheet.split(rows, guess.index, guess.has_header);
+        (!sheet.rows.is_empty()).then_some(sheet)
+    }
+
+    fn split(&mut self, mut rows: Vec<Vec<String>>, index: usize, has_header: bool) {
+        let (index, depth) = if has_header && index < rows.len() {
+            let stack = header_stack(&rows, index);
+            (stack.start, stack.len())
+        } else {
+            (index.min(rows.len()), 0)
+        };
+        let mut body = rows.split_off(index);
+        self.preamble = rows;
+        self.header_row_index = index;
+        self.has_header = depth > 0;
+        self.header_rows = body.drain(..depth).collect();
+        self.headers = if self.has_header {
+            flatten_headers(&self.header_rows)
+        } else {
+            let width = body.iter().map(|r| r.len()).max().unwrap_or(0);
+            (1..=width).map(|i| format!("column_{}", i)).collect()
+        };
+        self.rows = body;
+    }
+
+    /// The sheet's non-empty rows as parsed: preamble, header rows, then data.
+    pub fn source_rows(&self) -> impl Iterator<Item = &Vec<String>> {
+        self.preamble
+            .iter()
+            .chain(&self.header_rows)
+            .chain(&self.rows)
+    }
+
+    /// Re-split at `header`, an index into [`source_rows`](Self::source_rows),
+    /// or treat the current header row as data when `None`. Returns false,
+    /// leaving the sheet as it was, when that would leave no data rows.
+    pub fn set_header(&mut self, header: Option<usize>) -> bool {
+        let total = self.source_rows().count();
+        let (index, has_header) = match header {
+            Some(i) => (i, true),
+            None => (self.header_row_index, false),
+        };
+        if index + usize::from(has_header) >= total {
+            return false;
+        }
+        let rows = self.source_rows().cloned().collect();
+        self.split(rows, index, has_header);
+        self.header_confident = true;
+        true
+    }
+}
+
+/// How Excel date cells are written out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateStyle {
+    /// Serials count from 1904-01-01; read from the workbook.
+    pub date_1904: bool,
+    /// Minutes east of UTC appended to date-times.
+    pub utc_offset_minutes: Option<i32>,
+    pub locale: DateLocale,
+}
+
+impl DateStyle {
+    /// The style a sheet config asks for. An offset that doesn't parse is
+    /// ignored (config lint reports it).
+    pub fn from_config(config: Option<&SheetConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        let utc_offset_minutes = config.utc_offset.as_deref().and_then(|offset| {
+            let minutes = parse_utc_offset(offset);
+            if minutes.is_none() {
+                tracing::warn!("Ignoring invalid utc_offset \"{}\"", offset);
+            }
+            minutes
+        });
+        Self {
+            date_1904: false,
+            utc_offset_minutes,
+            locale: config.date_locale,
+        }
+    }
+}
+
+/// `"-03:00"`, `"+0530"`, `"Z"` → minutes east of UTC.
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let offset = offset.trim();
+    if offset.eq_ignore_ascii_case("z") || offset.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+    let (sign, rest) = match offset.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let digits = rest.replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes): (i32, i32) = (digits[..2].parse().ok()?, digits[2..].parse().ok()?);
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// Dispatch file parsing by extension. `dates` applies to Excel date cells.
+pub fn parse_file(filename: &str, data: &[u8], dates: DateStyle) -> Result<Vec<RawSheet>> {
     let ext = filename
         .rsplit('.')
         .next()
@@ -32,8 +194,8 @@ pub fn parse_file(filename: &str, data: &[u8]) -> Result<Vec<RawSheet>> {
 
     match ext.as_str() {
         "csv" => parse_csv(filename, data),
-        "xlsx" | "xlsm" => parse_excel_xlsx(data),
-        "xlsb" => parse_excel_xlsb(data),
+        "xlsx" | "xlsm" => parse_excel_xlsx(data, dates),
+        "xlsb" => parse_excel_xlsb(data, dates),
         _ => anyhow::bail!(
             "Unsupported file type: .{}. Supported: .csv, .xlsx, .xlsm, .xlsb",
             ext
@@ -45,25 +207,16 @@ pub fn parse_file(filename: &str, data: &[u8]) -> Result<Vec<RawSheet>> {
 fn parse_csv(filename: &str, data: &[u8]) -> Result<Vec<RawSheet>> {
     let mut reader = csv::ReaderBuilder::new()
         .flexible(true)
-        .has_headers(true)
+        .has_headers(false)
         .from_reader(data);
 
-    let headers: Vec<String> = reader
-        .headers()
-        .context("Failed to read CSV headers")?
-        .iter()
-        .map(|h| h.to_string())
-        .collect();
-
-    if headers.is_empty() {
-        anyhow::bail!("CSV file has no headers");
-    }
-
     let mut rows = Vec::new();
     for result in reader.records() {
         let record = result.context("Failed to read CSV record")?;
         let row: Vec<String> = record.iter().map(|f| f.to_string()).collect();
-        rows.push(row);
+        if !is_blank(&row) {
+            rows.push(row);
+        }
     }
 
     let name = filename
@@ -76,20 +229,25 @@ fn parse_csv(filename: &str, data: &[u8]) -> Result<Vec<RawSheet>> {
         .trim_end_matches(".csv")
         .to_string();
 
-    Ok(vec![RawSheet {
-        name,
-        headers,
-        rows,
-        source_type: SourceType::Csv,
-    }])
+    match RawSheet::from_rows(&name, rows, SourceType::Csv) {
+        Some(sheet) => Ok(vec![sheet]),
+        None => anyhow::bail!("CSV file has no data rows"),
+    }
 }
 
 /// Parse an xlsx/xlsm file. All worksheets become separate RawSheet entries.
-/// First row of each sheet is treated as headers.
-fn parse_excel_xlsx(data: &[u8]) -> Result<Vec<RawSheet>> {
+fn parse_excel_xlsx(data: &[u8], dates: DateStyle) -> Result<Vec<RawSheet>> {
     let cursor = Cursor::new(data);
     let mut workbook: Xlsx<_> =
         open_workbook_from_rs(cursor).context("Failed to open Excel workbook")?;
+    let formats = xlsx_formats::read_workbook_formats(data).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring cell formats: {:#}", e);
+        Default::default()
+    });
+    let dates = DateStyle {
+        date_1904: formats.date_1904,
+        ..dates
+    };
 
     let sheet_names: Vec<String> = workbook.sheet_names().to_vec();
     let mut sheets = Vec::new();
@@ -103,7 +261,14 @@ fn parse_excel_xlsx(data: &[u8]) -> Result<Vec<RawSheet>> {
             }
         };
 
-        if let Some(sheet) = range_to_raw_sheet(name, &range) {
+        let formulas = workbook.worksheet_formula(name).ok();
+        if let Some(sheet) = range_to_raw_sheet(
+            name,
+            &range,
+            formats.sheets.get(name),
+            formulas.as_ref(),
+            dates,
+        ) {
             sheets.push(sheet);
         }
     }
@@ -115,8 +280,9 @@ fn parse_excel_xlsx(data: &[u8]) -> Result<Vec<RawSheet>> {
     Ok(sheets)
 }
 
-/// Parse an xlsb file.
-fn parse_excel_xlsb(data: &[u8]) -> Result<Vec<RawSheet>> {
+/// Parse an xlsb file. Its date system isn't read, so 1904 workbooks come out
+/// four years early.
+fn parse_excel_xlsb(data: &[u8], dates: DateStyle) -> Result<Vec<RawSheet>> {
     let cursor = Cursor::new(data);
     let mut workbook: Xlsb<_> =
         open_workbook_from_rs(cursor).context("Failed to open Excel workbook")?;
@@ -133,7 +299,8 @@ fn parse_excel_xlsb(data: &[u8]) -> Result<Vec<RawSheet>> {
             }
         };
 
-        if let Some(sheet) = range_to_raw_sheet(name, &range) {
+        let formulas = workbook.worksheet_formula(name).ok();
+        if let Some(sheet) = range_to_raw_sheet(name, &range, None, formulas.as_ref(), dates) {
             sheets.push(sheet);
         }
     }
@@ -145,45 +312,114 @@ fn parse_excel_xlsb(data: &[u8]) -> Result<Vec<RawSheet>> {
     Ok(sheets)
 }
 
-/// Convert a calamine Range into a RawSheet. First row = headers.
+/// Convert a calamine Range into a RawSheet.
 /// Skips sheets that are empty or have only a header row.
-fn range_to_raw_sheet(name: &str, range: &calamine::Range<Data>) -> Option<RawSheet> {
-    let mut row_iter = range.rows();
-
-    // First row = headers
-    let header_row = row_iter.next()?;
-    let headers: Vec<String> = header_row.iter().map(|c| cell_to_string(c)).collect();
-
-    // Skip sheets with no real headers
-    if headers.is_empty() || headers.iter().all(|h| h.is_empty()) {
-        return None;
-    }
+fn range_to_raw_sheet(
+    name: &str,
+    range: &calamine::Range<Data>,
+    formats: Option<&CellFormats>,
+    formulas: Option<&calamine::Range<String>>,
+    dates: DateStyle,
+) -> Option<RawSheet> {
+    let (kept, rows): (Vec<usize>, Vec<Vec<String>>) = range
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell_to_string(cell, &dates))
+                .collect::<Vec<_>>()
+        })
+        .enumerate()
+        .filter(|(_, values)| !is_blank(values))
+        .unzip();
+
+    let mut sheet = RawSheet::from_rows(name, rows, SourceType::Excel)?;
+    let data_start = sheet.header_row_index + sheet.header_rows.len();
+    sheet.column_hints = column_hints(range, &kept[data_start..], formats, formulas);
+    Some(sheet)
+}
 
-    let mut rows = Vec::new();
-    for row in row_iter {
-        let values: Vec<String> = row.iter().map(|c| cell_to_string(c)).collect();
-        // Skip completely empty rows
-        if values.iter().all(|v| v.is_empty()) {
-            continue;
-        }
-        rows.push(values);
-    }
+/// Summarize each column's data cells (`data_rows` are relative row indices).
+fn column_hints(
+    range: &calamine::Range<Data>,
+    data_rows: &[usize],
+    formats: Option<&CellFormats>,
+    formulas: Option<&calamine::Range<String>>,
+) -> Vec<ColumnHint> {
+    let (row0, col0) = range.start().unwrap_or((0, 0));
+    (0..range.width())
+        .map(|col| {
+            let mut hint = ColumnHint::default();
+            let mut types = Vec::new();
+            let mut computed = 0;
+            for &row in data_rows {
+                let Some(cell) = range.get((row, col)).filter(|c| **c != Data::Empty) else {
+                    continue;
+                };
+                let position = (row0 + row as u32, col0 + col as u32);
+                let format = formats.and_then(|f| f.get(&position));
+                if hint.format.is_none() {
+                    hint.format = format.cloned();
+                }
+                types.push(cell_type(
+                    cell,
+                    format.and_then(|f| xlsx_formats::classify(f)),
+                ));
+                if let Some(formula) = formulas
+                    .and_then(|f| f.get_value(position))
+                    .filter(|f| !f.is_empty())
+                {
+                    computed += 1;
+                    if hint.formula.is_none() {
+                        hint.formula = Some(format!("={}", formula));
+                    }
+                }
+            }
+            if computed * 2 < types.len() {
+                hint.formula = None;
+            }
+            hint.data_type = column_type(&types);
+            hint
+        })
+        .collect()
+}
 
-    // Skip sheets with headers but no data rows
-    if rows.is_empty() {
-        return None;
+/// The data type a single cell's value and number format imply.
+fn cell_type(cell: &Data, format: Option<FormatKind>) -> Option<&'static str> {
+    match cell {
+        Data::Empty | Data::Error(_) | Data::DurationIso(_) => None,
+        Data::String(_) => Some("string"),
+        Data::Bool(_) => Some("boolean"),
+        Data::DateTime(_) | Data::DateTimeIso(_) => Some("date"),
+        Data::Int(_) | Data::Float(_) => Some(match format {
+            Some(FormatKind::Currency(data_type)) => data_type,
+            Some(FormatKind::Date) => "date",
+            Some(FormatKind::Percent | FormatKind::Decimal) => "float",
+            Some(FormatKind::Integer) => "integer",
+            Some(FormatKind::Text) | None => match cell {
+                Data::Float(f) if f.fract() != 0.0 => "float",
+                _ => "integer",
+            },
+        }),
     }
+}
 
-    Some(RawSheet {
-        name: name.to_string(),
-        headers,
-        rows,
-        source_type: SourceType::Excel,
+/// The type all cells agree on, widening integer to float.
+fn column_type(types: &[Option<&'static str>]) -> Option<&'static str> {
+    let mut types = types.iter().flatten();
+    let first = *types.next()?;
+    types.try_fold(first, |acc, &t| match (acc, t) {
+        _ if acc == t => Some(acc),
+        ("integer", "float") | ("float", "integer") => Some("float"),
+        _ => None,
     })
 }
 
+fn is_blank(row: &[String]) -> bool {
+    row.iter().all(|v| v.trim().is_empty())
+}
+
 /// Convert a calamine cell to a string representation.
-fn cell_to_string(cell: &Data) -> String {
+fn cell_to_string(cell: &Data, dates: &DateStyle) -> String {
     match cell {
         Data::Empty => String::new(),
         Data::String(s) => s.clone(),
@@ -199,7 +435,7 @@ fn cell_to_string(cell: &Data) -> String {
         Data::Bool(b) => b.to_string(),
         Data::DateTime(dt) => {
             // calamine DateTime — convert from Excel serial number
-            excel_serial_to_string(dt.as_f64())
+            excel_serial_to_string(dt.as_f64(), dates)
         }
         Data::DateTimeIso(s) => s.clone(),
         Data::DurationIso(s) => s.clone(),
@@ -209,12 +445,19 @@ fn cell_to_string(cell: &Data) -> String {
 
 /// Convert an Excel serial date number to a human-readable string.
 /// Excel epoch: 1899-12-30 (with the 1900 leap year bug — day 60 is "Feb 29, 1900" which doesn't exist).
-fn excel_serial_to_string(serial: f64) -> String {
+/// In the 1904 date system serial 0 is 1904-01-01, 1462 days later.
+fn excel_serial_to_string(serial: f64, style: &DateStyle) -> String {
+    let serial = if style.date_1904 {
+        serial + 1462.0
+    } else {
+        serial
+    };
     let days = serial as i64;
     let frac = serial - days as f64;
 
-    // Adjust for Excel's 1900 leap year bug (serial > 59 means after fake Feb 29, 1900)
-    let adjusted_days = if days > 59 { days - 1 } else { days };
+    // Counting from 1899-12-30 is right from March 1900 on; before the fake
+    // Feb 29, 1900 (serial 60) Excel is one day ahead
+    let adjusted_days = if days < 60 { days + 1 } else { days };
 
     let base = 25569i64; // days from 1899-12-30 to 1970-01-01
     let unix_days = adjusted_days - base;
@@ -266,16 +509,28 @@ fn excel_serial_to_string(serial: f64) -> String {
     }
     let day = remaining + 1;
 
+    let date = match style.locale {
+        DateLocale::Iso => format!("{:04}-{:02}-{:02}", year, month, day),
+        DateLocale::PtBr => format!("{:02}/{:02}/{:04}", day, month, year),
+        DateLocale::EnUs => format!("{:02}/{:02}/{:04}", month, day, year),
+    };
     if hours == 0 && minutes == 0 && seconds == 0 {
-        format!("{:04}-{:02}-{:02}", year, month, day)
-    } else {
-        format!(
-            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-            year, month, day, hours, minutes, seconds
-        )
+        return date;
+    }
+    let time = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
+    match (style.utc_offset_minutes, style.locale) {
+        (None, _) => format!("{} {}", date, time),
+        (Some(offset), DateLocale::Iso) => format!("{}T{}{}", date, time, format_offset(offset)),
+        (Some(offset), _) => format!("{} {} {}", date, time, format_offset(offset)),
     }
 }
 
+fn format_offset(minutes: i32) -> String {
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.abs();
+    format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
 fn is_leap(year: i32) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
@@ -361,9 +616,12 @@ fn merge_similar_tables(tables: Vec<RawSheet>) -> Vec<RawSheet> {
             let mut merged = iter.next().unwrap();
             let part_count = 1 + iter.len();
             for other in iter {
-                // The "headers" of continuation blocks are actually data rows
-                // (since the original table has no repeated header), so include them
-                merged.rows.push(other.headers);
+                // Continuation blocks rarely repeat the header, so a "header"
+                // that differs from the first block's is a data row
+                merged.rows.extend(other.preamble);
+                if other.has_header && other.headers != merged.headers {
+                    merged.rows.extend(other.header_rows);
+                }
                 merged.rows.extend(other.rows);
             }
             merged.name = format!("{} ({} parts merged)", merged.name, part_count);
@@ -418,30 +676,293 @@ fn markdown_table_to_raw_sheet(name: &str, lines: &[String]) -> Option<RawSheet>
         data_rows.push(cells);
     }
 
-    if data_rows.len() < 2 {
-        return None; // Need header + at least one data row
+    // Filter out completely empty rows
+    data_rows.retain(|row| !is_blank(row));
+
+    RawSheet::from_rows(name, data_rows, SourceType::OcrMarkdown)
+}
+
+// ============================================================================
+// Header detection
+// ============================================================================
+
+/// Rows considered as header candidates.
+const HEADER_SCAN_ROWS: usize = 10;
+
+/// Rows below a candidate used to judge it.
+const HEADER_BODY_ROWS: usize = 20;
+
+/// Where a sheet's header is, among its non-empty rows.
+#[derive(Debug, PartialEq)]
+struct HeaderGuess {
+    index: usize,
+    has_header: bool,
+    confident: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CellKind {
+    Empty,
+    Number,
+    Date,
+    Text,
+}
+
+/// Find the header among the first [`HEADER_SCAN_ROWS`] rows.
+///
+/// A header is a mostly text row that fills at least half the table's width
+/// and sits above the first row that looks like data (one with numbers or
+/// dates where the rows below have them). Candidates are scored by how
+/// consistent the column types below them are, with a bonus for each text
+/// cell over a numeric or date column. With no candidate the sheet has no
+/// header and data starts at the first data-looking row. A header whose
+/// columns are all text below it is kept, but not confidently. Rows that
+/// stack under a group row are never taken for data.
+fn detect_header(rows: &[Vec<String>]) -> HeaderGuess {
+    let kinds: Vec<Vec<CellKind>> = rows
+        .iter()
+        .take(HEADER_SCAN_ROWS + HEADER_BODY_ROWS)
+        .map(|row| row.iter().map(|c| cell_kind(c)).collect())
+        .collect();
+    let width = kinds
+        .iter()
+        .filter_map(|row| row.iter().rposition(|k| *k != CellKind::Empty))
+        .max()
+        .map_or(0, |last| last + 1);
+
+    let mut best: Option<(usize, f64, usize)> = None;
+    let mut first_data = None;
+    for i in 0..kinds.len().min(HEADER_SCAN_ROWS) {
+        let body = &kinds[i + 1..(i + 1 + HEADER_BODY_ROWS).min(kinds.len())];
+        if body.is_empty() {
+            break;
+        }
+        let candidate = score_header(&kinds[i], body, width);
+        // Sub-column labels under a group row are often years
+        if candidate.data_like && i > 0 && stacks_on(&rows[i - 1], &rows[i], width) {
+            continue;
+        }
+        if candidate.data_like {
+            first_data = Some(i);
+            break;
+        }
+        if candidate.filled * 2 >= width && best.is_none_or(|(_, score, _)| candidate.score > score)
+        {
+            best = Some((i, candidate.score, candidate.contrast));
+        }
+    }
+
+    match best {
+        Some((index, _, contrast)) => HeaderGuess {
+            index,
+            has_header: true,
+            confident: contrast > 0,
+        },
+        None => HeaderGuess {
+            index: first_data.unwrap_or(0),
+            has_header: false,
+            confident: first_data.is_some(),
+        },
     }
+}
 
-    let headers = data_rows.remove(0);
+struct HeaderScore {
+    score: f64,
+    filled: usize,
+    /// Text cells over numeric or date columns.
+    contrast: usize,
+    /// Mostly non-text, or a number or date where the column has them.
+    data_like: bool,
+}
 
-    // Skip if all headers are empty
-    if headers.iter().all(|h| h.is_empty()) {
-        return None;
+fn score_header(row: &[CellKind], body: &[Vec<CellKind>], width: usize) -> HeaderScore {
+    let filled = row.iter().filter(|k| **k != CellKind::Empty).count();
+    let text = row.iter().filter(|k| **k == CellKind::Text).count();
+
+    let mut columns = 0;
+    let mut consistency = 0.0;
+    let mut contrast = 0;
+    let mut matches = 0;
+    for col in 0..width {
+        let column: Vec<CellKind> = body
+            .iter()
+            .filter_map(|r| r.get(col).copied())
+            .filter(|k| *k != CellKind::Empty)
+            .collect();
+        let Some((mode, count)) = [CellKind::Number, CellKind::Date, CellKind::Text]
+            .into_iter()
+            .map(|kind| (kind, column.iter().filter(|k| **k == kind).count()))
+            .max_by_key(|(_, count)| *count)
+            .filter(|(_, count)| *count > 0)
+        else {
+            continue;
+        };
+        columns += 1;
+        consistency += count as f64 / column.len() as f64;
+        match row.get(col).copied().unwrap_or(CellKind::Empty) {
+            CellKind::Text if mode != CellKind::Text => contrast += 1,
+            kind @ (CellKind::Number | CellKind::Date) if kind == mode => matches += 1,
+            _ => {}
+        }
     }
 
-    // Filter out completely empty rows
-    data_rows.retain(|row| !row.iter().all(|c| c.is_empty()));
+    let score = if filled == 0 || columns == 0 {
+        0.0
+    } else {
+        (filled as f64 / width as f64)
+            * (text as f64 / filled as f64)
+            * (consistency / columns as f64)
+            + contrast as f64 / columns as f64
+    };
+    HeaderScore {
+        score,
+        filled,
+        contrast,
+        data_like: filled > 0 && (matches > 0 || text * 2 < filled),
+    }
+}
 
-    if data_rows.is_empty() {
-        return None;
+/// Most header rows stacked on each other.
+const MAX_HEADER_ROWS: usize = 3;
+
+/// The rows making up the header found at `index`: group rows above it whose
+/// labels span the columns it names, and sub-column rows below it. At least
+/// one row is left for data.
+fn header_stack(rows: &[Vec<String>], index: usize) -> Range<usize> {
+    let width = rows
+        .iter()
+        .take(index + MAX_HEADER_ROWS + 1)
+        .map(|r| r.len())
+        .max()
+        .unwrap_or(0);
+    let mut stack = index..index + 1;
+    while stack.len() < MAX_HEADER_ROWS
+        && stack.start > 0
+        && stacks_on(&rows[stack.start - 1], &rows[stack.start], width)
+    {
+        stack.start -= 1;
     }
+    while stack.len() < MAX_HEADER_ROWS
+        && stack.end + 1 < rows.len()
+        && stacks_on(&rows[stack.end - 1], &rows[stack.end], width)
+    {
+        stack.end += 1;
+    }
+    stack
+}
 
-    Some(RawSheet {
-        name: name.to_string(),
-        headers,
-        rows: data_rows,
-        source_type: SourceType::OcrMarkdown,
-    })
+/// Whether `group` is a header row whose labels span several columns of
+/// `sub`: each label covers the empty cells to its right (how merged cells
+/// read), and `sub` names at least two columns under every spanning label.
+///
+/// Data rows under a header with a blank cell fill the spans too, so either
+/// the first label must be a single column left empty in `sub` (a row-label
+/// column like "Conta"), or every label must span.
+fn stacks_on(group: &[String], sub: &[String], width: usize) -> bool {
+    let filled = |row: &[String], col: usize| row.get(col).is_some_and(|v| !v.trim().is_empty());
+    let labels: Vec<usize> = (0..width).filter(|&c| filled(group, c)).collect();
+    let spans: Vec<Range<usize>> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| start..labels.get(i + 1).copied().unwrap_or(width))
+        .collect();
+
+    let spanning: Vec<&Range<usize>> = spans.iter().filter(|s| s.len() > 1).collect();
+    if spanning.is_empty()
+        || !spanning
+            .iter()
+            .all(|s| (s.start..s.end).filter(|&c| filled(sub, c)).count() >= 2)
+    {
+        return false;
+    }
+
+    let row_label = spans[0].len() == 1 && !filled(sub, spans[0].start);
+    let all_spanning = spans.len() >= 2 && spanning.len() == spans.len();
+    row_label || all_spanning
+}
+
+/// Compound column names from stacked header rows: each group label is
+/// carried across the columns it spans (stopping where a row above starts a
+/// new label), and a column's labels are joined with `/`.
+fn flatten_headers(header_rows: &[Vec<String>]) -> Vec<String> {
+    let Some((leaf, groups)) = header_rows.split_last() else {
+        return Vec::new();
+    };
+    let width = header_rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut labels: Vec<Vec<&str>> = vec![Vec::new(); width];
+    for (level, row) in groups.iter().enumerate() {
+        let mut current = "";
+        for (col, parts) in labels.iter_mut().enumerate() {
+            if !cell(row, col).is_empty() {
+                current = cell(row, col);
+            } else if groups[..level].iter().any(|r| !cell(r, col).is_empty()) {
+                current = "";
+            }
+            parts.push(current);
+        }
+    }
+    for (col, parts) in labels.iter_mut().enumerate() {
+        parts.push(cell(leaf, col));
+    }
+
+    labels
+        .into_iter()
+        .map(|parts| {
+            parts
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect()
+}
+
+fn cell(row: &[String], col: usize) -> &str {
+    row.get(col).map_or("", |v| v.trim())
+}
+
+/// A header or column name reduced for comparison: `"Data Lançamento"` and
+/// `data_lancamento` both become `data_lancamento`.
+pub(crate) fn header_key(name: &str) -> String {
+    let folded: String = name
+        .chars()
+        .map(fold_accent)
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    folded
+        .split('_')
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Classify a cell as a number (`1.234,56`, `R$ -10`, `12%`), a date
+/// (`31/12/2024`, `2024-12-31 10:00`), text, or empty.
+pub(crate) fn cell_kind(value: &str) -> CellKind {
+    let value = value.trim();
+    if value.is_empty() {
+        return CellKind::Empty;
+    }
+
+    let first = value.split([' ', 'T']).next().unwrap_or(value);
+    for sep in ['/', '-', '.'] {
+        let parts: Vec<&str> = first.split(sep).collect();
+        let lens: Vec<usize> = parts.iter().map(|p| p.len()).collect();
+        let digits = parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+        let date_shape = matches!(lens.as_slice(), [1..=2, 1..=2, 2 | 4] | [4, 1..=2, 1..=2]);
+        if parts.len() == 3 && digits && date_shape {
+            return CellKind::Date;
+        }
+    }
+
+    if numeric::parse_number(value, NumberLocale::Auto).is_some() {
+        CellKind::Number
+    } else {
+        CellKind::Text
+    }
 }
 
 #[cfg(test)]
@@ -451,7 +972,7 @@ mod tests {
     #[test]
     fn test_parse_csv_basic() {
         let csv_data = b"name,age,city\nAlice,30,SP\nBob,25,RJ\n";
-        let sheets = parse_file("test.csv", csv_data).unwrap();
+        let sheets = parse_file("test.csv", csv_data, DateStyle::default()).unwrap();
         assert_eq!(sheets.len(), 1);
         assert_eq!(sheets[0].headers, vec!["name", "age", "city"]);
         assert_eq!(sheets[0].rows.len(), 2);
@@ -463,14 +984,14 @@ mod tests {
     fn test_parse_csv_flexible() {
         // Rows with different column counts should still parse
         let csv_data = b"a,b,c\n1,2,3\n4,5\n";
-        let sheets = parse_file("flex.csv", csv_data).unwrap();
+        let sheets = parse_file("flex.csv", csv_data, DateStyle::default()).unwrap();
         assert_eq!(sheets[0].rows.len(), 2);
         assert_eq!(sheets[0].rows[1], vec!["4", "5"]);
     }
 
     #[test]
     fn test_unsupported_extension() {
-        let result = parse_file("test.txt", b"data");
+        let result = parse_file("test.txt", b"data", DateStyle::default());
         assert!(result.is_err());
     }
 
@@ -495,4 +1016,168 @@ mod tests {
         assert_eq!(sheet.rows.len(), 2);
         assert_eq!(sheet.rows[0], vec!["Alice", "30", "SP"]);
     }
+
+    #[test]
+    fn test_header_below_title_rows() {
+        let csv_data = "Extrato Conta Corrente,,\nPeríodo: 03/2024,,\n,,\nData,Histórico,Valor\n01/03/2024,PIX recebido,\"1.200,00\"\n02/03/2024,Tarifa,\"-5,00\"\n";
+        let sheet = parse_file("extrato.csv", csv_data.as_bytes(), DateStyle::default())
+            .unwrap()
+            .remove(0);
+        assert!(sheet.has_header && sheet.header_confident);
+        assert_eq!(sheet.header_row_index, 2);
+        assert_eq!(sheet.preamble[1][0], "Período: 03/2024");
+        assert_eq!(sheet.headers, vec!["Data", "Histórico", "Valor"]);
+        assert_eq!(sheet.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_headerless_sheet() {
+        let csv_data = b"01/03/2024,PIX recebido,1200\n02/03/2024,Tarifa,-5\n";
+        let mut sheet = parse_file("semcabecalho.csv", csv_data, DateStyle::default())
+            .unwrap()
+            .remove(0);
+        assert!(!sheet.has_header && sheet.header_confident);
+        assert_eq!(sheet.headers, vec!["column_1", "column_2", "column_3"]);
+        assert_eq!(sheet.rows.len(), 2);
+
+        // Text-only columns leave the call to the LLM; applying its answer re-splits
+        let mut names = parse_file(
+            "nomes.csv",
+            b"Alice,SP\nBob,RJ\nCarol,MG\n",
+            DateStyle::default(),
+        )
+        .unwrap()
+        .remove(0);
+        assert!(names.has_header && !names.header_confident);
+        assert!(names.set_header(None));
+        assert_eq!(names.rows.len(), 3);
+        assert_eq!(names.headers, vec!["column_1", "column_2"]);
+        assert!(!sheet.set_header(Some(1)));
+    }
+
+    #[test]
+    fn test_excel_dates_follow_date_system_and_style() {
+        let iso = DateStyle::default();
+        assert_eq!(excel_serial_to_string(45352.0, &iso), "2024-03-01");
+        let mac = DateStyle {
+            date_1904: true,
+            ..iso
+        };
+        assert_eq!(excel_serial_to_string(43890.0, &mac), "2024-03-01");
+
+        let brasilia = DateStyle {
+            utc_offset_minutes: parse_utc_offset("-03:00"),
+            ..iso
+        };
+        assert_eq!(
+            excel_serial_to_string(45352.375, &brasilia),
+            "2024-03-01T09:00:00-03:00"
+        );
+        let pt_br = DateStyle {
+            locale: DateLocale::PtBr,
+            ..brasilia
+        };
+        assert_eq!(
+            excel_serial_to_string(45352.375, &pt_br),
+            "01/03/2024 09:00:00 -03:00"
+        );
+        assert_eq!(cell_kind("2024-03-01T09:00:00-03:00"), CellKind::Date);
+        assert_eq!(parse_utc_offset("+0530"), Some(330));
+        assert_eq!(parse_utc_offset("03:00"), None);
+    }
+
+    #[test]
+    fn test_column_hints_from_cell_formats() {
+        let mut range = calamine::Range::new((0, 0), (2, 3));
+        for (col, header) in ["Data", "Valor", "Taxa", "Total"].into_iter().enumerate() {
+            range.set_value((0, col as u32), Data::String(header.to_string()));
+        }
+        for row in 1..3 {
+            range.set_value(
+                (row, 0),
+                Data::DateTime(calamine::ExcelDateTime::new(
+                    45000.0,
+                    calamine::ExcelDateTimeType::DateTime,
+                    false,
+                )),
+            );
+            range.set_value((row, 1), Data::Float(1234.5));
+            range.set_value((row, 2), Data::Float(0.15));
+            range.set_value((row, 3), Data::Int(10));
+        }
+        let formats: CellFormats = (1..3)
+            .flat_map(|row| {
+                [
+                    ((row, 1), "\"R$\" #,##0.00".to_string()),
+                    ((row, 2), "0.0%".to_string()),
+                ]
+            })
+            .collect();
+        let mut formulas = calamine::Range::new((1, 3), (2, 3));
+        formulas.set_value((1, 3), "B2*C2".to_string());
+        formulas.set_value((2, 3), "B3*C3".to_string());
+
+        let sheet = range_to_raw_sheet(
+            "resumo",
+            &range,
+            Some(&formats),
+            Some(&formulas),
+            DateStyle::default(),
+        )
+        .unwrap();
+        let types: Vec<_> = sheet.column_hints.iter().map(|h| h.data_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some("date"),
+                Some("currency_brl"),
+                Some("float"),
+                Some("integer")
+            ]
+        );
+        assert_eq!(sheet.column_hints[2].format.as_deref(), Some("0.0%"));
+        assert_eq!(sheet.column_hints[3].formula.as_deref(), Some("=B2*C2"));
+        assert_eq!(sheet.column_hints[1].formula, None);
+    }
+
+    #[test]
+    fn test_stacked_headers_flattened() {
+        let csv_data = "Conta,Receita,,Despesa,\n,2023,2024,2023,2024\nVendas,100,120,80,90\nServiços,50,60,40,45\n";
+        let sheet = parse_file("dre.csv", csv_data.as_bytes(), DateStyle::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(sheet.header_rows.len(), 2);
+        assert_eq!(
+            sheet.headers,
+            vec![
+                "Conta",
+                "Receita/2023",
+                "Receita/2024",
+                "Despesa/2023",
+                "Despesa/2024"
+            ]
+        );
+        assert_eq!(sheet.rows.len(), 2);
+
+        let csv_data =
+            "Conta,Receita,,,\n,2023,,2024,\n,Real,Orçado,Real,Orçado\nVendas,100,120,80,90\n";
+        let sheet = parse_file("dre.csv", csv_data.as_bytes(), DateStyle::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(sheet.header_row_index, 0);
+        assert_eq!(sheet.headers[2], "Receita/2023/Orçado");
+        assert_eq!(sheet.headers[3], "Receita/2024/Real");
+        assert_eq!(sheet.rows, vec![vec!["Vendas", "100", "120", "80", "90"]]);
+
+        // A blank header cell doesn't pull the first data row into the header
+        let sheet = parse_file(
+            "x.csv",
+            b"Data,Historico,,Valor\n01/03/2024,PIX,abc,100\n02/03/2024,TED,def,200\n",
+            DateStyle::default(),
+        )
+        .unwrap()
+        .remove(0);
+        assert_eq!(sheet.header_rows.len(), 1);
+        assert_eq!(sheet.rows.len(), 2);
+    }
 }
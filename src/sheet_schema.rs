@@ -3,8 +3,12 @@
 //! Separate from `schema.rs` since the data model is fundamentally different:
 //! flat datasets with typed columns vs hierarchical document trees.
 
+use crate::numeric::value_as_f64;
 use crate::schema::{now_iso8601, ExtractionStatus};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 /// Root result of a sheet extraction.
@@ -23,6 +27,9 @@ pub struct SheetExtraction {
     pub schemas: Vec<DataSchema>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub relationships: Vec<SchemaRelationship>,
+    /// Latest push to each of the config's `sheet_config.sinks`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliveries: Vec<SinkDelivery>,
 }
 
 impl SheetExtraction {
@@ -37,8 +44,40 @@ impl SheetExtraction {
             summary: String::new(),
             schemas: Vec::new(),
             relationships: Vec::new(),
+            deliveries: Vec::new(),
         }
     }
+
+    /// When the dataset last changed: its latest append, or the extraction.
+    pub fn modified_at(&self) -> &str {
+        self.schemas
+            .iter()
+            .flat_map(|s| &s.sources)
+            .map(|s| s.added_at.as_str())
+            .chain([self.extracted_at.as_str()])
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// How pushing a dataset to one sink went.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkDelivery {
+    pub sink: String,
+    pub status: DeliveryStatus,
+    /// Rows delivered, counting only batches that were accepted.
+    pub rows: usize,
+    pub batches: usize,
+    pub attempted_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Delivered,
+    Failed,
 }
 
 /// A discovered data schema (one logical table).
@@ -52,6 +91,244 @@ pub struct DataSchema {
     pub row_count: usize,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub rows: Vec<serde_json::Value>,
+    /// Which file each run of rows came from. Recorded once the dataset has
+    /// rows from more than one file (`POST /datasets/:id/append`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<RowSource>,
+    /// Version of `columns`, bumped each time a later file adds columns.
+    #[serde(default = "first_version")]
+    pub version: u32,
+    /// Drift found in later files, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<SchemaChange>,
+    /// Per-column statistics over `rows`, refreshed whenever rows change.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stats: Vec<ColumnStats>,
+    /// Whether any row was corrected or deleted by hand
+    /// (`PATCH`/`DELETE /datasets/:id/rows/:row_index`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub edited: bool,
+    /// Manual row edits, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<RowEdit>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// How many of a column's most frequent values [`ColumnStats`] lists.
+const TOP_VALUES: usize = 5;
+
+impl DataSchema {
+    /// Recompute `stats` from the current rows.
+    pub fn refresh_stats(&mut self) {
+        self.stats = self
+            .columns
+            .iter()
+            .map(|c| ColumnStats::compute(c, &self.rows))
+            .collect();
+    }
+}
+
+/// Summary of one column's values, for checking an extraction at a glance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnStats {
+    pub column: String,
+    /// Share of rows (0–1) where the column is missing, null or blank.
+    pub null_rate: f64,
+    /// Distinct non-blank values.
+    pub distinct_count: usize,
+    /// Smallest value, for numeric and date columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<Value>,
+    /// Most frequent values, most frequent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_values: Vec<ValueCount>,
+}
+
+/// A value and how many rows hold it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+impl ColumnStats {
+    fn compute(column: &ColumnDef, rows: &[Value]) -> Self {
+        let values: Vec<&Value> = rows
+            .iter()
+            .filter_map(|row| row.get(&column.name))
+            .filter(|v| match v {
+                Value::Null => false,
+                Value::String(s) => !s.trim().is_empty(),
+                _ => true,
+            })
+            .collect();
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for value in &values {
+            let text = match value {
+                Value::String(s) => s.trim().to_string(),
+                other => other.to_string(),
+            };
+            *counts.entry(text).or_default() += 1;
+        }
+        let distinct_count = counts.len();
+        let mut top_values: Vec<ValueCount> = counts
+            .into_iter()
+            .map(|(value, count)| ValueCount { value, count })
+            .collect();
+        top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        top_values.truncate(TOP_VALUES);
+
+        let (min, max) = match column.data_type.as_str() {
+            "integer" | "float" | "currency_brl" | "currency_usd" => {
+                let numbers = values.iter().filter_map(|v| value_as_f64(v));
+                let range = numbers.fold(None, |range: Option<(f64, f64)>, n| {
+                    Some(range.map_or((n, n), |(lo, hi)| (lo.min(n), hi.max(n))))
+                });
+                match range {
+                    Some((lo, hi)) => (Some(Value::from(lo)), Some(Value::from(hi))),
+                    None => (None, None),
+                }
+            }
+            "date" => {
+                let dates = || {
+                    values
+                        .iter()
+                        .filter_map(|v| v.as_str())
+                        .filter_map(|s| date_key(s).map(|key| (key, s)))
+                };
+                let pick = |ord: Ordering| {
+                    dates()
+                        .reduce(|a, b| if b.0.cmp(&a.0) == ord { b } else { a })
+                        .map(|(_, s)| Value::from(s))
+                };
+                (pick(Ordering::Less), pick(Ordering::Greater))
+            }
+            _ => (None, None),
+        };
+
+        Self {
+            column: column.name.clone(),
+            null_rate: if rows.is_empty() {
+                0.0
+            } else {
+                (rows.len() - values.len()) as f64 / rows.len() as f64
+            },
+            distinct_count,
+            min,
+            max,
+            top_values,
+        }
+    }
+}
+
+/// Sort key for a date cell: `(year, month, day)` then any time part.
+/// Reads `YYYY-MM-DD` and, otherwise, day-first `DD/MM/YYYY`.
+fn date_key(text: &str) -> Option<(u32, u32, u32, &str)> {
+    let text = text.trim();
+    let split = text.find(['T', ' ']).unwrap_or(text.len());
+    let (date, time) = text.split_at(split);
+    let parts: Vec<&str> = date.split(['-', '/', '.']).collect();
+    let [a, b, c] = parts.as_slice() else {
+        return None;
+    };
+    let (year, month, day) = if a.len() == 4 { (a, b, c) } else { (c, b, a) };
+    let key = (
+        year.parse().ok()?,
+        month.parse().ok()?,
+        day.parse().ok()?,
+        time,
+    );
+    (1..=12).contains(&key.1).then_some(key)
+}
+
+/// Drift found when a later file was reconciled with the schema.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaChange {
+    /// The schema's version after the change.
+    pub version: u32,
+    pub source_file: String,
+    pub detected_at: String,
+    pub drift: SchemaDrift,
+    /// Column definitions before the change, when it added columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_columns: Option<Vec<ColumnDef>>,
+}
+
+/// How a file differs from the schema its rows went to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaDrift {
+    /// Schema columns the file lacks; its rows leave them out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_columns: Vec<String>,
+    /// File columns the schema lacked, added to it under these names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_columns: Vec<String>,
+    /// File headers mapped onto a differently named schema column.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed_columns: Vec<ColumnRename>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub type_changes: Vec<TypeChange>,
+}
+
+impl SchemaDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing_columns.is_empty()
+            && self.added_columns.is_empty()
+            && self.renamed_columns.is_empty()
+            && self.type_changes.is_empty()
+    }
+}
+
+/// A file header read as an existing column with another name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnRename {
+    /// The header in the file.
+    pub from: String,
+    /// The schema column it was mapped to.
+    pub to: String,
+}
+
+/// A column whose values in a later file don't have the schema's type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypeChange {
+    pub column: String,
+    pub expected: String,
+    pub found: String,
+}
+
+/// A manual correction to one of a schema's rows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowEdit {
+    /// The row's index at the time; deleting a row shifts later rows down.
+    pub row_index: usize,
+    pub action: RowEditAction,
+    pub reviewer: String,
+    pub edited_at: String,
+    /// What the edit replaced: the changed columns' previous values for an
+    /// update, the whole row for a delete.
+    pub original: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowEditAction {
+    Update,
+    Delete,
+}
+
+/// A run of a schema's rows that came from one file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RowSource {
+    pub source_file: String,
+    /// Row index range `[start, end)` within the schema.
+    pub rows: [usize; 2],
+    pub added_at: String,
 }
 
 /// Column definition within a schema.
@@ -70,6 +347,10 @@ pub struct ColumnDef {
     pub source: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Formula the source cells compute, e.g. `=C2*D2` (Excel, with
+    /// `sheet_config.include_formulas`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
 }
 
 /// Relationship between two schemas (e.g. foreign key).
@@ -82,3 +363,67 @@ pub struct SchemaRelationship {
     #[serde(rename = "type")]
     pub rel_type: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, data_type: &str) -> ColumnDef {
+        ColumnDef {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            format: None,
+            transform: None,
+            required: false,
+            source: None,
+            description: None,
+            formula: None,
+        }
+    }
+
+    #[test]
+    fn test_refresh_stats() {
+        let mut schema = DataSchema {
+            name: "transacoes".to_string(),
+            description: String::new(),
+            columns: vec![
+                column("data", "date"),
+                column("valor", "currency_brl"),
+                column("categoria", "string"),
+            ],
+            row_count: 4,
+            rows: vec![
+                json!({"data": "15/02/2024", "valor": 10.5, "categoria": "tarifa"}),
+                json!({"data": "2024-01-31", "valor": "R$ 1.234,56", "categoria": "pix"}),
+                json!({"data": "03/03/2024", "valor": -2, "categoria": "pix"}),
+                json!({"data": "", "valor": null}),
+            ],
+            sources: Vec::new(),
+            version: 1,
+            changes: Vec::new(),
+            stats: Vec::new(),
+            edited: false,
+            edits: Vec::new(),
+        };
+        schema.refresh_stats();
+
+        let [data, valor, categoria] = schema.stats.as_slice() else {
+            panic!("one entry per column");
+        };
+        assert_eq!(data.null_rate, 0.25);
+        assert_eq!(data.min, Some(json!("2024-01-31")));
+        assert_eq!(data.max, Some(json!("03/03/2024")));
+        assert_eq!(valor.min, Some(json!(-2.0)));
+        assert_eq!(valor.max, Some(json!(1234.56)));
+        assert_eq!(categoria.distinct_count, 2);
+        assert!(categoria.min.is_none());
+        assert_eq!(
+            categoria.top_values[0],
+            ValueCount {
+                value: "pix".to_string(),
+                count: 2
+            }
+        );
+    }
+}
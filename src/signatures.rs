@@ -0,0 +1,375 @@
+//! Signature and stamp detection.
+//!
+//! The opt-in `signatures` pipeline stage reads the signature blocks that
+//! court systems print on signed documents ("Documento assinado
+//! eletronicamente por ...", "Assinado de forma digital por NOME:CPF") from
+//! each node's content, with the date, verification code and ICP-Brasil
+//! certificate details printed next to them. With `signatures.vision` set in
+//! the config, the last page of each top-level node that has none is also
+//! shown to the LLM, which finds handwritten signatures and stamps.
+//!
+//! Results go into node metadata: `signed_by` (names), `signature_type`
+//! (the strongest kind found) and `signatures` (one entry per signature).
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::content_store::ContentStore;
+use crate::extractor;
+use crate::openrouter::{Message, OpenRouterClient};
+use crate::schema::{DocumentNode, Extraction};
+
+/// Characters after a signer's name searched for its date, code and certificate.
+const WINDOW: usize = 300;
+
+/// `signatures` section of an extraction config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SignatureConfig {
+    /// Ask the LLM about the last page of top-level nodes where no signature
+    /// block was read (one call with a page image per node; needs the
+    /// archived source file)
+    #[serde(default)]
+    pub vision: bool,
+}
+
+/// How a document was signed, strongest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureType {
+    /// With an ICP-Brasil certificate
+    Digital,
+    /// Through a court system's login ("assinado eletronicamente")
+    Electronic,
+    /// By hand, on paper
+    Handwritten,
+    /// A rubber stamp or seal
+    Stamp,
+}
+
+/// Certificate details printed with a digital signature.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Certificate {
+    /// The block mentions ICP-Brasil (or MP 2.200-2, which created it)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub icp_brasil: bool,
+    /// Certificate authority, e.g. "AC SOLUTI Multipla v5"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// CPF in the certificate's subject ("NOME:12345678900")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpf: Option<String>,
+}
+
+/// One signature found on a node's pages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub signature_type: SignatureType,
+    /// YYYY-MM-DD
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>,
+    /// Code for checking the document on the court's site
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_code: Option<String>,
+    /// OCR page the signature is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+}
+
+/// Outcome of [`apply`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignatureReport {
+    /// Nodes that got signature metadata
+    pub nodes: usize,
+    /// Signatures found, counted once per node
+    pub signatures: usize,
+}
+
+struct Patterns {
+    /// "assinado eletronicamente por: NOME", "assinado de forma digital por NOME:CPF"
+    block: Regex,
+    /// Where the next signature block starts
+    next: Regex,
+    date: Regex,
+    /// Adobe's "Dados: 2024.03.15 10:22:33 -03'00'"
+    adobe_date: Regex,
+    code: Regex,
+    icp_brasil: Regex,
+    issuer: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        // Two or more capitalized words, joined by da/de/do/das/dos, on one line
+        let name = r"[A-ZÀ-Ý][\p{L}'.]*(?:[^\S\n]+(?:d[aeo]s?[^\S\n]+)?[A-ZÀ-Ý][\p{L}'.]*)+";
+        Self {
+            block: Regex::new(&format!(
+                r"(?i:assinad[oa]\s+(eletronicamente|digitalmente|de\s+forma\s+digital)\s+por)\s*:?\s*({})(?::\s*(\d{{11}}))?",
+                name
+            ))
+            .expect("valid signature regex"),
+            next: Regex::new(r"(?i)assinad[oa]\s").expect("valid regex"),
+            date: Regex::new(r"\b(\d{2})/(\d{2})/(\d{4})\b").expect("valid date regex"),
+            adobe_date: Regex::new(r"Dados:\s*(\d{4})\.(\d{2})\.(\d{2})")
+                .expect("valid date regex"),
+            code: Regex::new(
+                r"(?i)(?:c[óo]digo(?:\s+verificador|\s+de\s+verifica[çc][ãa]o)?|n[úu]mero\s+do\s+documento)\s*:?\s*([A-Z0-9][A-Za-z0-9]{5,})",
+            )
+            .expect("valid code regex"),
+            icp_brasil: Regex::new(r"(?i)ICP[\s-]*Brasil|2\.200-2").expect("valid regex"),
+            issuer: Regex::new(r"\bAC[^\S\n]+[A-Z][\w-]*(?:[^\S\n]+[\w-]+){0,4}")
+                .expect("valid issuer regex"),
+        }
+    }
+}
+
+/// Signature blocks in a text, in order.
+fn read(patterns: &Patterns, text: &str) -> Vec<Signature> {
+    let mut signatures = Vec::new();
+    for caps in patterns.block.captures_iter(text) {
+        let end = caps.get(0).expect("whole match").end();
+        let mut window = &text[end..];
+        if let Some(next) = patterns.next.find(window) {
+            window = &window[..next.start()];
+        }
+        let cut = window
+            .char_indices()
+            .nth(WINDOW)
+            .map_or(window.len(), |(i, _)| i);
+        let window = &window[..cut];
+
+        let date = patterns
+            .date
+            .captures(window)
+            .map(|d| format!("{}-{}-{}", &d[3], &d[2], &d[1]))
+            .or_else(|| {
+                let d = patterns.adobe_date.captures(window)?;
+                Some(format!("{}-{}-{}", &d[1], &d[2], &d[3]))
+            });
+        let cpf = caps.get(3).map(|m| m.as_str().to_string());
+        let icp_brasil = patterns.icp_brasil.is_match(window);
+        let issuer = patterns
+            .issuer
+            .find(window)
+            .map(|m| m.as_str().trim().to_string());
+        let certificate =
+            (icp_brasil || issuer.is_some() || cpf.is_some()).then_some(Certificate {
+                icp_brasil,
+                issuer,
+                cpf,
+            });
+        let digital = !caps[1].to_lowercase().starts_with("eletr");
+        signatures.push(Signature {
+            name: Some(caps[2].trim().to_string()),
+            signature_type: if digital || certificate.is_some() {
+                SignatureType::Digital
+            } else {
+                SignatureType::Electronic
+            },
+            date,
+            certificate,
+            verification_code: patterns.code.captures(window).map(|c| c[1].to_string()),
+            page: None,
+        });
+    }
+    signatures
+}
+
+/// Signatures on a node's pages, each signer and date once (court systems
+/// repeat the block in every page's footer).
+fn detect(patterns: &Patterns, content: &str) -> Vec<Signature> {
+    let pages = extractor::pages_from_content(content);
+    let found: Vec<Signature> = if pages.is_empty() {
+        read(patterns, content)
+    } else {
+        pages
+            .iter()
+            .flat_map(|page| {
+                read(patterns, &page.text).into_iter().map(|s| Signature {
+                    page: Some(page.page_num),
+                    ..s
+                })
+            })
+            .collect()
+    };
+    let mut seen = HashSet::new();
+    found
+        .into_iter()
+        .filter(|s| {
+            let name = s.name.as_deref().map(str::to_lowercase);
+            seen.insert((name, s.signature_type, s.date.clone()))
+        })
+        .collect()
+}
+
+/// Write `signatures` into a node's metadata, with `signed_by` and
+/// `signature_type` summarizing them.
+pub fn annotate(node: &mut DocumentNode, signatures: &[Signature]) {
+    let Some(strongest) = signatures.iter().map(|s| s.signature_type).min() else {
+        return;
+    };
+    if node.metadata.is_null() {
+        node.metadata = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let Some(obj) = node.metadata.as_object_mut() {
+        let mut names: Vec<&str> = Vec::new();
+        for name in signatures.iter().filter_map(|s| s.name.as_deref()) {
+            if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                names.push(name);
+            }
+        }
+        obj.insert("signed_by".to_string(), json!(names));
+        obj.insert("signature_type".to_string(), json!(strongest));
+        obj.insert("signatures".to_string(), json!(signatures));
+    }
+}
+
+/// Read signature blocks from the content of every node and annotate the
+/// nodes that have any.
+pub fn apply(extraction: &mut Extraction, store: &ContentStore) -> SignatureReport {
+    fn walk(
+        nodes: &mut [DocumentNode],
+        patterns: &Patterns,
+        store: &ContentStore,
+        report: &mut SignatureReport,
+    ) {
+        for node in nodes {
+            let content = node.content_ref.as_deref().and_then(|r| store.get_full(r));
+            if let Some(content) = content {
+                let signatures = detect(patterns, &content);
+                if !signatures.is_empty() {
+                    annotate(node, &signatures);
+                    report.nodes += 1;
+                    report.signatures += signatures.len();
+                }
+            }
+            walk(&mut node.children, patterns, store, report);
+        }
+    }
+    let mut report = SignatureReport::default();
+    walk(
+        &mut extraction.children,
+        &Patterns::new(),
+        store,
+        &mut report,
+    );
+    report
+}
+
+/// Whether [`apply`] found a signature on a node.
+pub fn is_signed(node: &DocumentNode) -> bool {
+    node.metadata.get("signatures").is_some()
+}
+
+const VISION_PROMPT: &str = "This is the last page of a document from a court record. \
+List every signature and stamp on it, with the signer's name when it is legible and its type: \
+\"handwritten\" (signed by hand), \"stamp\" (a rubber stamp or seal), \"digital\" (a printed \
+digital-signature block with a certificate) or \"electronic\" (a printed electronic-signature \
+notice). Return an empty list when the page is not signed or stamped.";
+
+#[derive(Deserialize)]
+struct VisionAnswer {
+    #[serde(default)]
+    signatures: Vec<VisionSignature>,
+}
+
+#[derive(Deserialize)]
+struct VisionSignature {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(rename = "type")]
+    signature_type: SignatureType,
+}
+
+/// Ask the LLM for the signatures and stamps on a page image (PNG).
+pub async fn read_page_image(
+    client: &OpenRouterClient,
+    png: Vec<u8>,
+    page: u32,
+) -> Result<Vec<Signature>> {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "signatures": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": ["string", "null"]},
+                        "type": {"type": "string", "enum": ["digital", "electronic", "handwritten", "stamp"]}
+                    },
+                    "required": ["type"]
+                }
+            }
+        },
+        "required": ["signatures"]
+    });
+    let answer: VisionAnswer = client
+        .chat_json(
+            vec![Message::user_with_images(VISION_PROMPT, vec![png])],
+            "signatures",
+            schema,
+        )
+        .await?;
+    Ok(answer
+        .signatures
+        .into_iter()
+        .map(|s| Signature {
+            name: s.name.filter(|n| !n.trim().is_empty()),
+            signature_type: s.signature_type,
+            date: None,
+            certificate: None,
+            verification_code: None,
+            page: Some(page),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_signature_blocks() {
+        let patterns = Patterns::new();
+        let content = "--- Page 3 ---\nJULGO PROCEDENTE o pedido.\n\
+            Documento assinado eletronicamente por JOSÉ DOS SANTOS, Juiz de Direito, em 20/06/2024, \
+            às 14:02. A autenticidade pode ser conferida com o código verificador 7000123456v2.\n\n\
+            --- Page 4 ---\nAssinado eletronicamente por: JOSÉ DOS SANTOS - 20/06/2024 14:02:11\n\
+            Assinado de forma digital por MARIA DA SILVA:12345678909\n\
+            Dados: 2024.06.21 09:15:00 -03'00'\nCertificado AC SOLUTI Multipla v5, ICP-Brasil";
+        let found = detect(&patterns, content);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name.as_deref(), Some("JOSÉ DOS SANTOS"));
+        assert_eq!(found[0].signature_type, SignatureType::Electronic);
+        assert_eq!(found[0].date.as_deref(), Some("2024-06-20"));
+        assert_eq!(found[0].verification_code.as_deref(), Some("7000123456v2"));
+        assert_eq!(found[0].page, Some(3));
+        assert_eq!(found[1].signature_type, SignatureType::Digital);
+        assert_eq!(found[1].date.as_deref(), Some("2024-06-21"));
+        let certificate = found[1].certificate.as_ref().unwrap();
+        assert!(certificate.icp_brasil);
+        assert_eq!(certificate.cpf.as_deref(), Some("12345678909"));
+        assert_eq!(certificate.issuer.as_deref(), Some("AC SOLUTI Multipla v5"));
+
+        let mut node: DocumentNode = serde_json::from_value(json!({
+            "id": "sentenca", "type": "DECISAO", "summary": ""
+        }))
+        .unwrap();
+        annotate(&mut node, &found);
+        assert_eq!(
+            node.metadata["signed_by"],
+            json!(["JOSÉ DOS SANTOS", "MARIA DA SILVA"])
+        );
+        assert_eq!(node.metadata["signature_type"], "digital");
+        assert!(is_signed(&node));
+    }
+}
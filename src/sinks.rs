@@ -0,0 +1,285 @@
+//! Pushing completed datasets to external warehouses.
+//!
+//! A config's `sheet_config.sinks` lists where each completed dataset goes:
+//! a webhook (NDJSON batches), a Postgres table, or a BigQuery table. Every
+//! sink gets the same records, one per row — `{dataset_id, schema_name,
+//! row_index, row_data}` — so one table can hold rows of any schema, and a
+//! re-push overwrites instead of duplicating (Postgres upserts on the first
+//! three fields, BigQuery dedupes on them as the insert ID). How each push
+//! went is recorded on the dataset as a [`SinkDelivery`].
+
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tracing::{error, info};
+
+use crate::config::{SinkConfig, SinkTarget};
+use crate::gcp_auth::ServiceAccountAuth;
+use crate::schema::now_iso8601;
+use crate::sheet_schema::{DeliveryStatus, SheetExtraction, SinkDelivery};
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const BIGQUERY_SCOPE: &str = "https://www.googleapis.com/auth/bigquery.insertdata";
+
+/// Push every row of `dataset` to `sink`, stopping at the first batch that
+/// fails. Each request gets `timeout`.
+pub async fn deliver(
+    client: &reqwest::Client,
+    dataset: &SheetExtraction,
+    sink: &SinkConfig,
+    timeout: Duration,
+) -> SinkDelivery {
+    let records = records(dataset);
+    let batch_size = sink.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1);
+    let mut delivery = SinkDelivery {
+        sink: sink.label().to_string(),
+        status: DeliveryStatus::Delivered,
+        rows: 0,
+        batches: 0,
+        attempted_at: now_iso8601(),
+        error: None,
+    };
+
+    let result = async {
+        let mut target = Target::open(client, &sink.target).await?;
+        for batch in records.chunks(batch_size) {
+            tokio::time::timeout(timeout, target.push(batch))
+                .await
+                .unwrap_or_else(|_| Err(anyhow!("timed out after {}s", timeout.as_secs())))?;
+            delivery.rows += batch.len();
+            delivery.batches += 1;
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => info!(
+            "Pushed dataset {} to sink {} ({} rows in {} batches)",
+            dataset.id, delivery.sink, delivery.rows, delivery.batches
+        ),
+        Err(e) => {
+            error!(
+                "Pushing dataset {} to sink {} failed after {} rows: {:#}",
+                dataset.id, delivery.sink, delivery.rows, e
+            );
+            delivery.status = DeliveryStatus::Failed;
+            delivery.error = Some(format!("{:#}", e));
+        }
+    }
+    delivery
+}
+
+/// One record per row, in schema then row order.
+fn records(dataset: &SheetExtraction) -> Vec<Value> {
+    dataset
+        .schemas
+        .iter()
+        .flat_map(|schema| {
+            schema.rows.iter().enumerate().map(|(row_index, row)| {
+                json!({
+                    "dataset_id": dataset.id,
+                    "schema_name": schema.name,
+                    "row_index": row_index,
+                    "row_data": row,
+                })
+            })
+        })
+        .collect()
+}
+
+/// A batch as newline-delimited JSON.
+fn ndjson(batch: &[Value]) -> String {
+    batch.iter().map(|r| format!("{}\n", r)).collect()
+}
+
+/// A sink ready to take batches.
+enum Target<'a> {
+    Webhook {
+        client: &'a reqwest::Client,
+        url: &'a str,
+        token: Option<String>,
+    },
+    Postgres {
+        pool: sqlx::PgPool,
+        table: &'a str,
+    },
+    Bigquery {
+        client: &'a reqwest::Client,
+        token: String,
+        url: String,
+    },
+}
+
+impl<'a> Target<'a> {
+    async fn open(client: &'a reqwest::Client, target: &'a SinkTarget) -> Result<Self> {
+        Ok(match target {
+            SinkTarget::Webhook { url, token_env } => Self::Webhook {
+                client,
+                url,
+                token: token_env.as_deref().map(env).transpose()?,
+            },
+            SinkTarget::Postgres { url_env, table } => {
+                if !is_table_name(table) {
+                    bail!("invalid table name \"{}\"", table);
+                }
+                let pool = PgPoolOptions::new()
+                    .max_connections(1)
+                    .connect(&env(url_env)?)
+                    .await
+                    .context("Failed to connect to Postgres")?;
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {} (\
+                     dataset_id TEXT NOT NULL, schema_name TEXT NOT NULL, \
+                     row_index INTEGER NOT NULL, row_data JSONB NOT NULL, \
+                     PRIMARY KEY (dataset_id, schema_name, row_index))",
+                    table
+                ))
+                .execute(&pool)
+                .await?;
+                Self::Postgres { pool, table }
+            }
+            SinkTarget::Bigquery {
+                project,
+                dataset,
+                table,
+            } => {
+                let auth = ServiceAccountAuth::from_key_path(
+                    &env("BIGQUERY_SA_KEY_PATH")?,
+                    BIGQUERY_SCOPE,
+                )?;
+                Self::Bigquery {
+                    client,
+                    token: auth.get_access_token(client).await?,
+                    url: format!(
+                        "https://bigquery.googleapis.com/bigquery/v2/projects/{}/datasets/{}/tables/{}/insertAll",
+                        project, dataset, table
+                    ),
+                }
+            }
+        })
+    }
+
+    async fn push(&mut self, batch: &[Value]) -> Result<()> {
+        match self {
+            Self::Webhook { client, url, token } => {
+                let mut request = client
+                    .post(*url)
+                    .header("Content-Type", "application/x-ndjson")
+                    .body(ndjson(batch));
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                let resp = request.send().await?;
+                if !resp.status().is_success() {
+                    bail!("webhook returned {}", resp.status());
+                }
+            }
+            Self::Postgres { pool, table } => {
+                sqlx::query(&format!(
+                    "INSERT INTO {} (dataset_id, schema_name, row_index, row_data) \
+                     SELECT dataset_id, schema_name, row_index, row_data \
+                     FROM jsonb_to_recordset($1::jsonb) \
+                     AS r(dataset_id TEXT, schema_name TEXT, row_index INTEGER, row_data JSONB) \
+                     ON CONFLICT (dataset_id, schema_name, row_index) \
+                     DO UPDATE SET row_data = EXCLUDED.row_data",
+                    table
+                ))
+                .bind(Value::from(batch.to_vec()))
+                .execute(&*pool)
+                .await?;
+            }
+            Self::Bigquery { client, token, url } => {
+                // BigQuery takes JSON columns as strings
+                let rows: Vec<Value> = batch
+                    .iter()
+                    .map(|r| {
+                        json!({
+                            "insertId": format!(
+                                "{}:{}:{}",
+                                r["dataset_id"].as_str().unwrap_or_default(),
+                                r["schema_name"].as_str().unwrap_or_default(),
+                                r["row_index"]
+                            ),
+                            "json": {
+                                "dataset_id": r["dataset_id"],
+                                "schema_name": r["schema_name"],
+                                "row_index": r["row_index"],
+                                "row_data": r["row_data"].to_string(),
+                            },
+                        })
+                    })
+                    .collect();
+                let resp = client
+                    .post(url.as_str())
+                    .bearer_auth(&*token)
+                    .json(&json!({ "rows": rows }))
+                    .send()
+                    .await?;
+                let status = resp.status();
+                let body: Value = resp.json().await.unwrap_or_default();
+                if !status.is_success() {
+                    bail!("BigQuery returned {}: {}", status, body["error"]["message"]);
+                }
+                if let Some(errors) = body["insertErrors"].as_array().filter(|e| !e.is_empty()) {
+                    bail!("BigQuery rejected {} rows: {}", errors.len(), errors[0]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn env(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|_| anyhow!("{} not set", name))
+}
+
+/// A plain or schema-qualified SQL identifier, safe to splice into a query.
+pub fn is_table_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').count() <= 2
+        && name.split('.').all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sheet_schema::DataSchema;
+
+    #[test]
+    fn test_records_and_ndjson() {
+        let mut dataset = SheetExtraction::new("extrato.csv".to_string(), None);
+        dataset.schemas.push(DataSchema {
+            name: "transacoes".to_string(),
+            description: String::new(),
+            columns: Vec::new(),
+            row_count: 2,
+            rows: vec![json!({"valor": 10.5}), json!({"valor": -2.0})],
+            sources: Vec::new(),
+            version: 1,
+            changes: Vec::new(),
+            stats: Vec::new(),
+            edited: false,
+            edits: Vec::new(),
+        });
+
+        let records = records(&dataset);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["row_index"], 1);
+        assert_eq!(records[1]["row_data"]["valor"], -2.0);
+        let body = ndjson(&records);
+        assert_eq!(body.lines().count(), 2);
+        assert!(body.ends_with('\n'));
+
+        assert!(is_table_name("analytics.dataset_rows"));
+        assert!(!is_table_name("rows; DROP TABLE x"));
+        assert!(!is_table_name("a.b.c"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Trimmed views of an extraction for `GET /extractions/:id`.
+//!
+//! A full extraction can run to megabytes, most of it in the node tree. A
+//! [`Projection`] works on the serialized JSON:
+//!
+//! - `fields` keeps only the listed extraction fields (`id` always stays).
+//!   `children.<field>` entries pick node fields the same way and imply
+//!   `children`; nodes always keep `id` and `children`.
+//! - `exclude` drops the listed keys from the extraction and every node.
+//! - `depth` cuts the tree after that many levels (0 drops it entirely);
+//!   a node whose children were cut gets `child_count` instead.
+
+use std::collections::HashSet;
+
+use serde_json::{Map, Value};
+
+#[derive(Debug, Default)]
+pub struct Projection {
+    fields: Option<HashSet<String>>,
+    node_fields: Option<HashSet<String>>,
+    exclude: HashSet<String>,
+    depth: Option<usize>,
+}
+
+impl Projection {
+    /// Build from the comma-separated `fields` and `exclude` query parameters.
+    pub fn new(fields: Option<&str>, exclude: Option<&str>, depth: Option<usize>) -> Self {
+        let mut projection = Self {
+            exclude: split(exclude).collect(),
+            depth,
+            ..Self::default()
+        };
+        if fields.is_some() {
+            let mut top = HashSet::from(["id".to_string()]);
+            let mut nodes = HashSet::new();
+            for field in split(fields) {
+                match field.strip_prefix("children.") {
+                    Some(node_field) => {
+                        top.insert("children".to_string());
+                        nodes.insert(node_field.to_string());
+                    }
+                    None => {
+                        top.insert(field);
+                    }
+                }
+            }
+            if !nodes.is_empty() {
+                nodes.extend(["id".to_string(), "children".to_string()]);
+                projection.node_fields = Some(nodes);
+            }
+            projection.fields = Some(top);
+        }
+        projection
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_none() && self.exclude.is_empty() && self.depth.is_none()
+    }
+
+    /// Trim a serialized extraction in place.
+    pub fn apply(&self, extraction: &mut Value) {
+        let Some(object) = extraction.as_object_mut() else {
+            return;
+        };
+        self.trim(object, self.fields.as_ref());
+        if let Some(children) = object.get_mut("children") {
+            self.trim_nodes(children, 0);
+        }
+        if self.depth == Some(0) {
+            cut_children(object);
+        }
+    }
+
+    fn trim_nodes(&self, nodes: &mut Value, level: usize) {
+        let Some(nodes) = nodes.as_array_mut() else {
+            return;
+        };
+        for node in nodes.iter_mut().filter_map(Value::as_object_mut) {
+            self.trim(node, self.node_fields.as_ref());
+            if self.depth.is_some_and(|depth| level + 1 >= depth) {
+                cut_children(node);
+            } else if let Some(children) = node.get_mut("children") {
+                self.trim_nodes(children, level + 1);
+            }
+        }
+    }
+
+    fn trim(&self, object: &mut Map<String, Value>, keep: Option<&HashSet<String>>) {
+        object.retain(|key, _| {
+            keep.is_none_or(|keep| keep.contains(key)) && !self.exclude.contains(key)
+        });
+    }
+}
+
+/// Replace `children` with `child_count`.
+fn cut_children(object: &mut Map<String, Value>) {
+    if let Some(children) = object.remove("children") {
+        let count = children.as_array().map_or(0, Vec::len);
+        object.insert("child_count".to_string(), count.into());
+    }
+}
+
+fn split(list: Option<&str>) -> impl Iterator<Item = String> + '_ {
+    list.unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extraction() -> Value {
+        json!({
+            "id": "ext_1",
+            "summary": "s",
+            "metadata": {"k": 1},
+            "children": [{
+                "id": "a",
+                "type": "SECTION",
+                "summary": "a",
+                "confidence": {"overall": 0.9},
+                "references": [{"target": "b"}],
+                "children": [{"id": "a1", "summary": "a1", "children": [{"id": "a1x"}]}]
+            }]
+        })
+    }
+
+    #[test]
+    fn test_projection() {
+        let mut value = extraction();
+        Projection::new(
+            Some("summary,children.summary"),
+            Some("confidence"),
+            Some(2),
+        )
+        .apply(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "id": "ext_1",
+                "summary": "s",
+                "children": [{
+                    "id": "a",
+                    "summary": "a",
+                    "children": [{"id": "a1", "summary": "a1", "child_count": 1}]
+                }]
+            })
+        );
+
+        let mut value = extraction();
+        Projection::new(None, Some("references, confidence"), Some(0)).apply(&mut value);
+        assert_eq!(
+            value,
+            json!({"id": "ext_1", "summary": "s", "metadata": {"k": 1}, "child_count": 1})
+        );
+        assert!(Projection::new(None, None, None).is_empty());
+    }
+}
@@ -0,0 +1,597 @@
+//! Pluggable persistence backends.
+//!
+//! Defines the [`Storage`] trait and shared row types so extractions, nodes,
+//! content, datasets, and configs can be persisted to Supabase (PostgREST),
+//! plain Postgres, or an embedded SQLite database. The backend is selected via
+//! `STORAGE_BACKEND`.
+
+pub mod postgres;
+pub mod sqlite;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::config::ExtractionConfig;
+use crate::content_store::ContentStore;
+use crate::schema::{
+    ConfidenceScores, DocumentNode, Extraction, ExtractionDiagnostics, ExtractionStatus,
+    NodeReview, Relationship, StructureMapEntry,
+};
+use crate::sheet_schema::{
+    ColumnDef, DataSchema, RowEdit, RowSource, SchemaChange, SchemaRelationship, SheetExtraction,
+};
+
+/// Async trait implemented by each persistence backend.
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Cheap round trip to check the backend is reachable.
+    async fn ping(&self) -> Result<()>;
+
+    /// Persist a completed extraction with its nodes, content, and relationships.
+    async fn upload_extraction(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+    ) -> Result<()>;
+
+    /// List extractions matching `filter` (lightweight summaries), newest first.
+    async fn list_extractions(&self, filter: &ExtractionFilter) -> Result<Vec<ExtractionRow>>;
+
+    /// Fetch an extraction by ID without node content. Nodes that have
+    /// content keep their `content_ref`; the text is loaded on demand.
+    async fn fetch_extraction(&self, id: &str) -> Result<Option<Extraction>>;
+
+    /// Load the content of every node of an extraction into `content_store`,
+    /// returning how many nodes had content.
+    async fn fetch_extraction_content(
+        &self,
+        id: &str,
+        content_store: &ContentStore,
+    ) -> Result<usize>;
+
+    /// Save a reviewer's correction to one node, mark the extraction reviewed,
+    /// and append `review` to its audit trail. Returns `false` (and changes
+    /// nothing) when the extraction or node is not in storage.
+    async fn record_review(
+        &self,
+        extraction_id: &str,
+        node: &DocumentNode,
+        review: &NodeReview,
+    ) -> Result<bool>;
+
+    /// Re-save a stored extraction after a structural edit (move, merge,
+    /// split); `removed` lists node IDs that no longer exist. Returns `false`
+    /// (and changes nothing) when the extraction is not in storage.
+    async fn replace_tree(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        removed: &[String],
+    ) -> Result<bool>;
+
+    /// Delete an extraction with its nodes, content, relationships, and reviews.
+    /// Deleting an extraction that is not stored is not an error.
+    async fn delete_extraction(&self, id: &str) -> Result<()>;
+
+    /// Fetch content by node_id only (no extraction_id needed).
+    async fn fetch_content_by_node_id(&self, node_id: &str) -> Result<Option<String>>;
+
+    /// Persist a completed sheet extraction (dataset) and its rows.
+    async fn upload_dataset(&self, dataset: &SheetExtraction) -> Result<()>;
+
+    /// List all datasets (lightweight summaries).
+    async fn list_datasets(&self) -> Result<Vec<DatasetRow>>;
+
+    /// Fetch a full dataset by ID, reconstructing schemas with their rows.
+    async fn fetch_dataset(&self, id: &str) -> Result<Option<SheetExtraction>>;
+
+    /// Query rows from a specific schema within a dataset (paginated).
+    async fn query_dataset_rows(
+        &self,
+        dataset_id: &str,
+        schema_name: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<serde_json::Value>>;
+
+    /// Delete a dataset and its rows.
+    async fn delete_dataset(&self, id: &str) -> Result<()>;
+
+    /// List all persisted configs.
+    async fn list_configs(&self) -> Result<Vec<ExtractionConfig>>;
+
+    /// Upsert a config (insert or update).
+    async fn upsert_config(&self, config: &ExtractionConfig) -> Result<()>;
+
+    /// Delete a config by name.
+    async fn delete_config(&self, name: &str) -> Result<()>;
+}
+
+/// Known backend identifiers for `STORAGE_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackendKind {
+    Supabase,
+    Postgres,
+    Sqlite,
+}
+
+impl StorageBackendKind {
+    /// Parse an env-var string into a backend kind.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "supabase" => Some(Self::Supabase),
+            "postgres" => Some(Self::Postgres),
+            "sqlite" => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+/// Build the storage backend selected by `STORAGE_BACKEND`.
+///
+/// When unset, Supabase is used if its env vars are present and persistence is
+/// disabled otherwise (`Ok(None)`). An explicitly selected backend that fails
+/// to initialize is an error.
+pub async fn from_env() -> Result<Option<Arc<dyn Storage>>> {
+    let requested = std::env::var("STORAGE_BACKEND").ok();
+
+    let kind = match requested.as_deref() {
+        None | Some("") => {
+            return match crate::supabase::SupabaseClient::from_env() {
+                Ok(client) => Ok(Some(Arc::new(client))),
+                Err(e) => {
+                    info!(
+                        "Storage not configured: {} (upload=true will be skipped)",
+                        e
+                    );
+                    Ok(None)
+                }
+            };
+        }
+        Some(name) => StorageBackendKind::from_str(name).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown STORAGE_BACKEND: '{}'. Available: supabase, postgres, sqlite",
+                name
+            )
+        })?,
+    };
+
+    let storage: Arc<dyn Storage> = match kind {
+        StorageBackendKind::Supabase => Arc::new(crate::supabase::SupabaseClient::from_env()?),
+        StorageBackendKind::Postgres => Arc::new(postgres::PostgresStorage::from_env().await?),
+        StorageBackendKind::Sqlite => Arc::new(sqlite::SqliteStorage::from_env().await?),
+    };
+
+    Ok(Some(storage))
+}
+
+// ============================================================================
+// Shared row types
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+pub struct ExtractionRow {
+    pub id: String,
+    pub config_name: Option<String>,
+    #[serde(default)]
+    pub config_version: Option<String>,
+    #[serde(default)]
+    pub prompt_override: Option<String>,
+    pub source_file: String,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    #[serde(default)]
+    pub duplicate_of: Option<String>,
+    #[serde(default)]
+    pub parent_extraction_id: Option<String>,
+    #[serde(default)]
+    pub parent_node_id: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub reviewed: bool,
+    pub total_pages: Option<u32>,
+    #[serde(default)]
+    pub ocr_provider: Option<String>,
+    #[serde(default)]
+    pub ocr_confidence: Option<f64>,
+    pub summary: String,
+    pub structure_map: Option<Vec<StructureMapEntry>>,
+    pub metadata: Option<serde_json::Value>,
+    pub reference_index: Option<serde_json::Value>,
+    #[serde(default)]
+    pub diagnostics: Option<ExtractionDiagnostics>,
+    pub readable_id: Option<String>,
+    pub extracted_at: String,
+    pub extractor_version: Option<String>,
+    /// Written on upload so listings don't have to load the tree
+    #[serde(default)]
+    pub node_count: u32,
+}
+
+impl ExtractionRow {
+    /// Combine the main record with its relationships and rebuilt node tree,
+    /// whose `references` and `referenced_by` come from the relationships.
+    /// `structure_map` is rebuilt from the tree, which node edits keep current.
+    pub fn into_extraction(
+        self,
+        relationships: Vec<Relationship>,
+        children: Vec<DocumentNode>,
+    ) -> Extraction {
+        let mut extraction = Extraction {
+            id: self.id,
+            version: 1,
+            status: ExtractionStatus::Completed,
+            error: None,
+            stage: None,
+            progress_pct: None,
+            timing: None,
+            retry_count: 0,
+            config_name: self.config_name,
+            config_version: self.config_version,
+            prompt_override: self.prompt_override,
+            previous_version_id: None,
+            content_hash: self.content_hash,
+            fingerprint: self.fingerprint,
+            duplicate_of: self.duplicate_of,
+            parent_extraction_id: self.parent_extraction_id,
+            parent_node_id: self.parent_node_id,
+            language: self.language,
+            source_file: self.source_file,
+            source_url: self.source_url,
+            extracted_at: self.extracted_at,
+            extractor_version: self.extractor_version,
+            total_pages: self.total_pages,
+            ocr_provider: self.ocr_provider,
+            ocr_confidence: self.ocr_confidence,
+            summary: self.summary,
+            structure_map: Vec::new(),
+            relationships,
+            diagnostics: self.diagnostics,
+            metadata: self.metadata.unwrap_or(serde_json::Value::Null),
+            reference_index: self.reference_index.unwrap_or(serde_json::Value::Null),
+            readable_id: self.readable_id,
+            reviewed: self.reviewed,
+            reviews: Vec::new(),
+            children,
+        };
+        crate::relationships::link(&mut extraction);
+        extraction.refresh_structure_map();
+        extraction
+    }
+}
+
+/// Which extractions [`Storage::list_extractions`] returns. Every backend
+/// applies it in its query; [`ExtractionFilter::matches`] applies it to
+/// in-memory extractions the same way.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionFilter {
+    pub config_name: Option<String>,
+    pub config_version: Option<String>,
+    /// Case-insensitive substring of the source file name
+    pub source_file: Option<String>,
+    /// Extracted at or after this ISO 8601 date or timestamp
+    pub since: Option<String>,
+    /// Extracted before this ISO 8601 date or timestamp
+    pub until: Option<String>,
+    pub reviewed: Option<bool>,
+    /// Return at most this many rows (the newest)
+    pub limit: Option<usize>,
+}
+
+impl ExtractionFilter {
+    /// Whether an extraction with these fields passes the filter (ignores `limit`).
+    pub fn matches(
+        &self,
+        config_name: Option<&str>,
+        config_version: Option<&str>,
+        source_file: &str,
+        extracted_at: &str,
+        reviewed: bool,
+    ) -> bool {
+        self.config_name
+            .as_deref()
+            .is_none_or(|name| config_name == Some(name))
+            && self
+                .config_version
+                .as_deref()
+                .is_none_or(|version| config_version == Some(version))
+            && self
+                .source_file
+                .as_deref()
+                .is_none_or(|part| source_file.to_lowercase().contains(&part.to_lowercase()))
+            && self
+                .since
+                .as_deref()
+                .is_none_or(|since| extracted_at >= since)
+            && self
+                .until
+                .as_deref()
+                .is_none_or(|until| extracted_at < until)
+            && self.reviewed.is_none_or(|r| r == reviewed)
+    }
+}
+
+/// `LIKE` pattern matching `part` anywhere, with `\` as the escape character.
+pub fn like_pattern(part: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in part.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeRow {
+    pub id: String,
+    pub parent_id: Option<String>,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub subtype: Option<String>,
+    pub label: Option<String>,
+    pub page_start: Option<u32>,
+    pub page_end: Option<u32>,
+    #[serde(default)]
+    pub ocr_span_start: Option<usize>,
+    #[serde(default)]
+    pub ocr_span_end: Option<usize>,
+    #[serde(default)]
+    pub pdf_page_start: Option<u32>,
+    #[serde(default)]
+    pub pdf_page_end: Option<u32>,
+    pub date: Option<String>,
+    pub author: Option<String>,
+    pub summary: String,
+    pub confidence: Option<ConfidenceScores>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    pub reviewed: bool,
+    #[serde(default)]
+    pub child_extraction_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DatasetRow {
+    pub id: String,
+    pub source_file: String,
+    pub config_name: Option<String>,
+    pub extracted_at: String,
+    pub summary: String,
+    pub schemas: serde_json::Value,
+    pub relationships: Option<serde_json::Value>,
+    pub status: Option<String>,
+}
+
+/// Schema definition as stored in the `schemas` column (column defs, no rows).
+#[derive(Debug, Deserialize)]
+struct DatasetSchemaJson {
+    name: String,
+    description: String,
+    #[serde(default)]
+    columns: Vec<ColumnDef>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    row_count: usize,
+    #[serde(default)]
+    sources: Vec<RowSource>,
+    #[serde(default)]
+    version: Option<u32>,
+    #[serde(default)]
+    changes: Vec<SchemaChange>,
+    #[serde(default)]
+    edited: bool,
+    #[serde(default)]
+    edits: Vec<RowEdit>,
+}
+
+// ============================================================================
+// Shared helpers
+// ============================================================================
+
+/// `ocr_span_start`/`ocr_span_end` column values for a node.
+pub fn span_columns(node: &DocumentNode) -> (Option<i64>, Option<i64>) {
+    node.ocr_span
+        .map(|[start, end]| (Some(start as i64), Some(end as i64)))
+        .unwrap_or((None, None))
+}
+
+/// `pdf_page_start`/`pdf_page_end` column values for a node.
+pub fn pdf_page_columns(node: &DocumentNode) -> (Option<i64>, Option<i64>) {
+    node.pdf_page_range
+        .map(|[start, end]| (Some(i64::from(start)), Some(i64::from(end))))
+        .unwrap_or((None, None))
+}
+
+/// Flatten a node tree into `(parent_id, node)` pairs in depth-first order.
+pub fn flatten_nodes<'a>(
+    nodes: &'a [DocumentNode],
+    parent_id: Option<&'a str>,
+    out: &mut Vec<(Option<&'a str>, &'a DocumentNode)>,
+) {
+    for node in nodes {
+        out.push((parent_id, node));
+        if !node.children.is_empty() {
+            flatten_nodes(&node.children, Some(&node.id), out);
+        }
+    }
+}
+
+/// Build the `schemas` column value for a dataset (column defs only, no rows).
+pub fn dataset_schemas_json(dataset: &SheetExtraction) -> Vec<serde_json::Value> {
+    dataset
+        .schemas
+        .iter()
+        .map(|s| {
+            serde_json::json!({
+                "name": s.name,
+                "description": s.description,
+                "columns": s.columns,
+                "row_count": s.row_count,
+                "sources": s.sources,
+                "version": s.version,
+                "changes": s.changes,
+                "edited": s.edited,
+                "edits": s.edits,
+            })
+        })
+        .collect()
+}
+
+/// Reconstruct a dataset from its main record plus `(schema_name, row_data)`
+/// entries ordered by row index.
+pub fn assemble_dataset(
+    row: DatasetRow,
+    data_rows: Vec<(String, serde_json::Value)>,
+) -> SheetExtraction {
+    let schema_defs: Vec<DatasetSchemaJson> =
+        serde_json::from_value(row.schemas.clone()).unwrap_or_default();
+
+    let mut rows_by_schema: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    for (schema_name, row_data) in data_rows {
+        rows_by_schema
+            .entry(schema_name)
+            .or_default()
+            .push(row_data);
+    }
+
+    let schemas: Vec<DataSchema> = schema_defs
+        .into_iter()
+        .map(|s| {
+            let rows = rows_by_schema.remove(&s.name).unwrap_or_default();
+            // Stats aren't stored; they're derived from the rows
+            let mut schema = DataSchema {
+                name: s.name,
+                description: s.description,
+                columns: s.columns,
+                row_count: rows.len(),
+                rows,
+                sources: s.sources,
+                version: s.version.unwrap_or(1),
+                changes: s.changes,
+                stats: Vec::new(),
+                edited: s.edited,
+                edits: s.edits,
+            };
+            schema.refresh_stats();
+            schema
+        })
+        .collect();
+
+    let relationships: Vec<SchemaRelationship> = row
+        .relationships
+        .as_ref()
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+
+    SheetExtraction {
+        id: row.id,
+        status: ExtractionStatus::Completed,
+        error: None,
+        config_name: row.config_name,
+        source_file: row.source_file,
+        extracted_at: row.extracted_at,
+        summary: row.summary,
+        schemas,
+        relationships,
+        // Deliveries live with the dataset on disk; storage doesn't keep them
+        deliveries: Vec::new(),
+    }
+}
+
+/// Build a nested tree from flat node rows (roots have `parent_id = None`).
+/// Children keep the order in which they appear in `nodes`.
+/// Rebuild the node tree; nodes in `with_content` get a `content_ref`.
+pub fn build_tree(nodes: &[NodeRow], with_content: &HashSet<String>) -> Vec<DocumentNode> {
+    // Index nodes by id
+    let node_map: HashMap<&str, &NodeRow> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+
+    // Group children by parent_id
+    let mut children_of: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
+    for node in nodes {
+        children_of
+            .entry(node.parent_id.as_deref())
+            .or_default()
+            .push(&node.id);
+    }
+
+    fn build_node(
+        id: &str,
+        node_map: &HashMap<&str, &NodeRow>,
+        children_of: &HashMap<Option<&str>, Vec<&str>>,
+        with_content: &HashSet<String>,
+    ) -> DocumentNode {
+        let row = node_map[id];
+        let page_range = match (row.page_start, row.page_end) {
+            (Some(s), Some(e)) => Some([s, e]),
+            _ => None,
+        };
+        let ocr_span = match (row.ocr_span_start, row.ocr_span_end) {
+            (Some(s), Some(e)) => Some([s, e]),
+            _ => None,
+        };
+        let pdf_page_range = match (row.pdf_page_start, row.pdf_page_end) {
+            (Some(s), Some(e)) => Some([s, e]),
+            _ => None,
+        };
+        let content_ref = if with_content.contains(id) {
+            Some(format!("content://{}", id))
+        } else {
+            None
+        };
+
+        let children: Vec<DocumentNode> = children_of
+            .get(&Some(id))
+            .map(|ids| {
+                ids.iter()
+                    .map(|cid| build_node(cid, node_map, children_of, with_content))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        DocumentNode {
+            id: row.id.clone(),
+            node_type: row.node_type.clone(),
+            subtype: row.subtype.clone(),
+            label: row.label.clone(),
+            page_range,
+            ocr_span,
+            pdf_page_range,
+            date: row.date.clone(),
+            author: row.author.clone(),
+            summary: row.summary.clone(),
+            references: Vec::new(),
+            referenced_by: Vec::new(),
+            content_ref,
+            confidence: row.confidence.clone(),
+            metadata: row.metadata.clone().unwrap_or(serde_json::Value::Null),
+            reviewed: row.reviewed,
+            child_extraction_id: row.child_extraction_id.clone(),
+            children,
+        }
+    }
+
+    // Root nodes have parent_id = None
+    children_of
+        .get(&None)
+        .map(|ids| {
+            ids.iter()
+                .map(|id| build_node(id, &node_map, &children_of, with_content))
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,849 @@
+//! Direct Postgres storage backend (`STORAGE_BACKEND=postgres`).
+//!
+//! Talks to Postgres over the wire via sqlx instead of PostgREST, so no
+//! Supabase-specific headers (`Content-Profile`, `Prefer`) are involved.
+//! Tables live in the `extraction` schema and are created by the embedded
+//! migrations in `migrations/postgres/`.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgRow};
+use sqlx::{Postgres, QueryBuilder, Row};
+use tracing::{debug, info};
+
+use super::{
+    assemble_dataset, build_tree, dataset_schemas_json, flatten_nodes, like_pattern,
+    pdf_page_columns, span_columns, DatasetRow, ExtractionFilter, ExtractionRow, NodeRow, Storage,
+};
+use crate::compression::{self, Compression};
+use crate::config::ExtractionConfig;
+use crate::content_store::ContentStore;
+use crate::schema::{DocumentNode, Extraction, NodeReview, Relationship};
+use crate::sheet_schema::SheetExtraction;
+
+/// Rows per multi-value INSERT when uploading dataset rows.
+const ROW_BATCH_SIZE: usize = 100;
+
+/// Postgres-backed storage.
+#[derive(Clone)]
+pub struct PostgresStorage {
+    pool: PgPool,
+    compression: Compression,
+}
+
+impl PostgresStorage {
+    /// Connect using `DATABASE_URL` and run migrations.
+    pub async fn from_env() -> Result<Self> {
+        let url = std::env::var("DATABASE_URL").map_err(|_| anyhow!("DATABASE_URL not set"))?;
+        let max_connections = std::env::var("DATABASE_MAX_CONNECTIONS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5);
+        Self::connect(&url, max_connections).await
+    }
+
+    /// Connect to `url` and run migrations. TLS follows the URL's `sslmode`
+    /// (`disable`, `prefer` (the default), `require`, `verify-ca`,
+    /// `verify-full`) and `sslrootcert`.
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        let options = PgConnectOptions::from_str(url).context("Invalid DATABASE_URL")?;
+        let ssl_mode = options.get_ssl_mode();
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect_with(options)
+            .await
+            .context("Failed to connect to Postgres")?;
+
+        sqlx::migrate!("./migrations/postgres")
+            .run(&pool)
+            .await
+            .context("Failed to run Postgres migrations")?;
+
+        info!(
+            "Postgres storage ready ({} max connections, sslmode {:?})",
+            max_connections, ssl_mode
+        );
+        Ok(Self {
+            pool,
+            compression: Compression::from_env(),
+        })
+    }
+
+    fn extraction_row(row: &PgRow) -> Result<ExtractionRow> {
+        Ok(ExtractionRow {
+            id: row.try_get("id")?,
+            config_name: row.try_get("config_name")?,
+            config_version: row.try_get("config_version")?,
+            prompt_override: row.try_get("prompt_override")?,
+            source_file: row.try_get("source_file")?,
+            source_url: row.try_get("source_url")?,
+            content_hash: row.try_get("content_hash")?,
+            fingerprint: row.try_get("fingerprint")?,
+            duplicate_of: row.try_get("duplicate_of")?,
+            parent_extraction_id: row.try_get("parent_extraction_id")?,
+            parent_node_id: row.try_get("parent_node_id")?,
+            language: row.try_get("language")?,
+            reviewed: row.try_get("reviewed")?,
+            total_pages: row
+                .try_get::<Option<i32>, _>("total_pages")?
+                .map(|n| n as u32),
+            ocr_provider: row.try_get("ocr_provider")?,
+            ocr_confidence: row.try_get("ocr_confidence")?,
+            summary: row.try_get("summary")?,
+            structure_map: from_json(row.try_get("structure_map")?),
+            metadata: row.try_get("metadata")?,
+            reference_index: row.try_get("reference_index")?,
+            diagnostics: from_json(row.try_get("diagnostics")?),
+            readable_id: row.try_get("readable_id")?,
+            extracted_at: row.try_get("extracted_at")?,
+            extractor_version: row.try_get("extractor_version")?,
+            node_count: row.try_get::<i32, _>("node_count")? as u32,
+        })
+    }
+
+    fn dataset_row(row: &PgRow) -> Result<DatasetRow> {
+        Ok(DatasetRow {
+            id: row.try_get("id")?,
+            source_file: row.try_get("source_file")?,
+            config_name: row.try_get("config_name")?,
+            extracted_at: row.try_get("extracted_at")?,
+            summary: row.try_get("summary")?,
+            schemas: row.try_get("schemas")?,
+            relationships: row.try_get("relationships")?,
+            status: row.try_get("status")?,
+        })
+    }
+}
+
+/// Map JSON `null` to SQL NULL.
+fn non_null(value: Value) -> Option<Value> {
+    if value.is_null() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Decode an optional JSONB column into a typed value, ignoring mismatches.
+fn from_json<T: serde::de::DeserializeOwned>(value: Option<Value>) -> Option<T> {
+    value.and_then(|v| serde_json::from_value(v).ok())
+}
+
+/// Append one entry to the review audit trail (no-op if already stored).
+async fn insert_review(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    extraction_id: &str,
+    review: &NodeReview,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO extraction.node_reviews (id, extraction_id, node_id, reviewer, reviewed_at, changes) \
+         VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(&review.id)
+    .bind(extraction_id)
+    .bind(&review.node_id)
+    .bind(&review.reviewer)
+    .bind(&review.reviewed_at)
+    .bind(serde_json::to_value(&review.changes)?)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Read a `node_content` row's text, decompressing per `content_encoding`.
+fn decode_content(row: &PgRow) -> Result<String> {
+    let encoding: Option<String> = row.try_get("content_encoding")?;
+    compression::decode_text(row.try_get("content")?, encoding.as_deref())
+}
+
+#[async_trait::async_trait]
+impl Storage for PostgresStorage {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn upload_extraction(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+    ) -> Result<()> {
+        info!("Uploading extraction {} to Postgres", extraction.id);
+
+        let mut tx = self.pool.begin().await?;
+
+        // 1. Upsert main record; children are replaced wholesale
+        sqlx::query(
+            "INSERT INTO extraction.extractions (id, config_name, source_file, content_hash, total_pages, \
+             summary, structure_map, metadata, reference_index, readable_id, extracted_at, extractor_version, \
+             fingerprint, duplicate_of, language, reviewed, config_version, prompt_override, source_url, \
+             node_count, diagnostics, ocr_provider, ocr_confidence, parent_extraction_id, \
+             parent_node_id) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, \
+             $24, $25) \
+             ON CONFLICT (id) DO UPDATE SET config_name = EXCLUDED.config_name, \
+             source_file = EXCLUDED.source_file, content_hash = EXCLUDED.content_hash, \
+             total_pages = EXCLUDED.total_pages, summary = EXCLUDED.summary, \
+             structure_map = EXCLUDED.structure_map, metadata = EXCLUDED.metadata, \
+             reference_index = EXCLUDED.reference_index, readable_id = EXCLUDED.readable_id, \
+             extracted_at = EXCLUDED.extracted_at, extractor_version = EXCLUDED.extractor_version, \
+             fingerprint = EXCLUDED.fingerprint, duplicate_of = EXCLUDED.duplicate_of, \
+             language = EXCLUDED.language, reviewed = EXCLUDED.reviewed, \
+             config_version = EXCLUDED.config_version, prompt_override = EXCLUDED.prompt_override, \
+             source_url = EXCLUDED.source_url, node_count = EXCLUDED.node_count, \
+             diagnostics = EXCLUDED.diagnostics, ocr_provider = EXCLUDED.ocr_provider, \
+             ocr_confidence = EXCLUDED.ocr_confidence, parent_extraction_id = EXCLUDED.parent_extraction_id, \
+             parent_node_id = EXCLUDED.parent_node_id",
+        )
+        .bind(&extraction.id)
+        .bind(&extraction.config_name)
+        .bind(&extraction.source_file)
+        .bind(&extraction.content_hash)
+        .bind(extraction.total_pages.map(|n| n as i32))
+        .bind(&extraction.summary)
+        .bind(serde_json::to_value(&extraction.structure_map)?)
+        .bind(non_null(extraction.metadata.clone()))
+        .bind(non_null(extraction.reference_index.clone()))
+        .bind(&extraction.readable_id)
+        .bind(&extraction.extracted_at)
+        .bind(&extraction.extractor_version)
+        .bind(&extraction.fingerprint)
+        .bind(&extraction.duplicate_of)
+        .bind(&extraction.language)
+        .bind(extraction.reviewed)
+        .bind(&extraction.config_version)
+        .bind(&extraction.prompt_override)
+        .bind(&extraction.source_url)
+        .bind(extraction.node_count() as i32)
+        .bind(non_null(serde_json::to_value(&extraction.diagnostics)?))
+        .bind(&extraction.ocr_provider)
+        .bind(extraction.ocr_confidence)
+        .bind(&extraction.parent_extraction_id)
+        .bind(&extraction.parent_node_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for table in [
+            "extraction.extraction_nodes",
+            "extraction.node_content",
+            "extraction.extraction_relationships",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE extraction_id = $1", table))
+                .bind(&extraction.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // 2. Insert nodes (flattened, depth-first) and content
+        let mut flat = Vec::new();
+        flatten_nodes(&extraction.children, None, &mut flat);
+
+        for (position, (parent_id, node)) in flat.iter().enumerate() {
+            let (page_start, page_end) = node
+                .page_range
+                .map(|arr| (Some(arr[0] as i32), Some(arr[1] as i32)))
+                .unwrap_or((None, None));
+            let (span_start, span_end) = span_columns(node);
+            let (pdf_start, pdf_end) = pdf_page_columns(node);
+
+            sqlx::query(
+                "INSERT INTO extraction.extraction_nodes (extraction_id, id, parent_id, position, type, \
+                 subtype, label, page_start, page_end, ocr_span_start, ocr_span_end, pdf_page_start, \
+                 pdf_page_end, date, author, summary, confidence, metadata, reviewed, child_extraction_id) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)",
+            )
+            .bind(&extraction.id)
+            .bind(&node.id)
+            .bind(parent_id)
+            .bind(position as i32)
+            .bind(&node.node_type)
+            .bind(&node.subtype)
+            .bind(&node.label)
+            .bind(page_start)
+            .bind(page_end)
+            .bind(span_start)
+            .bind(span_end)
+            .bind(pdf_start)
+            .bind(pdf_end)
+            .bind(&node.date)
+            .bind(&node.author)
+            .bind(&node.summary)
+            .bind(non_null(serde_json::to_value(&node.confidence)?))
+            .bind(non_null(node.metadata.clone()))
+            .bind(node.reviewed)
+            .bind(&node.child_extraction_id)
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(content_ref) = &node.content_ref {
+                if let Some(chunk) = content_store.get(content_ref, 0, usize::MAX) {
+                    let (stored, encoding) = self.compression.encode_text(&chunk.content);
+                    sqlx::query(
+                        "INSERT INTO extraction.node_content (extraction_id, node_id, content, content_encoding, char_count) \
+                         VALUES ($1, $2, $3, $4, $5)",
+                    )
+                    .bind(&extraction.id)
+                    .bind(&node.id)
+                    .bind(&stored)
+                    .bind(encoding)
+                    .bind(chunk.total_chars as i32)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        // 3. Insert relationships
+        for rel in &extraction.relationships {
+            sqlx::query(
+                "INSERT INTO extraction.extraction_relationships \
+                 (extraction_id, from_node, to_node, relationship_type, citation) \
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(&extraction.id)
+            .bind(&rel.from)
+            .bind(&rel.to)
+            .bind(&rel.rel_type)
+            .bind(&rel.citation)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // 4. Append reviews (the audit trail is never rewritten)
+        for review in &extraction.reviews {
+            insert_review(&mut tx, &extraction.id, review).await?;
+        }
+
+        tx.commit().await?;
+
+        info!(
+            "Successfully uploaded extraction {} to Postgres ({} nodes)",
+            extraction.id,
+            flat.len()
+        );
+        Ok(())
+    }
+
+    async fn list_extractions(&self, filter: &ExtractionFilter) -> Result<Vec<ExtractionRow>> {
+        let mut qb: QueryBuilder<Postgres> =
+            QueryBuilder::new("SELECT * FROM extraction.extractions WHERE TRUE");
+        if let Some(ref name) = filter.config_name {
+            qb.push(" AND config_name = ").push_bind(name);
+        }
+        if let Some(ref version) = filter.config_version {
+            qb.push(" AND config_version = ").push_bind(version);
+        }
+        if let Some(ref part) = filter.source_file {
+            qb.push(" AND source_file ILIKE ")
+                .push_bind(like_pattern(part))
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(ref since) = filter.since {
+            qb.push(" AND extracted_at >= ").push_bind(since);
+        }
+        if let Some(ref until) = filter.until {
+            qb.push(" AND extracted_at < ").push_bind(until);
+        }
+        if let Some(reviewed) = filter.reviewed {
+            qb.push(" AND reviewed = ").push_bind(reviewed);
+        }
+        qb.push(" ORDER BY extracted_at DESC");
+        if let Some(limit) = filter.limit {
+            qb.push(" LIMIT ").push_bind(limit as i64);
+        }
+        let rows = qb.build().fetch_all(&self.pool).await?;
+        rows.iter().map(Self::extraction_row).collect()
+    }
+
+    async fn fetch_extraction(&self, id: &str) -> Result<Option<Extraction>> {
+        // 1. Fetch main record
+        let row = match sqlx::query("SELECT * FROM extraction.extractions WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            Some(r) => Self::extraction_row(&r)?,
+            None => return Ok(None),
+        };
+
+        // 2. Fetch all nodes in insertion order
+        let nodes: Vec<NodeRow> = sqlx::query(
+            "SELECT * FROM extraction.extraction_nodes WHERE extraction_id = $1 ORDER BY position",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<NodeRow> {
+            Ok(NodeRow {
+                id: r.try_get("id")?,
+                parent_id: r.try_get("parent_id")?,
+                node_type: r.try_get("type")?,
+                subtype: r.try_get("subtype")?,
+                label: r.try_get("label")?,
+                page_start: r.try_get::<Option<i32>, _>("page_start")?.map(|n| n as u32),
+                page_end: r.try_get::<Option<i32>, _>("page_end")?.map(|n| n as u32),
+                ocr_span_start: r
+                    .try_get::<Option<i64>, _>("ocr_span_start")?
+                    .map(|n| n as usize),
+                ocr_span_end: r
+                    .try_get::<Option<i64>, _>("ocr_span_end")?
+                    .map(|n| n as usize),
+                pdf_page_start: r
+                    .try_get::<Option<i64>, _>("pdf_page_start")?
+                    .map(|n| n as u32),
+                pdf_page_end: r
+                    .try_get::<Option<i64>, _>("pdf_page_end")?
+                    .map(|n| n as u32),
+                date: r.try_get("date")?,
+                author: r.try_get("author")?,
+                summary: r.try_get("summary")?,
+                confidence: from_json(r.try_get("confidence")?),
+                metadata: r.try_get("metadata")?,
+                reviewed: r.try_get("reviewed")?,
+                child_extraction_id: r.try_get("child_extraction_id")?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+        // 3. Note which nodes have content; the text is loaded on demand
+        let with_content: HashSet<String> = sqlx::query_scalar(
+            "SELECT node_id FROM extraction.node_content WHERE extraction_id = $1",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        // 4. Fetch relationships
+        let relationships: Vec<Relationship> = sqlx::query(
+            "SELECT from_node, to_node, relationship_type, citation \
+             FROM extraction.extraction_relationships WHERE extraction_id = $1 ORDER BY id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<Relationship> {
+            Ok(Relationship {
+                from: r.try_get("from_node")?,
+                to: r.try_get("to_node")?,
+                rel_type: r.try_get("relationship_type")?,
+                citation: r.try_get("citation")?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+        // 5. Reconstruct tree from flat nodes
+        let children = build_tree(&nodes, &with_content);
+        let mut extraction = row.into_extraction(relationships, children);
+
+        // 6. Fetch the review audit trail
+        extraction.reviews = sqlx::query(
+            "SELECT * FROM extraction.node_reviews WHERE extraction_id = $1 ORDER BY reviewed_at, id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<NodeReview> {
+            Ok(NodeReview {
+                id: r.try_get("id")?,
+                node_id: r.try_get("node_id")?,
+                reviewer: r.try_get("reviewer")?,
+                reviewed_at: r.try_get("reviewed_at")?,
+                changes: from_json(r.try_get("changes")?).unwrap_or_default(),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+        info!(
+            "Hydrated extraction {} from Postgres ({} nodes)",
+            extraction.id,
+            nodes.len()
+        );
+
+        Ok(Some(extraction))
+    }
+
+    async fn fetch_extraction_content(
+        &self,
+        id: &str,
+        content_store: &ContentStore,
+    ) -> Result<usize> {
+        let rows = sqlx::query(
+            "SELECT node_id, content, content_encoding FROM extraction.node_content WHERE extraction_id = $1",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        for r in &rows {
+            let node_id: String = r.try_get("node_id")?;
+            content_store.store(&node_id, decode_content(r)?);
+        }
+        Ok(rows.len())
+    }
+
+    async fn record_review(
+        &self,
+        extraction_id: &str,
+        node: &DocumentNode,
+        review: &NodeReview,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let (page_start, page_end) = node
+            .page_range
+            .map(|arr| (Some(arr[0] as i32), Some(arr[1] as i32)))
+            .unwrap_or((None, None));
+        let (span_start, span_end) = span_columns(node);
+        let (pdf_start, pdf_end) = pdf_page_columns(node);
+        let updated = sqlx::query(
+            "UPDATE extraction.extraction_nodes SET type = $3, subtype = $4, label = $5, \
+             page_start = $6, page_end = $7, ocr_span_start = $8, ocr_span_end = $9, \
+             pdf_page_start = $10, pdf_page_end = $11, date = $12, summary = $13, reviewed = $14 \
+             WHERE extraction_id = $1 AND id = $2",
+        )
+        .bind(extraction_id)
+        .bind(&node.id)
+        .bind(&node.node_type)
+        .bind(&node.subtype)
+        .bind(&node.label)
+        .bind(page_start)
+        .bind(page_end)
+        .bind(span_start)
+        .bind(span_end)
+        .bind(pdf_start)
+        .bind(pdf_end)
+        .bind(&node.date)
+        .bind(&node.summary)
+        .bind(node.reviewed)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("UPDATE extraction.extractions SET reviewed = TRUE WHERE id = $1")
+            .bind(extraction_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_review(&mut tx, extraction_id, review).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn replace_tree(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        _removed: &[String],
+    ) -> Result<bool> {
+        let stored = sqlx::query("SELECT 1 FROM extraction.extractions WHERE id = $1")
+            .bind(&extraction.id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if stored.is_none() {
+            return Ok(false);
+        }
+        // Nodes are replaced wholesale, so removed nodes go with the old tree
+        self.upload_extraction(extraction, content_store).await?;
+        Ok(true)
+    }
+
+    async fn fetch_content_by_node_id(&self, node_id: &str) -> Result<Option<String>> {
+        let row =
+            sqlx::query("SELECT content, content_encoding FROM extraction.node_content WHERE node_id = $1 LIMIT 1")
+                .bind(node_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(match row {
+            Some(r) => Some(decode_content(&r)?),
+            None => None,
+        })
+    }
+
+    async fn upload_dataset(&self, dataset: &SheetExtraction) -> Result<()> {
+        info!("Uploading dataset {} to Postgres", dataset.id);
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO extraction.datasets (id, source_file, config_name, extracted_at, summary, \
+             schemas, relationships, status) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, 'completed') \
+             ON CONFLICT (id) DO UPDATE SET source_file = EXCLUDED.source_file, \
+             config_name = EXCLUDED.config_name, extracted_at = EXCLUDED.extracted_at, \
+             summary = EXCLUDED.summary, schemas = EXCLUDED.schemas, \
+             relationships = EXCLUDED.relationships, status = EXCLUDED.status",
+        )
+        .bind(&dataset.id)
+        .bind(&dataset.source_file)
+        .bind(&dataset.config_name)
+        .bind(&dataset.extracted_at)
+        .bind(&dataset.summary)
+        .bind(Value::Array(dataset_schemas_json(dataset)))
+        .bind(serde_json::to_value(&dataset.relationships)?)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM extraction.dataset_rows WHERE dataset_id = $1")
+            .bind(&dataset.id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Batch insert rows (ROW_BATCH_SIZE per statement)
+        let mut total_inserted = 0usize;
+        for schema in &dataset.schemas {
+            let indexed: Vec<(usize, &Value)> = schema.rows.iter().enumerate().collect();
+            for batch in indexed.chunks(ROW_BATCH_SIZE) {
+                let mut qb: QueryBuilder<Postgres> = QueryBuilder::new(
+                    "INSERT INTO extraction.dataset_rows (dataset_id, schema_name, row_index, row_data) ",
+                );
+                qb.push_values(batch, |mut b, (row_idx, row_data)| {
+                    b.push_bind(&dataset.id)
+                        .push_bind(&schema.name)
+                        .push_bind(*row_idx as i32)
+                        .push_bind(*row_data);
+                });
+                qb.build().execute(&mut *tx).await?;
+                total_inserted += batch.len();
+            }
+            debug!(
+                "Inserted {} rows for dataset {} schema '{}'",
+                schema.rows.len(),
+                dataset.id,
+                schema.name
+            );
+        }
+
+        tx.commit().await?;
+
+        info!(
+            "Successfully uploaded dataset {} to Postgres ({} rows)",
+            dataset.id, total_inserted
+        );
+        Ok(())
+    }
+
+    async fn list_datasets(&self) -> Result<Vec<DatasetRow>> {
+        let rows = sqlx::query("SELECT * FROM extraction.datasets ORDER BY extracted_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::dataset_row).collect()
+    }
+
+    async fn fetch_dataset(&self, id: &str) -> Result<Option<SheetExtraction>> {
+        let row = match sqlx::query("SELECT * FROM extraction.datasets WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            Some(r) => Self::dataset_row(&r)?,
+            None => return Ok(None),
+        };
+
+        let data_rows: Vec<(String, Value)> = sqlx::query(
+            "SELECT schema_name, row_data FROM extraction.dataset_rows \
+             WHERE dataset_id = $1 ORDER BY row_index",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<(String, Value)> {
+            Ok((r.try_get("schema_name")?, r.try_get("row_data")?))
+        })
+        .collect::<Result<_>>()?;
+
+        let total_rows = data_rows.len();
+        let dataset = assemble_dataset(row, data_rows);
+
+        info!(
+            "Hydrated dataset {} from Postgres ({} rows)",
+            dataset.id, total_rows
+        );
+
+        Ok(Some(dataset))
+    }
+
+    async fn query_dataset_rows(
+        &self,
+        dataset_id: &str,
+        schema_name: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Value>> {
+        sqlx::query(
+            "SELECT row_data FROM extraction.dataset_rows WHERE dataset_id = $1 AND schema_name = $2 \
+             ORDER BY row_index LIMIT $3 OFFSET $4",
+        )
+        .bind(dataset_id)
+        .bind(schema_name)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| Ok(r.try_get("row_data")?))
+        .collect()
+    }
+
+    async fn list_configs(&self) -> Result<Vec<ExtractionConfig>> {
+        sqlx::query("SELECT config FROM extraction.configs ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|r| Ok(serde_json::from_value(r.try_get("config")?)?))
+            .collect()
+    }
+
+    async fn upsert_config(&self, config: &ExtractionConfig) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO extraction.configs (name, config) VALUES ($1, $2) \
+             ON CONFLICT (name) DO UPDATE SET config = EXCLUDED.config, updated_at = NOW()",
+        )
+        .bind(&config.name)
+        .bind(serde_json::to_value(config)?)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Upserted config: {}", config.name);
+        Ok(())
+    }
+
+    async fn delete_extraction(&self, id: &str) -> Result<()> {
+        // Nodes, content, relationships, and reviews cascade
+        sqlx::query("DELETE FROM extraction.extractions WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted extraction: {}", id);
+        Ok(())
+    }
+
+    async fn delete_dataset(&self, id: &str) -> Result<()> {
+        // Rows cascade
+        sqlx::query("DELETE FROM extraction.datasets WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted dataset: {}", id);
+        Ok(())
+    }
+
+    async fn delete_config(&self, name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM extraction.configs WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted config: {}", name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::DocumentNode;
+
+    /// Runs only when `TEST_DATABASE_URL` points at a disposable database.
+    async fn test_storage() -> Option<PostgresStorage> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        Some(PostgresStorage::connect(&url, 2).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let Some(storage) = test_storage().await else {
+            return;
+        };
+        let content_store = ContentStore::new();
+
+        let mut ext = Extraction::new("doc.pdf".into(), None);
+        let mut node: DocumentNode = serde_json::from_value(serde_json::json!({
+            "id": format!("{}_n", ext.id),
+            "type": "document",
+            "summary": "s",
+            "page_range": [1, 3],
+        }))
+        .unwrap();
+        node.content_ref = Some(content_store.store(&node.id, "text".into()));
+        ext.children = vec![node.clone()];
+
+        storage
+            .upload_extraction(&ext, &content_store)
+            .await
+            .unwrap();
+        storage
+            .upload_extraction(&ext, &content_store)
+            .await
+            .unwrap();
+
+        let loaded = storage.fetch_extraction(&ext.id).await.unwrap().unwrap();
+        assert_eq!(loaded.children.len(), 1);
+        assert_eq!(loaded.children[0].page_range, Some([1, 3]));
+        assert_eq!(
+            storage
+                .fetch_content_by_node_id(&node.id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("text")
+        );
+
+        node.reviewed = true;
+        node.summary = "corrected".into();
+        let review = NodeReview::new(node.id.clone(), "ana".into(), Vec::new());
+        assert!(storage.record_review(&ext.id, &node, &review).await.unwrap());
+        let loaded = storage.fetch_extraction(&ext.id).await.unwrap().unwrap();
+        assert!(loaded.reviewed && loaded.children[0].reviewed);
+        assert_eq!(loaded.children[0].summary, "corrected");
+        assert_eq!(loaded.reviews[0].reviewer, "ana");
+
+        let dataset = SheetExtraction {
+            id: format!("ds_{}", ext.id),
+            status: crate::schema::ExtractionStatus::Completed,
+            error: None,
+            config_name: None,
+            source_file: "a.csv".into(),
+            extracted_at: "2025-01-01T00:00:00Z".into(),
+            summary: String::new(),
+            schemas: vec![crate::sheet_schema::DataSchema {
+                name: "t".into(),
+                description: String::new(),
+                columns: Vec::new(),
+                row_count: 250,
+                rows: (0..250).map(|i| serde_json::json!({ "n": i })).collect(),
+                sources: Vec::new(),
+                version: 1,
+                changes: Vec::new(),
+                stats: Vec::new(),
+                edited: false,
+                edits: Vec::new(),
+            }],
+            relationships: Vec::new(),
+            deliveries: Vec::new(),
+        };
+        storage.upload_dataset(&dataset).await.unwrap();
+        let loaded = storage.fetch_dataset(&dataset.id).await.unwrap().unwrap();
+        assert_eq!(loaded.schemas[0].row_count, 250);
+        let page = storage
+            .query_dataset_rows(&dataset.id, "t", 200, 1)
+            .await
+            .unwrap();
+        assert_eq!(page, vec![serde_json::json!({ "n": 200 })]);
+    }
+}
@@ -0,0 +1,1036 @@
+//! Embedded SQLite storage backend (`STORAGE_BACKEND=sqlite`).
+//!
+//! Mirrors the Supabase tables in a single local database file so the service
+//! can persist extractions, datasets, and configs without external services.
+//! JSON columns are stored as TEXT and decoded on read.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{QueryBuilder, Row, Sqlite};
+use tracing::{debug, info};
+
+use super::{
+    assemble_dataset, build_tree, dataset_schemas_json, flatten_nodes, like_pattern,
+    pdf_page_columns, span_columns, DatasetRow, ExtractionFilter, ExtractionRow, NodeRow, Storage,
+};
+use crate::compression::{self, Compression};
+use crate::config::ExtractionConfig;
+use crate::content_store::ContentStore;
+use crate::schema::{DocumentNode, Extraction, NodeReview, Relationship};
+use crate::sheet_schema::SheetExtraction;
+
+/// Default database path when `SQLITE_PATH` is not set.
+const DEFAULT_SQLITE_PATH: &str = "data/extractor.db";
+
+/// SQLite-backed storage.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+    compression: Compression,
+}
+
+impl SqliteStorage {
+    /// Open (or create) the database at `SQLITE_PATH` and run migrations.
+    pub async fn from_env() -> Result<Self> {
+        let path = std::env::var("SQLITE_PATH").unwrap_or_else(|_| DEFAULT_SQLITE_PATH.to_string());
+        Self::open(&path).await
+    }
+
+    /// Open (or create) the database at `path` and run migrations.
+    pub async fn open(path: &str) -> Result<Self> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory for {}", path))?;
+            }
+        }
+
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path))?
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to open SQLite database at {}", path))?;
+
+        sqlx::migrate!("./migrations/sqlite")
+            .run(&pool)
+            .await
+            .context("Failed to run SQLite migrations")?;
+
+        info!("SQLite storage ready at {}", path);
+        Ok(Self {
+            pool,
+            compression: Compression::from_env(),
+        })
+    }
+
+    fn extraction_row(row: &SqliteRow) -> Result<ExtractionRow> {
+        Ok(ExtractionRow {
+            id: row.try_get("id")?,
+            config_name: row.try_get("config_name")?,
+            config_version: row.try_get("config_version")?,
+            prompt_override: row.try_get("prompt_override")?,
+            source_file: row.try_get("source_file")?,
+            source_url: row.try_get("source_url")?,
+            content_hash: row.try_get("content_hash")?,
+            fingerprint: row.try_get("fingerprint")?,
+            duplicate_of: row.try_get("duplicate_of")?,
+            parent_extraction_id: row.try_get("parent_extraction_id")?,
+            parent_node_id: row.try_get("parent_node_id")?,
+            language: row.try_get("language")?,
+            reviewed: row.try_get("reviewed")?,
+            total_pages: row
+                .try_get::<Option<i64>, _>("total_pages")?
+                .map(|n| n as u32),
+            ocr_provider: row.try_get("ocr_provider")?,
+            ocr_confidence: row.try_get("ocr_confidence")?,
+            summary: row.try_get("summary")?,
+            structure_map: from_json_text(row.try_get("structure_map")?),
+            metadata: from_json_text(row.try_get("metadata")?),
+            reference_index: from_json_text(row.try_get("reference_index")?),
+            diagnostics: from_json_text(row.try_get("diagnostics")?),
+            readable_id: row.try_get("readable_id")?,
+            extracted_at: row.try_get("extracted_at")?,
+            extractor_version: row.try_get("extractor_version")?,
+            node_count: row.try_get::<i64, _>("node_count")? as u32,
+        })
+    }
+
+    fn dataset_row(row: &SqliteRow) -> Result<DatasetRow> {
+        let schemas: String = row.try_get("schemas")?;
+        Ok(DatasetRow {
+            id: row.try_get("id")?,
+            source_file: row.try_get("source_file")?,
+            config_name: row.try_get("config_name")?,
+            extracted_at: row.try_get("extracted_at")?,
+            summary: row.try_get("summary")?,
+            schemas: serde_json::from_str(&schemas)?,
+            relationships: from_json_text(row.try_get("relationships")?),
+            status: row.try_get("status")?,
+        })
+    }
+}
+
+/// Serialize a value to JSON text, mapping `null` to SQL NULL.
+fn to_json_text<T: Serialize>(value: &T) -> Result<Option<String>> {
+    let v = serde_json::to_value(value)?;
+    if v.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(v.to_string()))
+    }
+}
+
+/// Decode an optional JSON TEXT column, ignoring malformed values.
+fn from_json_text<T: DeserializeOwned>(text: Option<String>) -> Option<T> {
+    text.and_then(|t| serde_json::from_str(&t).ok())
+}
+
+/// Append one entry to the review audit trail (no-op if already stored).
+async fn insert_review(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    extraction_id: &str,
+    review: &NodeReview,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO node_reviews (id, extraction_id, node_id, reviewer, reviewed_at, changes) \
+         VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT(id) DO NOTHING",
+    )
+    .bind(&review.id)
+    .bind(extraction_id)
+    .bind(&review.node_id)
+    .bind(&review.reviewer)
+    .bind(&review.reviewed_at)
+    .bind(serde_json::to_string(&review.changes)?)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Read a `node_content` row's text, decompressing per `content_encoding`.
+fn decode_content(row: &SqliteRow) -> Result<String> {
+    let encoding: Option<String> = row.try_get("content_encoding")?;
+    compression::decode_text(row.try_get("content")?, encoding.as_deref())
+}
+
+#[async_trait::async_trait]
+impl Storage for SqliteStorage {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn upload_extraction(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+    ) -> Result<()> {
+        info!("Uploading extraction {} to SQLite", extraction.id);
+
+        let mut tx = self.pool.begin().await?;
+
+        // 1. Upsert main record; children are replaced wholesale
+        sqlx::query(
+            "INSERT INTO extractions (id, config_name, source_file, content_hash, total_pages, summary, \
+             structure_map, metadata, reference_index, readable_id, extracted_at, extractor_version, \
+             fingerprint, duplicate_of, language, reviewed, config_version, prompt_override, source_url, \
+             node_count, diagnostics, ocr_provider, ocr_confidence, parent_extraction_id, \
+             parent_node_id) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(id) DO UPDATE SET config_name = excluded.config_name, \
+             source_file = excluded.source_file, content_hash = excluded.content_hash, \
+             total_pages = excluded.total_pages, summary = excluded.summary, \
+             structure_map = excluded.structure_map, metadata = excluded.metadata, \
+             reference_index = excluded.reference_index, readable_id = excluded.readable_id, \
+             extracted_at = excluded.extracted_at, extractor_version = excluded.extractor_version, \
+             fingerprint = excluded.fingerprint, duplicate_of = excluded.duplicate_of, \
+             language = excluded.language, reviewed = excluded.reviewed, \
+             config_version = excluded.config_version, prompt_override = excluded.prompt_override, \
+             source_url = excluded.source_url, node_count = excluded.node_count, \
+             diagnostics = excluded.diagnostics, ocr_provider = excluded.ocr_provider, \
+             ocr_confidence = excluded.ocr_confidence, parent_extraction_id = excluded.parent_extraction_id, \
+             parent_node_id = excluded.parent_node_id",
+        )
+        .bind(&extraction.id)
+        .bind(&extraction.config_name)
+        .bind(&extraction.source_file)
+        .bind(&extraction.content_hash)
+        .bind(extraction.total_pages.map(i64::from))
+        .bind(&extraction.summary)
+        .bind(to_json_text(&extraction.structure_map)?)
+        .bind(to_json_text(&extraction.metadata)?)
+        .bind(to_json_text(&extraction.reference_index)?)
+        .bind(&extraction.readable_id)
+        .bind(&extraction.extracted_at)
+        .bind(&extraction.extractor_version)
+        .bind(&extraction.fingerprint)
+        .bind(&extraction.duplicate_of)
+        .bind(&extraction.language)
+        .bind(extraction.reviewed)
+        .bind(&extraction.config_version)
+        .bind(&extraction.prompt_override)
+        .bind(&extraction.source_url)
+        .bind(extraction.node_count() as i64)
+        .bind(to_json_text(&extraction.diagnostics)?)
+        .bind(&extraction.ocr_provider)
+        .bind(extraction.ocr_confidence)
+        .bind(&extraction.parent_extraction_id)
+        .bind(&extraction.parent_node_id)
+        .execute(&mut *tx)
+        .await?;
+
+        for table in [
+            "extraction_nodes",
+            "node_content",
+            "extraction_relationships",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE extraction_id = ?", table))
+                .bind(&extraction.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // 2. Insert nodes (flattened, depth-first) and content
+        let mut flat = Vec::new();
+        flatten_nodes(&extraction.children, None, &mut flat);
+
+        for (position, (parent_id, node)) in flat.iter().enumerate() {
+            let (page_start, page_end) = node
+                .page_range
+                .map(|arr| (Some(i64::from(arr[0])), Some(i64::from(arr[1]))))
+                .unwrap_or((None, None));
+            let (span_start, span_end) = span_columns(node);
+            let (pdf_start, pdf_end) = pdf_page_columns(node);
+
+            sqlx::query(
+                "INSERT INTO extraction_nodes (extraction_id, id, parent_id, position, type, subtype, \
+                 label, page_start, page_end, ocr_span_start, ocr_span_end, pdf_page_start, \
+                 pdf_page_end, date, author, summary, confidence, metadata, reviewed, child_extraction_id) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&extraction.id)
+            .bind(&node.id)
+            .bind(parent_id)
+            .bind(position as i64)
+            .bind(&node.node_type)
+            .bind(&node.subtype)
+            .bind(&node.label)
+            .bind(page_start)
+            .bind(page_end)
+            .bind(span_start)
+            .bind(span_end)
+            .bind(pdf_start)
+            .bind(pdf_end)
+            .bind(&node.date)
+            .bind(&node.author)
+            .bind(&node.summary)
+            .bind(to_json_text(&node.confidence)?)
+            .bind(to_json_text(&node.metadata)?)
+            .bind(node.reviewed)
+            .bind(&node.child_extraction_id)
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(content_ref) = &node.content_ref {
+                if let Some(chunk) = content_store.get(content_ref, 0, usize::MAX) {
+                    let (stored, encoding) = self.compression.encode_text(&chunk.content);
+                    sqlx::query(
+                        "INSERT INTO node_content (extraction_id, node_id, content, content_encoding, char_count) \
+                         VALUES (?, ?, ?, ?, ?)",
+                    )
+                    .bind(&extraction.id)
+                    .bind(&node.id)
+                    .bind(&stored)
+                    .bind(encoding)
+                    .bind(chunk.total_chars as i64)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+
+        // 3. Insert relationships
+        for rel in &extraction.relationships {
+            sqlx::query(
+                "INSERT INTO extraction_relationships (extraction_id, from_node, to_node, relationship_type, citation) \
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&extraction.id)
+            .bind(&rel.from)
+            .bind(&rel.to)
+            .bind(&rel.rel_type)
+            .bind(&rel.citation)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // 4. Append reviews (the audit trail is never rewritten)
+        for review in &extraction.reviews {
+            insert_review(&mut tx, &extraction.id, review).await?;
+        }
+
+        tx.commit().await?;
+
+        info!(
+            "Successfully uploaded extraction {} to SQLite ({} nodes)",
+            extraction.id,
+            flat.len()
+        );
+        Ok(())
+    }
+
+    async fn list_extractions(&self, filter: &ExtractionFilter) -> Result<Vec<ExtractionRow>> {
+        let mut qb: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT * FROM extractions WHERE TRUE");
+        if let Some(ref name) = filter.config_name {
+            qb.push(" AND config_name = ").push_bind(name);
+        }
+        if let Some(ref version) = filter.config_version {
+            qb.push(" AND config_version = ").push_bind(version);
+        }
+        if let Some(ref part) = filter.source_file {
+            qb.push(" AND source_file LIKE ")
+                .push_bind(like_pattern(part))
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(ref since) = filter.since {
+            qb.push(" AND extracted_at >= ").push_bind(since);
+        }
+        if let Some(ref until) = filter.until {
+            qb.push(" AND extracted_at < ").push_bind(until);
+        }
+        if let Some(reviewed) = filter.reviewed {
+            qb.push(" AND reviewed = ").push_bind(reviewed);
+        }
+        qb.push(" ORDER BY extracted_at DESC");
+        if let Some(limit) = filter.limit {
+            qb.push(" LIMIT ").push_bind(limit as i64);
+        }
+        let rows = qb.build().fetch_all(&self.pool).await?;
+        rows.iter().map(Self::extraction_row).collect()
+    }
+
+    async fn fetch_extraction(&self, id: &str) -> Result<Option<Extraction>> {
+        // 1. Fetch main record
+        let row = match sqlx::query("SELECT * FROM extractions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            Some(r) => Self::extraction_row(&r)?,
+            None => return Ok(None),
+        };
+
+        // 2. Fetch all nodes in insertion order
+        let nodes: Vec<NodeRow> =
+            sqlx::query("SELECT * FROM extraction_nodes WHERE extraction_id = ? ORDER BY position")
+                .bind(id)
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(|r| -> Result<NodeRow> {
+                    Ok(NodeRow {
+                        id: r.try_get("id")?,
+                        parent_id: r.try_get("parent_id")?,
+                        node_type: r.try_get("type")?,
+                        subtype: r.try_get("subtype")?,
+                        label: r.try_get("label")?,
+                        page_start: r.try_get::<Option<i64>, _>("page_start")?.map(|n| n as u32),
+                        page_end: r.try_get::<Option<i64>, _>("page_end")?.map(|n| n as u32),
+                        ocr_span_start: r
+                            .try_get::<Option<i64>, _>("ocr_span_start")?
+                            .map(|n| n as usize),
+                        ocr_span_end: r
+                            .try_get::<Option<i64>, _>("ocr_span_end")?
+                            .map(|n| n as usize),
+                        pdf_page_start: r
+                            .try_get::<Option<i64>, _>("pdf_page_start")?
+                            .map(|n| n as u32),
+                        pdf_page_end: r
+                            .try_get::<Option<i64>, _>("pdf_page_end")?
+                            .map(|n| n as u32),
+                        date: r.try_get("date")?,
+                        author: r.try_get("author")?,
+                        summary: r.try_get("summary")?,
+                        confidence: from_json_text(r.try_get("confidence")?),
+                        metadata: from_json_text(r.try_get("metadata")?),
+                        reviewed: r.try_get("reviewed")?,
+                        child_extraction_id: r.try_get("child_extraction_id")?,
+                    })
+                })
+                .collect::<Result<_>>()?;
+
+        // 3. Note which nodes have content; the text is loaded on demand
+        let with_content: HashSet<String> =
+            sqlx::query_scalar("SELECT node_id FROM node_content WHERE extraction_id = ?")
+                .bind(id)
+                .fetch_all(&self.pool)
+                .await?
+                .into_iter()
+                .collect();
+
+        // 4. Fetch relationships
+        let relationships: Vec<Relationship> = sqlx::query(
+            "SELECT from_node, to_node, relationship_type, citation FROM extraction_relationships \
+             WHERE extraction_id = ? ORDER BY rowid",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<Relationship> {
+            Ok(Relationship {
+                from: r.try_get("from_node")?,
+                to: r.try_get("to_node")?,
+                rel_type: r.try_get("relationship_type")?,
+                citation: r.try_get("citation")?,
+            })
+        })
+        .collect::<Result<_>>()?;
+
+        // 5. Reconstruct tree from flat nodes
+        let children = build_tree(&nodes, &with_content);
+        let mut extraction = row.into_extraction(relationships, children);
+
+        // 6. Fetch the review audit trail
+        extraction.reviews = sqlx::query(
+            "SELECT * FROM node_reviews WHERE extraction_id = ? ORDER BY reviewed_at, rowid",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<NodeReview> {
+            Ok(NodeReview {
+                id: r.try_get("id")?,
+                node_id: r.try_get("node_id")?,
+                reviewer: r.try_get("reviewer")?,
+                reviewed_at: r.try_get("reviewed_at")?,
+                changes: from_json_text(r.try_get("changes")?).unwrap_or_default(),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+        info!(
+            "Hydrated extraction {} from SQLite ({} nodes)",
+            extraction.id,
+            nodes.len()
+        );
+
+        Ok(Some(extraction))
+    }
+
+    async fn fetch_extraction_content(
+        &self,
+        id: &str,
+        content_store: &ContentStore,
+    ) -> Result<usize> {
+        let rows = sqlx::query(
+            "SELECT node_id, content, content_encoding FROM node_content WHERE extraction_id = ?",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        for r in &rows {
+            let node_id: String = r.try_get("node_id")?;
+            content_store.store(&node_id, decode_content(r)?);
+        }
+        Ok(rows.len())
+    }
+
+    async fn record_review(
+        &self,
+        extraction_id: &str,
+        node: &DocumentNode,
+        review: &NodeReview,
+    ) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let (page_start, page_end) = node
+            .page_range
+            .map(|arr| (Some(i64::from(arr[0])), Some(i64::from(arr[1]))))
+            .unwrap_or((None, None));
+        let (span_start, span_end) = span_columns(node);
+        let (pdf_start, pdf_end) = pdf_page_columns(node);
+        let updated = sqlx::query(
+            "UPDATE extraction_nodes SET type = ?, subtype = ?, label = ?, page_start = ?, \
+             page_end = ?, ocr_span_start = ?, ocr_span_end = ?, pdf_page_start = ?, \
+             pdf_page_end = ?, date = ?, summary = ?, reviewed = ? \
+             WHERE extraction_id = ? AND id = ?",
+        )
+        .bind(&node.node_type)
+        .bind(&node.subtype)
+        .bind(&node.label)
+        .bind(page_start)
+        .bind(page_end)
+        .bind(span_start)
+        .bind(span_end)
+        .bind(pdf_start)
+        .bind(pdf_end)
+        .bind(&node.date)
+        .bind(&node.summary)
+        .bind(node.reviewed)
+        .bind(extraction_id)
+        .bind(&node.id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("UPDATE extractions SET reviewed = 1 WHERE id = ?")
+            .bind(extraction_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_review(&mut tx, extraction_id, review).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn replace_tree(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        _removed: &[String],
+    ) -> Result<bool> {
+        let stored = sqlx::query("SELECT 1 FROM extractions WHERE id = ?")
+            .bind(&extraction.id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if stored.is_none() {
+            return Ok(false);
+        }
+        // Nodes are replaced wholesale, so removed nodes go with the old tree
+        self.upload_extraction(extraction, content_store).await?;
+        Ok(true)
+    }
+
+    async fn fetch_content_by_node_id(&self, node_id: &str) -> Result<Option<String>> {
+        let row = sqlx::query(
+            "SELECT content, content_encoding FROM node_content WHERE node_id = ? LIMIT 1",
+        )
+        .bind(node_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(match row {
+            Some(r) => Some(decode_content(&r)?),
+            None => None,
+        })
+    }
+
+    async fn upload_dataset(&self, dataset: &SheetExtraction) -> Result<()> {
+        info!("Uploading dataset {} to SQLite", dataset.id);
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO datasets (id, source_file, config_name, extracted_at, summary, schemas, relationships, status) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, 'completed') \
+             ON CONFLICT(id) DO UPDATE SET source_file = excluded.source_file, \
+             config_name = excluded.config_name, extracted_at = excluded.extracted_at, \
+             summary = excluded.summary, schemas = excluded.schemas, \
+             relationships = excluded.relationships, status = excluded.status",
+        )
+        .bind(&dataset.id)
+        .bind(&dataset.source_file)
+        .bind(&dataset.config_name)
+        .bind(&dataset.extracted_at)
+        .bind(&dataset.summary)
+        .bind(serde_json::to_string(&dataset_schemas_json(dataset))?)
+        .bind(serde_json::to_string(&dataset.relationships)?)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM dataset_rows WHERE dataset_id = ?")
+            .bind(&dataset.id)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut total_inserted = 0usize;
+        for schema in &dataset.schemas {
+            for (row_idx, row_data) in schema.rows.iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO dataset_rows (dataset_id, schema_name, row_index, row_data) \
+                     VALUES (?, ?, ?, ?)",
+                )
+                .bind(&dataset.id)
+                .bind(&schema.name)
+                .bind(row_idx as i64)
+                .bind(row_data.to_string())
+                .execute(&mut *tx)
+                .await?;
+                total_inserted += 1;
+            }
+            debug!(
+                "Inserted {} rows for dataset {} schema '{}'",
+                schema.rows.len(),
+                dataset.id,
+                schema.name
+            );
+        }
+
+        tx.commit().await?;
+
+        info!(
+            "Successfully uploaded dataset {} to SQLite ({} rows)",
+            dataset.id, total_inserted
+        );
+        Ok(())
+    }
+
+    async fn list_datasets(&self) -> Result<Vec<DatasetRow>> {
+        let rows = sqlx::query("SELECT * FROM datasets ORDER BY extracted_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::dataset_row).collect()
+    }
+
+    async fn fetch_dataset(&self, id: &str) -> Result<Option<SheetExtraction>> {
+        let row = match sqlx::query("SELECT * FROM datasets WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            Some(r) => Self::dataset_row(&r)?,
+            None => return Ok(None),
+        };
+
+        let data_rows: Vec<(String, serde_json::Value)> = sqlx::query(
+            "SELECT schema_name, row_data FROM dataset_rows WHERE dataset_id = ? ORDER BY row_index",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| -> Result<(String, serde_json::Value)> {
+            let data: String = r.try_get("row_data")?;
+            Ok((r.try_get("schema_name")?, serde_json::from_str(&data)?))
+        })
+        .collect::<Result<_>>()?;
+
+        let total_rows = data_rows.len();
+        let dataset = assemble_dataset(row, data_rows);
+
+        info!(
+            "Hydrated dataset {} from SQLite ({} rows)",
+            dataset.id, total_rows
+        );
+
+        Ok(Some(dataset))
+    }
+
+    async fn query_dataset_rows(
+        &self,
+        dataset_id: &str,
+        schema_name: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<serde_json::Value>> {
+        sqlx::query(
+            "SELECT row_data FROM dataset_rows WHERE dataset_id = ? AND schema_name = ? \
+             ORDER BY row_index LIMIT ? OFFSET ?",
+        )
+        .bind(dataset_id)
+        .bind(schema_name)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|r| {
+            let data: String = r.try_get("row_data")?;
+            Ok(serde_json::from_str(&data)?)
+        })
+        .collect()
+    }
+
+    async fn list_configs(&self) -> Result<Vec<ExtractionConfig>> {
+        sqlx::query("SELECT config FROM configs ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|r| {
+                let config: String = r.try_get("config")?;
+                Ok(serde_json::from_str(&config)?)
+            })
+            .collect()
+    }
+
+    async fn upsert_config(&self, config: &ExtractionConfig) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO configs (name, config) VALUES (?, ?) \
+             ON CONFLICT(name) DO UPDATE SET config = excluded.config, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(&config.name)
+        .bind(serde_json::to_string(config)?)
+        .execute(&self.pool)
+        .await?;
+
+        debug!("Upserted config: {}", config.name);
+        Ok(())
+    }
+
+    async fn delete_extraction(&self, id: &str) -> Result<()> {
+        // Nodes, content, relationships, and reviews cascade
+        sqlx::query("DELETE FROM extractions WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted extraction: {}", id);
+        Ok(())
+    }
+
+    async fn delete_dataset(&self, id: &str) -> Result<()> {
+        // Rows cascade
+        sqlx::query("DELETE FROM datasets WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted dataset: {}", id);
+        Ok(())
+    }
+
+    async fn delete_config(&self, name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM configs WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        debug!("Deleted config: {}", name);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{DocumentNode, DroppedRelationship, ExtractionDiagnostics};
+    use crate::sheet_schema::DataSchema;
+
+    fn node(id: &str, children: Vec<DocumentNode>) -> DocumentNode {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": "document",
+            "summary": format!("summary of {}", id),
+            "page_range": [1, 2],
+            "metadata": {"k": id},
+        }))
+        .map(|mut n: DocumentNode| {
+            n.children = children;
+            n
+        })
+        .unwrap()
+    }
+
+    async fn temp_storage() -> SqliteStorage {
+        let path = std::env::temp_dir().join(format!(
+            "extractor_test_{}.db",
+            uuid::Uuid::new_v4().simple()
+        ));
+        SqliteStorage::open(path.to_str().unwrap()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extraction_roundtrip() {
+        let storage = temp_storage().await;
+        let content_store = ContentStore::new();
+
+        let mut ext = Extraction::new("doc.pdf".into(), Some("legal_br".into()));
+        ext.config_version = Some("0123456789abcdef".into());
+        ext.prompt_override = Some("Return JSON only.".into());
+        ext.ocr_provider = Some("docling".into());
+        ext.ocr_confidence = Some(0.9);
+        ext.parent_extraction_id = Some("ext_parent".into());
+        ext.parent_node_id = Some("anexo_1".into());
+        let mut leaf = node("leaf", vec![]);
+        leaf.content_ref = Some(content_store.store("leaf", "decisão".into()));
+        leaf.ocr_span = Some([10, 42]);
+        leaf.pdf_page_range = Some([5, 6]);
+        leaf.child_extraction_id = Some("ext_child".into());
+        ext.children = vec![node("root", vec![node("a", vec![]), leaf])];
+        ext.relationships.push(Relationship {
+            from: "a".into(),
+            to: "leaf".into(),
+            rel_type: "cites".into(),
+            citation: None,
+        });
+        ext.diagnostics = Some(ExtractionDiagnostics {
+            dropped_relationships: vec![DroppedRelationship {
+                relationship: ext.relationships[0].clone(),
+                reason: "unknown node 'b'".into(),
+            }],
+            ..Default::default()
+        });
+
+        storage
+            .upload_extraction(&ext, &content_store)
+            .await
+            .unwrap();
+        // Re-upload replaces children instead of duplicating them
+        storage
+            .upload_extraction(&ext, &content_store)
+            .await
+            .unwrap();
+
+        let char_count: i64 =
+            sqlx::query_scalar("SELECT char_count FROM node_content WHERE node_id = 'leaf'")
+                .fetch_one(&storage.pool)
+                .await
+                .unwrap();
+        assert_eq!(char_count, 7);
+
+        let fresh = ContentStore::new();
+        let loaded = storage.fetch_extraction(&ext.id).await.unwrap().unwrap();
+        assert_eq!(loaded.children.len(), 1);
+        assert_eq!(loaded.config_version.as_deref(), Some("0123456789abcdef"));
+        assert_eq!(loaded.prompt_override.as_deref(), Some("Return JSON only."));
+        assert_eq!(loaded.ocr_provider.as_deref(), Some("docling"));
+        assert_eq!(loaded.ocr_confidence, Some(0.9));
+        assert_eq!(loaded.parent_extraction_id.as_deref(), Some("ext_parent"));
+        assert_eq!(loaded.parent_node_id.as_deref(), Some("anexo_1"));
+        assert_eq!(
+            loaded.diagnostics.unwrap().dropped_relationships[0].reason,
+            "unknown node 'b'"
+        );
+        // Back-links are rebuilt from the stored relationships
+        assert_eq!(loaded.children[0].children[1].referenced_by[0].node, "a");
+        let filter = |f: ExtractionFilter| {
+            let storage = &storage;
+            async move { storage.list_extractions(&f).await.unwrap().len() }
+        };
+        assert_eq!(
+            filter(ExtractionFilter {
+                config_name: Some("legal_br".into()),
+                source_file: Some("DOC".into()),
+                since: Some(ext.extracted_at[..10].to_string()),
+                limit: Some(5),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+        for miss in [
+            ExtractionFilter {
+                source_file: Some("d_c".into()),
+                ..Default::default()
+            },
+            ExtractionFilter {
+                until: Some("2000-01-01".into()),
+                ..Default::default()
+            },
+            ExtractionFilter {
+                reviewed: Some(true),
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(filter(miss).await, 0);
+        }
+        let root = &loaded.children[0];
+        let child_ids: Vec<&str> = root.children.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(child_ids, vec!["a", "leaf"]);
+        assert_eq!(root.page_range, Some([1, 2]));
+        assert_eq!(root.ocr_span, None);
+        assert_eq!(root.children[1].ocr_span, Some([10, 42]));
+        assert_eq!(root.children[1].pdf_page_range, Some([5, 6]));
+        assert_eq!(
+            root.children[1].child_extraction_id.as_deref(),
+            Some("ext_child")
+        );
+        assert_eq!(root.metadata["k"], "root");
+        assert_eq!(loaded.relationships.len(), 1);
+        // Content stays in storage until asked for
+        assert_eq!(
+            root.children[1].content_ref.as_deref(),
+            Some("content://leaf")
+        );
+        assert!(!fresh.exists("content://leaf"));
+        assert!(
+            storage
+                .fetch_extraction_content(&ext.id, &fresh)
+                .await
+                .unwrap()
+                >= 1
+        );
+        assert_eq!(fresh.get_full("content://leaf").as_deref(), Some("decisão"));
+        assert_eq!(
+            storage
+                .fetch_content_by_node_id("leaf")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("decisão")
+        );
+        let rows = storage
+            .list_extractions(&ExtractionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].node_count, 3);
+
+        // Reviewer corrections update the node and append to the audit trail
+        let mut corrected = loaded.children[0].children[0].clone();
+        corrected.label = Some("Anexo".into());
+        corrected.reviewed = true;
+        let review = NodeReview::new(
+            "a".into(),
+            "ana".into(),
+            vec![crate::schema::FieldChange {
+                field: "label".into(),
+                old: serde_json::Value::Null,
+                new: "Anexo".into(),
+            }],
+        );
+        assert!(storage
+            .record_review(&ext.id, &corrected, &review)
+            .await
+            .unwrap());
+        assert!(!storage
+            .record_review(&ext.id, &node("missing", vec![]), &review)
+            .await
+            .unwrap());
+        let reviewed = storage.fetch_extraction(&ext.id).await.unwrap().unwrap();
+        assert!(reviewed.reviewed);
+        assert!(reviewed.children[0].children[0].reviewed);
+        assert_eq!(
+            reviewed.children[0].children[0].label.as_deref(),
+            Some("Anexo")
+        );
+        assert_eq!(reviewed.reviews.len(), 1);
+        assert_eq!(reviewed.reviews[0].changes[0].new, "Anexo");
+        assert!(
+            storage
+                .list_extractions(&ExtractionFilter::default())
+                .await
+                .unwrap()[0]
+                .reviewed
+        );
+
+        // Structural edits re-save the tree; unknown extractions are left alone
+        let mut edited = reviewed.clone();
+        edited.children[0].children.remove(0);
+        assert!(storage
+            .replace_tree(&edited, &fresh, &["a".to_string()])
+            .await
+            .unwrap());
+        let loaded = storage.fetch_extraction(&ext.id).await.unwrap().unwrap();
+        assert_eq!(loaded.children[0].children.len(), 1);
+        assert!(!storage
+            .replace_tree(&Extraction::new("x.pdf".into(), None), &fresh, &[])
+            .await
+            .unwrap());
+
+        assert!(storage.fetch_extraction("missing").await.unwrap().is_none());
+
+        storage.delete_extraction(&ext.id).await.unwrap();
+        assert!(storage
+            .list_extractions(&ExtractionFilter::default())
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(storage
+            .fetch_content_by_node_id("leaf")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dataset_roundtrip() {
+        let storage = temp_storage().await;
+        let dataset = SheetExtraction {
+            id: "ds_1".into(),
+            status: crate::schema::ExtractionStatus::Completed,
+            error: None,
+            config_name: None,
+            source_file: "a.csv".into(),
+            extracted_at: "2025-01-01T00:00:00Z".into(),
+            summary: String::new(),
+            schemas: vec![DataSchema {
+                name: "t".into(),
+                description: String::new(),
+                columns: Vec::new(),
+                row_count: 3,
+                rows: (0..3).map(|i| serde_json::json!({ "n": i })).collect(),
+                sources: Vec::new(),
+                version: 1,
+                changes: Vec::new(),
+                stats: Vec::new(),
+                edited: false,
+                edits: Vec::new(),
+            }],
+            relationships: Vec::new(),
+            deliveries: Vec::new(),
+        };
+
+        storage.upload_dataset(&dataset).await.unwrap();
+
+        let loaded = storage.fetch_dataset("ds_1").await.unwrap().unwrap();
+        assert_eq!(loaded.schemas[0].row_count, 3);
+        let page = storage.query_dataset_rows("ds_1", "t", 1, 1).await.unwrap();
+        assert_eq!(page, vec![serde_json::json!({ "n": 1 })]);
+        assert_eq!(storage.list_datasets().await.unwrap().len(), 1);
+
+        storage.delete_dataset("ds_1").await.unwrap();
+        assert!(storage.list_datasets().await.unwrap().is_empty());
+        assert!(storage
+            .query_dataset_rows("ds_1", "t", 0, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
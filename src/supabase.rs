@@ -1,16 +1,41 @@
 //! Supabase client for uploading and reading extraction results.
 
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
 use anyhow::{anyhow, Result};
 use reqwest::Client;
-use serde::Deserialize;
-use serde_json::json;
-use tracing::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
 
+use crate::compression::{self, Compression};
 use crate::config::ExtractionConfig;
+use crate::content_store::ContentStore;
 use crate::schema::{
-    ConfidenceScores, DocumentNode, Extraction, ExtractionStatus, Relationship, StructureMapEntry,
+    now_iso8601, DocumentNode, Extraction, NodeReview, Relationship, StructureMapEntry,
+};
+use crate::sheet_schema::SheetExtraction;
+use crate::storage::{
+    assemble_dataset, build_tree, dataset_schemas_json, flatten_nodes, like_pattern, DatasetRow,
+    ExtractionFilter, ExtractionRow, NodeRow, Storage,
 };
-use crate::sheet_schema::{ColumnDef, DataSchema, SchemaRelationship, SheetExtraction};
+
+/// Maximum rows per PostgREST array insert.
+const UPLOAD_BATCH_ROWS: usize = 200;
+
+/// Soft cap on the serialized size of one array insert (content rows can be large).
+const UPLOAD_BATCH_BYTES: usize = 2 * 1024 * 1024;
+
+/// `upload_state.target_type` values.
+const UPLOAD_TARGET_EXTRACTION: &str = "extraction";
+const UPLOAD_TARGET_DATASET: &str = "dataset";
+
+/// `upload_state.status` values.
+const UPLOAD_IN_PROGRESS: &str = "in_progress";
+const UPLOAD_PARTIAL: &str = "partial";
+const UPLOAD_COMPLETED: &str = "completed";
 
 /// Supabase client configuration.
 #[derive(Clone)]
@@ -18,6 +43,7 @@ pub struct SupabaseClient {
     client: Client,
     base_url: String,
     service_role_key: String,
+    compression: Compression,
 }
 
 impl SupabaseClient {
@@ -32,26 +58,28 @@ impl SupabaseClient {
             client: Client::new(),
             base_url,
             service_role_key,
+            compression: Compression::from_env(),
         })
     }
 
     /// Upload an extraction to Supabase.
+    ///
+    /// Rows are upserted, never deleted: nodes a different tree under the
+    /// same ID no longer has stay behind as orphans. Structural edits go
+    /// through [`SupabaseClient::replace_tree`], which removes them.
     pub async fn upload_extraction(
         &self,
         extraction: &Extraction,
-        content_store: &crate::content_store::ContentStore,
+        content_store: &ContentStore,
     ) -> Result<()> {
         info!("Uploading extraction {} to Supabase", extraction.id);
 
         // 1. Insert main extraction record
         self.insert_extraction(extraction).await?;
 
-        // 2. Insert nodes (flattened) and content
-        self.insert_nodes(&extraction.id, &extraction.children, None, content_store)
-            .await?;
-
-        // 3. Insert relationships
-        self.insert_relationships(&extraction.id, &extraction.relationships)
+        // 2. Batch-insert nodes, content, and relationships
+        let batches = self.plan_extraction_batches(extraction, content_store);
+        self.run_batches(UPLOAD_TARGET_EXTRACTION, &extraction.id, &batches)
             .await?;
 
         info!(
@@ -61,7 +89,7 @@ impl SupabaseClient {
         Ok(())
     }
 
-    /// Insert the main extraction record.
+    /// Upsert the main extraction record.
     async fn insert_extraction(&self, extraction: &Extraction) -> Result<()> {
         let url = format!("{}/rest/v1/extractions", self.base_url);
 
@@ -74,16 +102,29 @@ impl SupabaseClient {
         let body = json!({
             "id": extraction.id,
             "config_name": extraction.config_name,
+            "config_version": extraction.config_version,
+            "prompt_override": extraction.prompt_override,
             "source_file": extraction.source_file,
+            "source_url": extraction.source_url,
             "content_hash": extraction.content_hash,
             "total_pages": extraction.total_pages,
+            "ocr_provider": extraction.ocr_provider,
+            "ocr_confidence": extraction.ocr_confidence,
+            "parent_extraction_id": extraction.parent_extraction_id,
+            "parent_node_id": extraction.parent_node_id,
             "summary": extraction.summary,
             "structure_map": extraction.structure_map,
             "metadata": extraction.metadata,
             "reference_index": reference_index,
+            "diagnostics": extraction.diagnostics,
             "readable_id": extraction.readable_id,
+            "fingerprint": extraction.fingerprint,
+            "duplicate_of": extraction.duplicate_of,
+            "language": extraction.language,
+            "reviewed": extraction.reviewed,
             "extracted_at": extraction.extracted_at,
             "extractor_version": extraction.extractor_version,
+            "node_count": extraction.node_count(),
         });
 
         debug!("Inserting extraction: {}", extraction.id);
@@ -95,7 +136,7 @@ impl SupabaseClient {
             .header("Authorization", format!("Bearer {}", self.service_role_key))
             .header("Content-Type", "application/json")
             .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
+            .header("Prefer", "resolution=merge-duplicates,return=minimal")
             .json(&body)
             .send()
             .await?;
@@ -113,199 +154,254 @@ impl SupabaseClient {
         Ok(())
     }
 
-    /// Recursively insert nodes and their content.
-    async fn insert_nodes(
+    /// Plan the node, content, and relationship batches for an extraction.
+    fn plan_extraction_batches(
         &self,
-        extraction_id: &str,
-        nodes: &[DocumentNode],
-        parent_id: Option<&str>,
-        content_store: &crate::content_store::ContentStore,
-    ) -> Result<()> {
-        for node in nodes {
-            // Insert node
-            self.insert_node(extraction_id, node, parent_id).await?;
-
-            // Insert content if available
-            if let Some(content_ref) = &node.content_ref {
-                if let Some(chunk) = content_store.get(content_ref, 0, usize::MAX) {
-                    self.insert_content(extraction_id, &node.id, &chunk.content)
-                        .await?;
-                }
-            }
+        extraction: &Extraction,
+        content_store: &ContentStore,
+    ) -> Vec<Batch> {
+        let extraction_id = extraction.id.as_str();
+        let mut flat = Vec::new();
+        flatten_nodes(&extraction.children, None, &mut flat);
 
-            // Recursively insert children
-            if !node.children.is_empty() {
-                Box::pin(self.insert_nodes(
-                    extraction_id,
-                    &node.children,
-                    Some(&node.id),
-                    content_store,
+        let node_rows: Vec<(String, serde_json::Value)> = flat
+            .iter()
+            .map(|(parent_id, node)| (node.id.clone(), node_json(extraction_id, node, *parent_id)))
+            .collect();
+
+        // Content rows are keyed by node ID so they can be skipped when the node failed.
+        let content_rows: Vec<(String, serde_json::Value)> = flat
+            .iter()
+            .filter_map(|(_, node)| {
+                let chunk = content_store.get(node.content_ref.as_deref()?, 0, usize::MAX)?;
+                let (stored, encoding) = self.compression.encode_text(&chunk.content);
+                Some((
+                    node.id.clone(),
+                    json!({
+                        "extraction_id": extraction_id,
+                        "node_id": node.id,
+                        "content": stored,
+                        "content_encoding": encoding,
+                        "char_count": chunk.total_chars,
+                    }),
                 ))
-                .await?;
-            }
-        }
+            })
+            .collect();
 
-        Ok(())
+        let relationship_rows: Vec<(String, serde_json::Value)> = extraction
+            .relationships
+            .iter()
+            .map(|r| {
+                (
+                    format!("{}->{}", r.from, r.to),
+                    json!({
+                        "extraction_id": extraction_id,
+                        "from_node": r.from,
+                        "to_node": r.to,
+                        "relationship_type": r.rel_type,
+                    }),
+                )
+            })
+            .collect();
+
+        // Reviews upsert on their ID, so re-uploads never duplicate the audit trail.
+        let review_rows: Vec<(String, serde_json::Value)> = extraction
+            .reviews
+            .iter()
+            .map(|r| {
+                (
+                    r.id.clone(),
+                    json!({
+                        "id": r.id,
+                        "extraction_id": extraction_id,
+                        "node_id": r.node_id,
+                        "reviewer": r.reviewer,
+                        "reviewed_at": r.reviewed_at,
+                        "changes": r.changes,
+                    }),
+                )
+            })
+            .collect();
+
+        let mut batches = Batch::chunked("extraction_nodes", None, node_rows);
+        batches.extend(Batch::chunked(
+            "node_content",
+            Some("extraction_nodes"),
+            content_rows,
+        ));
+        batches.extend(Batch::chunked(
+            "extraction_relationships",
+            None,
+            relationship_rows,
+        ));
+        batches.extend(Batch::chunked("node_reviews", None, review_rows));
+        batches
     }
 
-    /// Insert a single node.
-    async fn insert_node(
+    /// Send planned batches, resuming from the upload-state record when possible.
+    ///
+    /// A failed batch does not abort the upload: the remaining batches are
+    /// still sent (rows whose parent row failed are held back), and every
+    /// failure is reported. Progress is recorded after each batch, so a
+    /// retry with the same plan only sends what is still missing.
+    async fn run_batches(
         &self,
-        extraction_id: &str,
-        node: &DocumentNode,
-        parent_id: Option<&str>,
+        target_type: &'static str,
+        target_id: &str,
+        batches: &[Batch],
     ) -> Result<()> {
-        let url = format!("{}/rest/v1/extraction_nodes", self.base_url);
-
-        let (page_start, page_end) = node
-            .page_range
-            .map(|arr| (Some(arr[0]), Some(arr[1])))
-            .unwrap_or((None, None));
-
-        let metadata = if node.metadata.is_null() {
-            None
-        } else {
-            Some(&node.metadata)
+        let fingerprint = plan_fingerprint(target_id, batches);
+        let previous = match self.fetch_upload_state(target_type, target_id).await {
+            Ok(state) => state,
+            Err(e) => {
+                warn!(
+                    "Could not read upload state for {} {}: {}",
+                    target_type, target_id, e
+                );
+                None
+            }
         };
 
-        let body = json!({
-            "id": node.id,
-            "extraction_id": extraction_id,
-            "parent_id": parent_id,
-            "type": node.node_type,
-            "subtype": node.subtype,
-            "label": node.label,
-            "page_start": page_start,
-            "page_end": page_end,
-            "date": node.date,
-            "author": node.author,
-            "summary": node.summary,
-            "confidence": node.confidence,
-            "node_metadata": metadata,
-        });
+        let completed_batches = resumed_batches(previous, &fingerprint);
+        if !completed_batches.is_empty() {
+            info!(
+                "Resuming upload of {} {}: {}/{} batches already done",
+                target_type,
+                target_id,
+                completed_batches.len(),
+                batches.len()
+            );
+        }
+        let mut state = UploadState {
+            target_type: target_type.to_string(),
+            target_id: target_id.to_string(),
+            fingerprint,
+            total_batches: batches.len(),
+            completed_batches,
+            status: UPLOAD_IN_PROGRESS.to_string(),
+            last_error: None,
+            updated_at: now_iso8601(),
+        };
+        self.save_upload_state(&mut state).await;
 
-        let resp = self
-            .client
-            .post(&url)
-            .header("apikey", &self.service_role_key)
-            .header("Authorization", format!("Bearer {}", self.service_role_key))
-            .header("Content-Type", "application/json")
-            .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
-            .json(&body)
-            .send()
-            .await?;
+        let mut done: HashSet<usize> = state.completed_batches.iter().copied().collect();
+        let mut failed_keys: HashMap<&'static str, HashSet<String>> = HashMap::new();
+        let mut failures: Vec<BatchFailure> = Vec::new();
 
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "Failed to insert node {}: {} - {}",
-                node.id,
-                status,
-                text
-            ));
-        }
+        for (index, batch) in batches.iter().enumerate() {
+            if done.contains(&index) {
+                continue;
+            }
 
-        debug!("Inserted node: {}", node.id);
-        Ok(())
-    }
+            let rows = rows_to_send(batch, &failed_keys);
+            if rows.is_empty() {
+                continue;
+            }
 
-    /// Insert node content.
-    async fn insert_content(
-        &self,
-        extraction_id: &str,
-        node_id: &str,
-        content: &str,
-    ) -> Result<()> {
-        let url = format!("{}/rest/v1/node_content", self.base_url);
+            match self.post_chunk(batch.table, &rows).await {
+                Ok(()) if rows.len() == batch.rows.len() => {
+                    done.insert(index);
+                    state.completed_batches.push(index);
+                    self.save_upload_state(&mut state).await;
+                }
+                Ok(()) => {}
+                Err(failure) => {
+                    failed_keys
+                        .entry(batch.table)
+                        .or_default()
+                        .extend(rows.iter().map(|(key, _)| key.clone()));
+                    failures.push(failure);
+                }
+            }
+        }
 
-        let body = json!({
-            "extraction_id": extraction_id,
-            "node_id": node_id,
-            "content": content,
-            "char_count": content.len(),
+        let failed_rows: usize = failures.iter().map(|f| f.rows).sum();
+        let summary = (!failures.is_empty()).then(|| {
+            format!(
+                "{} of {} batches failed ({} rows): {}",
+                failures.len(),
+                batches.len(),
+                failed_rows,
+                failures
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )
         });
 
-        let resp = self
-            .client
-            .post(&url)
-            .header("apikey", &self.service_role_key)
-            .header("Authorization", format!("Bearer {}", self.service_role_key))
-            .header("Content-Type", "application/json")
-            .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
-            .json(&body)
-            .send()
-            .await?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "Failed to insert content for {}: {} - {}",
-                node_id,
-                status,
-                text
-            ));
+        state.status = if done.len() == batches.len() {
+            UPLOAD_COMPLETED
+        } else {
+            UPLOAD_PARTIAL
+        }
+        .to_string();
+        state.last_error = summary.clone();
+        self.save_upload_state(&mut state).await;
+
+        match summary {
+            None => Ok(()),
+            Some(summary) => {
+                for failure in &failures {
+                    warn!("Upload of {} incomplete: {}", target_id, failure);
+                }
+                Err(anyhow!("Partial upload: {}", summary))
+            }
         }
-
-        debug!(
-            "Inserted content for node: {} ({} chars)",
-            node_id,
-            content.len()
-        );
-        Ok(())
     }
 
-    /// Insert relationships.
-    async fn insert_relationships(
+    /// POST one chunk of `(key, row)` pairs to `table`, describing the chunk on failure.
+    async fn post_chunk(
         &self,
-        extraction_id: &str,
-        relationships: &[Relationship],
-    ) -> Result<()> {
-        if relationships.is_empty() {
-            return Ok(());
-        }
-
-        let url = format!("{}/rest/v1/extraction_relationships", self.base_url);
+        table: &'static str,
+        chunk: &[&(String, serde_json::Value)],
+    ) -> std::result::Result<(), BatchFailure> {
+        let url = match conflict_target(table) {
+            Some(columns) => format!(
+                "{}/rest/v1/{}?on_conflict={}",
+                self.base_url, table, columns
+            ),
+            None => format!("{}/rest/v1/{}", self.base_url, table),
+        };
+        let rows: Vec<&serde_json::Value> = chunk.iter().map(|(_, row)| row).collect();
 
-        let bodies: Vec<_> = relationships
-            .iter()
-            .map(|r| {
-                json!({
-                    "extraction_id": extraction_id,
-                    "from_node": r.from,
-                    "to_node": r.to,
-                    "relationship_type": r.rel_type,
-                })
+        self.post_batch(&url, &rows)
+            .await
+            .map_err(|e| BatchFailure {
+                table,
+                first: chunk.first().map(|(k, _)| k.clone()).unwrap_or_default(),
+                last: chunk.last().map(|(k, _)| k.clone()).unwrap_or_default(),
+                rows: chunk.len(),
+                error: e.to_string(),
             })
-            .collect();
+    }
 
-        let resp = self
-            .client
-            .post(&url)
-            .header("apikey", &self.service_role_key)
-            .header("Authorization", format!("Bearer {}", self.service_role_key))
-            .header("Content-Type", "application/json")
-            .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
-            .json(&bodies)
-            .send()
+    /// Fetch the upload-state record for a target, if any.
+    async fn fetch_upload_state(
+        &self,
+        target_type: &str,
+        target_id: &str,
+    ) -> Result<Option<UploadState>> {
+        let rows: Vec<UploadState> = self
+            .get_json(&format!(
+                "upload_state?target_type=eq.{}&target_id=eq.{}&select=*",
+                target_type, target_id
+            ))
             .await?;
+        Ok(rows.into_iter().next())
+    }
 
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let text = resp.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "Failed to insert relationships: {} - {}",
-                status,
-                text
-            ));
+    /// Upsert the upload-state record. Failures are logged, not fatal: the
+    /// data inserts are idempotent, so losing progress only costs a re-send.
+    async fn save_upload_state(&self, state: &mut UploadState) {
+        state.updated_at = now_iso8601();
+        let url = format!("{}/rest/v1/upload_state", self.base_url);
+        if let Err(e) = self.post_batch(&url, std::slice::from_ref(state)).await {
+            warn!(
+                "Could not save upload state for {} {}: {}",
+                state.target_type, state.target_id, e
+            );
         }
-
-        debug!("Inserted {} relationships", relationships.len());
-        Ok(())
     }
 
     // ========================================================================
@@ -333,24 +429,40 @@ impl SupabaseClient {
         Ok(resp.json().await?)
     }
 
-    /// List all extractions (lightweight summaries).
-    pub async fn list_extractions(&self) -> Result<Vec<ExtractionRow>> {
-        self.get_json("extractions?select=id,config_name,source_file,content_hash,total_pages,summary,structure_map,metadata,readable_id,extracted_at,extractor_version&order=extracted_at.desc")
-            .await
+    /// GET rows, bringing each one written by an older version up to the
+    /// current shape with `upgrade` before decoding it.
+    async fn get_rows<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        upgrade: fn(&mut Value),
+    ) -> Result<Vec<T>> {
+        let rows: Vec<Value> = self.get_json(path).await?;
+        rows.into_iter()
+            .enumerate()
+            .map(|(i, mut row)| {
+                upgrade(&mut row);
+                serde_json::from_value(row)
+                    .map_err(|e| anyhow!("Unreadable row {} from {}: {}", i, path, e))
+            })
+            .collect()
     }
 
-    /// Fetch a full extraction by ID, reconstructing the tree from flat nodes.
-    pub async fn fetch_extraction(
-        &self,
-        id: &str,
-        content_store: &crate::content_store::ContentStore,
-    ) -> Result<Option<Extraction>> {
+    /// List extractions matching `filter` (lightweight summaries), newest first.
+    pub async fn list_extractions(&self, filter: &ExtractionFilter) -> Result<Vec<ExtractionRow>> {
+        let mut path = String::from("extractions?select=id,config_name,config_version,prompt_override,source_file,source_url,content_hash,total_pages,summary,structure_map,metadata,readable_id,fingerprint,duplicate_of,language,reviewed,extracted_at,extractor_version,node_count&order=extracted_at.desc");
+        path.push_str(&filter_params(filter));
+        self.get_rows(&path, upgrade_extraction_row).await
+    }
+
+    /// Fetch an extraction by ID, reconstructing the tree from flat nodes.
+    /// Node content is left in Supabase; only its presence is checked.
+    pub async fn fetch_extraction(&self, id: &str) -> Result<Option<Extraction>> {
         // 1. Fetch main record
         let rows: Vec<ExtractionRow> = self
-            .get_json(&format!(
-                "extractions?id=eq.{}&select=*",
-                id
-            ))
+            .get_rows(
+                &format!("extractions?id=eq.{}&select=*", id),
+                upgrade_extraction_row,
+            )
             .await?;
 
         let row = match rows.into_iter().next() {
@@ -360,36 +472,29 @@ impl SupabaseClient {
 
         // 2. Fetch all nodes
         let nodes: Vec<NodeRow> = self
-            .get_json(&format!(
-                "extraction_nodes?extraction_id=eq.{}&select=*",
-                id
-            ))
+            .get_rows(
+                &format!("extraction_nodes?extraction_id=eq.{}&select=*", id),
+                upgrade_node_row,
+            )
             .await?;
 
-        // 3. Fetch all content
-        let contents: Vec<ContentRow> = self
-            .get_json(&format!(
-                "node_content?extraction_id=eq.{}&select=node_id,content",
+        // 3. Note which nodes have content; the text is loaded on demand
+        let with_content: HashSet<String> = self
+            .get_json::<Vec<ContentRef>>(&format!(
+                "node_content?extraction_id=eq.{}&select=node_id",
                 id
             ))
-            .await?;
-
-        // Store content in content_store
-        let content_map: std::collections::HashMap<String, String> = contents
+            .await?
             .into_iter()
-            .map(|c| (c.node_id, c.content))
+            .map(|c| c.node_id)
             .collect();
 
-        for (node_id, content) in &content_map {
-            content_store.store(node_id, content.clone());
-        }
-
         // 4. Fetch relationships
         let rel_rows: Vec<RelationshipRow> = self
-            .get_json(&format!(
-                "extraction_relationships?extraction_id=eq.{}&select=*",
-                id
-            ))
+            .get_rows(
+                &format!("extraction_relationships?extraction_id=eq.{}&select=*", id),
+                upgrade_relationship_row,
+            )
             .await?;
 
         let relationships: Vec<Relationship> = rel_rows
@@ -403,28 +508,17 @@ impl SupabaseClient {
             .collect();
 
         // 5. Reconstruct tree from flat nodes
-        let children = build_tree(&nodes, &content_map);
-
-        let extraction = Extraction {
-            id: row.id,
-            version: 1,
-            status: crate::schema::ExtractionStatus::Completed,
-            error: None,
-            config_name: row.config_name,
-            previous_version_id: None,
-            content_hash: row.content_hash,
-            source_file: row.source_file,
-            extracted_at: row.extracted_at,
-            extractor_version: row.extractor_version,
-            total_pages: row.total_pages,
-            summary: row.summary,
-            structure_map: row.structure_map.unwrap_or_default(),
-            relationships,
-            metadata: row.metadata.unwrap_or(serde_json::Value::Null),
-            reference_index: row.reference_index.unwrap_or(serde_json::Value::Null),
-            readable_id: row.readable_id,
-            children,
-        };
+        let children = build_tree(&nodes, &with_content);
+
+        let mut extraction = row.into_extraction(relationships, children);
+
+        // 6. Fetch the review audit trail
+        extraction.reviews = self
+            .get_json(&format!(
+                "node_reviews?extraction_id=eq.{}&select=*&order=reviewed_at.asc",
+                id
+            ))
+            .await?;
 
         info!(
             "Hydrated extraction {} from Supabase ({} nodes)",
@@ -435,28 +529,197 @@ impl SupabaseClient {
         Ok(Some(extraction))
     }
 
-    /// Fetch content for a single node by node_id.
-    pub async fn fetch_content(&self, extraction_id: &str, node_id: &str) -> Result<Option<String>> {
-        let rows: Vec<ContentRow> = self
+    /// Load the content of every node of an extraction into `content_store`.
+    pub async fn fetch_extraction_content(
+        &self,
+        id: &str,
+        content_store: &ContentStore,
+    ) -> Result<usize> {
+        let contents: Vec<ContentRow> = self
             .get_json(&format!(
-                "node_content?extraction_id=eq.{}&node_id=eq.{}&select=content",
-                extraction_id, node_id
+                "node_content?extraction_id=eq.{}&select=node_id,content,content_encoding",
+                id
             ))
             .await?;
+        let count = contents.len();
+        for c in contents {
+            let node_id = c.node_id.clone();
+            content_store.store(&node_id, c.decode()?);
+        }
+        Ok(count)
+    }
+
+    /// Save a reviewer's correction to one node and append it to the audit trail.
+    /// Returns `false` when the node is not stored.
+    pub async fn record_review(
+        &self,
+        extraction_id: &str,
+        node: &DocumentNode,
+        review: &NodeReview,
+    ) -> Result<bool> {
+        let (page_start, page_end) = node
+            .page_range
+            .map(|arr| (Some(arr[0]), Some(arr[1])))
+            .unwrap_or((None, None));
+        let updated = self
+            .patch_json(
+                &format!(
+                    "extraction_nodes?extraction_id=eq.{}&id=eq.{}",
+                    extraction_id, node.id
+                ),
+                &json!({
+                    "type": node.node_type,
+                    "subtype": node.subtype,
+                    "label": node.label,
+                    "page_start": page_start,
+                    "page_end": page_end,
+                    "ocr_span_start": node.ocr_span.map(|s| s[0]),
+                    "ocr_span_end": node.ocr_span.map(|s| s[1]),
+                    "pdf_page_start": node.pdf_page_range.map(|r| r[0]),
+                    "pdf_page_end": node.pdf_page_range.map(|r| r[1]),
+                    "date": node.date,
+                    "summary": node.summary,
+                    "reviewed": node.reviewed,
+                }),
+            )
+            .await?;
+        if updated.is_empty() {
+            return Ok(false);
+        }
+
+        self.patch_json(
+            &format!("extractions?id=eq.{}", extraction_id),
+            &json!({ "reviewed": true }),
+        )
+        .await?;
+        let url = format!("{}/rest/v1/node_reviews", self.base_url);
+        self.post_batch(
+            &url,
+            &[json!({
+                "id": review.id,
+                "extraction_id": extraction_id,
+                "node_id": review.node_id,
+                "reviewer": review.reviewer,
+                "reviewed_at": review.reviewed_at,
+                "changes": review.changes,
+            })],
+        )
+        .await?;
+        Ok(true)
+    }
+
+    /// Re-upload a stored extraction after a structural edit, first deleting
+    /// the rows of `removed` nodes (upserts would leave them behind).
+    pub async fn replace_tree(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        removed: &[String],
+    ) -> Result<bool> {
+        let stored: Vec<serde_json::Value> = self
+            .get_json(&format!("extractions?id=eq.{}&select=id", extraction.id))
+            .await?;
+        if stored.is_empty() {
+            return Ok(false);
+        }
+
+        if !removed.is_empty() {
+            let ids = removed.join(",");
+            self.delete_rows(&format!(
+                "extraction_relationships?extraction_id=eq.{}&or=(from_node.in.({ids}),to_node.in.({ids}))",
+                extraction.id
+            ))
+            .await?;
+            // node_content rows cascade
+            self.delete_rows(&format!(
+                "extraction_nodes?extraction_id=eq.{}&id=in.({})",
+                extraction.id, ids
+            ))
+            .await?;
+        }
+        self.upload_extraction(extraction, content_store).await?;
+        Ok(true)
+    }
+
+    /// Delete an extraction; its nodes, content, relationships and reviews cascade.
+    pub async fn delete_extraction(&self, id: &str) -> Result<()> {
+        self.delete_rows(&format!(
+            "upload_state?target_type=eq.{}&target_id=eq.{}",
+            UPLOAD_TARGET_EXTRACTION, id
+        ))
+        .await?;
+        self.delete_rows(&format!("extractions?id=eq.{}", id)).await
+    }
+
+    /// Delete a dataset and its rows (`dataset_rows` does not cascade).
+    pub async fn delete_dataset(&self, id: &str) -> Result<()> {
+        self.delete_rows(&format!("dataset_rows?dataset_id=eq.{}", id))
+            .await?;
+        self.delete_rows(&format!(
+            "upload_state?target_type=eq.{}&target_id=eq.{}",
+            UPLOAD_TARGET_DATASET, id
+        ))
+        .await?;
+        self.delete_rows(&format!("datasets?id=eq.{}", id)).await
+    }
+
+    async fn delete_rows(&self, path: &str) -> Result<()> {
+        let url = format!("{}/rest/v1/{}", self.base_url, path);
+        let resp = self
+            .client
+            .delete(&url)
+            .header("apikey", &self.service_role_key)
+            .header("Authorization", format!("Bearer {}", self.service_role_key))
+            .header("Content-Profile", "extraction")
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("Supabase DELETE {} failed: {} - {}", path, status, text));
+        }
+        Ok(())
+    }
+
+    /// PATCH rows matching `path`, returning the updated rows.
+    async fn patch_json(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<Vec<serde_json::Value>> {
+        let url = format!("{}/rest/v1/{}", self.base_url, path);
+        let resp = self
+            .client
+            .patch(&url)
+            .header("apikey", &self.service_role_key)
+            .header("Authorization", format!("Bearer {}", self.service_role_key))
+            .header("Content-Type", "application/json")
+            .header("Content-Profile", "extraction")
+            .header("Prefer", "return=representation")
+            .json(body)
+            .send()
+            .await?;
 
-        Ok(rows.into_iter().next().map(|r| r.content))
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(anyhow!("Supabase PATCH {} failed: {} - {}", path, status, text));
+        }
+
+        Ok(resp.json().await?)
     }
 
     /// Fetch content by node_id only (no extraction_id needed).
     pub async fn fetch_content_by_node_id(&self, node_id: &str) -> Result<Option<String>> {
         let rows: Vec<ContentRow> = self
             .get_json(&format!(
-                "node_content?node_id=eq.{}&select=node_id,content&limit=1",
+                "node_content?node_id=eq.{}&select=node_id,content,content_encoding&limit=1",
                 node_id
             ))
             .await?;
 
-        Ok(rows.into_iter().next().map(|r| r.content))
+        rows.into_iter().next().map(ContentRow::decode).transpose()
     }
 
     // ========================================================================
@@ -468,18 +731,7 @@ impl SupabaseClient {
         info!("Uploading dataset {} to Supabase", dataset.id);
 
         // 1. Build schemas JSONB (column defs only, no rows)
-        let schemas_json: Vec<serde_json::Value> = dataset
-            .schemas
-            .iter()
-            .map(|s| {
-                json!({
-                    "name": s.name,
-                    "description": s.description,
-                    "columns": s.columns,
-                    "row_count": s.row_count,
-                })
-            })
-            .collect();
+        let schemas_json = dataset_schemas_json(dataset);
 
         let relationships_json: serde_json::Value = serde_json::to_value(&dataset.relationships)?;
 
@@ -503,7 +755,7 @@ impl SupabaseClient {
             .header("Authorization", format!("Bearer {}", self.service_role_key))
             .header("Content-Type", "application/json")
             .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
+            .header("Prefer", "resolution=merge-duplicates,return=minimal")
             .json(&body)
             .send()
             .await?;
@@ -518,49 +770,52 @@ impl SupabaseClient {
             ));
         }
 
-        // 3. Batch insert rows into dataset_rows (100 per batch)
-        let rows_url = format!("{}/rest/v1/dataset_rows", self.base_url);
-        let mut total_inserted = 0usize;
-
+        // 3. Batch insert rows into dataset_rows. IDs are derived from the
+        // position so a retried upload overwrites instead of duplicating.
+        let mut batches = Vec::new();
+        let mut total_rows = 0usize;
+        for (schema_idx, schema) in dataset.schemas.iter().enumerate() {
+            let rows: Vec<(String, serde_json::Value)> = schema
+                .rows
+                .iter()
+                .enumerate()
+                .map(|(row_idx, row_data)| {
+                    let id = format!("dsr_{}_{}_{}", dataset.id, schema_idx, row_idx);
+                    let row = json!({
+                        "id": id,
+                        "dataset_id": dataset.id,
+                        "schema_name": schema.name,
+                        "row_data": row_data,
+                        "row_index": row_idx,
+                    });
+                    (id, row)
+                })
+                .collect();
+            total_rows += rows.len();
+            batches.extend(Batch::chunked("dataset_rows", None, rows));
+        }
+        self.run_batches(UPLOAD_TARGET_DATASET, &dataset.id, &batches)
+            .await?;
+        // Rows past the end are left over from before rows were deleted
         for schema in &dataset.schemas {
-            let mut batch: Vec<serde_json::Value> = Vec::with_capacity(100);
-
-            for (row_idx, row_data) in schema.rows.iter().enumerate() {
-                batch.push(json!({
-                    "id": format!("dsr_{}", uuid::Uuid::new_v4().simple()),
-                    "dataset_id": dataset.id,
-                    "schema_name": schema.name,
-                    "row_data": row_data,
-                    "row_index": row_idx,
-                }));
-
-                if batch.len() >= 100 {
-                    self.post_batch(&rows_url, &batch).await?;
-                    total_inserted += batch.len();
-                    info!(
-                        "Inserted {} rows for dataset {} schema '{}'",
-                        total_inserted, dataset.id, schema.name
-                    );
-                    batch.clear();
-                }
-            }
-
-            // Flush remaining
-            if !batch.is_empty() {
-                self.post_batch(&rows_url, &batch).await?;
-                total_inserted += batch.len();
-            }
+            self.delete_rows(&format!(
+                "dataset_rows?dataset_id=eq.{}&schema_name=eq.{}&row_index=gte.{}",
+                dataset.id,
+                encode(&schema.name),
+                schema.rows.len()
+            ))
+            .await?;
         }
 
         info!(
             "Successfully uploaded dataset {} to Supabase ({} rows)",
-            dataset.id, total_inserted
+            dataset.id, total_rows
         );
         Ok(())
     }
 
     /// POST a batch of JSON objects.
-    async fn post_batch(&self, url: &str, batch: &[serde_json::Value]) -> Result<()> {
+    async fn post_batch<T: serde::Serialize>(&self, url: &str, batch: &[T]) -> Result<()> {
         let resp = self
             .client
             .post(url)
@@ -568,7 +823,7 @@ impl SupabaseClient {
             .header("Authorization", format!("Bearer {}", self.service_role_key))
             .header("Content-Type", "application/json")
             .header("Content-Profile", "extraction")
-            .header("Prefer", "return=minimal")
+            .header("Prefer", "resolution=merge-duplicates,return=minimal")
             .json(batch)
             .send()
             .await?;
@@ -608,56 +863,18 @@ impl SupabaseClient {
             .await?;
 
         // 3. Reconstruct schemas by merging column defs from JSONB + rows
-        let schema_defs: Vec<DatasetSchemaJson> =
-            serde_json::from_value(row.schemas.clone()).unwrap_or_default();
-
-        let mut schemas: Vec<DataSchema> = schema_defs
-            .into_iter()
-            .map(|s| {
-                let rows_for_schema: Vec<serde_json::Value> = data_rows
-                    .iter()
-                    .filter(|r| r.schema_name == s.name)
-                    .map(|r| r.row_data.clone())
-                    .collect();
-
-                let row_count = rows_for_schema.len();
-                DataSchema {
-                    name: s.name,
-                    description: s.description,
-                    columns: s.columns,
-                    row_count,
-                    rows: rows_for_schema,
-                }
-            })
-            .collect();
-
-        // Update row_count based on actual rows
-        for schema in &mut schemas {
-            schema.row_count = schema.rows.len();
-        }
-
-        let relationships: Vec<SchemaRelationship> = row
-            .relationships
-            .as_ref()
-            .and_then(|v| serde_json::from_value(v.clone()).ok())
-            .unwrap_or_default();
-
-        let dataset = SheetExtraction {
-            id: row.id,
-            status: ExtractionStatus::Completed,
-            error: None,
-            config_name: row.config_name,
-            source_file: row.source_file,
-            extracted_at: row.extracted_at,
-            summary: row.summary,
-            schemas,
-            relationships,
-        };
+        let total_rows = data_rows.len();
+        let dataset = assemble_dataset(
+            row,
+            data_rows
+                .into_iter()
+                .map(|r| (r.schema_name, r.row_data))
+                .collect(),
+        );
 
         info!(
             "Hydrated dataset {} from Supabase ({} rows)",
-            dataset.id,
-            data_rows.len()
+            dataset.id, total_rows
         );
 
         Ok(Some(dataset))
@@ -693,14 +910,6 @@ impl SupabaseClient {
         Ok(rows.into_iter().map(|r| r.config).collect())
     }
 
-    /// Get a single config by name.
-    pub async fn get_config(&self, name: &str) -> Result<Option<ExtractionConfig>> {
-        let rows: Vec<ConfigRow> = self
-            .get_json(&format!("configs?name=eq.{}&select=config", name))
-            .await?;
-        Ok(rows.into_iter().next().map(|r| r.config))
-    }
-
     /// Upsert a config (insert or update).
     pub async fn upsert_config(&self, config: &ExtractionConfig) -> Result<()> {
         let url = format!("{}/rest/v1/configs", self.base_url);
@@ -770,47 +979,253 @@ impl SupabaseClient {
 }
 
 // ============================================================================
-// Supabase row types
+// Batched upload helpers
 // ============================================================================
 
-#[derive(Debug, Deserialize)]
-pub struct ExtractionRow {
-    pub id: String,
-    pub config_name: Option<String>,
-    pub source_file: String,
-    pub content_hash: Option<String>,
-    pub total_pages: Option<u32>,
-    pub summary: String,
-    pub structure_map: Option<Vec<StructureMapEntry>>,
-    pub metadata: Option<serde_json::Value>,
-    pub reference_index: Option<serde_json::Value>,
-    pub readable_id: Option<String>,
-    pub extracted_at: String,
-    pub extractor_version: Option<String>,
+/// Build the `extraction_nodes` row for a node.
+fn node_json(
+    extraction_id: &str,
+    node: &DocumentNode,
+    parent_id: Option<&str>,
+) -> serde_json::Value {
+    let (page_start, page_end) = node
+        .page_range
+        .map(|arr| (Some(arr[0]), Some(arr[1])))
+        .unwrap_or((None, None));
+
+    let metadata = if node.metadata.is_null() {
+        None
+    } else {
+        Some(&node.metadata)
+    };
+
+    json!({
+        "id": node.id,
+        "extraction_id": extraction_id,
+        "parent_id": parent_id,
+        "type": node.node_type,
+        "subtype": node.subtype,
+        "label": node.label,
+        "page_start": page_start,
+        "page_end": page_end,
+        "ocr_span_start": node.ocr_span.map(|s| s[0]),
+        "ocr_span_end": node.ocr_span.map(|s| s[1]),
+        "pdf_page_start": node.pdf_page_range.map(|r| r[0]),
+        "pdf_page_end": node.pdf_page_range.map(|r| r[1]),
+        "date": node.date,
+        "author": node.author,
+        "summary": node.summary,
+        "confidence": node.confidence,
+        "node_metadata": metadata,
+        "reviewed": node.reviewed,
+        "child_extraction_id": node.child_extraction_id,
+    })
+}
+
+/// One planned PostgREST array insert.
+struct Batch {
+    table: &'static str,
+    /// Table whose rows share this batch's keys; rows are held back when
+    /// their key failed there earlier in the same run (e.g. content whose node failed).
+    parent: Option<&'static str>,
+    rows: Vec<(String, serde_json::Value)>,
+}
+
+impl Batch {
+    /// Split `rows` for `table` into upload-sized batches.
+    fn chunked(
+        table: &'static str,
+        parent: Option<&'static str>,
+        rows: Vec<(String, serde_json::Value)>,
+    ) -> Vec<Batch> {
+        chunk_rows(rows, UPLOAD_BATCH_ROWS, UPLOAD_BATCH_BYTES)
+            .into_iter()
+            .map(|rows| Batch {
+                table,
+                parent,
+                rows,
+            })
+            .collect()
+    }
+}
+
+/// Columns PostgREST should upsert on when they differ from the primary key.
+/// PostgREST query parameters for an [`ExtractionFilter`], each with a leading `&`.
+fn filter_params(filter: &ExtractionFilter) -> String {
+    let mut params = String::new();
+    let mut push = |column: &str, condition: String| {
+        params.push_str(&format!("&{}={}", column, condition));
+    };
+    if let Some(ref name) = filter.config_name {
+        push("config_name", format!("eq.{}", encode(name)));
+    }
+    if let Some(ref version) = filter.config_version {
+        push("config_version", format!("eq.{}", encode(version)));
+    }
+    if let Some(ref part) = filter.source_file {
+        push(
+            "source_file",
+            format!("ilike.{}", encode(&like_pattern(part))),
+        );
+    }
+    if let Some(ref since) = filter.since {
+        push("extracted_at", format!("gte.{}", encode(since)));
+    }
+    if let Some(ref until) = filter.until {
+        push("extracted_at", format!("lt.{}", encode(until)));
+    }
+    if let Some(reviewed) = filter.reviewed {
+        push("reviewed", format!("is.{}", reviewed));
+    }
+    if let Some(limit) = filter.limit {
+        push("limit", limit.to_string());
+    }
+    params
+}
+
+/// Percent-encode a query parameter value.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
 }
 
+fn conflict_target(table: &str) -> Option<&'static str> {
+    match table {
+        "extraction_relationships" => Some("extraction_id,from_node,to_node,relationship_type"),
+        _ => None,
+    }
+}
+
+/// Batches a new upload of the plan `fingerprint` can skip: those `previous`
+/// recorded as done for the same plan. A changed plan starts over, and so
+/// does a completed record, which means this is a deliberate re-upload.
+fn resumed_batches(previous: Option<UploadState>, fingerprint: &str) -> Vec<usize> {
+    previous
+        .filter(|s| s.fingerprint == fingerprint && s.status != UPLOAD_COMPLETED)
+        .map(|s| s.completed_batches)
+        .unwrap_or_default()
+}
+
+/// Rows of `batch` to send, holding back those whose key failed in the
+/// batch's parent table earlier in the run.
+fn rows_to_send<'a>(
+    batch: &'a Batch,
+    failed_keys: &HashMap<&'static str, HashSet<String>>,
+) -> Vec<&'a (String, serde_json::Value)> {
+    let held_back = batch.parent.and_then(|parent| failed_keys.get(parent));
+    batch
+        .rows
+        .iter()
+        .filter(|(key, _)| !held_back.is_some_and(|keys| keys.contains(key)))
+        .collect()
+}
+
+/// Hash of a batch plan; saved progress only applies to an identical plan.
+fn plan_fingerprint(target_id: &str, batches: &[Batch]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(target_id.as_bytes());
+    for batch in batches {
+        hasher.update([0]);
+        hasher.update(batch.table.as_bytes());
+        for (_, row) in &batch.rows {
+            hasher.update([0]);
+            hasher.update(row.to_string().as_bytes());
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Split keyed rows into chunks of at most `max_rows` rows and roughly
+/// `max_bytes` of JSON. A single oversized row still gets its own chunk.
+fn chunk_rows<K>(
+    rows: Vec<(K, serde_json::Value)>,
+    max_rows: usize,
+    max_bytes: usize,
+) -> Vec<Vec<(K, serde_json::Value)>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut current_bytes = 0usize;
+
+    for (key, row) in rows {
+        let size = row.to_string().len();
+        if !current.is_empty() && (current.len() >= max_rows || current_bytes + size > max_bytes) {
+            chunks.push(std::mem::take(&mut current));
+            current_bytes = 0;
+        }
+        current_bytes += size;
+        current.push((key, row));
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// A chunk that PostgREST rejected during a batched upload.
+#[derive(Debug)]
+struct BatchFailure {
+    table: &'static str,
+    /// Key (node ID or `from->to`) of the first and last row in the chunk.
+    first: String,
+    last: String,
+    rows: usize,
+    error: String,
+}
+
+impl fmt::Display for BatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} rows {}..{} ({} rows): {}",
+            self.table, self.first, self.last, self.rows, self.error
+        )
+    }
+}
+
+/// Progress record for a batched upload (`extraction.upload_state`).
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadState {
+    target_type: String,
+    target_id: String,
+    fingerprint: String,
+    total_batches: usize,
+    /// Indices into the batch plan that have been fully inserted.
+    completed_batches: Vec<usize>,
+    status: String,
+    last_error: Option<String>,
+    updated_at: String,
+}
+
+// ============================================================================
+// Supabase row types
+// ============================================================================
+
+/// A `node_content` row without its text.
 #[derive(Debug, Deserialize)]
-struct NodeRow {
-    id: String,
-    parent_id: Option<String>,
-    #[serde(rename = "type")]
-    node_type: String,
-    subtype: Option<String>,
-    label: Option<String>,
-    page_start: Option<u32>,
-    page_end: Option<u32>,
-    date: Option<String>,
-    author: Option<String>,
-    summary: String,
-    confidence: Option<ConfidenceScores>,
-    #[serde(default)]
-    metadata: Option<serde_json::Value>,
+struct ContentRef {
+    node_id: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct ContentRow {
     node_id: String,
     content: String,
+    #[serde(default)]
+    content_encoding: Option<String>,
+}
+
+impl ContentRow {
+    /// Decode the stored content (decompressing if needed).
+    fn decode(self) -> Result<String> {
+        compression::decode_text(self.content, self.content_encoding.as_deref())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -820,6 +1235,133 @@ struct RelationshipRow {
     relationship_type: String,
 }
 
+// ============================================================================
+// Legacy row shapes
+// ============================================================================
+//
+// Extractions written by earlier versions are read as JSON and patched into
+// the current row shape before decoding: NULLs in columns that are now NOT
+// NULL, JSON columns stored as strings, renamed columns, and relationship
+// types from when they were an enum (`RESPONDS_TO`, `RespondsTo`).
+
+/// Columns that are NULL or missing in old rows and decode as a plain value.
+fn default_missing(row: &mut serde_json::Map<String, Value>, key: &str, value: Value) {
+    if row.get(key).is_none_or(Value::is_null) {
+        row.insert(key.to_string(), value);
+    }
+}
+
+/// Move an old column name to the current one unless that is already set.
+fn rename_column(row: &mut serde_json::Map<String, Value>, old: &str, new: &str) {
+    if row.get(new).is_none_or(Value::is_null) {
+        if let Some(value) = row.remove(old) {
+            row.insert(new.to_string(), value);
+        }
+    }
+}
+
+/// Parse a JSON column that was stored as a string.
+fn parse_stringified(row: &mut serde_json::Map<String, Value>, key: &str) {
+    if let Some(Value::String(text)) = row.get(key) {
+        let parsed = serde_json::from_str(text).unwrap_or(Value::Null);
+        row.insert(key.to_string(), parsed);
+    }
+}
+
+fn upgrade_extraction_row(row: &mut Value) {
+    let Some(row) = row.as_object_mut() else {
+        return;
+    };
+    rename_column(row, "filename", "source_file");
+    rename_column(row, "created_at", "extracted_at");
+    for key in ["summary", "source_file", "extracted_at"] {
+        default_missing(row, key, Value::from(""));
+    }
+    default_missing(row, "reviewed", Value::Bool(false));
+    for key in ["structure_map", "metadata", "reference_index", "diagnostics"] {
+        parse_stringified(row, key);
+    }
+    if let Some(Value::String(pages)) = row.get("total_pages") {
+        let pages = pages.parse::<u32>().map_or(Value::Null, Value::from);
+        row.insert("total_pages".to_string(), pages);
+    }
+    // The structure map is only a navigation aid; drop one that predates
+    // the current entry shape rather than fail the whole extraction
+    if let Some(map) = row.get("structure_map") {
+        if serde_json::from_value::<Vec<StructureMapEntry>>(map.clone()).is_err() {
+            row.insert("structure_map".to_string(), Value::Null);
+        }
+    }
+}
+
+fn upgrade_node_row(row: &mut Value) {
+    let Some(row) = row.as_object_mut() else {
+        return;
+    };
+    rename_column(row, "node_type", "type");
+    default_missing(row, "summary", Value::from(""));
+    default_missing(row, "reviewed", Value::Bool(false));
+    for key in ["confidence", "metadata"] {
+        parse_stringified(row, key);
+    }
+    // A single score was the extraction confidence
+    match row.get("confidence") {
+        Some(Value::Number(score)) => {
+            let scores = json!({ "extraction": score });
+            row.insert("confidence".to_string(), scores);
+        }
+        Some(Value::Object(_)) | Some(Value::Null) | None => {}
+        Some(_) => {
+            row.insert("confidence".to_string(), Value::Null);
+        }
+    }
+    if let Some(Value::Array(range)) = row.remove("page_range") {
+        if let [start, end] = range.as_slice() {
+            default_missing(row, "page_start", start.clone());
+            default_missing(row, "page_end", end.clone());
+        }
+    }
+}
+
+fn upgrade_relationship_row(row: &mut Value) {
+    let Some(row) = row.as_object_mut() else {
+        return;
+    };
+    rename_column(row, "type", "relationship_type");
+    rename_column(row, "rel_type", "relationship_type");
+    rename_column(row, "from", "from_node");
+    rename_column(row, "to", "to_node");
+    if let Some(Value::String(rel_type)) = row.get("relationship_type") {
+        let rel_type = legacy_relationship_type(rel_type);
+        row.insert("relationship_type".to_string(), Value::from(rel_type));
+    }
+}
+
+/// Current snake_case spelling of a relationship type written as an enum
+/// variant (`RESPONDS_TO`, `RespondsTo`, `responds-to`).
+fn legacy_relationship_type(rel_type: &str) -> String {
+    let mut out = String::with_capacity(rel_type.len() + 4);
+    let mut prev_lower = false;
+    for c in rel_type.trim().chars() {
+        if c == '-' || c == ' ' || c == '_' {
+            if !out.is_empty() && !out.ends_with('_') {
+                out.push('_');
+            }
+            prev_lower = false;
+        } else if c.is_uppercase() {
+            if prev_lower {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+            prev_lower = false;
+        } else {
+            out.push(c);
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+    out
+}
+
 // ============================================================================
 // Config row types
 // ============================================================================
@@ -833,30 +1375,6 @@ struct ConfigRow {
 // Dataset row types
 // ============================================================================
 
-#[derive(Debug, Deserialize)]
-pub struct DatasetRow {
-    pub id: String,
-    pub source_file: String,
-    pub config_name: Option<String>,
-    pub extracted_at: String,
-    pub summary: String,
-    pub schemas: serde_json::Value,
-    pub relationships: Option<serde_json::Value>,
-    pub status: Option<String>,
-}
-
-/// Schema definition as stored in the JSONB `schemas` column.
-#[derive(Debug, Deserialize)]
-struct DatasetSchemaJson {
-    name: String,
-    description: String,
-    #[serde(default)]
-    columns: Vec<ColumnDef>,
-    #[allow(dead_code)]
-    #[serde(default)]
-    row_count: usize,
-}
-
 /// A single row entry from `dataset_rows` table.
 #[derive(Debug, Deserialize)]
 struct DatasetRowEntry {
@@ -874,76 +1392,278 @@ struct DatasetRowEntry {
     row_index: Option<i64>,
 }
 
-/// Build a nested tree from flat node rows.
-fn build_tree(
-    nodes: &[NodeRow],
-    content_map: &std::collections::HashMap<String, String>,
-) -> Vec<DocumentNode> {
-    use std::collections::HashMap;
+// ============================================================================
+// Storage trait implementation
+// ============================================================================
+
+#[async_trait::async_trait]
+impl Storage for SupabaseClient {
+    fn name(&self) -> &str {
+        "supabase"
+    }
+
+    async fn ping(&self) -> Result<()> {
+        let _: Vec<serde_json::Value> = self.get_json("configs?select=name&limit=1").await?;
+        Ok(())
+    }
+
+    async fn upload_extraction(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+    ) -> Result<()> {
+        SupabaseClient::upload_extraction(self, extraction, content_store).await
+    }
 
-    // Index nodes by id
-    let node_map: HashMap<&str, &NodeRow> = nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    async fn list_extractions(&self, filter: &ExtractionFilter) -> Result<Vec<ExtractionRow>> {
+        SupabaseClient::list_extractions(self, filter).await
+    }
 
-    // Group children by parent_id
-    let mut children_of: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
-    for node in nodes {
-        children_of
-            .entry(node.parent_id.as_deref())
-            .or_default()
-            .push(&node.id);
+    async fn fetch_extraction(&self, id: &str) -> Result<Option<Extraction>> {
+        SupabaseClient::fetch_extraction(self, id).await
     }
 
-    fn build_node(
+    async fn fetch_extraction_content(
+        &self,
         id: &str,
-        node_map: &HashMap<&str, &NodeRow>,
-        children_of: &HashMap<Option<&str>, Vec<&str>>,
-        content_map: &std::collections::HashMap<String, String>,
-    ) -> DocumentNode {
-        let row = node_map[id];
-        let page_range = match (row.page_start, row.page_end) {
-            (Some(s), Some(e)) => Some([s, e]),
-            _ => None,
-        };
-        let content_ref = if content_map.contains_key(id) {
-            Some(format!("content://{}", id))
-        } else {
-            None
-        };
+        content_store: &ContentStore,
+    ) -> Result<usize> {
+        SupabaseClient::fetch_extraction_content(self, id, content_store).await
+    }
 
-        let children: Vec<DocumentNode> = children_of
-            .get(&Some(id))
-            .map(|ids| {
-                ids.iter()
-                    .map(|cid| build_node(cid, node_map, children_of, content_map))
-                    .collect()
-            })
-            .unwrap_or_default();
-
-        DocumentNode {
-            id: row.id.clone(),
-            node_type: row.node_type.clone(),
-            subtype: row.subtype.clone(),
-            label: row.label.clone(),
-            page_range,
-            date: row.date.clone(),
-            author: row.author.clone(),
-            summary: row.summary.clone(),
-            references: Vec::new(),
-            referenced_by: Vec::new(),
-            content_ref,
-            confidence: row.confidence.clone(),
-            metadata: row.metadata.clone().unwrap_or(serde_json::Value::Null),
-            children,
+    async fn record_review(
+        &self,
+        extraction_id: &str,
+        node: &DocumentNode,
+        review: &NodeReview,
+    ) -> Result<bool> {
+        SupabaseClient::record_review(self, extraction_id, node, review).await
+    }
+
+    async fn replace_tree(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        removed: &[String],
+    ) -> Result<bool> {
+        SupabaseClient::replace_tree(self, extraction, content_store, removed).await
+    }
+
+    async fn delete_extraction(&self, id: &str) -> Result<()> {
+        SupabaseClient::delete_extraction(self, id).await
+    }
+
+    async fn fetch_content_by_node_id(&self, node_id: &str) -> Result<Option<String>> {
+        SupabaseClient::fetch_content_by_node_id(self, node_id).await
+    }
+
+    async fn upload_dataset(&self, dataset: &SheetExtraction) -> Result<()> {
+        SupabaseClient::upload_dataset(self, dataset).await
+    }
+
+    async fn list_datasets(&self) -> Result<Vec<DatasetRow>> {
+        SupabaseClient::list_datasets(self).await
+    }
+
+    async fn fetch_dataset(&self, id: &str) -> Result<Option<SheetExtraction>> {
+        SupabaseClient::fetch_dataset(self, id).await
+    }
+
+    async fn query_dataset_rows(
+        &self,
+        dataset_id: &str,
+        schema_name: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<serde_json::Value>> {
+        SupabaseClient::query_dataset_rows(self, dataset_id, schema_name, offset, limit).await
+    }
+
+    async fn delete_dataset(&self, id: &str) -> Result<()> {
+        SupabaseClient::delete_dataset(self, id).await
+    }
+
+    async fn list_configs(&self) -> Result<Vec<ExtractionConfig>> {
+        SupabaseClient::list_configs(self).await
+    }
+
+    async fn upsert_config(&self, config: &ExtractionConfig) -> Result<()> {
+        SupabaseClient::upsert_config(self, config).await
+    }
+
+    async fn delete_config(&self, name: &str) -> Result<()> {
+        SupabaseClient::delete_config(self, name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_rows_decode() {
+        let mut extraction = json!({
+            "id": "ext_old",
+            "config_name": "legal_br",
+            "content_hash": null,
+            "filename": "autos.pdf",
+            "summary": null,
+            "total_pages": "12",
+            "structure_map": [{"title": "Petição", "nodes": 3}],
+            "metadata": "{\"tribunal\": \"TJSP\"}",
+            "readable_id": null,
+            "created_at": "2024-03-01T10:00:00Z",
+            "extractor_version": null
+        });
+        upgrade_extraction_row(&mut extraction);
+        let row: ExtractionRow = serde_json::from_value(extraction).unwrap();
+        assert_eq!(row.source_file, "autos.pdf");
+        assert_eq!(row.extracted_at, "2024-03-01T10:00:00Z");
+        assert_eq!(row.total_pages, Some(12));
+        assert!(row.structure_map.is_none());
+        assert_eq!(row.metadata.unwrap()["tribunal"], "TJSP");
+
+        let mut node = json!({
+            "id": "n1",
+            "parent_id": null,
+            "node_type": "PETICAO",
+            "subtype": null,
+            "label": "Petição Inicial",
+            "page_range": [1, 4],
+            "date": null,
+            "author": null,
+            "summary": null,
+            "confidence": 0.82
+        });
+        upgrade_node_row(&mut node);
+        let node: NodeRow = serde_json::from_value(node).unwrap();
+        assert_eq!(node.node_type, "PETICAO");
+        assert_eq!((node.page_start, node.page_end), (Some(1), Some(4)));
+        assert_eq!(node.confidence.unwrap().extraction, Some(0.82));
+
+        for (old, current) in [
+            ("RESPONDS_TO", "responds_to"),
+            ("RespondsTo", "responds_to"),
+            ("decides-on", "decides_on"),
+            ("cites", "cites"),
+        ] {
+            let mut rel = json!({"from_node": "a", "to_node": "b", "type": old});
+            upgrade_relationship_row(&mut rel);
+            let rel: RelationshipRow = serde_json::from_value(rel).unwrap();
+            assert_eq!(rel.relationship_type, current);
         }
     }
 
-    // Root nodes have parent_id = None
-    children_of
-        .get(&None)
-        .map(|ids| {
-            ids.iter()
-                .map(|id| build_node(id, &node_map, &children_of, content_map))
-                .collect()
-        })
-        .unwrap_or_default()
+    #[test]
+    fn test_chunk_rows_by_count_and_size() {
+        let rows: Vec<(usize, serde_json::Value)> =
+            (0..5).map(|i| (i, json!({ "n": i }))).collect();
+        let sizes: Vec<usize> = chunk_rows(rows, 2, usize::MAX)
+            .iter()
+            .map(Vec::len)
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        // Byte budget splits earlier; an oversized row still goes out alone.
+        let big = "x".repeat(100);
+        let rows = vec![
+            (0, json!({ "c": "a" })),
+            (1, json!({ "c": big })),
+            (2, json!({ "c": "b" })),
+            (3, json!({ "c": "c" })),
+        ];
+        let keys: Vec<Vec<usize>> = chunk_rows(rows, 10, 50)
+            .into_iter()
+            .map(|c| c.into_iter().map(|(k, _)| k).collect())
+            .collect();
+        assert_eq!(keys, vec![vec![0], vec![1], vec![2, 3]]);
+    }
+
+    #[test]
+    fn test_upload_resume() {
+        let plan = |summary: &str| {
+            let mut batches = Batch::chunked(
+                "extraction_nodes",
+                None,
+                vec![
+                    ("peticao".to_string(), json!({ "id": "peticao" })),
+                    ("sentenca".to_string(), json!({ "summary": summary })),
+                ],
+            );
+            batches.extend(Batch::chunked(
+                "node_content",
+                Some("extraction_nodes"),
+                vec![
+                    ("peticao".to_string(), json!({ "content": "a" })),
+                    ("sentenca".to_string(), json!({ "content": "b" })),
+                ],
+            ));
+            batches
+        };
+        let fingerprint = plan_fingerprint("ext_1", &plan("Julga procedente"));
+        assert_eq!(
+            fingerprint,
+            plan_fingerprint("ext_1", &plan("Julga procedente"))
+        );
+        assert_ne!(
+            fingerprint,
+            plan_fingerprint("ext_1", &plan("Julga improcedente"))
+        );
+        assert_ne!(
+            fingerprint,
+            plan_fingerprint("ext_2", &plan("Julga procedente"))
+        );
+
+        let record = |fingerprint: &str, status: &str| UploadState {
+            target_type: UPLOAD_TARGET_EXTRACTION.to_string(),
+            target_id: "ext_1".to_string(),
+            fingerprint: fingerprint.to_string(),
+            total_batches: 2,
+            completed_batches: vec![0],
+            status: status.to_string(),
+            last_error: None,
+            updated_at: now_iso8601(),
+        };
+        assert_eq!(
+            resumed_batches(Some(record(&fingerprint, UPLOAD_PARTIAL)), &fingerprint),
+            vec![0]
+        );
+        // A completed upload or a changed plan is sent again in full
+        assert!(
+            resumed_batches(Some(record(&fingerprint, UPLOAD_COMPLETED)), &fingerprint).is_empty()
+        );
+        assert!(resumed_batches(Some(record("other", UPLOAD_PARTIAL)), &fingerprint).is_empty());
+        assert!(resumed_batches(None, &fingerprint).is_empty());
+
+        // Content waits for the node rows that failed earlier in the run
+        let batches = plan("Julga procedente");
+        let failed_keys =
+            HashMap::from([("extraction_nodes", HashSet::from(["sentenca".to_string()]))]);
+        let keys: Vec<&str> = rows_to_send(&batches[1], &failed_keys)
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect();
+        assert_eq!(keys, vec!["peticao"]);
+        assert_eq!(rows_to_send(&batches[0], &failed_keys).len(), 2);
+    }
+
+    #[test]
+    fn test_filter_params() {
+        let filter = ExtractionFilter {
+            config_name: Some("legal_br".into()),
+            source_file: Some("ação_1".into()),
+            since: Some("2024-01-01".into()),
+            until: Some("2024-02-01T00:00:00+00:00".into()),
+            reviewed: Some(true),
+            limit: Some(50),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter_params(&filter),
+            "&config_name=eq.legal_br&source_file=ilike.%25a%C3%A7%C3%A3o%5C_1%25\
+             &extracted_at=gte.2024-01-01&extracted_at=lt.2024-02-01T00%3A00%3A00%2B00%3A00\
+             &reviewed=is.true&limit=50"
+        );
+        assert_eq!(filter_params(&ExtractionFilter::default()), "");
+    }
 }
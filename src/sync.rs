@@ -0,0 +1,422 @@
+//! Durable outbox for storage uploads that failed.
+//!
+//! When an upload to the storage backend fails (typically a Supabase outage),
+//! the extraction or dataset is written to `SYNC_OUTBOX_DIR` (default
+//! `data/outbox/`) together with its node content. A background task retries
+//! pending entries every `SYNC_INTERVAL_SECS` (default 30) and removes them
+//! once the upload succeeds, so nothing is lost across restarts.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, warn};
+
+use crate::content_store::ContentStore;
+use crate::schema::{now_iso8601, DocumentNode, Extraction};
+use crate::sheet_schema::SheetExtraction;
+use crate::storage::Storage;
+
+const DEFAULT_INTERVAL_SECS: u64 = 30;
+
+/// What an outbox entry uploads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutboxKind {
+    Extraction,
+    Dataset,
+}
+
+impl OutboxKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Extraction => "extraction",
+            Self::Dataset => "dataset",
+        }
+    }
+}
+
+/// Metadata for a pending upload (everything but the payload).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub kind: OutboxKind,
+    pub id: String,
+    pub enqueued_at: String,
+    pub attempts: u32,
+    pub last_attempt_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Payload stored alongside the metadata.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum OutboxPayload {
+    Extraction {
+        extraction: Box<Extraction>,
+        /// Node ID → content, captured at enqueue time.
+        content: HashMap<String, String>,
+    },
+    Dataset {
+        dataset: Box<SheetExtraction>,
+    },
+}
+
+/// On-disk file format: one JSON file per pending upload.
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxFile {
+    #[serde(flatten)]
+    entry: OutboxEntry,
+    payload: OutboxPayload,
+}
+
+/// Backlog summary for `GET /sync/status`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStatus {
+    pub outbox_dir: String,
+    pub interval_secs: u64,
+    pub pending: usize,
+    pub synced_total: u64,
+    pub last_run_at: Option<String>,
+    pub last_success_at: Option<String>,
+    pub entries: Vec<OutboxEntry>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Keyed by file stem, so iteration order is stable.
+    entries: BTreeMap<String, OutboxEntry>,
+    synced_total: u64,
+    last_run_at: Option<String>,
+    last_success_at: Option<String>,
+}
+
+/// Disk-backed queue of uploads waiting to be retried.
+pub struct SyncOutbox {
+    dir: PathBuf,
+    interval: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl SyncOutbox {
+    /// Open (or create) the outbox in `dir`, loading any pending entries.
+    pub fn open(dir: impl Into<PathBuf>, interval: Duration) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create outbox dir {}", dir.display()))?;
+
+        let mut entries = BTreeMap::new();
+        for item in std::fs::read_dir(&dir)? {
+            let path = item?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match read_file(&path) {
+                Ok(file) => {
+                    entries.insert(entry_key(file.entry.kind, &file.entry.id), file.entry);
+                }
+                Err(e) => warn!("Skipping unreadable outbox file {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self {
+            dir,
+            interval,
+            inner: Mutex::new(Inner {
+                entries,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// Open the outbox from `SYNC_OUTBOX_DIR` and `SYNC_INTERVAL_SECS`.
+    pub fn from_env() -> Result<Self> {
+        let dir = std::env::var("SYNC_OUTBOX_DIR").unwrap_or_else(|_| "data/outbox".to_string());
+        let interval_secs = std::env::var("SYNC_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&s: &u64| s > 0)
+            .unwrap_or(DEFAULT_INTERVAL_SECS);
+        Self::open(dir, Duration::from_secs(interval_secs))
+    }
+
+    /// Number of uploads waiting to be retried.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Queue an extraction (and its node content) for upload.
+    pub fn enqueue_extraction(
+        &self,
+        extraction: &Extraction,
+        content_store: &ContentStore,
+        error: &str,
+    ) -> Result<()> {
+        let mut content = HashMap::new();
+        collect_content(&extraction.children, content_store, &mut content);
+        self.enqueue(
+            OutboxKind::Extraction,
+            &extraction.id,
+            OutboxPayload::Extraction {
+                extraction: Box::new(extraction.clone()),
+                content,
+            },
+            error,
+        )
+    }
+
+    /// Queue a dataset for upload.
+    pub fn enqueue_dataset(&self, dataset: &SheetExtraction, error: &str) -> Result<()> {
+        self.enqueue(
+            OutboxKind::Dataset,
+            &dataset.id,
+            OutboxPayload::Dataset {
+                dataset: Box::new(dataset.clone()),
+            },
+            error,
+        )
+    }
+
+    fn enqueue(
+        &self,
+        kind: OutboxKind,
+        id: &str,
+        payload: OutboxPayload,
+        error: &str,
+    ) -> Result<()> {
+        let now = now_iso8601();
+        let file = OutboxFile {
+            entry: OutboxEntry {
+                kind,
+                id: id.to_string(),
+                enqueued_at: now.clone(),
+                attempts: 1,
+                last_attempt_at: Some(now),
+                last_error: Some(error.to_string()),
+            },
+            payload,
+        };
+
+        let key = entry_key(kind, id);
+        write_file(&self.path_for(&key), &file)?;
+        self.inner.lock().unwrap().entries.insert(key, file.entry);
+        info!("Queued {} {} for background sync", kind.as_str(), id);
+        Ok(())
+    }
+
+    /// Current backlog and counters.
+    pub fn status(&self) -> SyncStatus {
+        let inner = self.inner.lock().unwrap();
+        SyncStatus {
+            outbox_dir: self.dir.display().to_string(),
+            interval_secs: self.interval.as_secs(),
+            pending: inner.entries.len(),
+            synced_total: inner.synced_total,
+            last_run_at: inner.last_run_at.clone(),
+            last_success_at: inner.last_success_at.clone(),
+            entries: inner.entries.values().cloned().collect(),
+        }
+    }
+
+    /// Try pending entries, least recently attempted first. Stops at the first
+    /// failure, since that usually means the backend is still unreachable; the
+    /// failed entry moves to the back so one bad entry cannot block the rest.
+    pub async fn run_once(&self, storage: &dyn Storage) {
+        let keys: Vec<String> = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.last_run_at = Some(now_iso8601());
+            let mut pending: Vec<(&String, &OutboxEntry)> = inner.entries.iter().collect();
+            pending.sort_by(|a, b| a.1.last_attempt_at.cmp(&b.1.last_attempt_at));
+            pending.into_iter().map(|(k, _)| k.clone()).collect()
+        };
+
+        for key in keys {
+            let path = self.path_for(&key);
+            let mut file = match read_file(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    error!("Dropping unreadable outbox entry {}: {}", key, e);
+                    self.inner.lock().unwrap().entries.remove(&key);
+                    continue;
+                }
+            };
+
+            match upload(storage, &file.payload).await {
+                Ok(()) => {
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        warn!("Failed to remove outbox file {}: {}", path.display(), e);
+                    }
+                    let mut inner = self.inner.lock().unwrap();
+                    inner.entries.remove(&key);
+                    inner.synced_total += 1;
+                    inner.last_success_at = Some(now_iso8601());
+                    info!(
+                        "Background sync uploaded {} {} to {}",
+                        file.entry.kind.as_str(),
+                        file.entry.id,
+                        storage.name()
+                    );
+                }
+                Err(e) => {
+                    file.entry.attempts += 1;
+                    file.entry.last_attempt_at = Some(now_iso8601());
+                    file.entry.last_error = Some(e.to_string());
+                    warn!(
+                        "Background sync of {} {} failed (attempt {}): {}",
+                        file.entry.kind.as_str(),
+                        file.entry.id,
+                        file.entry.attempts,
+                        e
+                    );
+                    if let Err(e) = write_file(&path, &file) {
+                        warn!("Failed to update outbox file {}: {}", path.display(), e);
+                    }
+                    self.inner.lock().unwrap().entries.insert(key, file.entry);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Spawn the background retry loop.
+    pub fn spawn(self: Arc<Self>, storage: Arc<dyn Storage>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                if self.pending() == 0 {
+                    continue;
+                }
+                debug!("Background sync: {} pending", self.pending());
+                self.run_once(storage.as_ref()).await;
+            }
+        });
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// Re-run the upload for a stored payload.
+async fn upload(storage: &dyn Storage, payload: &OutboxPayload) -> Result<()> {
+    match payload {
+        OutboxPayload::Extraction {
+            extraction,
+            content,
+        } => {
+            let content_store = ContentStore::new();
+            for (node_id, text) in content {
+                content_store.store(node_id, text.clone());
+            }
+            storage.upload_extraction(extraction, &content_store).await
+        }
+        OutboxPayload::Dataset { dataset } => storage.upload_dataset(dataset).await,
+    }
+}
+
+/// Gather the full content of every node that has a content ref.
+fn collect_content(
+    nodes: &[DocumentNode],
+    content_store: &ContentStore,
+    out: &mut HashMap<String, String>,
+) {
+    for node in nodes {
+        if let Some(text) = node
+            .content_ref
+            .as_deref()
+            .and_then(|r| content_store.get_full(r))
+        {
+            out.insert(node.id.clone(), text);
+        }
+        collect_content(&node.children, content_store, out);
+    }
+}
+
+/// File stem for an entry: kind plus the ID with unsafe characters replaced.
+fn entry_key(kind: OutboxKind, id: &str) -> String {
+    let safe: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}_{}", kind.as_str(), safe)
+}
+
+fn read_file(path: &Path) -> Result<OutboxFile> {
+    let bytes = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Write via a temp file and rename so a crash never leaves a torn entry.
+fn write_file(path: &Path, file: &OutboxFile) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(file)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::sqlite::SqliteStorage;
+
+    #[tokio::test]
+    async fn test_outbox_survives_restart_and_drains() {
+        let root =
+            std::env::temp_dir().join(format!("outbox_test_{}", uuid::Uuid::new_v4().simple()));
+        let dir = root.join("outbox");
+        let interval = Duration::from_secs(1);
+
+        let content_store = ContentStore::new();
+        let mut ext = Extraction::new("doc.pdf".into(), None);
+        let mut leaf: DocumentNode = serde_json::from_value(serde_json::json!({
+            "id": "leaf",
+            "type": "document",
+            "summary": "leaf",
+        }))
+        .unwrap();
+        leaf.content_ref = Some(content_store.store("leaf", "leaf text".into()));
+        ext.children = vec![leaf];
+
+        let outbox = SyncOutbox::open(&dir, interval).unwrap();
+        outbox
+            .enqueue_extraction(&ext, &content_store, "connection refused")
+            .unwrap();
+        drop(outbox);
+
+        // Reopening picks the entry back up from disk.
+        let outbox = SyncOutbox::open(&dir, interval).unwrap();
+        let status = outbox.status();
+        assert_eq!(status.pending, 1);
+        assert_eq!(status.entries[0].id, ext.id);
+        assert_eq!(
+            status.entries[0].last_error.as_deref(),
+            Some("connection refused")
+        );
+
+        let storage = SqliteStorage::open(root.join("db.sqlite").to_str().unwrap())
+            .await
+            .unwrap();
+        outbox.run_once(&storage).await;
+        assert_eq!(outbox.pending(), 0);
+        assert_eq!(outbox.status().synced_total, 1);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        assert_eq!(
+            storage
+                .fetch_content_by_node_id("leaf")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("leaf text")
+        );
+    }
+}
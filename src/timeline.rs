@@ -0,0 +1,442 @@
+//! Chronological view of an extraction.
+//!
+//! Collects every date attached to a node: the node's own `date`, date
+//! strings anywhere in its metadata (`effective_date`, `signatures[0].date`,
+//! ...) and reference index values that read as dates. Dates are normalized
+//! to YYYY-MM-DD; day-first numeric dates (`15/03/2024`) and Portuguese long
+//! dates (`15 de março de 2024`) are understood. The mentions of one date on
+//! one node make a single event, and events are ordered by date, then by
+//! position in the tree. Optionally the LLM names each event's kind.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::entities::ReferenceIndex;
+use crate::openrouter::{Message, OpenRouterClient};
+use crate::schema::{DocumentNode, Extraction};
+
+/// Summary characters shown to the LLM per event.
+const SUMMARY_CHARS: usize = 200;
+
+/// Where a date was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MentionSource {
+    /// The node's `date`
+    Node,
+    /// A string in the node's metadata
+    Metadata,
+    /// A reference index value
+    Entity,
+}
+
+/// One place a date appears.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DateMention {
+    pub source: MentionSource,
+    /// Metadata path (`signatures[0].date`) or entity type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// The date as written
+    pub raw: String,
+}
+
+/// A date on a node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// YYYY-MM-DD
+    pub date: String,
+    pub node_id: String,
+    pub node_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_range: Option<[u32; 2]>,
+    pub mentions: Vec<DateMention>,
+    /// Kind of event named by the LLM (`?classify=true`), e.g. "filing"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event_type: Option<String>,
+}
+
+/// A node's `date` that could not be read as a date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnparsedDate {
+    pub node_id: String,
+    pub raw: String,
+}
+
+/// `GET /extractions/:id/timeline` response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    pub extraction_id: String,
+    pub events: Vec<TimelineEvent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unparsed: Vec<UnparsedDate>,
+}
+
+struct DatePatterns {
+    /// 2024-03-15, 2024.03.15, 2024/03/15 (optionally followed by a time)
+    iso: Regex,
+    /// 15/03/2024, 15.03.2024, 15-03-24
+    day_first: Regex,
+    /// 15 de março de 2024, 1º de mar. de 2024
+    long: Regex,
+}
+
+impl DatePatterns {
+    fn new() -> Self {
+        Self {
+            iso: Regex::new(r"^(\d{4})[-./](\d{1,2})[-./](\d{1,2})(?:$|[T\s])")
+                .expect("valid date regex"),
+            day_first: Regex::new(r"^(\d{1,2})[-./](\d{1,2})[-./](\d{4}|\d{2})(?:$|\D)")
+                .expect("valid date regex"),
+            long: Regex::new(r"(?i)^(\d{1,2})\s*[ºo°]?\s+de\s+([a-zç]+)\.?\s+de\s+(\d{4})\b")
+                .expect("valid date regex"),
+        }
+    }
+
+    /// `raw` as YYYY-MM-DD, when it starts with a valid date.
+    fn normalize(&self, raw: &str) -> Option<String> {
+        let raw = raw.trim();
+        let (year, month, day): (i32, u32, u32) = if let Some(c) = self.iso.captures(raw) {
+            (c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?)
+        } else if let Some(c) = self.day_first.captures(raw) {
+            let year: i32 = c[3].parse().ok()?;
+            let year = match (c[3].len(), year) {
+                (2, y) if y <= 50 => 2000 + y,
+                (2, y) => 1900 + y,
+                (_, y) => y,
+            };
+            (year, c[2].parse().ok()?, c[1].parse().ok()?)
+        } else if let Some(c) = self.long.captures(raw) {
+            (c[3].parse().ok()?, month_number(&c[2])?, c[1].parse().ok()?)
+        } else {
+            return None;
+        };
+        let valid = (1..=12).contains(&month)
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && (1000..=9999).contains(&year);
+        valid.then(|| format!("{:04}-{:02}-{:02}", year, month, day))
+    }
+}
+
+/// Month number of a Portuguese month name or its abbreviation.
+fn month_number(name: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov", "dez",
+    ];
+    let name = name.to_lowercase().replace('ç', "c");
+    let prefix: String = name.chars().take(3).collect();
+    MONTHS
+        .iter()
+        .position(|m| *m == prefix)
+        .map(|i| i as u32 + 1)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+/// Date strings in a node's metadata, with their paths. Keys starting with
+/// `_` hold pipeline bookkeeping (`_entities`, `_toc`) and are skipped.
+fn metadata_dates(
+    patterns: &DatePatterns,
+    value: &Value,
+    path: &str,
+    out: &mut Vec<(String, String, String)>,
+) {
+    match value {
+        Value::String(s) => {
+            if let Some(date) = patterns.normalize(s) {
+                out.push((date, path.to_string(), s.clone()));
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                metadata_dates(patterns, item, &format!("{}[{}]", path, i), out);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter().filter(|(k, _)| !k.starts_with('_')) {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                metadata_dates(patterns, item, &path, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Nodes in tree order.
+fn flatten(nodes: &[DocumentNode]) -> Vec<&DocumentNode> {
+    fn walk<'a>(nodes: &'a [DocumentNode], out: &mut Vec<&'a DocumentNode>) {
+        for node in nodes {
+            out.push(node);
+            walk(&node.children, out);
+        }
+    }
+    let mut out = Vec::new();
+    walk(nodes, &mut out);
+    out
+}
+
+/// Build the timeline of an extraction.
+pub fn build(extraction: &Extraction) -> Timeline {
+    let patterns = DatePatterns::new();
+    let nodes = flatten(&extraction.children);
+    let position: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), i))
+        .collect();
+
+    // (node position, date) → mentions
+    let mut found: BTreeMap<(usize, String), Vec<DateMention>> = BTreeMap::new();
+    let mut unparsed = Vec::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(ref raw) = node.date {
+            match patterns.normalize(raw) {
+                Some(date) => found.entry((i, date)).or_default().push(DateMention {
+                    source: MentionSource::Node,
+                    field: None,
+                    raw: raw.clone(),
+                }),
+                None if !raw.trim().is_empty() => unparsed.push(UnparsedDate {
+                    node_id: node.id.clone(),
+                    raw: raw.clone(),
+                }),
+                None => {}
+            }
+        }
+        let mut dates = Vec::new();
+        metadata_dates(&patterns, &node.metadata, "", &mut dates);
+        for (date, field, raw) in dates {
+            found.entry((i, date)).or_default().push(DateMention {
+                source: MentionSource::Metadata,
+                field: Some(field),
+                raw,
+            });
+        }
+    }
+
+    if let Ok(index) = serde_json::from_value::<ReferenceIndex>(extraction.reference_index.clone())
+    {
+        let mut types: Vec<_> = index.entities.iter().collect();
+        types.sort_by(|a, b| a.0.cmp(b.0));
+        for (entity_type, occurrences) in types {
+            for occurrence in occurrences {
+                let Some(date) = patterns.normalize(&occurrence.value) else {
+                    continue;
+                };
+                for node_id in &occurrence.node_ids {
+                    let Some(&i) = position.get(node_id.as_str()) else {
+                        continue;
+                    };
+                    let mention = DateMention {
+                        source: MentionSource::Entity,
+                        field: Some(entity_type.clone()),
+                        raw: occurrence.value.clone(),
+                    };
+                    let mentions = found.entry((i, date.clone())).or_default();
+                    if !mentions.contains(&mention) {
+                        mentions.push(mention);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut events: Vec<(usize, TimelineEvent)> = found
+        .into_iter()
+        .map(|((i, date), mentions)| {
+            let node = nodes[i];
+            let event = TimelineEvent {
+                date,
+                node_id: node.id.clone(),
+                node_type: node.node_type.clone(),
+                label: node.label.clone(),
+                page_range: node.page_range,
+                mentions,
+                event_type: None,
+            };
+            (i, event)
+        })
+        .collect();
+    events.sort_by(|(a_pos, a), (b_pos, b)| a.date.cmp(&b.date).then(a_pos.cmp(b_pos)));
+
+    Timeline {
+        extraction_id: extraction.id.clone(),
+        events: events.into_iter().map(|(_, event)| event).collect(),
+        unparsed,
+    }
+}
+
+const CLASSIFY_PROMPT: &str = "Below are dated events from a document, one per line: \
+index, date, the section the date was found in, and where in the section it appears. \
+Name the kind of each event in one or two lowercase English words, such as \"filing\", \
+\"decision\", \"hearing\", \"citation\", \"deadline\", \"payment\", \"contract signed\", \
+\"signature\", \"travel\" or \"other\". Answer with one entry per index.";
+
+#[derive(Deserialize)]
+struct ClassifyAnswer {
+    #[serde(default)]
+    events: Vec<ClassifiedEvent>,
+}
+
+#[derive(Deserialize)]
+struct ClassifiedEvent {
+    index: usize,
+    event_type: String,
+}
+
+/// Ask the LLM for the `event_type` of every event, in one call.
+pub async fn classify(
+    client: &OpenRouterClient,
+    extraction: &Extraction,
+    timeline: &mut Timeline,
+) -> Result<()> {
+    if timeline.events.is_empty() {
+        return Ok(());
+    }
+    let nodes: HashMap<&str, &DocumentNode> = flatten(&extraction.children)
+        .into_iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+    let mut lines = String::new();
+    for (i, event) in timeline.events.iter().enumerate() {
+        let summary: String = nodes
+            .get(event.node_id.as_str())
+            .map(|node| node.summary.chars().take(SUMMARY_CHARS).collect())
+            .unwrap_or_default();
+        let fields: Vec<&str> = event
+            .mentions
+            .iter()
+            .map(|m| m.field.as_deref().unwrap_or("date"))
+            .collect();
+        lines.push_str(&format!(
+            "{}. {} | {} {} | {} | {}\n",
+            i,
+            event.date,
+            event.node_type,
+            event.label.as_deref().unwrap_or(""),
+            summary.replace('\n', " "),
+            fields.join(", ")
+        ));
+    }
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "events": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "index": {"type": "integer"},
+                        "event_type": {"type": "string"}
+                    },
+                    "required": ["index", "event_type"]
+                }
+            }
+        },
+        "required": ["events"]
+    });
+    let answer: ClassifyAnswer = client
+        .chat_json(
+            vec![Message::system(CLASSIFY_PROMPT), Message::user(lines)],
+            "timeline_events",
+            schema,
+        )
+        .await?;
+    for classified in answer.events {
+        let event_type = classified.event_type.trim().to_lowercase();
+        if let Some(event) = timeline.events.get_mut(classified.index) {
+            if !event_type.is_empty() {
+                event.event_type = Some(event_type);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_dates() {
+        let patterns = DatePatterns::new();
+        let n = |raw| patterns.normalize(raw);
+        assert_eq!(n("2024-03-15").as_deref(), Some("2024-03-15"));
+        assert_eq!(n("2024-03-15T10:00:00Z").as_deref(), Some("2024-03-15"));
+        assert_eq!(n("15/03/2024 14:02").as_deref(), Some("2024-03-15"));
+        assert_eq!(n("5.3.24").as_deref(), Some("2024-03-05"));
+        assert_eq!(n("1º de março de 2024").as_deref(), Some("2024-03-01"));
+        assert_eq!(n("20 de Dez. de 2023").as_deref(), Some("2023-12-20"));
+        assert_eq!(n("31/02/2024"), None);
+        assert_eq!(n("R$ 15.000,00"), None);
+        assert_eq!(n("processo 0001234-56.2024.8.26.0100"), None);
+    }
+
+    #[test]
+    fn test_build_timeline() {
+        let mut extraction = Extraction::new("processo.pdf".to_string(), None);
+        extraction.children = serde_json::from_value(json!([
+            {
+                "id": "inicial", "type": "PETICAO", "label": "Petição Inicial", "summary": "",
+                "date": "2024-03-15",
+                "metadata": {"protocolo": "123", "_entities": {"data": ["15/03/2024"]}}
+            },
+            {
+                "id": "sentenca", "type": "DECISAO", "summary": "", "date": "em junho",
+                "metadata": {"signatures": [{"name": "JOSÉ", "date": "2024-06-20"}]},
+                "children": [{"id": "voo", "type": "DOCUMENTO", "summary": ""}]
+            }
+        ]))
+        .unwrap();
+        extraction.reference_index = json!({"entities": {"data": [
+            {"value": "15/03/2024", "node_ids": ["inicial"]},
+            {"value": "01/02/2024", "node_ids": ["voo", "inicial"]}
+        ]}});
+
+        let timeline = build(&extraction);
+        let events: Vec<(&str, &str)> = timeline
+            .events
+            .iter()
+            .map(|e| (e.date.as_str(), e.node_id.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("2024-02-01", "inicial"),
+                ("2024-02-01", "voo"),
+                ("2024-03-15", "inicial"),
+                ("2024-06-20", "sentenca"),
+            ]
+        );
+        let sources: Vec<MentionSource> = timeline.events[2]
+            .mentions
+            .iter()
+            .map(|m| m.source)
+            .collect();
+        assert_eq!(sources, [MentionSource::Node, MentionSource::Entity]);
+        assert_eq!(
+            timeline.events[3].mentions[0].field.as_deref(),
+            Some("signatures[0].date")
+        );
+        assert_eq!(timeline.unparsed.len(), 1);
+        assert_eq!(timeline.unparsed[0].raw, "em junho");
+    }
+}
@@ -0,0 +1,506 @@
+//! Table of contents detection and alignment.
+//!
+//! Many court records open with an índice/sumário listing each document and
+//! the pages (fls.) it starts on. The `toc` pipeline stage parses it from the
+//! first pages and checks the LLM's nodes against it:
+//!
+//! - an entry whose title matches a node's label confirms or disputes that
+//!   node's `page_range`. A disputed range is corrected when the entry's title
+//!   appears on the page the index points to, and flagged otherwise;
+//! - an entry that lines up with an unmatched node's first page fills in a
+//!   missing label, or flags the differing one.
+//!
+//! Each checked node gets `metadata._toc` (`title`, `page_range`, `status`);
+//! the parsed index and its unmatched entries go under the extraction's
+//! `metadata._toc`.
+
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ocr::OcrPage;
+use crate::readable_id::fold_accent;
+use crate::review::find_node_mut;
+use crate::schema::{DocumentNode, Extraction};
+
+/// How many leading pages are searched for an index.
+pub const SCAN_PAGES: usize = 5;
+/// Entries a page needs to count as an index when it has no heading.
+const MIN_ENTRIES_WITHOUT_HEADING: usize = 6;
+/// Entries a page needs to count as an index under an índice/sumário heading.
+const MIN_ENTRIES: usize = 3;
+/// Word overlap (Jaccard) above which an entry and a node label match.
+const LABEL_MATCH: f64 = 0.6;
+
+const HEADINGS: &[&str] = &["indice", "sumario", "table of contents", "contents"];
+
+/// One line of the index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub title: String,
+    /// Pages as printed in the index; the end is inferred from the next
+    /// entry when the index only gives start pages
+    pub page_range: [u32; 2],
+}
+
+/// A parsed index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Toc {
+    /// PDF pages the index was read from
+    pub pages: Vec<u32>,
+    pub entries: Vec<TocEntry>,
+}
+
+/// What the index said about a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TocStatus {
+    /// Label and pages agree with the index
+    Match,
+    /// Pages moved to the index's, whose title appears on the new first page
+    Corrected,
+    /// Pages differ from the index and the index could not be confirmed
+    PageMismatch,
+    /// Missing label filled in from the index
+    Labeled,
+    /// Same first page as an entry, but a different label
+    LabelMismatch,
+}
+
+/// Outcome of [`align`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TocReport {
+    /// Pages added to every index page number to get the PDF page
+    pub offset: i64,
+    /// Node id → status
+    pub nodes: HashMap<String, TocStatus>,
+    /// Entries no node corresponds to
+    pub unmatched: Vec<TocEntry>,
+}
+
+/// Find and parse an index in the first [`SCAN_PAGES`] pages. An index may
+/// run over consecutive pages.
+pub fn detect(pages: &[OcrPage]) -> Option<Toc> {
+    let entry_re = Regex::new(
+        r"(?xi)^
+        (?:\d{1,3}[.)]\s+)?
+        (?P<title>.*?\pL.*?)
+        (?P<sep>\s*[.·…_]{2,}\s*|\s*\|\s*|\s{2,}|,?\s+(?:fls?|folhas?|p[áa]g(?:inas?|s)?)\.?\s*)
+        (?:(?:fls?|folhas?|p[áa]g(?:inas?|s)?)\.?\s*)?
+        (?P<start>\d{1,4})
+        (?:\s*(?:-|–|/|a|até)\s*(?P<end>\d{1,4}))?
+        $",
+    )
+    .expect("valid built-in regex");
+
+    let mut sorted: Vec<&OcrPage> = pages.iter().collect();
+    sorted.sort_by_key(|p| p.page_num);
+
+    let mut toc = Toc {
+        pages: Vec::new(),
+        entries: Vec::new(),
+    };
+    for page in sorted.into_iter().take(SCAN_PAGES) {
+        let entries = parse_entries(&entry_re, &page.text);
+        let is_index = entries.len() >= MIN_ENTRIES_WITHOUT_HEADING
+            || (entries.len() >= MIN_ENTRIES && has_heading(&page.text))
+            // A continuation page needs no heading of its own
+            || (!toc.pages.is_empty() && !entries.is_empty());
+        if is_index {
+            toc.pages.push(page.page_num);
+            toc.entries.extend(entries);
+        } else if !toc.pages.is_empty() {
+            break;
+        }
+    }
+    if toc.entries.is_empty() {
+        return None;
+    }
+
+    // Open-ended entries run until the next one starts
+    let starts: Vec<u32> = toc.entries.iter().map(|e| e.page_range[0]).collect();
+    for (i, entry) in toc.entries.iter_mut().enumerate() {
+        if entry.page_range[1] == 0 {
+            entry.page_range[1] = starts
+                .get(i + 1)
+                .map(|next| next.saturating_sub(1).max(entry.page_range[0]))
+                .unwrap_or(entry.page_range[0]);
+        }
+    }
+    Some(toc)
+}
+
+/// Index lines of one page. Entries with an end page of 0 are open-ended.
+fn parse_entries(entry_re: &Regex, text: &str) -> Vec<TocEntry> {
+    let mut entries: Vec<TocEntry> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim().trim_matches('|').trim();
+        let line = line.trim_start_matches(['#', '*', '-']).trim();
+        let Some(caps) = entry_re.captures(line) else {
+            continue;
+        };
+        let title = caps["title"]
+            .trim_matches(|c: char| c.is_whitespace() || matches!(c, '*' | '.' | ':' | '-' | '|'))
+            .to_string();
+        let Ok(start) = caps["start"].parse::<u32>() else {
+            continue;
+        };
+        let end = caps
+            .name("end")
+            .and_then(|m| m.as_str().parse::<u32>().ok())
+            .unwrap_or(0);
+        // Start pages only go forward; anything else is not an index line
+        let after_previous = entries.last().is_none_or(|e| e.page_range[0] <= start);
+        if title.is_empty() || title.chars().count() > 120 || start == 0 || !after_previous {
+            continue;
+        }
+        if end != 0 && end < start {
+            continue;
+        }
+        entries.push(TocEntry {
+            title,
+            page_range: [start, end],
+        });
+    }
+    entries
+}
+
+fn has_heading(text: &str) -> bool {
+    text.lines().map(fold).any(|line| {
+        let line = line.trim_matches(|c: char| !c.is_alphanumeric());
+        line.len() < 40 && HEADINGS.iter().any(|h| line.starts_with(h))
+    })
+}
+
+/// Lowercase, accent-free text with single spaces.
+fn fold(text: &str) -> String {
+    let folded: String = text
+        .chars()
+        .map(fold_accent)
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn words(text: &str) -> HashSet<String> {
+    fold(text)
+        .split(' ')
+        .filter(|w| w.chars().count() > 1)
+        .map(str::to_string)
+        .collect()
+}
+
+/// Jaccard overlap of the words of two titles.
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (words(a), words(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// Check `nodes` against `toc`, correcting and flagging them as described in
+/// the module docs.
+pub fn align(
+    nodes: &mut [DocumentNode],
+    toc: &Toc,
+    pages: &[OcrPage],
+    total_pages: Option<u32>,
+) -> TocReport {
+    let mut flat = Vec::new();
+    flatten(nodes, &mut flat);
+
+    // Entry index → the node whose label matches it best
+    let mut matched: Vec<Option<&FlatNode>> = vec![None; toc.entries.len()];
+    let mut taken: HashSet<&str> = HashSet::new();
+    for (i, entry) in toc.entries.iter().enumerate() {
+        let best = flat
+            .iter()
+            .filter(|n| !taken.contains(n.id.as_str()))
+            .filter_map(|n| {
+                let score = similarity(&entry.title, n.label.as_deref()?);
+                (score >= LABEL_MATCH).then_some((n, score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((n, _)) = best {
+            taken.insert(&n.id);
+            matched[i] = Some(n);
+        }
+    }
+
+    let offset = page_offset(toc, &matched);
+    let last_page = total_pages.unwrap_or(u32::MAX).max(1);
+    let expected = |entry: &TocEntry| -> Option<[u32; 2]> {
+        let shift = |p: u32| {
+            u32::try_from(i64::from(p) + offset)
+                .ok()
+                .filter(|p| *p >= 1)
+        };
+        let start = shift(entry.page_range[0])?;
+        let end = shift(entry.page_range[1])?.min(last_page);
+        (start <= end).then_some([start, end])
+    };
+
+    let mut report = TocReport {
+        offset,
+        ..TocReport::default()
+    };
+    for (entry, matched) in toc.entries.iter().zip(matched) {
+        let Some(toc_range) = expected(entry) else {
+            report.unmatched.push(entry.clone());
+            continue;
+        };
+        match matched {
+            Some(flat_node) => {
+                let Some(node) = find_node_mut(nodes, &flat_node.id) else {
+                    continue;
+                };
+                let status = if flat_node.page_range == Some(toc_range) {
+                    TocStatus::Match
+                } else if title_on_page(&entry.title, pages, toc_range[0]) {
+                    TocStatus::Corrected
+                } else {
+                    TocStatus::PageMismatch
+                };
+                if status == TocStatus::Corrected {
+                    node.page_range = Some(toc_range);
+                }
+                annotate(node, entry, toc_range, status, flat_node.page_range);
+                report.nodes.insert(flat_node.id.clone(), status);
+            }
+            None => {
+                let same_start = flat.iter().find(|n| {
+                    !taken.contains(n.id.as_str())
+                        && n.page_range.is_some_and(|r| r[0] == toc_range[0])
+                });
+                let Some(flat_node) = same_start else {
+                    report.unmatched.push(entry.clone());
+                    continue;
+                };
+                let Some(node) = find_node_mut(nodes, &flat_node.id) else {
+                    continue;
+                };
+                let status = if flat_node.label.is_none() {
+                    node.label = Some(entry.title.clone());
+                    TocStatus::Labeled
+                } else {
+                    TocStatus::LabelMismatch
+                };
+                annotate(node, entry, toc_range, status, flat_node.page_range);
+                taken.insert(&flat_node.id);
+                report.nodes.insert(flat_node.id.clone(), status);
+            }
+        }
+    }
+    report
+}
+
+/// Detect an index in the OCR pages and align the extraction's nodes with it.
+/// Returns `None` when the document has no index.
+pub fn apply(extraction: &mut Extraction, pages: &[OcrPage]) -> Option<TocReport> {
+    let toc = detect(pages)?;
+    let report = align(
+        &mut extraction.children,
+        &toc,
+        pages,
+        extraction.total_pages,
+    );
+    // Labels may have changed
+    extraction.refresh_structure_map();
+
+    if extraction.metadata.is_null() {
+        extraction.metadata = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let Some(obj) = extraction.metadata.as_object_mut() {
+        obj.insert(
+            "_toc".to_string(),
+            json!({
+                "pages": toc.pages,
+                "offset": report.offset,
+                "entries": toc.entries,
+                "unmatched": report.unmatched,
+            }),
+        );
+    }
+    Some(report)
+}
+
+/// What alignment needs of a node, copied out so the tree can be edited.
+struct FlatNode {
+    id: String,
+    label: Option<String>,
+    page_range: Option<[u32; 2]>,
+}
+
+fn flatten(nodes: &[DocumentNode], out: &mut Vec<FlatNode>) {
+    for node in nodes {
+        out.push(FlatNode {
+            id: node.id.clone(),
+            label: node.label.clone(),
+            page_range: node.page_range,
+        });
+        flatten(&node.children, out);
+    }
+}
+
+/// Difference between PDF and printed page numbers agreed on by most
+/// label matches (at least two), or 0.
+fn page_offset(toc: &Toc, matched: &[Option<&FlatNode>]) -> i64 {
+    let mut counts: HashMap<i64, usize> = HashMap::new();
+    for (entry, m) in toc.entries.iter().zip(matched) {
+        if let Some(range) = m.and_then(|n| n.page_range) {
+            *counts
+                .entry(i64::from(range[0]) - i64::from(entry.page_range[0]))
+                .or_default() += 1;
+        }
+    }
+    let total: usize = counts.values().sum();
+    counts
+        .into_iter()
+        .filter(|(_, n)| *n >= 2 && *n * 2 > total)
+        .map(|(offset, _)| offset)
+        .next()
+        .unwrap_or(0)
+}
+
+fn title_on_page(title: &str, pages: &[OcrPage], page_num: u32) -> bool {
+    let title = fold(title);
+    !title.is_empty()
+        && pages
+            .iter()
+            .find(|p| p.page_num == page_num)
+            .is_some_and(|p| fold(&p.text).contains(&title))
+}
+
+fn annotate(
+    node: &mut DocumentNode,
+    entry: &TocEntry,
+    toc_range: [u32; 2],
+    status: TocStatus,
+    llm_range: Option<[u32; 2]>,
+) {
+    if node.metadata.is_null() {
+        node.metadata = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let Some(obj) = node.metadata.as_object_mut() {
+        let mut toc = json!({
+            "title": entry.title,
+            "page_range": toc_range,
+            "status": status,
+        });
+        if matches!(status, TocStatus::Corrected | TocStatus::PageMismatch) {
+            toc["llm_page_range"] = json!(llm_range);
+        }
+        obj.insert("_toc".to_string(), toc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(page_num: u32, text: &str) -> OcrPage {
+        OcrPage {
+            page_num,
+            text: text.to_string(),
+            confidence: None,
+            pdf_page: None,
+        }
+    }
+
+    fn node(id: &str, label: Option<&str>, range: [u32; 2]) -> DocumentNode {
+        serde_json::from_value(json!({
+            "id": id,
+            "type": "DOCUMENT",
+            "label": label,
+            "page_range": range,
+            "summary": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_detect_and_align() {
+        let pages = vec![
+            page(1, "TRIBUNAL DE JUSTIÇA\nProcesso 0001234-56.2024.8.26.0100"),
+            page(
+                2,
+                "ÍNDICE\n\
+                 1. Petição Inicial ........ fls. 3\n\
+                 2. Procuração ............. 6\n\
+                 | Contestação | 7-8 |\n\
+                 Sentença, fls. 9",
+            ),
+            page(3, "PETIÇÃO INICIAL\nExcelentíssimo..."),
+            page(6, "PROCURAÇÃO AD JUDICIA"),
+            page(7, "Contestação"),
+            page(9, "SENTENÇA"),
+            page(10, "Certidão de trânsito"),
+        ];
+        let toc = detect(&pages).unwrap();
+        assert_eq!(toc.pages, vec![2]);
+        let titles: Vec<&str> = toc.entries.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec!["Petição Inicial", "Procuração", "Contestação", "Sentença"]
+        );
+        assert_eq!(toc.entries[0].page_range, [3, 5]);
+        assert_eq!(toc.entries[2].page_range, [7, 8]);
+        assert_eq!(toc.entries[3].page_range, [9, 9]);
+
+        let mut extraction = Extraction::new("autos.pdf".into(), None);
+        extraction.total_pages = Some(10);
+        extraction.children = vec![
+            // Ends too early; the index is confirmed by the title on page 3
+            node("peticao", Some("Petição inicial"), [3, 4]),
+            node("procuracao", Some("Procuração"), [6, 6]),
+            // Starts two pages early; page 7 carries the title
+            node("contestacao", Some("Contestação"), [5, 8]),
+            node("decisao", None, [9, 10]),
+        ];
+        let report = apply(&mut extraction, &pages).unwrap();
+        assert_eq!(report.offset, 0);
+        assert_eq!(report.nodes["peticao"], TocStatus::Corrected);
+        assert_eq!(report.nodes["procuracao"], TocStatus::Match);
+        assert_eq!(report.nodes["contestacao"], TocStatus::Corrected);
+        assert_eq!(report.nodes["decisao"], TocStatus::Labeled);
+        assert!(report.unmatched.is_empty());
+
+        let peticao = &extraction.children[0];
+        assert_eq!(peticao.page_range, Some([3, 5]));
+        assert_eq!(peticao.metadata["_toc"]["llm_page_range"], json!([3, 4]));
+        assert_eq!(extraction.children[3].label.as_deref(), Some("Sentença"));
+        assert_eq!(extraction.metadata["_toc"]["pages"], json!([2]));
+    }
+
+    #[test]
+    fn test_mismatches_are_flagged() {
+        let pages = vec![
+            page(
+                1,
+                "SUMÁRIO\nPetição Inicial .... 2\nDocumentos pessoais .... 4\nDespacho .... 6",
+            ),
+            page(2, "texto sem título"),
+        ];
+        let toc = detect(&pages).unwrap();
+        let mut nodes = vec![
+            node("peticao", Some("Petição Inicial"), [2, 2]),
+            node("anexos", Some("Anexos"), [4, 5]),
+        ];
+        let report = align(&mut nodes, &toc, &pages, Some(6));
+        // Index says 2-3, but page 2 does not carry the title
+        assert_eq!(report.nodes["peticao"], TocStatus::PageMismatch);
+        assert_eq!(nodes[0].page_range, Some([2, 2]));
+        assert_eq!(nodes[0].metadata["_toc"]["page_range"], json!([2, 3]));
+        assert_eq!(report.nodes["anexos"], TocStatus::LabelMismatch);
+        assert_eq!(nodes[1].label.as_deref(), Some("Anexos"));
+        assert_eq!(report.unmatched.len(), 1);
+        assert_eq!(report.unmatched[0].title, "Despacho");
+
+        assert!(detect(&[page(1, "Texto corrido sem índice.\nArt. 5")]).is_none());
+    }
+}
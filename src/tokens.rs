@@ -0,0 +1,145 @@
+//! Token counts and context budgets for LLM prompts.
+//!
+//! Documents go into prompts cut to a token budget, not a character count.
+//! Tokens are counted with the model's own encoding for OpenAI models and
+//! with `o200k_base` for the others, whose tokenizers are not published.
+//! The budget for a document is `LLM_DOCUMENT_TOKENS` (default 40,000),
+//! lowered for models whose context window cannot hold that many next to
+//! the instructions and the answer. `LLM_CONTEXT_TOKENS` overrides the
+//! window of the built-in table below.
+//!
+//! Cuts fall on page breaks where possible, so the LLM never sees half a
+//! page.
+
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::CoreBPE;
+
+/// Document tokens per prompt when `LLM_DOCUMENT_TOKENS` is not set.
+const DEFAULT_DOCUMENT_TOKENS: usize = 40_000;
+
+/// Context window of models missing from [`CONTEXT_WINDOWS`].
+const DEFAULT_CONTEXT_TOKENS: usize = 128_000;
+
+/// Room left in the context for the instructions around the document and
+/// for the answer (`max_tokens`).
+const RESERVED_TOKENS: usize = 8192 + 16384;
+
+/// Context windows, in tokens, of the models the extractor is used with.
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("google/gemini-3-flash-preview", 1_048_576),
+    ("google/gemini-2.5-pro", 1_048_576),
+    ("google/gemini-2.5-flash", 1_048_576),
+    ("google/gemini-2.5-flash-lite", 1_048_576),
+    ("openai/gpt-4o", 128_000),
+    ("openai/gpt-4o-mini", 128_000),
+    ("openai/gpt-4.1", 1_047_576),
+];
+
+/// A page break cut only counts when it keeps at least this share of the budget.
+const MIN_PAGE_CUT_SHARE: f64 = 0.5;
+
+fn encoding(model: &str) -> &'static CoreBPE {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    match get_tokenizer(name) {
+        Some(Tokenizer::Cl100kBase) => tiktoken_rs::cl100k_base_singleton(),
+        _ => tiktoken_rs::o200k_base_singleton(),
+    }
+}
+
+/// Tokens `text` takes in a prompt to `model`.
+pub fn count(model: &str, text: &str) -> usize {
+    encoding(model).encode_ordinary(text).len()
+}
+
+/// Context window of `model`.
+pub fn context_window(model: &str) -> usize {
+    if let Some(tokens) = env_tokens("LLM_CONTEXT_TOKENS") {
+        return tokens;
+    }
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(name, _)| *name == model)
+        .map_or(DEFAULT_CONTEXT_TOKENS, |(_, tokens)| *tokens)
+}
+
+/// Tokens of document text a prompt to `model` may carry.
+pub fn document_budget(model: &str) -> usize {
+    let cap = env_tokens("LLM_DOCUMENT_TOKENS").unwrap_or(DEFAULT_DOCUMENT_TOKENS);
+    cap.min(context_window(model).saturating_sub(RESERVED_TOKENS))
+}
+
+fn env_tokens(var: &str) -> Option<usize> {
+    std::env::var(var).ok().and_then(|v| v.parse().ok())
+}
+
+/// The longest start of `text` that fits in `max_tokens` for `model`.
+///
+/// `page_ends` are the byte offsets where pages end in `text`, ascending.
+/// The cut goes at the last page end that fits, unless that would drop
+/// more than half the budget; then at the last paragraph, line, or
+/// character that fits.
+pub fn fit<'a>(model: &str, text: &'a str, max_tokens: usize, page_ends: &[usize]) -> &'a str {
+    let encoding = encoding(model);
+    let tokens = encoding.encode_ordinary(text);
+    if tokens.len() <= max_tokens {
+        return text;
+    }
+    let limit: usize = encoding
+        ._decode_native_and_split(tokens[..max_tokens].to_vec())
+        .map(|bytes| bytes.len())
+        .sum();
+    let floor = (limit as f64 * MIN_PAGE_CUT_SHARE) as usize;
+
+    let page_cut = page_ends
+        .iter()
+        .rev()
+        .find(|&&end| end <= limit && text.is_char_boundary(end));
+    if let Some(&end) = page_cut.filter(|&&end| end >= floor) {
+        return &text[..end];
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let head = &text[..end];
+    let cut = head
+        .rfind("\n\n")
+        .filter(|&i| i >= floor)
+        .or_else(|| head.rfind('\n').filter(|&i| i >= floor))
+        .unwrap_or(end);
+    &text[..cut]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_cuts_at_page_breaks() {
+        let model = "google/gemini-3-flash-preview";
+        let page = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(20);
+        let text = [page.as_str(); 4].join("\n\n");
+        let page_ends: Vec<usize> = (1..=4).map(|n| n * page.len() + (n - 1) * 2).collect();
+        let page_tokens = count(model, &page);
+
+        assert_eq!(fit(model, &text, count(model, &text), &page_ends), text);
+        // Room for two and a half pages keeps two whole pages
+        let two = fit(model, &text, page_tokens * 5 / 2, &page_ends);
+        assert_eq!(two.len(), page_ends[1]);
+        assert!(count(model, two) <= page_tokens * 5 / 2);
+        // Less than a page cuts inside the first one, within the budget
+        let part = fit(model, &text, page_tokens / 3, &[]);
+        assert!(!part.is_empty() && part.len() < page.len());
+        assert!(count(model, part) <= page_tokens / 3);
+    }
+
+    #[test]
+    fn test_document_budget() {
+        assert_eq!(context_window("google/gemini-2.5-pro"), 1_048_576);
+        assert_eq!(context_window("someone/unknown"), DEFAULT_CONTEXT_TOKENS);
+        assert_eq!(
+            document_budget("google/gemini-2.5-pro"),
+            DEFAULT_DOCUMENT_TOKENS
+        );
+    }
+}
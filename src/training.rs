@@ -0,0 +1,188 @@
+//! Fine-tuning data from an extraction, for
+//! `GET /extractions/:id/export?format=training_jsonl`.
+//!
+//! Every node with content becomes one record pairing its text (`input`)
+//! with what the extraction made of it (`output`): type, label, date,
+//! summary, metadata and, for parents, the outline of its children. The
+//! output is the node as it stands, so reviewer corrections are already in
+//! it; for corrected nodes `original_output` rebuilds what the model first
+//! answered from the review log.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::eval::flatten;
+use crate::schema::{DocumentNode, Extraction};
+
+/// Node fields a review can change that are part of `output`.
+const OUTPUT_FIELDS: &[&str] = &["type", "subtype", "label", "date", "page_range", "summary"];
+
+#[derive(Debug, Serialize)]
+pub struct TrainingRecord {
+    pub extraction_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_version: Option<String>,
+    pub node_id: String,
+    pub input: String,
+    pub output: Value,
+    pub reviewed: bool,
+    /// The output before reviewer corrections, when there were any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_output: Option<Value>,
+}
+
+/// One record per node whose content is in `contents` (content_ref → text).
+pub fn records(extraction: &Extraction, contents: &HashMap<String, String>) -> Vec<TrainingRecord> {
+    flatten(&extraction.children)
+        .into_iter()
+        .filter_map(|node| {
+            let input = contents.get(node.content_ref.as_deref()?)?;
+            if input.trim().is_empty() {
+                return None;
+            }
+            let output = output(node);
+            let original_output = original(extraction, node, &output);
+            Some(TrainingRecord {
+                extraction_id: extraction.id.clone(),
+                config_name: extraction.config_name.clone(),
+                config_version: extraction.config_version.clone(),
+                node_id: node.id.clone(),
+                input: input.clone(),
+                output,
+                reviewed: node.reviewed,
+                original_output,
+            })
+        })
+        .collect()
+}
+
+/// Records as newline-delimited JSON.
+pub fn to_jsonl(records: &[TrainingRecord]) -> String {
+    records
+        .iter()
+        .filter_map(|r| serde_json::to_string(r).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// The structured answer for a node. Stage annotations (`_entities`, ...)
+/// are not part of it.
+fn output(node: &DocumentNode) -> Value {
+    let mut output = Map::new();
+    output.insert("type".to_string(), node.node_type.clone().into());
+    for (key, value) in [
+        ("subtype", &node.subtype),
+        ("label", &node.label),
+        ("date", &node.date),
+        ("author", &node.author),
+    ] {
+        if let Some(value) = value {
+            output.insert(key.to_string(), value.clone().into());
+        }
+    }
+    if let Some(range) = node.page_range {
+        output.insert("page_range".to_string(), json!(range));
+    }
+    output.insert("summary".to_string(), node.summary.clone().into());
+    if let Some(metadata) = node.metadata.as_object() {
+        let metadata: Map<String, Value> = metadata
+            .iter()
+            .filter(|(key, _)| !key.starts_with('_'))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if !metadata.is_empty() {
+            output.insert("metadata".to_string(), metadata.into());
+        }
+    }
+    if !node.children.is_empty() {
+        let children: Vec<Value> = node
+            .children
+            .iter()
+            .map(|child| json!({"type": child.node_type, "label": child.label, "page_range": child.page_range}))
+            .collect();
+        output.insert("children".to_string(), children.into());
+    }
+    output.into()
+}
+
+/// Undo the node's reviews, newest first, on the output fields they touched.
+fn original(extraction: &Extraction, node: &DocumentNode, output: &Value) -> Option<Value> {
+    let mut original = output.clone();
+    let fields = original.as_object_mut()?;
+    let mut changed = false;
+    for review in extraction
+        .reviews
+        .iter()
+        .rev()
+        .filter(|r| r.node_id == node.id)
+    {
+        for change in review.changes.iter().rev() {
+            if !OUTPUT_FIELDS.contains(&change.field.as_str()) {
+                continue;
+            }
+            changed = true;
+            if change.old.is_null() {
+                fields.remove(&change.field);
+            } else {
+                fields.insert(change.field.clone(), change.old.clone());
+            }
+        }
+    }
+    changed.then_some(original)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldChange, NodeReview};
+
+    #[test]
+    fn test_records() {
+        let mut extraction = Extraction::new("autos.pdf".to_string(), Some("legal_br".to_string()));
+        extraction.children = serde_json::from_value(json!([{
+            "id": "sentenca",
+            "type": "DECISAO",
+            "label": "Sentença",
+            "summary": "Julga procedente",
+            "content_ref": "content://sentenca",
+            "metadata": {"juiz": "Ana", "_entities": {"cpf": ["123"]}},
+            "reviewed": true
+        }, {
+            "id": "anexo",
+            "type": "DOCUMENTO",
+            "summary": "Sem conteúdo",
+            "content_ref": "content://anexo"
+        }]))
+        .unwrap();
+        extraction.reviews.push(NodeReview::new(
+            "sentenca".to_string(),
+            "ana".to_string(),
+            vec![FieldChange {
+                field: "type".to_string(),
+                old: "DOCUMENTO".into(),
+                new: "DECISAO".into(),
+            }],
+        ));
+        let contents = HashMap::from([(
+            "content://sentenca".to_string(),
+            "JULGO PROCEDENTE o pedido".to_string(),
+        )]);
+
+        let records = records(&extraction, &contents);
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.config_name.as_deref(), Some("legal_br"));
+        assert_eq!(record.input, "JULGO PROCEDENTE o pedido");
+        assert_eq!(record.output["type"], "DECISAO");
+        assert_eq!(record.output["metadata"], json!({"juiz": "Ana"}));
+        assert_eq!(
+            record.original_output.as_ref().unwrap()["type"],
+            "DOCUMENTO"
+        );
+        assert_eq!(to_jsonl(&records).lines().count(), 1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Checks on an uploaded file before it is queued.
+//!
+//! [`check`] looks at the file name, the multipart `Content-Type` and the
+//! first and last bytes of the file, so a wrong or broken upload is turned
+//! away with a reason instead of failing later in OCR or parsing. It catches
+//! extensions an endpoint does not take, a declared MIME type that
+//! contradicts the extension, contents that do not match the extension
+//! (magic bytes), and encrypted PDFs.
+
+use thiserror::Error;
+
+/// Bytes kept from each end of an upload for [`check`]. PDF trailers, where
+/// `/Encrypt` normally sits, fit well inside.
+pub const PROBE_BYTES: usize = 64 * 1024;
+
+/// What an endpoint takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accept {
+    /// PDFs and page images, for OCR (`/extract`, `/estimate`, `/experiments`)
+    Document,
+    /// Spreadsheets, or PDFs whose tables are read with OCR (`/extract-sheet`)
+    Sheet,
+}
+
+impl Accept {
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Accept::Document => &["pdf", "png", "jpg", "jpeg", "tif", "tiff"],
+            Accept::Sheet => &["csv", "xlsx", "xlsm", "xlsb", "pdf"],
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Rejection {
+    #[error("the uploaded file is empty")]
+    Empty,
+    #[error("unsupported file type '{extension}'; expected one of: {expected}")]
+    UnsupportedType { extension: String, expected: String },
+    #[error("Content-Type '{declared}' does not match a .{extension} file")]
+    ContentTypeMismatch { declared: String, extension: String },
+    #[error("the file is named .{extension} but its contents are not {what}")]
+    ContentMismatch {
+        extension: String,
+        what: &'static str,
+    },
+    #[error("the PDF is password-protected; remove the protection and upload it again")]
+    EncryptedPdf,
+}
+
+impl Rejection {
+    /// Machine-readable code for API error bodies.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rejection::Empty => "empty_file",
+            Rejection::UnsupportedType { .. } => "unsupported_file_type",
+            Rejection::ContentTypeMismatch { .. } => "content_type_mismatch",
+            Rejection::ContentMismatch { .. } => "invalid_file_contents",
+            Rejection::EncryptedPdf => "encrypted_pdf",
+        }
+    }
+}
+
+/// Check an upload of `size` bytes. `head` and `tail` are its first and last
+/// [`PROBE_BYTES`] (they overlap for small files).
+pub fn check(
+    accept: Accept,
+    filename: &str,
+    content_type: Option<&str>,
+    size: u64,
+    head: &[u8],
+    tail: &[u8],
+) -> Result<(), Rejection> {
+    if size == 0 {
+        return Err(Rejection::Empty);
+    }
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, e)| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    if !accept.extensions().contains(&extension.as_str()) {
+        return Err(Rejection::UnsupportedType {
+            extension: if extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", extension)
+            },
+            expected: accept
+                .extensions()
+                .iter()
+                .map(|e| format!(".{}", e))
+                .collect::<Vec<_>>()
+                .join(", "),
+        });
+    }
+
+    if let Some(declared) = content_type {
+        let essence = declared
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        if essence != "application/octet-stream" && !mime_types(&extension).contains(&&*essence) {
+            return Err(Rejection::ContentTypeMismatch {
+                declared: declared.to_string(),
+                extension,
+            });
+        }
+    }
+
+    let (matches, what) = match extension.as_str() {
+        // The header may follow a little junk; readers accept it within 1 KiB
+        "pdf" => (find(&head[..head.len().min(1024)], b"%PDF-"), "a PDF"),
+        "png" => (head.starts_with(b"\x89PNG\r\n\x1a\n"), "a PNG image"),
+        "jpg" | "jpeg" => (head.starts_with(&[0xFF, 0xD8, 0xFF]), "a JPEG image"),
+        "tif" | "tiff" => (
+            head.starts_with(b"II*\0") || head.starts_with(b"MM\0*"),
+            "a TIFF image",
+        ),
+        "xlsx" | "xlsm" | "xlsb" => (head.starts_with(b"PK\x03\x04"), "a spreadsheet"),
+        "csv" => (!head.contains(&0), "text"),
+        _ => (true, ""),
+    };
+    if !matches {
+        return Err(Rejection::ContentMismatch { extension, what });
+    }
+
+    if extension == "pdf" && (find(head, b"/Encrypt") || find(tail, b"/Encrypt")) {
+        return Err(Rejection::EncryptedPdf);
+    }
+    Ok(())
+}
+
+/// MIME types clients send for an extension.
+fn mime_types(extension: &str) -> &'static [&'static str] {
+    match extension {
+        "pdf" => &["application/pdf", "application/x-pdf"],
+        "png" => &["image/png"],
+        "jpg" | "jpeg" => &["image/jpeg", "image/pjpeg"],
+        "tif" | "tiff" => &["image/tiff"],
+        // Windows reports CSV files as Excel
+        "csv" => &[
+            "text/csv",
+            "text/plain",
+            "application/csv",
+            "application/vnd.ms-excel",
+        ],
+        "xlsx" => &["application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"],
+        "xlsm" => &["application/vnd.ms-excel.sheet.macroenabled.12"],
+        "xlsb" => &["application/vnd.ms-excel.sheet.binary.macroenabled.12"],
+        _ => &[],
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_uploads() {
+        let pdf = b"%PDF-1.7\n1 0 obj\n<<>>\nendobj\ntrailer\n<< /Root 1 0 R >>\n%%EOF";
+        let ok = |name: &str, mime: Option<&str>, data: &[u8]| {
+            check(Accept::Document, name, mime, data.len() as u64, data, data)
+        };
+        assert_eq!(ok("autos.PDF", Some("application/pdf"), pdf), Ok(()));
+        assert_eq!(
+            ok("autos.pdf", Some("application/octet-stream"), pdf),
+            Ok(())
+        );
+        assert_eq!(ok("autos.pdf", None, b""), Err(Rejection::Empty));
+        assert_eq!(
+            ok("autos.docx", None, pdf).unwrap_err().code(),
+            "unsupported_file_type"
+        );
+        assert_eq!(
+            ok("autos.pdf", Some("image/png"), pdf).unwrap_err().code(),
+            "content_type_mismatch"
+        );
+        assert_eq!(
+            ok("scan.png", None, pdf).unwrap_err().code(),
+            "invalid_file_contents"
+        );
+        let encrypted = b"%PDF-1.4\ntrailer\n<< /Root 1 0 R /Encrypt 5 0 R >>\n%%EOF";
+        assert_eq!(
+            ok("autos.pdf", None, encrypted),
+            Err(Rejection::EncryptedPdf)
+        );
+
+        let csv = b"cnpj,valor\n00.000.000/0001-91,10\n";
+        let sheet = |name: &str, mime: Option<&str>| {
+            check(Accept::Sheet, name, mime, csv.len() as u64, csv, csv)
+        };
+        assert_eq!(sheet("dados.csv", Some("application/vnd.ms-excel")), Ok(()));
+        assert!(sheet("scan.png", None).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+//! Cell number formats read straight from an xlsx package.
+//!
+//! calamine reads `styles.xml` only to tell dates from plain numbers, so a
+//! currency or percent column reaches us as bare floats. This module maps each
+//! styled cell to its format code (`R$ #,##0.00`, `0.00%`) so columns can be
+//! typed without asking the LLM. It also reports the workbook's date system,
+//! which calamine keeps to itself.
+
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+/// Format code of every cell with a non-General format, keyed by absolute
+/// zero-based `(row, column)` as calamine reports positions.
+pub type CellFormats = HashMap<(u32, u32), String>;
+
+/// What a number format says about a cell's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatKind {
+    Date,
+    /// `currency_brl` or `currency_usd`.
+    Currency(&'static str),
+    Percent,
+    Integer,
+    Decimal,
+    Text,
+}
+
+/// What an xlsx package says about its cells beyond their values.
+#[derive(Debug, Default)]
+pub struct WorkbookFormats {
+    /// Serial dates count from 1904-01-01 (workbooks from old Mac Excel)
+    /// rather than 1899-12-30.
+    pub date_1904: bool,
+    /// Cell formats per worksheet name.
+    pub sheets: HashMap<String, CellFormats>,
+}
+
+pub fn read_workbook_formats(data: &[u8]) -> Result<WorkbookFormats> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data)).context("Not an xlsx package")?;
+
+    let workbook = read_part(&mut zip, "xl/workbook.xml")?.context("Missing xl/workbook.xml")?;
+    let (sheet_list, date_1904) = parse_workbook(&workbook)?;
+    let mut formats = WorkbookFormats {
+        date_1904,
+        sheets: HashMap::new(),
+    };
+
+    let styles = match read_part(&mut zip, "xl/styles.xml")? {
+        Some(xml) => parse_styles(&xml)?,
+        None => return Ok(formats),
+    };
+    let rels = read_part(&mut zip, "xl/_rels/workbook.xml.rels")?
+        .context("Missing xl/_rels/workbook.xml.rels")?;
+    let targets = parse_relationships(&rels)?;
+
+    for (name, rel_id) in sheet_list {
+        let Some(target) = targets.get(&rel_id) else {
+            continue;
+        };
+        let path = match target.strip_prefix('/') {
+            Some(absolute) => absolute.to_string(),
+            None => format!("xl/{}", target),
+        };
+        if let Some(xml) = read_part(&mut zip, &path)? {
+            formats
+                .sheets
+                .insert(name, parse_sheet_formats(&xml, &styles)?);
+        }
+    }
+    Ok(formats)
+}
+
+/// Classify a format code. `None` for General and codes that say nothing
+/// about the value (fractions, scientific notation).
+pub fn classify(code: &str) -> Option<FormatKind> {
+    let code = code.trim();
+    if code.is_empty() || code.eq_ignore_ascii_case("general") {
+        return None;
+    }
+    if code == "@" {
+        return Some(FormatKind::Text);
+    }
+
+    // Split the code into the pattern letters and the literal text around
+    // them: quoted strings, escaped characters, and currency tags like
+    // [$R$-416] ([Red] and bare locale tags like [$-416] are dropped)
+    let mut pattern = String::new();
+    let mut literals = String::new();
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => literals.extend(chars.by_ref().take_while(|&c| c != '"')),
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some(symbol) = tag.strip_prefix('$') {
+                    literals.push_str(symbol.split('-').next().unwrap_or(""));
+                }
+            }
+            '\\' => literals.extend(chars.next()),
+            '_' | '*' => {
+                chars.next();
+            }
+            '$' => literals.push(c),
+            _ => pattern.push(c.to_ascii_lowercase()),
+        }
+    }
+    // Only the positive section of `pos;neg;zero`
+    let pattern = pattern.split(';').next().unwrap_or("");
+
+    if code.contains("R$") {
+        return Some(FormatKind::Currency("currency_brl"));
+    }
+    if literals.contains('$') {
+        return Some(FormatKind::Currency("currency_usd"));
+    }
+    if pattern.contains('%') {
+        Some(FormatKind::Percent)
+    } else if pattern.contains(['y', 'd', 'm', 'h', 's']) {
+        Some(FormatKind::Date)
+    } else if pattern.contains('e') || pattern.contains('?') || pattern.contains('/') {
+        None
+    } else if pattern.contains(".0") || pattern.contains(".#") {
+        Some(FormatKind::Decimal)
+    } else if pattern.contains(['0', '#']) {
+        Some(FormatKind::Integer)
+    } else {
+        None
+    }
+}
+
+fn read_part<R: Read + std::io::Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    path: &str,
+) -> Result<Option<String>> {
+    let mut file = match zip.by_name(path) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", path)),
+    };
+    let mut xml = String::new();
+    file.read_to_string(&mut xml)
+        .with_context(|| format!("Failed to read {}", path))?;
+    Ok(Some(xml))
+}
+
+fn attr(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .and_then(|a| attr_value(&a))
+}
+
+fn attr_value(a: &quick_xml::events::attributes::Attribute) -> Option<String> {
+    let raw = std::str::from_utf8(&a.value).ok()?;
+    quick_xml::escape::unescape(raw)
+        .ok()
+        .map(|v| v.into_owned())
+}
+
+/// Format code of each cell style (`cellXfs` entry), in order; `None` for General.
+fn parse_styles(xml: &str) -> Result<Vec<Option<String>>> {
+    let mut custom: HashMap<u32, String> = HashMap::new();
+    let mut xf_formats = Vec::new();
+    let mut in_cell_xfs = false;
+
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().context("Invalid xl/styles.xml")? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"numFmt" => {
+                    let id = attr(&e, b"numFmtId").and_then(|id| id.parse().ok());
+                    if let (Some(id), Some(code)) = (id, attr(&e, b"formatCode")) {
+                        custom.insert(id, code);
+                    }
+                }
+                b"cellXfs" => in_cell_xfs = true,
+                b"xf" if in_cell_xfs => {
+                    let id: u32 = attr(&e, b"numFmtId")
+                        .and_then(|id| id.parse().ok())
+                        .unwrap_or(0);
+                    xf_formats.push(id);
+                }
+                _ => {}
+            },
+            Event::End(e) if e.local_name().as_ref() == b"cellXfs" => in_cell_xfs = false,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(xf_formats
+        .into_iter()
+        .map(|id| {
+            custom
+                .get(&id)
+                .cloned()
+                .or_else(|| builtin_format(id).map(str::to_string))
+        })
+        .collect())
+}
+
+/// Sheet names with their relationship ids, and whether the workbook uses
+/// the 1904 date system, from `xl/workbook.xml`.
+fn parse_workbook(xml: &str) -> Result<(Vec<(String, String)>, bool)> {
+    let mut sheets = Vec::new();
+    let mut date_1904 = false;
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().context("Invalid xl/workbook.xml")? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"workbookPr" => {
+                date_1904 = matches!(attr(&e, b"date1904").as_deref(), Some("1" | "true"));
+            }
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+                let rel_id = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.local_name().as_ref() == b"id" && a.key.prefix().is_some())
+                    .and_then(|a| attr_value(&a));
+                if let (Some(name), Some(rel_id)) = (attr(&e, b"name"), rel_id) {
+                    sheets.push((name, rel_id));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((sheets, date_1904))
+}
+
+/// Relationship id → target path, from `xl/_rels/workbook.xml.rels`.
+fn parse_relationships(xml: &str) -> Result<HashMap<String, String>> {
+    let mut targets = HashMap::new();
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader
+            .read_event()
+            .context("Invalid workbook relationships")?
+        {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                if let (Some(id), Some(target)) = (attr(&e, b"Id"), attr(&e, b"Target")) {
+                    targets.insert(id, target);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
+
+fn parse_sheet_formats(xml: &str, styles: &[Option<String>]) -> Result<CellFormats> {
+    let mut formats = CellFormats::new();
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().context("Invalid worksheet")? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"c" => {
+                let style = attr(&e, b"s").and_then(|s| s.parse::<usize>().ok());
+                let code = style.and_then(|s| styles.get(s)).and_then(|c| c.as_ref());
+                let position = attr(&e, b"r").and_then(|r| cell_position(&r));
+                if let (Some(code), Some(position)) = (code, position) {
+                    formats.insert(position, code.clone());
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(formats)
+}
+
+/// `"B12"` → `(11, 1)`.
+fn cell_position(reference: &str) -> Option<(u32, u32)> {
+    let digits = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, row) = reference.split_at(digits);
+    if letters.is_empty() {
+        return None;
+    }
+    let col = letters.chars().try_fold(0u32, |acc, c| {
+        c.is_ascii_uppercase()
+            .then(|| acc * 26 + (c as u32 - 'A' as u32 + 1))
+    })?;
+    let row: u32 = row.parse().ok()?;
+    Some((row.checked_sub(1)?, col - 1))
+}
+
+/// Codes for the built-in format ids (ECMA-376 §18.8.30).
+fn builtin_format(id: u32) -> Option<&'static str> {
+    Some(match id {
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        5 => "$#,##0_);($#,##0)",
+        6 => "$#,##0_);[Red]($#,##0)",
+        7 => "$#,##0.00_);($#,##0.00)",
+        8 => "$#,##0.00_);[Red]($#,##0.00)",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_classify_format_codes() {
+        assert_eq!(
+            classify(r#"_-[$R$-416]\ * #,##0.00_-"#),
+            Some(FormatKind::Currency("currency_brl"))
+        );
+        assert_eq!(
+            classify("$#,##0.00_);($#,##0.00)"),
+            Some(FormatKind::Currency("currency_usd"))
+        );
+        assert_eq!(classify("0.0%"), Some(FormatKind::Percent));
+        assert_eq!(classify("[$-416]dd/mm/yyyy"), Some(FormatKind::Date));
+        assert_eq!(classify("[Red]#,##0"), Some(FormatKind::Integer));
+        assert_eq!(classify("#,##0.00 \"kg\""), Some(FormatKind::Decimal));
+        assert_eq!(classify("0.00E+00"), None);
+        assert_eq!(classify("General"), None);
+        assert_eq!(cell_position("AB12"), Some((11, 27)));
+    }
+
+    #[test]
+    fn test_read_workbook_formats() {
+        let parts = [
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><workbookPr date1904="1"/><sheets><sheet name="Resumo" sheetId="1" r:id="rId2"/></sheets></workbook>"#,
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships><Relationship Id="rId2" Target="worksheets/sheet1.xml"/></Relationships>"#,
+            ),
+            (
+                "xl/styles.xml",
+                r#"<styleSheet><numFmts count="1"><numFmt numFmtId="164" formatCode="&quot;R$&quot; #,##0.00"/></numFmts><cellStyleXfs><xf numFmtId="10"/></cellStyleXfs><cellXfs><xf numFmtId="0"/><xf numFmtId="164"/><xf numFmtId="14"/></cellXfs></styleSheet>"#,
+            ),
+            (
+                "xl/worksheets/sheet1.xml",
+                r#"<worksheet><sheetData><row r="2"><c r="A2" s="2"><v>45000</v></c><c r="B2" s="1"><v>10.5</v></c><c r="C2" s="0"><v>1</v></c></row></sheetData></worksheet>"#,
+            ),
+        ];
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, xml) in parts {
+            writer
+                .start_file(path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(xml.as_bytes()).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+
+        let formats = read_workbook_formats(&data).unwrap();
+        assert!(formats.date_1904);
+        let sheet = &formats.sheets["Resumo"];
+        assert_eq!(sheet.len(), 2);
+        assert_eq!(sheet[&(1, 0)], "mm-dd-yy");
+        assert_eq!(sheet[&(1, 1)], "\"R$\" #,##0.00");
+    }
+}
@@ -28,14 +28,19 @@ export const toolDefinitions: ToolDefinition[] = [
     function: {
       name: "list_extractions",
       description:
-        "List all extractions with IDs, source files, readable_id, summaries, and page counts. Supports filtering by readable_id (case-insensitive substring match).",
+        "List all extractions with IDs, source files, readable_id, summaries, and page counts. Supports filtering by readable_id (substring match ignoring case and punctuation).",
       parameters: {
         type: "object",
         properties: {
           readable_id: {
             type: "string",
             description:
-              "Filter by readable_id (case-insensitive substring match). Example: '0266175'",
+              "Filter by readable_id (substring match ignoring case and punctuation). Example: '0266175'",
+          },
+          reviewed: {
+            type: "boolean",
+            description:
+              "true: only extractions with reviewer-corrected nodes; false: only unreviewed ones",
           },
         },
       },
@@ -125,6 +130,10 @@ export const toolDefinitions: ToolDefinition[] = [
           },
           offset: { type: "number", description: "Character offset. Default: 0" },
           limit: { type: "number", description: "Max characters. Default: 4000" },
+          redacted: {
+            type: "boolean",
+            description: "Return the PII-redacted copy (needs the redact pipeline stage)",
+          },
         },
         required: ["ref"],
       },
@@ -34,6 +34,7 @@ export async function executeTool(
       case "list_extractions": {
         const params = new URLSearchParams();
         if (args.readable_id) params.set("readable_id", String(args.readable_id));
+        if (args.reviewed !== undefined) params.set("reviewed", String(args.reviewed));
         const qs = params.toString();
         const result = await api(`/extractions${qs ? `?${qs}` : ""}`);
         return truncate(result);
@@ -84,6 +85,7 @@ export async function executeTool(
         const params = new URLSearchParams();
         if (args.offset !== undefined) params.set("offset", String(args.offset));
         if (args.limit !== undefined) params.set("limit", String(args.limit));
+        if (args.redacted) params.set("redacted", "true");
         const qs = params.toString();
         const result = await api(`/content/${refPath}${qs ? `?${qs}` : ""}`);
         return truncate(result);